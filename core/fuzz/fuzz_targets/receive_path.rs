@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// A mode byte, then length-prefixed radio frames (see streetgrid_core::fuzz)
+fuzz_target!(|data: &[u8]| {
+    streetgrid_core::fuzz::run(data);
+});
@@ -0,0 +1,154 @@
+use std::collections::{HashMap, VecDeque};
+
+/// Samples kept per channel (an hour at the 5 s ADC interval), long enough to take in
+/// whole compressor and HVAC cycles rather than just the current one
+pub const DEFAULT_WINDOW: usize = 720;
+/// Deviation from the rolling mean, in standard deviations, that counts as abnormal
+pub const DEFAULT_THRESHOLD_SIGMA: f32 = 4.0;
+/// Consecutive abnormal readings before alerting, so a motor's start-up inrush caught
+/// by one reading is not a fault
+pub const DEFAULT_PERSIST_READINGS: u32 = 2;
+/// Floor on the standard deviation so a perfectly steady load doesn't alert on ADC noise
+const MIN_STD_DEV_AMPS: f32 = 0.25;
+
+/// A current reading that deviates sharply from the channel's recent history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    pub channel: u8,
+    pub amps: f32,
+    pub baseline_amps: f32,
+    /// Signed deviation in standard deviations (positive = drawing more than usual)
+    pub magnitude: f32,
+}
+
+#[derive(Debug, Default)]
+struct ChannelWindow {
+    samples: VecDeque<f32>,
+    /// Consecutive readings out of range so far
+    abnormal: u32,
+    /// Set while the channel is out of range so a sustained fault alerts only once
+    alerting: bool,
+}
+
+impl ChannelWindow {
+    /// Over the oldest `len` samples.
+    fn mean_std_dev(&self, len: usize) -> (f32, f32) {
+        let samples = || self.samples.iter().take(len);
+        let n = len as f32;
+        let mean = samples().sum::<f32>() / n;
+        let variance = samples().map(|s| (s - mean).powi(2)).sum::<f32>() / n;
+        (mean, variance.sqrt())
+    }
+}
+
+/// Rolling mean/stddev monitor that flags abnormal current per CT channel
+/// (e.g. a stuck compressor or a wiring fault).
+pub struct AnomalyDetector {
+    window: usize,
+    threshold_sigma: f32,
+    persist_readings: u32,
+    channels: HashMap<u8, ChannelWindow>,
+}
+
+impl AnomalyDetector {
+    pub fn new(window: usize, threshold_sigma: f32) -> Self {
+        Self {
+            window: window.max(2),
+            threshold_sigma,
+            persist_readings: 1,
+            channels: HashMap::new(),
+        }
+    }
+
+    /// Alert only once a channel has been out of range for `readings` in a row.
+    pub fn with_persistence(mut self, readings: u32) -> Self {
+        self.persist_readings = readings.max(1);
+        self
+    }
+
+    /// Feed a reading; returns an anomaly when the channel has been out of its normal
+    /// range for the persistence, as measured against the history before it left.
+    /// Nothing is reported until a full window of history has been collected.
+    pub fn observe(&mut self, channel: u8, amps: f32) -> Option<Anomaly> {
+        let win = self.channels.entry(channel).or_default();
+
+        let mut anomaly = None;
+        if win.samples.len() >= self.window {
+            // The baseline leaves out the abnormal readings still being confirmed
+            let confirming = win.abnormal.min(win.samples.len() as u32 - 1) as usize;
+            let (mean, std_dev) = win.mean_std_dev(win.samples.len() - confirming);
+            let magnitude = (amps - mean) / std_dev.max(MIN_STD_DEV_AMPS);
+            if magnitude.abs() >= self.threshold_sigma {
+                win.abnormal += 1;
+                if !win.alerting && win.abnormal >= self.persist_readings {
+                    win.alerting = true;
+                    anomaly = Some(Anomaly { channel, amps, baseline_amps: mean, magnitude });
+                }
+            } else {
+                win.abnormal = 0;
+                win.alerting = false;
+            }
+            win.samples.pop_front();
+        }
+        // Abnormal readings still enter the window so the baseline adapts to a new normal
+        win.samples.push_back(amps);
+        anomaly
+    }
+}
+
+impl Default for AnomalyDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW, DEFAULT_THRESHOLD_SIGMA).with_persistence(DEFAULT_PERSIST_READINGS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steady_load_is_not_flagged() {
+        let mut detector = AnomalyDetector::new(10, 4.0);
+        for i in 0..50 {
+            let amps = 8.0 + if i % 2 == 0 { 0.1 } else { -0.1 };
+            assert_eq!(detector.observe(2, amps), None);
+        }
+    }
+
+    #[test]
+    fn test_spike_alerts_once_until_recovered() {
+        let mut detector = AnomalyDetector::new(10, 4.0);
+        for _ in 0..10 {
+            assert_eq!(detector.observe(1, 5.0), None);
+        }
+
+        // Compressor stuck on: sustained jump only alerts on the first reading
+        let anomaly = detector.observe(1, 18.0).unwrap();
+        assert_eq!(anomaly.channel, 1);
+        assert_eq!(anomaly.baseline_amps, 5.0);
+        assert!(anomaly.magnitude > 4.0);
+        assert_eq!(detector.observe(1, 18.0), None);
+
+        // Other channels are tracked independently
+        assert_eq!(detector.observe(0, 3.0), None);
+    }
+
+    #[test]
+    fn test_momentary_inrush_is_not_flagged_with_persistence() {
+        let mut detector = AnomalyDetector::new(10, 4.0).with_persistence(2);
+        for _ in 0..10 {
+            assert_eq!(detector.observe(1, 1.5), None);
+        }
+        // A compressor start caught by one reading
+        assert_eq!(detector.observe(1, 8.0), None);
+        for _ in 0..10 {
+            assert_eq!(detector.observe(1, 1.5), None);
+        }
+
+        // A stalled compressor stays up, and is measured against the baseline before it
+        assert_eq!(detector.observe(1, 6.0), None);
+        let anomaly = detector.observe(1, 6.0).unwrap();
+        assert_eq!(anomaly.baseline_amps, 1.5);
+        assert_eq!(detector.observe(1, 6.0), None);
+    }
+}
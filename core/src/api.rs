@@ -0,0 +1,199 @@
+use log::{info, warn, debug};
+use std::io::{Error, ErrorKind, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use std::sync::{Arc, Mutex};
+use crate::delivery::DeliveryTracker;
+use crate::events::EventBus;
+
+/// Largest request head we accept; the API only serves simple GETs.
+const MAX_REQUEST_HEAD: usize = 8192;
+
+/// Minimal local HTTP API for dashboards and installer tooling on the LAN.
+///
+/// Routes:
+/// - `GET /events` — Server-Sent Events stream of live node events
+/// - `GET /deliveries` — round-trip time and delivery per destination, as JSON
+pub struct LocalApi {
+    listener: TcpListener,
+    events: EventBus,
+    deliveries: Option<Arc<Mutex<DeliveryTracker>>>,
+}
+
+impl LocalApi {
+    pub async fn bind(addr: &str, events: EventBus) -> Result<Self> {
+        let api = Self { listener: TcpListener::bind(addr).await?, events, deliveries: None };
+        info!("Local API listening on {}", api.local_addr()?);
+        Ok(api)
+    }
+
+    /// Serve the delivery statistics of the node's comms.
+    pub fn with_deliveries(mut self, deliveries: Arc<Mutex<DeliveryTracker>>) -> Self {
+        self.deliveries = Some(deliveries);
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept connections forever, one task per client.
+    pub async fn run(self) {
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, peer)) => {
+                    debug!("API connection from {}", peer);
+                    let events = self.events.clone();
+                    let deliveries = self.deliveries.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(stream, events, deliveries).await {
+                            debug!("API connection from {} closed: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => warn!("API accept failed: {}", e),
+            }
+        }
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, events: EventBus, deliveries: Option<Arc<Mutex<DeliveryTracker>>>) -> Result<()> {
+    let head = read_request_head(&mut stream).await?;
+    let (method, path) = parse_request_line(&head)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "malformed request line"))?;
+
+    match (method, path) {
+        ("GET", "/events") => stream_events(stream, events).await,
+        ("GET", "/deliveries") => match deliveries {
+            Some(deliveries) => {
+                let body = serde_json::to_string(&deliveries.lock().unwrap().stats())?;
+                write_response(&mut stream, "200 OK", "application/json", &body).await
+            }
+            None => write_response(&mut stream, "404 Not Found", "text/plain", "no comms configured\n").await,
+        },
+        _ => write_response(&mut stream, "404 Not Found", "text/plain", "not found\n").await,
+    }
+}
+
+async fn read_request_head(stream: &mut TcpStream) -> Result<String> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed before end of headers"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if buf.len() > MAX_REQUEST_HEAD {
+            return Err(Error::new(ErrorKind::InvalidData, "request head too large"));
+        }
+    }
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// Returns (method, path) from the first line of an HTTP request, ignoring any query string.
+fn parse_request_line(head: &str) -> Option<(&str, &str)> {
+    let mut parts = head.lines().next()?.split_whitespace();
+    let method = parts.next()?;
+    let target = parts.next()?;
+    let path = target.split('?').next()?;
+    Some((method, path))
+}
+
+async fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Serve `GET /events` as an SSE stream until the client disconnects.
+async fn stream_events(mut stream: TcpStream, events: EventBus) -> Result<()> {
+    let mut rx = events.subscribe();
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n"
+    ).await?;
+
+    loop {
+        match rx.recv().await {
+            Ok(record) => {
+                let json = serde_json::to_string(&record)?;
+                stream.write_all(format!("data: {}\n\n", json).as_bytes()).await?;
+            }
+            Err(RecvError::Lagged(missed)) => {
+                warn!("SSE client fell behind, {} events dropped", missed);
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::NodeEvent;
+
+    #[test]
+    fn test_parse_request_line() {
+        assert_eq!(parse_request_line("GET /events?x=1 HTTP/1.1\r\nHost: a\r\n\r\n"), Some(("GET", "/events")));
+        assert_eq!(parse_request_line(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_events_stream_over_sse() {
+        let bus = EventBus::new();
+        let api = LocalApi::bind("127.0.0.1:0", bus.clone()).await.unwrap();
+        let addr = api.local_addr().unwrap();
+        tokio::spawn(api.run());
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"GET /events HTTP/1.1\r\nHost: node\r\n\r\n").await.unwrap();
+
+        // Wait for the response headers so the subscription is in place before publishing
+        let mut buf = vec![0u8; 4096];
+        let n = client.read(&mut buf).await.unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).contains("text/event-stream"));
+
+        bus.publish(NodeEvent::Alarm { code: "undervoltage".to_string(), message: "105.0V".to_string() });
+        let n = client.read(&mut buf).await.unwrap();
+        let body = String::from_utf8_lossy(&buf[..n]);
+        assert!(body.starts_with("data: "));
+        assert!(body.contains("\"type\":\"alarm\""));
+    }
+
+    #[tokio::test]
+    async fn test_deliveries_as_json() {
+        let deliveries = Arc::new(Mutex::new(DeliveryTracker::default()));
+        let sent = std::time::Instant::now();
+        deliveries.lock().unwrap().sent("orchestrator", "registration/node_01", sent);
+        deliveries.lock().unwrap().answered("orchestrator", "registration/node_01", sent + std::time::Duration::from_millis(250));
+        let api = LocalApi::bind("127.0.0.1:0", EventBus::new()).await.unwrap().with_deliveries(deliveries);
+        let addr = api.local_addr().unwrap();
+        tokio::spawn(api.run());
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"GET /deliveries HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        let body: serde_json::Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body[0]["destination"], "orchestrator");
+        assert_eq!(body[0]["rtt_avg_ms"], 250);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_route_is_404() {
+        let api = LocalApi::bind("127.0.0.1:0", EventBus::new()).await.unwrap();
+        let addr = api.local_addr().unwrap();
+        tokio::spawn(api.run());
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"GET /nope HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+}
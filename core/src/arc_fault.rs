@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use crate::hal::adc::rms;
+use crate::harmonics::{self, Waveform};
+
+/// Current below which a circuit isn't checked: too little flows to tell arcing from noise
+pub const MIN_ARC_AMPS: f32 = 1.0;
+
+fn default_threshold() -> f32 {
+    0.15
+}
+
+fn default_samples() -> u32 {
+    3
+}
+
+/// How readily a relay's circuit is opened on arcing.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ArcFaultSettings {
+    /// Share of the current that changes from one mains cycle to the next above which a
+    /// sample looks like arcing (default 0.15)
+    #[serde(default = "default_threshold")]
+    pub threshold: f32,
+    /// Samples in a row that must look like arcing before the relay is opened (default 3),
+    /// so a load switching on isn't taken for one
+    #[serde(default = "default_samples")]
+    pub samples: u32,
+}
+
+impl Default for ArcFaultSettings {
+    fn default() -> Self {
+        Self { threshold: default_threshold(), samples: default_samples() }
+    }
+}
+
+/// Share of a current waveform that isn't repeated from one mains cycle to the next. A
+/// steady load, however distorted, repeats every cycle; an arc strikes and goes out at
+/// random, leaving flat spots and spikes that don't. None if the mains can't be found in it.
+pub fn erratic_share(waveform: &Waveform) -> Option<f32> {
+    let period = waveform.sample_rate_hz / harmonics::fundamental_hz(waveform)?;
+    let samples = &waveform.samples;
+    let signal = rms(samples);
+    if signal <= 0.0 || samples.len() < period.ceil() as usize + 2 {
+        return None;
+    }
+    let residual: Vec<f32> = (period.ceil() as usize..samples.len())
+        .map(|i| {
+            // The same point a cycle earlier, between samples
+            let back = i as f32 - period;
+            let (j, frac) = (back.floor() as usize, back.fract());
+            samples[i] - (samples[j] * (1.0 - frac) + samples[j + 1] * frac)
+        })
+        .collect();
+    let residual_rms = (residual.iter().map(|r| r * r).sum::<f32>() / residual.len() as f32).sqrt();
+    // Two independent cycles differ by √2 times their RMS
+    Some(residual_rms / (std::f32::consts::SQRT_2 * signal))
+}
+
+/// Watches circuits for arcing and keeps those opened on it open until an operator
+/// closes them again.
+#[derive(Debug, Default)]
+pub struct ArcDetector {
+    settings: HashMap<String, ArcFaultSettings>,
+    /// Samples in a row that looked like arcing, per relay
+    suspicious: HashMap<String, u32>,
+    locked_out: BTreeSet<String>,
+}
+
+impl ArcDetector {
+    pub fn new(settings: HashMap<String, ArcFaultSettings>) -> Self {
+        Self { settings, ..Default::default() }
+    }
+
+    pub fn watches(&self, relay_id: &str) -> bool {
+        self.settings.contains_key(relay_id)
+    }
+
+    /// Fold in a sample's erratic share, None if there was too little current to judge.
+    /// True once the relay has looked like arcing for long enough, and is now locked out.
+    pub fn observe(&mut self, relay_id: &str, erratic: Option<f32>) -> bool {
+        let Some(settings) = self.settings.get(relay_id) else { return false };
+        let count = self.suspicious.entry(relay_id.to_string()).or_default();
+        if erratic.is_some_and(|share| share > settings.threshold) {
+            *count += 1;
+        } else {
+            *count = 0;
+        }
+        if *count < settings.samples.max(1) {
+            return false;
+        }
+        *count = 0;
+        self.locked_out.insert(relay_id.to_string())
+    }
+
+    /// Relays opened on an arc, which nothing but an operator may close.
+    pub fn locked_out(&self) -> Vec<String> {
+        self.locked_out.iter().cloned().collect()
+    }
+
+    /// Lift a relay's lockout; false if it had none.
+    pub fn clear(&mut self, relay_id: &str) -> bool {
+        self.locked_out.remove(relay_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn current(arcing: bool) -> Waveform {
+        let sample_rate_hz = 960.0;
+        let mut seed: u32 = 7;
+        let samples = (0..256)
+            .map(|n| {
+                let angle = std::f32::consts::TAU * 60.0 * n as f32 / sample_rate_hz;
+                // A dimmer's chopped waveform: distorted, but the same every cycle
+                let steady = if angle.sin().abs() > 0.5 { 10.0 * angle.sin() } else { 0.0 };
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                let random = (seed >> 16) as f32 / 65_536.0;
+                if arcing && random < 0.4 { steady * random * 2.0 } else { steady }
+            })
+            .collect();
+        Waveform { samples, sample_rate_hz }
+    }
+
+    #[test]
+    fn test_erratic_current_locks_the_circuit_out() {
+        let steady = erratic_share(&current(false)).unwrap();
+        let arcing = erratic_share(&current(true)).unwrap();
+        assert!(steady < 0.05, "steady load read {}", steady);
+        assert!(arcing > 0.3, "arcing read {}", arcing);
+
+        let mut detector = ArcDetector::new(HashMap::from([("r_shed".to_string(), ArcFaultSettings::default())]));
+        assert!(!detector.watches("r_fridge"));
+        // A one-off, then too little current to judge, start the count again
+        assert!(!detector.observe("r_shed", Some(arcing)));
+        assert!(!detector.observe("r_shed", None));
+        assert!(!detector.observe("r_shed", Some(arcing)));
+        assert!(!detector.observe("r_shed", Some(arcing)));
+        assert!(detector.observe("r_shed", Some(arcing)));
+        assert_eq!(detector.locked_out(), vec!["r_shed".to_string()]);
+        assert!(detector.clear("r_shed"));
+        assert!(detector.locked_out().is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use thiserror::Error;
+use crate::storage::{Storage, StorageError};
+
+/// Storage key of the append-only audit log (one JSON entry per line).
+const AUDIT_KEY: &str = "audit.log";
+
+/// Why the audit log could not be read, written or trusted.
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error("audit entry JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("system clock: {0}")]
+    Clock(#[from] std::time::SystemTimeError),
+    #[error("entry {0} is missing its chain link")]
+    MissingLink(u64),
+    #[error("chain broken before entry {0}")]
+    ChainBroken(u64),
+}
+
+pub type Result<T, E = AuditError> = std::result::Result<T, E>;
+
+/// Authentication outcome of a received command.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureStatus {
+    Unsigned,
+    Valid,
+    Invalid,
+}
+
+/// Something worth reconstructing after an incident.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuditRecord {
+    Command {
+        source: String,
+        payload: String,
+        signature: SignatureStatus,
+    },
+    RelayActuation {
+        relay_id: String,
+        trigger: String,
+        from_closed: bool,
+        to_closed: bool,
+    },
+    Tamper {
+        opened: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub timestamp: i64,
+    /// Hex SHA-256 of the previous entry's JSON (zeros for the first); absent on
+    /// entries written before the log was chained
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub prev_hash: String,
+    #[serde(flatten)]
+    pub record: AuditRecord,
+}
+
+impl AuditEntry {
+    /// SHA-256 of the entry's JSON as written to disk and uploaded.
+    pub fn hash(&self) -> Result<[u8; 32]> {
+        Ok(Sha256::digest(serde_json::to_vec(self)?).into())
+    }
+}
+
+/// Latest point of the hash chain, published in heartbeats so later edits to the
+/// log on the SD card can be detected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AuditHead {
+    pub next_seq: u64,
+    pub hash: [u8; 32],
+}
+
+/// Append-only on-disk record of received commands and relay actuations.
+/// Each entry carries the hash of the one before it.
+pub struct AuditLog {
+    storage: Arc<Storage>,
+    next_seq: u64,
+    head: [u8; 32],
+}
+
+impl AuditLog {
+    /// Open the log, continuing the sequence numbering of existing entries.
+    pub fn open(storage: Arc<Storage>) -> Result<Self> {
+        let mut log = Self { storage, next_seq: 0, head: [0; 32] };
+        if let Some(last) = log.read_all()?.last() {
+            log.next_seq = last.seq + 1;
+            log.head = last.hash()?;
+        }
+        Ok(log)
+    }
+
+    /// Sequence number the next entry will get.
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    pub fn head(&self) -> AuditHead {
+        AuditHead { next_seq: self.next_seq, hash: self.head }
+    }
+
+    /// Continue numbering from at least `seq` (used when seeding replacement hardware).
+    pub fn advance_to(&mut self, seq: u64) {
+        self.next_seq = self.next_seq.max(seq);
+    }
+
+    pub fn record(&mut self, record: AuditRecord) -> Result<()> {
+        let entry = AuditEntry {
+            seq: self.next_seq,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs() as i64,
+            prev_hash: hex::encode(self.head),
+            record,
+        };
+        let line = serde_json::to_vec(&entry)?;
+        self.storage.append(AUDIT_KEY, &[line.as_slice(), b"\n"].concat())?;
+        self.head = Sha256::digest(&line).into();
+        self.next_seq += 1;
+        Ok(())
+    }
+
+    /// Check every link from the first chained entry on; fails at the first entry
+    /// that was edited, removed or inserted.
+    pub fn verify_chain(&self) -> Result<()> {
+        let mut prev: Option<AuditEntry> = None;
+        for entry in self.read_all()? {
+            if entry.prev_hash.is_empty() {
+                // Entries from before chaining are only allowed ahead of the chain
+                if prev.as_ref().is_some_and(|p| !p.prev_hash.is_empty()) {
+                    return Err(AuditError::MissingLink(entry.seq));
+                }
+            } else {
+                let expected = match &prev {
+                    Some(p) => p.hash()?,
+                    None => [0; 32],
+                };
+                if entry.prev_hash != hex::encode(expected) {
+                    return Err(AuditError::ChainBroken(entry.seq));
+                }
+            }
+            prev = Some(entry);
+        }
+        Ok(())
+    }
+
+    /// Entries with `seq >= since_seq`, oldest first, at most `max`.
+    pub fn entries_since(&self, since_seq: u64, max: usize) -> Result<Vec<AuditEntry>> {
+        Ok(self.read_all()?
+            .into_iter()
+            .filter(|e| e.seq >= since_seq)
+            .take(max)
+            .collect())
+    }
+
+    fn read_all(&self) -> Result<Vec<AuditEntry>> {
+        let Some(data) = self.storage.get(AUDIT_KEY)? else {
+            return Ok(Vec::new());
+        };
+        // A torn final line (power cut mid-write) is skipped rather than failing the whole log
+        Ok(data.split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| serde_json::from_slice(line).ok())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn temp_storage(name: &str) -> Arc<Storage> {
+        let dir = std::env::temp_dir().join(format!("streetgrid-audit-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        Storage::open(dir, Duration::ZERO).unwrap()
+    }
+
+    #[test]
+    fn test_records_are_sequenced_and_queryable() {
+        let storage = temp_storage("seq");
+        let mut log = AuditLog::open(storage.clone()).unwrap();
+        log.record(AuditRecord::Command {
+            source: "lora".to_string(),
+            payload: "LoadShed".to_string(),
+            signature: SignatureStatus::Unsigned,
+        }).unwrap();
+        log.record(AuditRecord::RelayActuation {
+            relay_id: "r_hvac".to_string(),
+            trigger: "load_shed".to_string(),
+            from_closed: true,
+            to_closed: false,
+        }).unwrap();
+        storage.flush().unwrap();
+
+        // Reopening continues numbering after the last entry
+        let mut reopened = AuditLog::open(storage).unwrap();
+        reopened.record(AuditRecord::RelayActuation {
+            relay_id: "r_hvac".to_string(),
+            trigger: "activate_by_priority".to_string(),
+            from_closed: false,
+            to_closed: true,
+        }).unwrap();
+
+        let entries = reopened.entries_since(1, 10).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].seq, 1);
+        assert_eq!(entries[1].seq, 2);
+        assert!(matches!(&entries[0].record, AuditRecord::RelayActuation { to_closed: false, .. }));
+        assert_eq!(reopened.entries_since(0, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_edits_break_the_hash_chain() {
+        let storage = temp_storage("chain");
+        let mut log = AuditLog::open(storage.clone()).unwrap();
+        for opened in [true, false, true] {
+            log.record(AuditRecord::Tamper { opened }).unwrap();
+        }
+        storage.flush().unwrap();
+        log.verify_chain().unwrap();
+
+        // Reopening resumes from the same head
+        let head = log.head();
+        assert_eq!(AuditLog::open(storage.clone()).unwrap().head(), head);
+        assert_eq!(head.hash, log.entries_since(2, 1).unwrap()[0].hash().unwrap());
+
+        // Rewrite the middle entry on disk, as someone with the SD card could
+        let data = String::from_utf8(storage.get(AUDIT_KEY).unwrap().unwrap()).unwrap();
+        let edited = data.replacen("\"opened\":false", "\"opened\":true", 1);
+        storage.put(AUDIT_KEY, edited.into_bytes()).unwrap();
+        assert!(log.verify_chain().is_err());
+    }
+}
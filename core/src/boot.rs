@@ -0,0 +1,1018 @@
+//! Assembling a node from its config.yaml: opening storage and the secure element, loading
+//! keys and identity, starting the transport and drivers, and attaching every configured
+//! feature to the [`EdgeNode`]. This is all the `streetgrid-firmware` binary does before
+//! running it.
+
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use log::{error, info, warn};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use crate::anomaly::{AnomalyDetector, DEFAULT_PERSIST_READINGS, DEFAULT_THRESHOLD_SIGMA, DEFAULT_WINDOW};
+use crate::api::LocalApi;
+use crate::arc_fault::ArcDetector;
+use crate::audit::AuditLog;
+use crate::capture::{CaptureError, CaptureLink, ReplayLink};
+use crate::clock::{Clock, SystemClock, VirtualClock};
+use crate::comms::{CommsError, CommunicationLayer, OrchestratorClient, TransportContext, TransportRegistry};
+use crate::config::{Config, StorageConfig};
+use crate::cycling::DutyCycler;
+use crate::election::Election;
+use crate::energy::{EnergyLedger, ENERGY_KEY};
+use crate::estop::EStopWatch;
+use crate::evse::EvCharger;
+use crate::export::SerialExporter;
+use crate::failover::{Failover, DEFAULT_TAKEOVER_AFTER_SECS};
+use crate::faults::{FaultSwitch, Faults, FaultyLink, FaultyRelays, FaultySensor};
+use crate::forecast::Forecaster;
+use crate::generator::{Generator, RunHours, GENERATOR_HOURS_KEY};
+use crate::hal::battery::mock::SimBattery;
+use crate::hal::vedirect::{BmsConfig, VeDirectBattery};
+use crate::hal::{
+    create_charger_control, create_emergency_stop, create_generator_control, create_inverter, create_lora_loopback,
+    create_lora_radio, create_power_sensor, create_relay_driver, create_secure_element, create_tamper_switch, AdcConfig,
+    EmergencyStopConfig, EvseConfig, GeneratorConfig, HalError, InverterConfig, PowerSensor, RelayControl, RelayPin,
+    SecureElement, SecureElementConfig, TamperConfig,
+};
+use crate::hil::{self, LoopbackReport};
+use crate::inverter::{Droop, SolarInverter};
+use crate::keys::{self, KeyError, Keyring, SealedKeyring, KEYRING_KEY, KEY_LEN, SEALED_KEYRING_KEY};
+use crate::link_budget::{LinkBudget, DEFAULT_MIN_FADE_MARGIN_DB};
+use crate::load_profile;
+use crate::measurements::{MeasurementStore, RetentionPolicy};
+use crate::motors::MotorStarter;
+use crate::multisig::{self, MultiSigPolicy};
+use crate::node::{EdgeNode, Identity, NodeError, IDENTITY_KEY};
+use crate::ota::{ImageVerifier, OtaUpdater};
+use crate::outages;
+use crate::phases::PhaseMonitor;
+use crate::pickup::ColdLoadPickup;
+use crate::ratelimit::{CommandLimiter, DEFAULT_LOCKOUT, DEFAULT_PER_MINUTE};
+use crate::relay_health::RelayHealth;
+use crate::repeater::Repeater;
+use crate::replay::{ReplayGuard, REPLAY_KEY};
+use crate::reserve::ReservePolicy;
+use crate::rftest::{self, RfTestReport};
+use crate::session::SessionTable;
+use crate::snapshot::{self, Calibration};
+use crate::sources::{SourceManager, DEFAULT_RESERVE_SOC};
+use crate::storage::{Storage, StorageError, DEFAULT_FLUSH_INTERVAL};
+use crate::submeter::SubMeter;
+use crate::sysinfo::{self, SystemMonitor};
+use crate::tariff::{CostShedder, Savings, TARIFF_SAVINGS_KEY};
+use crate::thermal::ThermalDerating;
+use crate::types::{MeshType, NodeRole, NodeState, RelayType};
+
+/// Why a node could not be assembled, or a bench test could not run.
+#[derive(Debug, Error)]
+pub enum BootError {
+    /// A command-line option out of range or not allowed here
+    #[error("{0}")]
+    Option(&'static str),
+    #[error("{0} needs a comms.lora section")]
+    NoLoRa(&'static str),
+    #[error("a repeater needs a comms section")]
+    NoComms,
+    #[error("replay: {0}")]
+    Replay(#[from] CaptureError),
+    #[error(transparent)]
+    Key(#[from] KeyError),
+    #[error(transparent)]
+    Comms(#[from] CommsError),
+    #[error(transparent)]
+    Hardware(#[from] HalError),
+    #[error("invalid node configuration: {0}")]
+    Node(#[from] NodeError),
+    /// A bench test's blocking task panicked
+    #[error("{0}")]
+    Interrupted(#[from] tokio::task::JoinError),
+}
+
+/// How the firmware was asked to run, on top of what config.yaml says.
+#[derive(Debug, Clone)]
+pub struct BootOptions {
+    /// Version of the running firmware, which OTA images must be newer than
+    pub firmware_version: &'static str,
+    /// Feed the node the received frames of this capture instead of the configured transport
+    pub replay: Option<String>,
+    /// Run the schedules this many times faster than real time (mock and simulation runs only)
+    pub time_scale: Option<f64>,
+    /// Inject recoverable faults with this chance each, and check the node's invariants
+    pub chaos: Option<f64>,
+    /// Seed for `chaos`, to repeat a run (random and logged when not given)
+    pub chaos_seed: Option<u64>,
+}
+
+/// What config.yaml describes, assembled and ready to run.
+pub enum Assembled {
+    Node {
+        node: Box<EdgeNode>,
+        /// The virtual clock and its speed-up, when running faster than real time
+        accelerated: Option<(VirtualClock, f64)>,
+    },
+    /// A node with no relays or sensors, that only forwards mesh traffic
+    Repeater(Box<Repeater>),
+}
+
+impl Assembled {
+    /// Build the node (or repeater) `config` describes. Optional hardware and features that
+    /// fail to start are logged and left out, so a node comes up with whatever does work.
+    pub async fn from_config(config: &Config, options: &BootOptions) -> Result<Self, BootError> {
+        // Accelerated time starts from now, and everything that timestamps follows it
+        let accelerated = match options.time_scale {
+            Some(scale) if scale.is_nan() || scale <= 0.0 => return Err(BootError::Option("--time-scale must be positive")),
+            Some(_) if cfg!(target_os = "linux") && config.hardware.is_some() => {
+                return Err(BootError::Option("--time-scale is for mock and simulation runs, not real hardware"));
+            }
+            Some(scale) => {
+                warn!("Running {}x faster than real time", scale);
+                Some((VirtualClock::at(SystemClock.unix()), scale))
+            }
+            None => None,
+        };
+        let clock: Arc<dyn Clock> = match &accelerated {
+            Some((clock, _)) => Arc::new(clock.clone()),
+            None => Arc::new(SystemClock),
+        };
+
+        info!("StreetGrid Firmware v{} - Multi-Relay Support", options.firmware_version);
+        info!("Node ID: {}", config.id);
+
+        let (faults, chaos) = fault_switch(config, options)?;
+        // Captures record what actually went over the air, so sit beneath injected faults
+        let capture_path = config.comms.as_ref().and_then(|c| c.capture.clone());
+        let wrap_transport = |layer: Arc<dyn CommunicationLayer>| -> Arc<dyn CommunicationLayer> {
+            let layer: Arc<dyn CommunicationLayer> = match &capture_path {
+                Some(path) => match CaptureLink::open(layer.clone(), path, clock.clone()) {
+                    Ok(capture) => {
+                        info!("Capturing all frames sent and received to {}", path);
+                        Arc::new(capture)
+                    }
+                    Err(e) => {
+                        warn!("Not capturing traffic: {}", e);
+                        layer
+                    }
+                },
+                None => layer,
+            };
+            match &faults {
+                Some(faults) => Arc::new(FaultyLink::new(layer, faults.clone())),
+                None => layer,
+            }
+        };
+
+        // Secure element: hardware RNG and identity key, and the key sealing the keyring at rest
+        let se_config = config.hardware.as_ref().and_then(|hw| hw.secure_element.as_ref()).map(|se| {
+            let defaults = SecureElementConfig::default();
+            SecureElementConfig {
+                i2c_bus: se.i2c_bus.unwrap_or(defaults.i2c_bus),
+                address: se.address.unwrap_or(defaults.address),
+                sign_slot: se.sign_slot.unwrap_or(defaults.sign_slot),
+                kek_slot: se.kek_slot.unwrap_or(defaults.kek_slot),
+            }
+        });
+        let (mut secure_element, kek) = open_secure_element(se_config.as_ref());
+
+        // Open storage first: a restored snapshot overrides relay metadata and calibration
+        let storage = config.storage.as_ref().and_then(|storage_config| open_storage(storage_config, kek.as_ref()));
+        let relays = match storage.as_deref().and_then(snapshot::load_relays) {
+            Some(relays) => {
+                info!("Using {} relays from restored snapshot", relays.len());
+                relays
+            }
+            None => config.relays.clone(),
+        };
+        let restored_calibration = storage.as_deref().and_then(snapshot::load_calibration);
+
+        // A node with a provisioning token stays in Joining until it has been issued an identity
+        let identity = match storage.as_ref().map(|s| s.get_json::<Identity>(IDENTITY_KEY)) {
+            Some(Ok(identity)) => identity,
+            Some(Err(e)) => {
+                warn!("Ignoring unreadable identity: {}", e);
+                None
+            }
+            None => None,
+        };
+        let provisioning_token = config.provisioning.as_ref().map(|p| p.token.clone());
+        let joining = provisioning_token.is_some() && identity.is_none();
+        let node_id = identity.map(|i| i.node_id).unwrap_or_else(|| config.id.clone());
+
+        // Initialize communications (IP transports load their TLS identity from storage)
+        let client: Option<OrchestratorClient> = if let Some(path) = &options.replay {
+            info!("Replaying received frames from {}", path);
+            Some(OrchestratorClient::new(Arc::new(ReplayLink::open(path, clock.clone())?)))
+        } else if let Some(comms_config) = &config.comms {
+            let context = TransportContext { node_id: &node_id, hardware: config.hardware.as_ref(), storage: storage.as_deref() };
+            TransportRegistry::default().create(comms_config, &context)?
+                .map(|layer| OrchestratorClient::new(wrap_transport(layer)))
+        } else {
+            None
+        };
+
+        let keyring = load_keyring(config, storage.as_deref(), kek.as_ref(), joining)?;
+        let client = match (client, &keyring) {
+            (Some(client), Some(keyring)) => Some(client.with_keyring(keyring.clone())),
+            (client, _) => client,
+        }.map(|client| client.with_origin(&node_id));
+
+        // A repeater has no relays or sensors: it only forwards mesh traffic
+        if config.node_type == Some(NodeRole::Repeater) {
+            let client = client.ok_or(BootError::NoComms)?;
+            let sysinfo = config.storage.as_ref().map(|s| SystemMonitor::new(&s.path)).unwrap_or_default();
+            return Ok(Self::Repeater(Box::new(Repeater::new(&node_id, client, sysinfo))));
+        }
+
+        let drivers = open_drivers(config, restored_calibration);
+        let (relay_driver, power_sensor) = match &faults {
+            Some(faults) => (
+                drivers.relay_driver.map(|d| Box::new(FaultyRelays::new(d, faults.clone())) as Box<dyn RelayControl>),
+                drivers.power_sensor.map(|s| Box::new(FaultySensor::new(s, faults.clone())) as Box<dyn PowerSensor>),
+            ),
+            None => (drivers.relay_driver, drivers.power_sensor),
+        };
+
+        let mut builder = EdgeNode::builder(&node_id)
+            .relays(relays)
+            .relay_pins(drivers.relay_pins)
+            .voltage_ref(drivers.voltage_ref)
+            .mesh_type(config.mesh_type.clone().unwrap_or_default());
+        if let Some(client) = client {
+            builder = builder.client(client);
+        }
+        if let Some(driver) = relay_driver {
+            builder = builder.relay_driver(driver);
+        }
+        if let Some(sensor) = power_sensor {
+            builder = builder.power_sensor(sensor);
+        }
+        if let Some(protection) = &config.protection {
+            info!("Grid protection: {:?}", protection);
+            builder = builder.protection(protection.clone());
+        }
+        let mut node = builder.build()?;
+        if accelerated.is_some() {
+            node.set_clock(clock.clone());
+        }
+        node.invariant_checks = chaos;
+
+        node.calibration = drivers.calibration;
+        node.keyring = keyring;
+        node.secure_element = secure_element.take();
+        node.sign_slot = se_config.as_ref().map(|c| c.sign_slot).unwrap_or_default();
+        node.kek = kek;
+        node.provisioning_token = provisioning_token;
+        if joining {
+            node.state = NodeState::Joining;
+        }
+        attach_hardware(&mut node, config);
+        attach_features(&mut node, config, options);
+        if let (Some(storage), Some(storage_config)) = (storage, &config.storage) {
+            attach_storage(&mut node, storage, storage_config);
+        }
+        attach_services(&mut node, config).await;
+
+        Ok(Self::Node { node: Box::new(node), accelerated })
+    }
+
+    /// Run until `stop` resolves; a node then shuts down into its configured safe state.
+    pub async fn run(self, stop: impl Future<Output = ()>) {
+        match self {
+            Self::Node { mut node, accelerated } => {
+                match accelerated {
+                    Some((clock, scale)) => node.run_accelerated(clock, scale, stop).await,
+                    None => node.run(stop).await,
+                }
+                node.shutdown().await;
+            }
+            Self::Repeater(mut repeater) => {
+                tokio::select! {
+                    _ = repeater.run() => {}
+                    _ = stop => {}
+                }
+            }
+        }
+    }
+}
+
+/// Injected hardware and radio faults, for exercising error handling on a bench, and
+/// whether they include chaos.
+fn fault_switch(config: &Config, options: &BootOptions) -> Result<(Option<FaultSwitch>, bool), BootError> {
+    let mut fault_config = config.faults.clone();
+    if let Some(chaos) = options.chaos {
+        if !(chaos > 0.0 && chaos <= 1.0) {
+            return Err(BootError::Option("--chaos must be a probability between 0 and 1"));
+        }
+        fault_config.get_or_insert_with(Faults::default).chaos = Some(chaos);
+    }
+    let chaos = fault_config.as_ref().is_some_and(|f| f.chaos.is_some());
+    let Some(f) = fault_config else {
+        return Ok((None, chaos));
+    };
+    let seed = match options.chaos_seed {
+        Some(seed) => seed,
+        None => {
+            let mut seed = [0u8; 8];
+            keys::random_bytes(None, &mut seed)?;
+            u64::from_le_bytes(seed)
+        }
+    };
+    warn!("Fault injection enabled: {:?}", f);
+    if chaos {
+        warn!("Chaos seed {}: pass --chaos-seed to repeat this run", seed);
+    }
+    Ok((Some(FaultSwitch::with_seed(f, seed)), chaos))
+}
+
+/// Open the secure element and derive the key sealing the keyring from it; either is None
+/// (logged) when the chip can't be used.
+fn open_secure_element(se_config: Option<&SecureElementConfig>) -> (Option<Box<dyn SecureElement>>, Option<[u8; KEY_LEN]>) {
+    let mut secure_element = se_config.and_then(|cfg| match create_secure_element(cfg) {
+        Ok(se) => Some(se),
+        Err(e) => {
+            warn!("Secure element not available, falling back to file-based keys: {}", e);
+            None
+        }
+    });
+    let kek = match (&mut secure_element, se_config) {
+        (Some(se), Some(cfg)) => match keys::derive_kek(se.as_mut(), cfg.kek_slot) {
+            Ok(kek) => {
+                if let Ok(serial) = se.serial_number() {
+                    info!("Secure element {} holds the mesh keys", hex::encode(serial));
+                }
+                Some(kek)
+            }
+            Err(e) => {
+                warn!("Secure element key derivation failed, keys stay file-based: {}", e);
+                None
+            }
+        },
+        _ => None,
+    };
+    (secure_element, kek)
+}
+
+/// Open the storage directory, encrypted if configured; None (logged) if it can't be.
+fn open_storage(storage_config: &StorageConfig, kek: Option<&[u8; KEY_LEN]>) -> Option<Arc<Storage>> {
+    let flush_interval = storage_config.flush_interval_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_FLUSH_INTERVAL);
+    let opened = if storage_config.encrypt.unwrap_or(false) {
+        // Prefer a key only the secure element can reproduce; else a key file kept off the card
+        let key = match (kek, &storage_config.key_file) {
+            (Some(kek), _) => Ok(keys::derive_storage_key(kek)),
+            (None, Some(path)) => keys::load_key_file(path).map_err(StorageError::from),
+            (None, None) => {
+                warn!("Failed to open storage at {}: encryption needs a secure element or storage.key_file", storage_config.path);
+                return None;
+            }
+        };
+        key.and_then(|key| Storage::open_encrypted(&storage_config.path, flush_interval, &key))
+    } else {
+        Storage::open(&storage_config.path, flush_interval)
+    };
+    match opened {
+        Ok(storage) => {
+            info!("Persistent storage at {} (flush every {:?}, {})", storage_config.path, flush_interval,
+                  if storage.is_encrypted() { "encrypted" } else { "unencrypted" });
+            Some(storage)
+        }
+        Err(e) => {
+            warn!("Failed to open storage at {}: {}", storage_config.path, e);
+            None
+        }
+    }
+}
+
+/// Mesh keys: a keyring persisted by an earlier rotation wins over the configured PSK.
+fn load_keyring(
+    config: &Config,
+    storage: Option<&Storage>,
+    kek: Option<&[u8; KEY_LEN]>,
+    joining: bool,
+) -> Result<Option<Arc<Mutex<Keyring>>>, BootError> {
+    let sealed_keyring = match (storage, kek) {
+        (Some(storage), Some(kek)) => match storage.get_json::<SealedKeyring>(SEALED_KEYRING_KEY) {
+            Ok(sealed) => sealed.map(|s| s.unseal(kek)).transpose().unwrap_or_else(|e| {
+                warn!("Ignoring sealed keyring: {}", e);
+                None
+            }),
+            Err(e) => {
+                warn!("Ignoring unreadable sealed keyring: {}", e);
+                None
+            }
+        },
+        _ => None,
+    };
+    let persisted_keyring = match sealed_keyring {
+        Some(keyring) => Some(keyring),
+        // A plain keyring is migrated into the secure element's protection on the next save
+        None => match storage.map(|s| s.get_json::<Keyring>(KEYRING_KEY)) {
+            Some(Ok(keyring)) => keyring,
+            Some(Err(e)) => {
+                warn!("Ignoring unreadable keyring: {}", e);
+                None
+            }
+            None => None,
+        },
+    };
+    let keyring = match (persisted_keyring, config.security.as_ref()) {
+        _ if joining => None, // Keys are issued on join
+        (Some(keyring), _) => Some(keyring),
+        (None, Some(security)) => match &security.psk {
+            Some(psk) => Some(Keyring::from_hex(security.key_epoch.unwrap_or(1), psk)?),
+            None => None,
+        },
+        (None, None) => None,
+    }.map(|k| Arc::new(Mutex::new(k)));
+    match &keyring {
+        None if joining => info!("Awaiting commissioning: node boots into Joining"),
+        Some(k) => info!("Mesh authentication enabled (key epoch {})", k.lock().unwrap().current_epoch()),
+        None => warn!("No mesh PSK configured: commands are accepted unsigned"),
+    }
+    Ok(keyring)
+}
+
+/// Relay and ADC drivers, with what the builder needs to know about them.
+struct Drivers {
+    relay_driver: Option<Box<dyn RelayControl>>,
+    relay_pins: HashMap<String, u8>,
+    power_sensor: Option<Box<dyn PowerSensor>>,
+    voltage_ref: f32,
+    calibration: Option<Calibration>,
+}
+
+/// Initialize the relay and ADC drivers; a restored calibration overrides the configured one.
+fn open_drivers(config: &Config, restored_calibration: Option<Calibration>) -> Drivers {
+    let Some(hw_config) = &config.hardware else {
+        return Drivers { relay_driver: None, relay_pins: HashMap::new(), power_sensor: None, voltage_ref: 120.0, calibration: None };
+    };
+    let relay_pins = hw_config.relay_pins.clone().unwrap_or_default();
+    let feedback_pins = hw_config.relay_feedback_pins.clone().unwrap_or_default();
+    for relay_id in feedback_pins.keys().filter(|id| !relay_pins.contains_key(*id)) {
+        warn!("Ignoring feedback pin for {}: it has no relay pin", relay_id);
+    }
+    let relay_pin_configs: Vec<RelayPin> = relay_pins.iter()
+        .map(|(id, pin)| RelayPin {
+            relay_id: id.clone(),
+            gpio_pin: *pin,
+            active_low: false, // Default to active-high
+            feedback_pin: feedback_pins.get(id).copied(),
+        })
+        .collect();
+
+    let relay_driver = if !relay_pin_configs.is_empty() {
+        match create_relay_driver(&relay_pin_configs) {
+            Ok(d) => Some(d),
+            Err(e) => {
+                warn!("Failed to initialize relay driver: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let Some(adc_config) = &hw_config.adc else {
+        return Drivers { relay_driver, relay_pins, power_sensor: None, voltage_ref: 120.0, calibration: None };
+    };
+    let cal = restored_calibration.unwrap_or(Calibration {
+        ct_ratio: adc_config.ct_ratio.unwrap_or(100.0),
+        burden_resistor: adc_config.burden_resistor.unwrap_or(33.0),
+        voltage_ref: adc_config.voltage_ref.unwrap_or(120.0),
+    });
+    let adc_cfg = AdcConfig {
+        i2c_bus: adc_config.i2c_bus.unwrap_or(1),
+        address: adc_config.address.unwrap_or(0x48),
+        ct_ratio: cal.ct_ratio,
+        voltage_ref: cal.voltage_ref,
+        burden_resistor: cal.burden_resistor,
+        voltage_channel: adc_config.voltage_channel,
+        voltage_ratio: adc_config.voltage_ratio,
+    };
+    let voltage_ref = adc_cfg.voltage_ref;
+    let power_sensor = match create_power_sensor(adc_cfg) {
+        Ok(s) => Some(s),
+        Err(e) => {
+            warn!("Failed to initialize power sensor: {}", e);
+            None
+        }
+    };
+    Drivers { relay_driver, relay_pins, power_sensor, voltage_ref, calibration: Some(cal) }
+}
+
+/// Attach the optional hardware under `hardware`: CT wiring, sub-meter, tamper switch,
+/// emergency stop, EV charger, inverter, battery management and generator.
+fn attach_hardware(node: &mut EdgeNode, config: &Config) {
+    let hardware = config.hardware.as_ref();
+    node.ct_channels = hardware.and_then(|hw| hw.ct_channels.clone()).unwrap_or_default();
+    node.ct_directions = hardware
+        .and_then(|hw| hw.adc.as_ref())
+        .and_then(|adc| adc.ct_directions.clone())
+        .unwrap_or_default();
+    if let Some(circuits) = hardware.and_then(|hw| hw.submeter.as_ref()) {
+        let adc_config = hardware.and_then(|hw| hw.adc.as_ref());
+        let cal = node.calibration.clone().unwrap_or(Calibration { ct_ratio: 100.0, burden_resistor: 33.0, voltage_ref: 120.0 });
+        let main_address = adc_config.and_then(|adc| adc.address).unwrap_or(0x48);
+        let addresses: BTreeSet<u8> = circuits.values().map(|c| c.address.unwrap_or(main_address)).collect();
+        let mut chips: BTreeMap<u8, Box<dyn PowerSensor>> = BTreeMap::new();
+        for address in addresses {
+            let chip_config = AdcConfig {
+                i2c_bus: adc_config.and_then(|adc| adc.i2c_bus).unwrap_or(1),
+                address,
+                ct_ratio: cal.ct_ratio,
+                voltage_ref: cal.voltage_ref,
+                burden_resistor: cal.burden_resistor,
+                // Only the main ADC samples the mains voltage
+                voltage_channel: adc_config.and_then(|adc| adc.voltage_channel).filter(|_| address == main_address),
+                voltage_ratio: adc_config.and_then(|adc| adc.voltage_ratio).filter(|_| address == main_address),
+            };
+            match create_power_sensor(chip_config) {
+                Ok(chip) => {
+                    chips.insert(address, chip);
+                }
+                Err(e) => warn!("Sub-meter ADC at {:#04x} not available: {}", address, e),
+            }
+        }
+        match SubMeter::new(chips, circuits, main_address, cal.ct_ratio, cal.burden_resistor) {
+            Ok(submeter) => {
+                info!("Sub-metering {} circuits", circuits.len());
+                node.submeter = Some(submeter);
+            }
+            Err(e) => warn!("Not sub-metering: {}", e),
+        }
+    }
+    if let Some(always_drawing) = hardware.and_then(|hw| hw.always_drawing.as_ref()) {
+        let mut valid = Vec::new();
+        for relay_id in always_drawing {
+            if !node.ct_channels.contains_key(relay_id) {
+                warn!("Not checking {} for drawing nothing: it has no CT channel", relay_id);
+            } else {
+                valid.push(relay_id.clone());
+            }
+        }
+        node.relay_health = RelayHealth::new(valid);
+    }
+    if let Some(tamper_config) = hardware.and_then(|hw| hw.tamper.as_ref()) {
+        let switch_config = TamperConfig {
+            gpio_pin: tamper_config.gpio_pin,
+            active_low: tamper_config.active_low.unwrap_or(false),
+        };
+        match create_tamper_switch(&switch_config) {
+            Ok(switch) => {
+                info!("Tamper switch on GPIO {}", switch_config.gpio_pin);
+                node.tamper = Some(switch);
+                node.tamper_safe_state = tamper_config.safe_state.clone().unwrap_or_default();
+            }
+            Err(e) => warn!("Tamper switch not available: {}", e),
+        }
+    }
+    if let Some(estop_config) = hardware.and_then(|hw| hw.emergency_stop.as_ref()) {
+        let input_config = EmergencyStopConfig {
+            gpio_pin: estop_config.gpio_pin,
+            active_low: estop_config.active_low.unwrap_or(false),
+        };
+        match (create_emergency_stop(&input_config), &node.relay_driver) {
+            (Ok(input), Some(driver)) => {
+                info!("Emergency stop on GPIO {}", input_config.gpio_pin);
+                let pins = node.relay_pins.values().copied().collect();
+                node.estop = Some(EStopWatch::spawn(input, driver.shared(), pins));
+            }
+            (Ok(_), None) => warn!("Emergency stop not armed: no relay driver"),
+            (Err(e), _) => warn!("Emergency stop not available: {}", e),
+        }
+    }
+    if let Some(evse_config) = hardware.and_then(|hw| hw.evse.as_ref()) {
+        let charger_config = EvseConfig { pwm_channel: evse_config.pwm_channel.unwrap_or(0) };
+        match create_charger_control(&charger_config) {
+            Ok(control) if node.relays.iter().any(|r| r.id == evse_config.relay_id && r.relay_type == RelayType::Load) => {
+                info!("EV charger on {} limited through PWM channel {}", evse_config.relay_id, charger_config.pwm_channel);
+                node.ev_charger = Some(EvCharger::new(&evse_config.relay_id, evse_config.max_amps, control));
+            }
+            Ok(_) => warn!("EV charger relay {} is not one of our load relays", evse_config.relay_id),
+            Err(e) => warn!("EV charger control not available: {}", e),
+        }
+    }
+    if let Some(inverter_config) = hardware.and_then(|hw| hw.inverter.as_ref()) {
+        let defaults = InverterConfig::default();
+        let modbus_config = InverterConfig {
+            tcp: inverter_config.tcp.clone(),
+            serial_port: inverter_config.serial_port.clone(),
+            baud_rate: inverter_config.baud_rate.unwrap_or(defaults.baud_rate),
+            unit_id: inverter_config.unit_id.unwrap_or(defaults.unit_id),
+            base_address: inverter_config.base_address.unwrap_or(defaults.base_address),
+        };
+        match create_inverter(&modbus_config) {
+            Ok(control) => {
+                let droop = Droop {
+                    curtail_hz: inverter_config.curtail_start_hz.zip(inverter_config.curtail_stop_hz),
+                    curtail_volts: inverter_config.curtail_start_volts.zip(inverter_config.curtail_stop_volts),
+                    volt_var: inverter_config.volt_var_deadband_volts.zip(inverter_config.volt_var_full_volts),
+                };
+                info!("SunSpec inverter connected; droops {:?}", droop);
+                node.inverter = Some(SolarInverter::new(control, droop));
+            }
+            Err(e) => warn!("Solar inverter not available: {}", e),
+        }
+    }
+    if let Some(phases) = hardware.and_then(|hw| hw.phases.as_ref()) {
+        let mut valid = BTreeMap::new();
+        for (phase, sensing) in phases {
+            if sensing.max_amps.is_some_and(|amps| amps <= 0.0) {
+                warn!("Not sensing phase {}: max_amps must be positive", phase.as_str());
+            } else {
+                info!("Sensing phase {} on channel {}{}", phase.as_str(), sensing.current_channel,
+                      sensing.max_amps.map(|amps| format!(", shedding above {} A", amps)).unwrap_or_default());
+                valid.insert(*phase, sensing.clone());
+            }
+        }
+        if node.power_sensor.is_none() {
+            warn!("Phases configured without an ADC; they won't be measured");
+        }
+        node.phase_monitor = Some(PhaseMonitor::new(valid));
+    }
+    if let Some(generator_config) = hardware.and_then(|hw| hw.generator.as_ref()) {
+        let control_config = GeneratorConfig {
+            start_pin: generator_config.start_pin,
+            sense_pin: generator_config.sense_pin,
+            sense_active_low: generator_config.sense_active_low.unwrap_or(false),
+        };
+        match create_generator_control(&control_config) {
+            Ok(control) if node.relays.iter().any(|r| r.id == generator_config.transfer_relay && r.relay_type == RelayType::Source) => {
+                let hours = match node.storage.as_ref().map(|s| s.get_json::<RunHours>(GENERATOR_HOURS_KEY)) {
+                    Some(Ok(Some(run_hours))) => run_hours.hours,
+                    Some(Err(e)) => {
+                        warn!("Ignoring unreadable generator run hours: {}", e);
+                        0.0
+                    }
+                    _ => 0.0,
+                };
+                info!("Generator on {} ({:.1} run hours)", generator_config.transfer_relay, hours);
+                node.generator = Some(Generator::new(&generator_config.transfer_relay, generator_config.settings(), control, hours));
+            }
+            Ok(_) => warn!("Generator transfer relay {} is not one of our source relays", generator_config.transfer_relay),
+            Err(e) => warn!("Generator control not available: {}", e),
+        }
+    }
+}
+
+/// Attach the configured node features: security policy, mesh roles, power sources,
+/// load management and protection.
+fn attach_features(node: &mut EdgeNode, config: &Config, options: &BootOptions) {
+    if let Some(ota_config) = &config.ota {
+        match ImageVerifier::new(&ota_config.release_keys, &ota_config.hardware, options.firmware_version) {
+            Ok(verifier) => node.ota = Some(OtaUpdater::new(verifier, &ota_config.install_path)),
+            Err(e) => warn!("OTA disabled: {}", e),
+        }
+    }
+    node.hardware_id = sysinfo::hardware_id();
+    node.bound_hardware_id = config.security.as_ref().and_then(|s| s.hardware_id.clone());
+    match (&node.hardware_id, &node.bound_hardware_id) {
+        (_, Some(bound)) if !node.hardware_binding_ok() => {
+            error!("Identity is bound to hardware {} but this board is {:?}", bound, node.hardware_id);
+        }
+        (Some(id), _) => info!("Hardware ID: {}", id),
+        (None, _) => warn!("Could not determine hardware ID"),
+    }
+    if config.security.as_ref().and_then(|s| s.require_sessions).unwrap_or(false) {
+        if node.keyring.is_none() {
+            warn!("Sessions required without a mesh key: session grants cannot be authenticated");
+        }
+        node.sessions = Some(SessionTable::default());
+    }
+    if let Some(multisig_config) = config.security.as_ref().and_then(|s| s.multisig.as_ref()) {
+        let commands = multisig_config.commands.clone()
+            .unwrap_or_else(|| multisig::default_commands(&node.mesh_type));
+        match MultiSigPolicy::new(&multisig_config.signers, multisig_config.threshold, commands.clone()) {
+            Ok(policy) => {
+                info!("{}-of-{} approval required for {:?}", multisig_config.threshold, multisig_config.signers.len(), commands);
+                node.multisig = Some(policy);
+            }
+            // Fail closed: without a usable policy these commands are never obeyed
+            Err(e) => {
+                error!("Invalid multi-signature policy: {}", e);
+                node.multisig = Some(MultiSigPolicy::deny_all(commands));
+            }
+        }
+    }
+    if let Some(lora) = config.comms.as_ref().and_then(|c| c.lora.as_ref()) {
+        let budget = LinkBudget::new(&lora.hal_config(None), lora.antenna_gain_dbi.unwrap_or(0.0));
+        node.min_fade_margin_db = lora.min_fade_margin_db.unwrap_or(DEFAULT_MIN_FADE_MARGIN_DB);
+        info!("LoRa link budget {:.1} dB (sensitivity {:.1} dBm), about {:.0} m with {:.0} dB fade margin",
+              budget.max_path_loss_db, budget.sensitivity_dbm, budget.range_m(node.min_fade_margin_db), node.min_fade_margin_db);
+        node.link_budget = Some(budget);
+    }
+    if let Some(islanding) = &config.islanding {
+        info!("Islanding when {} neighbours agree on under-voltage", islanding.quorum);
+        node.island_quorum = Some(islanding.quorum);
+    }
+    if config.election.as_ref().is_some_and(|e| e.enabled) {
+        node.election = Some(Election::new(&node.id, node.clock.unix()));
+    }
+    if let Some(sources) = &config.sources {
+        node.sources = Some(SourceManager::new(
+            sources.battery_capacity_wh.unwrap_or(0.0),
+            sources.reserve_soc.unwrap_or(DEFAULT_RESERVE_SOC),
+            sources.solar_peak_watts.unwrap_or(0.0),
+        ));
+        // On real hardware without a management bus configured, the battery is reported full
+        if let Some(battery) = sources.battery().filter(|_| !(cfg!(target_os = "linux") && config.hardware.is_some())) {
+            info!("Simulating a {:.0} Wh battery at {:.0}% charge", battery.capacity_wh, battery.initial_soc * 100.0);
+            node.battery = Some(Box::new(SimBattery::new(battery)));
+        }
+    }
+    if let Some(bms) = config.hardware.as_ref().and_then(|hw| hw.bms.as_ref()) {
+        node.battery = Some(Box::new(VeDirectBattery::open(BmsConfig {
+            serial_port: bms.serial_port.clone(),
+            max_charge_amps: bms.max_charge_amps,
+            max_discharge_amps: bms.max_discharge_amps,
+            cutoff_voltage: bms.cutoff_voltage,
+        })));
+    }
+    if config.demand_response.as_ref().is_some_and(|dr| dr.opt_out) {
+        info!("Opted out of utility demand-response events");
+        node.demand_response.opt_out = true;
+    }
+    if let Some(strategy) = config.demand_response.as_ref().and_then(|dr| dr.strategy) {
+        info!("Shedding for demand-response events by {:?}", strategy);
+        node.demand_response.strategy = strategy;
+    }
+    if let Some(cycles) = config.demand_response.as_ref().and_then(|dr| dr.duty_cycle.as_ref()) {
+        let mut valid = HashMap::new();
+        for (relay_id, cycle) in cycles {
+            if !node.relays.iter().any(|r| r.id == *relay_id && r.relay_type == RelayType::Load) {
+                warn!("Not cycling {}: no such load relay", relay_id);
+            } else if cycle.on_minutes == 0 || cycle.off_minutes == 0 {
+                warn!("Not cycling {}: on and off periods must be at least a minute", relay_id);
+            } else {
+                info!("Cycling {} through demand-response events: {} min on, {} min off", relay_id, cycle.on_minutes, cycle.off_minutes);
+                valid.insert(relay_id.clone(), *cycle);
+            }
+        }
+        node.duty_cycler = DutyCycler::new(valid);
+    }
+    if let Some(tariff_config) = &config.tariff {
+        let savings = match node.storage.as_ref().map(|s| s.get_json::<Savings>(TARIFF_SAVINGS_KEY)) {
+            Some(Ok(Some(savings))) => savings,
+            Some(Err(e)) => {
+                warn!("Ignoring unreadable tariff savings: {}", e);
+                Savings::default()
+            }
+            _ => Savings::default(),
+        };
+        let ceilings = tariff_config.defer_above.clone().unwrap_or_default();
+        info!("Time-of-use tariff with {} periods; deferring {:?} loads through peaks", tariff_config.periods.len(), ceilings.keys().collect::<Vec<_>>());
+        node.cost_shedder = Some(CostShedder::new(tariff_config.tariff(), ceilings, savings));
+    }
+    if config.forecast.as_ref().is_some_and(|f| f.enabled) {
+        info!("Shedding ahead of the next hour's forecast load");
+        node.forecaster = Some(Forecaster::default());
+    }
+    node.zones = config.zones.clone().unwrap_or_default();
+    node.phase = config.phase;
+    if let Some(arc_fault) = &config.arc_fault {
+        let mut valid = HashMap::new();
+        for (relay_id, settings) in arc_fault {
+            if !node.relays.iter().any(|r| r.id == *relay_id && r.relay_type == RelayType::Load) {
+                warn!("Not watching {} for arcing: no such load relay", relay_id);
+            } else if !node.ct_channels.contains_key(relay_id) {
+                warn!("Not watching {} for arcing: it has no CT channel", relay_id);
+            } else if settings.threshold <= 0.0 {
+                warn!("Not watching {} for arcing: threshold must be positive", relay_id);
+            } else {
+                info!("Watching {} for arcing above {:.0}% erratic current", relay_id, settings.threshold * 100.0);
+                valid.insert(relay_id.clone(), *settings);
+            }
+        }
+        node.arc_detector = Some(ArcDetector::new(valid));
+    }
+    if let Some(thermal) = &config.thermal {
+        if thermal.max_amps <= 0.0 {
+            warn!("Not derating for temperature: max_amps must be positive");
+        } else if thermal.full_derate_c <= thermal.derate_above_c {
+            warn!("Not derating for temperature: full_derate_c must be above derate_above_c");
+        } else if !(thermal.derated_share > 0.0 && thermal.derated_share <= 1.0) {
+            warn!("Not derating for temperature: derated_share must be above 0 and at most 1");
+        } else {
+            info!("Derating the relays from {:.0} A above {:.0} °C, read from {}", thermal.max_amps, thermal.derate_above_c, thermal.sensor);
+            node.thermal = Some(ThermalDerating::new(thermal.clone()));
+        }
+    }
+    if let Some(pickup) = &config.cold_load_pickup {
+        if pickup.max_amps_per_minute <= 0.0 {
+            warn!("Not pacing restores: max_amps_per_minute must be positive");
+        } else if !(pickup.settled_share > 0.0 && pickup.settled_share < 1.0) {
+            warn!("Not pacing restores: settled_share must be between 0 and 1");
+        } else {
+            info!("Pacing restored loads to {:.0} A of pickup a minute", pickup.max_amps_per_minute);
+            node.cold_load_pickup = Some(ColdLoadPickup::new(*pickup));
+        }
+    }
+    if let Some(reserve) = &config.battery_reserve {
+        if reserve.tiers.iter().any(|t| !(t.below_soc > 0.0 && t.below_soc <= 1.0)) {
+            warn!("Not applying battery reserve tiers: below_soc must be above 0 and at most 1");
+        } else if !(0.0..0.5).contains(&reserve.recovery_margin) {
+            warn!("Not applying battery reserve tiers: recovery_margin must be from 0 up to 0.5");
+        } else {
+            info!("Battery reserve tiers while islanded: {}", reserve.tiers.iter()
+                .map(|t| format!("{} below {:.0}%", t.action.as_str(), t.below_soc * 100.0))
+                .collect::<Vec<_>>()
+                .join(", "));
+            node.reserve = Some(ReservePolicy::new(reserve.clone()));
+        }
+    }
+    if let Some(motors) = &config.motors {
+        let is_load = |id: &str| node.relays.iter().any(|r| r.id == id && r.relay_type == RelayType::Load);
+        let mut relays = motors.loads.iter().flat_map(|(id, m)| std::iter::once(id).chain(&m.soft_start_relay));
+        if let Some(id) = relays.find(|id| !is_load(id)) {
+            warn!("Not sequencing motor starts: {} is not a load relay", id);
+        } else if motors.loads.values().any(|m| m.soft_start_secs < 0 || m.min_off_secs < 0) || motors.stagger_secs < 0 {
+            warn!("Not sequencing motor starts: times can't be negative");
+        } else {
+            info!("Starting {} motor loads at least {} s apart", motors.loads.len(), motors.stagger_secs);
+            node.motors = Some(MotorStarter::new(motors.clone(), node.clock.unix()));
+        }
+    }
+    if let Some(safe_state) = config.shutdown.as_ref().and_then(|s| s.safe_state.as_ref()) {
+        info!("On shutdown, relays go to {:?}", safe_state);
+        node.shutdown_safe_state = safe_state.clone();
+    }
+    if config.shutdown.as_ref().and_then(|s| s.resume_on_boot).unwrap_or(false) {
+        info!("A restart resumes the relay positions from before shutdown");
+        node.resume_on_boot = true;
+    }
+    if let Some(failover) = &config.failover {
+        info!("Following orchestrator {} with {} on standby", failover.primary, failover.secondary);
+        let takeover_after = failover.takeover_after_secs.unwrap_or(DEFAULT_TAKEOVER_AFTER_SECS);
+        node.failover = Some(Failover::new(&failover.primary, &failover.secondary, takeover_after, node.clock.unix()));
+    }
+    node.role = config.node_type.unwrap_or_default();
+    if node.role == NodeRole::Mid {
+        info!("Acting as the transformer's MID: Grid relays are the transformer breaker");
+        let mut seed = [0u8; 32];
+        match config.mid.as_ref().and_then(|m| m.signing_key.as_deref()) {
+            Some(key) if hex::decode_to_slice(key.trim(), &mut seed).is_ok() => node.mid_key = Some(SigningKey::from_bytes(&seed)),
+            Some(_) => warn!("Not sending MID status: mid.signing_key is not 32 bytes of hex"),
+            None => warn!("Not sending MID status: no mid.signing_key"),
+        }
+    } else if let Some(mid) = &config.mid {
+        if node.mesh_type == MeshType::GovernmentSanctioned {
+            info!("Grid relays follow MID {}", mid.id);
+        } else {
+            warn!("mid is only used on GovernmentSanctioned meshes; ignoring it");
+        }
+        let mut bytes = [0u8; 32];
+        match mid.public_key.as_deref() {
+            Some(key) if hex::decode_to_slice(key.trim(), &mut bytes).is_ok() => match VerifyingKey::from_bytes(&bytes) {
+                Ok(key) => {
+                    if let Some(client) = &node.client {
+                        client.trust_mid(&mid.id, key);
+                    }
+                }
+                Err(_) => warn!("mid.public_key is not an Ed25519 key; ignoring its status"),
+            },
+            Some(_) => warn!("mid.public_key is not 32 bytes of hex; ignoring its status"),
+            None => warn!("No mid.public_key; ignoring its status"),
+        }
+        node.mid_id = Some(mid.id.clone());
+    }
+    if node.mesh_type == MeshType::AdHoc && node.election.is_some() {
+        node.energy = Some(match node.storage.as_ref().map(|s| s.get_json::<EnergyLedger>(ENERGY_KEY)) {
+            Some(Ok(Some(ledger))) => ledger,
+            Some(Err(e)) => {
+                warn!("Ignoring unreadable energy ledger: {}", e);
+                EnergyLedger::default()
+            }
+            _ => EnergyLedger::default(),
+        });
+    }
+    if let Some(transfer) = &config.transfer {
+        if let Some(dead_time_ms) = transfer.dead_time_ms {
+            node.transfer.dead_time = Duration::from_millis(dead_time_ms);
+        }
+        match &transfer.inverter_relay {
+            Some(relay_id) if node.relays.iter().any(|r| r.id == *relay_id && r.relay_type == RelayType::Source) => {
+                info!("Grid-forming inverter on {}", relay_id);
+                node.transfer.inverter_relay = Some(relay_id.clone());
+            }
+            Some(relay_id) => warn!("Inverter relay {} is not one of our source relays", relay_id),
+            None => {}
+        }
+        info!("Source transfers leave {:?} dead time", node.transfer.dead_time);
+    }
+    if let Some(rate_config) = &config.rate_limit {
+        node.limiter = CommandLimiter::new(
+            rate_config.per_minute.unwrap_or(DEFAULT_PER_MINUTE),
+            rate_config.overrides.clone().unwrap_or_default(),
+            rate_config.lockout_secs.map(Duration::from_secs).unwrap_or(DEFAULT_LOCKOUT),
+        );
+    }
+    if let Some(anomaly_config) = &config.anomaly {
+        node.anomaly = AnomalyDetector::new(
+            anomaly_config.window.unwrap_or(DEFAULT_WINDOW),
+            anomaly_config.threshold_sigma.unwrap_or(DEFAULT_THRESHOLD_SIGMA),
+        ).with_persistence(anomaly_config.persist_readings.unwrap_or(DEFAULT_PERSIST_READINGS));
+    }
+}
+
+/// Hand the node its storage, with the background flusher, the audit log, the measurement
+/// store and its compaction, and the history persisted across reboots.
+fn attach_storage(node: &mut EdgeNode, storage: Arc<Storage>, storage_config: &StorageConfig) {
+    tokio::spawn(storage.clone().run_flusher());
+    node.sysinfo = SystemMonitor::new(&storage_config.path);
+    match AuditLog::open(storage.clone()) {
+        Ok(audit) => {
+            if let Err(e) = audit.verify_chain() {
+                error!("Audit log failed verification, it may have been edited: {}", e);
+            }
+            node.audit = Some(audit);
+        }
+        Err(e) => warn!("Failed to open audit log: {}", e),
+    }
+
+    let retention = storage_config.retention.clone();
+    let defaults = RetentionPolicy::default();
+    let policy = RetentionPolicy {
+        raw_days: retention.as_ref().and_then(|r| r.raw_days).unwrap_or(defaults.raw_days),
+        hourly_months: retention.as_ref().and_then(|r| r.hourly_months).unwrap_or(defaults.hourly_months),
+        max_bytes: retention.as_ref().and_then(|r| r.max_mb).map(|mb| mb * 1024 * 1024),
+    };
+    let compaction_interval = retention.as_ref()
+        .and_then(|r| r.compaction_interval_secs)
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(3600));
+    let store = Arc::new(MeasurementStore::new(storage.clone(), policy));
+    tokio::spawn(store.clone().run_compaction(compaction_interval));
+    node.measurements = Some(store);
+    match storage.get_json(load_profile::PROFILE_KEY) {
+        Ok(Some(profile)) => node.load_profile = profile,
+        Ok(None) => {}
+        Err(e) => warn!("Ignoring unreadable load profile: {}", e),
+    }
+    match storage.get_json(outages::OUTAGE_KEY) {
+        Ok(Some(log)) => node.outages = log,
+        Ok(None) => {}
+        Err(e) => warn!("Ignoring unreadable outage counters: {}", e),
+    }
+    node.storage = Some(storage);
+}
+
+/// Replay protection, and the services watching the node from outside: the local API and
+/// the serial event exporter.
+async fn attach_services(node: &mut EdgeNode, config: &Config) {
+    if config.security.as_ref().and_then(|s| s.replay_protection).unwrap_or(false) {
+        let restored = match node.storage.as_ref().map(|s| s.get_json::<ReplayGuard>(REPLAY_KEY)) {
+            Some(Ok(guard)) => guard,
+            Some(Err(e)) => {
+                warn!("Ignoring unreadable replay counters: {}", e);
+                None
+            }
+            None => {
+                warn!("Replay protection without storage: counters reset on reboot");
+                None
+            }
+        };
+        node.replay = Some(restored.unwrap_or_default());
+    }
+
+    if let Some(api_config) = &config.api {
+        match LocalApi::bind(&api_config.bind, node.events.clone()).await {
+            Ok(mut api) => {
+                if let Some(client) = &node.client {
+                    api = api.with_deliveries(client.deliveries());
+                }
+                tokio::spawn(api.run());
+            }
+            Err(e) => warn!("Failed to start local API on {}: {}", api_config.bind, e),
+        }
+    }
+
+    if let Some(serial_config) = config.export.as_ref().and_then(|e| e.serial.as_ref()) {
+        SerialExporter::new(&serial_config.port, serial_config.baud.unwrap_or(115_200))
+            .spawn(&node.events);
+    }
+}
+
+/// Radio loopback bench test: `frames` frames between the two configured radios, or from
+/// one radio looped back into itself.
+pub async fn hil_loopback(config: &Config, frames: u32) -> Result<LoopbackReport, BootError> {
+    let Some(lora) = config.comms.as_ref().and_then(|c| c.lora.as_ref()) else {
+        return Err(BootError::NoLoRa("--hil-loopback"));
+    };
+    let hardware = config.hardware.as_ref();
+    let first = lora.hal_config(hardware.and_then(|hw| hw.lora.as_ref()));
+    let second = hardware.and_then(|hw| hw.lora_loopback.as_ref()).map(|m| lora.hal_config(Some(m)));
+    let (tx, rx) = create_lora_loopback(first, second)?;
+    Ok(tokio::task::spawn_blocking(move || hil::run_bench(tx, rx, frames)).await?)
+}
+
+/// RF test `mode` on the node's radio at the configured settings.
+pub async fn radio_test(config: &Config, mode: String, duration: Duration) -> Result<RfTestReport, BootError> {
+    let Some(lora) = config.comms.as_ref().and_then(|c| c.lora.as_ref()) else {
+        return Err(BootError::NoLoRa("--radio-test"));
+    };
+    let module = config.hardware.as_ref().and_then(|hw| hw.lora.as_ref());
+    let radio = create_lora_radio(lora.hal_config(module))?;
+    Ok(tokio::task::spawn_blocking(move || rftest::run(&mode, radio, duration)).await?)
+}
@@ -0,0 +1,234 @@
+use async_trait::async_trait;
+use log::{debug, warn};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use crate::clock::Clock;
+use crate::comms::{self, CommunicationLayer, LinkStats, NeighborhoodMessage};
+use crate::tls::{Pin, TlsIdentity};
+
+/// Why a capture file could not be opened or read back.
+#[derive(Debug, Error)]
+pub enum CaptureError {
+    #[error("opening capture file {path}: {source}")]
+    Open { path: String, source: std::io::Error },
+    #[error("reading capture {path}: {source}")]
+    Read { path: String, source: std::io::Error },
+    #[error("{path} line {line}: {source}")]
+    Frame { path: String, line: usize, source: serde_json::Error },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Tx,
+    Rx,
+}
+
+/// One frame of a capture: a line of NDJSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    /// Unix time in milliseconds
+    pub t_ms: i64,
+    pub dir: Direction,
+    /// The encoded NeighborhoodMessage
+    #[serde(with = "hex")]
+    pub bytes: Vec<u8>,
+    /// Signal strength a received frame arrived at, if the radio reported it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rssi: Option<i16>,
+}
+
+/// Transport that writes every frame sent and received to a capture file, for
+/// reproducing a field incident later with a `ReplayLink`.
+pub struct CaptureLink {
+    inner: Arc<dyn CommunicationLayer>,
+    file: Mutex<File>,
+    clock: Arc<dyn Clock>,
+}
+
+impl CaptureLink {
+    /// Capture `inner`'s traffic, appending to the file at `path`.
+    pub fn open(inner: Arc<dyn CommunicationLayer>, path: &str, clock: Arc<dyn Clock>) -> Result<Self, CaptureError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|source| CaptureError::Open { path: path.to_string(), source })?;
+        Ok(Self { inner, file: Mutex::new(file), clock })
+    }
+
+    fn record(&self, dir: Direction, msg: &NeighborhoodMessage, rssi: Option<i16>) {
+        let frame = Frame { t_ms: self.clock.now().timestamp_millis(), dir, bytes: msg.encode_to_vec(), rssi };
+        let result = serde_json::to_vec(&frame).map_err(std::io::Error::from).and_then(|mut line| {
+            line.push(b'\n');
+            // Flushed line by line: the capture is most wanted after a crash
+            let mut file = self.file.lock().unwrap();
+            file.write_all(&line)?;
+            file.flush()
+        });
+        if let Err(e) = result {
+            warn!("Failed to capture frame: {}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl CommunicationLayer for CaptureLink {
+    async fn send(&self, msg: NeighborhoodMessage) -> comms::Result<()> {
+        self.record(Direction::Tx, &msg, None);
+        self.inner.send(msg).await
+    }
+
+    async fn receive(&self) -> comms::Result<Option<NeighborhoodMessage>> {
+        let received = self.inner.receive().await?;
+        if let Some(msg) = &received {
+            self.record(Direction::Rx, msg, self.inner.link_stats().last_rssi);
+        }
+        Ok(received)
+    }
+
+    fn link_stats(&self) -> LinkStats {
+        self.inner.link_stats()
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn rotate_tls(&self, identity: TlsIdentity, pins: Vec<Pin>) -> comms::Result<()> {
+        self.inner.rotate_tls(identity, pins).await
+    }
+
+    async fn close(&self) -> comms::Result<()> {
+        self.inner.close().await
+    }
+}
+
+/// Transport that plays back the frames received in a capture, at their original
+/// spacing from when it was opened. What the node sends goes nowhere.
+pub struct ReplayLink {
+    frames: Mutex<VecDeque<Frame>>,
+    clock: Arc<dyn Clock>,
+    /// Capture time of the first frame, and the time playback started
+    offset_ms: i64,
+    stats: Mutex<LinkStats>,
+}
+
+impl ReplayLink {
+    /// Load the capture at `path` and start playing it now.
+    pub fn open(path: &str, clock: Arc<dyn Clock>) -> Result<Self, CaptureError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|source| CaptureError::Read { path: path.to_string(), source })?;
+        let frames = text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| serde_json::from_str(line)
+                .map_err(|source| CaptureError::Frame { path: path.to_string(), line: i + 1, source }))
+            .collect::<Result<Vec<Frame>, _>>()?;
+        Ok(Self::new(frames, clock))
+    }
+
+    pub fn new(frames: Vec<Frame>, clock: Arc<dyn Clock>) -> Self {
+        let mut frames: Vec<Frame> = frames.into_iter().filter(|f| f.dir == Direction::Rx).collect();
+        frames.sort_by_key(|f| f.t_ms);
+        let first_ms = frames.first().map(|f| f.t_ms).unwrap_or_default();
+        let offset_ms = clock.now().timestamp_millis() - first_ms;
+        Self { frames: Mutex::new(frames.into()), clock, offset_ms, stats: Mutex::new(LinkStats::default()) }
+    }
+
+    /// Frames still to play.
+    pub fn remaining(&self) -> usize {
+        self.frames.lock().unwrap().len()
+    }
+}
+
+#[async_trait]
+impl CommunicationLayer for ReplayLink {
+    async fn send(&self, msg: NeighborhoodMessage) -> comms::Result<()> {
+        debug!("Replay: discarding sent {:?}", msg.payload);
+        self.stats.lock().unwrap().tx_packets += 1;
+        Ok(())
+    }
+
+    async fn receive(&self) -> comms::Result<Option<NeighborhoodMessage>> {
+        let now_ms = self.clock.now().timestamp_millis();
+        let mut frames = self.frames.lock().unwrap();
+        while frames.front().is_some_and(|f| f.t_ms + self.offset_ms <= now_ms) {
+            let frame = frames.pop_front().unwrap();
+            match NeighborhoodMessage::decode(frame.bytes.as_slice()) {
+                Ok(msg) => {
+                    let mut stats = self.stats.lock().unwrap();
+                    stats.rx_packets += 1;
+                    stats.last_rssi = frame.rssi;
+                    return Ok(Some(msg));
+                }
+                Err(e) => warn!("Replay: skipping undecodable frame at {} ms: {}", frame.t_ms, e),
+            }
+        }
+        Ok(None)
+    }
+
+    fn link_stats(&self) -> LinkStats {
+        LinkStats { queue_depth: self.remaining() as u32, ..self.stats.lock().unwrap().clone() }
+    }
+
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::comms::streetgrid::neighborhood_message::Payload;
+    use crate::comms::Heartbeat;
+    use crate::medium::{Medium, RadioConfig};
+    use std::time::Duration;
+
+    fn heartbeat(node_id: &str) -> NeighborhoodMessage {
+        NeighborhoodMessage {
+            payload: Some(Payload::Heartbeat(Heartbeat { node_id: node_id.to_string(), ..Default::default() })),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_capture_replays_received_frames_at_their_spacing() {
+        let path = std::env::temp_dir().join(format!("streetgrid-capture-{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let clock = VirtualClock::at(1_700_000_000);
+        let medium = Medium::new(RadioConfig::default());
+        let neighbour = medium.join("neighbour", [0.0, 0.0]);
+        let link = medium.join("node", [50.0, 0.0]);
+        let capture = CaptureLink::open(link, path.to_str().unwrap(), Arc::new(clock.clone())).unwrap();
+
+        neighbour.send(heartbeat("a")).await.unwrap();
+        medium.propagate();
+        assert!(capture.receive().await.unwrap().is_some());
+        capture.send(heartbeat("node")).await.unwrap();
+        clock.advance(Duration::from_secs(3));
+        neighbour.send(heartbeat("b")).await.unwrap();
+        medium.propagate();
+        assert!(capture.receive().await.unwrap().is_some());
+
+        let frames: Vec<Frame> = std::fs::read_to_string(&path).unwrap().lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(frames.iter().map(|f| f.dir).collect::<Vec<_>>(), vec![Direction::Rx, Direction::Tx, Direction::Rx]);
+        assert_eq!(frames[0].rssi, Some(-85));
+
+        // Played back later, only what was received, three seconds apart
+        clock.advance(Duration::from_secs(3600));
+        let replay = ReplayLink::open(path.to_str().unwrap(), Arc::new(clock.clone())).unwrap();
+        assert_eq!(replay.receive().await.unwrap(), Some(heartbeat("a")));
+        assert_eq!(replay.link_stats().last_rssi, Some(-85));
+        assert_eq!(replay.receive().await.unwrap(), None);
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(replay.receive().await.unwrap(), Some(heartbeat("b")));
+        assert_eq!(replay.remaining(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
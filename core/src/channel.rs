@@ -0,0 +1,80 @@
+use async_trait::async_trait;
+use prost::Message;
+use std::sync::Mutex;
+use tokio::sync::mpsc::{self, error::TryRecvError};
+use crate::comms::{CommsError, CommunicationLayer, LinkStats, NeighborhoodMessage, Result};
+
+/// In-memory link over tokio channels carrying encoded frames, as the radio would, for
+/// wiring a node to an orchestrator in the same process. The orchestrator's end holds
+/// the opposite sender and receiver.
+#[derive(Debug)]
+pub struct ChannelLink {
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    rx: Mutex<mpsc::UnboundedReceiver<Vec<u8>>>,
+    stats: Mutex<LinkStats>,
+}
+
+impl ChannelLink {
+    pub fn new(tx: mpsc::UnboundedSender<Vec<u8>>, rx: mpsc::UnboundedReceiver<Vec<u8>>) -> Self {
+        Self { tx, rx: Mutex::new(rx), stats: Mutex::new(LinkStats::default()) }
+    }
+}
+
+#[async_trait]
+impl CommunicationLayer for ChannelLink {
+    async fn send(&self, msg: NeighborhoodMessage) -> Result<()> {
+        if self.tx.send(msg.encode_to_vec()).is_err() {
+            return Err(CommsError::Closed);
+        }
+        self.stats.lock().unwrap().tx_packets += 1;
+        Ok(())
+    }
+
+    async fn receive(&self) -> Result<Option<NeighborhoodMessage>> {
+        let bytes = match self.rx.lock().unwrap().try_recv() {
+            Ok(bytes) => bytes,
+            Err(TryRecvError::Empty) => return Ok(None),
+            Err(TryRecvError::Disconnected) => return Err(CommsError::Closed),
+        };
+        self.stats.lock().unwrap().rx_packets += 1;
+        Ok(Some(NeighborhoodMessage::decode(bytes.as_slice())?))
+    }
+
+    fn link_stats(&self) -> LinkStats {
+        LinkStats { queue_depth: self.rx.lock().unwrap().len() as u32, ..self.stats.lock().unwrap().clone() }
+    }
+
+    fn name(&self) -> &'static str {
+        "channel"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comms::streetgrid::neighborhood_message::Payload;
+    use crate::comms::Heartbeat;
+
+    #[tokio::test]
+    async fn test_frames_cross_the_channel() {
+        let (tx, mut far_rx) = mpsc::unbounded_channel();
+        let (far_tx, rx) = mpsc::unbounded_channel();
+        let link = ChannelLink::new(tx, rx);
+
+        let msg = NeighborhoodMessage {
+            payload: Some(Payload::Heartbeat(Heartbeat { node_id: "node_01".to_string(), ..Default::default() })),
+            ..Default::default()
+        };
+        link.send(msg.clone()).await.unwrap();
+        let frame = far_rx.recv().await.unwrap();
+        assert_eq!(NeighborhoodMessage::decode(frame.as_slice()).unwrap(), msg);
+
+        assert!(link.receive().await.unwrap().is_none());
+        far_tx.send(frame).unwrap();
+        assert_eq!(link.receive().await.unwrap(), Some(msg));
+        assert_eq!((link.link_stats().tx_packets, link.link_stats().rx_packets), (1, 1));
+
+        drop(far_tx);
+        assert!(link.receive().await.is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use chrono::{DateTime, Utc};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where the node and its comms read the time. Nodes in the field use the system
+/// clock; tests and simulations use a virtual clock they advance themselves, so
+/// timeouts and delays play out without waiting for them.
+pub trait Clock: Debug + Send + Sync {
+    /// Wall-clock time, for timestamps and deadlines
+    fn now(&self) -> DateTime<Utc>;
+    /// Monotonic time, for rate limits
+    fn instant(&self) -> Instant;
+
+    /// Unix seconds
+    fn unix(&self) -> i64 {
+        self.now().timestamp()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that stands still until advanced. Clones share the same time, so a test
+/// can keep one and hand another to the node.
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    start: DateTime<Utc>,
+    origin: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl VirtualClock {
+    /// A clock reading `unix_secs`.
+    pub fn at(unix_secs: i64) -> Self {
+        Self {
+            start: DateTime::from_timestamp(unix_secs, 0).unwrap_or_default(),
+            origin: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.start + self.elapsed()
+    }
+
+    fn instant(&self) -> Instant {
+        self.origin + self.elapsed()
+    }
+}
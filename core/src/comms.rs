@@ -0,0 +1,1866 @@
+use async_trait::async_trait;
+use prost::Message;
+use log::{debug, info, warn};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use crate::sysinfo::SystemStats;
+
+// Include the generated proto modules
+pub mod streetgrid {
+    include!(concat!(env!("OUT_DIR"), "/streetgrid.rs"));
+}
+
+pub use streetgrid::{
+    NeighborhoodMessage, FeatureReport, Heartbeat, LinkMetrics, SystemMetrics, LoadShed, VoltageAlert, RelayInfo,
+    EnterIsland, EnterBlackStart, ActivateRelayByIndex, ActivateRelayByPriority,
+    AuditLogRequest, AuditLogEntry, AuditLogUpload,
+    SnapshotRequest, SnapshotData, SnapshotRestore, AnomalyAlert,
+    RelayLoadProfile, LoadProfileReport,
+    KeyRotation, KeyRotationAck, MessageAuth,
+    JoinRequest, JoinAccept, JoinReject, Alarm,
+    FirmwareUpdate, FirmwareStatus, SessionGrant, SessionRevoke, TamperAlert,
+    ReplayDesync, CounterReset, CertRotation, SecurityReport,
+    FactoryReset, FaultReset, DisconnectGrid, Approval, Neighbor, NeighborReport, VoltageObservation, Coordination,
+    PowerOffer, SourceCapacity, PowerRequest, PowerGrant, EnergyEntry, EnergyLedgerRequest, EnergyLedgerUpload,
+    RebalanceDirective, RebalanceAck, MidStatus, MidCommand, LivenessProbe, LivenessPing, LivenessReport,
+    RepeaterReport, OrchestratorTakeover, RegistrationAck, BlackStartAck, DemandResponse, DemandResponseAck, VoltageSag,
+    FailedRelay, DeliveryMetrics, PhaseMeasurement, ChannelPower, ThermalStatus, CircuitMeasurement,
+    OutageReport, RelayOutage, PriorityOutage, CommandResult, RelayState
+};
+use streetgrid::neighborhood_message::Payload;
+use crate::audit::{AuditEntry, AuditHead};
+use crate::anomaly::Anomaly;
+use crate::protection::Sag;
+use crate::phases::PhaseReading;
+use crate::hal::adc::PowerQuality;
+use crate::relay_health::{Evidence, RelayFailure};
+use crate::reserve::ReserveAction;
+use crate::submeter::CircuitReading;
+use crate::load_profile::LoadProfile;
+use crate::outages::{self, OutageMonth};
+use crate::keys::Keyring;
+use crate::tls::{Pin, TlsError, TlsIdentity};
+use crate::multisig::CoSignatures;
+use crate::neighbors::NeighborTable;
+use crate::delivery::{DeliveryStats, DeliveryTracker};
+use crate::quorum::VoteTable;
+use crate::election::KIND_ORCHESTRATOR_ALIVE;
+use crate::power::{Demand, PowerLedger};
+use crate::sources::Capacity;
+use crate::mid::{self, MidTable};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use crate::repeater::{RepeaterStats, SeenCache};
+use crate::types::Phase;
+use crate::security::{self, SecurityCounts, SecurityEvent};
+use crate::audit::SignatureStatus;
+use crate::clock::{Clock, SystemClock};
+use crate::config::{CommsConfig, HardwareConfig};
+use crate::hal::blocking::off_loop;
+use crate::hal::{create_lora_radio, HalError, LoRaHalConfig, LoRaRadio};
+use crate::mqtt::{MqttCommunication, MqttSettings};
+use crate::serial::SerialCommunication;
+use crate::storage::Storage;
+use thiserror::Error;
+
+/// Why a message could not be sent or received, or a transport started.
+#[derive(Debug, Error)]
+pub enum CommsError {
+    /// The radio under the transport failed
+    #[error("radio: {0}")]
+    Hardware(#[from] HalError),
+    #[error("serial port: {0}")]
+    Serial(#[from] serialport::Error),
+    #[error("MQTT: {0}")]
+    Mqtt(#[from] rumqttc::ClientError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// The link is out of service for now, e.g. a radio switched off
+    #[error("{0} link is down")]
+    LinkDown(&'static str),
+    /// The other end of the link is gone for good
+    #[error("link closed")]
+    Closed,
+    #[error("{size}-byte message is too long for a {transport} frame")]
+    TooLong { size: usize, transport: &'static str },
+    #[error("cannot decode frame: {0}")]
+    Decode(#[from] prost::DecodeError),
+    #[error("cannot encode message: {0}")]
+    Encode(#[from] serde_json::Error),
+    #[error("{0} transport does not use TLS")]
+    NoTls(&'static str),
+    /// A TLS identity or pin that can't be loaded or used
+    #[error("TLS: {0}")]
+    Tls(#[from] TlsError),
+    /// The comms config doesn't describe a transport that can be started
+    #[error("{0}")]
+    Config(String),
+    /// Messages were still queued when the transport had to close
+    #[error("{0} outbound queue not flushed in time")]
+    Unflushed(&'static str),
+}
+
+impl CommsError {
+    /// Whether sending again shortly may succeed: a busy radio, a full MQTT request
+    /// queue, a glitch on the line.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Hardware(e) => e.is_transient(),
+            Self::LinkDown(_) | Self::Mqtt(_) | Self::Io(_) => true,
+            _ => false,
+        }
+    }
+}
+
+pub type Result<T, E = CommsError> = std::result::Result<T, E>;
+
+/// Link-level counters kept by a transport since boot.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LinkStats {
+    pub last_rssi: Option<i16>,
+    pub last_snr: Option<f32>,
+    pub tx_packets: u32,
+    pub rx_packets: u32,
+    pub retransmissions: u32,
+    pub queue_depth: u32,
+}
+
+impl From<LinkStats> for LinkMetrics {
+    fn from(stats: LinkStats) -> Self {
+        LinkMetrics {
+            last_rssi: stats.last_rssi.unwrap_or(0) as i32,
+            last_snr: stats.last_snr.unwrap_or(0.0),
+            tx_packets: stats.tx_packets,
+            rx_packets: stats.rx_packets,
+            retransmissions: stats.retransmissions,
+            queue_depth: stats.queue_depth,
+            deliveries: Vec::new(),
+        }
+    }
+}
+
+impl From<DeliveryStats> for DeliveryMetrics {
+    fn from(stats: DeliveryStats) -> Self {
+        DeliveryMetrics {
+            destination: stats.destination,
+            sent: stats.sent,
+            delivered: stats.delivered,
+            rtt_avg_ms: stats.rtt_avg_ms.unwrap_or(0),
+            rtt_max_ms: stats.rtt_max_ms.unwrap_or(0),
+        }
+    }
+}
+
+impl From<SystemStats> for SystemMetrics {
+    fn from(stats: SystemStats) -> Self {
+        SystemMetrics {
+            uptime_secs: stats.uptime_secs,
+            free_memory_kb: stats.free_memory_kb,
+            cpu_load: stats.cpu_load,
+            fs_free_kb: stats.fs_free_kb,
+            sd_write_errors: stats.sd_write_errors,
+            storage_used_kb: stats.storage_used_kb,
+        }
+    }
+}
+
+/// Decode a frame as it came off the air. Anyone in radio range can send us anything,
+/// so an undecodable frame is counted as malformed rather than treated as an error.
+pub fn decode_frame(frame: &[u8]) -> Option<NeighborhoodMessage> {
+    match NeighborhoodMessage::decode(frame) {
+        Ok(msg) => Some(msg),
+        Err(e) => {
+            debug!("Dropping undecodable {}-byte frame: {}", frame.len(), e);
+            security::record(SecurityEvent::MalformedPacket);
+            None
+        }
+    }
+}
+
+#[async_trait]
+pub trait CommunicationLayer: Send + Sync {
+    async fn send(&self, msg: NeighborhoodMessage) -> Result<()>;
+    async fn receive(&self) -> Result<Option<NeighborhoodMessage>>;
+
+    /// Current link statistics. Transports without radio metrics report defaults.
+    fn link_stats(&self) -> LinkStats {
+        LinkStats::default()
+    }
+
+    /// Short transport name recorded as the source of received commands.
+    fn name(&self) -> &'static str {
+        "unknown"
+    }
+
+    /// Switch to a new TLS client identity (and orchestrator pins, unless empty).
+    async fn rotate_tls(&self, _identity: TlsIdentity, _pins: Vec<Pin>) -> Result<()> {
+        Err(CommsError::NoTls(self.name()))
+    }
+
+    /// Hand over everything sent so far before the node exits. Transports that send
+    /// as they are called have nothing queued.
+    async fn close(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum IncomingCommand {
+    LoadShed(LoadShed),
+    EnterIsland(EnterIsland),
+    EnterBlackStart(EnterBlackStart),
+    ActivateRelayByIndex(ActivateRelayByIndex),
+    ActivateRelayByPriority(ActivateRelayByPriority),
+    AuditLogRequest(AuditLogRequest),
+    SnapshotRequest(SnapshotRequest),
+    SnapshotRestore(SnapshotRestore),
+    KeyRotation(KeyRotation),
+    JoinAccept(JoinAccept),
+    JoinReject(JoinReject),
+    FirmwareUpdate(FirmwareUpdate),
+    SessionGrant(SessionGrant),
+    SessionRevoke(SessionRevoke),
+    CounterReset(CounterReset),
+    CertRotation(CertRotation),
+    FactoryReset(FactoryReset),
+    FaultReset(FaultReset),
+    DisconnectGrid(DisconnectGrid),
+    EnergyLedgerRequest(EnergyLedgerRequest),
+    Rebalance(RebalanceDirective),
+    MidCommand(MidCommand),
+    LivenessProbe(LivenessProbe),
+    OrchestratorTakeover(OrchestratorTakeover),
+    RegistrationAck(RegistrationAck),
+    DemandResponse(DemandResponse),
+    WhoIsThere,
+}
+
+impl IncomingCommand {
+    /// The command a message carries; None for messages that aren't commands (heartbeats,
+    /// feature reports and the like).
+    pub fn from_payload(payload: Payload) -> Option<Self> {
+        Some(match payload {
+            Payload::LoadShed(ls) => IncomingCommand::LoadShed(ls),
+            Payload::EnterIsland(ei) => IncomingCommand::EnterIsland(ei),
+            Payload::EnterBlackStart(ebs) => IncomingCommand::EnterBlackStart(ebs),
+            Payload::ActivateRelayByIndex(ar) => IncomingCommand::ActivateRelayByIndex(ar),
+            Payload::ActivateRelayByPriority(arp) => IncomingCommand::ActivateRelayByPriority(arp),
+            Payload::AuditLogRequest(req) => IncomingCommand::AuditLogRequest(req),
+            Payload::SnapshotRequest(req) => IncomingCommand::SnapshotRequest(req),
+            Payload::SnapshotRestore(restore) => IncomingCommand::SnapshotRestore(restore),
+            Payload::KeyRotation(rotation) => IncomingCommand::KeyRotation(rotation),
+            Payload::JoinAccept(accept) => IncomingCommand::JoinAccept(accept),
+            Payload::JoinReject(reject) => IncomingCommand::JoinReject(reject),
+            Payload::FirmwareUpdate(update) => IncomingCommand::FirmwareUpdate(update),
+            Payload::SessionGrant(grant) => IncomingCommand::SessionGrant(grant),
+            Payload::SessionRevoke(revoke) => IncomingCommand::SessionRevoke(revoke),
+            Payload::CounterReset(reset) => IncomingCommand::CounterReset(reset),
+            Payload::CertRotation(rotation) => IncomingCommand::CertRotation(rotation),
+            Payload::FactoryReset(reset) => IncomingCommand::FactoryReset(reset),
+            Payload::FaultReset(reset) => IncomingCommand::FaultReset(reset),
+            Payload::DisconnectGrid(disconnect) => IncomingCommand::DisconnectGrid(disconnect),
+            Payload::EnergyLedgerRequest(req) => IncomingCommand::EnergyLedgerRequest(req),
+            Payload::RebalanceDirective(directive) => IncomingCommand::Rebalance(directive),
+            Payload::MidCommand(cmd) => IncomingCommand::MidCommand(cmd),
+            Payload::LivenessProbe(probe) => IncomingCommand::LivenessProbe(probe),
+            Payload::OrchestratorTakeover(takeover) => IncomingCommand::OrchestratorTakeover(takeover),
+            Payload::RegistrationAck(ack) => IncomingCommand::RegistrationAck(ack),
+            Payload::DemandResponse(dr) => IncomingCommand::DemandResponse(dr),
+            Payload::WhoIsThere(_) => IncomingCommand::WhoIsThere,
+            _ => return None,
+        })
+    }
+
+    /// Stable snake_case name, used for rate limits, logs and CommandResults.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IncomingCommand::LoadShed(_) => "load_shed",
+            IncomingCommand::EnterIsland(_) => "enter_island",
+            IncomingCommand::EnterBlackStart(_) => "enter_black_start",
+            IncomingCommand::ActivateRelayByIndex(_) => "activate_relay_by_index",
+            IncomingCommand::ActivateRelayByPriority(_) => "activate_relay_by_priority",
+            IncomingCommand::AuditLogRequest(_) => "audit_log_request",
+            IncomingCommand::SnapshotRequest(_) => "snapshot_request",
+            IncomingCommand::SnapshotRestore(_) => "snapshot_restore",
+            IncomingCommand::KeyRotation(_) => "key_rotation",
+            IncomingCommand::JoinAccept(_) => "join_accept",
+            IncomingCommand::JoinReject(_) => "join_reject",
+            IncomingCommand::FirmwareUpdate(_) => "firmware_update",
+            IncomingCommand::SessionGrant(_) => "session_grant",
+            IncomingCommand::SessionRevoke(_) => "session_revoke",
+            IncomingCommand::CounterReset(_) => "counter_reset",
+            IncomingCommand::CertRotation(_) => "cert_rotation",
+            IncomingCommand::FactoryReset(_) => "factory_reset",
+            IncomingCommand::FaultReset(_) => "fault_reset",
+            IncomingCommand::DisconnectGrid(_) => "disconnect_grid",
+            IncomingCommand::EnergyLedgerRequest(_) => "energy_ledger_request",
+            IncomingCommand::Rebalance(_) => "rebalance",
+            IncomingCommand::MidCommand(_) => "mid_command",
+            IncomingCommand::LivenessProbe(_) => "liveness_probe",
+            IncomingCommand::OrchestratorTakeover(_) => "orchestrator_takeover",
+            IncomingCommand::RegistrationAck(_) => "registration_ack",
+            IncomingCommand::DemandResponse(_) => "demand_response",
+            IncomingCommand::WhoIsThere => "who_is_there",
+        }
+    }
+
+    /// Address a zone-wide command to this node. False for commands that only make
+    /// sense for one node (relay indices, snapshots, keys and the like).
+    pub fn retarget(&mut self, node_id: &str) -> bool {
+        let target = match self {
+            IncomingCommand::LoadShed(cmd) => &mut cmd.target_node_id,
+            IncomingCommand::EnterIsland(cmd) => &mut cmd.target_node_id,
+            IncomingCommand::EnterBlackStart(cmd) => &mut cmd.target_node_id,
+            IncomingCommand::ActivateRelayByPriority(cmd) => &mut cmd.target_node_id,
+            IncomingCommand::DisconnectGrid(cmd) => &mut cmd.target_node_id,
+            IncomingCommand::DemandResponse(cmd) => &mut cmd.target_node_id,
+            _ => return false,
+        };
+        *target = node_id.to_string();
+        true
+    }
+
+    /// The one node the command is for; `None` for broadcasts to every node.
+    pub fn target_node_id(&self) -> Option<&str> {
+        let target = match self {
+            IncomingCommand::LoadShed(cmd) => &cmd.target_node_id,
+            IncomingCommand::EnterIsland(cmd) => &cmd.target_node_id,
+            IncomingCommand::EnterBlackStart(cmd) => &cmd.target_node_id,
+            IncomingCommand::ActivateRelayByIndex(cmd) => &cmd.target_node_id,
+            IncomingCommand::ActivateRelayByPriority(cmd) => &cmd.target_node_id,
+            IncomingCommand::AuditLogRequest(cmd) => &cmd.target_node_id,
+            IncomingCommand::SnapshotRequest(cmd) => &cmd.target_node_id,
+            IncomingCommand::SnapshotRestore(cmd) => &cmd.target_node_id,
+            IncomingCommand::KeyRotation(cmd) => &cmd.target_node_id,
+            IncomingCommand::JoinAccept(cmd) => &cmd.target_node_id,
+            IncomingCommand::JoinReject(cmd) => &cmd.target_node_id,
+            IncomingCommand::FirmwareUpdate(cmd) => &cmd.target_node_id,
+            IncomingCommand::SessionGrant(cmd) => &cmd.target_node_id,
+            IncomingCommand::SessionRevoke(cmd) => &cmd.target_node_id,
+            IncomingCommand::CounterReset(cmd) => &cmd.target_node_id,
+            IncomingCommand::CertRotation(cmd) => &cmd.target_node_id,
+            IncomingCommand::FactoryReset(cmd) => &cmd.target_node_id,
+            IncomingCommand::FaultReset(cmd) => &cmd.target_node_id,
+            IncomingCommand::DisconnectGrid(cmd) => &cmd.target_node_id,
+            IncomingCommand::EnergyLedgerRequest(cmd) => &cmd.target_node_id,
+            IncomingCommand::Rebalance(cmd) => &cmd.target_node_id,
+            IncomingCommand::MidCommand(cmd) => &cmd.target_node_id,
+            IncomingCommand::LivenessProbe(cmd) => &cmd.target_node_id,
+            IncomingCommand::RegistrationAck(cmd) => &cmd.target_node_id,
+            IncomingCommand::DemandResponse(cmd) => &cmd.target_node_id,
+            IncomingCommand::OrchestratorTakeover(_) | IncomingCommand::WhoIsThere => return None,
+        };
+        Some(target.as_str()).filter(|t| !t.is_empty())
+    }
+
+    /// Commands that can only open relays; these are honoured even under flood lockout.
+    pub fn is_safety_critical(&self) -> bool {
+        match self {
+            IncomingCommand::LoadShed(ls) => ls.shed_load,
+            IncomingCommand::EnterIsland(_) => true,
+            IncomingCommand::MidCommand(cmd) => cmd.isolate,
+            _ => false,
+        }
+    }
+
+    /// Operator commands that must name a live session when sessions are enforced.
+    /// Mesh housekeeping and safety-critical opens are exempt.
+    pub fn requires_session(&self) -> bool {
+        !self.is_safety_critical()
+            && !matches!(
+                self,
+                IncomingCommand::KeyRotation(_)
+                    | IncomingCommand::JoinAccept(_)
+                    | IncomingCommand::JoinReject(_)
+                    | IncomingCommand::SessionGrant(_)
+                    | IncomingCommand::SessionRevoke(_)
+                    | IncomingCommand::CounterReset(_)
+                    | IncomingCommand::CertRotation(_)
+                    | IncomingCommand::LivenessProbe(_)
+                    | IncomingCommand::OrchestratorTakeover(_)
+                    | IncomingCommand::RegistrationAck(_)
+                    | IncomingCommand::WhoIsThere
+            )
+    }
+}
+
+/// A received command and the outcome of checking its MAC.
+#[derive(Debug)]
+pub struct ReceivedCommand {
+    pub command: IncomingCommand,
+    pub signature: SignatureStatus,
+    /// Operator session the command was issued under, if any
+    pub session_id: Option<String>,
+    /// Issuer and its per-sender counter, for replay protection
+    pub sender_id: Option<String>,
+    pub counter: u64,
+    /// Co-signatures for the multi-signature policy, if the envelope carried any
+    pub co_signatures: Option<CoSignatures>,
+    /// Zone the command was addressed to, instead of a single node
+    pub zone: Option<String>,
+}
+
+/// Electrical readings carried in a heartbeat, where the node measures them.
+#[derive(Debug, Clone, Default)]
+pub struct HeartbeatReadings {
+    /// Each phase of a three-phase service
+    pub phases: Vec<PhaseReading>,
+    /// Power quality per ADC channel
+    pub power: Vec<(u8, PowerQuality)>,
+    /// Harmonic distortion of the mains voltage
+    pub voltage_thd: Option<f32>,
+    /// Board temperature, and the current the relays are derated to while it runs hot
+    pub thermal: Option<(f32, Option<f32>)>,
+    /// Battery reserve tier in force while islanded
+    pub reserve_tier: Option<ReserveAction>,
+    /// Sub-metered circuits, by name
+    pub circuits: Vec<(String, CircuitReading)>,
+}
+
+pub struct OrchestratorClient {
+    layer: Arc<dyn CommunicationLayer>,
+    /// Mesh keys; without one, messages go out unsigned and nothing is verified
+    keyring: Option<Arc<Mutex<Keyring>>>,
+    /// Other nodes overheard on the link, for topology reports
+    neighbors: Mutex<NeighborTable>,
+    /// Neighbours' voltage observations, for the islanding quorum
+    votes: Mutex<VoteTable>,
+    /// Election traffic waiting for the node, oldest first
+    coordination: Mutex<VecDeque<Coordination>>,
+    /// Island supply and demand, and grants from the coordinator
+    power: Mutex<PowerLedger>,
+    /// Neighbours' energy ledger entries waiting for the node to keep copies
+    energy_entries: Mutex<VecDeque<EnergyEntry>>,
+    /// Latest status from the transformer isolation devices we hear
+    mids: Mutex<MidTable>,
+    /// As a MID, the counter on our last status
+    mid_counter: AtomicU64,
+    /// Frames recently received, so a repeater's echo of one is dropped
+    seen: Mutex<SeenCache>,
+    /// Probes relayed by neighbours looking for a silent node, oldest first
+    liveness_pings: Mutex<VecDeque<LivenessPing>>,
+    /// Round trips of the messages we expect an answer to, shared with the local API
+    deliveries: Arc<Mutex<DeliveryTracker>>,
+    /// Our node ID, stamped with a sequence number on every frame we send
+    origin: Option<String>,
+    origin_seq: AtomicU32,
+    /// Time source for message timestamps and key epochs
+    clock: Arc<dyn Clock>,
+}
+
+/// Most election messages, ledger entries or liveness pings held between polls; older ones are dropped first.
+const MAX_PENDING_PEER_MESSAGES: usize = 32;
+
+/// Tries at a message whose transport fails transiently (a busy radio, a full MQTT queue)
+const SEND_ATTEMPTS: u32 = 3;
+
+/// Wait before trying again, doubled each time
+const SEND_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(20);
+
+/// Destination of the messages the orchestrator answers, in delivery statistics.
+pub const ORCHESTRATOR_DESTINATION: &str = "orchestrator";
+
+/// A FeatureReport, answered by a RegistrationAck for the node.
+fn registration_exchange(node_id: &str) -> String {
+    format!("registration/{}", node_id)
+}
+
+/// A liveness ping, answered by a heartbeat from the node it looks for.
+const LIVENESS_EXCHANGE: &str = "liveness";
+
+/// Node that sent a periodic message other nodes can overhear.
+fn peer_node(payload: &Payload) -> Option<&str> {
+    match payload {
+        Payload::Heartbeat(hb) => Some(&hb.node_id),
+        Payload::FeatureReport(report) => Some(&report.node_id),
+        Payload::NeighborReport(report) => Some(&report.node_id),
+        Payload::VoltageObservation(obs) => Some(&obs.node_id),
+        Payload::PowerOffer(offer) => Some(&offer.node_id),
+        Payload::SourceCapacity(capacity) => Some(&capacity.node_id),
+        Payload::PowerRequest(request) => Some(&request.node_id),
+        Payload::PowerGrant(grant) => Some(&grant.coordinator_id),
+        Payload::EnergyEntry(entry) => Some(&entry.node_id),
+        Payload::MidStatus(status) => Some(&status.mid_id),
+        Payload::LivenessPing(ping) => Some(&ping.node_id),
+        // The orchestrator's beacon isn't a radio neighbour
+        Payload::Coordination(c) if c.kind != KIND_ORCHESTRATOR_ALIVE => Some(&c.node_id),
+        _ => None,
+    }
+}
+
+impl OrchestratorClient {
+    pub fn new(layer: Arc<dyn CommunicationLayer>) -> Self {
+        Self {
+            layer,
+            keyring: None,
+            neighbors: Mutex::new(NeighborTable::default()),
+            votes: Mutex::new(VoteTable::default()),
+            coordination: Mutex::new(VecDeque::new()),
+            power: Mutex::new(PowerLedger::default()),
+            energy_entries: Mutex::new(VecDeque::new()),
+            mids: Mutex::new(MidTable::default()),
+            mid_counter: AtomicU64::new(0),
+            seen: Mutex::new(SeenCache::default()),
+            liveness_pings: Mutex::new(VecDeque::new()),
+            deliveries: Arc::new(Mutex::new(DeliveryTracker::default())),
+            origin: None,
+            origin_seq: AtomicU32::new(0),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Read the time from `clock` rather than the system clock.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Sign outbound and verify inbound messages with a (shared) keyring.
+    pub fn with_keyring(mut self, keyring: Arc<Mutex<Keyring>>) -> Self {
+        self.keyring = Some(keyring);
+        self
+    }
+
+    /// Number our frames as `node_id`'s, so neighbours can tell how many they miss.
+    pub fn with_origin(mut self, node_id: &str) -> Self {
+        self.origin = Some(node_id.to_string());
+        self
+    }
+
+    /// Start authenticating once keys are issued after boot (e.g. on joining the mesh).
+    pub fn set_keyring(&mut self, keyring: Arc<Mutex<Keyring>>) {
+        self.keyring = Some(keyring);
+    }
+
+    async fn send(&self, payload: Payload) -> Result<()> {
+        let mut msg = NeighborhoodMessage { payload: Some(payload), ..Default::default() };
+        if let Some(origin) = &self.origin {
+            msg.origin_id = origin.clone();
+            msg.origin_seq = self.origin_seq.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+        }
+        if let Some(keyring) = &self.keyring {
+            let (key_epoch, mac) = keyring.lock().unwrap().sign(&msg.encode_to_vec(), self.clock.unix());
+            msg.auth = Some(MessageAuth { key_epoch, mac });
+        }
+        let mut delay = SEND_RETRY_DELAY;
+        for _ in 1..SEND_ATTEMPTS {
+            match self.layer.send(msg.clone()).await {
+                Err(e) if e.is_transient() => {
+                    debug!("Sending again in {} ms: {}", delay.as_millis(), e);
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+        self.layer.send(msg).await
+    }
+
+    /// Whether peer traffic may influence what the node does. A forged vote or election
+    /// message could island the street, so with a mesh key only signed ones count.
+    fn trusted_peer(&self, signature: SignatureStatus) -> bool {
+        match self.keyring {
+            Some(_) => signature == SignatureStatus::Valid,
+            None => signature != SignatureStatus::Invalid,
+        }
+    }
+
+    fn check_signature(&self, msg: &mut NeighborhoodMessage) -> SignatureStatus {
+        let auth = msg.auth.take();
+        let (Some(keyring), Some(auth)) = (&self.keyring, auth) else {
+            return SignatureStatus::Unsigned;
+        };
+        if keyring.lock().unwrap().verify(auth.key_epoch, &msg.encode_to_vec(), &auth.mac, self.clock.unix()) {
+            SignatureStatus::Valid
+        } else {
+            SignatureStatus::Invalid
+        }
+    }
+
+    /// Reconnect the transport with a new TLS identity.
+    pub async fn rotate_tls(&self, identity: TlsIdentity, pins: Vec<Pin>) -> Result<()> {
+        self.layer.rotate_tls(identity, pins).await
+    }
+
+    /// Flush and close the transport; nothing can be sent after.
+    pub async fn close(&self) -> Result<()> {
+        self.layer.close().await
+    }
+
+    /// Name of the underlying transport.
+    pub fn source(&self) -> &'static str {
+        self.layer.name()
+    }
+
+    /// A heartbeat for `send_heartbeat`; `orchestrator` is the one we follow, when a
+    /// standby is configured.
+    pub fn heartbeat(
+        &self,
+        node_id: &str,
+        battery_level: f32,
+        system: SystemStats,
+        audit: Option<AuditHead>,
+        orchestrator: Option<&str>,
+        readings: &HeartbeatReadings,
+    ) -> Heartbeat {
+        Heartbeat {
+            node_id: node_id.to_string(),
+            timestamp: self.clock.unix(),
+            battery_level,
+            link: Some(self.link_metrics()),
+            system: Some(system.into()),
+            key_epoch: self.keyring.as_ref().map(|k| k.lock().unwrap().active_epoch(self.clock.unix())).unwrap_or(0),
+            audit_seq: audit.map(|a| a.next_seq).unwrap_or(0),
+            audit_head: audit.map(|a| a.hash.to_vec()).unwrap_or_default(),
+            firmware_version: env!("CARGO_PKG_VERSION").to_string(),
+            orchestrator_id: orchestrator.unwrap_or_default().to_string(),
+            phases: readings.phases.iter()
+                .map(|p| PhaseMeasurement { phase: p.phase.as_str().to_string(), volts: p.volts, amps: p.amps })
+                .collect(),
+            power: readings.power.iter()
+                .map(|(channel, q)| ChannelPower {
+                    channel: *channel as u32,
+                    real_watts: q.real_watts,
+                    reactive_var: q.reactive_var,
+                    apparent_va: q.apparent_va,
+                    power_factor: q.power_factor,
+                })
+                .collect(),
+            voltage_thd: readings.voltage_thd.unwrap_or_default(),
+            thermal: readings.thermal.map(|(board_temp_c, derated_amps)| ThermalStatus {
+                board_temp_c,
+                derated_amps: derated_amps.unwrap_or_default(),
+            }),
+            reserve_tier: readings.reserve_tier.map(|action| action.as_str().to_string()).unwrap_or_default(),
+            circuits: readings.circuits.iter()
+                .map(|(circuit, reading)| CircuitMeasurement {
+                    circuit: circuit.clone(),
+                    watts: reading.watts,
+                    imported_wh: reading.imported_wh,
+                    exported_wh: reading.exported_wh,
+                })
+                .collect(),
+            shutting_down: false,
+        }
+    }
+
+    pub async fn send_heartbeat(&self, heartbeat: Heartbeat) -> Result<()> {
+        self.send(Payload::Heartbeat(heartbeat)).await
+    }
+
+    /// The transport's link counters, with delivery of the messages we expect answers to.
+    fn link_metrics(&self) -> LinkMetrics {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        deliveries.expire(self.clock.instant());
+        let mut stats = self.layer.link_stats();
+        stats.retransmissions += deliveries.retransmissions();
+        LinkMetrics {
+            deliveries: deliveries.stats().into_iter().map(DeliveryMetrics::from).collect(),
+            ..stats.into()
+        }
+    }
+
+    /// Delivery statistics per destination, kept up to date as messages are answered.
+    pub fn deliveries(&self) -> Arc<Mutex<DeliveryTracker>> {
+        self.deliveries.clone()
+    }
+
+    pub async fn send_feature_report(
+        &self,
+        node_id: &str,
+        relays: Vec<RelayInfo>,
+        mesh_type: &str,
+        hardware_id: Option<&str>,
+        zones: Vec<String>,
+        phase: Option<Phase>,
+    ) -> Result<()> {
+        info!("Sending FeatureReport with {} relays", relays.len());
+        let report = FeatureReport {
+            node_id: node_id.to_string(),
+            relays,
+            mesh_type: mesh_type.to_string(),
+            hardware_id: hardware_id.unwrap_or_default().to_string(),
+            zones,
+            phase: phase.map(|p| p.as_str().to_string()).unwrap_or_default(),
+        };
+        self.deliveries.lock().unwrap().sent(ORCHESTRATOR_DESTINATION, &registration_exchange(node_id), self.clock.instant());
+        self.send(Payload::FeatureReport(report)).await
+    }
+
+    pub async fn send_voltage_alert(&self, node_id: &str, voltage: f32) -> Result<()> {
+        let alert = VoltageAlert {
+            node_id: node_id.to_string(),
+            voltage,
+            timestamp: self.clock.unix(),
+        };
+        info!("Sending VoltageAlert: voltage={} for node {}", voltage, node_id);
+        self.send(Payload::VoltageAlert(alert)).await
+    }
+
+    pub async fn send_voltage_sag(&self, node_id: &str, sag: &Sag) -> Result<()> {
+        let report = VoltageSag {
+            node_id: node_id.to_string(),
+            start: sag.start,
+            duration_secs: sag.duration.as_secs_f32(),
+            min_voltage: sag.min_voltage,
+            escalated: sag.escalated,
+        };
+        info!("Sending VoltageSag: {:.1} V for {:.1} s for node {}", sag.min_voltage, report.duration_secs, node_id);
+        self.send(Payload::VoltageSag(report)).await
+    }
+
+    pub async fn send_failed_relay(&self, node_id: &str, relay_id: &str, failure: RelayFailure, evidence: Evidence) -> Result<()> {
+        let alert = FailedRelay {
+            node_id: node_id.to_string(),
+            relay_id: relay_id.to_string(),
+            failure: failure.as_str().to_string(),
+            evidence: evidence.as_str().to_string(),
+            timestamp: self.clock.unix(),
+        };
+        info!("Sending FailedRelay: {} {} for node {}", relay_id, alert.failure, node_id);
+        self.send(Payload::FailedRelay(alert)).await
+    }
+
+    pub async fn send_anomaly_alert(&self, node_id: &str, relay_id: Option<&str>, anomaly: &Anomaly) -> Result<()> {
+        let alert = AnomalyAlert {
+            node_id: node_id.to_string(),
+            channel: anomaly.channel as u32,
+            relay_id: relay_id.unwrap_or_default().to_string(),
+            current_amps: anomaly.amps,
+            baseline_amps: anomaly.baseline_amps,
+            magnitude: anomaly.magnitude,
+            timestamp: self.clock.unix(),
+        };
+        info!("Sending AnomalyAlert: channel {} at {:.1}A ({:+.1} sigma) for node {}",
+              anomaly.channel, anomaly.amps, anomaly.magnitude, node_id);
+        self.send(Payload::AnomalyAlert(alert)).await
+    }
+
+    pub async fn send_load_profile(&self, node_id: &str, profile: &LoadProfile) -> Result<()> {
+        let profiles: Vec<RelayLoadProfile> = profile.sample_counts()
+            .map(|(relay_id, samples)| RelayLoadProfile {
+                relay_id: relay_id.to_string(),
+                hourly_watts: profile.hourly_watts(relay_id).unwrap_or_default(),
+                samples,
+            })
+            .collect();
+        let report = LoadProfileReport {
+            node_id: node_id.to_string(),
+            profiles,
+            timestamp: self.clock.unix(),
+        };
+        info!("Sending LoadProfileReport for node {}", node_id);
+        self.send(Payload::LoadProfileReport(report)).await
+    }
+
+    pub async fn send_outage_report(&self, node_id: &str, month: &OutageMonth) -> Result<()> {
+        let interruptions = |i: &outages::Interruptions| Some(streetgrid::Interruptions { count: i.count, secs: i.secs });
+        let report = OutageReport {
+            node_id: node_id.to_string(),
+            month: month.month.clone(),
+            grid_losses: interruptions(&month.grid_losses),
+            islands: interruptions(&month.islands),
+            relays: month.relays.iter()
+                .map(|(relay_id, i)| RelayOutage { relay_id: relay_id.clone(), interruptions: interruptions(i) })
+                .collect(),
+            priorities: month.shed_by_priority.iter()
+                .map(|(priority, i)| PriorityOutage { priority: format!("{:?}", priority), interruptions: interruptions(i) })
+                .collect(),
+            timestamp: self.clock.unix(),
+        };
+        info!("Sending OutageReport for {}: {} grid losses, {} islands", month.month, month.grid_losses.count, month.islands.count);
+        self.send(Payload::OutageReport(report)).await
+    }
+
+    pub async fn send_audit_log(&self, node_id: &str, entries: &[AuditEntry], more: bool) -> Result<()> {
+        let entries = entries.iter()
+            .map(|e| Ok(AuditLogEntry {
+                seq: e.seq,
+                timestamp: e.timestamp,
+                json: serde_json::to_string(e)?,
+            }))
+            .collect::<Result<Vec<_>>>()?;
+        let upload = AuditLogUpload {
+            node_id: node_id.to_string(),
+            entries,
+            more,
+        };
+        self.send(Payload::AuditLogUpload(upload)).await
+    }
+
+    pub async fn send_snapshot(&self, snapshot: SnapshotData) -> Result<()> {
+        info!("Sending SnapshotData ({} bytes) for node {}", snapshot.payload.len(), snapshot.node_id);
+        self.send(Payload::SnapshotData(snapshot)).await
+    }
+
+    pub async fn send_alarm(&self, node_id: &str, code: &str, message: &str, critical: bool) -> Result<()> {
+        let alarm = Alarm {
+            node_id: node_id.to_string(),
+            code: code.to_string(),
+            message: message.to_string(),
+            timestamp: self.clock.unix(),
+            critical,
+        };
+        info!("Sending Alarm {} for node {}", code, node_id);
+        self.send(Payload::Alarm(alarm)).await
+    }
+
+    pub async fn send_firmware_status(&self, status: FirmwareStatus) -> Result<()> {
+        info!("Sending FirmwareStatus (installed: {}) for node {}", status.installed, status.node_id);
+        self.send(Payload::FirmwareStatus(status)).await
+    }
+
+    pub async fn send_tamper_alert(&self, node_id: &str, opened: bool, safe_state_applied: bool) -> Result<()> {
+        let alert = TamperAlert {
+            node_id: node_id.to_string(),
+            opened,
+            timestamp: self.clock.unix(),
+            safe_state_applied,
+        };
+        info!("Sending TamperAlert (opened: {}) for node {}", opened, node_id);
+        self.send(Payload::TamperAlert(alert)).await
+    }
+
+    pub async fn send_neighbor_report(&self, node_id: &str) -> Result<()> {
+        let now = self.clock.unix();
+        let neighbors = self.neighbors.lock().unwrap().current(now);
+        let report = NeighborReport {
+            node_id: node_id.to_string(),
+            timestamp: now,
+            neighbors: neighbors.into_iter()
+                .map(|n| Neighbor {
+                    node_id: n.node_id,
+                    rssi: n.rssi.map(i32::from).unwrap_or(0),
+                    snr: n.snr.unwrap_or(0.0),
+                    last_heard: n.last_heard,
+                    packets: n.packets,
+                    frames_heard: n.frames_heard,
+                    frames_expected: n.frames_expected,
+                })
+                .collect(),
+        };
+        info!("Sending NeighborReport with {} neighbors", report.neighbors.len());
+        self.send(Payload::NeighborReport(report)).await
+    }
+
+    pub async fn send_voltage_observation(&self, node_id: &str, voltage: f32, undervoltage: bool) -> Result<()> {
+        let observation = VoltageObservation {
+            node_id: node_id.to_string(),
+            voltage,
+            undervoltage,
+            timestamp: self.clock.unix(),
+        };
+        info!("Sharing VoltageObservation {:.1}V (undervoltage: {})", voltage, undervoltage);
+        self.send(Payload::VoltageObservation(observation)).await
+    }
+
+    pub async fn send_coordination(&self, msg: Coordination) -> Result<()> {
+        info!("Sending Coordination kind {} for term {}", msg.kind, msg.term);
+        self.send(Payload::Coordination(msg)).await
+    }
+
+    /// Election messages received since the last call.
+    pub fn take_coordination(&self) -> Vec<Coordination> {
+        self.coordination.lock().unwrap().drain(..).collect()
+    }
+
+    /// Advertise spare supply; also counted locally, in case we are the coordinator.
+    pub async fn send_power_offer(&self, node_id: &str, available_watts: f32) -> Result<()> {
+        let now = self.clock.unix();
+        self.power.lock().unwrap().offer(node_id, available_watts, now);
+        info!("Offering {:.0} W to the island", available_watts);
+        self.send(Payload::PowerOffer(PowerOffer { node_id: node_id.to_string(), available_watts, timestamp: now })).await
+    }
+
+    /// Advertise our battery and solar; also counted locally, in case we are the coordinator.
+    pub async fn send_source_capacity(&self, node_id: &str, capacity: Capacity) -> Result<()> {
+        let now = self.clock.unix();
+        self.power.lock().unwrap().capacity(node_id, capacity, now);
+        info!("Source capacity: {:.0} W spare, SOC {:.0}%, {:.0} Wh solar expected",
+            capacity.export_watts, capacity.soc * 100.0, capacity.solar_forecast_wh);
+        self.send(Payload::SourceCapacity(SourceCapacity {
+            node_id: node_id.to_string(),
+            export_watts: capacity.export_watts,
+            soc: capacity.soc,
+            battery_capacity_wh: capacity.battery_capacity_wh,
+            stored_wh: capacity.stored_wh,
+            solar_forecast_wh: capacity.solar_forecast_wh,
+            timestamp: now,
+        })).await
+    }
+
+    /// Ask for a budget; also counted locally, in case we are the coordinator.
+    pub async fn send_power_request(&self, node_id: &str, demand: Demand) -> Result<()> {
+        let now = self.clock.unix();
+        self.power.lock().unwrap().request(node_id, demand, now, now);
+        info!("Requesting {:.0} W ({:.0} W critical)", demand.requested_watts, demand.critical_watts);
+        self.send(Payload::PowerRequest(PowerRequest {
+            node_id: node_id.to_string(),
+            requested_watts: demand.requested_watts,
+            critical_watts: demand.critical_watts,
+            timestamp: now,
+            community_watts: demand.community_watts.to_vec(),
+        })).await
+    }
+
+    /// As coordinator, grant a budget; our own grant is kept locally too.
+    pub async fn send_power_grant(&self, grant: PowerGrant) -> Result<()> {
+        self.power.lock().unwrap().grant(grant.clone(), self.clock.unix());
+        info!("Granting {} {:.0} W", grant.target_node_id, grant.budget_watts);
+        self.send(Payload::PowerGrant(grant)).await
+    }
+
+    /// Budgets for every node asking, from the supply currently on offer, with the
+    /// timestamp of the request each answers.
+    pub fn allocate_power(&self) -> Vec<(String, f32, i64)> {
+        let mut power = self.power.lock().unwrap();
+        power.allocate(self.clock.unix()).into_iter()
+            .map(|(node_id, watts)| {
+                let requested_at = power.requested_at(&node_id).unwrap_or_default();
+                (node_id, watts, requested_at)
+            })
+            .collect()
+    }
+
+    /// Latest grant addressed to `node_id` since the last call.
+    pub fn take_power_grant(&self, node_id: &str) -> Option<PowerGrant> {
+        self.power.lock().unwrap().take_grant(node_id)
+    }
+
+    /// Share one of our signed energy ledger entries with the mesh.
+    pub async fn send_energy_entry(&self, entry: EnergyEntry) -> Result<()> {
+        info!("Sharing energy entry #{}: exported {:.0} Wh, imported {:.0} Wh", entry.seq, entry.exported_wh, entry.imported_wh);
+        self.send(Payload::EnergyEntry(entry)).await
+    }
+
+    pub async fn send_energy_ledger(&self, node_id: &str, entries: Vec<EnergyEntry>, more: bool) -> Result<()> {
+        let upload = EnergyLedgerUpload { node_id: node_id.to_string(), entries, more };
+        self.send(Payload::EnergyLedgerUpload(upload)).await
+    }
+
+    /// Neighbours' energy entries received since the last call.
+    pub fn take_energy_entries(&self) -> Vec<EnergyEntry> {
+        self.energy_entries.lock().unwrap().drain(..).collect()
+    }
+
+    /// As a MID, broadcast our isolation state to the edge nodes behind us.
+    /// The counter starts from the clock so it keeps rising across restarts.
+    pub async fn send_mid_status(&self, mid_id: &str, isolated: bool, reconnect_permitted: bool, key: &SigningKey) -> Result<()> {
+        let now = self.clock.now();
+        let millis = now.timestamp_millis().max(0) as u64;
+        let last = self.mid_counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| Some(last.max(millis) + 1)).unwrap();
+        let counter = last.max(millis) + 1;
+        let mut status = MidStatus { mid_id: mid_id.to_string(), isolated, reconnect_permitted, timestamp: now.timestamp(), counter, signature: Vec::new() };
+        mid::sign(&mut status, key);
+        info!("Sending MidStatus (isolated: {}, reconnect permitted: {})", isolated, reconnect_permitted);
+        self.send(Payload::MidStatus(status)).await
+    }
+
+    /// Accept status from `mid_id` only when signed with `key`.
+    pub fn trust_mid(&self, mid_id: &str, key: VerifyingKey) {
+        self.mids.lock().unwrap().trust(mid_id, key);
+    }
+
+    /// Recent status of the MID at our transformer; None if it has gone quiet.
+    pub fn mid_status(&self, mid_id: &str) -> Option<MidStatus> {
+        self.mids.lock().unwrap().fresh(mid_id, self.clock.unix()).cloned()
+    }
+
+    /// Relay an orchestrator probe to a node it can no longer hear.
+    pub async fn send_liveness_ping(&self, node_id: &str, probe: &LivenessProbe) -> Result<()> {
+        let ping = LivenessPing {
+            node_id: node_id.to_string(),
+            silent_node_id: probe.silent_node_id.clone(),
+            probe_id: probe.probe_id.clone(),
+        };
+        info!("Pinging {} for the orchestrator", ping.silent_node_id);
+        self.deliveries.lock().unwrap().sent(&ping.silent_node_id, LIVENESS_EXCHANGE, self.clock.instant());
+        self.send(Payload::LivenessPing(ping)).await
+    }
+
+    /// Liveness pings heard since the last call.
+    pub fn take_liveness_pings(&self) -> Vec<LivenessPing> {
+        self.liveness_pings.lock().unwrap().drain(..).collect()
+    }
+
+    /// Tell the orchestrator when we last heard a node it probed.
+    pub async fn send_liveness_report(&self, node_id: &str, probe: &LivenessProbe) -> Result<()> {
+        let heard = self.neighbors.lock().unwrap().get(&probe.silent_node_id).cloned();
+        let report = LivenessReport {
+            node_id: node_id.to_string(),
+            silent_node_id: probe.silent_node_id.clone(),
+            probe_id: probe.probe_id.clone(),
+            last_heard: heard.as_ref().map(|n| n.last_heard).unwrap_or(0),
+            rssi: heard.and_then(|n| n.rssi).map(i32::from).unwrap_or(0),
+        };
+        info!("Sending LivenessReport for {} (last heard {})", report.silent_node_id, report.last_heard);
+        self.send(Payload::LivenessReport(report)).await
+    }
+
+    pub async fn send_repeater_report(&self, node_id: &str, stats: &RepeaterStats) -> Result<()> {
+        let report = RepeaterReport {
+            node_id: node_id.to_string(),
+            forwarded: stats.forwarded,
+            duplicates: stats.duplicates,
+            rejected: stats.rejected,
+        };
+        self.send(Payload::RepeaterReport(report)).await
+    }
+
+    /// As a repeater: the next message heard, unmodified so its MAC still verifies
+    /// downstream, and whether it passed authentication.
+    pub async fn receive_for_forwarding(&self) -> Result<Option<(NeighborhoodMessage, bool)>> {
+        let Some(msg) = self.layer.receive().await? else {
+            return Ok(None);
+        };
+        let signature = self.check_signature(&mut NeighborhoodMessage { relay_hops: 0, ..msg.clone() });
+        if let Some(node_id) = msg.payload.as_ref().and_then(peer_node).filter(|_| msg.relay_hops == 0) {
+            if signature != SignatureStatus::Invalid {
+                let stats = self.layer.link_stats();
+                self.neighbors.lock().unwrap().heard(node_id, stats.last_rssi, stats.last_snr, self.clock.unix());
+            }
+        }
+        self.count_frame(&msg, signature);
+        let trusted = self.trusted_peer(signature);
+        if !trusted {
+            security::record(SecurityEvent::AuthFailure);
+        }
+        Ok(Some((msg, trusted)))
+    }
+
+    /// Count a numbered frame heard directly from another node towards its packet error rate.
+    fn count_frame(&self, msg: &NeighborhoodMessage, signature: SignatureStatus) {
+        if msg.origin_id.is_empty() || msg.origin_seq == 0 || msg.relay_hops > 0 || signature == SignatureStatus::Invalid {
+            return;
+        }
+        // Our own frames, repeated back to us
+        if self.origin.as_deref() == Some(msg.origin_id.as_str()) {
+            return;
+        }
+        self.neighbors.lock().unwrap().frame(&msg.origin_id, msg.origin_seq, self.clock.unix());
+    }
+
+    /// Send a message on as received, without re-signing it.
+    pub async fn forward(&self, msg: NeighborhoodMessage) -> Result<()> {
+        self.layer.send(msg).await
+    }
+
+    /// Nodes heard on the link recently.
+    pub fn neighbors(&self) -> Vec<crate::neighbors::Neighbor> {
+        self.neighbors.lock().unwrap().current(self.clock.unix())
+    }
+
+    /// Neighbours currently reporting under-voltage.
+    pub fn undervoltage_neighbors(&self) -> Vec<String> {
+        self.votes.lock().unwrap().agreeing(self.clock.unix())
+    }
+
+    pub async fn send_security_report(&self, node_id: &str, interval_secs: u32, counts: &SecurityCounts) -> Result<()> {
+        let report = SecurityReport {
+            node_id: node_id.to_string(),
+            timestamp: self.clock.unix(),
+            interval_secs,
+            auth_failures: counts.auth_failures,
+            replay_attempts: counts.replay_attempts,
+            malformed_packets: counts.malformed_packets,
+            rate_limit_trips: counts.rate_limit_trips,
+            lockout_drops: counts.lockout_drops,
+            session_rejections: counts.session_rejections,
+        };
+        info!("Sending SecurityReport: {:?}", counts);
+        self.send(Payload::SecurityReport(report)).await
+    }
+
+    pub async fn send_rebalance_ack(&self, ack: RebalanceAck) -> Result<()> {
+        info!("Sending RebalanceAck for {} (ok: {})", ack.directive_id, ack.ok);
+        self.send(Payload::RebalanceAck(ack)).await
+    }
+
+    pub async fn send_blackstart_ack(&self, ack: BlackStartAck) -> Result<()> {
+        info!("Sending BlackStartAck for {} (ok: {})", ack.step_id, ack.ok);
+        self.send(Payload::BlackStartAck(ack)).await
+    }
+
+    pub async fn send_command_result(&self, result: CommandResult) -> Result<()> {
+        info!("Sending CommandResult for {} #{} (ok: {})", result.command, result.counter, result.ok);
+        self.send(Payload::CommandResult(result)).await
+    }
+
+    pub async fn send_demand_response_ack(&self, ack: DemandResponseAck) -> Result<()> {
+        info!("Sending DemandResponseAck for {} (opted out: {})", ack.event_id, ack.opted_out);
+        self.send(Payload::DemandResponseAck(ack)).await
+    }
+
+    pub async fn send_replay_desync(&self, desync: ReplayDesync) -> Result<()> {
+        info!("Sending ReplayDesync for sender {} (last counter {})", desync.sender_id, desync.last_counter);
+        self.send(Payload::ReplayDesync(desync)).await
+    }
+
+    pub async fn send_join_request(&self, request: JoinRequest) -> Result<()> {
+        info!("Sending JoinRequest for node {}", request.node_id);
+        self.send(Payload::JoinRequest(request)).await
+    }
+
+    pub async fn send_key_rotation_ack(&self, ack: KeyRotationAck) -> Result<()> {
+        info!("Sending KeyRotationAck for epoch {} (accepted: {})", ack.new_epoch, ack.accepted);
+        self.send(Payload::KeyRotationAck(ack)).await
+    }
+
+    pub async fn receive(&self) -> Result<Option<ReceivedCommand>> {
+        let Some(mut msg) = self.layer.receive().await? else {
+            return Ok(None);
+        };
+        let relayed = std::mem::take(&mut msg.relay_hops) > 0;
+        let signature = self.check_signature(&mut msg);
+        // A repeater's echo of a frame we already heard directly; its counter would look replayed
+        if signature != SignatureStatus::Invalid && !self.seen.lock().unwrap().insert(&msg) && relayed {
+            debug!("Dropping repeated copy of a frame from {}", msg.origin_id);
+            return Ok(None);
+        }
+        // Traffic from other nodes tells us who we can hear; relayed frames came at the repeater's signal
+        let peer = msg.payload.as_ref().and_then(peer_node);
+        if let Some(node_id) = peer.filter(|_| !relayed) {
+            if signature != SignatureStatus::Invalid {
+                let stats = self.layer.link_stats();
+                self.neighbors.lock().unwrap().heard(node_id, stats.last_rssi, stats.last_snr, self.clock.unix());
+            }
+        }
+        if !relayed {
+            self.count_frame(&msg, signature);
+        }
+        if signature != SignatureStatus::Invalid {
+            let answer = match &msg.payload {
+                Some(Payload::RegistrationAck(ack)) => Some((ORCHESTRATOR_DESTINATION, registration_exchange(&ack.target_node_id))),
+                Some(Payload::Heartbeat(hb)) => Some((hb.node_id.as_str(), LIVENESS_EXCHANGE.to_string())),
+                _ => None,
+            };
+            if let Some((destination, exchange)) = answer {
+                if let Some(rtt) = self.deliveries.lock().unwrap().answered(destination, &exchange, self.clock.instant()) {
+                    debug!("{} answered {} in {} ms", destination, exchange, rtt.as_millis());
+                }
+            }
+        }
+        match &msg.payload {
+            Some(
+                Payload::VoltageObservation(_)
+                | Payload::Coordination(_)
+                | Payload::PowerOffer(_)
+                | Payload::SourceCapacity(_)
+                | Payload::PowerRequest(_)
+                | Payload::PowerGrant(_)
+                | Payload::EnergyEntry(_)
+                | Payload::MidStatus(_)
+                | Payload::LivenessPing(_),
+            ) if !self.trusted_peer(signature) => {
+                security::record(SecurityEvent::AuthFailure);
+                return Ok(None);
+            }
+            Some(Payload::VoltageObservation(obs)) => {
+                if !self.votes.lock().unwrap().record(&obs.node_id, obs.voltage, obs.undervoltage, obs.timestamp, self.clock.unix()) {
+                    debug!("Ignoring stale voltage observation from {}", obs.node_id);
+                }
+                return Ok(None);
+            }
+            Some(Payload::Coordination(coordination)) => {
+                let mut pending = self.coordination.lock().unwrap();
+                if pending.len() >= MAX_PENDING_PEER_MESSAGES {
+                    pending.pop_front();
+                }
+                pending.push_back(coordination.clone());
+                return Ok(None);
+            }
+            Some(Payload::PowerOffer(offer)) => {
+                self.power.lock().unwrap().offer(&offer.node_id, offer.available_watts, self.clock.unix());
+                return Ok(None);
+            }
+            Some(Payload::SourceCapacity(advert)) => {
+                let capacity = Capacity {
+                    export_watts: advert.export_watts.max(0.0),
+                    soc: advert.soc.clamp(0.0, 1.0),
+                    battery_capacity_wh: advert.battery_capacity_wh.max(0.0),
+                    stored_wh: advert.stored_wh.max(0.0),
+                    solar_forecast_wh: advert.solar_forecast_wh.max(0.0),
+                };
+                self.power.lock().unwrap().capacity(&advert.node_id, capacity, self.clock.unix());
+                return Ok(None);
+            }
+            Some(Payload::PowerRequest(request)) => {
+                let requested = request.requested_watts.max(0.0);
+                let mut demand = Demand { requested_watts: requested, critical_watts: request.critical_watts.max(0.0).min(requested), ..Default::default() };
+                // No tier may claim more than the whole request, or it would jump the queue
+                for (slot, watts) in demand.community_watts.iter_mut().zip(&request.community_watts) {
+                    *slot = watts.max(0.0).min(requested);
+                }
+                self.power.lock().unwrap().request(&request.node_id, demand, request.timestamp, self.clock.unix());
+                return Ok(None);
+            }
+            Some(Payload::PowerGrant(grant)) => {
+                if !self.power.lock().unwrap().grant(grant.clone(), self.clock.unix()) {
+                    debug!("Ignoring stale power grant from {} for {}", grant.coordinator_id, grant.target_node_id);
+                }
+                return Ok(None);
+            }
+            Some(Payload::EnergyEntry(entry)) => {
+                let mut pending = self.energy_entries.lock().unwrap();
+                if pending.len() >= MAX_PENDING_PEER_MESSAGES {
+                    pending.pop_front();
+                }
+                pending.push_back(entry.clone());
+                return Ok(None);
+            }
+            Some(Payload::MidStatus(status)) => {
+                if let Err(e) = self.mids.lock().unwrap().record(status.clone(), self.clock.unix()) {
+                    debug!("Ignoring MID status: {}", e);
+                }
+                return Ok(None);
+            }
+            Some(Payload::LivenessPing(ping)) => {
+                let mut pending = self.liveness_pings.lock().unwrap();
+                if pending.len() >= MAX_PENDING_PEER_MESSAGES {
+                    pending.pop_front();
+                }
+                pending.push_back(ping.clone());
+                return Ok(None);
+            }
+            _ if peer.is_some() => return Ok(None),
+            _ => {}
+        }
+        let co_signatures = (!msg.approvals.is_empty()).then(|| {
+            let approvals = std::mem::take(&mut msg.approvals);
+            CoSignatures { message: msg.encode_to_vec(), approvals }
+        });
+        let command = match msg.payload.map(IncomingCommand::from_payload) {
+            Some(Some(command)) => command,
+            // Empty, or a payload type this firmware doesn't know
+            None => {
+                security::record(SecurityEvent::MalformedPacket);
+                return Ok(None);
+            }
+            Some(None) => return Ok(None), // Ignore other messages (heartbeat, feature report, etc.)
+        };
+        let session_id = Some(msg.session_id).filter(|s| !s.is_empty());
+        let sender_id = Some(msg.sender_id).filter(|s| !s.is_empty());
+        let zone = Some(msg.target_zone).filter(|z| !z.is_empty());
+        Ok(Some(ReceivedCommand { command, signature, session_id, sender_id, counter: msg.counter, co_signatures, zone }))
+    }
+}
+
+/// Mesh transport over the node's LoRa radio: each message is one encoded packet.
+/// Radio calls run on the blocking pool, since a transmit lasts the packet's whole airtime.
+pub struct LoRaCommunication {
+    radio: Arc<Mutex<Box<dyn LoRaRadio>>>,
+    tx_packets: AtomicU32,
+    rx_packets: AtomicU32,
+    last_rssi: Mutex<Option<i16>>,
+}
+
+impl LoRaCommunication {
+    pub fn new(radio: Box<dyn LoRaRadio>) -> Self {
+        Self {
+            radio: Arc::new(Mutex::new(radio)),
+            tx_packets: AtomicU32::new(0),
+            rx_packets: AtomicU32::new(0),
+            last_rssi: Mutex::new(None),
+        }
+    }
+
+    /// Bring up the board's radio with `config`.
+    pub fn open(config: LoRaHalConfig) -> Result<Self> {
+        info!("Initializing LoRa communication with frequency {}", config.frequency);
+        Ok(Self::new(create_lora_radio(config)?))
+    }
+}
+
+#[async_trait]
+impl CommunicationLayer for LoRaCommunication {
+    async fn send(&self, msg: NeighborhoodMessage) -> Result<()> {
+        let frame = msg.encode_to_vec();
+        debug!("(LoRa) Sending {} bytes: {:?}", frame.len(), msg);
+        off_loop(&self.radio, move |radio| radio.transmit(&frame)).await?;
+        self.tx_packets.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn receive(&self) -> Result<Option<NeighborhoodMessage>> {
+        let received = off_loop(&self.radio, |radio| Ok(radio.receive()?.map(|frame| (frame, radio.last_rssi())))).await?;
+        let Some((frame, rssi)) = received else {
+            return Ok(None);
+        };
+        self.rx_packets.fetch_add(1, Ordering::Relaxed);
+        *self.last_rssi.lock().unwrap() = rssi;
+        Ok(decode_frame(&frame))
+    }
+
+    fn name(&self) -> &'static str {
+        "lora"
+    }
+
+    fn link_stats(&self) -> LinkStats {
+        // SNR becomes available once the SX126x driver reports packet status (M3)
+        LinkStats {
+            last_rssi: *self.last_rssi.lock().unwrap(),
+            tx_packets: self.tx_packets.load(Ordering::Relaxed),
+            rx_packets: self.rx_packets.load(Ordering::Relaxed),
+            ..Default::default()
+        }
+    }
+}
+
+/// Baud rate of the serial transport when none is configured.
+pub const DEFAULT_SERIAL_BAUD: u32 = 115_200;
+
+/// Sent frames kept by the mock transport for inspection; older ones are dropped.
+const MOCK_SENT_CAPACITY: usize = 256;
+
+/// Stand-in transport for bench setups without a radio or broker: logs what it sends
+/// and delivers only the messages handed to `inject`.
+#[derive(Default)]
+pub struct MockCommunication {
+    sent: Mutex<VecDeque<NeighborhoodMessage>>,
+    inbox: Mutex<VecDeque<NeighborhoodMessage>>,
+    tx_packets: AtomicU32,
+    rx_packets: AtomicU32,
+}
+
+impl MockCommunication {
+    /// Queue a message to be received, as if it had arrived over the air.
+    pub fn inject(&self, msg: NeighborhoodMessage) {
+        self.inbox.lock().unwrap().push_back(msg);
+    }
+
+    /// The most recently sent messages, oldest first.
+    pub fn sent(&self) -> Vec<NeighborhoodMessage> {
+        self.sent.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[async_trait]
+impl CommunicationLayer for MockCommunication {
+    async fn send(&self, msg: NeighborhoodMessage) -> Result<()> {
+        info!("(mock) Sending {} bytes: {:?}", msg.encoded_len(), msg);
+        let mut sent = self.sent.lock().unwrap();
+        if sent.len() == MOCK_SENT_CAPACITY {
+            sent.pop_front();
+        }
+        sent.push_back(msg);
+        self.tx_packets.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn receive(&self) -> Result<Option<NeighborhoodMessage>> {
+        let msg = self.inbox.lock().unwrap().pop_front();
+        if msg.is_some() {
+            self.rx_packets.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(msg)
+    }
+
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn link_stats(&self) -> LinkStats {
+        LinkStats {
+            tx_packets: self.tx_packets.load(Ordering::Relaxed),
+            rx_packets: self.rx_packets.load(Ordering::Relaxed),
+            ..Default::default()
+        }
+    }
+}
+
+/// What a transport may need to start besides its own section of the comms config.
+pub struct TransportContext<'a> {
+    pub node_id: &'a str,
+    /// Where radio modules are wired
+    pub hardware: Option<&'a HardwareConfig>,
+    /// IP transports load their TLS identity from here
+    pub storage: Option<&'a Storage>,
+}
+
+/// One kind of transport the node can start from its section of the comms config.
+#[derive(Clone, Copy)]
+pub struct TransportFactory {
+    pub name: &'static str,
+    /// Whether the comms config has a section for this transport
+    pub configured: fn(&CommsConfig) -> bool,
+    pub create: fn(&CommsConfig, &TransportContext) -> Result<Arc<dyn CommunicationLayer>>,
+}
+
+/// The transports a node knows how to start, in order of preference. A new transport
+/// is a factory registered in `Default`, plus its section in `CommsConfig`.
+pub struct TransportRegistry {
+    factories: Vec<TransportFactory>,
+}
+
+impl Default for TransportRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(TransportFactory {
+            name: "lora",
+            configured: |config| config.lora.is_some(),
+            create: |config, context| {
+                let lora = config.lora.as_ref().ok_or_else(|| CommsError::Config("comms has no lora section".to_string()))?;
+                let module = context.hardware.and_then(|hw| hw.lora.as_ref());
+                Ok(Arc::new(LoRaCommunication::open(lora.hal_config(module))?))
+            },
+        });
+        registry.register(TransportFactory {
+            name: "mqtt",
+            configured: |config| config.mqtt.is_some(),
+            create: |config, context| {
+                let mqtt = config.mqtt.as_ref().ok_or_else(|| CommsError::Config("comms has no mqtt section".to_string()))?;
+                info!("Initializing MQTT communication with {}", mqtt.host);
+                let settings = MqttSettings::from_config(mqtt, context.node_id, context.storage)?;
+                Ok(Arc::new(MqttCommunication::connect(settings)?))
+            },
+        });
+        registry.register(TransportFactory {
+            name: "serial",
+            configured: |config| config.serial.is_some(),
+            create: |config, _| {
+                let serial = config.serial.as_ref().ok_or_else(|| CommsError::Config("comms has no serial section".to_string()))?;
+                Ok(Arc::new(SerialCommunication::open(&serial.port, serial.baud.unwrap_or(DEFAULT_SERIAL_BAUD))?))
+            },
+        });
+        registry.register(TransportFactory {
+            name: "mock",
+            configured: |config| config.mock.unwrap_or(false),
+            create: |_, _| {
+                info!("Using the mock transport: nothing is transmitted");
+                Ok(Arc::new(MockCommunication::default()))
+            },
+        });
+        registry
+    }
+}
+
+impl TransportRegistry {
+    /// A registry without any transports.
+    pub fn empty() -> Self {
+        Self { factories: Vec::new() }
+    }
+
+    /// Add a transport, preferred after those already registered; replaces one of the same name.
+    pub fn register(&mut self, factory: TransportFactory) {
+        match self.factories.iter_mut().find(|f| f.name == factory.name) {
+            Some(existing) => *existing = factory,
+            None => self.factories.push(factory),
+        }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.factories.iter().map(|f| f.name).collect()
+    }
+
+    /// Start the transport named by `config.transport`, or else the first configured one.
+    /// Ok(None) when the config has no transport section at all.
+    pub fn create(&self, config: &CommsConfig, context: &TransportContext) -> Result<Option<Arc<dyn CommunicationLayer>>> {
+        let configured: Vec<&TransportFactory> = self.factories.iter().filter(|f| (f.configured)(config)).collect();
+        let factory = match &config.transport {
+            Some(name) => match self.factories.iter().find(|f| f.name == name) {
+                Some(factory) if (factory.configured)(config) => factory,
+                Some(_) => return Err(CommsError::Config(format!("transport \"{}\" selected but comms has no {} section", name, name))),
+                None => return Err(CommsError::Config(format!("unknown transport \"{}\" (known: {})", name, self.names().join(", ")))),
+            },
+            None => match configured.first() {
+                Some(factory) => {
+                    if configured.len() > 1 {
+                        let others: Vec<&str> = configured[1..].iter().map(|f| f.name).collect();
+                        warn!("Using the {} transport; set comms.transport to use {} instead", factory.name, others.join(" or "));
+                    }
+                    factory
+                }
+                None => return Ok(None),
+            },
+        };
+        (factory.create)(config, context).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Transport that records sent messages and reports fixed link stats.
+    struct RecordingLayer {
+        sent: Mutex<Vec<NeighborhoodMessage>>,
+        inbox: Mutex<Vec<NeighborhoodMessage>>,
+        stats: LinkStats,
+    }
+
+    #[async_trait]
+    impl CommunicationLayer for RecordingLayer {
+        async fn send(&self, msg: NeighborhoodMessage) -> Result<()> {
+            self.sent.lock().unwrap().push(msg);
+            Ok(())
+        }
+
+        async fn receive(&self) -> Result<Option<NeighborhoodMessage>> {
+            Ok(self.inbox.lock().unwrap().pop())
+        }
+
+        fn link_stats(&self) -> LinkStats {
+            self.stats.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_carries_link_metrics() {
+        let layer = Arc::new(RecordingLayer {
+            sent: Mutex::new(Vec::new()),
+            inbox: Mutex::new(Vec::new()),
+            stats: LinkStats {
+                last_rssi: Some(-97),
+                last_snr: Some(-4.5),
+                tx_packets: 12,
+                rx_packets: 7,
+                retransmissions: 3,
+                queue_depth: 2,
+            },
+        });
+        let client = OrchestratorClient::new(layer.clone());
+        let system = SystemStats { uptime_secs: 3600, ..Default::default() };
+        client.send_heartbeat(client.heartbeat("node_01", 0.8, system, None, None, &HeartbeatReadings::default())).await.unwrap();
+
+        let sent = layer.sent.lock().unwrap();
+        let Some(Payload::Heartbeat(hb)) = &sent[0].payload else {
+            panic!("expected heartbeat");
+        };
+        let link = hb.link.as_ref().unwrap();
+        assert_eq!(link.last_rssi, -97);
+        assert_eq!(link.tx_packets, 12);
+        assert_eq!(link.retransmissions, 3);
+        assert_eq!(link.queue_depth, 2);
+        assert_eq!(hb.system.as_ref().unwrap().uptime_secs, 3600);
+    }
+
+    /// Transport failing with each of `failures` in turn, then sending.
+    #[derive(Default)]
+    struct FailingLayer {
+        failures: Mutex<VecDeque<CommsError>>,
+        attempts: AtomicU32,
+    }
+
+    #[async_trait]
+    impl CommunicationLayer for FailingLayer {
+        async fn send(&self, _msg: NeighborhoodMessage) -> Result<()> {
+            self.attempts.fetch_add(1, Ordering::Relaxed);
+            self.failures.lock().unwrap().pop_front().map_or(Ok(()), Err)
+        }
+
+        async fn receive(&self) -> Result<Option<NeighborhoodMessage>> {
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transient_send_failures_are_retried() {
+        let busy = || CommsError::Hardware(HalError::Busy("radio"));
+        let layer = Arc::new(FailingLayer { failures: Mutex::new([busy(), busy()].into()), ..Default::default() });
+        OrchestratorClient::new(layer.clone()).send_alarm("node_01", "test", "", false).await.unwrap();
+        assert_eq!(layer.attempts.load(Ordering::Relaxed), 3);
+
+        let layer = Arc::new(FailingLayer { failures: Mutex::new([busy(), busy(), busy()].into()), ..Default::default() });
+        let error = OrchestratorClient::new(layer.clone()).send_alarm("node_01", "test", "", false).await.unwrap_err();
+        assert!(matches!(error, CommsError::Hardware(HalError::Busy(_))));
+        assert_eq!(layer.attempts.load(Ordering::Relaxed), 3);
+
+        // Trying again won't make a message fit
+        let too_long = CommsError::TooLong { size: 70_000, transport: "serial" };
+        let layer = Arc::new(FailingLayer { failures: Mutex::new([too_long].into()), ..Default::default() });
+        assert!(OrchestratorClient::new(layer.clone()).send_alarm("node_01", "test", "", false).await.is_err());
+        assert_eq!(layer.attempts.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_reports_round_trips_of_answered_messages() {
+        let layer = Arc::new(RecordingLayer { sent: Mutex::new(Vec::new()), inbox: Mutex::new(Vec::new()), stats: LinkStats::default() });
+        let clock = crate::clock::VirtualClock::at(1_700_000_000);
+        let mut client = OrchestratorClient::new(layer.clone());
+        client.set_clock(Arc::new(clock.clone()));
+
+        client.send_feature_report("node_01", vec![], "p2p", None, vec![], None).await.unwrap();
+        let probe = LivenessProbe { target_node_id: "node_01".to_string(), silent_node_id: "node_03".to_string(), probe_id: "p1".to_string() };
+        client.send_liveness_ping("node_01", &probe).await.unwrap();
+        // Answered after 400 ms; the silent node never answers
+        clock.advance(std::time::Duration::from_millis(400));
+        let ack = RegistrationAck { target_node_id: "node_01".to_string() };
+        layer.inbox.lock().unwrap().push(NeighborhoodMessage { payload: Some(Payload::RegistrationAck(ack)), ..Default::default() });
+        assert!(client.receive().await.unwrap().is_some());
+        // Resent: one more retransmission
+        client.send_feature_report("node_01", vec![], "p2p", None, vec![], None).await.unwrap();
+        client.send_feature_report("node_01", vec![], "p2p", None, vec![], None).await.unwrap();
+        clock.advance(crate::delivery::DELIVERY_TIMEOUT);
+
+        client.send_heartbeat(client.heartbeat("node_01", 0.8, SystemStats::default(), None, None, &HeartbeatReadings::default())).await.unwrap();
+        let sent = layer.sent.lock().unwrap();
+        let Some(Payload::Heartbeat(hb)) = &sent.last().unwrap().payload else {
+            panic!("expected heartbeat");
+        };
+        let link = hb.link.as_ref().unwrap();
+        assert_eq!(link.retransmissions, 1);
+        assert_eq!(link.deliveries.len(), 2);
+        assert_eq!((link.deliveries[0].destination.as_str(), link.deliveries[0].sent, link.deliveries[0].delivered), ("node_03", 1, 0));
+        let orchestrator = &link.deliveries[1];
+        assert_eq!((orchestrator.sent, orchestrator.delivered, orchestrator.rtt_avg_ms), (3, 1, 400));
+    }
+
+    #[tokio::test]
+    async fn test_signed_messages_are_verified() {
+        let keyring = Arc::new(Mutex::new(Keyring::new(1, [9; 32])));
+        let layer = Arc::new(RecordingLayer {
+            sent: Mutex::new(Vec::new()),
+            inbox: Mutex::new(Vec::new()),
+            stats: LinkStats::default(),
+        });
+        let client = OrchestratorClient::new(layer.clone()).with_keyring(keyring.clone());
+
+        let mut msg = NeighborhoodMessage {
+            payload: Some(Payload::LoadShed(LoadShed { target_node_id: "node_01".to_string(), shed_load: true })),
+            ..Default::default()
+        };
+        let (key_epoch, mac) = keyring.lock().unwrap().sign(&msg.encode_to_vec(), SystemClock.unix());
+        let mut tampered = msg.clone();
+        tampered.auth = Some(MessageAuth { key_epoch, mac: mac.clone() });
+        if let Some(Payload::LoadShed(ls)) = &mut tampered.payload {
+            ls.target_node_id = "node_02".to_string();
+        }
+        let unsigned = msg.clone();
+        msg.auth = Some(MessageAuth { key_epoch, mac });
+        layer.inbox.lock().unwrap().extend([unsigned, tampered, msg]);
+
+        assert_eq!(client.receive().await.unwrap().unwrap().signature, SignatureStatus::Valid);
+        assert_eq!(client.receive().await.unwrap().unwrap().signature, SignatureStatus::Invalid);
+        assert_eq!(client.receive().await.unwrap().unwrap().signature, SignatureStatus::Unsigned);
+
+        // Outbound messages carry the active epoch
+        client.send_heartbeat(client.heartbeat("node_01", 1.0, SystemStats::default(), None, None, &HeartbeatReadings::default())).await.unwrap();
+        let sent = layer.sent.lock().unwrap();
+        assert_eq!(sent[0].auth.as_ref().unwrap().key_epoch, 1);
+        let Some(Payload::Heartbeat(hb)) = &sent[0].payload else { panic!("expected heartbeat") };
+        assert_eq!(hb.key_epoch, 1);
+    }
+
+    #[tokio::test]
+    async fn test_overheard_nodes_are_reported_as_neighbors() {
+        let layer = Arc::new(RecordingLayer {
+            sent: Mutex::new(Vec::new()),
+            inbox: Mutex::new(Vec::new()),
+            stats: LinkStats { last_rssi: Some(-101), last_snr: Some(2.5), ..Default::default() },
+        });
+        let client = OrchestratorClient::new(layer.clone());
+        layer.inbox.lock().unwrap().push(NeighborhoodMessage {
+            payload: Some(Payload::Heartbeat(Heartbeat { node_id: "node_02".to_string(), ..Default::default() })),
+            ..Default::default()
+        });
+        assert!(client.receive().await.unwrap().is_none());
+
+        client.send_neighbor_report("node_01").await.unwrap();
+        let sent = layer.sent.lock().unwrap();
+        let Some(Payload::NeighborReport(report)) = &sent[0].payload else {
+            panic!("expected neighbor report");
+        };
+        assert_eq!(report.neighbors.len(), 1);
+        assert_eq!(report.neighbors[0].node_id, "node_02");
+        assert_eq!(report.neighbors[0].rssi, -101);
+    }
+
+    #[tokio::test]
+    async fn test_requested_tiers_are_capped_at_the_request() {
+        let layer = Arc::new(RecordingLayer { sent: Mutex::new(Vec::new()), inbox: Mutex::new(Vec::new()), stats: LinkStats::default() });
+        let client = OrchestratorClient::new(layer.clone());
+        let request = |node_id: &str, requested_watts: f32, community: f32| NeighborhoodMessage {
+            payload: Some(Payload::PowerRequest(PowerRequest {
+                node_id: node_id.to_string(),
+                requested_watts,
+                critical_watts: 0.0,
+                community_watts: vec![0.0, 0.0, community],
+                timestamp: SystemClock.unix(),
+            })),
+            ..Default::default()
+        };
+        // node_03 asks for 100 W but claims 5 kW of its highest tier
+        layer.inbox.lock().unwrap().extend([request("node_02", 900.0, 900.0), request("node_03", 100.0, 5000.0)]);
+        client.receive().await.unwrap();
+        client.receive().await.unwrap();
+        client.send_power_offer("node_01", 1000.0).await.unwrap();
+
+        let budgets: std::collections::HashMap<String, f32> = client.allocate_power().into_iter().map(|(node, watts, _)| (node, watts)).collect();
+        assert_eq!(budgets["node_02"], 900.0);
+        assert_eq!(budgets["node_03"], 100.0);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_copies_are_dropped_and_not_taken_for_neighbors() {
+        let layer = Arc::new(RecordingLayer { sent: Mutex::new(Vec::new()), inbox: Mutex::new(Vec::new()), stats: LinkStats::default() });
+        let client = OrchestratorClient::new(layer.clone());
+        let shed = NeighborhoodMessage {
+            payload: Some(Payload::LoadShed(LoadShed { target_node_id: "node_01".to_string(), shed_load: true })),
+            sender_id: "orch_a".to_string(),
+            counter: 7,
+            ..Default::default()
+        };
+        let echo = NeighborhoodMessage { relay_hops: 1, ..shed.clone() };
+        let relayed = NeighborhoodMessage {
+            payload: Some(Payload::Heartbeat(Heartbeat { node_id: "node_03".to_string(), ..Default::default() })),
+            relay_hops: 2,
+            ..Default::default()
+        };
+        // Inbox pops from the back
+        layer.inbox.lock().unwrap().extend([relayed, echo, shed]);
+
+        assert!(client.receive().await.unwrap().is_some());
+        assert!(client.receive().await.unwrap().is_none());
+        assert!(client.receive().await.unwrap().is_none());
+        assert!(client.neighbors().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_neighbor_packet_error_rate_from_numbered_frames() {
+        let recording = || Arc::new(RecordingLayer { sent: Mutex::new(Vec::new()), inbox: Mutex::new(Vec::new()), stats: LinkStats::default() });
+        let (air, ours) = (recording(), recording());
+        let neighbor = OrchestratorClient::new(air.clone()).with_origin("node_02");
+        for _ in 0..10 {
+            neighbor.send_voltage_alert("node_02", 230.0).await.unwrap();
+        }
+        let client = OrchestratorClient::new(ours.clone()).with_origin("node_01");
+        // Two lost on the way; inbox pops from the back
+        let mut heard: Vec<_> = air.sent.lock().unwrap().drain(..).enumerate().filter(|(i, _)| *i != 3 && *i != 6).map(|(_, m)| m).collect();
+        heard.reverse();
+        *ours.inbox.lock().unwrap() = heard;
+        for _ in 0..8 {
+            client.receive().await.unwrap();
+        }
+
+        client.send_neighbor_report("node_01").await.unwrap();
+        let sent = ours.sent.lock().unwrap();
+        assert_eq!((sent[0].origin_id.as_str(), sent[0].origin_seq), ("node_01", 1));
+        let Some(Payload::NeighborReport(report)) = &sent[0].payload else {
+            panic!("expected neighbor report");
+        };
+        assert_eq!((report.neighbors[0].frames_heard, report.neighbors[0].frames_expected), (8, 10));
+    }
+
+    #[tokio::test]
+    async fn test_only_signed_voltage_votes_count_with_keyring() {
+        let keyring = Arc::new(Mutex::new(Keyring::new(1, [9; 32])));
+        let layer = Arc::new(RecordingLayer {
+            sent: Mutex::new(Vec::new()),
+            inbox: Mutex::new(Vec::new()),
+            stats: LinkStats::default(),
+        });
+        let client = OrchestratorClient::new(layer.clone()).with_keyring(keyring.clone());
+        let vote = |node_id: &str| NeighborhoodMessage {
+            payload: Some(Payload::VoltageObservation(VoltageObservation {
+                node_id: node_id.to_string(),
+                voltage: 92.0,
+                undervoltage: true,
+                timestamp: SystemClock.unix(),
+            })),
+            ..Default::default()
+        };
+        let mut signed = vote("node_02");
+        let (key_epoch, mac) = keyring.lock().unwrap().sign(&signed.encode_to_vec(), SystemClock.unix());
+        signed.auth = Some(MessageAuth { key_epoch, mac });
+        layer.inbox.lock().unwrap().extend([vote("node_03"), signed]);
+
+        assert!(client.receive().await.unwrap().is_none());
+        assert!(client.receive().await.unwrap().is_none());
+        assert_eq!(client.undervoltage_neighbors(), vec!["node_02"]);
+    }
+
+    #[tokio::test]
+    async fn test_mid_status_counts_only_when_signed_by_the_mid() {
+        let layer = || Arc::new(RecordingLayer {
+            sent: Mutex::new(Vec::new()),
+            inbox: Mutex::new(Vec::new()),
+            stats: LinkStats::default(),
+        });
+        let key = SigningKey::from_bytes(&[5; 32]);
+        let (mid_layer, node_layer) = (layer(), layer());
+        let mid = OrchestratorClient::new(mid_layer.clone());
+        let node = OrchestratorClient::new(node_layer.clone());
+        node.trust_mid("mid_t1", key.verifying_key());
+
+        // A neighbour holding only the mesh key claims reconnection is permitted
+        let forged = NeighborhoodMessage {
+            payload: Some(Payload::MidStatus(MidStatus {
+                mid_id: "mid_t1".to_string(),
+                reconnect_permitted: true,
+                timestamp: SystemClock.unix(),
+                counter: u64::MAX,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        node_layer.inbox.lock().unwrap().push(forged);
+        assert!(node.receive().await.unwrap().is_none());
+        assert!(node.mid_status("mid_t1").is_none());
+
+        mid.send_mid_status("mid_t1", true, false, &key).await.unwrap();
+        mid.send_mid_status("mid_t1", true, true, &key).await.unwrap();
+        let sent = mid_layer.sent.lock().unwrap().clone();
+        // The later status first, then the earlier one replayed
+        node_layer.inbox.lock().unwrap().extend(sent);
+        assert!(node.receive().await.unwrap().is_none());
+        assert!(node.receive().await.unwrap().is_none());
+        assert!(node.mid_status("mid_t1").unwrap().reconnect_permitted);
+    }
+
+    #[tokio::test]
+    async fn test_lora_transmits_and_receives_through_the_radio() {
+        use crate::hal::lora::mock::MockLoRaRadio;
+
+        let (radio, peer) = MockLoRaRadio::pair(LoRaHalConfig::default()).unwrap();
+        let lora = LoRaCommunication::new(Box::new(radio));
+        let peer = LoRaCommunication::new(Box::new(peer));
+        let msg = NeighborhoodMessage { sender_id: "node_01".to_string(), counter: 3, ..Default::default() };
+        lora.send(msg.clone()).await.unwrap();
+        lora.send(NeighborhoodMessage::default()).await.unwrap();
+        assert_eq!(lora.link_stats().tx_packets, 2);
+        assert_eq!(lora.link_stats().last_rssi, None);
+
+        assert_eq!(peer.receive().await.unwrap(), Some(msg));
+        assert_eq!(peer.receive().await.unwrap(), Some(NeighborhoodMessage::default()));
+        assert_eq!(peer.receive().await.unwrap(), None);
+        let stats = peer.link_stats();
+        assert_eq!(stats.rx_packets, 2);
+        assert_eq!(stats.last_rssi, Some(-50));
+    }
+
+    #[test]
+    fn test_registry_starts_the_selected_or_first_configured_transport() {
+        use crate::config::LoRaConfig;
+
+        let registry = TransportRegistry::default();
+        let context = TransportContext { node_id: "node_01", hardware: None, storage: None };
+        let mut config = CommsConfig {
+            transport: None,
+            lora: None,
+            mqtt: None,
+            serial: None,
+            mock: None,
+            capture: None,
+        };
+        assert!(registry.create(&config, &context).unwrap().is_none());
+
+        config.mock = Some(true);
+        config.lora = Some(LoRaConfig {
+            frequency: 915_000_000,
+            bandwidth: 125_000,
+            tx_power: 14,
+            spreading_factor: 7,
+            antenna_gain_dbi: None,
+            min_fade_margin_db: None,
+        });
+        assert_eq!(registry.create(&config, &context).unwrap().unwrap().name(), "lora");
+        config.transport = Some("mock".to_string());
+        assert_eq!(registry.create(&config, &context).unwrap().unwrap().name(), "mock");
+
+        // Selecting a transport without its section, or one we don't know, is a config error
+        config.transport = Some("serial".to_string());
+        assert!(matches!(registry.create(&config, &context), Err(CommsError::Config(_))));
+        config.transport = Some("carrier_pigeon".to_string());
+        assert!(matches!(registry.create(&config, &context), Err(CommsError::Config(_))));
+    }
+}
@@ -0,0 +1,532 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::collections::{BTreeMap, HashMap};
+use thiserror::Error;
+use crate::types::{CtDirection, Relay, MeshType, NodeRole, Phase, Priority};
+use crate::faults::Faults;
+use crate::protection::ProtectionSettings;
+use crate::hal::{BatteryConfig, LoRaHalConfig};
+use crate::generator::GeneratorSettings;
+use crate::tariff::{Tariff, TariffPeriod};
+use crate::cycling::DutyCycle;
+use crate::shedding::ShedStrategy;
+use crate::phases::PhaseSensing;
+use crate::submeter::MeteredCircuit;
+use crate::arc_fault::ArcFaultSettings;
+use crate::thermal::ThermalSettings;
+use crate::pickup::PickupSettings;
+use crate::reserve::ReserveSettings;
+use crate::motors::MotorSettings;
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Config {
+    pub id: String,
+    pub node_type: Option<NodeRole>,
+    pub mesh_type: Option<MeshType>,
+    /// Empty on a repeater
+    #[serde(default)]
+    pub relays: Vec<Relay>,
+    pub comms: Option<CommsConfig>,
+    pub hardware: Option<HardwareConfig>,
+    pub api: Option<ApiConfig>,
+    pub storage: Option<StorageConfig>,
+    pub export: Option<ExportConfig>,
+    pub anomaly: Option<AnomalyConfig>,
+    pub security: Option<SecurityConfig>,
+    pub provisioning: Option<ProvisioningConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub ota: Option<OtaConfig>,
+    pub islanding: Option<IslandingConfig>,
+    /// When readings mean the grid has failed
+    pub protection: Option<ProtectionSettings>,
+    pub election: Option<ElectionConfig>,
+    pub mid: Option<MidConfig>,
+    /// How the house is switched between grid, generator and inverter
+    pub transfer: Option<TransferConfig>,
+    pub failover: Option<FailoverConfig>,
+    pub sources: Option<SourcesConfig>,
+    pub demand_response: Option<DemandResponseConfig>,
+    /// Time-of-use prices, and loads deferred through their peaks
+    pub tariff: Option<TariffConfig>,
+    /// Shed ahead of a forecast overload
+    pub forecast: Option<ForecastConfig>,
+    /// Hardware and radio faults to inject; for bench testing only
+    pub faults: Option<Faults>,
+    /// Groups the node belongs to (e.g. "phase_A", "block_3"), for zone-wide commands
+    pub zones: Option<Vec<String>>,
+    /// Transformer phase the household is wired to
+    pub phase: Option<Phase>,
+    /// Load relays opened on arcing, by relay ID; each needs a CT clamp
+    pub arc_fault: Option<HashMap<String, ArcFaultSettings>>,
+    /// Derate the relays while the board runs hot
+    pub thermal: Option<ThermalSettings>,
+    /// Pace loads put back after a shed or outage to the pickup current they draw
+    pub cold_load_pickup: Option<PickupSettings>,
+    /// Loads given up tier by tier as the battery runs down while islanded
+    pub battery_reserve: Option<ReserveSettings>,
+    /// Motor loads started one at a time, rested, and through soft-start relays
+    pub motors: Option<MotorSettings>,
+    /// What the node does on its way down when stopped by SIGTERM or SIGINT
+    pub shutdown: Option<ShutdownConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShutdownConfig {
+    /// Relay positions (relay_id -> closed) applied before the node exits
+    pub safe_state: Option<HashMap<String, bool>>,
+    /// Restart with the relays where they were before the safe state, rather than in it
+    /// (default false)
+    pub resume_on_boot: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FailoverConfig {
+    /// Sender IDs of the two orchestrators
+    pub primary: String,
+    pub secondary: String,
+    /// Primary silence after which the secondary's commands are accepted (default 300)
+    pub takeover_after_secs: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SourcesConfig {
+    /// Usable battery capacity; omit without a battery
+    pub battery_capacity_wh: Option<f32>,
+    /// Charge kept back for the household and never exported (default 0.2)
+    pub reserve_soc: Option<f32>,
+    /// Panel output on a clear day at solar noon; omit without solar
+    pub solar_peak_watts: Option<f32>,
+    /// Share of charging energy the simulated battery stores (default 0.95)
+    pub charge_efficiency: Option<f32>,
+    /// Share of the simulated battery's energy that reaches the loads (default 0.95)
+    pub discharge_efficiency: Option<f32>,
+    /// Charge of the simulated battery at boot (default 1.0)
+    pub initial_soc: Option<f32>,
+}
+
+impl SourcesConfig {
+    /// The simulated battery of mock runs and scenarios, if there is a battery.
+    pub fn battery(&self) -> Option<BatteryConfig> {
+        let defaults = BatteryConfig::default();
+        self.battery_capacity_wh.filter(|wh| *wh > 0.0).map(|capacity_wh| BatteryConfig {
+            capacity_wh,
+            initial_soc: self.initial_soc.unwrap_or(defaults.initial_soc),
+            charge_efficiency: self.charge_efficiency.unwrap_or(defaults.charge_efficiency),
+            discharge_efficiency: self.discharge_efficiency.unwrap_or(defaults.discharge_efficiency),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DemandResponseConfig {
+    /// Decline utility demand-response events; loads are then never shed for them
+    #[serde(default)]
+    pub opt_out: bool,
+    /// Load relays cycled through events instead of opened for all of them
+    pub duty_cycle: Option<HashMap<String, DutyCycle>>,
+    /// How loads are picked for events that don't say (default every load at or below
+    /// the event's priority)
+    pub strategy: Option<ShedStrategy>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TariffConfig {
+    /// Price per kWh outside the periods
+    pub base_price: f32,
+    #[serde(default)]
+    pub periods: Vec<TariffPeriod>,
+    /// Defer loads of each priority while the price is above this; Critical loads never are
+    pub defer_above: Option<BTreeMap<Priority, f32>>,
+}
+
+impl TariffConfig {
+    pub fn tariff(&self) -> Tariff {
+        Tariff { base_price: self.base_price, periods: self.periods.clone() }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ForecastConfig {
+    /// Open loads when the next hour's forecast runs over the island budget or a demand-response cap
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MidConfig {
+    /// MID at our transformer; on a GovernmentSanctioned mesh its status gates the Grid relays
+    pub id: String,
+    /// Hex-encoded Ed25519 public key the MID signs its status with
+    pub public_key: Option<String>,
+    /// On the MID itself: its 32-byte hex Ed25519 signing key
+    pub signing_key: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransferConfig {
+    /// Time with no source connected between breaking one and making the next (default 500)
+    pub dead_time_ms: Option<u64>,
+    /// Source relay of an inverter that forms its own grid when islanded
+    pub inverter_relay: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ElectionConfig {
+    /// Take part in electing a coordinator when the orchestrator goes quiet
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IslandingConfig {
+    /// Neighbours that must also report under-voltage before the node islands on its own
+    pub quorum: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OtaConfig {
+    /// Hex-encoded Ed25519 public keys allowed to sign firmware manifests
+    pub release_keys: Vec<String>,
+    /// Hardware identifier images must target, e.g. "rpi4"
+    pub hardware: String,
+    /// Path the verified firmware binary is installed to
+    pub install_path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RateLimitConfig {
+    /// Commands of one type allowed per minute
+    pub per_minute: Option<u32>,
+    /// Per-command-type overrides, keyed by snake_case command name (e.g. "activate_relay_by_index")
+    pub overrides: Option<HashMap<String, u32>>,
+    /// Seconds non-safety commands are refused after a limit is exceeded
+    pub lockout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProvisioningConfig {
+    /// Commissioning token; until the orchestrator accepts it the node stays in `Joining`
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SecurityConfig {
+    /// Initial mesh pre-shared key, 32 bytes hex-encoded. Superseded by rotations persisted in storage.
+    pub psk: Option<String>,
+    /// Epoch of the configured PSK
+    pub key_epoch: Option<u32>,
+    /// Require operator commands to reference a session granted by the orchestrator
+    pub require_sessions: Option<bool>,
+    /// Require a per-sender counter on commands and reject ones not newer than the last seen
+    pub replay_protection: Option<bool>,
+    /// Hardware ID (Pi CPU serial or machine-id) this identity is bound to; on any other
+    /// board the node refuses to register and raises a `hardware_mismatch` alarm
+    pub hardware_id: Option<String>,
+    /// Co-signature requirement for destructive commands
+    pub multisig: Option<MultiSigConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MultiSigConfig {
+    /// Hex-encoded Ed25519 public keys of the independent authorities
+    pub signers: Vec<String>,
+    /// Distinct signers that must approve each command
+    pub threshold: usize,
+    /// Command kinds needing approval; defaults to factory_reset, plus disconnect_grid and mid_command
+    /// on a GovernmentSanctioned mesh
+    pub commands: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnomalyConfig {
+    /// Readings per channel used for the rolling mean/stddev
+    pub window: Option<usize>,
+    /// Deviation in standard deviations that raises an AnomalyAlert
+    pub threshold_sigma: Option<f32>,
+    /// Consecutive abnormal readings before alerting
+    pub persist_readings: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportConfig {
+    pub serial: Option<SerialExportConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SerialExportConfig {
+    /// UART device, e.g. "/dev/ttyS0"
+    pub port: String,
+    pub baud: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StorageConfig {
+    /// Directory for persisted state, counters and logs
+    pub path: String,
+    /// Minimum seconds between SD card flushes
+    pub flush_interval_secs: Option<u64>,
+    pub retention: Option<RetentionConfig>,
+    /// Encrypt everything persisted, keyed by the secure element or `key_file`
+    pub encrypt: Option<bool>,
+    /// 32-byte hex device key used for encryption when no secure element is fitted
+    pub key_file: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetentionConfig {
+    /// Days of raw measurements kept before rolling up to hourly summaries
+    pub raw_days: Option<u32>,
+    /// Months of hourly summaries kept
+    pub hourly_months: Option<u32>,
+    /// Hard cap on measurement data in megabytes
+    pub max_mb: Option<u64>,
+    pub compaction_interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiConfig {
+    /// Address the local HTTP API listens on, e.g. "0.0.0.0:8080"
+    pub bind: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HardwareConfig {
+    pub relay_pins: Option<HashMap<String, u8>>,
+    /// Input wired to each relay's auxiliary contact, which pulls it low while closed
+    pub relay_feedback_pins: Option<HashMap<String, u8>>,
+    /// ADC channel of the CT clamp on each relay's circuit
+    pub ct_channels: Option<HashMap<String, u8>>,
+    /// Loads with a CT clamp that draw current whenever closed (e.g. a fridge), so a
+    /// closed relay reading none has failed open
+    pub always_drawing: Option<Vec<String>>,
+    /// Per-phase sensing of a three-phase service
+    pub phases: Option<BTreeMap<Phase, PhaseSensing>>,
+    /// Household circuits metered through their own CT clamps, by name
+    pub submeter: Option<BTreeMap<String, MeteredCircuit>>,
+    pub adc: Option<AdcHardwareConfig>,
+    pub secure_element: Option<SecureElementHardwareConfig>,
+    pub tamper: Option<TamperHardwareConfig>,
+    /// Emergency-stop button, which opens every relay and latches the node Faulted
+    pub emergency_stop: Option<EmergencyStopHardwareConfig>,
+    /// EV charger whose pilot signal we drive
+    pub evse: Option<EvseHardwareConfig>,
+    /// SunSpec solar inverter
+    pub inverter: Option<InverterHardwareConfig>,
+    /// Battery monitor or BMS on a VE.Direct port
+    pub bms: Option<BmsHardwareConfig>,
+    /// Backup generator with a two-wire remote start
+    pub generator: Option<GeneratorHardwareConfig>,
+    /// SX126x radio module
+    pub lora: Option<LoRaHardwareConfig>,
+    /// Second radio on a hardware-in-the-loop bench, wired in range of the first
+    pub lora_loopback: Option<LoRaHardwareConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoRaHardwareConfig {
+    pub spi_bus: Option<u8>,
+    pub spi_cs: Option<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TamperHardwareConfig {
+    pub gpio_pin: u8,
+    /// Input reads LOW when the enclosure is open
+    pub active_low: Option<bool>,
+    /// Relay positions (relay_id -> closed) forced when the enclosure is opened
+    pub safe_state: Option<HashMap<String, bool>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmergencyStopHardwareConfig {
+    pub gpio_pin: u8,
+    /// Input reads LOW while the stop is asserted
+    pub active_low: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EvseHardwareConfig {
+    /// Load relay feeding the charger
+    pub relay_id: String,
+    /// Hardware PWM channel generating the pilot (default 0, GPIO 18)
+    pub pwm_channel: Option<u8>,
+    /// Current the charger and its circuit are installed for
+    pub max_amps: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InverterHardwareConfig {
+    /// Modbus TCP address (host:port) of the inverter
+    pub tcp: Option<String>,
+    /// Or the RS-485 adapter it hangs off, for Modbus RTU
+    pub serial_port: Option<String>,
+    pub baud_rate: Option<u32>,
+    /// Modbus unit ID (default 1)
+    pub unit_id: Option<u8>,
+    /// Start of the SunSpec register map (default 40000)
+    pub base_address: Option<u16>,
+    /// While islanded, output is curtailed from this frequency down to nothing at
+    /// `curtail_stop_hz`; both must be set
+    pub curtail_start_hz: Option<f32>,
+    pub curtail_stop_hz: Option<f32>,
+    /// Likewise by voltage, from `curtail_start_volts` down to nothing at `curtail_stop_volts`
+    pub curtail_start_volts: Option<f32>,
+    pub curtail_stop_volts: Option<f32>,
+    /// While islanded, reactive power is absorbed above nominal voltage and supplied
+    /// below it, starting this far from nominal and reaching the inverter's maximum at
+    /// `volt_var_full_volts`; both must be set
+    pub volt_var_deadband_volts: Option<f32>,
+    pub volt_var_full_volts: Option<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BmsHardwareConfig {
+    pub serial_port: String,
+    /// Pack limits, which VE.Direct doesn't carry
+    pub max_charge_amps: f32,
+    pub max_discharge_amps: f32,
+    /// Pack voltage at which the island may draw no more from it
+    pub cutoff_voltage: Option<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GeneratorHardwareConfig {
+    /// Source relay transferring the house to the generator
+    pub transfer_relay: String,
+    /// Output closing the remote-start contact
+    pub start_pin: u8,
+    /// Input from the voltage-sensing relay on the generator's output
+    pub sense_pin: u8,
+    pub sense_active_low: Option<bool>,
+    /// Start/stop timers (defaults 15, 60, 10 and 120 s)
+    pub crank_secs: Option<u64>,
+    pub warmup_secs: Option<u64>,
+    pub stabilize_secs: Option<u64>,
+    pub cooldown_secs: Option<u64>,
+    /// While islanded, start at or below this battery charge (default 0.3) and stop above
+    /// `stop_soc` (default 0.8)
+    pub start_soc: Option<f32>,
+    pub stop_soc: Option<f32>,
+}
+
+impl GeneratorHardwareConfig {
+    pub fn settings(&self) -> GeneratorSettings {
+        let defaults = GeneratorSettings::default();
+        let secs = |value: Option<u64>, default: Duration| value.map_or(default, Duration::from_secs);
+        GeneratorSettings {
+            crank: secs(self.crank_secs, defaults.crank),
+            warmup: secs(self.warmup_secs, defaults.warmup),
+            stabilize: secs(self.stabilize_secs, defaults.stabilize),
+            cooldown: secs(self.cooldown_secs, defaults.cooldown),
+            start_soc: self.start_soc.unwrap_or(defaults.start_soc),
+            stop_soc: self.stop_soc.unwrap_or(defaults.stop_soc),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SecureElementHardwareConfig {
+    pub i2c_bus: Option<u8>,
+    pub address: Option<u8>,
+    /// Slot holding the device identity (ECDSA) key
+    pub sign_slot: Option<u8>,
+    /// Slot holding the secret the keyring encryption key is derived from
+    pub kek_slot: Option<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdcHardwareConfig {
+    pub i2c_bus: Option<u8>,
+    pub address: Option<u8>,
+    pub ct_ratio: Option<f32>,
+    pub voltage_ref: Option<f32>,
+    pub burden_resistor: Option<f32>,
+    /// Channel sampling the mains voltage, so CT readings tell import from export
+    pub voltage_channel: Option<u8>,
+    /// Mains volts per volt at the ADC input of a voltage channel, to measure phase voltages
+    pub voltage_ratio: Option<f32>,
+    /// How to sign each channel's readings (default Measured)
+    pub ct_directions: Option<HashMap<u8, CtDirection>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommsConfig {
+    /// Transport to use when several are configured (default: the first of lora, mqtt, serial, mock)
+    pub transport: Option<String>,
+    pub lora: Option<LoRaConfig>,
+    pub mqtt: Option<MqttConfig>,
+    /// Protobuf frames over a UART, e.g. to a gateway board or a wired neighbour
+    pub serial: Option<SerialCommsConfig>,
+    /// Log frames instead of sending them, for bench setups without a radio or broker
+    pub mock: Option<bool>,
+    /// Append every frame sent and received to this file (NDJSON), for `--replay`
+    pub capture: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerialCommsConfig {
+    /// UART device, e.g. "/dev/ttyUSB0"
+    pub port: String,
+    pub baud: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub tls: Option<MqttTlsConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MqttTlsConfig {
+    /// Hex SHA-256 fingerprints of the orchestrator/broker certificate; several allow rollover
+    pub orchestrator_pins: Option<Vec<String>>,
+    /// CA bundle (PEM path) used instead of pins
+    pub ca_cert: Option<String>,
+    /// Pre-provisioned client certificate and key (PEM paths); superseded by one issued on joining
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoRaConfig {
+    pub frequency: u64,
+    pub bandwidth: u64,
+    pub tx_power: i32,
+    pub spreading_factor: u8,
+    /// Gain of the antenna, assumed the same at every node (default 0 dBi)
+    pub antenna_gain_dbi: Option<f32>,
+    /// Links to neighbours with less fade margin than this are warned about (default 10 dB)
+    pub min_fade_margin_db: Option<f32>,
+}
+
+impl LoRaConfig {
+    /// Radio settings for the module at `module` (the default SPI device if unset).
+    pub fn hal_config(&self, module: Option<&LoRaHardwareConfig>) -> LoRaHalConfig {
+        let defaults = LoRaHalConfig::default();
+        LoRaHalConfig {
+            spi_bus: module.and_then(|m| m.spi_bus).unwrap_or(defaults.spi_bus),
+            spi_cs: module.and_then(|m| m.spi_cs).unwrap_or(defaults.spi_cs),
+            frequency: self.frequency,
+            bandwidth: self.bandwidth as u32,
+            spreading_factor: self.spreading_factor,
+            tx_power: self.tx_power as i8,
+        }
+    }
+}
+
+/// Why config.yaml could not be loaded.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("reading {path}: {source}")]
+    Read { path: String, source: std::io::Error },
+    #[error("parsing {path}: {source}")]
+    Parse { path: String, source: serde_yaml::Error },
+}
+
+pub fn load_config(path: &str) -> Result<Config, ConfigError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|source| ConfigError::Read { path: path.to_string(), source })?;
+    let config: Config = serde_yaml::from_str(&contents)
+        .map_err(|source| ConfigError::Parse { path: path.to_string(), source })?;
+    Ok(config)
+}
@@ -0,0 +1,495 @@
+//! Wire-format conformance: every mesh message, encoded with known field values, must
+//! match the golden byte traces under proto/conformance, and traces from the previous
+//! release must still decode to what that release meant by them.
+
+use prost::Message;
+use std::collections::BTreeMap;
+use crate::comms::streetgrid::*;
+use crate::comms::streetgrid::neighborhood_message::Payload;
+
+const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../proto/conformance");
+const PROTO: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../proto/neighborhood.proto"));
+
+/// Name of the payload's field in NeighborhoodMessage. Exhaustive, so a new message
+/// doesn't build until it has a place here.
+fn field_name(payload: &Payload) -> &'static str {
+    match payload {
+        Payload::Heartbeat(_) => "heartbeat",
+        Payload::LoadShed(_) => "load_shed",
+        Payload::FeatureReport(_) => "feature_report",
+        Payload::VoltageAlert(_) => "voltage_alert",
+        Payload::EnterIsland(_) => "enter_island",
+        Payload::EnterBlackStart(_) => "enter_black_start",
+        Payload::ActivateRelayByIndex(_) => "activate_relay_by_index",
+        Payload::ActivateRelayByPriority(_) => "activate_relay_by_priority",
+        Payload::AuditLogRequest(_) => "audit_log_request",
+        Payload::AuditLogUpload(_) => "audit_log_upload",
+        Payload::SnapshotRequest(_) => "snapshot_request",
+        Payload::SnapshotData(_) => "snapshot_data",
+        Payload::SnapshotRestore(_) => "snapshot_restore",
+        Payload::AnomalyAlert(_) => "anomaly_alert",
+        Payload::LoadProfileReport(_) => "load_profile_report",
+        Payload::KeyRotation(_) => "key_rotation",
+        Payload::KeyRotationAck(_) => "key_rotation_ack",
+        Payload::JoinRequest(_) => "join_request",
+        Payload::JoinAccept(_) => "join_accept",
+        Payload::JoinReject(_) => "join_reject",
+        Payload::Alarm(_) => "alarm",
+        Payload::FirmwareUpdate(_) => "firmware_update",
+        Payload::FirmwareStatus(_) => "firmware_status",
+        Payload::SessionGrant(_) => "session_grant",
+        Payload::SessionRevoke(_) => "session_revoke",
+        Payload::TamperAlert(_) => "tamper_alert",
+        Payload::ReplayDesync(_) => "replay_desync",
+        Payload::CounterReset(_) => "counter_reset",
+        Payload::CertRotation(_) => "cert_rotation",
+        Payload::SecurityReport(_) => "security_report",
+        Payload::FactoryReset(_) => "factory_reset",
+        Payload::DisconnectGrid(_) => "disconnect_grid",
+        Payload::NeighborReport(_) => "neighbor_report",
+        Payload::VoltageObservation(_) => "voltage_observation",
+        Payload::Coordination(_) => "coordination",
+        Payload::PowerOffer(_) => "power_offer",
+        Payload::PowerRequest(_) => "power_request",
+        Payload::PowerGrant(_) => "power_grant",
+        Payload::EnergyEntry(_) => "energy_entry",
+        Payload::EnergyLedgerRequest(_) => "energy_ledger_request",
+        Payload::EnergyLedgerUpload(_) => "energy_ledger_upload",
+        Payload::RebalanceDirective(_) => "rebalance_directive",
+        Payload::RebalanceAck(_) => "rebalance_ack",
+        Payload::MidStatus(_) => "mid_status",
+        Payload::MidCommand(_) => "mid_command",
+        Payload::LivenessProbe(_) => "liveness_probe",
+        Payload::LivenessPing(_) => "liveness_ping",
+        Payload::LivenessReport(_) => "liveness_report",
+        Payload::RepeaterReport(_) => "repeater_report",
+        Payload::OrchestratorTakeover(_) => "orchestrator_takeover",
+        Payload::RegistrationAck(_) => "registration_ack",
+        Payload::WhoIsThere(_) => "who_is_there",
+        Payload::BlackStartAck(_) => "black_start_ack",
+        Payload::SourceCapacity(_) => "source_capacity",
+        Payload::DemandResponse(_) => "demand_response",
+        Payload::DemandResponseAck(_) => "demand_response_ack",
+        Payload::VoltageSag(_) => "voltage_sag",
+        Payload::FailedRelay(_) => "failed_relay",
+        Payload::OutageReport(_) => "outage_report",
+        Payload::FaultReset(_) => "fault_reset",
+        Payload::CommandResult(_) => "command_result",
+    }
+}
+
+/// Payload fields of NeighborhoodMessage as the .proto declares them.
+fn declared_payloads() -> Vec<String> {
+    let start = PROTO.find("message NeighborhoodMessage").unwrap();
+    let oneof = &PROTO[start..];
+    let oneof = &oneof[oneof.find("oneof payload {").unwrap()..oneof.find('}').unwrap()];
+    oneof.lines()
+        .filter_map(|line| line.trim().strip_suffix(';'))
+        .filter_map(|field| field.split_whitespace().nth(1).map(str::to_string))
+        .collect()
+}
+
+const TS: i64 = 1_700_000_000;
+
+fn bytes(tag: u8, len: usize) -> Vec<u8> {
+    (0..len as u8).map(|i| tag.wrapping_add(i)).collect()
+}
+
+fn relay_info() -> RelayInfo {
+    RelayInfo {
+        index: 2,
+        id: "r_crit".to_string(),
+        name: "Critical Panel".to_string(),
+        relay_type: 1,
+        priority: 0,
+        amperage: 15.5,
+        is_closed: true,
+        community_criticality: 3,
+        phase: "B".to_string(),
+    }
+}
+
+fn snapshot_data() -> SnapshotData {
+    SnapshotData {
+        node_id: "node_01".to_string(),
+        timestamp: TS,
+        format_version: 1,
+        payload: br#"{"relays":[]}"#.to_vec(),
+    }
+}
+
+fn energy_entry() -> EnergyEntry {
+    EnergyEntry {
+        node_id: "node_01".to_string(),
+        seq: 7,
+        period_start: TS - 900,
+        period_end: TS,
+        exported_wh: 125.5,
+        imported_wh: 0.25,
+        device_public_key: bytes(0x40, 64),
+        signature: bytes(0x80, 64),
+    }
+}
+
+/// One message of every kind, every field set to something other than its default.
+fn samples() -> Vec<Payload> {
+    let node = || "node_01".to_string();
+    let target = || "node_02".to_string();
+    vec![
+        Payload::Heartbeat(Heartbeat {
+            node_id: node(),
+            timestamp: TS,
+            battery_level: 0.75,
+            link: Some(LinkMetrics { last_rssi: -87, last_snr: 7.25, tx_packets: 120, rx_packets: 118, retransmissions: 3, queue_depth: 1, deliveries: vec![] }),
+            system: Some(SystemMetrics {
+                uptime_secs: 86_400,
+                free_memory_kb: 512_000,
+                cpu_load: 0.5,
+                fs_free_kb: 8_000_000,
+                sd_write_errors: 2,
+                storage_used_kb: 4096,
+            }),
+            key_epoch: 3,
+            audit_seq: 42,
+            audit_head: bytes(0xa0, 32),
+            firmware_version: "0.1.0".to_string(),
+            orchestrator_id: "orchestrator".to_string(),
+            phases: vec![PhaseMeasurement { phase: "B".to_string(), volts: 229.5, amps: 41.0 }],
+            power: vec![ChannelPower { channel: 2, real_watts: 1150.0, reactive_var: 1992.0, apparent_va: 2300.0, power_factor: 0.5 }],
+            voltage_thd: 0.0625,
+            thermal: Some(ThermalStatus { board_temp_c: 71.5, derated_amps: 45.0 }),
+            reserve_tier: "shed_medium".to_string(),
+            circuits: vec![CircuitMeasurement { circuit: "kitchen".to_string(), watts: 1224.0, imported_wh: 8532.5, exported_wh: 0.0 }],
+            shutting_down: true,
+        }),
+        Payload::LoadShed(LoadShed { target_node_id: target(), shed_load: true }),
+        Payload::FeatureReport(FeatureReport {
+            node_id: node(),
+            relays: vec![relay_info(), RelayInfo { index: 0, id: "r_grid".to_string(), relay_type: 2, ..relay_info() }],
+            mesh_type: "GovernmentSanctioned".to_string(),
+            hardware_id: "10000000a1b2c3d4".to_string(),
+            zones: vec!["phase_B".to_string(), "block_3".to_string()],
+            phase: "A".to_string(),
+        }),
+        Payload::VoltageAlert(VoltageAlert { node_id: node(), voltage: 96.5, timestamp: TS }),
+        Payload::EnterIsland(EnterIsland { target_node_id: target() }),
+        Payload::EnterBlackStart(EnterBlackStart { target_node_id: target(), step_id: "bs_1".to_string() }),
+        Payload::ActivateRelayByIndex(ActivateRelayByIndex { target_node_id: target(), relay_index: 4, step_id: "bs_2".to_string() }),
+        Payload::ActivateRelayByPriority(ActivateRelayByPriority { target_node_id: target(), priority: 2, step_id: "bs_3".to_string() }),
+        Payload::AuditLogRequest(AuditLogRequest { target_node_id: target(), since_seq: 100, max_entries: 50 }),
+        Payload::AuditLogUpload(AuditLogUpload {
+            node_id: node(),
+            entries: vec![AuditLogEntry { seq: 100, timestamp: TS, json: r#"{"seq":100}"#.to_string() }],
+            more: true,
+        }),
+        Payload::SnapshotRequest(SnapshotRequest { target_node_id: target() }),
+        Payload::SnapshotData(snapshot_data()),
+        Payload::SnapshotRestore(SnapshotRestore { target_node_id: target(), snapshot: Some(snapshot_data()) }),
+        Payload::AnomalyAlert(AnomalyAlert {
+            node_id: node(),
+            channel: 2,
+            relay_id: "r_hvac".to_string(),
+            current_amps: 31.5,
+            baseline_amps: 12.25,
+            magnitude: -4.5,
+            timestamp: TS,
+        }),
+        Payload::LoadProfileReport(LoadProfileReport {
+            node_id: node(),
+            profiles: vec![RelayLoadProfile { relay_id: "r_hvac".to_string(), hourly_watts: vec![100.0, 250.5, 0.0], samples: 720 }],
+            timestamp: TS,
+        }),
+        Payload::KeyRotation(KeyRotation {
+            target_node_id: target(),
+            new_epoch: 4,
+            wrapped_key: bytes(0x10, 48),
+            nonce: bytes(0x60, 12),
+            activate_at: TS + 3600,
+            grace_secs: 600,
+        }),
+        Payload::KeyRotationAck(KeyRotationAck { node_id: node(), new_epoch: 4, accepted: true, active_epoch: 3, error: "none".to_string() }),
+        Payload::JoinRequest(JoinRequest {
+            node_id: node(),
+            timestamp: TS,
+            token_proof: bytes(0x20, 32),
+            firmware_version: "0.1.0".to_string(),
+            nonce: bytes(0x70, 16),
+            device_public_key: bytes(0x40, 64),
+            device_signature: bytes(0x90, 64),
+        }),
+        Payload::JoinAccept(JoinAccept {
+            target_node_id: node(),
+            assigned_node_id: "node_17".to_string(),
+            key_epoch: 3,
+            wrapped_key: bytes(0x10, 48),
+            nonce: bytes(0x60, 12),
+            client_cert: b"-----BEGIN CERTIFICATE-----".to_vec(),
+            wrapped_client_key: bytes(0x30, 40),
+            client_key_nonce: bytes(0x68, 12),
+        }),
+        Payload::JoinReject(JoinReject { target_node_id: node(), reason: "unknown token".to_string() }),
+        Payload::Alarm(Alarm { node_id: node(), code: "command_flood".to_string(), message: "locked out".to_string(), timestamp: TS, critical: true }),
+        Payload::FirmwareUpdate(FirmwareUpdate { target_node_id: target(), url: "http://orchestrator.local/fw.img".to_string() }),
+        Payload::FirmwareStatus(FirmwareStatus { node_id: node(), version: "0.2.0".to_string(), installed: true, reason: "ok".to_string() }),
+        Payload::SessionGrant(SessionGrant {
+            session_id: "s_1".to_string(),
+            target_node_id: target(),
+            scopes: vec!["activate_relay_by_index".to_string(), "load_shed".to_string()],
+            expires_at: TS + 900,
+        }),
+        Payload::SessionRevoke(SessionRevoke { session_id: "s_1".to_string(), target_node_id: target() }),
+        Payload::TamperAlert(TamperAlert { node_id: node(), opened: true, timestamp: TS, safe_state_applied: true }),
+        Payload::ReplayDesync(ReplayDesync {
+            node_id: node(),
+            sender_id: "orchestrator".to_string(),
+            last_counter: 9000,
+            challenge: bytes(0x50, 16),
+            timestamp: TS,
+        }),
+        Payload::CounterReset(CounterReset {
+            target_node_id: target(),
+            sender_id: "orchestrator".to_string(),
+            counter: 9001,
+            challenge: bytes(0x50, 16),
+        }),
+        Payload::CertRotation(CertRotation {
+            target_node_id: target(),
+            client_cert: b"-----BEGIN CERTIFICATE-----".to_vec(),
+            wrapped_client_key: bytes(0x30, 40),
+            nonce: bytes(0x60, 12),
+            orchestrator_pins: vec!["ab".repeat(32)],
+        }),
+        Payload::SecurityReport(SecurityReport {
+            node_id: node(),
+            timestamp: TS,
+            interval_secs: 300,
+            auth_failures: 1,
+            replay_attempts: 2,
+            malformed_packets: 3,
+            rate_limit_trips: 4,
+            lockout_drops: 5,
+            session_rejections: 6,
+        }),
+        Payload::FactoryReset(FactoryReset { target_node_id: target() }),
+        Payload::DisconnectGrid(DisconnectGrid { target_node_id: target() }),
+        Payload::NeighborReport(NeighborReport {
+            node_id: node(),
+            timestamp: TS,
+            neighbors: vec![Neighbor { node_id: target(), rssi: -92, snr: -3.5, last_heard: TS - 30, packets: 17, frames_heard: 15, frames_expected: 18 }],
+        }),
+        Payload::VoltageObservation(VoltageObservation { node_id: node(), voltage: 101.5, undervoltage: true, timestamp: TS }),
+        Payload::Coordination(Coordination { node_id: node(), kind: 2, term: 5, priority: 1, timestamp: TS }),
+        Payload::PowerOffer(PowerOffer { node_id: node(), available_watts: 2400.0, timestamp: TS }),
+        Payload::PowerRequest(PowerRequest {
+            node_id: node(),
+            requested_watts: 6000.0,
+            critical_watts: 1800.0,
+            timestamp: TS,
+            community_watts: vec![0.0, 120.0, 360.0],
+        }),
+        Payload::PowerGrant(PowerGrant {
+            target_node_id: target(),
+            coordinator_id: node(),
+            term: 5,
+            budget_watts: 3000.0,
+            timestamp: TS,
+            request_timestamp: TS - 10,
+        }),
+        Payload::EnergyEntry(energy_entry()),
+        Payload::EnergyLedgerRequest(EnergyLedgerRequest { target_node_id: target(), since: TS - 86_400 }),
+        Payload::EnergyLedgerUpload(EnergyLedgerUpload { node_id: node(), entries: vec![energy_entry()], more: true }),
+        Payload::RebalanceDirective(RebalanceDirective {
+            target_node_id: target(),
+            directive_id: "d_1".to_string(),
+            open_relays: vec!["r_hvac".to_string()],
+            close_relays: vec!["r_aux".to_string()],
+            rollback: true,
+        }),
+        Payload::RebalanceAck(RebalanceAck {
+            node_id: node(),
+            directive_id: "d_1".to_string(),
+            ok: true,
+            error: "none".to_string(),
+            rollback: true,
+        }),
+        Payload::MidStatus(MidStatus { mid_id: "mid_t1".to_string(), isolated: true, reconnect_permitted: true, timestamp: TS, counter: 7, signature: vec![0x5A; 64] }),
+        Payload::MidCommand(MidCommand { target_node_id: "mid_t1".to_string(), isolate: true, permit_reconnect: true }),
+        Payload::LivenessProbe(LivenessProbe { target_node_id: target(), silent_node_id: "node_03".to_string(), probe_id: "p_1".to_string() }),
+        Payload::LivenessPing(LivenessPing { node_id: node(), silent_node_id: "node_03".to_string(), probe_id: "p_1".to_string() }),
+        Payload::LivenessReport(LivenessReport {
+            node_id: node(),
+            silent_node_id: "node_03".to_string(),
+            probe_id: "p_1".to_string(),
+            last_heard: TS - 120,
+            rssi: -101,
+        }),
+        Payload::RepeaterReport(RepeaterReport { node_id: node(), forwarded: 1000, duplicates: 40, rejected: 2 }),
+        Payload::OrchestratorTakeover(OrchestratorTakeover { orchestrator_id: "orchestrator_standby".to_string(), timestamp: TS }),
+        Payload::RegistrationAck(RegistrationAck { target_node_id: target() }),
+        Payload::WhoIsThere(WhoIsThere { timestamp: TS }),
+        Payload::BlackStartAck(BlackStartAck { node_id: node(), step_id: "bs_2".to_string(), ok: true, error: "none".to_string() }),
+        Payload::SourceCapacity(SourceCapacity {
+            node_id: node(),
+            export_watts: 1500.0,
+            soc: 0.8,
+            battery_capacity_wh: 13_500.0,
+            stored_wh: 8100.0,
+            solar_forecast_wh: 22_000.0,
+            timestamp: TS,
+        }),
+        Payload::DemandResponse(DemandResponse {
+            target_node_id: target(),
+            event_id: "evt_1".to_string(),
+            shed_priority: 2,
+            start: TS + 600,
+            end: TS + 2400,
+            cancel: true,
+            max_watts: 0.0,
+            strategy: "round_robin".to_string(),
+        }),
+        Payload::DemandResponseAck(DemandResponseAck { node_id: node(), event_id: "evt_1".to_string(), opted_out: true, shed_watts: 2400.0 }),
+        Payload::VoltageSag(VoltageSag { node_id: node(), start: TS, duration_secs: 2.5, min_voltage: 88.0, escalated: true }),
+        Payload::FailedRelay(FailedRelay {
+            node_id: node(),
+            relay_id: "r_hvac".to_string(),
+            failure: "welded".to_string(),
+            evidence: "feedback".to_string(),
+            timestamp: TS,
+        }),
+        Payload::OutageReport(OutageReport {
+            node_id: node(),
+            month: "2026-03".to_string(),
+            grid_losses: Some(Interruptions { count: 2, secs: 5400 }),
+            islands: Some(Interruptions { count: 1, secs: 4800 }),
+            relays: vec![RelayOutage { relay_id: "r_hvac".to_string(), interruptions: Some(Interruptions { count: 3, secs: 3600 }) }],
+            priorities: vec![PriorityOutage { priority: "Medium".to_string(), interruptions: Some(Interruptions { count: 3, secs: 3600 }) }],
+            timestamp: TS,
+        }),
+        Payload::FaultReset(FaultReset { target_node_id: target() }),
+        Payload::CommandResult(CommandResult {
+            node_id: node(),
+            sender_id: "orchestrator".to_string(),
+            counter: 42,
+            command: "activate_relay_by_index".to_string(),
+            ok: false,
+            error_code: "no_relay_at_index".to_string(),
+            error: "no relay at index 5".to_string(),
+            relays: vec![RelayState { id: "r_crit".to_string(), is_closed: true }],
+            timestamp: TS,
+        }),
+    ]
+}
+
+/// A signed, co-signed, zone-addressed command: every envelope field set.
+fn envelope() -> NeighborhoodMessage {
+    NeighborhoodMessage {
+        payload: Some(Payload::DisconnectGrid(DisconnectGrid { target_node_id: String::new() })),
+        auth: Some(MessageAuth { key_epoch: 3, mac: bytes(0xc0, 32) }),
+        session_id: "s_1".to_string(),
+        sender_id: "orchestrator".to_string(),
+        counter: 9002,
+        approvals: vec![Approval { public_key: bytes(0x00, 32), signature: bytes(0xe0, 64) }],
+        target_zone: "phase_A".to_string(),
+        origin_id: "node_01".to_string(),
+        origin_seq: 4711,
+        relay_hops: 1,
+    }
+}
+
+/// Every trace the current release must produce, keyed as in the golden file.
+fn current() -> BTreeMap<String, NeighborhoodMessage> {
+    let mut traces: BTreeMap<String, NeighborhoodMessage> = samples().into_iter()
+        .map(|payload| (field_name(&payload).to_string(), NeighborhoodMessage { payload: Some(payload), ..Default::default() }))
+        .collect();
+    traces.insert("envelope".to_string(), envelope());
+    traces
+}
+
+/// What the messages in the v0.1.0 traces were encoded from (see v0.1.0.txtpb).
+fn v0_1_0() -> BTreeMap<String, NeighborhoodMessage> {
+    let relay = |index: u32, id: &str, relay_type: i32, amperage: f32, is_closed: bool| RelayInfo {
+        index,
+        id: id.to_string(),
+        name: id.to_string(),
+        relay_type,
+        priority: 0,
+        amperage,
+        is_closed,
+        ..Default::default()
+    };
+    let target = || "node_02".to_string();
+    [
+        Payload::Heartbeat(Heartbeat { node_id: "node_01".to_string(), timestamp: TS, battery_level: 0.5, ..Default::default() }),
+        Payload::LoadShed(LoadShed { target_node_id: target(), shed_load: true }),
+        Payload::FeatureReport(FeatureReport {
+            node_id: "node_01".to_string(),
+            relays: vec![relay(0, "r_grid", 2, 100.0, true), relay(1, "r_crit", 1, 15.0, false)],
+            mesh_type: "AdHoc".to_string(),
+            ..Default::default()
+        }),
+        Payload::VoltageAlert(VoltageAlert { node_id: "node_01".to_string(), voltage: 98.5, timestamp: TS }),
+        Payload::EnterIsland(EnterIsland { target_node_id: target() }),
+        Payload::EnterBlackStart(EnterBlackStart { target_node_id: target(), ..Default::default() }),
+        Payload::ActivateRelayByIndex(ActivateRelayByIndex { target_node_id: target(), relay_index: 3, ..Default::default() }),
+        Payload::ActivateRelayByPriority(ActivateRelayByPriority { target_node_id: target(), priority: 1, ..Default::default() }),
+    ]
+    .into_iter()
+    .map(|payload| (field_name(&payload).to_string(), NeighborhoodMessage { payload: Some(payload), ..Default::default() }))
+    .collect()
+}
+
+/// Traces in a golden file: `<name> <hex>` lines, `#` comments.
+fn read_golden(file: &str) -> BTreeMap<String, Vec<u8>> {
+    let path = format!("{}/{}", GOLDEN_DIR, file);
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("reading {}: {}", path, e));
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (name, trace) = line.split_once(' ').unwrap_or_else(|| panic!("{}: bad line {:?}", path, line));
+            (name.to_string(), hex::decode(trace.trim()).unwrap_or_else(|e| panic!("{}: {}: {}", path, name, e)))
+        })
+        .collect()
+}
+
+#[test]
+fn test_every_message_type_has_a_sample() {
+    let sampled: Vec<&str> = samples().iter().map(field_name).collect();
+    assert_eq!(sampled, declared_payloads());
+}
+
+#[test]
+fn test_encoding_matches_golden_traces() {
+    let traces = current();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        let mut text = String::from(
+            "# NeighborhoodMessage byte traces, one per payload plus a full envelope, encoded from the\n\
+             # samples in core/src/conformance.rs. A diff here is a wire-format change: regenerate\n\
+             # with UPDATE_GOLDEN=1 cargo test conformance only when that is intended.\n",
+        );
+        for (name, msg) in &traces {
+            text.push_str(&format!("{} {}\n", name, hex::encode(msg.encode_to_vec())));
+        }
+        std::fs::write(format!("{}/neighborhood.golden", GOLDEN_DIR), text).unwrap();
+    }
+
+    let golden = read_golden("neighborhood.golden");
+    assert_eq!(golden.keys().collect::<Vec<_>>(), traces.keys().collect::<Vec<_>>());
+    for (name, msg) in &traces {
+        assert_eq!(hex::encode(msg.encode_to_vec()), hex::encode(&golden[name]), "{} no longer encodes as its golden trace", name);
+        assert_eq!(&NeighborhoodMessage::decode(golden[name].as_slice()).unwrap(), msg, "{} golden trace decodes differently", name);
+    }
+}
+
+#[test]
+fn test_previous_release_traces_still_decode() {
+    let golden = read_golden("v0.1.0.golden");
+    let expected = v0_1_0();
+    assert_eq!(golden.keys().collect::<Vec<_>>(), expected.keys().collect::<Vec<_>>());
+    for (name, msg) in &expected {
+        let decoded = NeighborhoodMessage::decode(golden[name].as_slice()).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        assert_eq!(&decoded, msg, "v0.1.0 {} decodes differently", name);
+        // And we still say it the way that release would read it
+        assert_eq!(decoded.encode_to_vec(), golden[name], "v0.1.0 {} re-encodes differently", name);
+    }
+}
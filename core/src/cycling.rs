@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// How long a cycled load is let run, and held off, in turn.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DutyCycle {
+    pub on_minutes: u32,
+    pub off_minutes: u32,
+}
+
+/// Loads that are cycled through a demand-response event instead of being held off for
+/// all of it, so a house isn't left without heating or cooling for hours.
+#[derive(Debug, Default)]
+pub struct DutyCycler {
+    cycles: HashMap<String, DutyCycle>,
+    /// Relays being cycled, whether they are in their on period, and since when
+    active: BTreeMap<String, (bool, i64)>,
+}
+
+impl DutyCycler {
+    pub fn new(cycles: HashMap<String, DutyCycle>) -> Self {
+        Self { cycles, active: BTreeMap::new() }
+    }
+
+    /// Whether the relay is cycled rather than shed outright.
+    pub fn cycles(&self, relay_id: &str) -> bool {
+        self.cycles.contains_key(relay_id)
+    }
+
+    /// Start cycling a relay that was just opened, with its off period.
+    pub fn start(&mut self, relay_id: &str, now: i64) {
+        self.active.insert(relay_id.to_string(), (false, now));
+    }
+
+    /// Stop cycling a relay; false if it wasn't.
+    pub fn stop(&mut self, relay_id: &str) -> bool {
+        self.active.remove(relay_id).is_some()
+    }
+
+    /// Relays whose period is over at `now`, and whether each is to close or open.
+    pub fn due(&mut self, now: i64) -> Vec<(String, bool)> {
+        let mut switches = Vec::new();
+        for (relay_id, (on, since)) in self.active.iter_mut() {
+            let Some(cycle) = self.cycles.get(relay_id) else { continue };
+            let minutes = if *on { cycle.on_minutes } else { cycle.off_minutes };
+            if now - *since >= minutes as i64 * 60 {
+                *on = !*on;
+                *since = now;
+                switches.push((relay_id.clone(), *on));
+            }
+        }
+        switches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hvac_runs_a_quarter_hour_in_every_three_quarters() {
+        let cycle = DutyCycle { on_minutes: 15, off_minutes: 30 };
+        let mut cycler = DutyCycler::new(HashMap::from([("r_hvac".to_string(), cycle)]));
+        assert!(cycler.cycles("r_hvac") && !cycler.cycles("r_tv"));
+
+        cycler.start("r_hvac", 0);
+        assert!(cycler.due(29 * 60).is_empty());
+        assert_eq!(cycler.due(30 * 60), vec![("r_hvac".to_string(), true)]);
+        assert!(cycler.due(44 * 60).is_empty());
+        assert_eq!(cycler.due(45 * 60), vec![("r_hvac".to_string(), false)]);
+
+        assert!(cycler.stop("r_hvac"));
+        assert!(!cycler.stop("r_hvac"));
+        assert!(cycler.due(90 * 60).is_empty());
+    }
+}
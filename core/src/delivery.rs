@@ -0,0 +1,191 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Messages still unanswered after this long count as lost.
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Outcomes kept per destination; older ones fall out of the statistics.
+pub const DELIVERY_WINDOW: usize = 32;
+
+/// Most destinations tracked; keeps the statistics inside a heartbeat.
+pub const MAX_DESTINATIONS: usize = 8;
+
+/// Rolling delivery statistics to one destination, over its last `DELIVERY_WINDOW`
+/// messages that were answered or timed out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DeliveryStats {
+    pub destination: String,
+    pub sent: u32,
+    pub delivered: u32,
+    /// Sent and still waiting for an answer
+    pub pending: u32,
+    pub rtt_avg_ms: Option<u32>,
+    pub rtt_min_ms: Option<u32>,
+    pub rtt_max_ms: Option<u32>,
+    pub rtt_last_ms: Option<u32>,
+}
+
+impl DeliveryStats {
+    pub fn delivery_ratio(&self) -> Option<f32> {
+        (self.sent > 0).then(|| self.delivered as f32 / self.sent as f32)
+    }
+}
+
+/// Last outcomes to one destination: the round-trip time, or None if lost.
+#[derive(Debug, Default)]
+struct Outcomes {
+    window: VecDeque<Option<Duration>>,
+    last_rtt: Option<Duration>,
+    last_used: Option<Instant>,
+}
+
+/// Round-trip times and delivery of the messages we expect an answer to, per destination.
+/// A message is known by its destination and a key naming the exchange (e.g. the
+/// registration, or a probe ID), so an answer settles the message it is for.
+#[derive(Debug, Default)]
+pub struct DeliveryTracker {
+    pending: BTreeMap<(String, String), Instant>,
+    outcomes: BTreeMap<String, Outcomes>,
+    retransmissions: u32,
+}
+
+impl DeliveryTracker {
+    /// Note a message to `destination` awaiting an answer. Sending it again before then
+    /// counts the first attempt as lost, and as a retransmission.
+    pub fn sent(&mut self, destination: &str, key: &str, at: Instant) {
+        self.expire(at);
+        if self.pending.insert((destination.to_string(), key.to_string()), at).is_some() {
+            self.retransmissions += 1;
+            self.record(destination, None, at);
+        }
+        self.record_destination(destination, at);
+    }
+
+    /// An answer arrived; returns the round-trip time if we were waiting for it.
+    pub fn answered(&mut self, destination: &str, key: &str, at: Instant) -> Option<Duration> {
+        let sent = self.pending.remove(&(destination.to_string(), key.to_string()))?;
+        let rtt = at.saturating_duration_since(sent);
+        self.record(destination, Some(rtt), at);
+        Some(rtt)
+    }
+
+    /// Count messages unanswered for `DELIVERY_TIMEOUT` as lost.
+    pub fn expire(&mut self, at: Instant) {
+        let lost: Vec<_> = self.pending.iter()
+            .filter(|(_, &sent)| at.saturating_duration_since(sent) >= DELIVERY_TIMEOUT)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in lost {
+            self.pending.remove(&key);
+            self.record(&key.0, None, at);
+        }
+    }
+
+    /// Messages sent again since boot because the first attempt went unanswered.
+    pub fn retransmissions(&self) -> u32 {
+        self.retransmissions
+    }
+
+    /// Make room for `destination`; the longest-unused destination goes.
+    fn record_destination(&mut self, destination: &str, at: Instant) {
+        if !self.outcomes.contains_key(destination) && self.outcomes.len() >= MAX_DESTINATIONS {
+            if let Some(oldest) = self.outcomes.iter().min_by_key(|(_, o)| o.last_used).map(|(d, _)| d.clone()) {
+                self.outcomes.remove(&oldest);
+                self.pending.retain(|(d, _), _| *d != oldest);
+            }
+        }
+        self.outcomes.entry(destination.to_string()).or_default().last_used = Some(at);
+    }
+
+    fn record(&mut self, destination: &str, rtt: Option<Duration>, at: Instant) {
+        self.record_destination(destination, at);
+        let outcomes = self.outcomes.get_mut(destination).unwrap();
+        if outcomes.window.len() >= DELIVERY_WINDOW {
+            outcomes.window.pop_front();
+        }
+        outcomes.window.push_back(rtt);
+        if rtt.is_some() {
+            outcomes.last_rtt = rtt;
+        }
+    }
+
+    /// Statistics per destination, in name order.
+    pub fn stats(&self) -> Vec<DeliveryStats> {
+        let ms = |d: Duration| d.as_millis().min(u32::MAX as u128) as u32;
+        self.outcomes.iter()
+            .map(|(destination, outcomes)| {
+                let rtts: Vec<u32> = outcomes.window.iter().flatten().map(|&d| ms(d)).collect();
+                DeliveryStats {
+                    destination: destination.clone(),
+                    sent: outcomes.window.len() as u32,
+                    delivered: rtts.len() as u32,
+                    pending: self.pending.keys().filter(|(d, _)| d == destination).count() as u32,
+                    rtt_avg_ms: (!rtts.is_empty()).then(|| (rtts.iter().map(|&r| r as u64).sum::<u64>() / rtts.len() as u64) as u32),
+                    rtt_min_ms: rtts.iter().min().copied(),
+                    rtt_max_ms: rtts.iter().max().copied(),
+                    rtt_last_ms: outcomes.last_rtt.map(ms),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trips_and_losses_per_destination() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut tracker = DeliveryTracker::default();
+
+        tracker.sent("orchestrator", "registration", at(0));
+        assert_eq!(tracker.answered("orchestrator", "registration", at(300)), Some(Duration::from_millis(300)));
+        // Answered twice, or never asked
+        assert_eq!(tracker.answered("orchestrator", "registration", at(400)), None);
+        assert_eq!(tracker.answered("node_02", "probe-1", at(400)), None);
+
+        // Resent before an answer: the first attempt is lost
+        tracker.sent("orchestrator", "registration", at(1000));
+        tracker.sent("orchestrator", "registration", at(2000));
+        tracker.answered("orchestrator", "registration", at(2100));
+        assert_eq!(tracker.retransmissions(), 1);
+
+        // Never answered
+        tracker.sent("node_02", "probe-1", at(0));
+        tracker.sent("node_02", "probe-2", at(59_000));
+        tracker.expire(at(61_000));
+
+        let stats = tracker.stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].destination.as_str(), stats[0].sent, stats[0].delivered, stats[0].pending), ("node_02", 1, 0, 1));
+        assert_eq!(stats[0].rtt_avg_ms, None);
+        let orchestrator = &stats[1];
+        assert_eq!((orchestrator.sent, orchestrator.delivered), (3, 2));
+        assert_eq!(orchestrator.rtt_avg_ms, Some(200));
+        assert_eq!((orchestrator.rtt_min_ms, orchestrator.rtt_max_ms, orchestrator.rtt_last_ms), (Some(100), Some(300), Some(100)));
+        assert!((orchestrator.delivery_ratio().unwrap() - 2.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_statistics_are_bounded() {
+        let start = Instant::now();
+        let mut tracker = DeliveryTracker::default();
+        for i in 0..DELIVERY_WINDOW as u64 + 5 {
+            let at = start + Duration::from_secs(i);
+            tracker.sent("orchestrator", "registration", at);
+            tracker.answered("orchestrator", "registration", at + Duration::from_millis(i));
+        }
+        assert_eq!(tracker.stats()[0].sent, DELIVERY_WINDOW as u32);
+        assert_eq!(tracker.stats()[0].rtt_min_ms, Some(5));
+
+        for i in 0..MAX_DESTINATIONS + 2 {
+            tracker.sent(&format!("n{}", i), "probe", start + Duration::from_secs(100 + i as u64));
+        }
+        let stats = tracker.stats();
+        assert_eq!(stats.len(), MAX_DESTINATIONS);
+        assert!(!stats.iter().any(|s| s.destination == "orchestrator"));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::shedding::{Candidate, ShedStrategy};
+use crate::types::{Priority, Relay, RelayType};
+
+/// How often accepted demand-response events are started and ended
+pub const DEMAND_RESPONSE_TICK_SECS: u64 = 30;
+
+/// Events remembered at once; beyond this the one ending soonest is dropped
+const MAX_EVENTS: usize = 8;
+
+/// A utility demand-response event the node accepted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrEvent {
+    pub event_id: String,
+    /// Load relays of this priority and below are opened
+    pub shed_priority: Priority,
+    pub start: i64,
+    pub end: i64,
+    /// Most the household may draw while the event runs, if the utility caps it
+    pub max_watts: Option<f32>,
+    /// How the relays to open are picked to meet `max_watts`
+    pub strategy: ShedStrategy,
+    /// Relays opened for the event and their priority, closed again when it ends;
+    /// None until it starts
+    shed: Option<Vec<(String, Priority)>>,
+}
+
+/// Relays an event at `shed_priority` would open now. Loads the neighbourhood relies
+/// on stay closed whatever their priority, as they do for the island budget.
+pub fn sheddable(relays: &[Relay], shed_priority: Priority) -> impl Iterator<Item = &Relay> {
+    relays.iter().filter(move |r| is_sheddable(r, shed_priority))
+}
+
+fn is_sheddable(relay: &Relay, shed_priority: Priority) -> bool {
+    relay.relay_type == RelayType::Load
+        && relay.priority != Priority::Critical
+        && relay.priority >= shed_priority
+        && relay.community_criticality == 0
+        && relay.is_closed
+}
+
+/// Demand-response events accepted from the orchestrator, and whether the household
+/// takes part at all.
+#[derive(Debug, Default)]
+pub struct DrSchedule {
+    /// Decline every event; the orchestrator is told so in the acknowledgment
+    pub opt_out: bool,
+    /// Strategy for events that don't name one
+    pub strategy: ShedStrategy,
+    events: Vec<DrEvent>,
+}
+
+impl DrSchedule {
+    /// Schedule an event, or update one we already have (a utility may move its end).
+    /// Without a strategy of its own it sheds by the schedule's.
+    pub fn accept(&mut self, event_id: &str, shed_priority: Priority, start: i64, end: i64, max_watts: Option<f32>, strategy: Option<ShedStrategy>) {
+        let strategy = strategy.unwrap_or(self.strategy);
+        if let Some(event) = self.events.iter_mut().find(|e| e.event_id == event_id) {
+            event.shed_priority = shed_priority;
+            event.start = start;
+            event.end = end;
+            event.max_watts = max_watts;
+            event.strategy = strategy;
+            return;
+        }
+        if self.events.len() >= MAX_EVENTS {
+            if let Some(index) = self.events.iter().enumerate().min_by_key(|(_, e)| e.end).map(|(i, _)| i) {
+                self.events.remove(index);
+            }
+        }
+        self.events.push(DrEvent { event_id: event_id.to_string(), shed_priority, start, end, max_watts, strategy, shed: None });
+    }
+
+    /// The tightest cap on our draw among the events running at `now`.
+    pub fn max_watts(&self, now: i64) -> Option<f32> {
+        self.events.iter()
+            .filter(|e| e.start <= now && now < e.end)
+            .filter_map(|e| e.max_watts)
+            .reduce(f32::min)
+    }
+
+    /// Withdraw an event; returns the relays to close again.
+    pub fn cancel(&mut self, event_id: &str) -> Vec<String> {
+        let Some(index) = self.events.iter().position(|e| e.event_id == event_id) else {
+            return Vec::new();
+        };
+        let event = self.events.remove(index);
+        self.release(event)
+    }
+
+    /// Start events whose time has come, move rotating ones on and end those that are
+    /// over, as of `now`. `watts` is what each relay draws when closed. Returns the relays
+    /// to open and the relays to close again.
+    pub fn due(&mut self, relays: &[Relay], watts: impl Fn(&Relay) -> f32, now: i64) -> (Vec<String>, Vec<String>) {
+        let mut close = Vec::new();
+        while let Some(index) = self.events.iter().position(|e| e.end <= now) {
+            let event = self.events.remove(index);
+            close.extend(self.release(event));
+        }
+        let mut open = Vec::new();
+        for index in 0..self.events.len() {
+            let event = &self.events[index];
+            if event.start > now || (event.shed.is_some() && !event.strategy.rotates()) {
+                continue;
+            }
+            let held = event.shed.clone().unwrap_or_default();
+            let holds = |relay: &Relay| held.iter().any(|(id, _)| *id == relay.id);
+            let candidates: Vec<Candidate> = relays.iter()
+                .filter(|r| holds(r) || (is_sheddable(r, event.shed_priority) && !open.contains(&r.id)))
+                .map(|r| Candidate { relay_id: r.id.clone(), watts: watts(r) })
+                .collect();
+            // What the household would draw with the event's relays back on
+            let draw: f32 = relays.iter()
+                .filter(|r| r.relay_type == RelayType::Load && (r.is_closed || holds(r)))
+                .map(&watts)
+                .sum();
+            let selected = event.strategy.select(&candidates, event.max_watts.map(|cap| draw - cap), now);
+
+            let mut shed = Vec::new();
+            for relay in relays.iter().filter(|r| selected.contains(&r.id)) {
+                if !holds(relay) && relay.is_closed {
+                    open.push(relay.id.clone());
+                }
+                shed.push((relay.id.clone(), relay.priority));
+            }
+            // Relays the rotation has moved past go back on, unless another event wants them off
+            for (relay_id, priority) in held.into_iter().filter(|(id, _)| !selected.contains(id)) {
+                let other = self.events.iter_mut().enumerate()
+                    .find(|(i, e)| *i != index && e.shed.is_some() && priority >= e.shed_priority);
+                match other {
+                    Some((_, other)) => other.shed.get_or_insert_with(Vec::new).push((relay_id, priority)),
+                    None if relays.iter().any(|r| r.id == relay_id && !r.is_closed) => close.push(relay_id),
+                    None => {}
+                }
+            }
+            self.events[index].shed = Some(shed);
+        }
+        (open, close)
+    }
+
+    /// Keep a relay opened for another reason off while a running event covers its
+    /// priority, and close it when that event ends. False if none does.
+    pub fn take_over(&mut self, relay_id: &str, priority: Priority) -> bool {
+        match self.events.iter_mut().find(|e| e.shed.is_some() && priority >= e.shed_priority) {
+            Some(event) => {
+                event.shed.get_or_insert_with(Vec::new).push((relay_id.to_string(), priority));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Relays an ended event opened, less those another running event still wants
+    /// open; those are handed over to it.
+    fn release(&mut self, event: DrEvent) -> Vec<String> {
+        let mut close = Vec::new();
+        for (relay_id, priority) in event.shed.unwrap_or_default() {
+            match self.events.iter_mut().find(|e| e.shed.is_some() && priority >= e.shed_priority) {
+                Some(other) => other.shed.get_or_insert_with(Vec::new).push((relay_id, priority)),
+                None => close.push(relay_id),
+            }
+        }
+        close
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(id: &str, priority: Priority) -> Relay {
+        Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage: 10.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        }
+    }
+
+    #[test]
+    fn test_events_shed_while_running_and_hand_over_relays() {
+        let mut relays = vec![load("r_medical", Priority::Critical), load("r_hvac", Priority::Medium), load("r_tv", Priority::Low)];
+        relays.push(Relay { community_criticality: 2, ..load("r_shared_freezer", Priority::Low) });
+        let watts = |r: &Relay| r.amperage * 120.0;
+        let mut schedule = DrSchedule::default();
+        schedule.accept("evt_1", Priority::Medium, 100, 200, None, None);
+        schedule.accept("evt_2", Priority::Low, 150, 300, Some(3000.0), None);
+
+        assert_eq!(schedule.due(&relays, watts, 50), (vec![], vec![]));
+        assert_eq!((schedule.max_watts(100), schedule.max_watts(150)), (None, Some(3000.0)));
+        let (open, close) = schedule.due(&relays, watts, 100);
+        assert_eq!((open, close), (vec!["r_hvac".to_string(), "r_tv".to_string()], vec![]));
+        for relay in relays.iter_mut().filter(|r| r.id == "r_hvac" || r.id == "r_tv") {
+            relay.is_closed = false;
+        }
+        // The second event finds nothing left to open, but keeps the low-priority load off
+        assert_eq!(schedule.due(&relays, watts, 150), (vec![], vec![]));
+        assert_eq!(schedule.due(&relays, watts, 200), (vec![], vec!["r_hvac".to_string()]));
+        assert_eq!(schedule.cancel("evt_2"), vec!["r_tv".to_string()]);
+        assert!(schedule.cancel("evt_2").is_empty());
+
+        // A load deferred for another reason is kept off by a running event, then released
+        assert!(!schedule.take_over("r_washer", Priority::Low));
+        schedule.accept("evt_3", Priority::Low, 400, 500, None, None);
+        schedule.due(&relays, watts, 400);
+        assert!(!schedule.take_over("r_fridge", Priority::High));
+        assert!(schedule.take_over("r_washer", Priority::Low));
+        assert_eq!(schedule.due(&relays, watts, 500), (vec![], vec!["r_washer".to_string()]));
+    }
+}
@@ -0,0 +1,109 @@
+use serde::Deserialize;
+use std::f32::consts::TAU;
+
+/// Line voltage and frequency as a sensor sees them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridState {
+    pub volts: f32,
+    pub hz: f32,
+}
+
+/// A grid disturbance of one of the standard shapes, for tuning protection settings
+/// against in simulation. Depths are fractions of nominal; times are seconds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Disturbance {
+    /// Voltage drops by `depth` (0.3 = to 70%) for `duration`, falling and recovering over `ramp`
+    Sag {
+        depth: f32,
+        duration: f32,
+        #[serde(default)]
+        ramp: f32,
+    },
+    /// Voltage rises by `rise` for `duration`, with the same shape as a sag
+    Swell {
+        rise: f32,
+        duration: f32,
+        #[serde(default)]
+        ramp: f32,
+    },
+    /// Frequency slews to `to_hz` at `rate` Hz/s, holds there for `hold`, and slews back
+    FrequencyRamp { to_hz: f32, rate: f32, hold: f32 },
+    /// Voltage swings by ±`amplitude` at `hz` for `duration`
+    Flicker { amplitude: f32, hz: f32, duration: f32 },
+}
+
+impl Disturbance {
+    /// How long the disturbance lasts, from a grid at `nominal`.
+    pub fn duration(&self, nominal: GridState) -> f32 {
+        match self {
+            Disturbance::Sag { duration, ramp, .. } | Disturbance::Swell { duration, ramp, .. } => duration + 2.0 * ramp,
+            Disturbance::FrequencyRamp { to_hz, rate, hold } => 2.0 * slew_time(nominal.hz, *to_hz, *rate) + hold,
+            Disturbance::Flicker { duration, .. } => *duration,
+        }
+    }
+
+    /// The grid `t` seconds into the disturbance, starting from `nominal`; None once it is over.
+    pub fn at(&self, t: f32, nominal: GridState) -> Option<GridState> {
+        if !(0.0..self.duration(nominal)).contains(&t) {
+            return None;
+        }
+        let mut grid = nominal;
+        match self {
+            Disturbance::Sag { depth, duration, ramp } => grid.volts *= 1.0 - depth * trapezoid(t, *ramp, *duration),
+            Disturbance::Swell { rise, duration, ramp } => grid.volts *= 1.0 + rise * trapezoid(t, *ramp, *duration),
+            Disturbance::FrequencyRamp { to_hz, rate, hold } => {
+                let slew = slew_time(nominal.hz, *to_hz, *rate);
+                grid.hz += (to_hz - nominal.hz) * trapezoid(t, slew, *hold);
+            }
+            Disturbance::Flicker { amplitude, hz, .. } => grid.volts *= 1.0 + amplitude * (TAU * hz * t).sin(),
+        }
+        Some(grid)
+    }
+}
+
+/// Seconds to slew between two frequencies at `rate` Hz/s.
+fn slew_time(from_hz: f32, to_hz: f32, rate: f32) -> f32 {
+    if rate > 0.0 { (to_hz - from_hz).abs() / rate } else { 0.0 }
+}
+
+/// Envelope rising from 0 to 1 over `ramp`, holding for `hold`, and falling over `ramp`.
+fn trapezoid(t: f32, ramp: f32, hold: f32) -> f32 {
+    let level = if t < ramp {
+        t / ramp
+    } else if t < ramp + hold {
+        1.0
+    } else {
+        (2.0 * ramp + hold - t) / ramp
+    };
+    level.clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standard_shapes() {
+        let nominal = GridState { volts: 120.0, hz: 60.0 };
+        let near = |a: f32, b: f32| (a - b).abs() < 1e-3;
+
+        let sag = Disturbance::Sag { depth: 0.25, duration: 4.0, ramp: 1.0 };
+        assert_eq!(sag.duration(nominal), 6.0);
+        assert!(near(sag.at(0.5, nominal).unwrap().volts, 105.0));
+        assert!(near(sag.at(3.0, nominal).unwrap().volts, 90.0));
+        assert!(near(sag.at(5.5, nominal).unwrap().volts, 105.0));
+        assert_eq!(sag.at(6.0, nominal), None);
+
+        // 1.5 Hz down at 0.5 Hz/s takes 3s each way
+        let ramp = Disturbance::FrequencyRamp { to_hz: 58.5, rate: 0.5, hold: 10.0 };
+        assert_eq!(ramp.duration(nominal), 16.0);
+        assert!(near(ramp.at(1.0, nominal).unwrap().hz, 59.5));
+        assert!(near(ramp.at(8.0, nominal).unwrap().hz, 58.5));
+        assert_eq!(ramp.at(8.0, nominal).unwrap().volts, 120.0);
+
+        let flicker = Disturbance::Flicker { amplitude: 0.05, hz: 0.25, duration: 8.0 };
+        assert!(near(flicker.at(1.0, nominal).unwrap().volts, 126.0));
+        assert!(near(flicker.at(3.0, nominal).unwrap().volts, 114.0));
+    }
+}
@@ -0,0 +1,233 @@
+use crate::comms::Coordination;
+
+/// `Coordination.kind` values
+pub const KIND_ELECTION: u32 = 0;
+pub const KIND_COORDINATOR: u32 = 1;
+pub const KIND_RESTORE_STEP: u32 = 2;
+pub const KIND_ORCHESTRATOR_ALIVE: u32 = 3;
+
+/// Silence from the orchestrator after which nodes elect a coordinator.
+pub const ORCHESTRATOR_TIMEOUT_SECS: i64 = 3 * 60;
+
+/// How long a candidate waits for a higher node to take over before declaring itself.
+pub const ELECTION_TIMEOUT_SECS: i64 = 15;
+
+/// How often the coordinator repeats its announcement.
+pub const ANNOUNCE_INTERVAL_SECS: i64 = 30;
+
+/// Silence from the coordinator after which a new election is held.
+pub const COORDINATOR_TIMEOUT_SECS: i64 = 3 * ANNOUNCE_INTERVAL_SECS;
+
+/// Time the coordinator leaves between restoring one priority class and the next.
+pub const RESTORE_STEP_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Follower,
+    Candidate { since: i64 },
+    Coordinator,
+}
+
+/// Bully election among nodes, held only while the orchestrator is unreachable. The
+/// coordinator sequences black-start restoration one priority class at a time.
+#[derive(Debug)]
+pub struct Election {
+    node_id: String,
+    pub term: u64,
+    pub role: Role,
+    /// Coordinator followed in the current term
+    coordinator: Option<String>,
+    last_orchestrator: i64,
+    last_coordinator: i64,
+    last_announce: i64,
+    /// Next priority to restore and when the previous step went out
+    next_restore: i32,
+    last_restore: i64,
+}
+
+impl Election {
+    /// Starts out assuming the orchestrator was just heard, so a booting node doesn't
+    /// call an election before it has had a chance to hear one.
+    pub fn new(node_id: &str, now: i64) -> Self {
+        Self {
+            node_id: node_id.to_string(),
+            term: 0,
+            role: Role::Follower,
+            coordinator: None,
+            last_orchestrator: now,
+            last_coordinator: 0,
+            last_announce: 0,
+            next_restore: 0,
+            last_restore: 0,
+        }
+    }
+
+    pub fn is_coordinator(&self) -> bool {
+        self.role == Role::Coordinator
+    }
+
+    pub fn coordinator(&self) -> Option<&str> {
+        self.coordinator.as_deref()
+    }
+
+    fn orchestrator_reachable(&self, now: i64) -> bool {
+        now - self.last_orchestrator < ORCHESTRATOR_TIMEOUT_SECS
+    }
+
+    /// The orchestrator is back: it coordinates again.
+    pub fn orchestrator_heard(&mut self, now: i64) {
+        self.last_orchestrator = now;
+        if self.coordinator.is_some() || self.role != Role::Follower {
+            self.role = Role::Follower;
+            self.coordinator = None;
+        }
+    }
+
+    fn message(&self, kind: u32, priority: i32, now: i64) -> Coordination {
+        Coordination { node_id: self.node_id.clone(), kind, term: self.term, priority, timestamp: now }
+    }
+
+    fn start_election(&mut self, now: i64) -> Coordination {
+        self.term += 1;
+        self.role = Role::Candidate { since: now };
+        self.coordinator = None;
+        self.message(KIND_ELECTION, 0, now)
+    }
+
+    /// Fold in a message from another node (or the orchestrator); returns our reply, if any.
+    pub fn observe(&mut self, msg: &Coordination, now: i64) -> Option<Coordination> {
+        if msg.kind == KIND_ORCHESTRATOR_ALIVE {
+            // A recorded beacon replayed later mustn't suppress elections
+            if (now - msg.timestamp).abs() < ORCHESTRATOR_TIMEOUT_SECS {
+                self.orchestrator_heard(now);
+            }
+            return None;
+        }
+        if self.orchestrator_reachable(now) || msg.node_id == self.node_id {
+            return None;
+        }
+        self.term = self.term.max(msg.term);
+        let outranks = self.node_id > msg.node_id;
+        match msg.kind {
+            // Bully: a higher node answers a lower candidate by running itself
+            KIND_ELECTION | KIND_COORDINATOR if outranks => match self.role {
+                Role::Candidate { .. } => None,
+                Role::Coordinator => {
+                    self.last_announce = now;
+                    Some(self.message(KIND_COORDINATOR, 0, now))
+                }
+                Role::Follower => Some(self.start_election(now)),
+            },
+            KIND_ELECTION => {
+                // A higher node is running; wait for its announcement
+                self.role = Role::Follower;
+                self.coordinator = None;
+                self.last_coordinator = now;
+                None
+            }
+            KIND_COORDINATOR => {
+                self.role = Role::Follower;
+                self.coordinator = Some(msg.node_id.clone());
+                self.last_coordinator = now;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether a restore step comes from the coordinator we follow.
+    pub fn accepts_restore_step(&self, msg: &Coordination) -> bool {
+        msg.kind == KIND_RESTORE_STEP && self.coordinator() == Some(msg.node_id.as_str()) && msg.term == self.term
+    }
+
+    /// Advance timers; returns a message to broadcast, if any.
+    pub fn tick(&mut self, now: i64) -> Option<Coordination> {
+        if self.orchestrator_reachable(now) {
+            return None;
+        }
+        match self.role {
+            Role::Follower if now - self.last_coordinator > COORDINATOR_TIMEOUT_SECS => {
+                Some(self.start_election(now))
+            }
+            Role::Candidate { since } if now - since >= ELECTION_TIMEOUT_SECS => {
+                self.role = Role::Coordinator;
+                self.coordinator = Some(self.node_id.clone());
+                self.next_restore = 0;
+                self.last_restore = 0;
+                self.last_announce = now;
+                Some(self.message(KIND_COORDINATOR, 0, now))
+            }
+            Role::Coordinator if now - self.last_announce >= ANNOUNCE_INTERVAL_SECS => {
+                self.last_announce = now;
+                Some(self.message(KIND_COORDINATOR, 0, now))
+            }
+            _ => None,
+        }
+    }
+
+    /// As coordinator during black start: the next priority class to restore, once
+    /// the previous one has had time to settle.
+    pub fn next_restore_step(&mut self, now: i64) -> Option<Coordination> {
+        if !self.is_coordinator() || self.next_restore > 3 || now - self.last_restore < RESTORE_STEP_SECS {
+            return None;
+        }
+        let step = self.message(KIND_RESTORE_STEP, self.next_restore, now);
+        self.next_restore += 1;
+        self.last_restore = now;
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from(node_id: &str, kind: u32, term: u64) -> Coordination {
+        Coordination { node_id: node_id.to_string(), kind, term, priority: 0, timestamp: 0 }
+    }
+
+    #[test]
+    fn test_highest_node_becomes_coordinator_without_orchestrator() {
+        let mut low = Election::new("node_01", 0);
+        let mut high = Election::new("node_02", 0);
+
+        // Nothing happens while the orchestrator is around
+        assert!(low.tick(60).is_none());
+
+        let t = ORCHESTRATOR_TIMEOUT_SECS;
+        let call = low.tick(t).unwrap();
+        assert_eq!(call.kind, KIND_ELECTION);
+        let takeover = high.observe(&call, t).unwrap();
+        assert_eq!((takeover.kind, takeover.term), (KIND_ELECTION, 2));
+        assert!(low.observe(&takeover, t).is_none());
+
+        let t = t + ELECTION_TIMEOUT_SECS;
+        let announce = high.tick(t).unwrap();
+        assert_eq!(announce.kind, KIND_COORDINATOR);
+        low.observe(&announce, t);
+        assert!(low.tick(t).is_none());
+        assert_eq!(low.coordinator(), Some("node_02"));
+
+        // Restoration goes out one priority class at a time
+        let step = high.next_restore_step(t).unwrap();
+        assert_eq!(step.priority, 0);
+        assert!(low.accepts_restore_step(&step));
+        assert!(high.next_restore_step(t + 1).is_none());
+        assert_eq!(high.next_restore_step(t + RESTORE_STEP_SECS).unwrap().priority, 1);
+
+        // The orchestrator coming back ends the coordinator's tenure
+        let mut beacon = from("orchestrator", KIND_ORCHESTRATOR_ALIVE, 0);
+        beacon.timestamp = t;
+        high.observe(&beacon, t);
+        assert!(!high.is_coordinator());
+        assert!(high.next_restore_step(t + 2 * RESTORE_STEP_SECS).is_none());
+    }
+
+    #[test]
+    fn test_stale_orchestrator_beacon_is_ignored() {
+        let mut election = Election::new("node_01", 0);
+        let t = ORCHESTRATOR_TIMEOUT_SECS;
+        election.observe(&from("orchestrator", KIND_ORCHESTRATOR_ALIVE, 0), t);
+        assert_eq!(election.tick(t).unwrap().kind, KIND_ELECTION);
+    }
+}
@@ -0,0 +1,175 @@
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::comms::EnergyEntry;
+
+/// Storage key of the energy exchange ledger
+pub const ENERGY_KEY: &str = "energy.json";
+
+/// Length of the period each ledger entry covers while islanded.
+pub const ENTRY_PERIOD_SECS: i64 = 15 * 60;
+
+/// Most entries kept (own and neighbours'); the oldest are dropped first.
+pub const MAX_LEDGER_ENTRIES: usize = 2000;
+
+/// Ledger entry as stored on the SD card; mirrors `EnergyEntry`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub node_id: String,
+    pub seq: u64,
+    pub period_start: i64,
+    pub period_end: i64,
+    pub exported_wh: f32,
+    pub imported_wh: f32,
+    #[serde(with = "hex")]
+    pub device_public_key: Vec<u8>,
+    #[serde(with = "hex")]
+    pub signature: Vec<u8>,
+}
+
+impl From<LedgerEntry> for EnergyEntry {
+    fn from(e: LedgerEntry) -> Self {
+        EnergyEntry {
+            node_id: e.node_id,
+            seq: e.seq,
+            period_start: e.period_start,
+            period_end: e.period_end,
+            exported_wh: e.exported_wh,
+            imported_wh: e.imported_wh,
+            device_public_key: e.device_public_key,
+            signature: e.signature,
+        }
+    }
+}
+
+impl From<EnergyEntry> for LedgerEntry {
+    fn from(e: EnergyEntry) -> Self {
+        LedgerEntry {
+            node_id: e.node_id,
+            seq: e.seq,
+            period_start: e.period_start,
+            period_end: e.period_end,
+            exported_wh: e.exported_wh,
+            imported_wh: e.imported_wh,
+            device_public_key: e.device_public_key,
+            signature: e.signature,
+        }
+    }
+}
+
+/// Digest the secure element signs: SHA-256 of the entry encoded without key and signature.
+pub fn signing_digest(entry: &EnergyEntry) -> [u8; 32] {
+    let unsigned = EnergyEntry { device_public_key: Vec::new(), signature: Vec::new(), ..entry.clone() };
+    Sha256::digest(unsigned.encode_to_vec()).into()
+}
+
+/// Energy metered in the period not yet closed into an entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct OpenPeriod {
+    start: i64,
+    last_sample: i64,
+    exported_wh: f32,
+    imported_wh: f32,
+}
+
+/// Energy this household exchanged with the island, plus copies of its neighbours' entries.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EnergyLedger {
+    last_seq: u64,
+    open: Option<OpenPeriod>,
+    entries: Vec<LedgerEntry>,
+}
+
+impl EnergyLedger {
+    /// Meter `net_watts` (positive: drawing from the island, negative: supplying it)
+    /// since the previous sample.
+    pub fn accumulate(&mut self, net_watts: f32, now: i64) {
+        let open = self.open.get_or_insert(OpenPeriod { start: now, last_sample: now, exported_wh: 0.0, imported_wh: 0.0 });
+        let wh = net_watts * (now - open.last_sample) as f32 / 3600.0;
+        if wh > 0.0 {
+            open.imported_wh += wh;
+        } else {
+            open.exported_wh -= wh;
+        }
+        open.last_sample = now;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open.is_some()
+    }
+
+    pub fn period_elapsed(&self, now: i64) -> bool {
+        self.open.as_ref().is_some_and(|o| now - o.start >= ENTRY_PERIOD_SECS)
+    }
+
+    /// Close the open period into an unsigned entry; None if nothing was exchanged.
+    pub fn close(&mut self, node_id: &str) -> Option<EnergyEntry> {
+        let open = self.open.take()?;
+        if open.exported_wh == 0.0 && open.imported_wh == 0.0 {
+            return None;
+        }
+        self.last_seq += 1;
+        Some(EnergyEntry {
+            node_id: node_id.to_string(),
+            seq: self.last_seq,
+            period_start: open.start,
+            period_end: open.last_sample,
+            exported_wh: open.exported_wh,
+            imported_wh: open.imported_wh,
+            ..Default::default()
+        })
+    }
+
+    /// Keep an entry (ours or a neighbour's); duplicates are ignored.
+    pub fn insert(&mut self, entry: EnergyEntry) {
+        if self.entries.iter().any(|e| e.node_id == entry.node_id && e.seq == entry.seq) {
+            return;
+        }
+        if self.entries.len() >= MAX_LEDGER_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.entries.push(entry.into());
+    }
+
+    /// Entries whose period ended after `since`, oldest first.
+    pub fn since(&self, since: i64) -> Vec<EnergyEntry> {
+        let mut entries: Vec<&LedgerEntry> = self.entries.iter().filter(|e| e.period_end > since).collect();
+        entries.sort_by_key(|e| e.period_end);
+        entries.into_iter().cloned().map(EnergyEntry::from).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_periods_close_into_entries() {
+        let mut ledger = EnergyLedger::default();
+        ledger.accumulate(0.0, 0);
+        ledger.accumulate(1200.0, 1800); // drew 1.2 kW for half an hour
+        ledger.accumulate(-600.0, 3600); // then supplied 600 W
+        assert!(ledger.period_elapsed(3600));
+
+        let entry = ledger.close("node_01").unwrap();
+        assert_eq!((entry.seq, entry.period_start, entry.period_end), (1, 0, 3600));
+        assert_eq!((entry.imported_wh, entry.exported_wh), (600.0, 300.0));
+        assert!(!ledger.is_open());
+
+        // Signing covers the figures but not the signature itself
+        let mut signed = entry.clone();
+        signed.signature = vec![1; 64];
+        assert_eq!(signing_digest(&signed), signing_digest(&entry));
+        signed.imported_wh = 1.0;
+        assert_ne!(signing_digest(&signed), signing_digest(&entry));
+
+        ledger.insert(entry.clone());
+        ledger.insert(entry);
+        assert_eq!(ledger.since(0).len(), 1);
+        assert!(ledger.since(3600).is_empty());
+
+        // Nothing exchanged, nothing recorded
+        ledger.accumulate(0.0, 4000);
+        assert!(ledger.close("node_01").is_none());
+    }
+}
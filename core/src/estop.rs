@@ -0,0 +1,101 @@
+use log::{error, info};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::hal::{EmergencyStop, RelayControl};
+
+/// How often the watch reads the input
+pub const ESTOP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Watches the emergency-stop input on a thread of its own and, the moment it is
+/// asserted, opens every relay straight through the driver: no queue, interlock or
+/// event loop stands between the button and the contacts. The trip latches until
+/// `reset`, which only succeeds once the input has been released.
+pub struct EStopWatch {
+    tripped: Arc<AtomicBool>,
+    asserted: Arc<AtomicBool>,
+}
+
+impl EStopWatch {
+    /// Watch `input`, opening the relays on `pins` through `driver` when it trips.
+    pub fn spawn(mut input: Box<dyn EmergencyStop>, driver: Arc<Mutex<Box<dyn RelayControl>>>, pins: Vec<u8>) -> Self {
+        let tripped = Arc::new(AtomicBool::new(false));
+        let asserted = Arc::new(AtomicBool::new(false));
+        let (trip, assert) = (tripped.clone(), asserted.clone());
+        std::thread::spawn(move || loop {
+            // An input that can't be read is treated as asserted: the stop must fail safe
+            let now_asserted = input.is_asserted().unwrap_or_else(|e| {
+                error!("Emergency stop read failed: {}", e);
+                true
+            });
+            assert.store(now_asserted, Ordering::SeqCst);
+            if now_asserted && !trip.swap(true, Ordering::SeqCst) {
+                // Whoever holds the driver only ever does so for one pin write
+                let mut driver = driver.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                for pin in &pins {
+                    if let Err(e) = driver.set_relay(*pin, false) {
+                        error!("Emergency stop failed to open pin {}: {}", pin, e);
+                    }
+                }
+                error!("EMERGENCY STOP: opened {} relays", pins.len());
+            }
+            std::thread::sleep(ESTOP_POLL_INTERVAL);
+        });
+        info!("Watching the emergency stop every {} ms", ESTOP_POLL_INTERVAL.as_millis());
+        Self { tripped, asserted }
+    }
+
+    /// Whether the stop has tripped since the last reset.
+    pub fn tripped(&self) -> bool {
+        self.tripped.load(Ordering::SeqCst)
+    }
+
+    /// Whether the input is asserted right now.
+    pub fn asserted(&self) -> bool {
+        self.asserted.load(Ordering::SeqCst)
+    }
+
+    /// Clear the latch. False, leaving it tripped, while the input is still asserted.
+    pub fn reset(&self) -> bool {
+        if self.asserted() {
+            return false;
+        }
+        self.tripped.store(false, Ordering::SeqCst);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::estop::mock::MockEmergencyStop;
+    use crate::hal::gpio::mock::MockRelayDriver;
+
+    #[test]
+    fn test_stop_opens_every_relay_and_latches_until_released_and_reset() {
+        let mut driver: Box<dyn RelayControl> = Box::new(MockRelayDriver::new(&[]).unwrap());
+        driver.set_relay(17, true).unwrap();
+        driver.set_relay(27, true).unwrap();
+        let driver = Arc::new(Mutex::new(driver));
+        let input = MockEmergencyStop::new();
+        let button = input.handle();
+        let watch = EStopWatch::spawn(Box::new(input), driver.clone(), vec![17, 27]);
+        let settle = || std::thread::sleep(ESTOP_POLL_INTERVAL * 5);
+
+        settle();
+        assert!(!watch.tripped());
+        button.store(true, Ordering::SeqCst);
+        settle();
+        assert!(watch.tripped());
+        assert!(!driver.lock().unwrap().get_relay(17).unwrap());
+        assert!(!driver.lock().unwrap().get_relay(27).unwrap());
+
+        // Held down, it can't be reset; released, it stays latched until it is
+        assert!(!watch.reset());
+        button.store(false, Ordering::SeqCst);
+        settle();
+        assert!(watch.tripped());
+        assert!(watch.reset());
+        assert!(!watch.tripped());
+    }
+}
@@ -0,0 +1,100 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+use crate::types::{NodeState, Phase};
+
+/// Number of events buffered per subscriber before slow readers start losing events.
+const EVENT_BUFFER: usize = 256;
+
+/// Live events emitted by the node for local consumers (API, exporters).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NodeEvent {
+    RelayChanged { relay_id: String, closed: bool },
+    StateChanged { state: NodeState },
+    Alarm { code: String, message: String },
+    /// Power on an ADC channel; negative while exporting
+    Measurement { channel: u8, watts: f32, voltage: f32 },
+    /// Running total of what deferring loads through tariff peaks has saved
+    TariffSavings { kwh_deferred: f64, saved: f64 },
+    /// The voltage dipped below the under-voltage threshold and has recovered; `escalated`
+    /// if it outlasted the ride-through window
+    VoltageSag { start: i64, duration_secs: f32, min_voltage: f32, escalated: bool },
+    /// One phase of a three-phase service
+    PhaseMeasurement { phase: Phase, volts: f32, amps: f32 },
+    /// Real power on an ADC channel (negative while exporting), and what the load's power factor adds to it
+    PowerQuality { channel: u8, real_watts: f32, reactive_var: f32, apparent_va: f32, power_factor: f32 },
+    /// Total harmonic distortion of the mains voltage, as a share of the fundamental
+    VoltageDistortion { thd: f32 },
+    /// A relay's contacts stopped following its commands (`failure` is "welded" or
+    /// "failed_open"), as its auxiliary contact or CT clamp showed
+    RelayFailed { relay_id: String, failure: String, evidence: String },
+    /// The board started or stopped derating the relays for its temperature
+    ThermalDerating { temperature_c: f32, derating: bool, allowed_amps: Option<f32> },
+    /// The battery's charge moved the island into another reserve tier, or out of them all
+    BatteryReserve { soc: f32, tier: Option<String> },
+    /// Power through a sub-metered circuit (negative while exporting), and the energy
+    /// each way through it since the node started
+    CircuitPower { circuit: String, watts: f32, imported_wh: f64, exported_wh: f64 },
+}
+
+/// An event stamped with the time it was published.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EventRecord {
+    pub timestamp: i64,
+    #[serde(flatten)]
+    pub event: NodeEvent,
+}
+
+/// Fan-out bus for node events. Cheap to clone; publishing never blocks.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<EventRecord>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_BUFFER);
+        Self { tx }
+    }
+
+    /// Publish an event. Dropped silently when nobody is listening.
+    pub fn publish(&self, event: NodeEvent) {
+        let record = EventRecord {
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0),
+            event,
+        };
+        let _ = self.tx.send(record);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<EventRecord> {
+        self.tx.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_event_serializes_flat() {
+        let bus = EventBus::new();
+        let mut rx = bus.subscribe();
+        bus.publish(NodeEvent::RelayChanged { relay_id: "r_aux".to_string(), closed: false });
+
+        let record = rx.recv().await.unwrap();
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["type"], "relay_changed");
+        assert_eq!(json["relay_id"], "r_aux");
+        assert_eq!(json["closed"], false);
+        assert!(json["timestamp"].as_i64().unwrap() > 0);
+    }
+}
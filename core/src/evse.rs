@@ -0,0 +1,75 @@
+use log::{info, warn};
+use crate::hal::evse::{ChargerControl, MIN_PILOT_AMPS};
+
+/// An EV charger on one of our load relays. Under stress its current is turned down,
+/// as far as the pilot's minimum, before the relay is opened.
+pub struct EvCharger {
+    pub relay_id: String,
+    /// Current the charger is installed for
+    pub max_amps: f32,
+    /// Current offered to the car now
+    limit_amps: f32,
+    /// Held at the minimum for a demand-response event
+    held_down: bool,
+    control: Box<dyn ChargerControl>,
+}
+
+impl EvCharger {
+    pub fn new(relay_id: &str, max_amps: f32, control: Box<dyn ChargerControl>) -> Self {
+        let max_amps = max_amps.max(MIN_PILOT_AMPS);
+        Self { relay_id: relay_id.to_string(), max_amps, limit_amps: max_amps, held_down: false, control }
+    }
+
+    pub fn limit_amps(&self) -> f32 {
+        self.limit_amps
+    }
+
+    /// Most we offer the car now: the minimum during a demand-response event.
+    pub fn ceiling_amps(&self) -> f32 {
+        if self.held_down { MIN_PILOT_AMPS } else { self.max_amps }
+    }
+
+    pub fn hold_down(&mut self, held: bool) {
+        self.held_down = held;
+    }
+
+    /// Offer the car `amps`, kept between the pilot's minimum and our ceiling. Returns
+    /// the limit in effect afterwards, the old one if the charger could not be set.
+    pub fn set_limit(&mut self, amps: f32) -> f32 {
+        let amps = amps.clamp(MIN_PILOT_AMPS, self.ceiling_amps()).floor();
+        if amps == self.limit_amps {
+            return amps;
+        }
+        match self.control.set_current_limit(amps) {
+            Ok(()) => {
+                info!("EV charging on {} limited to {:.0} A (was {:.0} A)", self.relay_id, amps, self.limit_amps);
+                self.limit_amps = amps;
+            }
+            Err(e) => warn!("Failed to limit EV charging on {}: {}", self.relay_id, e),
+        }
+        self.limit_amps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::evse::mock::MockChargerControl;
+
+    #[test]
+    fn test_limit_stays_within_pilot_range_and_ceiling() {
+        let control = MockChargerControl::new();
+        let pilot = control.handle();
+        let mut charger = EvCharger::new("r_ev", 32.0, Box::new(control));
+
+        assert_eq!(charger.set_limit(20.7), 20.0);
+        assert_eq!(*pilot.lock().unwrap(), Some(20.0));
+        assert_eq!(charger.set_limit(2.0), MIN_PILOT_AMPS);
+        assert_eq!(charger.set_limit(48.0), 32.0);
+
+        charger.hold_down(true);
+        assert_eq!(charger.set_limit(32.0), MIN_PILOT_AMPS);
+        charger.hold_down(false);
+        assert_eq!(charger.set_limit(charger.ceiling_amps()), 32.0);
+    }
+}
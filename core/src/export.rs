@@ -0,0 +1,89 @@
+use log::{info, warn};
+use std::io::Write;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use crate::events::{EventBus, EventRecord};
+
+/// Delay before reopening the serial port after an I/O error.
+const REOPEN_DELAY: Duration = Duration::from_secs(5);
+
+/// Streams node events as newline-delimited JSON over a UART, for data loggers
+/// or a laptop attached during commissioning and incident investigation.
+pub struct SerialExporter {
+    port: String,
+    baud: u32,
+}
+
+impl SerialExporter {
+    pub fn new(port: &str, baud: u32) -> Self {
+        Self { port: port.to_string(), baud }
+    }
+
+    /// Run on a dedicated thread: serial writes are blocking.
+    pub fn spawn(self, events: &EventBus) {
+        let rx = events.subscribe();
+        std::thread::Builder::new()
+            .name("serial-export".to_string())
+            .spawn(move || self.run(rx))
+            .expect("failed to spawn serial export thread");
+    }
+
+    fn run(self, mut rx: broadcast::Receiver<EventRecord>) {
+        info!("Exporting events as NDJSON on {} @ {} baud", self.port, self.baud);
+        loop {
+            match serialport::new(&self.port, self.baud).open() {
+                Ok(mut port) => match pump(&mut rx, &mut port) {
+                    Ok(()) => return, // Event bus closed, node is shutting down
+                    Err(e) => warn!("Serial export on {} failed: {}", self.port, e),
+                },
+                Err(e) => warn!("Cannot open serial port {}: {}", self.port, e),
+            }
+            std::thread::sleep(REOPEN_DELAY);
+        }
+    }
+}
+
+/// Write one JSON object per line until the event bus closes or the writer fails.
+fn pump(rx: &mut broadcast::Receiver<EventRecord>, out: &mut impl Write) -> std::io::Result<()> {
+    loop {
+        match rx.blocking_recv() {
+            Ok(record) => {
+                let mut line = serde_json::to_vec(&record)?;
+                line.push(b'\n');
+                out.write_all(&line)?;
+                out.flush()?;
+            }
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Serial export fell behind, {} events dropped", missed);
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::NodeEvent;
+
+    #[test]
+    fn test_pump_writes_ndjson() {
+        let bus = EventBus::new();
+        let mut rx = bus.subscribe();
+        bus.publish(NodeEvent::Measurement { channel: 0, watts: 1200.0, voltage: 120.0 });
+        bus.publish(NodeEvent::RelayChanged { relay_id: "r_aux".to_string(), closed: false });
+        drop(bus);
+
+        let mut out = Vec::new();
+        pump(&mut rx, &mut out).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let first: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(first["type"], "measurement");
+        assert_eq!(first["watts"], 1200.0);
+        let second: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(second["relay_id"], "r_aux");
+    }
+}
@@ -0,0 +1,122 @@
+use log::{info, warn};
+
+/// How long the primary must be silent before the secondary's commands are accepted.
+pub const DEFAULT_TAKEOVER_AFTER_SECS: i64 = 5 * 60;
+
+/// Which of the two configured orchestrators the node takes commands from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Following {
+    Primary,
+    Secondary,
+}
+
+/// Arbitrates between a primary and a standby orchestrator. The secondary's commands
+/// are only accepted once it has announced a takeover or the primary has been silent
+/// long enough; the primary takes back over by issuing a command. Commands from any
+/// other sender, or none, are refused.
+#[derive(Debug)]
+pub struct Failover {
+    primary: String,
+    secondary: String,
+    takeover_after_secs: i64,
+    following: Following,
+    /// Last command or alive beacon from the primary
+    primary_heard: i64,
+}
+
+impl Failover {
+    pub fn new(primary: &str, secondary: &str, takeover_after_secs: i64, now: i64) -> Self {
+        Self {
+            primary: primary.to_string(),
+            secondary: secondary.to_string(),
+            takeover_after_secs,
+            following: Following::Primary,
+            // Give the primary a full period after boot before the standby may step in
+            primary_heard: now,
+        }
+    }
+
+    /// Orchestrator whose commands we currently follow.
+    pub fn following(&self) -> &str {
+        match self.following {
+            Following::Primary => &self.primary,
+            Following::Secondary => &self.secondary,
+        }
+    }
+
+    /// An alive beacon from `orchestrator_id`. It keeps the primary from being
+    /// considered silent, but doesn't undo a takeover.
+    pub fn beacon(&mut self, orchestrator_id: &str, now: i64) {
+        if orchestrator_id == self.primary {
+            self.primary_heard = now;
+        }
+    }
+
+    /// Whether to act on a fresh, authenticated command from `sender_id`.
+    pub fn accept(&mut self, sender_id: Option<&str>, now: i64) -> bool {
+        match sender_id {
+            Some(sender) if sender == self.primary => {
+                self.primary_heard = now;
+                if self.following == Following::Secondary {
+                    info!("Primary orchestrator {} is back; following it again", self.primary);
+                    self.following = Following::Primary;
+                }
+                true
+            }
+            Some(sender) if sender == self.secondary => {
+                if self.following == Following::Secondary {
+                    return true;
+                }
+                let silent = now - self.primary_heard;
+                if silent < self.takeover_after_secs {
+                    return false;
+                }
+                warn!("Primary orchestrator silent for {}s; following secondary {}", silent, self.secondary);
+                self.following = Following::Secondary;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// A signed takeover announcement from `orchestrator_id`; false unless it is our secondary.
+    pub fn take_over(&mut self, orchestrator_id: &str) -> bool {
+        if orchestrator_id != self.secondary {
+            return false;
+        }
+        if self.following == Following::Primary {
+            warn!("Secondary orchestrator {} announced a takeover; following it", self.secondary);
+            self.following = Following::Secondary;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secondary_needs_takeover_or_primary_silence() {
+        let mut failover = Failover::new("orch_a", "orch_b", 300, 0);
+        assert!(failover.accept(Some("orch_a"), 10));
+        assert!(!failover.accept(Some("orch_b"), 20));
+        assert!(!failover.accept(Some("orch_c"), 20));
+        assert!(!failover.accept(None, 20));
+        assert_eq!(failover.following(), "orch_a");
+
+        // Beacons keep the primary alive
+        failover.beacon("orch_a", 250);
+        assert!(!failover.accept(Some("orch_b"), 500));
+        assert!(failover.accept(Some("orch_b"), 550));
+        assert_eq!(failover.following(), "orch_b");
+
+        // The primary reclaims by issuing a command
+        assert!(failover.accept(Some("orch_a"), 600));
+        assert_eq!(failover.following(), "orch_a");
+        assert!(!failover.take_over("orch_c"));
+        assert!(failover.take_over("orch_b"));
+        assert!(failover.accept(Some("orch_b"), 601));
+        assert_eq!(failover.following(), "orch_b");
+    }
+}
@@ -0,0 +1,312 @@
+use async_trait::async_trait;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use crate::comms::{self, CommunicationLayer, LinkStats, NeighborhoodMessage};
+use crate::hal::{self, HalError, PowerSensor, RelayControl};
+use crate::medium::SimRng;
+use crate::tls::{Pin, TlsIdentity};
+
+/// Readings a garbled ADC returns, in turn
+const GARBAGE_READINGS: [f32; 4] = [f32::NAN, -37.5, 65_535.0, f32::INFINITY];
+
+/// Receive polls a message held back by chaos sits out (two seconds at the node's
+/// 100 ms poll)
+const CHAOS_DELAY_POLLS: u32 = 20;
+
+/// Faults to inject into the hardware and radio, for testing how the node copes.
+/// Never configure these on a node in service.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Faults {
+    /// Every relay write fails
+    #[serde(default)]
+    pub relay_fail: bool,
+    /// CT readings are nonsense: NaN, negative, or far beyond any clamp's rating
+    #[serde(default)]
+    pub adc_garbage: bool,
+    /// Every Nth packet sent or received is silently lost
+    #[serde(default)]
+    pub radio_drop_every: Option<u32>,
+    /// Chance, 0 to 1, of a recoverable fault at each opportunity: a packet lost, an
+    /// ADC read failing, a received message held back a while (soak runs, `--chaos`)
+    #[serde(default)]
+    pub chaos: Option<f64>,
+}
+
+/// Faults in effect, shared by the wrappers and whatever changes them while running
+/// (a simulation scenario).
+#[derive(Debug, Clone)]
+pub struct FaultSwitch {
+    faults: Arc<Mutex<Faults>>,
+    /// Chaos draws, seeded so a soak run that found something can be repeated
+    rng: Arc<Mutex<SimRng>>,
+}
+
+impl Default for FaultSwitch {
+    fn default() -> Self {
+        Self::new(Faults::default())
+    }
+}
+
+impl FaultSwitch {
+    pub fn new(faults: Faults) -> Self {
+        Self::with_seed(faults, 1)
+    }
+
+    pub fn with_seed(faults: Faults, seed: u64) -> Self {
+        Self { faults: Arc::new(Mutex::new(faults)), rng: Arc::new(Mutex::new(SimRng::new(seed))) }
+    }
+
+    pub fn set(&self, faults: Faults) {
+        *self.faults.lock().unwrap() = faults;
+    }
+
+    fn get(&self) -> Faults {
+        self.faults.lock().unwrap().clone()
+    }
+
+    /// Whether chaos strikes this time.
+    fn chaos(&self) -> bool {
+        match self.get().chaos.filter(|p| *p > 0.0) {
+            Some(p) => self.rng.lock().unwrap().next_f64() < p,
+            None => false,
+        }
+    }
+}
+
+/// Relay driver whose writes fail on demand.
+pub struct FaultyRelays {
+    inner: Box<dyn RelayControl>,
+    faults: FaultSwitch,
+}
+
+impl FaultyRelays {
+    pub fn new(inner: Box<dyn RelayControl>, faults: FaultSwitch) -> Self {
+        Self { inner, faults }
+    }
+}
+
+impl RelayControl for FaultyRelays {
+    fn set_relay(&mut self, pin: u8, closed: bool) -> hal::Result<()> {
+        if self.faults.get().relay_fail {
+            return Err(HalError::Bus(format!("injected fault: relay on pin {} did not switch", pin)));
+        }
+        self.inner.set_relay(pin, closed)
+    }
+
+    fn get_relay(&self, pin: u8) -> hal::Result<bool> {
+        self.inner.get_relay(pin)
+    }
+}
+
+/// Power sensor that returns garbage on demand.
+pub struct FaultySensor {
+    inner: Box<dyn PowerSensor>,
+    faults: FaultSwitch,
+    garbage: usize,
+}
+
+impl FaultySensor {
+    pub fn new(inner: Box<dyn PowerSensor>, faults: FaultSwitch) -> Self {
+        Self { inner, faults, garbage: 0 }
+    }
+
+    fn garbage(&mut self) -> f32 {
+        self.garbage = (self.garbage + 1) % GARBAGE_READINGS.len();
+        GARBAGE_READINGS[self.garbage]
+    }
+}
+
+impl PowerSensor for FaultySensor {
+    fn read_raw(&mut self, channel: u8) -> hal::Result<i16> {
+        if self.faults.chaos() {
+            return Err(HalError::Bus(format!("injected fault: transient ADC error on channel {}", channel)));
+        }
+        if self.faults.get().adc_garbage {
+            return Ok(i16::MIN);
+        }
+        self.inner.read_raw(channel)
+    }
+
+    fn read_current_amps(&mut self, channel: u8) -> hal::Result<f32> {
+        if self.faults.chaos() {
+            return Err(HalError::Bus(format!("injected fault: transient ADC error on channel {}", channel)));
+        }
+        if self.faults.get().adc_garbage {
+            return Ok(self.garbage());
+        }
+        self.inner.read_current_amps(channel)
+    }
+
+    fn read_watts(&mut self, channel: u8) -> hal::Result<f32> {
+        if self.faults.chaos() {
+            return Err(HalError::Bus(format!("injected fault: transient ADC error on channel {}", channel)));
+        }
+        if self.faults.get().adc_garbage {
+            return Ok(self.garbage());
+        }
+        self.inner.read_watts(channel)
+    }
+
+    fn read_power_sign(&mut self, channel: u8) -> hal::Result<f32> {
+        if self.faults.chaos() {
+            return Err(HalError::Bus(format!("injected fault: transient ADC error on channel {}", channel)));
+        }
+        self.inner.read_power_sign(channel)
+    }
+
+    fn read_frequency_hz(&mut self) -> hal::Result<f32> {
+        if self.faults.chaos() {
+            return Err(HalError::Bus("injected fault: transient frequency read error".to_string()));
+        }
+        if self.faults.get().adc_garbage {
+            return Ok(self.garbage());
+        }
+        self.inner.read_frequency_hz()
+    }
+}
+
+/// Transport that loses every Nth packet on demand, in either direction, and under
+/// chaos loses or holds back packets at random.
+pub struct FaultyLink {
+    inner: Arc<dyn CommunicationLayer>,
+    faults: FaultSwitch,
+    packets: AtomicU32,
+    /// Received messages held back, with the polls each still has to wait
+    held: Mutex<VecDeque<(NeighborhoodMessage, u32)>>,
+}
+
+impl FaultyLink {
+    pub fn new(inner: Arc<dyn CommunicationLayer>, faults: FaultSwitch) -> Self {
+        Self { inner, faults, packets: AtomicU32::new(0), held: Mutex::new(VecDeque::new()) }
+    }
+
+    /// Count a packet; true if it is one to lose.
+    fn drop_next(&self) -> bool {
+        if self.faults.chaos() {
+            return true;
+        }
+        let Some(every) = self.faults.get().radio_drop_every.filter(|n| *n > 0) else {
+            return false;
+        };
+        (self.packets.fetch_add(1, Ordering::Relaxed) + 1).is_multiple_of(every)
+    }
+
+    /// Count down the held messages; the first whose wait is over, if any.
+    fn release_held(&self) -> Option<NeighborhoodMessage> {
+        let mut held = self.held.lock().unwrap();
+        for (_, polls) in held.iter_mut() {
+            *polls = polls.saturating_sub(1);
+        }
+        let due = held.iter().position(|(_, polls)| *polls == 0)?;
+        held.remove(due).map(|(msg, _)| msg)
+    }
+}
+
+#[async_trait]
+impl CommunicationLayer for FaultyLink {
+    async fn send(&self, msg: NeighborhoodMessage) -> comms::Result<()> {
+        if self.drop_next() {
+            debug!("Injected fault: dropping outgoing packet");
+            return Ok(());
+        }
+        self.inner.send(msg).await
+    }
+
+    async fn receive(&self) -> comms::Result<Option<NeighborhoodMessage>> {
+        if let Some(msg) = self.release_held() {
+            debug!("Injected fault: delivering a held-back packet");
+            return Ok(Some(msg));
+        }
+        let Some(msg) = self.inner.receive().await? else {
+            return Ok(None);
+        };
+        if self.drop_next() {
+            debug!("Injected fault: dropping incoming packet");
+            return Ok(None);
+        }
+        if self.faults.chaos() {
+            debug!("Injected fault: holding back incoming packet");
+            self.held.lock().unwrap().push_back((msg, CHAOS_DELAY_POLLS));
+            return Ok(None);
+        }
+        Ok(Some(msg))
+    }
+
+    fn link_stats(&self) -> LinkStats {
+        self.inner.link_stats()
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn rotate_tls(&self, identity: TlsIdentity, pins: Vec<Pin>) -> comms::Result<()> {
+        self.inner.rotate_tls(identity, pins).await
+    }
+
+    async fn close(&self) -> comms::Result<()> {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comms::MockCommunication;
+    use crate::hal::gpio::mock::MockRelayDriver;
+
+    #[tokio::test]
+    async fn test_faults_switch_on_and_off() {
+        let faults = FaultSwitch::default();
+        let mut relays = FaultyRelays::new(Box::new(MockRelayDriver::new(&[]).unwrap()), faults.clone());
+        assert!(relays.set_relay(17, true).is_ok());
+        faults.set(Faults { relay_fail: true, ..Default::default() });
+        assert!(relays.set_relay(17, false).is_err());
+        assert!(relays.get_relay(17).unwrap());
+
+        faults.set(Faults { radio_drop_every: Some(2), ..Default::default() });
+        let link = FaultyLink::new(Arc::new(MockCommunication::default()), faults.clone());
+        let sent: Vec<bool> = (0..4).map(|_| link.drop_next()).collect();
+        assert_eq!(sent, vec![false, true, false, true]);
+        faults.set(Faults::default());
+        assert!(!link.drop_next());
+    }
+
+    #[tokio::test]
+    async fn test_chaos_fails_reads_and_holds_back_messages() {
+        use crate::hal::adc::mock::MockAdcSensor;
+        use crate::hal::AdcConfig;
+        use crate::comms::streetgrid::neighborhood_message::Payload;
+        use crate::comms::streetgrid::Heartbeat;
+
+        let faults = FaultSwitch::with_seed(Faults { chaos: Some(1.0), ..Default::default() }, 7);
+        let mut sensor = FaultySensor::new(Box::new(MockAdcSensor::new(AdcConfig::default()).unwrap()), faults.clone());
+        assert!(sensor.read_watts(0).is_err());
+        faults.set(Faults::default());
+        assert!(sensor.read_watts(0).is_ok());
+
+        let inner = Arc::new(MockCommunication::default());
+        let link = FaultyLink::new(inner.clone(), faults.clone());
+        let heartbeat = NeighborhoodMessage {
+            payload: Some(Payload::Heartbeat(Heartbeat { node_id: "node_02".to_string(), ..Default::default() })),
+            ..Default::default()
+        };
+        // Not lost: held back, then delivered once its wait is over
+        link.held.lock().unwrap().push_back((heartbeat.clone(), 2));
+        assert!(link.receive().await.unwrap().is_none());
+        assert_eq!(link.receive().await.unwrap(), Some(heartbeat));
+        assert!(link.receive().await.unwrap().is_none());
+
+        // At a low rate only some of many draws strike, and the same seed strikes the same ones
+        let draws = |seed| {
+            let faults = FaultSwitch::with_seed(Faults { chaos: Some(0.05), ..Default::default() }, seed);
+            (0..1000).map(|_| faults.chaos()).collect::<Vec<_>>()
+        };
+        let struck = draws(3).iter().filter(|s| **s).count();
+        assert!((20..80).contains(&struck), "{}", struck);
+        assert_eq!(draws(3), draws(3));
+    }
+}
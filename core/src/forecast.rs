@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use crate::interlock;
+use crate::load_profile::LoadProfile;
+use crate::types::{Relay, RelayType};
+
+/// How often the next hour's load is forecast against the budget and any demand-response cap
+pub const FORECAST_TICK_SECS: u64 = 60;
+
+/// Weight of each new reading in a relay's recent average: at the 5 s ADC interval it
+/// follows the last couple of minutes.
+const RECENT_WEIGHT: f32 = 0.05;
+
+/// Share of how far a relay is from its usual draw this hour that is expected to carry
+/// into the next.
+const PERSISTENCE: f32 = 0.5;
+
+/// Share of the target left free before a shed load is put back, so a load isn't
+/// closed and opened again on every small change in the forecast.
+const RESTORE_HEADROOM: f32 = 0.1;
+
+/// Predicts each circuit's draw over the next hour from its recent readings and the
+/// profile learned for the time of day, and opens loads ahead of an overload.
+#[derive(Debug, Default)]
+pub struct Forecaster {
+    /// Recent draw of each metered relay
+    recent: BTreeMap<String, f32>,
+    /// Relays opened because the forecast ran over
+    shed: Vec<String>,
+}
+
+impl Forecaster {
+    /// Fold a CT reading into the relay's recent average.
+    pub fn observe(&mut self, relay_id: &str, watts: f32) {
+        self.recent.entry(relay_id.to_string())
+            .and_modify(|recent| *recent += (watts - *recent) * RECENT_WEIGHT)
+            .or_insert(watts);
+    }
+
+    pub fn shed(&self) -> &[String] {
+        &self.shed
+    }
+
+    /// Draw expected of a relay over the hour after local `hour`: what it usually draws
+    /// then, moved by part of how far it is from its usual draw now. None with no history.
+    pub fn expected_watts(&self, relay_id: &str, profile: &LoadProfile, hour: u32) -> Option<f32> {
+        let recent = self.recent.get(relay_id).copied();
+        let usual_now = profile.expected_watts(relay_id, hour);
+        match (profile.expected_watts(relay_id, (hour + 1) % 24), recent, usual_now) {
+            (Some(next), Some(recent), Some(now)) => Some(next + PERSISTENCE * (recent - now)),
+            (Some(next), _, _) => Some(next),
+            (None, recent, _) => recent,
+        }
+    }
+
+    /// Loads to open, lowest priority first, for the `forecast` draw of the closed loads to
+    /// come within `target`, and loads shed earlier to close again. Those fit back with
+    /// headroom under the target, or all of them once `restore` says nothing caps us.
+    /// Loads the budget never sheds are left alone.
+    pub fn tick(&mut self, relays: &[Relay], forecast: impl Fn(&Relay) -> f32, target: Option<f32>, restore: bool) -> (Vec<String>, Vec<String>) {
+        // Loads closed again by someone else are no longer ours to restore
+        self.shed.retain(|id| relays.iter().any(|r| r.id == *id && !r.is_closed));
+        let loads = || relays.iter().filter(|r| r.relay_type == RelayType::Load);
+        let Some(target) = target else {
+            return (Vec::new(), if restore { std::mem::take(&mut self.shed) } else { Vec::new() });
+        };
+        let mut total: f32 = loads().filter(|r| r.is_closed).map(&forecast).sum();
+
+        let mut open = Vec::new();
+        if total > target {
+            let mut sheddable: Vec<&Relay> = loads()
+                .filter(|r| r.is_closed && !interlock::protected_from_budget(r) && forecast(r) > 0.0)
+                .collect();
+            sheddable.sort_by_key(|r| std::cmp::Reverse(r.priority));
+            for relay in sheddable {
+                if total <= target {
+                    break;
+                }
+                total -= forecast(relay);
+                open.push(relay.id.clone());
+            }
+            self.shed.extend(open.iter().cloned());
+            return (open, Vec::new());
+        }
+
+        let mut close = Vec::new();
+        let mut waiting: Vec<&Relay> = loads().filter(|r| self.shed.contains(&r.id)).collect();
+        waiting.sort_by_key(|r| r.priority);
+        for relay in waiting {
+            let watts = forecast(relay);
+            if total + watts <= target * (1.0 - RESTORE_HEADROOM) {
+                total += watts;
+                close.push(relay.id.clone());
+            }
+        }
+        self.shed.retain(|id| !close.contains(id));
+        (open, close)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Priority;
+
+    fn load(id: &str, priority: Priority) -> Relay {
+        Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage: 20.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        }
+    }
+
+    #[test]
+    fn test_evening_peak_is_shed_before_it_arrives() {
+        let mut profile = LoadProfile::default();
+        for _ in 0..10 {
+            profile.record("r_hvac", 17, 500.0);
+            profile.record("r_hvac", 18, 2500.0);
+            profile.record("r_dryer", 18, 1500.0);
+            profile.record("r_fridge", 18, 150.0);
+        }
+        let mut forecaster = Forecaster::default();
+        for _ in 0..100 {
+            forecaster.observe("r_hvac", 900.0);
+        }
+        // Running 400 W above its usual 5 pm draw: half of that carries into the 6 pm peak
+        let expected = forecaster.expected_watts("r_hvac", &profile, 17).unwrap();
+        assert!((expected - 2700.0).abs() < 1.0);
+        assert_eq!(forecaster.expected_watts("r_dryer", &profile, 17), Some(1500.0));
+        assert_eq!(forecaster.expected_watts("r_lights", &profile, 17), None);
+
+        let mut relays = vec![load("r_fridge", Priority::Critical), load("r_hvac", Priority::Medium), load("r_dryer", Priority::Low)];
+        let forecast = |relay: &Relay| forecaster.expected_watts(&relay.id, &profile, 17).unwrap_or(0.0);
+        let watts: BTreeMap<String, f32> = relays.iter().map(|r| (r.id.clone(), forecast(r))).collect();
+
+        // 4350 W forecast against a 3000 W budget: the dryer goes first, which is enough
+        assert_eq!(forecaster.tick(&relays, |r| watts[&r.id], Some(3000.0), false), (vec!["r_dryer".to_string()], vec![]));
+        relays[2].is_closed = false;
+        assert_eq!(forecaster.tick(&relays, |r| watts[&r.id], Some(3000.0), false), (vec![], vec![]));
+        // It comes back only with headroom to spare, and the fridge is never shed
+        assert_eq!(forecaster.tick(&relays, |r| watts[&r.id], Some(4800.0), false), (vec![], vec![]));
+        assert_eq!(forecaster.tick(&relays, |r| watts[&r.id], Some(1000.0), false), (vec!["r_hvac".to_string()], vec![]));
+        relays[1].is_closed = false;
+        assert_eq!(forecaster.shed(), ["r_dryer".to_string(), "r_hvac".to_string()]);
+        assert_eq!(forecaster.tick(&relays, |r| watts[&r.id], Some(3500.0), false), (vec![], vec!["r_hvac".to_string()]));
+        assert_eq!(forecaster.tick(&relays, |r| watts[&r.id], None, true), (vec![], vec!["r_dryer".to_string()]));
+    }
+}
@@ -0,0 +1,239 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use crate::hal::generator::GeneratorControl;
+
+/// Storage key of the generator's accumulated run time
+pub const GENERATOR_HOURS_KEY: &str = "generator_hours.json";
+
+/// How often the generator sequence advances
+pub const GENERATOR_TICK_SECS: u64 = 1;
+
+/// When the generator runs and how it is started and stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorSettings {
+    /// Longest the engine may crank before the start counts as failed
+    pub crank: Duration,
+    /// Running unloaded before its output is watched for stability
+    pub warmup: Duration,
+    /// Output continuously good for this long before the load is transferred
+    pub stabilize: Duration,
+    /// Running unloaded after the load comes off, before it is stopped
+    pub cooldown: Duration,
+    /// While islanded, start at or below this battery charge and stop above `stop_soc`.
+    /// Without a battery gauge the generator runs whenever we are islanded.
+    pub start_soc: f32,
+    pub stop_soc: f32,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        Self {
+            crank: Duration::from_secs(15),
+            warmup: Duration::from_secs(60),
+            stabilize: Duration::from_secs(10),
+            cooldown: Duration::from_secs(120),
+            start_soc: 0.3,
+            stop_soc: 0.8,
+        }
+    }
+}
+
+/// Where the generator is in its start/stop sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GeneratorState {
+    Off,
+    Cranking,
+    WarmingUp,
+    /// Waiting for the output to hold steady
+    Stabilizing,
+    /// Carrying the load through the transfer relay
+    Transferred,
+    CoolingDown,
+    /// Failed to start or lost its output; stays stopped until no longer wanted
+    Failed,
+}
+
+/// Accumulated run time, persisted for maintenance intervals.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RunHours {
+    pub hours: f64,
+}
+
+/// A backup generator feeding the house through `transfer_relay`. The relay may only
+/// be closed while the sequence is `Transferred`; the node keeps it so.
+pub struct Generator {
+    pub transfer_relay: String,
+    settings: GeneratorSettings,
+    control: Box<dyn GeneratorControl>,
+    state: GeneratorState,
+    /// When the current state began, or the output was last seen bad while stabilizing
+    since: Option<Instant>,
+    last_tick: Option<Instant>,
+    run_hours: f64,
+}
+
+impl Generator {
+    pub fn new(transfer_relay: &str, settings: GeneratorSettings, control: Box<dyn GeneratorControl>, run_hours: f64) -> Self {
+        Self {
+            transfer_relay: transfer_relay.to_string(),
+            settings,
+            control,
+            state: GeneratorState::Off,
+            since: None,
+            last_tick: None,
+            run_hours,
+        }
+    }
+
+    pub fn state(&self) -> GeneratorState {
+        self.state
+    }
+
+    pub fn run_hours(&self) -> f64 {
+        self.run_hours
+    }
+
+    /// Whether the engine is turning, for run-hour tracking.
+    fn engine_running(&self) -> bool {
+        matches!(self.state, GeneratorState::WarmingUp | GeneratorState::Stabilizing | GeneratorState::Transferred | GeneratorState::CoolingDown)
+    }
+
+    /// Whether to run now: only off the grid, and then by battery charge with hysteresis.
+    fn wanted(&self, off_grid: bool, soc: Option<f32>) -> bool {
+        match soc {
+            _ if !off_grid => false,
+            None => true,
+            Some(soc) if self.state == GeneratorState::Off => soc <= self.settings.start_soc,
+            Some(soc) => soc < self.settings.stop_soc,
+        }
+    }
+
+    fn enter(&mut self, state: GeneratorState, now: Instant) {
+        info!("Generator {:?} -> {:?}", self.state, state);
+        self.state = state;
+        self.since = Some(now);
+    }
+
+    fn set_run(&mut self, run: bool) {
+        if let Err(e) = self.control.set_run(run) {
+            warn!("Failed to {} generator: {}", if run { "start" } else { "stop" }, e);
+        }
+    }
+
+    fn stop(&mut self, state: GeneratorState, now: Instant) {
+        self.set_run(false);
+        self.enter(state, now);
+        info!("Generator stopped; {:.1} run hours", self.run_hours);
+    }
+
+    /// Advance the sequence to `now`, off the grid or not, with the battery at `soc`
+    /// if there is a gauge.
+    pub fn tick(&mut self, now: Instant, off_grid: bool, soc: Option<f32>) {
+        if self.engine_running() {
+            let elapsed = self.last_tick.map_or(Duration::ZERO, |at| now.saturating_duration_since(at));
+            self.run_hours += elapsed.as_secs_f64() / 3600.0;
+        }
+        self.last_tick = Some(now);
+
+        let wanted = self.wanted(off_grid, soc);
+        let ok = self.control.output_ok().unwrap_or_else(|e| {
+            warn!("Generator output unknown: {}", e);
+            false
+        });
+        let elapsed = self.since.map_or(Duration::ZERO, |since| now.saturating_duration_since(since));
+        match self.state {
+            GeneratorState::Off if wanted => {
+                self.set_run(true);
+                self.enter(GeneratorState::Cranking, now);
+            }
+            GeneratorState::Off => {}
+            GeneratorState::Cranking | GeneratorState::WarmingUp | GeneratorState::Stabilizing if !wanted => {
+                // Never loaded, so no cooldown
+                self.stop(GeneratorState::Off, now);
+            }
+            GeneratorState::Cranking if ok => self.enter(GeneratorState::WarmingUp, now),
+            GeneratorState::Cranking if elapsed >= self.settings.crank => {
+                warn!("Generator did not start within {:?}", self.settings.crank);
+                self.stop(GeneratorState::Failed, now);
+            }
+            GeneratorState::WarmingUp if !ok => {
+                warn!("Generator stalled while warming up");
+                self.stop(GeneratorState::Failed, now);
+            }
+            GeneratorState::WarmingUp if elapsed >= self.settings.warmup => self.enter(GeneratorState::Stabilizing, now),
+            // Any dip starts the wait again
+            GeneratorState::Stabilizing if !ok => self.since = Some(now),
+            GeneratorState::Stabilizing if elapsed >= self.settings.stabilize => self.enter(GeneratorState::Transferred, now),
+            GeneratorState::Transferred if !ok => {
+                warn!("Generator output lost under load");
+                self.stop(GeneratorState::Failed, now);
+            }
+            GeneratorState::Transferred if !wanted => self.enter(GeneratorState::CoolingDown, now),
+            GeneratorState::CoolingDown if wanted && ok => self.enter(GeneratorState::Stabilizing, now),
+            GeneratorState::CoolingDown if !ok || elapsed >= self.settings.cooldown => self.stop(GeneratorState::Off, now),
+            GeneratorState::Failed if !wanted => self.enter(GeneratorState::Off, now),
+            _ => {}
+        }
+    }
+
+    /// The transfer relay would not close: wait for stable output again before retrying.
+    pub fn transfer_refused(&mut self, now: Instant) {
+        if self.state == GeneratorState::Transferred {
+            self.enter(GeneratorState::Stabilizing, now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::generator::mock::MockGenerator;
+
+    #[test]
+    fn test_start_transfer_cooldown_and_failure() {
+        let control = MockGenerator::new();
+        let mock = control.handle();
+        let mut generator = Generator::new("r_gen", GeneratorSettings::default(), Box::new(control), 10.0);
+        let start = Instant::now();
+        let run_until = |generator: &mut Generator, secs: u64, off_grid: bool, soc: Option<f32>| {
+            for s in 0..=secs {
+                generator.tick(start + Duration::from_secs(s), off_grid, soc);
+            }
+        };
+
+        // On the grid, or with charge to spare, it stays off
+        run_until(&mut generator, 5, false, None);
+        generator.tick(start + Duration::from_secs(5), true, Some(0.5));
+        assert_eq!(generator.state(), GeneratorState::Off);
+
+        // Battery low while islanded: crank, warm up a minute, hold steady 10 s, transfer
+        generator.tick(start + Duration::from_secs(6), true, Some(0.25));
+        assert_eq!((generator.state(), mock.lock().unwrap().running), (GeneratorState::Cranking, true));
+        generator.tick(start + Duration::from_secs(7), true, Some(0.25));
+        assert_eq!(generator.state(), GeneratorState::WarmingUp);
+        generator.tick(start + Duration::from_secs(67), true, Some(0.4));
+        assert_eq!(generator.state(), GeneratorState::Stabilizing);
+        generator.tick(start + Duration::from_secs(72), true, Some(0.4));
+        assert_eq!(generator.state(), GeneratorState::Stabilizing);
+        generator.tick(start + Duration::from_secs(77), true, Some(0.4));
+        assert_eq!(generator.state(), GeneratorState::Transferred);
+
+        // Charged: load off, two minutes unloaded, then stopped; its hours were counted
+        generator.tick(start + Duration::from_secs(3600), true, Some(0.85));
+        assert_eq!((generator.state(), mock.lock().unwrap().running), (GeneratorState::CoolingDown, true));
+        generator.tick(start + Duration::from_secs(3720), true, Some(0.85));
+        assert_eq!((generator.state(), mock.lock().unwrap().running), (GeneratorState::Off, false));
+        assert!((generator.run_hours() - 10.0 - (3720.0 - 7.0) / 3600.0).abs() < 1e-6);
+
+        // A generator that won't start gives up after cranking 15 s, until no longer wanted
+        mock.lock().unwrap().fails = true;
+        generator.tick(start + Duration::from_secs(4000), true, None);
+        generator.tick(start + Duration::from_secs(4015), true, None);
+        assert_eq!((generator.state(), mock.lock().unwrap().running), (GeneratorState::Failed, false));
+        generator.tick(start + Duration::from_secs(4100), true, None);
+        assert_eq!(generator.state(), GeneratorState::Failed);
+        generator.tick(start + Duration::from_secs(4101), false, None);
+        assert_eq!(generator.state(), GeneratorState::Off);
+    }
+}
@@ -0,0 +1,531 @@
+use super::{HalError, Result};
+use crate::harmonics::Waveform;
+
+/// Trait for power sensing abstraction.
+/// Allows mocking for non-Pi development and testing.
+pub trait PowerSensor: Send + Sync {
+    /// Read raw ADC value from a channel (0-3 for ADS1115).
+    fn read_raw(&mut self, channel: u8) -> Result<i16>;
+    
+    /// Read current in Amps from CT clamp.
+    fn read_current_amps(&mut self, channel: u8) -> Result<f32>;
+    
+    /// Read power in Watts (current × voltage reference).
+    fn read_watts(&mut self, channel: u8) -> Result<f32>;
+
+    /// RMS volts at a channel's input over several mains cycles, for a CT clamp whose
+    /// ratio and burden are its own rather than the sensor's.
+    fn read_input_rms(&mut self, channel: u8) -> Result<f32> {
+        let mut samples = Vec::with_capacity(PHASE_SAMPLES);
+        for _ in 0..PHASE_SAMPLES {
+            samples.push(self.read_raw(channel)? as f32 / 32768.0 * FULL_SCALE_VOLTS);
+        }
+        Ok(rms(&samples))
+    }
+
+    /// Which way power flows through a channel's CT: 1.0 imported, -1.0 exported.
+    /// Without a voltage reference the direction can't be told, and it reads as import.
+    fn read_power_sign(&mut self, _channel: u8) -> Result<f32> {
+        Ok(1.0)
+    }
+
+    /// Read the RMS mains voltage on a channel sampling it through a transformer, where
+    /// the hardware is calibrated to.
+    fn read_voltage(&mut self, _channel: u8) -> Result<f32> {
+        Err(HalError::Unsupported("mains voltage measurement"))
+    }
+
+    /// Real, reactive and apparent power on a channel, from voltage sampled alongside
+    /// its current; real power is negative while exporting.
+    fn read_power_quality(&mut self, _channel: u8) -> Result<PowerQuality> {
+        Err(HalError::Unsupported("voltage sampling alongside current"))
+    }
+
+    /// Sample the mains voltage waveform for its harmonics, where a voltage channel is fitted.
+    fn sample_voltage_waveform(&mut self) -> Result<Waveform> {
+        Err(HalError::Unsupported("voltage waveform sampling"))
+    }
+
+    /// Sample the current waveform through a CT, in amps, for signs of arcing.
+    fn sample_current_waveform(&mut self, _channel: u8) -> Result<Waveform> {
+        Err(HalError::Unsupported("current waveform sampling"))
+    }
+
+    /// Read the grid frequency in Hz, where the hardware measures it.
+    fn read_frequency_hz(&mut self) -> Result<f32> {
+        Err(HalError::Unsupported("grid frequency measurement"))
+    }
+}
+
+/// ADC configuration
+#[derive(Debug, Clone)]
+pub struct AdcConfig {
+    pub i2c_bus: u8,
+    pub address: u8,
+    pub ct_ratio: f32,      // e.g., 100.0 for 100A:50mA CT
+    pub voltage_ref: f32,   // Reference voltage for power calculation (e.g., 120.0V)
+    pub burden_resistor: f32, // Burden resistor value in ohms
+    /// Channel sampling the mains voltage (through a transformer), for the direction of power
+    pub voltage_channel: Option<u8>,
+    /// Mains volts per volt at the ADC input of a voltage channel, through its transformer
+    /// and divider; without it voltages aren't measured
+    pub voltage_ratio: Option<f32>,
+}
+
+impl Default for AdcConfig {
+    fn default() -> Self {
+        Self {
+            i2c_bus: 1,
+            address: 0x48, // Default ADS1115 address
+            ct_ratio: 100.0,
+            voltage_ref: 120.0,
+            burden_resistor: 33.0, // Common value for 100A CT
+            voltage_channel: None,
+            voltage_ratio: None,
+        }
+    }
+}
+
+/// Input range the ADC is set to, in volts either side of ground
+pub const FULL_SCALE_VOLTS: f32 = 4.096;
+
+/// Voltage and current sample pairs taken over several mains cycles for the direction of power
+pub const PHASE_SAMPLES: usize = 64;
+
+/// Samples of a waveform taken for its harmonics or signs of arcing
+pub const WAVEFORM_SAMPLES: usize = 256;
+
+/// Direction of power from simultaneous (voltage, current) samples: the mean of their
+/// product is real power, positive while it flows in. 1.0 for import, -1.0 for export.
+pub fn power_sign(pairs: &[(f32, f32)]) -> f32 {
+    let n = pairs.len().max(1) as f32;
+    let mean_v = pairs.iter().map(|(v, _)| v).sum::<f32>() / n;
+    let mean_i = pairs.iter().map(|(_, i)| i).sum::<f32>() / n;
+    let real: f32 = pairs.iter().map(|(v, i)| (v - mean_v) * (i - mean_i)).sum();
+    if real < 0.0 { -1.0 } else { 1.0 }
+}
+
+/// Power on a circuit, split into what does work and what only circulates.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PowerQuality {
+    /// Negative while exporting
+    pub real_watts: f32,
+    pub reactive_var: f32,
+    pub apparent_va: f32,
+    /// Share of the apparent power that is real, from 0 to 1
+    pub power_factor: f32,
+}
+
+/// Power from simultaneous (volts, amps) samples over whole mains cycles: real power is
+/// the mean of their product, apparent power the product of their RMS values, and
+/// reactive power what makes up the difference.
+pub fn power_quality(pairs: &[(f32, f32)]) -> PowerQuality {
+    let n = pairs.len().max(1) as f32;
+    let mean_v = pairs.iter().map(|(v, _)| v).sum::<f32>() / n;
+    let mean_i = pairs.iter().map(|(_, i)| i).sum::<f32>() / n;
+    let real = pairs.iter().map(|(v, i)| (v - mean_v) * (i - mean_i)).sum::<f32>() / n;
+    let volts: Vec<f32> = pairs.iter().map(|(v, _)| *v).collect();
+    let amps: Vec<f32> = pairs.iter().map(|(_, i)| *i).collect();
+    let apparent = rms(&volts) * rms(&amps);
+    PowerQuality {
+        real_watts: real,
+        reactive_var: (apparent * apparent - real * real).max(0.0).sqrt(),
+        apparent_va: apparent,
+        power_factor: if apparent > 0.0 { (real.abs() / apparent).min(1.0) } else { 1.0 },
+    }
+}
+
+/// RMS of a sampled waveform about its mean, which strips the bias single-ended readings sit on.
+pub fn rms(samples: &[f32]) -> f32 {
+    let n = samples.len().max(1) as f32;
+    let mean = samples.iter().sum::<f32>() / n;
+    (samples.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / n).sqrt()
+}
+
+// ============================================================================
+// Real Raspberry Pi Implementation (only compiled on ARM)
+// ============================================================================
+
+#[cfg(any())]
+pub mod rpi {
+    use super::*;
+    use ads1x1x::{Ads1x1x, ChannelSelection, DynamicOneShot, FullScaleRange, SlaveAddr};
+    use linux_embedded_hal::I2cdev;
+    
+    pub struct Ads1115Sensor {
+        adc: Ads1x1x<I2cdev, ads1x1x::ic::Ads1115, ads1x1x::ic::Resolution16Bit, DynamicOneShot>,
+        config: AdcConfig,
+    }
+    
+    impl Ads1115Sensor {
+        pub fn new(config: AdcConfig) -> Result<Self> {
+            let device = format!("/dev/i2c-{}", config.i2c_bus);
+            let i2c = I2cdev::new(&device).map_err(|e| HalError::Open { device, reason: e.to_string() })?;
+            // The ADDR pin picks one of four addresses, so up to four share a bus
+            let address = match config.address {
+                0x48 => SlaveAddr::new_gnd(),
+                0x49 => SlaveAddr::new_vdd(),
+                0x4A => SlaveAddr::new_sda(),
+                0x4B => SlaveAddr::new_scl(),
+                other => return Err(HalError::NotConfigured(format!("ADS1115 can't be at address {:#04x}", other))),
+            };
+            let mut adc = Ads1x1x::new_ads1115(i2c, address);
+            
+            // Set gain for ±4.096V range (good for CT clamp readings)
+            adc.set_full_scale_range(FullScaleRange::Within4_096V)
+                .map_err(|e| HalError::Bus(format!("Failed to set ADC range: {:?}", e)))?;
+            // As fast as it converts, so a waveform's harmonics are sampled too
+            adc.set_data_rate(ads1x1x::DataRate16Bit::Sps860)
+                .map_err(|e| HalError::Bus(format!("Failed to set ADC data rate: {:?}", e)))?;
+            
+            Ok(Self { adc, config })
+        }
+        
+        fn channel_selection(channel: u8) -> ChannelSelection {
+            match channel {
+                0 => ChannelSelection::SingleA0,
+                1 => ChannelSelection::SingleA1,
+                2 => ChannelSelection::SingleA2,
+                3 => ChannelSelection::SingleA3,
+                _ => ChannelSelection::SingleA0,
+            }
+        }
+
+        fn sample_waveform(&mut self, channel: u8) -> Result<Waveform> {
+            let started = std::time::Instant::now();
+            let mut samples = Vec::with_capacity(WAVEFORM_SAMPLES);
+            for _ in 0..WAVEFORM_SAMPLES {
+                samples.push(self.read_raw(channel)? as f32);
+            }
+            // One-shot conversions over I2C come at whatever rate the bus allows
+            let sample_rate_hz = WAVEFORM_SAMPLES as f32 / started.elapsed().as_secs_f32();
+            Ok(Waveform { samples, sample_rate_hz })
+        }
+    }
+    
+    impl PowerSensor for Ads1115Sensor {
+        fn read_raw(&mut self, channel: u8) -> Result<i16> {
+            let ch = Self::channel_selection(channel);
+            self.adc.read(ch)
+                .map_err(|e| HalError::Bus(format!("ADC read error: {:?}", e)))
+        }
+        
+        fn read_current_amps(&mut self, channel: u8) -> Result<f32> {
+            let raw = self.read_raw(channel)?;
+            
+            // Convert raw ADC to voltage (±4.096V range, 16-bit signed)
+            let voltage = (raw as f32 / 32768.0) * 4.096;
+            
+            // V = I_secondary × R_burden
+            // I_secondary = V / R_burden
+            // I_primary = I_secondary × CT_ratio
+            let secondary_current = voltage / self.config.burden_resistor;
+            let primary_current = secondary_current * self.config.ct_ratio;
+            
+            Ok(primary_current.abs())
+        }
+        
+        fn read_watts(&mut self, channel: u8) -> Result<f32> {
+            let amps = self.read_current_amps(channel)?;
+            Ok(amps * self.config.voltage_ref)
+        }
+
+        fn read_power_sign(&mut self, channel: u8) -> Result<f32> {
+            let Some(reference) = self.config.voltage_channel else { return Ok(1.0) };
+            let mut pairs = Vec::with_capacity(PHASE_SAMPLES);
+            for _ in 0..PHASE_SAMPLES {
+                pairs.push((self.read_raw(reference)? as f32, self.read_raw(channel)? as f32));
+            }
+            Ok(power_sign(&pairs))
+        }
+
+        fn read_voltage(&mut self, channel: u8) -> Result<f32> {
+            let Some(ratio) = self.config.voltage_ratio else {
+                return Err(HalError::NotConfigured(format!("no voltage_ratio configured for channel {}", channel)))
+            };
+            let mut samples = Vec::with_capacity(PHASE_SAMPLES);
+            for _ in 0..PHASE_SAMPLES {
+                samples.push(self.read_raw(channel)? as f32 / 32768.0 * 4.096);
+            }
+            Ok(rms(&samples) * ratio)
+        }
+
+        fn read_power_quality(&mut self, channel: u8) -> Result<PowerQuality> {
+            let Some(reference) = self.config.voltage_channel else {
+                return Err(HalError::NotConfigured(format!("no voltage channel to sample alongside channel {}", channel)))
+            };
+            let to_volts = |raw: i16| raw as f32 / 32768.0 * 4.096;
+            let mut pairs = Vec::with_capacity(PHASE_SAMPLES);
+            for _ in 0..PHASE_SAMPLES {
+                let v = to_volts(self.read_raw(reference)?);
+                let i = to_volts(self.read_raw(channel)?) / self.config.burden_resistor * self.config.ct_ratio;
+                pairs.push((v, i));
+            }
+            // Uncalibrated, the mains is taken to be at the reference voltage; the power factor holds either way
+            let v_rms = rms(&pairs.iter().map(|(v, _)| *v).collect::<Vec<_>>());
+            let scale = match self.config.voltage_ratio {
+                Some(ratio) => ratio,
+                None if v_rms > 0.0 => self.config.voltage_ref / v_rms,
+                None => return Err(HalError::NoData(format!("no signal on voltage channel {}", reference))),
+            };
+            Ok(power_quality(&pairs.iter().map(|(v, i)| (v * scale, *i)).collect::<Vec<_>>()))
+        }
+
+        fn sample_voltage_waveform(&mut self) -> Result<Waveform> {
+            let Some(channel) = self.config.voltage_channel else {
+                return Err(HalError::NotConfigured("no voltage channel to sample".to_string()))
+            };
+            self.sample_waveform(channel)
+        }
+
+        fn sample_current_waveform(&mut self, channel: u8) -> Result<Waveform> {
+            let mut waveform = self.sample_waveform(channel)?;
+            let amps_per_count = 4.096 / 32768.0 / self.config.burden_resistor * self.config.ct_ratio;
+            for sample in &mut waveform.samples {
+                *sample *= amps_per_count;
+            }
+            Ok(waveform)
+        }
+    }
+}
+
+// ============================================================================
+// Mock Implementation (for development and testing on non-Pi platforms)
+// ============================================================================
+
+pub mod mock {
+    use super::*;
+    use log::debug;
+    
+    pub struct MockAdcSensor {
+        config: AdcConfig,
+        /// Simulated current values per channel (in Amps)
+        simulated_amps: [f32; 4],
+        /// Simulated RMS voltage per channel; unset channels read the reference voltage
+        simulated_volts: [Option<f32>; 4],
+        /// Simulated power factor per channel
+        simulated_power_factor: [f32; 4],
+        /// Third harmonic on the simulated mains voltage, as a share of the fundamental
+        simulated_voltage_thd: f32,
+        /// Channels whose simulated current is arcing
+        simulated_arcing: [bool; 4],
+    }
+    
+    impl MockAdcSensor {
+        pub fn new(config: AdcConfig) -> Result<Self> {
+            Ok(Self {
+                config,
+                simulated_amps: [0.0, 0.0, 0.0, 0.0],
+                simulated_volts: [None; 4],
+                simulated_power_factor: [1.0; 4],
+                simulated_voltage_thd: 0.0,
+                simulated_arcing: [false; 4],
+            })
+        }
+        
+        /// Set simulated current for testing; negative for power flowing out
+        pub fn set_simulated_current(&mut self, channel: u8, amps: f32) {
+            if (channel as usize) < self.simulated_amps.len() {
+                self.simulated_amps[channel as usize] = amps;
+            }
+        }
+
+        /// Set the simulated power factor of the load on a channel
+        pub fn set_simulated_power_factor(&mut self, channel: u8, power_factor: f32) {
+            if (channel as usize) < self.simulated_power_factor.len() {
+                self.simulated_power_factor[channel as usize] = power_factor;
+            }
+        }
+
+        /// Distort the simulated mains voltage with a third harmonic of `thd` times the fundamental
+        pub fn set_simulated_voltage_thd(&mut self, thd: f32) {
+            self.simulated_voltage_thd = thd;
+        }
+
+        /// Make the simulated current on a channel arc, striking and going out at random
+        pub fn set_simulated_arcing(&mut self, channel: u8, arcing: bool) {
+            if (channel as usize) < self.simulated_arcing.len() {
+                self.simulated_arcing[channel as usize] = arcing;
+            }
+        }
+
+        /// Set the simulated RMS voltage on a voltage channel
+        pub fn set_simulated_voltage(&mut self, channel: u8, volts: f32) {
+            if (channel as usize) < self.simulated_volts.len() {
+                self.simulated_volts[channel as usize] = Some(volts);
+            }
+        }
+    }
+    
+    impl PowerSensor for MockAdcSensor {
+        fn read_raw(&mut self, channel: u8) -> Result<i16> {
+            // Simulate raw ADC value from current
+            let amps = self.simulated_amps.get(channel as usize).copied().unwrap_or(0.0);
+            let secondary_current = amps / self.config.ct_ratio;
+            let voltage = secondary_current * self.config.burden_resistor;
+            let raw = ((voltage / 4.096) * 32768.0) as i16;
+            debug!("[MOCK ADC] Channel {} → raw {}", channel, raw);
+            Ok(raw)
+        }
+        
+        fn read_current_amps(&mut self, channel: u8) -> Result<f32> {
+            let amps = self.simulated_amps.get(channel as usize).copied().unwrap_or(0.0);
+            debug!("[MOCK ADC] Channel {} → {} A", channel, amps);
+            Ok(amps.abs())
+        }
+        
+        fn read_watts(&mut self, channel: u8) -> Result<f32> {
+            let amps = self.read_current_amps(channel)?;
+            let watts = amps * self.config.voltage_ref;
+            debug!("[MOCK ADC] Channel {} → {} W", channel, watts);
+            Ok(watts)
+        }
+
+        fn read_power_sign(&mut self, channel: u8) -> Result<f32> {
+            let amps = self.simulated_amps.get(channel as usize).copied().unwrap_or(0.0);
+            Ok(if amps < 0.0 { -1.0 } else { 1.0 })
+        }
+
+        fn read_input_rms(&mut self, channel: u8) -> Result<f32> {
+            let amps = self.simulated_amps.get(channel as usize).copied().unwrap_or(0.0);
+            Ok(amps.abs() / self.config.ct_ratio * self.config.burden_resistor)
+        }
+
+        fn read_voltage(&mut self, channel: u8) -> Result<f32> {
+            let volts = self.simulated_volts.get(channel as usize).copied().flatten();
+            Ok(volts.unwrap_or(self.config.voltage_ref))
+        }
+
+        fn read_power_quality(&mut self, channel: u8) -> Result<PowerQuality> {
+            let amps = self.simulated_amps.get(channel as usize).copied().unwrap_or(0.0);
+            let power_factor = self.simulated_power_factor.get(channel as usize).copied().unwrap_or(1.0);
+            let apparent = amps.abs() * self.config.voltage_ref;
+            Ok(PowerQuality {
+                real_watts: apparent * power_factor * amps.signum(),
+                reactive_var: apparent * (1.0 - power_factor * power_factor).max(0.0).sqrt(),
+                apparent_va: apparent,
+                power_factor,
+            })
+        }
+
+        fn sample_voltage_waveform(&mut self) -> Result<Waveform> {
+            // A 60 Hz mains at 16 samples a cycle, about as fast as an ADS1115 converts
+            let sample_rate_hz = 960.0;
+            let peak = self.config.voltage_ref * std::f32::consts::SQRT_2;
+            let samples = (0..WAVEFORM_SAMPLES)
+                .map(|n| {
+                    let angle = std::f32::consts::TAU * 60.0 * n as f32 / sample_rate_hz;
+                    peak * (angle.sin() + self.simulated_voltage_thd * (3.0 * angle).sin())
+                })
+                .collect();
+            Ok(Waveform { samples, sample_rate_hz })
+        }
+
+        fn sample_current_waveform(&mut self, channel: u8) -> Result<Waveform> {
+            let sample_rate_hz = 960.0;
+            let peak = self.simulated_amps.get(channel as usize).copied().unwrap_or(0.0) * std::f32::consts::SQRT_2;
+            let arcing = self.simulated_arcing.get(channel as usize).copied().unwrap_or(false);
+            let mut seed = channel as u32 + 1;
+            let samples = (0..WAVEFORM_SAMPLES)
+                .map(|n| {
+                    let steady = peak * (std::f32::consts::TAU * 60.0 * n as f32 / sample_rate_hz).sin();
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    let random = (seed >> 16) as f32 / 65_536.0;
+                    if arcing && random < 0.4 { steady * random * 2.0 } else { steady }
+                })
+                .collect();
+            Ok(Waveform { samples, sample_rate_hz })
+        }
+    }
+}
+
+// ============================================================================
+// Factory function to create appropriate sensor
+// ============================================================================
+
+#[cfg(any())]
+pub fn create_power_sensor(config: AdcConfig) -> Result<Box<dyn PowerSensor>> {
+    Ok(Box::new(rpi::Ads1115Sensor::new(config)?))
+}
+
+#[cfg(not(any()))]
+pub fn create_power_sensor(config: AdcConfig) -> Result<Box<dyn PowerSensor>> {
+    log::warn!("Using MOCK ADC sensor (not on Raspberry Pi)");
+    Ok(Box::new(mock::MockAdcSensor::new(config)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_mock_adc_current_reading() {
+        let config = AdcConfig::default();
+        let mut sensor = mock::MockAdcSensor::new(config).unwrap();
+        
+        sensor.set_simulated_current(0, 15.0); // 15 Amps
+        
+        let amps = sensor.read_current_amps(0).unwrap();
+        assert!((amps - 15.0).abs() < 0.01);
+    }
+    
+    #[test]
+    fn test_mock_adc_power_reading() {
+        let config = AdcConfig {
+            voltage_ref: 120.0,
+            ..Default::default()
+        };
+        let mut sensor = mock::MockAdcSensor::new(config).unwrap();
+        
+        sensor.set_simulated_current(0, 10.0); // 10 Amps
+        
+        let watts = sensor.read_watts(0).unwrap();
+        assert!((watts - 1200.0).abs() < 0.01); // 10A × 120V = 1200W
+    }
+
+    #[test]
+    fn test_power_sign_follows_current_against_voltage() {
+        let cycle = |shift: f32| -> Vec<(f32, f32)> {
+            (0..PHASE_SAMPLES)
+                .map(|n| {
+                    let angle = n as f32 * std::f32::consts::TAU / 16.0;
+                    // Single-ended readings sit on a bias
+                    (1000.0 + 800.0 * angle.sin(), 500.0 + 300.0 * (angle + shift).sin())
+                })
+                .collect()
+        };
+        // In phase, or lagging like a motor load: import
+        assert_eq!(power_sign(&cycle(0.0)), 1.0);
+        assert_eq!(power_sign(&cycle(-0.6)), 1.0);
+        // Half a cycle out: a solar inverter pushing power back
+        assert_eq!(power_sign(&cycle(std::f32::consts::PI)), -1.0);
+    }
+
+    #[test]
+    fn test_lagging_current_lowers_the_power_factor() {
+        let cycle = |shift: f32| -> Vec<(f32, f32)> {
+            (0..PHASE_SAMPLES)
+                .map(|n| {
+                    let angle = n as f32 * std::f32::consts::TAU / 16.0;
+                    (230.0 * std::f32::consts::SQRT_2 * angle.sin(), 10.0 * std::f32::consts::SQRT_2 * (angle - shift).sin())
+                })
+                .collect()
+        };
+        let resistive = power_quality(&cycle(0.0));
+        assert!((resistive.real_watts - 2300.0).abs() < 1.0);
+        assert!((resistive.power_factor - 1.0).abs() < 1e-3);
+        // A motor with the current 60 degrees behind: half the 2300 VA does work
+        let motor = power_quality(&cycle(std::f32::consts::FRAC_PI_3));
+        assert!((motor.apparent_va - 2300.0).abs() < 1.0);
+        assert!((motor.power_factor - 0.5).abs() < 1e-3);
+        assert!((motor.reactive_var - 2300.0 * 0.866).abs() < 2.0);
+    }
+
+    #[test]
+    fn test_rms_of_a_biased_sine() {
+        let samples: Vec<f32> = (0..PHASE_SAMPLES)
+            .map(|n| 1.65 + 1.0 * (n as f32 * std::f32::consts::TAU / 16.0).sin())
+            .collect();
+        assert!((rms(&samples) - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-3);
+    }
+}
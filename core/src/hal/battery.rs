@@ -0,0 +1,131 @@
+use super::Result;
+use std::time::Duration;
+
+/// What the battery's management system reports, as far as the gauge knows it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BatteryStatus {
+    /// 0.0 (empty) to 1.0 (full)
+    pub soc: f32,
+    /// Pack voltage
+    pub voltage: Option<f32>,
+    /// Pack current, positive while charging
+    pub current: Option<f32>,
+    /// Most current the pack may take or give now
+    pub max_charge_amps: Option<f32>,
+    pub max_discharge_amps: Option<f32>,
+}
+
+impl BatteryStatus {
+    /// Most power the pack may give now, when both its voltage and limit are known.
+    pub fn max_discharge_watts(&self) -> Option<f32> {
+        Some(self.voltage? * self.max_discharge_amps?)
+    }
+}
+
+/// State of charge of the household battery.
+pub trait BatteryGauge: Send + Sync {
+    /// 0.0 (empty) to 1.0 (full).
+    fn state_of_charge(&mut self) -> Result<f32>;
+
+    /// Charge and, from a management system, the pack's electrical state and limits.
+    fn status(&mut self) -> Result<BatteryStatus> {
+        Ok(BatteryStatus { soc: self.state_of_charge()?, ..Default::default() })
+    }
+
+    /// What the household's loads drew from the battery and its panels made over the
+    /// last `elapsed`. A gauge on the battery's management bus measures this itself.
+    fn account(&mut self, _load_watts: f32, _solar_watts: f32, _elapsed: Duration) {}
+}
+
+/// Battery configuration
+#[derive(Debug, Clone)]
+pub struct BatteryConfig {
+    /// Usable capacity
+    pub capacity_wh: f32,
+    /// State of charge at boot
+    pub initial_soc: f32,
+    /// Share of the energy put in that can be drawn out again
+    pub charge_efficiency: f32,
+    /// Share of the energy drawn that reaches the loads (inverter losses)
+    pub discharge_efficiency: f32,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            capacity_wh: 10_000.0,
+            initial_soc: 1.0,
+            charge_efficiency: 0.95,
+            discharge_efficiency: 0.95,
+        }
+    }
+}
+
+// ============================================================================
+// Mock Implementation (for development and simulation)
+// ============================================================================
+
+pub mod mock {
+    use super::*;
+    use log::debug;
+
+    /// A battery whose charge follows the load it feeds and the solar it stores, for
+    /// mock runs and scenarios. Solar covers the load first; the surplus charges it,
+    /// and a shortfall drains it, each with its losses.
+    pub struct SimBattery {
+        config: BatteryConfig,
+        soc: f32,
+    }
+
+    impl SimBattery {
+        pub fn new(config: BatteryConfig) -> Self {
+            let soc = config.initial_soc.clamp(0.0, 1.0);
+            Self { config, soc }
+        }
+    }
+
+    impl BatteryGauge for SimBattery {
+        fn state_of_charge(&mut self) -> Result<f32> {
+            Ok(self.soc)
+        }
+
+        fn account(&mut self, load_watts: f32, solar_watts: f32, elapsed: Duration) {
+            if self.config.capacity_wh <= 0.0 {
+                return;
+            }
+            let net_watts = solar_watts.max(0.0) - load_watts.max(0.0);
+            let stored_watts = if net_watts >= 0.0 {
+                net_watts * self.config.charge_efficiency
+            } else {
+                net_watts / self.config.discharge_efficiency.max(0.01)
+            };
+            let wh = stored_watts * elapsed.as_secs_f32() / 3600.0;
+            self.soc = (self.soc + wh / self.config.capacity_wh).clamp(0.0, 1.0);
+            debug!("[MOCK battery] {:+.0} W net → SOC {:.1}%", net_watts, self.soc * 100.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::mock::SimBattery;
+
+    #[test]
+    fn test_sim_battery_drains_under_load_and_charges_from_solar() {
+        let config = BatteryConfig { capacity_wh: 1000.0, initial_soc: 0.5, charge_efficiency: 0.9, discharge_efficiency: 0.8 };
+        let mut battery = SimBattery::new(config);
+        // 400 W for an hour, with losses: 500 Wh out of the battery
+        battery.account(400.0, 0.0, Duration::from_secs(3600));
+        assert!(battery.state_of_charge().unwrap().abs() < 1e-4);
+        // Empty stays empty
+        battery.account(400.0, 0.0, Duration::from_secs(60));
+        assert_eq!(battery.state_of_charge().unwrap(), 0.0);
+
+        // Solar covers a 200 W load and stores 90% of the 1 kW left over
+        battery.account(200.0, 1200.0, Duration::from_secs(1800));
+        assert!((battery.state_of_charge().unwrap() - 0.45).abs() < 1e-4);
+        battery.account(0.0, 5000.0, Duration::from_secs(3600));
+        assert_eq!(battery.state_of_charge().unwrap(), 1.0);
+    }
+}
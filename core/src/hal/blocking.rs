@@ -0,0 +1,117 @@
+use super::Result;
+use std::sync::{Arc, Mutex};
+use crate::harmonics::Waveform;
+use super::adc::PowerQuality;
+use super::{HalError, PowerSensor, RelayControl};
+
+/// Run a driver call on tokio's blocking pool, so a slow I2C or SPI transfer holds up
+/// a pool thread rather than the node's event loop.
+pub(crate) async fn off_loop<D: ?Sized + Send + 'static, T: Send + 'static>(
+    driver: &Arc<Mutex<Box<D>>>,
+    call: impl FnOnce(&mut D) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let driver = driver.clone();
+    tokio::task::spawn_blocking(move || call(driver.lock().unwrap().as_mut()))
+        .await
+        .map_err(|e| HalError::Interrupted(e.to_string()))?
+}
+
+/// Async access to a blocking power sensor; clones share the one device.
+#[derive(Clone)]
+pub struct AsyncPowerSensor {
+    inner: Arc<Mutex<Box<dyn PowerSensor>>>,
+}
+
+impl AsyncPowerSensor {
+    pub fn new(sensor: Box<dyn PowerSensor>) -> Self {
+        Self { inner: Arc::new(Mutex::new(sensor)) }
+    }
+
+    /// Run several reads in one trip to the blocking pool.
+    pub async fn read<T: Send + 'static>(&self, read: impl FnOnce(&mut dyn PowerSensor) -> Result<T> + Send + 'static) -> Result<T> {
+        off_loop(&self.inner, move |s| read(s)).await
+    }
+
+    pub async fn read_current_amps(&self, channel: u8) -> Result<f32> {
+        self.read(move |s| s.read_current_amps(channel)).await
+    }
+
+    pub async fn read_watts(&self, channel: u8) -> Result<f32> {
+        self.read(move |s| s.read_watts(channel)).await
+    }
+
+    pub async fn read_power_sign(&self, channel: u8) -> Result<f32> {
+        self.read(move |s| s.read_power_sign(channel)).await
+    }
+
+    pub async fn read_voltage(&self, channel: u8) -> Result<f32> {
+        self.read(move |s| s.read_voltage(channel)).await
+    }
+
+    pub async fn read_power_quality(&self, channel: u8) -> Result<PowerQuality> {
+        self.read(move |s| s.read_power_quality(channel)).await
+    }
+
+    pub async fn sample_voltage_waveform(&self) -> Result<Waveform> {
+        self.read(|s| s.sample_voltage_waveform()).await
+    }
+
+    pub async fn sample_current_waveform(&self, channel: u8) -> Result<Waveform> {
+        self.read(move |s| s.sample_current_waveform(channel)).await
+    }
+
+    pub async fn read_frequency_hz(&self) -> Result<f32> {
+        self.read(|s| s.read_frequency_hz()).await
+    }
+}
+
+/// Async access to a blocking relay driver; clones share the one driver.
+#[derive(Clone)]
+pub struct AsyncRelayControl {
+    inner: Arc<Mutex<Box<dyn RelayControl>>>,
+}
+
+impl AsyncRelayControl {
+    pub fn new(driver: Box<dyn RelayControl>) -> Self {
+        Self { inner: Arc::new(Mutex::new(driver)) }
+    }
+
+    /// The driver itself, for threads that switch relays outside the event loop (emergency stop).
+    pub fn shared(&self) -> Arc<Mutex<Box<dyn RelayControl>>> {
+        self.inner.clone()
+    }
+
+    pub async fn set_relay(&self, pin: u8, closed: bool) -> Result<()> {
+        off_loop(&self.inner, move |d| d.set_relay(pin, closed)).await
+    }
+
+    pub async fn get_relay(&self, pin: u8) -> Result<bool> {
+        off_loop(&self.inner, move |d| d.get_relay(pin)).await
+    }
+
+    pub async fn read_feedback(&self, pin: u8) -> Result<Option<bool>> {
+        off_loop(&self.inner, move |d| d.read_feedback(pin)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::adc::mock::MockAdcSensor;
+    use crate::hal::gpio::mock::MockRelayDriver;
+    use crate::hal::AdcConfig;
+
+    #[tokio::test]
+    async fn test_adapters_reach_the_shared_driver() {
+        let mut adc = MockAdcSensor::new(AdcConfig::default()).unwrap();
+        adc.set_simulated_current(1, 12.5);
+        let sensor = AsyncPowerSensor::new(Box::new(adc));
+        assert_eq!(sensor.clone().read_current_amps(1).await.unwrap(), 12.5);
+
+        let relays = AsyncRelayControl::new(Box::new(MockRelayDriver::new(&[]).unwrap()));
+        relays.set_relay(17, true).await.unwrap();
+        assert!(relays.shared().lock().unwrap().get_relay(17).unwrap());
+        relays.set_relay(17, false).await.unwrap();
+        assert!(!relays.get_relay(17).await.unwrap());
+    }
+}
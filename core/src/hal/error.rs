@@ -0,0 +1,72 @@
+use thiserror::Error;
+
+/// Why a hardware driver call failed, so callers can retry a busy radio or a glitching
+/// bus and give up on a pin that was never configured.
+#[derive(Debug, Error)]
+pub enum HalError {
+    /// The driver has no output or input on this pin
+    #[error("pin {0} not configured")]
+    PinNotConfigured(u8),
+    /// The hardware can't do this at all
+    #[error("{0} not supported")]
+    Unsupported(&'static str),
+    /// The hardware could, but its configuration leaves out what it needs
+    #[error("{0}")]
+    NotConfigured(String),
+    /// The device is in the middle of something else
+    #[error("{0} busy")]
+    Busy(&'static str),
+    /// The device is there but has nothing to report yet
+    #[error("{0}")]
+    NoData(String),
+    /// The device file or network endpoint could not be opened
+    #[error("cannot open {device}: {reason}")]
+    Open { device: String, reason: String },
+    /// A transfer on the GPIO, I2C, SPI or PWM peripheral failed
+    #[error("{0}")]
+    Bus(String),
+    /// The device answered with something we can't use
+    #[error("{0}")]
+    Protocol(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// The blocking pool dropped the call (a panicking driver, or the runtime shutting down)
+    #[error("driver call did not complete: {0}")]
+    Interrupted(String),
+}
+
+impl HalError {
+    /// Whether the same call may succeed if tried again shortly.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Busy(_) | Self::NoData(_) | Self::Bus(_) | Self::Io(_))
+    }
+}
+
+pub type Result<T, E = HalError> = std::result::Result<T, E>;
+
+impl From<serialport::Error> for HalError {
+    fn from(e: serialport::Error) -> Self {
+        Self::Bus(format!("serial: {}", e))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<rppal::gpio::Error> for HalError {
+    fn from(e: rppal::gpio::Error) -> Self {
+        Self::Bus(format!("GPIO: {}", e))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<rppal::pwm::Error> for HalError {
+    fn from(e: rppal::pwm::Error) -> Self {
+        Self::Bus(format!("PWM: {}", e))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<rppal::i2c::Error> for HalError {
+    fn from(e: rppal::i2c::Error) -> Self {
+        Self::Bus(format!("I2C: {}", e))
+    }
+}
@@ -0,0 +1,99 @@
+use super::Result;
+
+/// Trait for the emergency-stop input.
+/// Allows mocking for non-Pi development and testing.
+pub trait EmergencyStop: Send {
+    /// True while the stop is asserted.
+    fn is_asserted(&mut self) -> Result<bool>;
+}
+
+/// Emergency-stop input configuration
+#[derive(Debug, Clone)]
+pub struct EmergencyStopConfig {
+    pub gpio_pin: u8,
+    /// If true, the input reads LOW when asserted. The pin is pulled up, so with this
+    /// false a normally-closed button to ground also asserts the stop if its wire is cut.
+    pub active_low: bool,
+}
+
+// ============================================================================
+// Real Raspberry Pi Implementation (only compiled on ARM)
+// ============================================================================
+
+#[cfg(target_os = "linux")]
+pub mod rpi {
+    use super::*;
+    use rppal::gpio::{Gpio, InputPin};
+
+    pub struct RpiEmergencyStop {
+        pin: InputPin,
+        active_low: bool,
+    }
+
+    impl RpiEmergencyStop {
+        pub fn new(config: &EmergencyStopConfig) -> Result<Self> {
+            let pin = Gpio::new()?.get(config.gpio_pin)?.into_input_pullup();
+            Ok(Self { pin, active_low: config.active_low })
+        }
+    }
+
+    impl EmergencyStop for RpiEmergencyStop {
+        fn is_asserted(&mut self) -> Result<bool> {
+            let is_high = self.pin.is_high();
+            Ok(if self.active_low { !is_high } else { is_high })
+        }
+    }
+}
+
+// ============================================================================
+// Mock Implementation (for development and testing on non-Pi platforms)
+// ============================================================================
+
+pub mod mock {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// Stop whose button is pressed and released through a shared flag.
+    pub struct MockEmergencyStop {
+        asserted: Arc<AtomicBool>,
+    }
+
+    impl MockEmergencyStop {
+        pub fn new() -> Self {
+            Self { asserted: Arc::new(AtomicBool::new(false)) }
+        }
+
+        /// Handle for pressing and releasing the mock button.
+        pub fn handle(&self) -> Arc<AtomicBool> {
+            self.asserted.clone()
+        }
+    }
+
+    impl Default for MockEmergencyStop {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl EmergencyStop for MockEmergencyStop {
+        fn is_asserted(&mut self) -> Result<bool> {
+            Ok(self.asserted.load(Ordering::Relaxed))
+        }
+    }
+}
+
+// ============================================================================
+// Factory function to create appropriate input
+// ============================================================================
+
+#[cfg(target_os = "linux")]
+pub fn create_emergency_stop(config: &EmergencyStopConfig) -> Result<Box<dyn EmergencyStop>> {
+    Ok(Box::new(rpi::RpiEmergencyStop::new(config)?))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn create_emergency_stop(_config: &EmergencyStopConfig) -> Result<Box<dyn EmergencyStop>> {
+    log::warn!("Using MOCK emergency stop (not on Raspberry Pi)");
+    Ok(Box::new(mock::MockEmergencyStop::new()))
+}
@@ -0,0 +1,147 @@
+use super::{HalError, Result};
+
+/// Least current the J1772 pilot can offer; below it the car must stop charging.
+pub const MIN_PILOT_AMPS: f32 = 6.0;
+/// Most current the J1772 pilot can offer.
+pub const MAX_PILOT_AMPS: f32 = 80.0;
+
+/// Pilot PWM frequency fixed by J1772
+const PILOT_HZ: f64 = 1000.0;
+
+/// Trait for an EV charger (EVSE) whose charging current the node can cap.
+/// Allows mocking for non-Pi development and testing.
+pub trait ChargerControl: Send {
+    /// Offer the car at most `amps`; 0 pauses charging without opening the circuit.
+    fn set_current_limit(&mut self, amps: f32) -> Result<()>;
+}
+
+/// EV charger configuration
+#[derive(Debug, Clone)]
+pub struct EvseConfig {
+    /// Hardware PWM channel driving the pilot signal (0 = GPIO 18, 1 = GPIO 19)
+    pub pwm_channel: u8,
+}
+
+/// Pilot duty cycle (0..1) offering `amps`, per J1772: 10% per 6 A up to 51 A, then
+/// a steeper scale to 80 A. A constant high pilot (duty 1.0) allows no charging.
+pub fn pilot_duty(amps: f32) -> f32 {
+    if amps < MIN_PILOT_AMPS {
+        return 1.0;
+    }
+    let amps = amps.min(MAX_PILOT_AMPS);
+    if amps <= 51.0 {
+        amps / 60.0
+    } else {
+        amps / 250.0 + 0.64
+    }
+}
+
+// ============================================================================
+// Real Raspberry Pi Implementation (only compiled on ARM)
+// ============================================================================
+
+#[cfg(target_os = "linux")]
+pub mod rpi {
+    use super::*;
+    use log::info;
+    use rppal::pwm::{Channel, Polarity, Pwm};
+
+    /// Pilot generated on a hardware PWM pin and level-shifted to ±12 V by the
+    /// charger's interface board.
+    pub struct RpiChargerControl {
+        pwm: Pwm,
+    }
+
+    impl RpiChargerControl {
+        pub fn new(config: &EvseConfig) -> Result<Self> {
+            let channel = match config.pwm_channel {
+                0 => Channel::Pwm0,
+                1 => Channel::Pwm1,
+                other => return Err(HalError::NotConfigured(format!("no PWM channel {}", other))),
+            };
+            // Until told otherwise, no charging
+            let pwm = Pwm::with_frequency(channel, PILOT_HZ, 1.0, Polarity::Normal, true)?;
+            Ok(Self { pwm })
+        }
+    }
+
+    impl ChargerControl for RpiChargerControl {
+        fn set_current_limit(&mut self, amps: f32) -> Result<()> {
+            let duty = pilot_duty(amps);
+            info!("EV pilot at {:.1}% duty ({:.0} A)", duty * 100.0, amps);
+            self.pwm.set_duty_cycle(duty as f64)?;
+            Ok(())
+        }
+    }
+}
+
+// ============================================================================
+// Mock Implementation (for development and testing on non-Pi platforms)
+// ============================================================================
+
+pub mod mock {
+    use super::*;
+    use log::info;
+    use std::sync::{Arc, Mutex};
+
+    /// Charger whose offered current is readable through a shared handle.
+    pub struct MockChargerControl {
+        limit: Arc<Mutex<Option<f32>>>,
+    }
+
+    impl MockChargerControl {
+        pub fn new() -> Self {
+            Self { limit: Arc::new(Mutex::new(None)) }
+        }
+
+        /// Handle on the last limit set; None until one is.
+        pub fn handle(&self) -> Arc<Mutex<Option<f32>>> {
+            self.limit.clone()
+        }
+    }
+
+    impl Default for MockChargerControl {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl ChargerControl for MockChargerControl {
+        fn set_current_limit(&mut self, amps: f32) -> Result<()> {
+            info!("[MOCK] EV pilot at {:.1}% duty ({:.0} A)", pilot_duty(amps) * 100.0, amps);
+            *self.limit.lock().unwrap() = Some(amps);
+            Ok(())
+        }
+    }
+}
+
+// ============================================================================
+// Factory function to create appropriate charger control
+// ============================================================================
+
+#[cfg(target_os = "linux")]
+pub fn create_charger_control(config: &EvseConfig) -> Result<Box<dyn ChargerControl>> {
+    Ok(Box::new(rpi::RpiChargerControl::new(config)?))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn create_charger_control(_config: &EvseConfig) -> Result<Box<dyn ChargerControl>> {
+    log::warn!("Using MOCK EV charger control (not on Raspberry Pi)");
+    Ok(Box::new(mock::MockChargerControl::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pilot_duty_follows_j1772() {
+        assert_eq!(pilot_duty(0.0), 1.0);
+        assert_eq!(pilot_duty(5.9), 1.0);
+        assert!((pilot_duty(6.0) - 0.10).abs() < 1e-6);
+        assert!((pilot_duty(32.0) - 0.5333).abs() < 1e-4);
+        assert!((pilot_duty(51.0) - 0.85).abs() < 1e-6);
+        assert!((pilot_duty(80.0) - 0.96).abs() < 1e-6);
+        assert!((pilot_duty(100.0) - 0.96).abs() < 1e-6);
+    }
+}
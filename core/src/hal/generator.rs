@@ -0,0 +1,127 @@
+use super::Result;
+
+/// Trait for a backup generator with a two-wire remote start.
+/// Allows mocking for non-Pi development and testing.
+pub trait GeneratorControl: Send {
+    /// Close (run) or open (stop) the remote-start contact.
+    fn set_run(&mut self, run: bool) -> Result<()>;
+    /// Whether the generator's output is within voltage limits, as its voltage-sensing
+    /// relay reports.
+    fn output_ok(&mut self) -> Result<bool>;
+}
+
+/// Generator configuration
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    /// Output driving the remote-start contact
+    pub start_pin: u8,
+    /// Input from the voltage-sensing relay on the generator's output
+    pub sense_pin: u8,
+    /// If true, the sense input reads LOW while the output is good
+    pub sense_active_low: bool,
+}
+
+// ============================================================================
+// Real Raspberry Pi Implementation (only compiled on ARM)
+// ============================================================================
+
+#[cfg(target_os = "linux")]
+pub mod rpi {
+    use super::*;
+    use rppal::gpio::{Gpio, InputPin, OutputPin};
+
+    pub struct RpiGenerator {
+        start: OutputPin,
+        sense: InputPin,
+        sense_active_low: bool,
+    }
+
+    impl RpiGenerator {
+        pub fn new(config: &GeneratorConfig) -> Result<Self> {
+            let gpio = Gpio::new()?;
+            let mut start = gpio.get(config.start_pin)?.into_output();
+            start.set_low();
+            let sense = gpio.get(config.sense_pin)?.into_input_pulldown();
+            Ok(Self { start, sense, sense_active_low: config.sense_active_low })
+        }
+    }
+
+    impl GeneratorControl for RpiGenerator {
+        fn set_run(&mut self, run: bool) -> Result<()> {
+            if run { self.start.set_high() } else { self.start.set_low() }
+            Ok(())
+        }
+
+        fn output_ok(&mut self) -> Result<bool> {
+            let is_high = self.sense.is_high();
+            Ok(if self.sense_active_low { !is_high } else { is_high })
+        }
+    }
+}
+
+// ============================================================================
+// Mock Implementation (for development and testing on non-Pi platforms)
+// ============================================================================
+
+pub mod mock {
+    use super::*;
+    use log::info;
+    use std::sync::{Arc, Mutex};
+
+    /// What the mock generator was told and how it behaves.
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub struct MockGeneratorState {
+        pub running: bool,
+        /// Cranks without ever starting
+        pub fails: bool,
+    }
+
+    /// Generator whose output is good as soon as it is told to run, unless it fails.
+    pub struct MockGenerator {
+        state: Arc<Mutex<MockGeneratorState>>,
+    }
+
+    impl MockGenerator {
+        pub fn new() -> Self {
+            Self { state: Arc::new(Mutex::new(MockGeneratorState::default())) }
+        }
+
+        pub fn handle(&self) -> Arc<Mutex<MockGeneratorState>> {
+            self.state.clone()
+        }
+    }
+
+    impl Default for MockGenerator {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl GeneratorControl for MockGenerator {
+        fn set_run(&mut self, run: bool) -> Result<()> {
+            info!("[MOCK] Generator {}", if run { "run" } else { "stop" });
+            self.state.lock().unwrap().running = run;
+            Ok(())
+        }
+
+        fn output_ok(&mut self) -> Result<bool> {
+            let state = self.state.lock().unwrap();
+            Ok(state.running && !state.fails)
+        }
+    }
+}
+
+// ============================================================================
+// Factory function to create appropriate generator control
+// ============================================================================
+
+#[cfg(target_os = "linux")]
+pub fn create_generator_control(config: &GeneratorConfig) -> Result<Box<dyn GeneratorControl>> {
+    Ok(Box::new(rpi::RpiGenerator::new(config)?))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn create_generator_control(_config: &GeneratorConfig) -> Result<Box<dyn GeneratorControl>> {
+    log::warn!("Using MOCK generator (not on Raspberry Pi)");
+    Ok(Box::new(mock::MockGenerator::new()))
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use super::{HalError, Result};
 
 /// Trait for relay control abstraction.
 /// Allows mocking for non-Pi development and testing.
@@ -9,6 +9,12 @@ pub trait RelayControl: Send + Sync {
     
     /// Get current relay state.
     fn get_relay(&self, pin: u8) -> Result<bool>;
+
+    /// Position of the auxiliary contact of the relay on `pin`: true if the main
+    /// contacts are closed, None if it has no feedback input.
+    fn read_feedback(&self, _pin: u8) -> Result<Option<bool>> {
+        Ok(None)
+    }
 }
 
 /// Pin configuration for a relay
@@ -17,6 +23,8 @@ pub struct RelayPin {
     pub relay_id: String,
     pub gpio_pin: u8,
     pub active_low: bool, // If true, LOW = closed
+    /// Input wired to the relay's auxiliary contact, which pulls it LOW while closed
+    pub feedback_pin: Option<u8>,
 }
 
 // ============================================================================
@@ -26,12 +34,14 @@ pub struct RelayPin {
 #[cfg(target_os = "linux")]
 pub mod rpi {
     use super::*;
-    use rppal::gpio::{Gpio, OutputPin};
+    use rppal::gpio::{Gpio, InputPin, OutputPin};
     use std::collections::HashMap;
     
     pub struct RpiRelayDriver {
         pins: HashMap<u8, OutputPin>,
         active_low: HashMap<u8, bool>,
+        /// Auxiliary contact inputs, by the relay's output pin
+        feedback: HashMap<u8, InputPin>,
     }
     
     impl RpiRelayDriver {
@@ -39,21 +49,25 @@ pub mod rpi {
             let gpio = Gpio::new()?;
             let mut pins = HashMap::new();
             let mut active_low = HashMap::new();
+            let mut feedback = HashMap::new();
             
             for rp in relay_pins {
                 let pin = gpio.get(rp.gpio_pin)?.into_output();
                 pins.insert(rp.gpio_pin, pin);
                 active_low.insert(rp.gpio_pin, rp.active_low);
+                if let Some(feedback_pin) = rp.feedback_pin {
+                    feedback.insert(rp.gpio_pin, gpio.get(feedback_pin)?.into_input_pullup());
+                }
             }
             
-            Ok(Self { pins, active_low })
+            Ok(Self { pins, active_low, feedback })
         }
     }
     
     impl RelayControl for RpiRelayDriver {
         fn set_relay(&mut self, pin: u8, closed: bool) -> Result<()> {
             let output_pin = self.pins.get_mut(&pin)
-                .ok_or_else(|| anyhow::anyhow!("Pin {} not configured", pin))?;
+                .ok_or(HalError::PinNotConfigured(pin))?;
             
             let is_active_low = *self.active_low.get(&pin).unwrap_or(&false);
             
@@ -71,7 +85,7 @@ pub mod rpi {
         
         fn get_relay(&self, pin: u8) -> Result<bool> {
             let output_pin = self.pins.get(&pin)
-                .ok_or_else(|| anyhow::anyhow!("Pin {} not configured", pin))?;
+                .ok_or(HalError::PinNotConfigured(pin))?;
             
             let is_active_low = *self.active_low.get(&pin).unwrap_or(&false);
             let is_high = output_pin.is_set_high();
@@ -79,6 +93,10 @@ pub mod rpi {
             // Invert if active_low
             Ok(if is_active_low { !is_high } else { is_high })
         }
+
+        fn read_feedback(&self, pin: u8) -> Result<Option<bool>> {
+            Ok(self.feedback.get(&pin).map(|input| input.is_low()))
+        }
     }
 }
 
@@ -94,14 +112,25 @@ pub mod mock {
     
     pub struct MockRelayDriver {
         states: Mutex<HashMap<u8, bool>>,
+        /// Output pins of relays with an auxiliary contact
+        feedback: Vec<u8>,
+        /// Relays whose contacts are stuck in one position, whatever they're told
+        stuck: HashMap<u8, bool>,
     }
     
     impl MockRelayDriver {
-        pub fn new(_relay_pins: &[RelayPin]) -> Result<Self> {
+        pub fn new(relay_pins: &[RelayPin]) -> Result<Self> {
             Ok(Self {
                 states: Mutex::new(HashMap::new()),
+                feedback: relay_pins.iter().filter(|rp| rp.feedback_pin.is_some()).map(|rp| rp.gpio_pin).collect(),
+                stuck: HashMap::new(),
             })
         }
+
+        /// Weld the relay on `pin` shut (true) or burn its contacts open (false).
+        pub fn set_stuck(&mut self, pin: u8, closed: bool) {
+            self.stuck.insert(pin, closed);
+        }
     }
     
     impl RelayControl for MockRelayDriver {
@@ -114,6 +143,16 @@ pub mod mock {
         fn get_relay(&self, pin: u8) -> Result<bool> {
             Ok(*self.states.lock().unwrap().get(&pin).unwrap_or(&false))
         }
+
+        fn read_feedback(&self, pin: u8) -> Result<Option<bool>> {
+            if !self.feedback.contains(&pin) {
+                return Ok(None);
+            }
+            Ok(Some(match self.stuck.get(&pin) {
+                Some(closed) => *closed,
+                None => self.get_relay(pin)?,
+            }))
+        }
     }
 }
 
@@ -139,7 +178,7 @@ mod tests {
     #[test]
     fn test_mock_relay_toggle() {
         let pins = vec![
-            RelayPin { relay_id: "test".to_string(), gpio_pin: 17, active_low: false },
+            RelayPin { relay_id: "test".to_string(), gpio_pin: 17, active_low: false, feedback_pin: None },
         ];
         let mut driver = mock::MockRelayDriver::new(&pins).unwrap();
         
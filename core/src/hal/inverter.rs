@@ -0,0 +1,301 @@
+use super::{HalError, Result};
+
+/// Trait for a solar inverter the node can read and curtail.
+/// Allows mocking for development and testing without one.
+pub trait InverterControl: Send {
+    /// AC power being delivered now, in watts
+    fn ac_watts(&mut self) -> Result<f32>;
+    /// Hold output to `fraction` (0..1) of the inverter's maximum, or lift the limit with None
+    fn set_power_limit(&mut self, fraction: Option<f32>) -> Result<()>;
+    /// Supply (positive) or absorb (negative) `fraction` (-1..1) of the inverter's maximum
+    /// reactive power, or return to unity power factor with None
+    fn set_reactive_power(&mut self, fraction: Option<f32>) -> Result<()>;
+}
+
+/// How to reach a SunSpec inverter: Modbus TCP at `tcp`, or Modbus RTU on `serial_port`.
+#[derive(Debug, Clone)]
+pub struct InverterConfig {
+    pub tcp: Option<String>,
+    pub serial_port: Option<String>,
+    pub baud_rate: u32,
+    pub unit_id: u8,
+    /// Where the SunSpec map starts (40000 on most inverters; 0 and 50000 are also used)
+    pub base_address: u16,
+}
+
+impl Default for InverterConfig {
+    fn default() -> Self {
+        Self { tcp: None, serial_port: None, baud_rate: 9600, unit_id: 1, base_address: 40000 }
+    }
+}
+
+// ============================================================================
+// SunSpec Modbus Implementation
+// ============================================================================
+
+pub mod sunspec {
+    use super::*;
+    use crate::hal::modbus::ModbusClient;
+    use log::info;
+    use std::collections::HashMap;
+
+    /// "SunS", marking the start of the map
+    const MARKER: [u16; 2] = [0x5375, 0x6E53];
+    /// Model ID ending the map
+    const END_MODEL: u16 = 0xFFFF;
+    /// Models walked before giving up on finding the end
+    const MAX_MODELS: usize = 64;
+
+    /// Integer inverter models: single phase, split phase, three phase
+    const INVERTER_MODELS: [u16; 3] = [101, 102, 103];
+    /// Offsets of W and W_SF in an inverter model's data
+    const W: u16 = 12;
+    /// Immediate controls model
+    const CONTROLS_MODEL: u16 = 123;
+    /// Offsets of WMaxLimPct, WMaxLim_Ena and WMaxLimPct_SF in the controls' data
+    const WMAX_LIM_PCT: u16 = 3;
+    const WMAX_LIM_ENA: u16 = 7;
+    const WMAX_LIM_PCT_SF: u16 = 21;
+    /// Offsets of VArMaxPct, VArPct_Mod, VArPct_Ena and VArPct_SF in the controls' data
+    const VAR_MAX_PCT: u16 = 14;
+    const VAR_PCT_MOD: u16 = 19;
+    const VAR_PCT_ENA: u16 = 20;
+    const VAR_PCT_SF: u16 = 23;
+    /// VArPct_Mod: setpoints are a percentage of VArMax
+    const VAR_PCT_OF_MAX: u16 = 2;
+
+    /// Scale factor register that isn't implemented
+    const NOT_IMPLEMENTED: i16 = i16::MIN;
+
+    /// An inverter speaking SunSpec over Modbus: output from its inverter model,
+    /// limits through its immediate controls.
+    pub struct SunSpecInverter {
+        client: ModbusClient,
+        /// Model ID -> address of its data, after the ID and length
+        models: HashMap<u16, u16>,
+        inverter: u16,
+    }
+
+    impl SunSpecInverter {
+        /// Walk the inverter's model list from `base_address`.
+        pub fn connect(mut client: ModbusClient, base_address: u16) -> Result<Self> {
+            if client.read_holding_registers(base_address, 2)? != MARKER {
+                return Err(HalError::Protocol(format!("no SunSpec map at {}", base_address)));
+            }
+            let mut models = HashMap::new();
+            let mut address = base_address + 2;
+            for _ in 0..MAX_MODELS {
+                let header = client.read_holding_registers(address, 2)?;
+                if header[0] == END_MODEL {
+                    break;
+                }
+                models.insert(header[0], address + 2);
+                address = address
+                    .checked_add(2 + header[1])
+                    .ok_or_else(|| HalError::Protocol("SunSpec map runs past the register space".to_string()))?;
+            }
+            let inverter = INVERTER_MODELS.iter()
+                .find_map(|id| models.get(id).copied())
+                .ok_or(HalError::Unsupported("SunSpec inverter model (101-103)"))?;
+            info!("SunSpec inverter with models {:?}", {
+                let mut ids: Vec<&u16> = models.keys().collect();
+                ids.sort();
+                ids
+            });
+            Ok(Self { client, models, inverter })
+        }
+
+        /// `value` scaled by the power of ten in `scale_factor`.
+        fn scaled(value: i16, scale_factor: i16) -> Result<f32> {
+            if scale_factor == NOT_IMPLEMENTED {
+                return Err(HalError::Unsupported("scale factor"));
+            }
+            Ok(value as f32 * 10f32.powi(scale_factor as i32))
+        }
+
+        /// Address of the immediate controls' data.
+        fn controls(&self) -> Result<u16> {
+            self.models.get(&CONTROLS_MODEL).copied()
+                .ok_or(HalError::Unsupported("immediate controls (model 123)"))
+        }
+    }
+
+    impl InverterControl for SunSpecInverter {
+        fn ac_watts(&mut self) -> Result<f32> {
+            let registers = self.client.read_holding_registers(self.inverter + W, 2)?;
+            Self::scaled(registers[0] as i16, registers[1] as i16)
+        }
+
+        fn set_power_limit(&mut self, fraction: Option<f32>) -> Result<()> {
+            let controls = self.controls()?;
+            let Some(fraction) = fraction else {
+                return self.client.write_registers(controls + WMAX_LIM_ENA, &[0]);
+            };
+            let scale_factor = self.client.read_holding_registers(controls + WMAX_LIM_PCT_SF, 1)?[0] as i16;
+            let step = Self::scaled(1, scale_factor)?;
+            let percent = (fraction.clamp(0.0, 1.0) * 100.0 / step).round() as u16;
+            // Limit, no time window, no reversion, no ramp, enabled
+            self.client.write_registers(controls + WMAX_LIM_PCT, &[percent, 0, 0, 0, 1])
+        }
+
+        fn set_reactive_power(&mut self, fraction: Option<f32>) -> Result<()> {
+            let controls = self.controls()?;
+            let Some(fraction) = fraction else {
+                return self.client.write_registers(controls + VAR_PCT_ENA, &[0]);
+            };
+            let scale_factor = self.client.read_holding_registers(controls + VAR_PCT_SF, 1)?[0] as i16;
+            let step = Self::scaled(1, scale_factor)?;
+            let percent = (fraction.clamp(-1.0, 1.0) * 100.0 / step).round() as i16;
+            self.client.write_registers(controls + VAR_MAX_PCT, &[percent as u16])?;
+            self.client.write_registers(controls + VAR_PCT_MOD, &[VAR_PCT_OF_MAX, 1])
+        }
+    }
+}
+
+// ============================================================================
+// Mock Implementation (for development and testing without an inverter)
+// ============================================================================
+
+pub mod mock {
+    use super::*;
+    use log::info;
+    use std::sync::{Arc, Mutex};
+
+    /// What a mock inverter makes and the limit and reactive power it was given.
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub struct MockInverterState {
+        /// Output before any limit
+        pub available_watts: f32,
+        pub max_watts: f32,
+        pub limit: Option<f32>,
+        pub reactive: Option<f32>,
+    }
+
+    /// Inverter whose output and limit are shared through a handle.
+    pub struct MockInverter {
+        state: Arc<Mutex<MockInverterState>>,
+    }
+
+    impl MockInverter {
+        pub fn new(max_watts: f32) -> Self {
+            Self { state: Arc::new(Mutex::new(MockInverterState { max_watts, ..Default::default() })) }
+        }
+
+        pub fn handle(&self) -> Arc<Mutex<MockInverterState>> {
+            self.state.clone()
+        }
+    }
+
+    impl InverterControl for MockInverter {
+        fn ac_watts(&mut self) -> Result<f32> {
+            let state = self.state.lock().unwrap();
+            Ok(state.available_watts.min(state.limit.unwrap_or(1.0) * state.max_watts))
+        }
+
+        fn set_power_limit(&mut self, fraction: Option<f32>) -> Result<()> {
+            info!("[MOCK] Inverter limit {:?}", fraction);
+            self.state.lock().unwrap().limit = fraction;
+            Ok(())
+        }
+
+        fn set_reactive_power(&mut self, fraction: Option<f32>) -> Result<()> {
+            info!("[MOCK] Inverter reactive power {:?}", fraction);
+            self.state.lock().unwrap().reactive = fraction;
+            Ok(())
+        }
+    }
+}
+
+// ============================================================================
+// Factory function to create the inverter client
+// ============================================================================
+
+/// Modbus runs over TCP or a serial adapter, so the same client serves every platform.
+pub fn create_inverter(config: &InverterConfig) -> Result<Box<dyn InverterControl>> {
+    use crate::hal::modbus::{ModbusClient, ModbusRtu, ModbusTcp, ModbusTransport};
+    let transport: Box<dyn ModbusTransport> = match (&config.tcp, &config.serial_port) {
+        (Some(address), _) => Box::new(ModbusTcp::connect(address)?),
+        (None, Some(path)) => Box::new(ModbusRtu::open(path, config.baud_rate)?),
+        (None, None) => return Err(HalError::NotConfigured("inverter needs either tcp or serial_port".to_string())),
+    };
+    let client = ModbusClient::new(transport, config.unit_id);
+    Ok(Box::new(sunspec::SunSpecInverter::connect(client, config.base_address)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::modbus::{ModbusClient, ModbusTransport};
+    use std::sync::{Arc, Mutex};
+
+    /// A unit serving a register map from `base`.
+    struct RegisterMap {
+        base: u16,
+        registers: Arc<Mutex<Vec<u16>>>,
+    }
+
+    impl ModbusTransport for RegisterMap {
+        fn transact(&mut self, _unit: u8, pdu: &[u8]) -> Result<Vec<u8>> {
+            let word = |i: usize| u16::from_be_bytes([pdu[i], pdu[i + 1]]);
+            let (start, count) = ((word(1) - self.base) as usize, word(3) as usize);
+            let mut registers = self.registers.lock().unwrap();
+            if start + count > registers.len() {
+                return Ok(vec![pdu[0] | 0x80, 0x02]);
+            }
+            match pdu[0] {
+                0x03 => {
+                    let mut response = vec![0x03, 2 * count as u8];
+                    for value in &registers[start..start + count] {
+                        response.extend_from_slice(&value.to_be_bytes());
+                    }
+                    Ok(response)
+                }
+                _ => {
+                    for i in 0..count {
+                        registers[start + i] = word(6 + 2 * i);
+                    }
+                    Ok(pdu[..5].to_vec())
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sunspec_reads_output_and_sets_limits() {
+        // Marker, a common model, a three-phase inverter model, immediate controls, end
+        let mut map = vec![0x5375, 0x6E53, 1, 4, 0, 0, 0, 0];
+        let inverter = map.len() + 2;
+        map.extend([103, 50]);
+        map.extend([0; 50]);
+        let controls = map.len() + 2;
+        map.extend([123, 24]);
+        map.extend([0; 24]);
+        map.extend([0xFFFF, 0]);
+        // 4530 W as 453 x 10^1; limits in hundredths of a percent
+        map[inverter + 12] = 453;
+        map[inverter + 13] = 1;
+        map[controls + 21] = -2i16 as u16;
+        map[controls + 23] = -1i16 as u16;
+
+        let registers = Arc::new(Mutex::new(map));
+        let client = ModbusClient::new(Box::new(RegisterMap { base: 40000, registers: registers.clone() }), 1);
+        let mut inverter_control = sunspec::SunSpecInverter::connect(client, 40000).unwrap();
+        assert_eq!(inverter_control.ac_watts().unwrap(), 4530.0);
+
+        inverter_control.set_power_limit(Some(0.425)).unwrap();
+        assert_eq!(registers.lock().unwrap()[controls + 3..controls + 8], [4250, 0, 0, 0, 1]);
+        inverter_control.set_power_limit(None).unwrap();
+        assert_eq!(registers.lock().unwrap()[controls + 7], 0);
+
+        // Absorbing 30% of VArMax, in tenths of a percent
+        inverter_control.set_reactive_power(Some(-0.3)).unwrap();
+        assert_eq!(registers.lock().unwrap()[controls + 14], -300i16 as u16);
+        assert_eq!(registers.lock().unwrap()[controls + 19..controls + 21], [2, 1]);
+        inverter_control.set_reactive_power(None).unwrap();
+        assert_eq!(registers.lock().unwrap()[controls + 20], 0);
+
+        // Not a SunSpec device
+        let client = ModbusClient::new(Box::new(RegisterMap { base: 0, registers: Arc::new(Mutex::new(vec![0; 8])) }), 1);
+        assert!(sunspec::SunSpecInverter::connect(client, 0).is_err());
+    }
+}
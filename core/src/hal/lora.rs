@@ -1,4 +1,4 @@
-use anyhow::Result;
+use super::{HalError, Result};
 use log::info;
 
 /// LoRa configuration
@@ -39,6 +39,17 @@ pub trait LoRaRadio: Send + Sync {
     
     /// Set radio to standby mode (low power).
     fn standby(&mut self) -> Result<()>;
+
+    /// Channel activity detection: whether a LoRa preamble is on the air right now.
+    fn channel_activity(&mut self) -> Result<bool> {
+        Err(HalError::Unsupported("channel activity detection"))
+    }
+
+    /// Transmit an unmodulated carrier at the configured frequency and power until
+    /// `standby`, for RF test equipment.
+    fn continuous_wave(&mut self) -> Result<()> {
+        Err(HalError::Unsupported("continuous wave"))
+    }
 }
 
 // ============================================================================
@@ -85,6 +96,17 @@ pub mod rpi {
             info!("[SX126x STUB] Entering standby");
             Ok(())
         }
+
+        fn channel_activity(&mut self) -> Result<bool> {
+            // TODO M3: SetCad, wait for CadDone on DIO1, read CadDetected from the IRQ status
+            Ok(false)
+        }
+
+        fn continuous_wave(&mut self) -> Result<()> {
+            // TODO M3: SetTxContinuousWave (0xD1) after SetRfFrequency and SetTxParams
+            info!("[SX126x STUB] Continuous wave at {} Hz, {} dBm", self.config.frequency, self.config.tx_power);
+            Ok(())
+        }
     }
 }
 
@@ -95,12 +117,15 @@ pub mod rpi {
 pub mod mock {
     use super::*;
     use std::collections::VecDeque;
-    use std::sync::Mutex;
+    use std::sync::{Arc, Mutex};
     
     pub struct MockLoRaRadio {
         config: LoRaHalConfig,
         tx_log: Mutex<Vec<Vec<u8>>>,
-        rx_queue: Mutex<VecDeque<Vec<u8>>>,
+        rx_queue: Arc<Mutex<VecDeque<Vec<u8>>>>,
+        /// Where transmitted frames arrive, when wired to a radio (or to ourselves)
+        air: Option<Arc<Mutex<VecDeque<Vec<u8>>>>>,
+        carrier: bool,
     }
     
     impl MockLoRaRadio {
@@ -109,10 +134,28 @@ pub mod mock {
             Ok(Self {
                 config,
                 tx_log: Mutex::new(Vec::new()),
-                rx_queue: Mutex::new(VecDeque::new()),
+                rx_queue: Arc::new(Mutex::new(VecDeque::new())),
+                air: None,
+                carrier: false,
             })
         }
         
+        /// Two radios in range of each other, as on a loopback bench.
+        pub fn pair(config: LoRaHalConfig) -> Result<(Self, Self)> {
+            let mut a = Self::new(config.clone())?;
+            let mut b = Self::new(config)?;
+            a.air = Some(b.rx_queue.clone());
+            b.air = Some(a.rx_queue.clone());
+            Ok((a, b))
+        }
+        
+        /// A radio that hears its own transmissions.
+        pub fn looped(config: LoRaHalConfig) -> Result<Self> {
+            let mut radio = Self::new(config)?;
+            radio.air = Some(radio.rx_queue.clone());
+            Ok(radio)
+        }
+        
         /// Inject a message to be received (for testing).
         pub fn inject_rx(&self, data: Vec<u8>) {
             self.rx_queue.lock().unwrap().push_back(data);
@@ -122,12 +165,20 @@ pub mod mock {
         pub fn get_tx_log(&self) -> Vec<Vec<u8>> {
             self.tx_log.lock().unwrap().clone()
         }
+
+        /// Whether a continuous wave is on (for testing).
+        pub fn carrier_on(&self) -> bool {
+            self.carrier
+        }
     }
     
     impl LoRaRadio for MockLoRaRadio {
         fn transmit(&mut self, data: &[u8]) -> Result<()> {
             info!("[MOCK LoRa] TX {} bytes: {:02x?}", data.len(), data);
             self.tx_log.lock().unwrap().push(data.to_vec());
+            if let Some(air) = &self.air {
+                air.lock().unwrap().push_back(data.to_vec());
+            }
             Ok(())
         }
         
@@ -141,6 +192,18 @@ pub mod mock {
         
         fn standby(&mut self) -> Result<()> {
             info!("[MOCK LoRa] Standby");
+            self.carrier = false;
+            Ok(())
+        }
+        
+        fn channel_activity(&mut self) -> Result<bool> {
+            // A frame waiting to be received is one still on the air
+            Ok(!self.rx_queue.lock().unwrap().is_empty())
+        }
+
+        fn continuous_wave(&mut self) -> Result<()> {
+            info!("[MOCK LoRa] Continuous wave at {} Hz", self.config.frequency);
+            self.carrier = true;
             Ok(())
         }
     }
@@ -161,6 +224,32 @@ pub fn create_lora_radio(config: LoRaHalConfig) -> Result<Box<dyn LoRaRadio>> {
     Ok(Box::new(mock::MockLoRaRadio::new(config)?))
 }
 
+/// The radio under test, and the second radio it talks to if the bench has one.
+pub type LoopbackRadios = (Box<dyn LoRaRadio>, Option<Box<dyn LoRaRadio>>);
+
+/// Radios of a hardware-in-the-loop loopback bench: a second radio to talk to, or
+/// without one a single radio whose TX is looped into its RX.
+#[cfg(target_os = "linux")]
+pub fn create_lora_loopback(config: LoRaHalConfig, second: Option<LoRaHalConfig>) -> Result<LoopbackRadios> {
+    let second = match second {
+        Some(config) => Some(create_lora_radio(config)?),
+        None => None,
+    };
+    Ok((create_lora_radio(config)?, second))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn create_lora_loopback(config: LoRaHalConfig, second: Option<LoRaHalConfig>) -> Result<LoopbackRadios> {
+    log::warn!("Using MOCK LoRa radios for the loopback (not on Raspberry Pi)");
+    Ok(match second {
+        Some(_) => {
+            let (a, b) = mock::MockLoRaRadio::pair(config)?;
+            (Box::new(a), Some(Box::new(b)))
+        }
+        None => (Box::new(mock::MockLoRaRadio::looped(config)?), None),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
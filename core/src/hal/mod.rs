@@ -0,0 +1,27 @@
+pub mod gpio;
+pub mod adc;
+pub mod lora;
+pub mod secure_element;
+pub mod tamper;
+pub mod battery;
+pub mod vedirect;
+pub mod evse;
+pub mod modbus;
+pub mod inverter;
+pub mod generator;
+pub mod estop;
+pub mod blocking;
+pub mod error;
+
+pub use gpio::{RelayControl, RelayPin, create_relay_driver};
+pub use adc::{PowerSensor, AdcConfig, create_power_sensor};
+pub use lora::{LoRaRadio, LoRaHalConfig, create_lora_radio, create_lora_loopback};
+pub use secure_element::{SecureElement, SecureElementConfig, create_secure_element};
+pub use tamper::{TamperSwitch, TamperConfig, create_tamper_switch};
+pub use battery::{BatteryGauge, BatteryConfig, BatteryStatus};
+pub use evse::{ChargerControl, EvseConfig, create_charger_control};
+pub use inverter::{InverterControl, InverterConfig, create_inverter};
+pub use generator::{GeneratorControl, GeneratorConfig, create_generator_control};
+pub use estop::{EmergencyStop, EmergencyStopConfig, create_emergency_stop};
+pub use blocking::{AsyncPowerSensor, AsyncRelayControl};
+pub use error::{HalError, Result};
@@ -0,0 +1,202 @@
+use super::{HalError, Result};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// How long to wait for a device to answer
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+/// Set in the function code of a response reporting an exception
+const EXCEPTION: u8 = 0x80;
+/// Exception code of a unit still processing an earlier request
+const SERVER_DEVICE_BUSY: u8 = 0x06;
+
+/// Carries Modbus request PDUs to a unit and brings back its response PDU.
+pub trait ModbusTransport: Send {
+    fn transact(&mut self, unit: u8, pdu: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Modbus TCP: each PDU behind an MBAP header.
+pub struct ModbusTcp {
+    stream: TcpStream,
+    transaction: u16,
+}
+
+impl ModbusTcp {
+    pub fn connect(address: &str) -> Result<Self> {
+        let stream = TcpStream::connect(address)
+            .map_err(|e| HalError::Open { device: address.to_string(), reason: e.to_string() })?;
+        stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream, transaction: 0 })
+    }
+}
+
+impl ModbusTransport for ModbusTcp {
+    fn transact(&mut self, unit: u8, pdu: &[u8]) -> Result<Vec<u8>> {
+        self.transaction = self.transaction.wrapping_add(1);
+        let mut frame = Vec::with_capacity(7 + pdu.len());
+        frame.extend_from_slice(&self.transaction.to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+        frame.push(unit);
+        frame.extend_from_slice(pdu);
+        self.stream.write_all(&frame)?;
+
+        let mut header = [0u8; 7];
+        self.stream.read_exact(&mut header)?;
+        let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if u16::from_be_bytes([header[0], header[1]]) != self.transaction || length < 2 {
+            return Err(HalError::Protocol("malformed Modbus TCP response".to_string()));
+        }
+        let mut response = vec![0u8; length - 1];
+        self.stream.read_exact(&mut response)?;
+        Ok(response)
+    }
+}
+
+/// Modbus RTU on a serial line (RS-485): each PDU addressed to the unit and followed
+/// by a CRC.
+pub struct ModbusRtu {
+    port: Box<dyn serialport::SerialPort>,
+}
+
+impl ModbusRtu {
+    pub fn open(path: &str, baud_rate: u32) -> Result<Self> {
+        let port = serialport::new(path, baud_rate)
+            .timeout(RESPONSE_TIMEOUT)
+            .open()
+            .map_err(|e| HalError::Open { device: path.to_string(), reason: e.to_string() })?;
+        Ok(Self { port })
+    }
+}
+
+impl ModbusTransport for ModbusRtu {
+    fn transact(&mut self, unit: u8, pdu: &[u8]) -> Result<Vec<u8>> {
+        let mut frame = vec![unit];
+        frame.extend_from_slice(pdu);
+        frame.extend_from_slice(&crc16(&frame).to_le_bytes());
+        self.port.clear(serialport::ClearBuffer::Input)?;
+        self.port.write_all(&frame)?;
+
+        // Address and function code tell how much more is coming
+        let mut response = vec![0u8; 2];
+        self.port.read_exact(&mut response)?;
+        let remaining = match response[1] {
+            code if code & EXCEPTION != 0 => 3,
+            READ_HOLDING_REGISTERS => {
+                let mut count = [0u8; 1];
+                self.port.read_exact(&mut count)?;
+                response.push(count[0]);
+                count[0] as usize + 2
+            }
+            _ => 6,
+        };
+        let start = response.len();
+        response.resize(start + remaining, 0);
+        self.port.read_exact(&mut response[start..])?;
+
+        let (body, crc) = response.split_at(response.len() - 2);
+        if crc16(body).to_le_bytes() != crc {
+            return Err(HalError::Bus("Modbus RTU response failed its CRC".to_string()));
+        }
+        if body[0] != unit {
+            return Err(HalError::Protocol(format!("Modbus RTU response from unit {}, expected {}", body[0], unit)));
+        }
+        Ok(body[1..].to_vec())
+    }
+}
+
+/// CRC-16/MODBUS of `bytes`.
+pub fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for byte in bytes {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// Register reads and writes against one unit, over either transport.
+pub struct ModbusClient {
+    transport: Box<dyn ModbusTransport>,
+    unit: u8,
+}
+
+impl ModbusClient {
+    pub fn new(transport: Box<dyn ModbusTransport>, unit: u8) -> Self {
+        Self { transport, unit }
+    }
+
+    pub fn read_holding_registers(&mut self, address: u16, count: u16) -> Result<Vec<u16>> {
+        let mut pdu = vec![READ_HOLDING_REGISTERS];
+        pdu.extend_from_slice(&address.to_be_bytes());
+        pdu.extend_from_slice(&count.to_be_bytes());
+        let response = self.request(&pdu)?;
+        if response.len() < 2 || response[1] as usize != 2 * count as usize || response.len() != 2 + 2 * count as usize {
+            return Err(HalError::Protocol(format!("short read of {} registers at {}", count, address)));
+        }
+        Ok(response[2..].chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect())
+    }
+
+    pub fn write_registers(&mut self, address: u16, values: &[u16]) -> Result<()> {
+        let mut pdu = vec![WRITE_MULTIPLE_REGISTERS];
+        pdu.extend_from_slice(&address.to_be_bytes());
+        pdu.extend_from_slice(&(values.len() as u16).to_be_bytes());
+        pdu.push(2 * values.len() as u8);
+        for value in values {
+            pdu.extend_from_slice(&value.to_be_bytes());
+        }
+        self.request(&pdu)?;
+        Ok(())
+    }
+
+    /// Send `pdu` and return the response, failing on an exception or a mismatched reply.
+    fn request(&mut self, pdu: &[u8]) -> Result<Vec<u8>> {
+        let response = self.transport.transact(self.unit, pdu)?;
+        match response.first() {
+            Some(code) if *code == pdu[0] => Ok(response),
+            Some(code) if *code == pdu[0] | EXCEPTION => match response.get(1).copied().unwrap_or(0) {
+                SERVER_DEVICE_BUSY => Err(HalError::Busy("Modbus unit")),
+                exception => Err(HalError::Protocol(format!("Modbus exception {} for function {:#04x}", exception, pdu[0]))),
+            },
+            _ => Err(HalError::Protocol(format!("unexpected Modbus response to function {:#04x}", pdu[0]))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_frames_and_exceptions() {
+        // The specification's example request
+        assert_eq!(crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]).to_le_bytes(), [0xC5, 0xCD]);
+
+        // A TCP device answering one read, then rejecting a write
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let device = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 12];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(&request[6..], &[0x07, 0x03, 0x9C, 0x40, 0x00, 0x02]);
+            stream.write_all(&[request[0], request[1], 0, 0, 0, 7, 0x07, 0x03, 4, 0x53, 0x75, 0x6E, 0x53]).unwrap();
+            let mut request = [0u8; 15];
+            stream.read_exact(&mut request).unwrap();
+            stream.write_all(&[request[0], request[1], 0, 0, 0, 3, 0x07, 0x90, 0x02]).unwrap();
+        });
+
+        let mut client = ModbusClient::new(Box::new(ModbusTcp::connect(&address).unwrap()), 7);
+        assert_eq!(client.read_holding_registers(40000, 2).unwrap(), vec![0x5375, 0x6E53]);
+        let error = client.write_registers(40100, &[1]).unwrap_err();
+        assert!(error.to_string().contains("exception 2"));
+        device.join().unwrap();
+    }
+}
@@ -0,0 +1,316 @@
+use super::{HalError, Result};
+
+/// Trait for a hardware secure element holding keys that never leave the chip.
+/// Allows mocking for non-Pi development and testing.
+pub trait SecureElement: Send {
+    /// Factory-programmed 9-byte serial number.
+    fn serial_number(&mut self) -> Result<[u8; 9]>;
+
+    /// 32 bytes from the hardware RNG.
+    fn random(&mut self) -> Result<[u8; 32]>;
+
+    /// ECDSA P-256 signature (r || s) over a SHA-256 digest with the private key in `slot`.
+    fn sign(&mut self, slot: u8, digest: &[u8; 32]) -> Result<[u8; 64]>;
+
+    /// Public key (x || y) of the private key in `slot`.
+    fn public_key(&mut self, slot: u8) -> Result<[u8; 64]>;
+
+    /// Keyed digest of `challenge` with the secret in `slot`; used to derive
+    /// key-encryption keys so secrets on the SD card are useless without the chip.
+    fn mac(&mut self, slot: u8, challenge: &[u8; 32]) -> Result<[u8; 32]>;
+}
+
+/// Secure element configuration
+#[derive(Debug, Clone)]
+pub struct SecureElementConfig {
+    pub i2c_bus: u8,
+    pub address: u8,
+    /// Slot holding the device's ECDSA identity key
+    pub sign_slot: u8,
+    /// Slot holding the secret used to derive the key-encryption key
+    pub kek_slot: u8,
+}
+
+impl Default for SecureElementConfig {
+    fn default() -> Self {
+        Self {
+            i2c_bus: 1,
+            address: 0x60, // Default ATECC608 address
+            sign_slot: 0,
+            kek_slot: 2,
+        }
+    }
+}
+
+/// CRC-16 used by the ATECC family (polynomial 0x8005, LSB-first input), little-endian on the wire.
+fn atca_crc(data: &[u8]) -> [u8; 2] {
+    let mut crc: u16 = 0;
+    for byte in data {
+        for shift in 0..8 {
+            let data_bit = (byte >> shift) & 1;
+            let crc_bit = (crc >> 15) as u8;
+            crc <<= 1;
+            if data_bit != crc_bit {
+                crc ^= 0x8005;
+            }
+        }
+    }
+    crc.to_le_bytes()
+}
+
+/// Build a command packet: word address, count, opcode, param1, param2 (LE), data, CRC.
+fn command_packet(opcode: u8, param1: u8, param2: u16, data: &[u8]) -> Vec<u8> {
+    let count = (7 + data.len()) as u8;
+    let mut packet = vec![0x03, count, opcode, param1];
+    packet.extend_from_slice(&param2.to_le_bytes());
+    packet.extend_from_slice(data);
+    let crc = atca_crc(&packet[1..]);
+    packet.extend_from_slice(&crc);
+    packet
+}
+
+/// Validate a response (count, data, CRC) and return its data.
+fn parse_response(response: &[u8]) -> Result<&[u8]> {
+    let count = *response.first().ok_or_else(|| HalError::Protocol("empty response".to_string()))? as usize;
+    if count < 4 || count > response.len() {
+        return Err(HalError::Protocol(format!("bad response length {}", count)));
+    }
+    let (body, crc) = response[..count].split_at(count - 2);
+    if atca_crc(body) != crc {
+        return Err(HalError::Protocol("response CRC mismatch".to_string()));
+    }
+    let data = &body[1..];
+    // A 1-byte payload is a status code; 0x00 is success
+    if data.len() == 1 && data[0] != 0x00 {
+        return Err(HalError::Protocol(format!("secure element error status 0x{:02x}", data[0])));
+    }
+    Ok(data)
+}
+
+// ============================================================================
+// Real Raspberry Pi Implementation (only compiled on ARM)
+// ============================================================================
+
+#[cfg(target_os = "linux")]
+pub mod rpi {
+    use super::*;
+    use rppal::i2c::I2c;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    const OP_READ: u8 = 0x02;
+    const OP_MAC: u8 = 0x08;
+    const OP_NONCE: u8 = 0x16;
+    const OP_RANDOM: u8 = 0x1B;
+    const OP_GENKEY: u8 = 0x40;
+    const OP_SIGN: u8 = 0x41;
+
+    /// Expected response to a wake pulse
+    const WAKE_OK: [u8; 4] = [0x04, 0x11, 0x33, 0x43];
+
+    pub struct Atecc608 {
+        i2c: I2c,
+        address: u8,
+    }
+
+    impl Atecc608 {
+        /// Open the device and check it answers a wake pulse.
+        pub fn new(config: &SecureElementConfig) -> Result<Self> {
+            let mut i2c = I2c::with_bus(config.i2c_bus)?;
+            i2c.set_slave_address(config.address as u16)?;
+            let mut device = Self { i2c, address: config.address };
+            device.wake()?;
+            device.idle();
+            Ok(device)
+        }
+
+        fn wake(&mut self) -> Result<()> {
+            // Holding SDA low long enough wakes the chip; a write to address 0 does that.
+            // It is never acknowledged, so the error is expected.
+            let _ = self.i2c.set_slave_address(0x00).and_then(|_| self.i2c.write(&[0x00]));
+            self.i2c.set_slave_address(self.address as u16)?;
+            sleep(Duration::from_micros(1500));
+            let mut response = [0u8; 4];
+            self.i2c.read(&mut response)?;
+            if response != WAKE_OK {
+                return Err(HalError::Protocol(format!("unexpected wake response {:02x?}", response)));
+            }
+            Ok(())
+        }
+
+        fn idle(&mut self) {
+            let _ = self.i2c.write(&[0x02]);
+        }
+
+        /// Wake, run one command and idle again (the chip's watchdog sleeps it after ~1.3 s).
+        fn execute(&mut self, opcode: u8, param1: u8, param2: u16, data: &[u8], exec_ms: u64, response_len: usize) -> Result<Vec<u8>> {
+            self.wake()?;
+            let result = (|| {
+                self.i2c.write(&command_packet(opcode, param1, param2, data))?;
+                sleep(Duration::from_millis(exec_ms));
+                let mut response = vec![0u8; response_len + 3];
+                self.i2c.read(&mut response)?;
+                Ok(parse_response(&response)?.to_vec())
+            })();
+            self.idle();
+            result
+        }
+    }
+
+    /// A response's data as the fixed-size value the command returns.
+    fn sized<const N: usize>(data: &[u8]) -> Result<[u8; N]> {
+        data.try_into().map_err(|_| HalError::Protocol(format!("{}-byte response, expected {}", data.len(), N)))
+    }
+
+    impl SecureElement for Atecc608 {
+        fn serial_number(&mut self) -> Result<[u8; 9]> {
+            // Config zone block 0: SN[0..4] at bytes 0-3, SN[4..9] at bytes 8-12
+            let block = self.execute(OP_READ, 0x80, 0x0000, &[], 5, 32)?;
+            let mut serial = [0u8; 9];
+            serial[..4].copy_from_slice(&block[0..4]);
+            serial[4..].copy_from_slice(&block[8..13]);
+            Ok(serial)
+        }
+
+        fn random(&mut self) -> Result<[u8; 32]> {
+            let data = self.execute(OP_RANDOM, 0x00, 0x0000, &[0u8; 20], 23, 32)?;
+            sized(&data)
+        }
+
+        fn sign(&mut self, slot: u8, digest: &[u8; 32]) -> Result<[u8; 64]> {
+            // Load the digest into TempKey (pass-through nonce), then sign it externally
+            self.execute(OP_NONCE, 0x03, 0x0000, digest, 7, 1)?;
+            let data = self.execute(OP_SIGN, 0x80, slot as u16, &[], 115, 64)?;
+            sized(&data)
+        }
+
+        fn public_key(&mut self, slot: u8) -> Result<[u8; 64]> {
+            let data = self.execute(OP_GENKEY, 0x00, slot as u16, &[], 115, 64)?;
+            sized(&data)
+        }
+
+        fn mac(&mut self, slot: u8, challenge: &[u8; 32]) -> Result<[u8; 32]> {
+            let data = self.execute(OP_MAC, 0x00, slot as u16, challenge, 14, 32)?;
+            sized(&data)
+        }
+    }
+}
+
+// ============================================================================
+// Mock Implementation (for development and testing on non-Pi platforms)
+// ============================================================================
+
+pub mod mock {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    /// Deterministic stand-in: "keys" are derived from a fixed device secret and
+    /// signatures are hashes, not ECDSA. Good for exercising code paths only.
+    pub struct MockSecureElement {
+        secret: [u8; 32],
+        counter: u64,
+    }
+
+    impl MockSecureElement {
+        pub fn new(secret: [u8; 32]) -> Self {
+            Self { secret, counter: 0 }
+        }
+
+        fn digest(&self, parts: &[&[u8]]) -> [u8; 32] {
+            let mut hasher = Sha256::new();
+            hasher.update(self.secret);
+            for part in parts {
+                hasher.update(part);
+            }
+            hasher.finalize().into()
+        }
+    }
+
+    impl SecureElement for MockSecureElement {
+        fn serial_number(&mut self) -> Result<[u8; 9]> {
+            let mut serial = [0u8; 9];
+            serial.copy_from_slice(&self.digest(&[b"serial"])[..9]);
+            serial[0] = 0x01;
+            serial[1] = 0x23;
+            Ok(serial)
+        }
+
+        fn random(&mut self) -> Result<[u8; 32]> {
+            self.counter += 1;
+            Ok(self.digest(&[b"random", &self.counter.to_le_bytes()]))
+        }
+
+        fn sign(&mut self, slot: u8, digest: &[u8; 32]) -> Result<[u8; 64]> {
+            let mut signature = [0u8; 64];
+            signature[..32].copy_from_slice(&self.digest(&[b"sign-r", &[slot], digest]));
+            signature[32..].copy_from_slice(&self.digest(&[b"sign-s", &[slot], digest]));
+            Ok(signature)
+        }
+
+        fn public_key(&mut self, slot: u8) -> Result<[u8; 64]> {
+            let mut key = [0u8; 64];
+            key[..32].copy_from_slice(&self.digest(&[b"pub-x", &[slot]]));
+            key[32..].copy_from_slice(&self.digest(&[b"pub-y", &[slot]]));
+            Ok(key)
+        }
+
+        fn mac(&mut self, slot: u8, challenge: &[u8; 32]) -> Result<[u8; 32]> {
+            Ok(self.digest(&[b"mac", &[slot], challenge]))
+        }
+    }
+}
+
+// ============================================================================
+// Factory function to create appropriate secure element
+// ============================================================================
+
+#[cfg(target_os = "linux")]
+pub fn create_secure_element(config: &SecureElementConfig) -> Result<Box<dyn SecureElement>> {
+    Ok(Box::new(rpi::Atecc608::new(config)?))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn create_secure_element(_config: &SecureElementConfig) -> Result<Box<dyn SecureElement>> {
+    log::warn!("Using MOCK secure element (not on Raspberry Pi)");
+    Ok(Box::new(mock::MockSecureElement::new([0x5E; 32])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_packet_crc() {
+        // Random command as documented by Microchip: 03 07 1B 00 00 00 CRC
+        let packet = command_packet(0x1B, 0x00, 0x0000, &[]);
+        assert_eq!(&packet[..6], &[0x03, 0x07, 0x1B, 0x00, 0x00, 0x00]);
+        assert_eq!(atca_crc(&packet[1..6]), [packet[6], packet[7]]);
+        assert_eq!(packet.len(), 8);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        // Wake response carries status 0x11 with a valid CRC
+        assert_eq!(atca_crc(&[0x04, 0x11]), [0x33, 0x43]);
+        assert!(parse_response(&[0x04, 0x11, 0x33, 0x43]).is_err());
+
+        let mut ok = vec![0x04, 0x00];
+        ok.extend_from_slice(&atca_crc(&ok));
+        assert_eq!(parse_response(&ok).unwrap(), &[0x00]);
+
+        let mut data = vec![0x07, 0xAA, 0xBB, 0xCC, 0xDD];
+        data.extend_from_slice(&atca_crc(&data));
+        assert_eq!(parse_response(&data).unwrap(), &[0xAA, 0xBB, 0xCC, 0xDD]);
+
+        data[2] ^= 0xFF;
+        assert!(parse_response(&data).is_err());
+    }
+
+    #[test]
+    fn test_mock_is_deterministic() {
+        let mut se = mock::MockSecureElement::new([1; 32]);
+        let challenge = [9; 32];
+        assert_eq!(se.mac(2, &challenge).unwrap(), se.mac(2, &challenge).unwrap());
+        assert_ne!(se.random().unwrap(), se.random().unwrap());
+    }
+}
@@ -0,0 +1,99 @@
+use super::Result;
+
+/// Trait for the enclosure tamper switch.
+/// Allows mocking for non-Pi development and testing.
+pub trait TamperSwitch: Send {
+    /// True while the enclosure is open.
+    fn is_open(&mut self) -> Result<bool>;
+}
+
+/// Tamper switch configuration
+#[derive(Debug, Clone)]
+pub struct TamperConfig {
+    pub gpio_pin: u8,
+    /// If true, the input reads LOW when the enclosure is open. The pin is pulled up,
+    /// so a normally-closed switch to ground also reports "open" if its wire is cut.
+    pub active_low: bool,
+}
+
+// ============================================================================
+// Real Raspberry Pi Implementation (only compiled on ARM)
+// ============================================================================
+
+#[cfg(target_os = "linux")]
+pub mod rpi {
+    use super::*;
+    use rppal::gpio::{Gpio, InputPin};
+
+    pub struct RpiTamperSwitch {
+        pin: InputPin,
+        active_low: bool,
+    }
+
+    impl RpiTamperSwitch {
+        pub fn new(config: &TamperConfig) -> Result<Self> {
+            let pin = Gpio::new()?.get(config.gpio_pin)?.into_input_pullup();
+            Ok(Self { pin, active_low: config.active_low })
+        }
+    }
+
+    impl TamperSwitch for RpiTamperSwitch {
+        fn is_open(&mut self) -> Result<bool> {
+            let is_high = self.pin.is_high();
+            Ok(if self.active_low { !is_high } else { is_high })
+        }
+    }
+}
+
+// ============================================================================
+// Mock Implementation (for development and testing on non-Pi platforms)
+// ============================================================================
+
+pub mod mock {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// Switch whose position is set through a shared flag.
+    pub struct MockTamperSwitch {
+        open: Arc<AtomicBool>,
+    }
+
+    impl MockTamperSwitch {
+        pub fn new() -> Self {
+            Self { open: Arc::new(AtomicBool::new(false)) }
+        }
+
+        /// Handle for opening and closing the mock enclosure.
+        pub fn handle(&self) -> Arc<AtomicBool> {
+            self.open.clone()
+        }
+    }
+
+    impl Default for MockTamperSwitch {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl TamperSwitch for MockTamperSwitch {
+        fn is_open(&mut self) -> Result<bool> {
+            Ok(self.open.load(Ordering::Relaxed))
+        }
+    }
+}
+
+// ============================================================================
+// Factory function to create appropriate switch
+// ============================================================================
+
+#[cfg(target_os = "linux")]
+pub fn create_tamper_switch(config: &TamperConfig) -> Result<Box<dyn TamperSwitch>> {
+    Ok(Box::new(rpi::RpiTamperSwitch::new(config)?))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn create_tamper_switch(_config: &TamperConfig) -> Result<Box<dyn TamperSwitch>> {
+    log::warn!("Using MOCK tamper switch (not on Raspberry Pi)");
+    Ok(Box::new(mock::MockTamperSwitch::new()))
+}
@@ -0,0 +1,203 @@
+use super::{HalError, Result};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::hal::battery::{BatteryGauge, BatteryStatus};
+
+/// VE.Direct's fixed line speed
+pub const VE_DIRECT_BAUD: u32 = 19200;
+
+/// A monitor sends a block every second; with none for this long it has gone quiet.
+const STALE_AFTER: Duration = Duration::from_secs(10);
+
+/// Delay before reopening the serial port after an I/O error.
+const REOPEN_DELAY: Duration = Duration::from_secs(5);
+
+/// A Victron battery monitor (BMV, SmartShunt) or BMS on a VE.Direct port, and the
+/// pack limits it doesn't report itself.
+#[derive(Debug, Clone)]
+pub struct BmsConfig {
+    pub serial_port: String,
+    pub max_charge_amps: f32,
+    pub max_discharge_amps: f32,
+    /// Pack voltage at or below which nothing more may be drawn
+    pub cutoff_voltage: Option<f32>,
+}
+
+/// One block of the text protocol: label -> value.
+pub type Block = HashMap<String, String>;
+
+/// Splits the VE.Direct text protocol into blocks, dropping those that fail their
+/// checksum and the HEX protocol frames interleaved with them.
+#[derive(Debug, Default)]
+pub struct BlockParser {
+    sum: u8,
+    line: Vec<u8>,
+    fields: Block,
+    hex: bool,
+}
+
+impl BlockParser {
+    /// Take the next byte off the wire; returns a block once its checksum byte arrives.
+    pub fn push(&mut self, byte: u8) -> Option<Block> {
+        if self.hex {
+            self.hex = byte != b'\n';
+            return None;
+        }
+        // Whatever follows the checksum label is the checksum, even a ':' or newline
+        if self.line == b"Checksum\t" {
+            let valid = self.sum.wrapping_add(byte) == 0;
+            let fields = std::mem::take(&mut self.fields);
+            self.sum = 0;
+            self.line.clear();
+            if !valid {
+                warn!("Dropping VE.Direct block with a bad checksum");
+            }
+            return valid.then_some(fields);
+        }
+        if byte == b':' {
+            self.hex = true;
+            return None;
+        }
+        self.sum = self.sum.wrapping_add(byte);
+        if byte == b'\r' || byte == b'\n' {
+            if let Some((label, value)) = std::str::from_utf8(&self.line).ok().and_then(|l| l.split_once('\t')) {
+                self.fields.insert(label.to_string(), value.to_string());
+            }
+            self.line.clear();
+        } else {
+            self.line.push(byte);
+        }
+        None
+    }
+}
+
+/// Battery read from the latest VE.Direct block, kept current by a reader thread.
+pub struct VeDirectBattery {
+    config: BmsConfig,
+    latest: Arc<Mutex<Option<(Instant, Block)>>>,
+}
+
+impl VeDirectBattery {
+    /// Start reading the monitor on a dedicated thread: serial reads are blocking.
+    pub fn open(config: BmsConfig) -> Self {
+        let battery = Self { config, latest: Arc::new(Mutex::new(None)) };
+        let (path, latest) = (battery.config.serial_port.clone(), battery.latest.clone());
+        std::thread::Builder::new()
+            .name("ve-direct".to_string())
+            .spawn(move || {
+                info!("Reading battery over VE.Direct on {}", path);
+                loop {
+                    match serialport::new(&path, VE_DIRECT_BAUD).timeout(STALE_AFTER).open() {
+                        Ok(mut port) => {
+                            if let Err(e) = pump(&mut port, &latest) {
+                                warn!("VE.Direct on {} failed: {}", path, e);
+                            }
+                        }
+                        Err(e) => warn!("Cannot open serial port {}: {}", path, e),
+                    }
+                    std::thread::sleep(REOPEN_DELAY);
+                }
+            })
+            .expect("failed to spawn VE.Direct thread");
+        battery
+    }
+}
+
+/// Keep `latest` at the newest good block until the reader fails.
+fn pump(port: &mut impl Read, latest: &Mutex<Option<(Instant, Block)>>) -> Result<()> {
+    let mut parser = BlockParser::default();
+    let mut buf = [0u8; 256];
+    loop {
+        let n = match port.read(&mut buf) {
+            Ok(0) => return Err(HalError::Bus("port closed".to_string())),
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::TimedOut => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for byte in &buf[..n] {
+            if let Some(block) = parser.push(*byte) {
+                *latest.lock().unwrap() = Some((Instant::now(), block));
+            }
+        }
+    }
+}
+
+impl BatteryGauge for VeDirectBattery {
+    fn state_of_charge(&mut self) -> Result<f32> {
+        Ok(self.status()?.soc)
+    }
+
+    /// SOC in tenths of a percent, V in mV, I in mA. The configured discharge limit
+    /// drops to nothing once the pack is at its cut-off voltage.
+    fn status(&mut self) -> Result<BatteryStatus> {
+        let latest = self.latest.lock().unwrap();
+        let block = match &*latest {
+            Some((at, block)) if at.elapsed() < STALE_AFTER => block,
+            _ => return Err(HalError::NoData(format!("no VE.Direct data from {}", self.config.serial_port))),
+        };
+        let number = |label: &str| block.get(label).and_then(|value| value.parse::<f32>().ok());
+        // A monitor that hasn't synchronised yet reports "---"
+        let soc = number("SOC").ok_or_else(|| HalError::NoData("battery monitor has no state of charge yet".to_string()))? / 1000.0;
+        let voltage = number("V").map(|mv| mv / 1000.0);
+        let at_cutoff = matches!((voltage, self.config.cutoff_voltage), (Some(v), Some(cutoff)) if v <= cutoff);
+        Ok(BatteryStatus {
+            soc: soc.clamp(0.0, 1.0),
+            voltage,
+            current: number("I").map(|ma| ma / 1000.0),
+            max_charge_amps: Some(self.config.max_charge_amps),
+            max_discharge_amps: Some(if at_cutoff { 0.0 } else { self.config.max_discharge_amps }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(fields: &[(&str, &str)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (label, value) in fields {
+            bytes.extend_from_slice(format!("\r\n{}\t{}", label, value).as_bytes());
+        }
+        bytes.extend_from_slice(b"\r\nChecksum\t");
+        let sum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        bytes.push(0u8.wrapping_sub(sum));
+        bytes
+    }
+
+    #[test]
+    fn test_blocks_become_battery_status() {
+        let config = BmsConfig {
+            serial_port: "/dev/ttyUSB0".to_string(),
+            max_charge_amps: 50.0,
+            max_discharge_amps: 100.0,
+            cutoff_voltage: Some(46.0),
+        };
+        let mut battery = VeDirectBattery { config, latest: Arc::new(Mutex::new(None)) };
+        assert!(battery.status().is_err());
+
+        // A good block with a HEX frame inside it, then a corrupted one
+        let mut wire = block(&[("PID", "0xA389"), ("V", "51200"), ("I", "-12500"), ("SOC", "634")]);
+        wire.splice(10..10, b":A0102000543\n".iter().copied());
+        let mut corrupt = block(&[("V", "45000"), ("SOC", "100")]);
+        corrupt[4] ^= 1;
+        wire.extend(corrupt);
+        assert!(pump(&mut wire.as_slice(), &battery.latest).is_err());
+
+        let status = battery.status().unwrap();
+        assert!((status.soc - 0.634).abs() < 1e-6);
+        assert_eq!((status.voltage, status.current), (Some(51.2), Some(-12.5)));
+        assert_eq!(status.max_discharge_watts(), Some(5120.0));
+
+        // At the cut-off voltage nothing more may be drawn
+        pump(&mut block(&[("V", "45900"), ("I", "0"), ("SOC", "120")]).as_slice(), &battery.latest).ok();
+        assert_eq!(battery.status().unwrap().max_discharge_amps, Some(0.0));
+
+        // Not yet synchronised
+        pump(&mut block(&[("V", "51000"), ("SOC", "---")]).as_slice(), &battery.latest).ok();
+        assert!(battery.state_of_charge().is_err());
+    }
+}
@@ -0,0 +1,156 @@
+/// Voltage THD above which the node alarms: IEEE 519's limit for systems up to 1 kV
+pub const VOLTAGE_THD_LIMIT: f32 = 0.08;
+
+/// Mains fundamentals looked for in the spectrum, covering 50 and 60 Hz grids and an
+/// island drifting off either
+const FUNDAMENTAL_HZ: std::ops::RangeInclusive<f32> = 40.0..=70.0;
+
+/// Bins either side of a peak summed into its energy; a Hann window spreads a tone over
+/// about this many, wherever it falls between bins.
+const PEAK_HALF_WIDTH: usize = 2;
+
+/// Bins at least between the fundamental and DC, and between harmonics, so their peaks don't overlap
+const MIN_FUNDAMENTAL_BIN: usize = 3 * PEAK_HALF_WIDTH;
+
+/// A waveform sampled at a steady rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waveform {
+    pub samples: Vec<f32>,
+    pub sample_rate_hz: f32,
+}
+
+/// Power spectrum of a waveform with its mains fundamental found.
+struct Spectrum {
+    /// Power in each bin up to Nyquist
+    power: Vec<f32>,
+    bin_hz: f32,
+    /// Bin the fundamental falls in, between bins, and its energy
+    fundamental: f32,
+    fundamental_energy: f32,
+}
+
+/// Total harmonic distortion of a mains waveform: the RMS of its harmonics relative to
+/// the fundamental, from an FFT of the samples. None unless the waveform is sampled fast
+/// enough, and for long enough, to resolve the fundamental and its third harmonic.
+pub fn thd(waveform: &Waveform) -> Option<f32> {
+    let Spectrum { power, fundamental, fundamental_energy, .. } = spectrum(waveform)?;
+    let harmonic_energy = |order: usize| {
+        let centre = (fundamental * order as f32).round() as usize;
+        (centre + PEAK_HALF_WIDTH < power.len()).then(|| around(centre).map(|b| power[b]).sum::<f32>())
+    };
+    // The third harmonic is the one non-linear loads are known by
+    harmonic_energy(3)?;
+    let distortion: f32 = (2..).map_while(harmonic_energy).sum();
+    Some((distortion / fundamental_energy).sqrt())
+}
+
+/// Frequency of the mains in a waveform, if it covers enough cycles to find it.
+pub fn fundamental_hz(waveform: &Waveform) -> Option<f32> {
+    spectrum(waveform).map(|s| s.fundamental * s.bin_hz)
+}
+
+fn around(centre: usize) -> std::ops::RangeInclusive<usize> {
+    centre - PEAK_HALF_WIDTH..=centre + PEAK_HALF_WIDTH
+}
+
+fn spectrum(waveform: &Waveform) -> Option<Spectrum> {
+    let n = prev_power_of_two(waveform.samples.len());
+    if n < 16 || waveform.sample_rate_hz <= 0.0 {
+        return None;
+    }
+    let samples = &waveform.samples[..n];
+    let mean = samples.iter().sum::<f32>() / n as f32;
+    let mut re: Vec<f32> = samples.iter().enumerate()
+        .map(|(i, s)| (s - mean) * hann(i, n))
+        .collect();
+    let mut im = vec![0.0; n];
+    fft(&mut re, &mut im);
+    let power: Vec<f32> = re.iter().zip(&im).take(n / 2).map(|(r, i)| r * r + i * i).collect();
+
+    // The fundamental is the strongest peak in the mains band, placed between bins by its centroid
+    let bin_hz = waveform.sample_rate_hz / n as f32;
+    let band = (FUNDAMENTAL_HZ.start() / bin_hz).ceil() as usize..=(FUNDAMENTAL_HZ.end() / bin_hz) as usize;
+    let peak = band.filter(|b| *b + PEAK_HALF_WIDTH < power.len()).max_by(|a, b| power[*a].total_cmp(&power[*b]))?;
+    if peak < MIN_FUNDAMENTAL_BIN {
+        return None;
+    }
+    let fundamental_energy: f32 = around(peak).map(|b| power[b]).sum();
+    if fundamental_energy <= 0.0 {
+        return None;
+    }
+    let fundamental = around(peak).map(|b| b as f32 * power[b]).sum::<f32>() / fundamental_energy;
+    Some(Spectrum { power, bin_hz, fundamental, fundamental_energy })
+}
+
+fn prev_power_of_two(n: usize) -> usize {
+    if n == 0 { 0 } else { 1 << (usize::BITS - 1 - n.leading_zeros()) }
+}
+
+fn hann(i: usize, n: usize) -> f32 {
+    0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / n as f32).cos()
+}
+
+/// In-place radix-2 FFT; the length must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -std::f32::consts::TAU / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_re, w_im) = ((angle * k as f32).cos(), (angle * k as f32).sin());
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mains(hz: f32, sample_rate_hz: f32, harmonics: &[(usize, f32)]) -> Waveform {
+        let samples = (0..512)
+            .map(|i| {
+                let t = i as f32 / sample_rate_hz;
+                let tone = |order: usize, amplitude: f32| amplitude * (std::f32::consts::TAU * hz * order as f32 * t).sin();
+                // Single-ended readings sit on a bias
+                1.65 + tone(1, 1.0) + harmonics.iter().map(|(order, amplitude)| tone(*order, *amplitude)).sum::<f32>()
+            })
+            .collect();
+        Waveform { samples, sample_rate_hz }
+    }
+
+    #[test]
+    fn test_distortion_is_measured_relative_to_the_fundamental() {
+        let clean = thd(&mains(60.0, 3840.0, &[])).unwrap();
+        assert!(clean < 0.01, "clean sine read {}", clean);
+
+        // 8% third and 6% fifth harmonic: 10% THD, on a 50 Hz grid sampled at an odd rate
+        let distorted = thd(&mains(50.0, 2000.0, &[(3, 0.08), (5, 0.06)])).unwrap();
+        assert!((distorted - 0.10).abs() < 0.01, "distorted sine read {}", distorted);
+
+        // At the ADS1115's default 128 SPS the third harmonic is past Nyquist
+        assert_eq!(thd(&mains(60.0, 128.0, &[(3, 0.08)])), None);
+    }
+}
@@ -0,0 +1,277 @@
+//! Hardware-in-the-loop loopback: a bench node's radio talks to a second radio on the
+//! same node, or hears itself through a TX-to-RX loopback, so the SX126x driver's TX,
+//! RX, channel activity detection and IRQ handling can be checked on real hardware.
+
+use log::{info, warn};
+use serde::Serialize;
+use std::fmt;
+use std::time::{Duration, Instant};
+use crate::hal::{HalError, LoRaRadio};
+
+/// Marks a loopback test frame
+const MAGIC: &[u8; 4] = b"SGHL";
+/// Largest payload the SX126x sends in one packet
+const MAX_FRAME: usize = 255;
+/// Between polls of the receiver
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Frames sent when `--hil-loopback` is given no count
+pub const DEFAULT_FRAMES: u32 = 20;
+/// How long to wait for each frame to arrive
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Test frame `seq`: magic, sequence number, then a pattern. Lengths sweep from a
+/// short frame up to the full 255 bytes so FIFO and length handling get exercised.
+fn frame(seq: u32) -> Vec<u8> {
+    let len = if seq % 8 == 7 { MAX_FRAME } else { 16 + (seq as usize * 37) % (MAX_FRAME - 16) };
+    let mut frame = Vec::with_capacity(len);
+    frame.extend_from_slice(MAGIC);
+    frame.extend_from_slice(&seq.to_be_bytes());
+    frame.extend((frame.len()..len).map(|i| (seq as usize * 31 + i) as u8));
+    frame
+}
+
+/// Sequence number of a loopback frame, if that's what it is.
+fn sequence(frame: &[u8]) -> Option<u32> {
+    let seq = frame.strip_prefix(MAGIC)?.get(..4)?;
+    Some(u32::from_be_bytes(seq.try_into().unwrap()))
+}
+
+/// What a loopback run saw. Lost frames mean a receive (RX-done IRQ) never fired.
+#[derive(Debug, Default, Serialize)]
+pub struct LoopbackReport {
+    pub sent: u32,
+    pub received: u32,
+    pub lost: u32,
+    /// Arrived with different bytes than were sent
+    pub corrupted: u32,
+    /// Arrived after a later frame was expected (e.g. one given up on as lost)
+    pub late: u32,
+    /// False when the driver has no channel activity detection
+    pub cad_supported: bool,
+    /// CAD reported activity on a quiet channel
+    pub cad_false_alarms: u32,
+    /// CAD saw nothing while the other radio was transmitting
+    pub cad_missed: u32,
+    pub last_rssi: Option<i16>,
+    /// Longest time from the start of a transmission to its reception
+    pub max_latency_ms: u64,
+    /// Driver errors, in order
+    pub errors: Vec<String>,
+}
+
+impl LoopbackReport {
+    pub fn passed(&self) -> bool {
+        self.sent > 0
+            && self.received == self.sent
+            && self.corrupted == 0
+            && self.late == 0
+            && self.cad_false_alarms == 0
+            && self.cad_missed == 0
+            && self.errors.is_empty()
+    }
+
+    fn error(&mut self, what: &str, e: HalError) {
+        warn!("HIL: {} failed: {}", what, e);
+        self.errors.push(format!("{}: {}", what, e));
+    }
+
+    /// Run CAD on `radio`, recording whether it is supported; None once it isn't.
+    fn cad(&mut self, radio: &mut dyn LoRaRadio) -> Option<bool> {
+        if !self.cad_supported {
+            return None;
+        }
+        match radio.channel_activity() {
+            Ok(active) => Some(active),
+            Err(e @ HalError::Unsupported(_)) => {
+                info!("HIL: not checking CAD: {}", e);
+                self.cad_supported = false;
+                None
+            }
+            Err(e) => {
+                self.error("channel activity detection", e);
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Display for LoopbackReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}/{} received, {} lost, {} corrupted, {} late, max latency {} ms",
+            if self.passed() { "PASS" } else { "FAIL" },
+            self.received, self.sent, self.lost, self.corrupted, self.late, self.max_latency_ms)?;
+        if self.cad_supported {
+            write!(f, ", CAD {} missed / {} false alarms", self.cad_missed, self.cad_false_alarms)?;
+        } else {
+            write!(f, ", CAD not checked")?;
+        }
+        for error in &self.errors {
+            write!(f, "\n  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Send `frames` test frames from `tx` and check each arrives intact at `rx`, or back
+/// at `tx` itself without one. With two radios, CAD on the receiver must see each
+/// transmission in progress and nothing while the channel is quiet.
+pub fn run_loopback(tx: &mut dyn LoRaRadio, mut rx: Option<&mut dyn LoRaRadio>, frames: u32, timeout: Duration) -> LoopbackReport {
+    let mut report = LoopbackReport { cad_supported: true, ..Default::default() };
+    for seq in 0..frames {
+        let frame = frame(seq);
+        let listener: &mut dyn LoRaRadio = match rx.as_deref_mut() {
+            Some(rx) => rx,
+            None => &mut *tx,
+        };
+        if report.cad(listener) == Some(true) {
+            report.cad_false_alarms += 1;
+        }
+
+        let started = Instant::now();
+        report.sent += 1;
+        let sent = match rx.as_deref_mut() {
+            // Listen for the transmission while it is on the air
+            Some(rx) => std::thread::scope(|scope| {
+                let sending = scope.spawn(|| tx.transmit(&frame));
+                let mut heard = false;
+                while !heard && !sending.is_finished() {
+                    heard = report.cad(rx).unwrap_or(true);
+                }
+                // A transmission too short to catch mid-air is still in the receiver
+                heard = heard || report.cad(rx).unwrap_or(true);
+                if !heard {
+                    report.cad_missed += 1;
+                }
+                sending.join().unwrap()
+            }),
+            None => tx.transmit(&frame),
+        };
+        if let Err(e) = sent {
+            report.error(&format!("transmit frame {}", seq), e);
+            continue;
+        }
+
+        let receiver: &mut dyn LoRaRadio = match rx.as_deref_mut() {
+            Some(rx) => rx,
+            None => &mut *tx,
+        };
+        loop {
+            match receiver.receive() {
+                Ok(Some(received)) => match sequence(&received) {
+                    Some(n) if n == seq && received == frame => {
+                        report.received += 1;
+                        report.last_rssi = receiver.last_rssi();
+                        report.max_latency_ms = report.max_latency_ms.max(started.elapsed().as_millis() as u64);
+                        break;
+                    }
+                    Some(n) if n < seq => report.late += 1,
+                    _ => {
+                        warn!("HIL: frame {} arrived as {} bytes: {:02x?}", seq, received.len(), received);
+                        report.corrupted += 1;
+                        break;
+                    }
+                },
+                Ok(None) if started.elapsed() >= timeout => {
+                    warn!("HIL: frame {} not received within {:?}", seq, timeout);
+                    report.lost += 1;
+                    break;
+                }
+                Ok(None) => std::thread::sleep(POLL_INTERVAL),
+                Err(e) => {
+                    report.error(&format!("receive frame {}", seq), e);
+                    break;
+                }
+            }
+        }
+    }
+    report
+}
+
+/// Run the loopback on the bench radios, leaving them in standby.
+pub fn run_bench(mut tx: Box<dyn LoRaRadio>, mut rx: Option<Box<dyn LoRaRadio>>, frames: u32) -> LoopbackReport {
+    info!("HIL loopback: {} frames {}", frames, if rx.is_some() { "between two radios" } else { "looped back into one radio" });
+    let report = run_loopback(tx.as_mut(), rx.as_mut().map(|rx| rx.as_mut() as &mut dyn LoRaRadio), frames, DEFAULT_TIMEOUT);
+    for radio in std::iter::once(&mut tx).chain(rx.as_mut()) {
+        if let Err(e) = radio.standby() {
+            warn!("HIL: standby failed: {}", e);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::lora::mock::MockLoRaRadio;
+    use crate::hal::LoRaHalConfig;
+    use crate::hal::Result;
+
+    /// A radio whose RX loses every third frame and flips a bit in every fifth.
+    struct Flaky(MockLoRaRadio, u32);
+
+    impl LoRaRadio for Flaky {
+        fn transmit(&mut self, data: &[u8]) -> Result<()> {
+            self.0.transmit(data)
+        }
+
+        fn receive(&mut self) -> Result<Option<Vec<u8>>> {
+            let Some(mut frame) = self.0.receive()? else { return Ok(None) };
+            self.1 += 1;
+            if self.1.is_multiple_of(3) {
+                return Ok(None);
+            }
+            if self.1.is_multiple_of(5) {
+                frame[10] ^= 0x04;
+            }
+            Ok(Some(frame))
+        }
+
+        fn last_rssi(&self) -> Option<i16> {
+            self.0.last_rssi()
+        }
+
+        fn standby(&mut self) -> Result<()> {
+            self.0.standby()
+        }
+    }
+
+    #[test]
+    fn test_frames_sweep_to_the_largest_packet() {
+        let lengths: Vec<usize> = (0..DEFAULT_FRAMES).map(|seq| frame(seq).len()).collect();
+        assert!(lengths.iter().all(|&len| (16..=MAX_FRAME).contains(&len)));
+        assert!(lengths.contains(&MAX_FRAME));
+        assert_eq!(sequence(&frame(12)), Some(12));
+        assert_eq!(sequence(b"SGH"), None);
+    }
+
+    #[test]
+    fn test_loopback_passes_on_working_radios() {
+        let (mut a, mut b) = MockLoRaRadio::pair(LoRaHalConfig::default()).unwrap();
+        let report = run_loopback(&mut a, Some(&mut b), 10, Duration::from_millis(50));
+        assert!(report.passed(), "{}", report);
+        assert_eq!((report.sent, report.received, report.last_rssi), (10, 10, Some(-50)));
+        assert!(report.cad_supported);
+
+        let mut looped = MockLoRaRadio::looped(LoRaHalConfig::default()).unwrap();
+        let report = run_loopback(&mut looped, None, 10, Duration::from_millis(50));
+        assert!(report.passed(), "{}", report);
+    }
+
+    #[test]
+    fn test_loopback_catches_lost_and_corrupted_frames() {
+        let (mut a, b) = MockLoRaRadio::pair(LoRaHalConfig::default()).unwrap();
+        let mut flaky = Flaky(b, 0);
+        let report = run_loopback(&mut a, Some(&mut flaky), 10, Duration::from_millis(20));
+        assert!(!report.passed());
+        assert_eq!((report.lost, report.corrupted), (3, 2));
+        // The driver without CAD is reported, not failed
+        assert!(!report.cad_supported);
+
+        // Radios out of reach of each other: nothing arrives
+        let mut deaf = MockLoRaRadio::new(LoRaHalConfig::default()).unwrap();
+        let mut other = MockLoRaRadio::new(LoRaHalConfig::default()).unwrap();
+        let report = run_loopback(&mut deaf, Some(&mut other), 2, Duration::from_millis(5));
+        assert_eq!((report.lost, report.cad_missed), (2, 2));
+    }
+}
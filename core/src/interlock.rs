@@ -0,0 +1,416 @@
+use std::fmt;
+use crate::thermal::Derating;
+use crate::transfer::Supply;
+use crate::types::{MeshType, NodeState, Relay, RelayType};
+
+/// What the relay interlocks depend on, apart from the relays themselves.
+#[derive(Debug, Clone)]
+pub struct Conditions {
+    pub state: NodeState,
+    /// The emergency stop has tripped, whether or not the node has latched Faulted yet
+    pub estop_tripped: bool,
+    pub mesh_type: MeshType,
+    /// False behind a MID that has not permitted reconnection
+    pub grid_close_permitted: bool,
+    /// Watts our loads may draw while islanded, as granted by the coordinator
+    pub power_budget: Option<f32>,
+    pub voltage_ref: f32,
+    /// What closed load circuits measured exporting take off their rated draw
+    pub export_credit_watts: f32,
+    /// Relays of the transfer group and the source each connects; only one source is
+    /// ever connected at a time
+    pub transfer_relays: Vec<(String, Supply)>,
+    /// A source was disconnected less than the transfer dead time ago
+    pub transfer_dead_time: bool,
+    /// Relays opened on a fault or found failed, which only an operator may close again
+    pub locked_out: Vec<String>,
+    /// Limits on the relays while the board runs hot
+    pub derating: Option<Derating>,
+}
+
+/// Why an interlock kept a relay from closing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Refusal {
+    /// An AdHoc island must stay off the utility grid
+    Islanded,
+    /// The MID at our transformer has not permitted reconnection
+    MidNotPermitted,
+    /// The load would take us over the granted power budget
+    OverBudget { load_watts: f32, budget: f32 },
+    /// Another source is still connected through this transfer relay
+    SourceConnected(String),
+    /// The last source was disconnected less than the dead time ago
+    DeadTime,
+    /// The relay was opened on a fault that an operator has yet to clear
+    LockedOut,
+    /// The board is too hot for the relays to carry the load as well
+    Derated,
+    /// The emergency stop has latched the node until a fault reset
+    EmergencyStop,
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Refusal::Islanded => write!(f, "an AdHoc island stays off the utility grid"),
+            Refusal::MidNotPermitted => write!(f, "the MID has not permitted reconnection"),
+            Refusal::OverBudget { load_watts, budget } => write!(f, "{:.0} W would exceed the {:.0} W budget", load_watts, budget),
+            Refusal::SourceConnected(relay_id) => write!(f, "another source is still connected through {}", relay_id),
+            Refusal::DeadTime => write!(f, "waiting out the transfer dead time"),
+            Refusal::LockedOut => write!(f, "locked out after a fault until an operator closes it"),
+            Refusal::Derated => write!(f, "derated until the board cools"),
+            Refusal::EmergencyStop => write!(f, "latched by the emergency stop until a fault reset"),
+        }
+    }
+}
+
+/// Loads the power budget never sheds: our own Critical loads, and those the
+/// neighbourhood relies on, which the coordinator funded first.
+pub fn protected_from_budget(relay: &Relay) -> bool {
+    relay.priority == crate::types::Priority::Critical || relay.community_criticality > 0
+}
+
+/// Rated draw of the closed loads.
+pub fn load_watts(relays: &[Relay], voltage_ref: f32) -> f32 {
+    relays.iter()
+        .filter(|r| r.relay_type == RelayType::Load && r.is_closed)
+        .map(|r| r.amperage * voltage_ref)
+        .sum()
+}
+
+/// Whether `relay` may close now. Opening is always allowed.
+pub fn check_close(relays: &[Relay], relay: &Relay, conditions: &Conditions) -> Result<(), Refusal> {
+    if relay.is_closed {
+        return Ok(());
+    }
+    if conditions.state == NodeState::Faulted || conditions.estop_tripped {
+        return Err(Refusal::EmergencyStop);
+    }
+    if conditions.locked_out.contains(&relay.id) {
+        return Err(Refusal::LockedOut);
+    }
+    if let Some((_, supply)) = conditions.transfer_relays.iter().find(|(id, _)| *id == relay.id) {
+        if let Some((other, _)) = closed_transfer_relays(relays, conditions).find(|(_, s)| s != supply) {
+            return Err(Refusal::SourceConnected(other.clone()));
+        }
+        if conditions.transfer_dead_time {
+            return Err(Refusal::DeadTime);
+        }
+    }
+    match relay.relay_type {
+        RelayType::Grid if conditions.state == NodeState::Islanded && conditions.mesh_type == MeshType::AdHoc => {
+            Err(Refusal::Islanded)
+        }
+        RelayType::Grid if !conditions.grid_close_permitted => Err(Refusal::MidNotPermitted),
+        RelayType::Load => {
+            if let Some(derating) = &conditions.derating {
+                let amps = load_watts(relays, conditions.voltage_ref) / conditions.voltage_ref + relay.amperage;
+                if relay.amperage >= derating.high_current_amps || amps > derating.allowed_amps {
+                    return Err(Refusal::Derated);
+                }
+            }
+            let load_watts = load_watts(relays, conditions.voltage_ref) - conditions.export_credit_watts + relay.amperage * conditions.voltage_ref;
+            match conditions.power_budget {
+                Some(budget) if load_watts > budget => Err(Refusal::OverBudget { load_watts, budget }),
+                _ => Ok(()),
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Closed relays of the transfer group.
+fn closed_transfer_relays<'a>(relays: &'a [Relay], conditions: &'a Conditions) -> impl Iterator<Item = &'a (String, Supply)> {
+    conditions.transfer_relays.iter().filter(|(id, _)| relays.iter().any(|r| r.id == *id && r.is_closed))
+}
+
+/// Interlocks the relays break as they stand; none should, whatever the node was told
+/// or measured on the way there.
+pub fn violations(relays: &[Relay], conditions: &Conditions) -> Vec<String> {
+    let mut violations = Vec::new();
+    let mut transfer = closed_transfer_relays(relays, conditions);
+    if let Some((first, supply)) = transfer.next() {
+        for (other, _) in transfer.filter(|(_, s)| s != supply) {
+            violations.push(format!("transfer relays {} and {} closed together", first, other));
+        }
+    }
+    if conditions.state == NodeState::Islanded && conditions.mesh_type == MeshType::AdHoc {
+        for relay in relays.iter().filter(|r| r.relay_type == RelayType::Grid && r.is_closed) {
+            violations.push(format!("grid relay {} closed in an AdHoc island", relay.id));
+        }
+    }
+    for relay in relays.iter().filter(|r| r.is_closed && conditions.locked_out.contains(&r.id)) {
+        violations.push(format!("{} closed while locked out", relay.id));
+    }
+    if let Some(budget) = conditions.power_budget {
+        // Protected loads may run over a shrunken budget, but nothing else may stay on
+        let load = load_watts(relays, conditions.voltage_ref) - conditions.export_credit_watts;
+        if load > budget {
+            for relay in relays.iter().filter(|r| r.relay_type == RelayType::Load && r.is_closed && !protected_from_budget(r)) {
+                violations.push(format!("{} on with {:.0} W drawn against a {:.0} W budget", relay.id, load, budget));
+            }
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::SignatureStatus;
+    use crate::clock::VirtualClock;
+    use crate::comms::{
+        ActivateRelayByIndex, ActivateRelayByPriority, DisconnectGrid, EnterBlackStart, EnterIsland, IncomingCommand,
+        LoadShed, RebalanceDirective, ReceivedCommand,
+    };
+    use crate::node::{EdgeNode, Task};
+    use crate::ratelimit::{CommandLimiter, DEFAULT_LOCKOUT};
+    use crate::types::Priority;
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const NODE_ID: &str = "prop_node";
+
+    fn relay(id: &str, relay_type: RelayType, priority: Priority, amperage: f32, community_criticality: u8) -> Relay {
+        Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type,
+            priority,
+            amperage,
+            is_closed: true,
+            community_criticality,
+            phase: None,
+        }
+    }
+
+    /// A household with a grid tie, a battery and loads of every kind.
+    fn household() -> Vec<Relay> {
+        vec![
+            relay("r_grid", RelayType::Grid, Priority::Critical, 100.0, 0),
+            relay("r_battery", RelayType::Source, Priority::High, 30.0, 0),
+            relay("r_oxygen", RelayType::Load, Priority::Critical, 3.0, 3),
+            relay("r_fridge", RelayType::Load, Priority::High, 5.0, 0),
+            relay("r_pump", RelayType::Load, Priority::High, 8.0, 1),
+            relay("r_hvac", RelayType::Load, Priority::Medium, 20.0, 0),
+            relay("r_ev", RelayType::Load, Priority::Low, 32.0, 0),
+        ]
+    }
+
+    /// Something that happens to the node between checks of its interlocks.
+    #[derive(Debug, Clone)]
+    enum Step {
+        Voltage(f32),
+        LoadShed(bool),
+        EnterIsland,
+        EnterBlackStart,
+        ActivateIndex(u32),
+        ActivatePriority(i32),
+        DisconnectGrid,
+        Rebalance { open: Vec<usize>, close: Vec<usize> },
+        Grant(f32),
+        Tick(Task),
+    }
+
+    fn step() -> impl Strategy<Value = Step> {
+        let relay_count = household().len();
+        prop_oneof![
+            (90.0f32..130.0).prop_map(Step::Voltage),
+            any::<bool>().prop_map(Step::LoadShed),
+            Just(Step::EnterIsland),
+            Just(Step::EnterBlackStart),
+            (0..relay_count as u32 + 1).prop_map(Step::ActivateIndex),
+            (0..4).prop_map(Step::ActivatePriority),
+            Just(Step::DisconnectGrid),
+            (prop::collection::vec(0..relay_count, 0..3), prop::collection::vec(0..relay_count, 0..3))
+                .prop_map(|(open, close)| Step::Rebalance { open, close }),
+            (0.0f32..12_000.0).prop_map(Step::Grant),
+            prop::sample::select(vec![Task::Adc, Task::PowerSharing, Task::DemandResponse]).prop_map(Step::Tick),
+        ]
+    }
+
+    fn command(command: IncomingCommand) -> ReceivedCommand {
+        ReceivedCommand {
+            command,
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: None,
+            counter: 0,
+            co_signatures: None,
+            zone: None,
+        }
+    }
+
+    async fn apply(node: &mut EdgeNode, step: Step) {
+        let target_node_id = NODE_ID.to_string();
+        let ids = |indices: Vec<usize>| indices.into_iter().map(|i| household()[i].id.clone()).collect();
+        let incoming = match step {
+            Step::Voltage(volts) => {
+                node.voltage_ref = volts;
+                node.run_task(Task::Adc).await;
+                return;
+            }
+            Step::Grant(watts) => {
+                node.set_power_budget(watts).await;
+                return;
+            }
+            Step::Tick(task) => {
+                node.run_task(task).await;
+                return;
+            }
+            Step::Rebalance { open, close } => {
+                let directive = RebalanceDirective {
+                    target_node_id,
+                    directive_id: "d".to_string(),
+                    open_relays: ids(open),
+                    close_relays: ids(close),
+                    rollback: false,
+                };
+                let _ = node.apply_rebalance(&directive).await;
+                return;
+            }
+            Step::LoadShed(shed_load) => IncomingCommand::LoadShed(LoadShed { target_node_id, shed_load }),
+            Step::EnterIsland => IncomingCommand::EnterIsland(EnterIsland { target_node_id }),
+            Step::EnterBlackStart => IncomingCommand::EnterBlackStart(EnterBlackStart { target_node_id, step_id: String::new() }),
+            Step::ActivateIndex(relay_index) => {
+                IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex { target_node_id, relay_index, step_id: String::new() })
+            }
+            Step::ActivatePriority(priority) => {
+                IncomingCommand::ActivateRelayByPriority(ActivateRelayByPriority { target_node_id, priority, step_id: String::new() })
+            }
+            Step::DisconnectGrid => IncomingCommand::DisconnectGrid(DisconnectGrid { target_node_id }),
+        };
+        node.handle_command(command(incoming)).await;
+    }
+
+    fn conditions(node: &EdgeNode) -> Conditions {
+        Conditions {
+            state: node.state,
+            estop_tripped: false,
+            mesh_type: node.mesh_type.clone(),
+            grid_close_permitted: true,
+            power_budget: node.power_budget,
+            voltage_ref: node.voltage_ref,
+            export_credit_watts: 0.0,
+            transfer_relays: Vec::new(),
+            transfer_dead_time: false,
+            locked_out: Vec::new(),
+            derating: None,
+        }
+    }
+
+    #[test]
+    fn test_closing_is_refused_in_an_adhoc_island_and_over_budget() {
+        let mut relays = household();
+        for relay in relays.iter_mut().filter(|r| r.id == "r_grid" || r.id == "r_fridge") {
+            relay.is_closed = false;
+        }
+        let (grid, fridge) = (relays[0].clone(), relays[3].clone());
+        let mut conditions = Conditions {
+            state: NodeState::Islanded,
+            estop_tripped: false,
+            mesh_type: MeshType::AdHoc,
+            grid_close_permitted: true,
+            power_budget: None,
+            voltage_ref: 120.0,
+            export_credit_watts: 0.0,
+            transfer_relays: Vec::new(),
+            transfer_dead_time: false,
+            locked_out: Vec::new(),
+            derating: None,
+        };
+        assert_eq!(check_close(&relays, &grid, &conditions), Err(Refusal::Islanded));
+        conditions.mesh_type = MeshType::GovernmentSanctioned;
+        assert_eq!(check_close(&relays, &grid, &conditions), Ok(()));
+        conditions.grid_close_permitted = false;
+        assert_eq!(check_close(&relays, &grid, &conditions), Err(Refusal::MidNotPermitted));
+
+        // The closed loads already draw 63 A; 5 A more is 8160 W
+        conditions.power_budget = Some(8100.0);
+        assert!(matches!(check_close(&relays, &fridge, &conditions), Err(Refusal::OverBudget { .. })));
+        conditions.power_budget = Some(8160.0);
+        assert_eq!(check_close(&relays, &fridge, &conditions), Ok(()));
+        // A board derated to 60 A has no room for the fridge, nor does one with room that
+        // counts 5 A as high current
+        conditions.derating = Some(Derating { allowed_amps: 60.0, high_current_amps: 16.0 });
+        assert_eq!(check_close(&relays, &fridge, &conditions), Err(Refusal::Derated));
+        conditions.derating = Some(Derating { allowed_amps: 70.0, high_current_amps: 5.0 });
+        assert_eq!(check_close(&relays, &fridge, &conditions), Err(Refusal::Derated));
+        conditions.derating = None;
+        // A tripped stop holds everything open before the node has latched Faulted
+        conditions.estop_tripped = true;
+        assert_eq!(check_close(&relays, &fridge, &conditions), Err(Refusal::EmergencyStop));
+        conditions.estop_tripped = false;
+        assert_eq!(violations(&relays, &conditions), Vec::<String>::new());
+        conditions.power_budget = Some(1000.0);
+        // The oxygen concentrator and the well pump are protected; the HVAC and EV are not
+        assert_eq!(violations(&relays, &conditions).len(), 2);
+    }
+
+    #[test]
+    fn test_transfer_relays_never_connect_two_sources() {
+        let mut relays = household();
+        relays.push(relay("r_gen", RelayType::Source, Priority::High, 40.0, 0));
+        relays[7].is_closed = false;
+        let mut conditions = Conditions {
+            state: NodeState::Normal,
+            estop_tripped: false,
+            mesh_type: MeshType::AdHoc,
+            grid_close_permitted: true,
+            power_budget: None,
+            voltage_ref: 120.0,
+            export_credit_watts: 0.0,
+            transfer_relays: vec![("r_grid".to_string(), Supply::Grid), ("r_gen".to_string(), Supply::Generator)],
+            transfer_dead_time: false,
+            locked_out: Vec::new(),
+            derating: None,
+        };
+        assert_eq!(check_close(&relays, &relays[7], &conditions), Err(Refusal::SourceConnected("r_grid".to_string())));
+
+        relays[0].is_closed = false;
+        conditions.transfer_dead_time = true;
+        assert_eq!(check_close(&relays, &relays[7], &conditions), Err(Refusal::DeadTime));
+        conditions.transfer_dead_time = false;
+        assert_eq!(check_close(&relays, &relays[7], &conditions), Ok(()));
+        relays[7].is_closed = true;
+        assert_eq!(check_close(&relays, &relays[0], &conditions), Err(Refusal::SourceConnected("r_gen".to_string())));
+
+        relays[0].is_closed = true;
+        assert_eq!(violations(&relays, &conditions), vec!["transfer relays r_grid and r_gen closed together".to_string()]);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(256))]
+
+        #[test]
+        fn prop_interlocks_hold_whatever_the_node_is_told(
+            adhoc in any::<bool>(),
+            initially_closed in prop::collection::vec(any::<bool>(), 7),
+            steps in prop::collection::vec(step(), 1..40),
+        ) {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+            runtime.block_on(async {
+                let mut relays = household();
+                for (relay, closed) in relays.iter_mut().zip(initially_closed) {
+                    relay.is_closed = closed;
+                }
+                let mesh_type = if adhoc { MeshType::AdHoc } else { MeshType::GovernmentSanctioned };
+                let mut node = EdgeNode::builder(NODE_ID).relays(relays).mesh_type(mesh_type).build().unwrap();
+                let clock = VirtualClock::at(1_700_000_000);
+                node.set_clock(Arc::new(clock.clone()));
+                node.limiter = CommandLimiter::new(u32::MAX, HashMap::new(), DEFAULT_LOCKOUT);
+
+                for (i, step) in steps.into_iter().enumerate() {
+                    let description = format!("{:?}", step);
+                    apply(&mut node, step).await;
+                    clock.advance(Duration::from_secs(5));
+                    let violations = violations(&node.relays, &conditions(&node));
+                    prop_assert!(violations.is_empty(), "after step {} ({}) in {:?}: {:?}", i, description, node.state, violations);
+                }
+                Ok(())
+            })?;
+        }
+    }
+}
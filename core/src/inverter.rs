@@ -0,0 +1,185 @@
+use log::{info, warn};
+use crate::hal::inverter::InverterControl;
+
+/// Limits and reactive power are written in steps of this share of the inverter's
+/// maximum, so a wandering frequency or voltage doesn't rewrite them every reading
+const LIMIT_STEP: f32 = 0.01;
+
+/// Droop curves the inverter follows while islanded, so that it shares in holding the
+/// island's frequency and voltage rather than only being switched in and out.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Droop {
+    /// Frequency-watt: frequency at which curtailment starts, and where output reaches zero
+    pub curtail_hz: Option<(f32, f32)>,
+    /// Volt-watt: voltage at which curtailment starts, and where output reaches zero
+    pub curtail_volts: Option<(f32, f32)>,
+    /// Volt-var: distance from nominal voltage inside which no reactive power is asked
+    /// for, and at which all of it is, absorbed above nominal and supplied below
+    pub volt_var: Option<(f32, f32)>,
+}
+
+/// Share of the way from `start` to `stop` that `value` has gone, in steps of `LIMIT_STEP`.
+fn along(value: f32, (start, stop): (f32, f32)) -> f32 {
+    let fraction = (value - start) / (stop - start).max(f32::EPSILON);
+    (fraction.clamp(0.0, 1.0) / LIMIT_STEP).floor() * LIMIT_STEP
+}
+
+/// The household's solar inverter: its measured output feeds the node's sources, and
+/// while islanded it follows droop curves on the island's frequency and voltage. As the
+/// grid-forming inverter raises the frequency to signal that its battery is full
+/// (frequency-shift power control), or solar pushes the voltage up, it is curtailed;
+/// away from nominal voltage it absorbs or supplies reactive power to pull it back.
+pub struct SolarInverter {
+    control: Box<dyn InverterControl>,
+    droop: Droop,
+    limit: Option<f32>,
+    reactive: Option<f32>,
+    output_watts: Option<f32>,
+}
+
+impl SolarInverter {
+    pub fn new(control: Box<dyn InverterControl>, droop: Droop) -> Self {
+        Self { control, droop, limit: None, reactive: None, output_watts: None }
+    }
+
+    /// Last output read, None if the inverter isn't answering.
+    pub fn output_watts(&self) -> Option<f32> {
+        self.output_watts
+    }
+
+    pub fn limit(&self) -> Option<f32> {
+        self.limit
+    }
+
+    /// Reactive power asked for, as a share of the inverter's maximum; positive supplies it.
+    pub fn reactive(&self) -> Option<f32> {
+        self.reactive
+    }
+
+    /// Read the inverter's output now.
+    pub fn read(&mut self) -> Option<f32> {
+        self.output_watts = match self.control.ac_watts() {
+            Ok(watts) => Some(watts.max(0.0)),
+            Err(e) => {
+                warn!("Failed to read inverter output: {}", e);
+                None
+            }
+        };
+        self.output_watts
+    }
+
+    /// Set the inverter's power limit and reactive power for the island's frequency and
+    /// voltage. Output is the least the frequency-watt and volt-watt curves allow: full
+    /// up to their start, none at their stop, a straight line between. On the grid, the
+    /// utility's rules apply and both are lifted; so is frequency-watt without a reading.
+    pub fn regulate(&mut self, frequency: Option<f32>, volts: f32, nominal_volts: f32, islanded: bool) {
+        let (limit, reactive) = if islanded {
+            let curtailed = [
+                self.droop.curtail_hz.zip(frequency).map(|(curve, hz)| along(hz, curve)),
+                self.droop.curtail_volts.map(|curve| along(volts, curve)),
+            ];
+            let limit = curtailed.into_iter().flatten()
+                .filter(|share| *share > 0.0)
+                .map(|share| 1.0 - share)
+                .reduce(f32::min);
+            let deviation = volts - nominal_volts;
+            let reactive = self.droop.volt_var
+                .map(|curve| along(deviation.abs(), curve))
+                .filter(|share| *share > 0.0)
+                .map(|share| -share.copysign(deviation));
+            (limit, reactive)
+        } else {
+            (None, None)
+        };
+
+        if limit != self.limit {
+            match self.control.set_power_limit(limit) {
+                Ok(()) => {
+                    match limit {
+                        Some(fraction) => info!("Solar curtailed to {:.0}% at {:.2} Hz, {:.1} V", fraction * 100.0, frequency.unwrap_or_default(), volts),
+                        None => info!("Solar curtailment lifted"),
+                    }
+                    self.limit = limit;
+                }
+                Err(e) => warn!("Failed to set inverter limit: {}", e),
+            }
+        }
+        if reactive != self.reactive {
+            match self.control.set_reactive_power(reactive) {
+                Ok(()) => {
+                    match reactive {
+                        Some(fraction) if fraction < 0.0 => info!("Inverter absorbing {:.0}% of its reactive power at {:.1} V", -fraction * 100.0, volts),
+                        Some(fraction) => info!("Inverter supplying {:.0}% of its reactive power at {:.1} V", fraction * 100.0, volts),
+                        None => info!("Inverter back to unity power factor"),
+                    }
+                    self.reactive = reactive;
+                }
+                Err(e) => warn!("Failed to set inverter reactive power: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::inverter::mock::{MockInverter, MockInverterState};
+
+    #[test]
+    fn test_curtailed_along_the_frequency_droop_only_while_islanded() {
+        let control = MockInverter::new(5000.0);
+        let state = control.handle();
+        state.lock().unwrap().available_watts = 4000.0;
+        let droop = Droop { curtail_hz: Some((60.5, 61.5)), ..Default::default() };
+        let mut inverter = SolarInverter::new(Box::new(control), droop);
+        assert_eq!(inverter.read(), Some(4000.0));
+
+        inverter.regulate(Some(61.0), 120.0, 120.0, false);
+        assert_eq!(state.lock().unwrap().limit, None);
+
+        inverter.regulate(Some(60.4), 120.0, 120.0, true);
+        assert_eq!(inverter.limit(), None);
+        inverter.regulate(Some(61.0), 120.0, 120.0, true);
+        assert!((inverter.limit().unwrap() - 0.5).abs() < 1e-4);
+        assert_eq!(inverter.read(), Some(2500.0));
+        inverter.regulate(Some(62.0), 120.0, 120.0, true);
+        assert_eq!(inverter.limit(), Some(0.0));
+
+        // Back to nominal, or no reading at all: full output
+        inverter.regulate(None, 120.0, 120.0, true);
+        assert_eq!(state.lock().unwrap().limit, None);
+        assert_eq!(inverter.read(), Some(4000.0));
+    }
+
+    #[test]
+    fn test_voltage_droops_curtail_and_trade_reactive_power_while_islanded() {
+        let control = MockInverter::new(5000.0);
+        let state = control.handle();
+        let droop = Droop {
+            curtail_hz: Some((60.5, 61.5)),
+            curtail_volts: Some((126.0, 132.0)),
+            volt_var: Some((2.0, 6.0)),
+        };
+        let mut inverter = SolarInverter::new(Box::new(control), droop);
+
+        // Inside the deadband, nothing asked of it
+        inverter.regulate(Some(60.0), 121.0, 120.0, true);
+        assert_eq!((inverter.limit(), inverter.reactive()), (None, None));
+
+        // High voltage: absorb reactive power, and curtail by whichever droop bites harder
+        inverter.regulate(Some(60.7), 127.5, 120.0, true);
+        assert_eq!(state.lock().unwrap().reactive, Some(-1.0));
+        assert!((inverter.limit().unwrap() - 0.75).abs() < 1e-4);
+        inverter.regulate(Some(61.0), 127.5, 120.0, true);
+        assert!((inverter.limit().unwrap() - 0.5).abs() < 1e-4);
+
+        // Sagging: supply it
+        inverter.regulate(Some(60.0), 116.0, 120.0, true);
+        assert_eq!(inverter.limit(), None);
+        assert!((state.lock().unwrap().reactive.unwrap() - 0.5).abs() < 1e-4);
+
+        // Back on the grid, both are lifted
+        inverter.regulate(Some(60.0), 116.0, 120.0, false);
+        assert_eq!(*state.lock().unwrap(), MockInverterState { max_watts: 5000.0, ..Default::default() });
+    }
+}
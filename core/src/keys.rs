@@ -0,0 +1,441 @@
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use crate::hal::{HalError, SecureElement};
+
+/// Storage key of the persisted keyring (survives rotations across reboots).
+pub const KEYRING_KEY: &str = "keyring.json";
+/// Storage key of the keyring sealed under a secure-element-derived key.
+pub const SEALED_KEYRING_KEY: &str = "keyring.sealed.json";
+
+/// Challenge the secure element MACs to derive the key-encryption key.
+const KEK_CONTEXT: &[u8] = b"streetgrid-kek-v1";
+
+/// Label for the storage encryption key derived from the key-encryption key.
+const STORAGE_KEY_CONTEXT: &[u8] = b"streetgrid-storage-v1";
+
+/// Domain separator for TLS client keys distributed over the mesh.
+const TLS_KEY_CONTEXT: &[u8] = b"streetgrid-tls-v1";
+
+/// How long a superseded key is still accepted when a rotation doesn't say.
+pub const DEFAULT_GRACE_SECS: u32 = 3600;
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+
+/// Domain separator for keys derived from provisioning tokens.
+const JOIN_KEY_CONTEXT: &[u8] = b"streetgrid-join-v1";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Why a key could not be loaded, derived, unwrapped or sealed.
+#[derive(Debug, Error)]
+pub enum KeyError {
+    #[error("OS RNG failed: {0}")]
+    Rng(getrandom::Error),
+    #[error("secure element: {0}")]
+    SecureElement(#[from] HalError),
+    #[error("reading key file {path}: {source}")]
+    Read { path: String, source: std::io::Error },
+    /// A configured key or key file that isn't the hex encoding of 32 bytes
+    #[error("{0} must be {KEY_LEN} hex-encoded bytes")]
+    NotHex(&'static str),
+    #[error("nonce must be {NONCE_LEN} bytes")]
+    NonceLength,
+    /// A distributed key that doesn't decrypt under the key it was wrapped with
+    #[error("wrapped key failed authentication")]
+    WrappedKey,
+    #[error("unwrapped key is {0} bytes, expected {KEY_LEN}")]
+    KeyLength(usize),
+    #[error("unwrapped TLS key is not UTF-8")]
+    NotUtf8(#[from] std::string::FromUtf8Error),
+    #[error("key epoch {epoch} is not newer than current epoch {current}")]
+    StaleEpoch { epoch: u32, current: u32 },
+    #[error("sealed keyring failed authentication (different secure element?)")]
+    Unseal,
+    #[error("failed to seal keyring")]
+    Seal,
+    #[error("keyring encoding: {0}")]
+    Encoding(#[from] serde_json::Error),
+}
+
+pub type Result<T, E = KeyError> = std::result::Result<T, E>;
+
+/// Key derived from a node's provisioning token, used only while joining.
+pub fn join_key(token: &str) -> [u8; KEY_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(JOIN_KEY_CONTEXT);
+    hasher.update(token.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Proof of token possession sent in a JoinRequest (the token itself never goes on air).
+pub fn join_proof(token: &str, node_id: &str, timestamp: i64, nonce: &[u8]) -> Vec<u8> {
+    let key = EpochKey { epoch: 0, key: join_key(token) };
+    key.mac(&join_challenge(node_id, timestamp, nonce))
+}
+
+/// Bytes a JoinRequest's proof and device signature cover.
+pub fn join_challenge(node_id: &str, timestamp: i64, nonce: &[u8]) -> Vec<u8> {
+    let mut data = node_id.as_bytes().to_vec();
+    data.extend_from_slice(&timestamp.to_be_bytes());
+    data.extend_from_slice(nonce);
+    data
+}
+
+/// Fill `buf` from the secure element's RNG when present, the OS otherwise.
+pub fn random_bytes(se: Option<&mut (dyn SecureElement + '_)>, buf: &mut [u8]) -> Result<()> {
+    if let Some(se) = se {
+        for chunk in buf.chunks_mut(32) {
+            let random = se.random()?;
+            chunk.copy_from_slice(&random[..chunk.len()]);
+        }
+        return Ok(());
+    }
+    getrandom::getrandom(buf).map_err(KeyError::Rng)
+}
+
+/// Key-encryption key that only the secure element in `slot` can reproduce.
+pub fn derive_kek(se: &mut dyn SecureElement, slot: u8) -> Result<[u8; KEY_LEN]> {
+    let challenge: [u8; 32] = Sha256::digest(KEK_CONTEXT).into();
+    Ok(se.mac(slot, &challenge)?)
+}
+
+/// Key encrypting persisted data, derived from the secure element's key-encryption key
+/// so storage and keyring sealing never share a key.
+pub fn derive_storage_key(kek: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(kek).expect("HMAC accepts any key length");
+    mac.update(STORAGE_KEY_CONTEXT);
+    mac.finalize().into_bytes().into()
+}
+
+/// Read a 32-byte hex-encoded device key file (kept off the SD card, e.g. on a USB key).
+pub fn load_key_file(path: &str) -> Result<[u8; KEY_LEN]> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|source| KeyError::Read { path: path.to_string(), source })?;
+    let mut key = [0u8; KEY_LEN];
+    hex::decode_to_slice(contents.trim(), &mut key).map_err(|_| KeyError::NotHex("key file"))?;
+    Ok(key)
+}
+
+/// TLS client key (PEM) issued in a JoinAccept, wrapped under the provisioning token's join key.
+pub fn unwrap_join_tls_key(token: &str, node_id: &str, wrapped: &[u8], nonce: &[u8]) -> Result<String> {
+    let pem = unwrap_secret(&join_key(token), &tls_key_aad(node_id), wrapped, nonce)?;
+    Ok(String::from_utf8(pem)?)
+}
+
+/// A keyring encrypted at rest, so a cloned SD card is useless without the chip.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedKeyring {
+    #[serde(with = "hex::serde")]
+    nonce: [u8; NONCE_LEN],
+    #[serde(with = "hex::serde")]
+    ciphertext: Vec<u8>,
+}
+
+impl SealedKeyring {
+    pub fn unseal(&self, kek: &[u8; KEY_LEN]) -> Result<Keyring> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(kek));
+        let plain = cipher
+            .decrypt(Nonce::from_slice(&self.nonce), Payload { msg: &self.ciphertext, aad: KEK_CONTEXT })
+            .map_err(|_| KeyError::Unseal)?;
+        Ok(serde_json::from_slice(&plain)?)
+    }
+}
+
+/// AAD binding a join key to its epoch and the identity it was issued for.
+fn join_aad(epoch: u32, node_id: &str) -> Vec<u8> {
+    let mut aad = epoch.to_be_bytes().to_vec();
+    aad.extend_from_slice(node_id.as_bytes());
+    aad
+}
+
+/// AAD binding a distributed TLS client key to the node it was issued for.
+fn tls_key_aad(node_id: &str) -> Vec<u8> {
+    let mut aad = TLS_KEY_CONTEXT.to_vec();
+    aad.extend_from_slice(node_id.as_bytes());
+    aad
+}
+
+/// Decrypt a secret distributed under `wrapping_key`.
+fn unwrap_secret(wrapping_key: &[u8; KEY_LEN], aad: &[u8], wrapped: &[u8], nonce: &[u8]) -> Result<Vec<u8>> {
+    if nonce.len() != NONCE_LEN {
+        return Err(KeyError::NonceLength);
+    }
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(wrapping_key));
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: wrapped, aad })
+        .map_err(|_| KeyError::WrappedKey)
+}
+
+/// Decrypt a key distributed under `wrapping_key`.
+fn unwrap_key(wrapping_key: &[u8; KEY_LEN], aad: &[u8], wrapped: &[u8], nonce: &[u8]) -> Result<[u8; KEY_LEN]> {
+    let plain = unwrap_secret(wrapping_key, aad, wrapped, nonce)?;
+    plain.as_slice()
+        .try_into()
+        .map_err(|_| KeyError::KeyLength(plain.len()))
+}
+
+/// A mesh key and the epoch it belongs to. Epochs only ever increase.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct EpochKey {
+    pub epoch: u32,
+    #[serde(with = "hex::serde")]
+    key: [u8; KEY_LEN],
+}
+
+impl EpochKey {
+    fn mac(&self, data: &[u8]) -> Vec<u8> {
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    fn verify(&self, data: &[u8], tag: &[u8]) -> bool {
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(data);
+        mac.verify_slice(tag).is_ok()
+    }
+}
+
+impl std::fmt::Debug for EpochKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EpochKey({})", self.epoch)
+    }
+}
+
+/// Superseded key, accepted for inbound messages until the grace window closes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct RetiredKey {
+    key: EpochKey,
+    accept_until: i64,
+}
+
+/// Distributed key waiting for its activation time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PendingKey {
+    key: EpochKey,
+    activate_at: i64,
+    grace_secs: u32,
+}
+
+/// Mesh pre-shared keys: the active one, the one being rotated out, and the one rotating in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keyring {
+    current: EpochKey,
+    previous: Option<RetiredKey>,
+    pending: Option<PendingKey>,
+}
+
+impl Keyring {
+    pub fn new(epoch: u32, key: [u8; KEY_LEN]) -> Self {
+        Self {
+            current: EpochKey { epoch, key },
+            previous: None,
+            pending: None,
+        }
+    }
+
+    /// Build from the hex-encoded PSK in config.yaml.
+    pub fn from_hex(epoch: u32, psk: &str) -> Result<Self> {
+        let mut key = [0u8; KEY_LEN];
+        hex::decode_to_slice(psk.trim(), &mut key).map_err(|_| KeyError::NotHex("PSK"))?;
+        Ok(Self::new(epoch, key))
+    }
+
+    /// Build from the mesh key issued in a JoinAccept, wrapped under the provisioning token.
+    pub fn from_join(token: &str, assigned_node_id: &str, epoch: u32, wrapped: &[u8], nonce: &[u8]) -> Result<Self> {
+        let key = unwrap_key(&join_key(token), &join_aad(epoch, assigned_node_id), wrapped, nonce)?;
+        Ok(Self::new(epoch, key))
+    }
+
+    pub fn current_epoch(&self) -> u32 {
+        self.current.epoch
+    }
+
+    /// Key used for outbound messages at `now` (a pending key counts once its activation time passes).
+    fn active(&self, now: i64) -> &EpochKey {
+        match &self.pending {
+            Some(p) if now >= p.activate_at => &p.key,
+            _ => &self.current,
+        }
+    }
+
+    /// Epoch in use for outbound messages, as reported to the orchestrator.
+    pub fn active_epoch(&self, now: i64) -> u32 {
+        self.active(now).epoch
+    }
+
+    /// MAC `data` with the active key, returning the epoch used alongside the tag.
+    pub fn sign(&self, data: &[u8], now: i64) -> (u32, Vec<u8>) {
+        let key = self.active(now);
+        (key.epoch, key.mac(data))
+    }
+
+    /// Check a MAC made under `epoch`. The previous key is honoured during its grace window,
+    /// and a pending key from its activation time, so nodes and orchestrator needn't switch in lockstep.
+    pub fn verify(&self, epoch: u32, data: &[u8], tag: &[u8], now: i64) -> bool {
+        let key = if epoch == self.current.epoch {
+            Some(&self.current)
+        } else {
+            let retired = self.previous.as_ref()
+                .filter(|p| p.key.epoch == epoch && now <= p.accept_until)
+                .map(|p| &p.key);
+            let pending = self.pending.as_ref()
+                .filter(|p| p.key.epoch == epoch && now >= p.activate_at)
+                .map(|p| &p.key);
+            retired.or(pending)
+        };
+        key.is_some_and(|k| k.verify(data, tag))
+    }
+
+    /// Accept a new key wrapped (ChaCha20-Poly1305) under the current one, to activate at `activate_at`.
+    pub fn stage(&mut self, epoch: u32, wrapped: &[u8], nonce: &[u8], activate_at: i64, grace_secs: u32) -> Result<()> {
+        if epoch <= self.current.epoch {
+            return Err(KeyError::StaleEpoch { epoch, current: self.current.epoch });
+        }
+        let key = unwrap_key(&self.current.key, &epoch.to_be_bytes(), wrapped, nonce)?;
+
+        self.pending = Some(PendingKey {
+            key: EpochKey { epoch, key },
+            activate_at,
+            grace_secs,
+        });
+        Ok(())
+    }
+
+    /// TLS client key (PEM) delivered by a certificate rotation, wrapped under the current mesh key.
+    pub fn unwrap_tls_key(&self, node_id: &str, wrapped: &[u8], nonce: &[u8]) -> Result<String> {
+        let pem = unwrap_secret(&self.current.key, &tls_key_aad(node_id), wrapped, nonce)?;
+        Ok(String::from_utf8(pem)?)
+    }
+
+    /// Encrypt for storage under `kek` with a fresh `nonce`.
+    pub fn seal(&self, kek: &[u8; KEY_LEN], nonce: [u8; NONCE_LEN]) -> Result<SealedKeyring> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(kek));
+        let plain = serde_json::to_vec(self)?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &plain, aad: KEK_CONTEXT })
+            .map_err(|_| KeyError::Seal)?;
+        Ok(SealedKeyring { nonce, ciphertext })
+    }
+
+    /// Promote a due pending key and drop a retired key whose grace window has closed.
+    /// Returns true if the keyring changed and should be persisted.
+    pub fn advance(&mut self, now: i64) -> bool {
+        let mut changed = false;
+        if self.pending.as_ref().is_some_and(|p| now >= p.activate_at) {
+            let pending = self.pending.take().expect("checked above");
+            let retired = std::mem::replace(&mut self.current, pending.key);
+            self.previous = Some(RetiredKey {
+                key: retired,
+                accept_until: pending.activate_at + pending.grace_secs as i64,
+            });
+            changed = true;
+        }
+        if self.previous.as_ref().is_some_and(|p| now > p.accept_until) {
+            self.previous = None;
+            changed = true;
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: [u8; KEY_LEN] = [1; KEY_LEN];
+    const NEW: [u8; KEY_LEN] = [2; KEY_LEN];
+    const NONCE: [u8; NONCE_LEN] = [7; NONCE_LEN];
+
+    /// What the orchestrator does: wrap `new_key` under the key being rotated out.
+    fn wrap_key(wrapping_key: &[u8; KEY_LEN], epoch: u32, new_key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN]) -> Vec<u8> {
+        wrap_with_aad(wrapping_key, &epoch.to_be_bytes(), new_key, nonce)
+    }
+
+    fn wrap_with_aad(wrapping_key: &[u8; KEY_LEN], aad: &[u8], new_key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN]) -> Vec<u8> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(wrapping_key));
+        cipher
+            .encrypt(Nonce::from_slice(nonce), Payload { msg: new_key, aad })
+            .expect("encrypting a key cannot fail")
+    }
+
+    #[test]
+    fn test_rotation_with_grace_window() {
+        let mut ring = Keyring::new(1, OLD);
+        let (epoch, old_tag) = ring.sign(b"hello", 0);
+        assert_eq!(epoch, 1);
+
+        let wrapped = wrap_key(&OLD, 2, &NEW, &NONCE);
+        ring.stage(2, &wrapped, &NONCE, 100, 50).unwrap();
+        assert_eq!(ring.active_epoch(99), 1);
+        assert_eq!(ring.active_epoch(100), 2);
+
+        assert!(ring.advance(100));
+        assert_eq!(ring.current_epoch(), 2);
+        let (_, new_tag) = ring.sign(b"hello", 100);
+
+        // Both keys are accepted during the grace window, only the new one after it
+        assert!(ring.verify(1, b"hello", &old_tag, 150));
+        assert!(ring.verify(2, b"hello", &new_tag, 150));
+        assert!(!ring.verify(2, b"hello", &old_tag, 150));
+        assert!(ring.advance(151));
+        assert!(!ring.verify(1, b"hello", &old_tag, 151));
+        assert!(!ring.advance(151));
+    }
+
+    #[test]
+    fn test_stage_rejects_bad_wrapping() {
+        let mut ring = Keyring::new(3, OLD);
+
+        // Wrapped under the wrong key
+        let wrapped = wrap_key(&NEW, 4, &NEW, &NONCE);
+        assert!(ring.stage(4, &wrapped, &NONCE, 0, 0).is_err());
+
+        // Epoch in the AAD doesn't match the announced epoch
+        let wrapped = wrap_key(&OLD, 5, &NEW, &NONCE);
+        assert!(ring.stage(4, &wrapped, &NONCE, 0, 0).is_err());
+
+        // Epochs never go backwards
+        let wrapped = wrap_key(&OLD, 3, &NEW, &NONCE);
+        assert!(ring.stage(3, &wrapped, &NONCE, 0, 0).is_err());
+        assert_eq!(ring.active_epoch(0), 3);
+    }
+
+    #[test]
+    fn test_join_key_is_bound_to_token_and_identity() {
+        let wrapped = wrap_with_aad(&join_key("tok-123"), &join_aad(1, "node_07"), &NEW, &NONCE);
+
+        let ring = Keyring::from_join("tok-123", "node_07", 1, &wrapped, &NONCE).unwrap();
+        assert_eq!(ring, Keyring::new(1, NEW));
+        assert!(Keyring::from_join("wrong", "node_07", 1, &wrapped, &NONCE).is_err());
+        assert!(Keyring::from_join("tok-123", "node_08", 1, &wrapped, &NONCE).is_err());
+
+        assert_eq!(join_proof("tok-123", "node_07", 5, b"n"), join_proof("tok-123", "node_07", 5, b"n"));
+        assert_ne!(join_proof("tok-123", "node_07", 5, b"n"), join_proof("tok-123", "node_07", 6, b"n"));
+        assert_ne!(join_proof("tok-123", "node_07", 5, b"n"), join_proof("tok-123", "node_07", 5, b"m"));
+    }
+
+    #[test]
+    fn test_sealed_keyring_needs_the_same_chip() {
+        use crate::hal::secure_element::mock::MockSecureElement;
+
+        let ring = Keyring::new(2, NEW);
+        let mut se = MockSecureElement::new([1; 32]);
+        let kek = derive_kek(&mut se, 2).unwrap();
+        let mut nonce = [0u8; NONCE_LEN];
+        random_bytes(Some(&mut se), &mut nonce).unwrap();
+
+        let sealed = ring.seal(&kek, nonce).unwrap();
+        let json = serde_json::to_vec(&sealed).unwrap();
+        let stored: SealedKeyring = serde_json::from_slice(&json).unwrap();
+        assert_eq!(stored.unseal(&kek).unwrap(), ring);
+
+        let other_kek = derive_kek(&mut MockSecureElement::new([2; 32]), 2).unwrap();
+        assert!(stored.unseal(&other_kek).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+//! Node logic of a StreetGrid edge node, independent of any one board.
+//!
+//! The `streetgrid-firmware` binary is a thin shell around this crate; integrators building their own
+//! hardware variants depend on it directly. The entry points are:
+//!
+//! - [`types`]: relays, priorities, node states and roles shared by everything else
+//! - [`node`]: [`node::EdgeNode`], the state machine that reads sensors, switches relays and answers the mesh,
+//!   assembled with [`node::EdgeNodeBuilder`]
+//! - [`comms`]: the wire messages (generated from `proto/neighborhood.proto`) and the
+//!   [`comms::CommunicationLayer`] transports they travel over
+//! - [`hal`]: the hardware traits ([`hal::RelayControl`], [`hal::PowerSensor`], radios, gauges) with mock and
+//!   Raspberry Pi implementations
+//! - [`config`]: the YAML configuration a node is built from
+//! - [`boot`]: assembling a node, its drivers and features from that configuration
+//!
+//! Hardware, transports and the node fail with [`hal::HalError`], [`comms::CommsError`] and
+//! [`node::NodeError`], whose `is_transient` tells a busy radio worth retrying from a pin that was never set up.
+//!
+//! The remaining modules are the individual features the node composes.
+
+pub mod types;
+pub mod node;
+pub mod config;
+pub mod boot;
+pub mod comms;
+pub mod hal;
+pub mod sysinfo;
+pub mod events;
+pub mod api;
+pub mod storage;
+pub mod export;
+pub mod audit;
+pub mod measurements;
+pub mod snapshot;
+pub mod anomaly;
+pub mod load_profile;
+pub mod keys;
+pub mod ratelimit;
+pub mod ota;
+pub mod session;
+pub mod replay;
+pub mod tls;
+pub mod mqtt;
+pub mod serial;
+pub mod multisig;
+pub mod neighbors;
+pub mod delivery;
+pub mod link_budget;
+pub mod quorum;
+pub mod election;
+pub mod failover;
+pub mod power;
+pub mod sources;
+pub mod evse;
+pub mod inverter;
+pub mod generator;
+pub mod transfer;
+pub mod tariff;
+pub mod forecast;
+pub mod demand_response;
+pub mod cycling;
+pub mod shedding;
+pub mod harmonics;
+pub mod arc_fault;
+pub mod relay_health;
+pub mod thermal;
+pub mod pickup;
+pub mod motors;
+pub mod estop;
+pub mod reserve;
+pub mod submeter;
+pub mod outages;
+pub mod phases;
+pub mod energy;
+pub mod mid;
+pub mod repeater;
+pub mod security;
+pub mod clock;
+pub mod scenario;
+pub mod medium;
+pub mod channel;
+pub mod faults;
+pub mod interlock;
+pub mod protection;
+pub mod disturbance;
+pub mod load_model;
+pub mod capture;
+pub mod fuzz;
+pub mod hil;
+pub mod rftest;
+#[cfg(test)]
+mod conformance;
@@ -0,0 +1,150 @@
+use crate::hal::LoRaHalConfig;
+use crate::neighbors::Neighbor;
+
+/// Fade margin below which a link is flagged: rain, foliage and parked vans take
+/// this much off a link that works on a clear day
+pub const DEFAULT_MIN_FADE_MARGIN_DB: f32 = 10.0;
+
+/// Noise figure of the SX126x receive chain
+const NOISE_FIGURE_DB: f32 = 6.0;
+
+/// Thermal noise density at room temperature, dBm/Hz
+const THERMAL_NOISE_DBM_HZ: f32 = -174.0;
+
+/// Path loss exponent of a residential street (2 in free space, 3 to 4 through buildings)
+const PATH_LOSS_EXPONENT: f32 = 2.7;
+
+/// Lowest SNR the demodulator still decodes at a spreading factor (SX126x datasheet).
+pub fn required_snr_db(spreading_factor: u8) -> f32 {
+    match spreading_factor {
+        ..=5 => -2.5,
+        6 => -5.0,
+        7 => -7.5,
+        8 => -10.0,
+        9 => -12.5,
+        10 => -15.0,
+        11 => -17.5,
+        _ => -20.0,
+    }
+}
+
+/// What the radio settings allow: the weakest signal still received, and the path loss
+/// a transmission can take before it is lost.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkBudget {
+    pub frequency: u64,
+    pub required_snr_db: f32,
+    pub sensitivity_dbm: f32,
+    /// Gain of the antenna at either end
+    pub antenna_gain_dbi: f32,
+    /// Transmit power radiated by the antenna
+    pub eirp_dbm: f32,
+    pub max_path_loss_db: f32,
+}
+
+/// Estimated headroom on the link to one neighbour.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkMargin {
+    pub node_id: String,
+    pub rssi: i16,
+    /// dB the signal could fade before packets are lost
+    pub margin_db: f32,
+    /// Distance a signal this strong suggests, by the street's path loss model
+    pub estimated_distance_m: f32,
+}
+
+impl LinkBudget {
+    /// Budget of two identical nodes with `antenna_gain_dbi` antennas.
+    pub fn new(config: &LoRaHalConfig, antenna_gain_dbi: f32) -> Self {
+        let required_snr_db = required_snr_db(config.spreading_factor);
+        let noise_floor = THERMAL_NOISE_DBM_HZ + 10.0 * (config.bandwidth.max(1) as f32).log10() + NOISE_FIGURE_DB;
+        let sensitivity_dbm = noise_floor + required_snr_db;
+        let eirp_dbm = config.tx_power as f32 + antenna_gain_dbi;
+        Self {
+            frequency: config.frequency,
+            required_snr_db,
+            sensitivity_dbm,
+            antenna_gain_dbi,
+            eirp_dbm,
+            max_path_loss_db: eirp_dbm + antenna_gain_dbi - sensitivity_dbm,
+        }
+    }
+
+    /// Free-space loss over the first metre at our frequency.
+    fn reference_loss_db(&self) -> f32 {
+        20.0 * (self.frequency.max(1) as f32).log10() - 147.55
+    }
+
+    /// Distance at which a signal arrives with `margin_db` to spare.
+    pub fn range_m(&self, margin_db: f32) -> f32 {
+        let loss = self.max_path_loss_db - margin_db - self.reference_loss_db();
+        10f32.powf(loss / (10.0 * PATH_LOSS_EXPONENT)).max(1.0)
+    }
+
+    /// How far a received signal is above what we can still decode. The SNR limit
+    /// wins where the band is noisy, so a measured SNR counts too.
+    pub fn margin_db(&self, rssi: i16, snr: Option<f32>) -> f32 {
+        let by_rssi = rssi as f32 - self.sensitivity_dbm;
+        match snr {
+            Some(snr) => by_rssi.min(snr - self.required_snr_db),
+            None => by_rssi,
+        }
+    }
+
+    /// Margin to each neighbour we have a signal reading for.
+    pub fn margins(&self, neighbors: &[Neighbor]) -> Vec<LinkMargin> {
+        neighbors.iter()
+            .filter_map(|n| {
+                let rssi = n.rssi?;
+                let loss = self.eirp_dbm + self.antenna_gain_dbi - rssi as f32;
+                Some(LinkMargin {
+                    node_id: n.node_id.clone(),
+                    rssi,
+                    margin_db: self.margin_db(rssi, n.snr),
+                    estimated_distance_m: 10f32.powf((loss - self.reference_loss_db()) / (10.0 * PATH_LOSS_EXPONENT)).max(1.0),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn neighbor(node_id: &str, rssi: i16, snr: Option<f32>) -> Neighbor {
+        Neighbor { node_id: node_id.to_string(), rssi: Some(rssi), snr, packets: 1, ..Default::default() }
+    }
+
+    #[test]
+    fn test_budget_follows_the_radio_settings() {
+        let sf7 = LinkBudget::new(&LoRaHalConfig::default(), 0.0);
+        // SF7 at 125 kHz: -174 + 51 + 6 - 7.5
+        assert!((sf7.sensitivity_dbm - -124.5).abs() < 0.1, "{}", sf7.sensitivity_dbm);
+        assert!((sf7.max_path_loss_db - 138.5).abs() < 0.1);
+
+        // Each step up in spreading factor buys 2.5 dB and a longer reach
+        let sf12 = LinkBudget::new(&LoRaHalConfig { spreading_factor: 12, ..Default::default() }, 0.0);
+        assert!((sf7.sensitivity_dbm - sf12.sensitivity_dbm - 12.5).abs() < 0.1);
+        assert!(sf12.range_m(DEFAULT_MIN_FADE_MARGIN_DB) > sf7.range_m(DEFAULT_MIN_FADE_MARGIN_DB));
+        // Asking for more margin shortens it
+        assert!(sf7.range_m(0.0) > sf7.range_m(DEFAULT_MIN_FADE_MARGIN_DB));
+    }
+
+    #[test]
+    fn test_margins_per_neighbor() {
+        let budget = LinkBudget::new(&LoRaHalConfig::default(), 0.0);
+        let neighbors = vec![
+            neighbor("node_02", -90, None),
+            // Strong but noisy: the SNR limit is the closer one
+            neighbor("node_03", -100, Some(-5.0)),
+            Neighbor { node_id: "node_04".to_string(), packets: 1, ..Default::default() },
+        ];
+        let margins = budget.margins(&neighbors);
+        assert_eq!(margins.len(), 2);
+        assert!((margins[0].margin_db - 34.5).abs() < 0.1);
+        assert!((margins[1].margin_db - 2.5).abs() < 0.1);
+        // Weaker signals come from further away
+        assert!(margins[1].estimated_distance_m > margins[0].estimated_distance_m);
+    }
+}
@@ -0,0 +1,114 @@
+use serde::Deserialize;
+
+/// How a household circuit draws current once its relay closes, for simulated CT
+/// clamps, named by `model`. Currents are amps; times are seconds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum LoadModel {
+    /// A steady draw
+    Constant { amps: f32 },
+    /// A compressor cycling `on` then `off` under its thermostat, starting as power
+    /// returns; each start draws `inrush_amps` for `inrush` while the motor spins up
+    Fridge {
+        amps: f32,
+        on: f32,
+        off: f32,
+        #[serde(default)]
+        inrush_amps: f32,
+        #[serde(default = "default_inrush")]
+        inrush: f32,
+    },
+    /// A compressor running the `duty` share (0..1) of every `period`, and the air
+    /// handler's `fan_amps` throughout
+    Hvac {
+        amps: f32,
+        duty: f32,
+        period: f32,
+        #[serde(default)]
+        fan_amps: f32,
+    },
+    /// A charging car: the charger ramps to `amps` over `ramp`, holds it for `bulk`
+    /// while the battery fills, then the current decays with time constant `taper`
+    /// until it falls below a tenth of `amps` and charging ends
+    EvCharger {
+        amps: f32,
+        bulk: f32,
+        taper: f32,
+        #[serde(default = "default_ramp")]
+        ramp: f32,
+    },
+}
+
+fn default_inrush() -> f32 {
+    1.0
+}
+
+fn default_ramp() -> f32 {
+    10.0
+}
+
+/// Share of its full current below which a car's charge counts as complete
+const EV_CUTOFF: f32 = 0.1;
+
+impl LoadModel {
+    /// Current drawn `t` seconds after the circuit was switched on.
+    pub fn amps_at(&self, t: f32) -> f32 {
+        let t = t.max(0.0);
+        match *self {
+            LoadModel::Constant { amps } => amps,
+            LoadModel::Fridge { amps, on, off, inrush_amps, inrush } => {
+                let phase = t % (on + off).max(f32::EPSILON);
+                if phase >= on {
+                    0.0
+                } else if phase < inrush {
+                    inrush_amps.max(amps)
+                } else {
+                    amps
+                }
+            }
+            LoadModel::Hvac { amps, duty, period, fan_amps } => {
+                let running = t % period.max(f32::EPSILON) < duty.clamp(0.0, 1.0) * period;
+                fan_amps + if running { amps } else { 0.0 }
+            }
+            LoadModel::EvCharger { amps, bulk, taper, ramp } => {
+                if t < ramp {
+                    amps * t / ramp
+                } else if t < ramp + bulk {
+                    amps
+                } else {
+                    let tapered = amps * (-(t - ramp - bulk) / taper.max(f32::EPSILON)).exp();
+                    if tapered < EV_CUTOFF * amps { 0.0 } else { tapered }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_shapes() {
+        let fridge = LoadModel::Fridge { amps: 1.5, on: 600.0, off: 1200.0, inrush_amps: 8.0, inrush: 1.0 };
+        assert_eq!(fridge.amps_at(0.5), 8.0);
+        assert_eq!(fridge.amps_at(300.0), 1.5);
+        assert_eq!(fridge.amps_at(900.0), 0.0);
+        // Next cycle starts again with the inrush
+        assert_eq!(fridge.amps_at(1800.2), 8.0);
+
+        let hvac = LoadModel::Hvac { amps: 15.0, duty: 0.25, period: 1200.0, fan_amps: 2.0 };
+        assert_eq!(hvac.amps_at(100.0), 17.0);
+        assert_eq!(hvac.amps_at(400.0), 2.0);
+        let mean = (0..1200).map(|s| hvac.amps_at(s as f32)).sum::<f32>() / 1200.0;
+        assert!((mean - (2.0 + 15.0 * 0.25)).abs() < 0.1);
+
+        let ev = LoadModel::EvCharger { amps: 32.0, bulk: 3600.0, taper: 900.0, ramp: 10.0 };
+        assert_eq!(ev.amps_at(5.0), 16.0);
+        assert_eq!(ev.amps_at(1000.0), 32.0);
+        assert!((ev.amps_at(3610.0 + 900.0) - 32.0 / std::f32::consts::E).abs() < 0.01);
+        // Done once the taper falls below a tenth: ln(10) time constants in
+        assert!(ev.amps_at(3610.0 + 2000.0) > 3.2);
+        assert_eq!(ev.amps_at(3610.0 + 2100.0), 0.0);
+    }
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Storage key of the learned profiles.
+pub const PROFILE_KEY: &str = "load_profile.json";
+
+const HOURS: usize = 24;
+
+/// Samples after which a bucket behaves like a moving average: at the 5 s ADC interval
+/// an hour bucket collects 720 samples per day, so this keeps roughly two weeks of memory.
+const MAX_WEIGHT: u32 = 720 * 14;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+struct HourBucket {
+    mean_watts: f32,
+    samples: u32,
+}
+
+/// Typical consumption per relay for each local hour of the day, learned from CT readings.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoadProfile {
+    relays: BTreeMap<String, Vec<HourBucket>>,
+}
+
+impl LoadProfile {
+    /// Fold a reading into the relay's bucket for `hour` (0-23, local time).
+    pub fn record(&mut self, relay_id: &str, hour: u32, watts: f32) {
+        let buckets = self.relays
+            .entry(relay_id.to_string())
+            .or_insert_with(|| vec![HourBucket::default(); HOURS]);
+        let Some(bucket) = buckets.get_mut(hour as usize) else { return };
+
+        bucket.samples = (bucket.samples + 1).min(MAX_WEIGHT);
+        bucket.mean_watts += (watts - bucket.mean_watts) / bucket.samples as f32;
+    }
+
+    /// Mean watts for each hour of the day, or None if the relay has never been sampled.
+    pub fn hourly_watts(&self, relay_id: &str) -> Option<Vec<f32>> {
+        self.relays.get(relay_id).map(|b| b.iter().map(|h| h.mean_watts).collect())
+    }
+
+    /// Mean watts of a relay at `hour`, or None if that hour has never been sampled.
+    pub fn expected_watts(&self, relay_id: &str, hour: u32) -> Option<f32> {
+        let bucket = self.relays.get(relay_id)?.get(hour as usize)?;
+        (bucket.samples > 0).then_some(bucket.mean_watts)
+    }
+
+    /// Number of samples behind each relay's profile (capped per bucket).
+    pub fn sample_counts(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.relays.iter().map(|(id, b)| (id.as_str(), b.iter().map(|h| h.samples).sum()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_learns_hourly_mean() {
+        let mut profile = LoadProfile::default();
+        profile.record("r_hvac", 14, 2000.0);
+        profile.record("r_hvac", 14, 3000.0);
+        profile.record("r_hvac", 3, 100.0);
+        profile.record("r_hvac", 24, 9999.0); // Out of range, ignored
+
+        let hourly = profile.hourly_watts("r_hvac").unwrap();
+        assert_eq!(hourly.len(), 24);
+        assert_eq!(hourly[14], 2500.0);
+        assert_eq!(hourly[3], 100.0);
+        assert_eq!(hourly[0], 0.0);
+        assert_eq!((profile.expected_watts("r_hvac", 3), profile.expected_watts("r_hvac", 0)), (Some(100.0), None));
+        assert_eq!(profile.hourly_watts("r_aux"), None);
+        assert_eq!(profile.sample_counts().collect::<Vec<_>>(), vec![("r_hvac", 3)]);
+    }
+
+    #[test]
+    fn test_old_history_is_discounted() {
+        let mut profile = LoadProfile::default();
+        for _ in 0..MAX_WEIGHT {
+            profile.record("r_aux", 20, 100.0);
+        }
+        // A new habit shifts the mean even after the bucket saturates
+        for _ in 0..MAX_WEIGHT {
+            profile.record("r_aux", 20, 500.0);
+        }
+        assert!(profile.hourly_watts("r_aux").unwrap()[20] > 300.0);
+    }
+}
@@ -0,0 +1,305 @@
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, NaiveDate, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::storage::{Result, Storage};
+use crate::sysinfo;
+
+const RAW_DIR: &str = "measurements/raw";
+const HOURLY_DIR: &str = "measurements/hourly";
+
+/// How long measurement data is kept on the SD card.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    /// Days of raw samples to keep before rolling them up into hourly summaries
+    pub raw_days: u32,
+    /// Months of hourly rollups to keep
+    pub hourly_months: u32,
+    /// Hard cap on measurement data; oldest files are dropped first when exceeded
+    pub max_bytes: Option<u64>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            raw_days: 7,
+            hourly_months: 12,
+            max_bytes: None,
+        }
+    }
+}
+
+/// One ADC reading.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub timestamp: i64,
+    pub channel: u8,
+    pub watts: f32,
+}
+
+/// Summary of one channel over one hour.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HourlyRollup {
+    /// Unix timestamp of the start of the hour (UTC)
+    pub hour: i64,
+    pub channel: u8,
+    pub samples: u32,
+    pub mean_watts: f32,
+    pub min_watts: f32,
+    pub max_watts: f32,
+}
+
+/// Measurement history on local storage: raw samples in one file per day,
+/// hourly rollups in one file per month.
+pub struct MeasurementStore {
+    storage: Arc<Storage>,
+    policy: RetentionPolicy,
+}
+
+impl MeasurementStore {
+    pub fn new(storage: Arc<Storage>, policy: RetentionPolicy) -> Self {
+        Self { storage, policy }
+    }
+
+    pub fn record(&self, sample: &Sample) -> Result<()> {
+        let mut line = serde_json::to_vec(sample)?;
+        line.push(b'\n');
+        self.storage.append(&raw_key(day_of(sample.timestamp)), &line)
+    }
+
+    /// Apply the retention policy as of `now`: roll up and delete expired raw days,
+    /// delete expired rollup months, then enforce the size cap.
+    /// Returns the bytes of measurement data left on disk.
+    pub fn compact(&self, now: DateTime<Utc>) -> Result<u64> {
+        let today = now.date_naive();
+        let raw_cutoff = today - ChronoDuration::days(self.policy.raw_days as i64);
+
+        for key in self.storage.list(RAW_DIR)? {
+            let Some(day) = parse_raw_key(&key) else { continue };
+            // Never today's file, which samples are still being appended to
+            if day < raw_cutoff && day < today {
+                self.roll_up_day(&key, day)?;
+                info!("Compacted raw measurements for {}", day);
+            }
+        }
+
+        let month_cutoff = months_before(today, self.policy.hourly_months);
+        for key in self.storage.list(HOURLY_DIR)? {
+            let Some(month) = parse_hourly_key(&key) else { continue };
+            if month < month_cutoff {
+                self.storage.remove(&key)?;
+                info!("Dropped hourly rollups for {}", month.format("%Y-%m"));
+            }
+        }
+
+        // Make removals real before measuring what is left
+        self.storage.flush()?;
+        let mut used = self.storage.disk_usage(Some("measurements"))?;
+
+        if let Some(max_bytes) = self.policy.max_bytes {
+            // Oldest raw day first, then oldest rollup month; never today's raw file
+            let today_key = raw_key(today);
+            let mut victims: Vec<String> = self.storage.list(RAW_DIR)?
+                .into_iter()
+                .filter(|k| *k != today_key)
+                .collect();
+            victims.extend(self.storage.list(HOURLY_DIR)?);
+            for key in victims {
+                if used <= max_bytes {
+                    break;
+                }
+                warn!("Measurement storage over {} bytes, dropping {}", max_bytes, key);
+                self.storage.remove(&key)?;
+                self.storage.flush()?;
+                used = self.storage.disk_usage(Some("measurements"))?;
+            }
+        }
+
+        sysinfo::set_storage_used(self.storage.disk_usage(None)?);
+        Ok(used)
+    }
+
+    /// Hourly rollups stored for the month containing `month`.
+    pub fn hourly(&self, month: NaiveDate) -> Result<Vec<HourlyRollup>> {
+        parse_lines(self.storage.get(&hourly_key(month))?)
+    }
+
+    /// Background task running compaction every `interval`.
+    pub async fn run_compaction(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval.max(Duration::from_secs(1)));
+        loop {
+            ticker.tick().await;
+            let store = self.clone();
+            match tokio::task::spawn_blocking(move || store.compact(Utc::now())).await {
+                Ok(Ok(used)) => info!("Measurement storage: {} kB in use", used / 1024),
+                Ok(Err(e)) => warn!("Measurement compaction failed: {}", e),
+                Err(e) => warn!("Measurement compaction task panicked: {}", e),
+            }
+        }
+    }
+
+    /// Replace a raw day file with its hourly rollups. The file is read and removed in
+    /// one step: a late sample recorded meanwhile starts a new file rather than being lost.
+    fn roll_up_day(&self, key: &str, day: NaiveDate) -> Result<()> {
+        let samples: Vec<Sample> = parse_lines(self.storage.take(key)?)?;
+        let rollups = roll_up(&samples);
+        if rollups.is_empty() {
+            return Ok(());
+        }
+        let mut lines = Vec::new();
+        for rollup in &rollups {
+            lines.extend(serde_json::to_vec(rollup)?);
+            lines.push(b'\n');
+        }
+        self.storage.append(&hourly_key(day), &lines)
+    }
+}
+
+/// Aggregate samples into per-hour, per-channel summaries.
+fn roll_up(samples: &[Sample]) -> Vec<HourlyRollup> {
+    let mut buckets: BTreeMap<(i64, u8), HourlyRollup> = BTreeMap::new();
+    for s in samples {
+        let hour = s.timestamp - s.timestamp.rem_euclid(3600);
+        let bucket = buckets.entry((hour, s.channel)).or_insert(HourlyRollup {
+            hour,
+            channel: s.channel,
+            samples: 0,
+            mean_watts: 0.0,
+            min_watts: f32::MAX,
+            max_watts: f32::MIN,
+        });
+        bucket.samples += 1;
+        bucket.mean_watts += (s.watts - bucket.mean_watts) / bucket.samples as f32;
+        bucket.min_watts = bucket.min_watts.min(s.watts);
+        bucket.max_watts = bucket.max_watts.max(s.watts);
+    }
+    buckets.into_values().collect()
+}
+
+/// Parse newline-delimited JSON, skipping torn or corrupt lines.
+fn parse_lines<T: serde::de::DeserializeOwned>(data: Option<Vec<u8>>) -> Result<Vec<T>> {
+    let Some(data) = data else { return Ok(Vec::new()) };
+    Ok(data.split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| serde_json::from_slice(line).ok())
+        .collect())
+}
+
+fn day_of(timestamp: i64) -> NaiveDate {
+    DateTime::from_timestamp(timestamp, 0).unwrap_or_default().date_naive()
+}
+
+fn raw_key(day: NaiveDate) -> String {
+    format!("{}/{}.ndjson", RAW_DIR, day.format("%Y-%m-%d"))
+}
+
+fn hourly_key(day: NaiveDate) -> String {
+    format!("{}/{}.ndjson", HOURLY_DIR, day.format("%Y-%m"))
+}
+
+fn parse_raw_key(key: &str) -> Option<NaiveDate> {
+    let name = key.strip_prefix(RAW_DIR)?.strip_prefix('/')?.strip_suffix(".ndjson")?;
+    NaiveDate::parse_from_str(name, "%Y-%m-%d").ok()
+}
+
+/// First day of the month a rollup file covers.
+fn parse_hourly_key(key: &str) -> Option<NaiveDate> {
+    let name = key.strip_prefix(HOURLY_DIR)?.strip_prefix('/')?.strip_suffix(".ndjson")?;
+    NaiveDate::parse_from_str(&format!("{}-01", name), "%Y-%m-%d").ok()
+}
+
+/// First day of the month `months` before the month containing `day`.
+fn months_before(day: NaiveDate, months: u32) -> NaiveDate {
+    let total = day.year() * 12 + day.month0() as i32 - months as i32;
+    NaiveDate::from_ymd_opt(total.div_euclid(12), total.rem_euclid(12) as u32 + 1, 1).unwrap_or(day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn temp_storage(name: &str) -> Arc<Storage> {
+        let dir = std::env::temp_dir().join(format!("streetgrid-meas-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        Storage::open(dir, Duration::ZERO).unwrap()
+    }
+
+    #[test]
+    fn test_roll_up_averages_per_hour_and_channel() {
+        let samples = vec![
+            Sample { timestamp: 3600, channel: 0, watts: 100.0 },
+            Sample { timestamp: 3700, channel: 0, watts: 300.0 },
+            Sample { timestamp: 3700, channel: 1, watts: 50.0 },
+            Sample { timestamp: 7200, channel: 0, watts: 10.0 },
+        ];
+        let rollups = roll_up(&samples);
+        assert_eq!(rollups.len(), 3);
+        assert_eq!(rollups[0].hour, 3600);
+        assert_eq!(rollups[0].samples, 2);
+        assert!((rollups[0].mean_watts - 200.0).abs() < 0.01);
+        assert_eq!(rollups[0].max_watts, 300.0);
+        assert_eq!(rollups[1].channel, 1);
+        assert_eq!(rollups[2].hour, 7200);
+    }
+
+    #[test]
+    fn test_compaction_applies_retention() {
+        let storage = temp_storage("retention");
+        let store = MeasurementStore::new(storage.clone(), RetentionPolicy {
+            raw_days: 2,
+            hourly_months: 1,
+            max_bytes: None,
+        });
+        let now = Utc.with_ymd_and_hms(2025, 3, 10, 12, 0, 0).unwrap();
+        let day = |d: u32| Utc.with_ymd_and_hms(2025, 3, d, 8, 30, 0).unwrap().timestamp();
+
+        store.record(&Sample { timestamp: day(5), channel: 0, watts: 500.0 }).unwrap();
+        store.record(&Sample { timestamp: day(9), channel: 0, watts: 700.0 }).unwrap();
+        // Rollups from two months ago are past retention
+        storage.put(&format!("{}/2025-01.ndjson", HOURLY_DIR), b"{}\\n".to_vec()).unwrap();
+
+        store.compact(now).unwrap();
+
+        let raw = storage.list(RAW_DIR).unwrap();
+        assert_eq!(raw, vec![format!("{}/2025-03-09.ndjson", RAW_DIR)]);
+        assert_eq!(storage.list(HOURLY_DIR).unwrap(), vec![format!("{}/2025-03.ndjson", HOURLY_DIR)]);
+
+        let hourly = store.hourly(now.date_naive()).unwrap();
+        assert_eq!(hourly.len(), 1);
+        assert_eq!(hourly[0].hour, Utc.with_ymd_and_hms(2025, 3, 5, 8, 0, 0).unwrap().timestamp());
+        assert_eq!(hourly[0].mean_watts, 500.0);
+    }
+
+    #[test]
+    fn test_size_cap_drops_oldest_first() {
+        let storage = temp_storage("cap");
+        let store = MeasurementStore::new(storage.clone(), RetentionPolicy {
+            raw_days: 30,
+            hourly_months: 12,
+            max_bytes: Some(100),
+        });
+        let now = Utc.with_ymd_and_hms(2025, 3, 10, 12, 0, 0).unwrap();
+        for d in [8, 9, 10] {
+            for i in 0..2 {
+                let ts = Utc.with_ymd_and_hms(2025, 3, d, 1, i, 0).unwrap().timestamp();
+                store.record(&Sample { timestamp: ts, channel: 0, watts: 1.0 }).unwrap();
+            }
+        }
+
+        let used = store.compact(now).unwrap();
+        assert!(used <= 100);
+        let raw = storage.list(RAW_DIR).unwrap();
+        assert!(raw.contains(&format!("{}/2025-03-10.ndjson", RAW_DIR)));
+        assert!(!raw.contains(&format!("{}/2025-03-08.ndjson", RAW_DIR)));
+    }
+
+    #[test]
+    fn test_months_before() {
+        let d = NaiveDate::from_ymd_opt(2025, 2, 14).unwrap();
+        assert_eq!(months_before(d, 0), NaiveDate::from_ymd_opt(2025, 2, 1).unwrap());
+        assert_eq!(months_before(d, 3), NaiveDate::from_ymd_opt(2024, 11, 1).unwrap());
+    }
+}
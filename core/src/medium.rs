@@ -0,0 +1,255 @@
+use async_trait::async_trait;
+use prost::Message;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use crate::comms::{CommsError, CommunicationLayer, LinkStats, NeighborhoodMessage, Result};
+
+/// Radio conditions of a simulated street. Without any, every station hears every
+/// other and nothing is lost.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RadioConfig {
+    /// How far a transmission carries, in metres
+    #[serde(default = "default_range")]
+    pub range_m: f32,
+    /// Chance each copy of a message is lost on its way to a receiver, 0 to 1
+    #[serde(default)]
+    pub loss: f64,
+    /// Whether transmissions a receiver hears in the same slot garble each other there
+    #[serde(default)]
+    pub collisions: bool,
+    /// Seed for losses and boot times, so a run can be repeated exactly
+    #[serde(default = "default_seed")]
+    pub seed: u64,
+}
+
+fn default_range() -> f32 {
+    f32::INFINITY
+}
+
+fn default_seed() -> u64 {
+    1
+}
+
+impl Default for RadioConfig {
+    fn default() -> Self {
+        Self { range_m: default_range(), loss: 0.0, collisions: false, seed: default_seed() }
+    }
+}
+
+/// Deterministic pseudo-random numbers (SplitMix64); the simulation must not depend
+/// on the system's entropy.
+#[derive(Debug)]
+pub struct SimRng(u64);
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Signal strength at `distance_m`, from a log-distance path loss model.
+fn rssi_at(distance_m: f32) -> i16 {
+    (-40.0 - 27.0 * distance_m.max(1.0).log10()) as i16
+}
+
+#[derive(Debug)]
+struct Station {
+    id: String,
+    position: [f32; 2],
+    up: bool,
+    inbox: VecDeque<(NeighborhoodMessage, i16)>,
+    stats: LinkStats,
+}
+
+#[derive(Debug)]
+struct Air {
+    stations: Vec<Station>,
+    /// Sent since the last propagation: transmitting station and message
+    in_flight: Vec<(usize, NeighborhoodMessage)>,
+    /// Station that first sent each message, so forwarded copies keep their origin
+    origins: HashMap<[u8; 32], usize>,
+    rng: SimRng,
+}
+
+/// A shared RF channel between simulated stations. Transmissions are collected
+/// until `propagate`, which delivers each one to the stations in range.
+#[derive(Debug)]
+pub struct Medium {
+    config: RadioConfig,
+    air: Mutex<Air>,
+}
+
+impl Medium {
+    pub fn new(config: RadioConfig) -> Arc<Self> {
+        let rng = SimRng::new(config.seed);
+        Arc::new(Self { config, air: Mutex::new(Air { stations: Vec::new(), in_flight: Vec::new(), origins: HashMap::new(), rng }) })
+    }
+
+    /// Place a station at `position` (metres) and hand out its radio.
+    pub fn join(self: &Arc<Self>, id: &str, position: [f32; 2]) -> Arc<MediumLink> {
+        let mut air = self.air.lock().unwrap();
+        air.stations.push(Station { id: id.to_string(), position, up: true, inbox: VecDeque::new(), stats: LinkStats::default() });
+        Arc::new(MediumLink { medium: self.clone(), station: air.stations.len() - 1 })
+    }
+
+    /// Switch a station's radio off or on; while off it neither sends nor hears.
+    pub fn set_up(&self, id: &str, up: bool) {
+        for station in self.air.lock().unwrap().stations.iter_mut().filter(|s| s.id == id) {
+            station.up = up;
+        }
+    }
+
+    /// Deliver everything sent since the last call.
+    pub fn propagate(&self) {
+        let mut air = self.air.lock().unwrap();
+        let in_flight = std::mem::take(&mut air.in_flight);
+        let positions: Vec<[f32; 2]> = air.stations.iter().map(|s| s.position).collect();
+        let distance = |a: usize, b: usize| {
+            let (pa, pb) = (positions[a], positions[b]);
+            ((pa[0] - pb[0]).powi(2) + (pa[1] - pb[1]).powi(2)).sqrt()
+        };
+        for receiver in 0..positions.len() {
+            if !air.stations[receiver].up {
+                continue;
+            }
+            let audible: Vec<&(usize, NeighborhoodMessage)> = in_flight.iter()
+                .filter(|(from, _)| *from != receiver && distance(*from, receiver) <= self.config.range_m)
+                .collect();
+            if self.config.collisions && audible.len() > 1 {
+                continue;
+            }
+            for (from, msg) in audible {
+                if air.rng.next_f64() < self.config.loss {
+                    continue;
+                }
+                let rssi = rssi_at(distance(*from, receiver));
+                air.stations[receiver].inbox.push_back((msg.clone(), rssi));
+            }
+        }
+    }
+
+    /// Station that first transmitted `msg`, by ID.
+    pub fn origin(&self, msg: &NeighborhoodMessage) -> Option<String> {
+        let air = self.air.lock().unwrap();
+        air.origins.get(&digest(msg)).map(|&station| air.stations[station].id.clone())
+    }
+
+    /// Forget where messages came from; origins are only needed until they are read.
+    pub fn clear_origins(&self) {
+        self.air.lock().unwrap().origins.clear();
+    }
+}
+
+/// Same for every copy of a frame, however many repeaters it went through.
+fn digest(msg: &NeighborhoodMessage) -> [u8; 32] {
+    Sha256::digest(NeighborhoodMessage { relay_hops: 0, ..msg.clone() }.encode_to_vec()).into()
+}
+
+/// One station's radio on a `Medium`.
+#[derive(Debug)]
+pub struct MediumLink {
+    medium: Arc<Medium>,
+    station: usize,
+}
+
+#[async_trait]
+impl CommunicationLayer for MediumLink {
+    async fn send(&self, msg: NeighborhoodMessage) -> Result<()> {
+        let mut air = self.medium.air.lock().unwrap();
+        if !air.stations[self.station].up {
+            return Err(CommsError::LinkDown("radio"));
+        }
+        air.origins.entry(digest(&msg)).or_insert(self.station);
+        air.stations[self.station].stats.tx_packets += 1;
+        air.in_flight.push((self.station, msg));
+        Ok(())
+    }
+
+    async fn receive(&self) -> Result<Option<NeighborhoodMessage>> {
+        let mut air = self.medium.air.lock().unwrap();
+        let station = &mut air.stations[self.station];
+        let Some((msg, rssi)) = station.inbox.pop_front() else {
+            return Ok(None);
+        };
+        station.stats.rx_packets += 1;
+        station.stats.last_rssi = Some(rssi);
+        Ok(Some(msg))
+    }
+
+    fn link_stats(&self) -> LinkStats {
+        let air = self.medium.air.lock().unwrap();
+        let station = &air.stations[self.station];
+        LinkStats { queue_depth: station.inbox.len() as u32, ..station.stats.clone() }
+    }
+
+    fn name(&self) -> &'static str {
+        "sim"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comms::streetgrid::neighborhood_message::Payload;
+    use crate::comms::Heartbeat;
+
+    fn heartbeat(node_id: &str) -> NeighborhoodMessage {
+        NeighborhoodMessage {
+            payload: Some(Payload::Heartbeat(Heartbeat { node_id: node_id.to_string(), ..Default::default() })),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_range_collisions_and_loss() {
+        let medium = Medium::new(RadioConfig { range_m: 150.0, collisions: true, ..Default::default() });
+        let a = medium.join("a", [0.0, 0.0]);
+        let b = medium.join("b", [100.0, 0.0]);
+        let c = medium.join("c", [200.0, 0.0]);
+
+        // Out of range of a, c only hears b; a's copy at b is forwarded with a as origin
+        a.send(heartbeat("a")).await.unwrap();
+        medium.propagate();
+        assert!(c.receive().await.unwrap().is_none());
+        let heard = b.receive().await.unwrap().unwrap();
+        assert_eq!(b.link_stats().last_rssi, Some(-94));
+        b.send(heard.clone()).await.unwrap();
+        medium.propagate();
+        assert_eq!(c.receive().await.unwrap(), Some(heard.clone()));
+        assert_eq!(medium.origin(&heard).as_deref(), Some("a"));
+
+        // a and c transmitting together garble each other at b, but not at the far ends
+        a.send(heartbeat("a")).await.unwrap();
+        c.send(heartbeat("c")).await.unwrap();
+        medium.propagate();
+        assert!(b.receive().await.unwrap().is_none());
+
+        medium.set_up("b", false);
+        assert!(b.send(heartbeat("b")).await.is_err());
+
+        let lossy = Medium::new(RadioConfig { loss: 0.5, seed: 7, ..Default::default() });
+        let tx = lossy.join("tx", [0.0, 0.0]);
+        let rx = lossy.join("rx", [10.0, 0.0]);
+        for _ in 0..1000 {
+            tx.send(heartbeat("tx")).await.unwrap();
+            lossy.propagate();
+        }
+        let received = rx.link_stats().queue_depth;
+        assert!((400..600).contains(&received), "{} of 1000 received", received);
+    }
+}
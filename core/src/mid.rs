@@ -0,0 +1,130 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use prost::Message;
+use std::collections::HashMap;
+use thiserror::Error;
+use crate::comms::MidStatus;
+
+/// How often a MID broadcasts its status.
+pub const MID_STATUS_INTERVAL_SECS: u64 = 30;
+
+/// Status older than this is treated as unknown: the MID may be down.
+pub const MID_STATUS_TTL_SECS: i64 = 3 * MID_STATUS_INTERVAL_SECS as i64;
+
+/// Why a MID status was not recorded.
+#[derive(Debug, Error, PartialEq)]
+pub enum MidError {
+    #[error("no key held for MID {0}")]
+    UnknownMid(String),
+    #[error("status from MID {0} is not signed by its key")]
+    BadSignature(String),
+    #[error("status from MID {0} is stale")]
+    Stale(String),
+    #[error("status from MID {0} is no newer than the last")]
+    Replayed(String),
+}
+
+pub type Result<T, E = MidError> = std::result::Result<T, E>;
+
+/// Latest status heard from each MID whose key we hold. A MID signs its status with its
+/// own Ed25519 key, which no other node has, so holding the mesh key is not enough to
+/// lift the Grid relay interlock.
+#[derive(Debug, Default)]
+pub struct MidTable {
+    keys: HashMap<String, VerifyingKey>,
+    statuses: HashMap<String, (MidStatus, i64)>,
+}
+
+impl MidTable {
+    /// Accept statuses from `mid_id` signed with `key`.
+    pub fn trust(&mut self, mid_id: &str, key: VerifyingKey) {
+        self.keys.insert(mid_id.to_string(), key);
+    }
+
+    /// Record a status if its MID signed it, sent it within the TTL and counted past its last one.
+    pub fn record(&mut self, status: MidStatus, now: i64) -> Result<()> {
+        let Some(key) = self.keys.get(&status.mid_id) else {
+            return Err(MidError::UnknownMid(status.mid_id));
+        };
+        if !verify(&status, key) {
+            return Err(MidError::BadSignature(status.mid_id));
+        }
+        if (now - status.timestamp).abs() > MID_STATUS_TTL_SECS {
+            return Err(MidError::Stale(status.mid_id));
+        }
+        if self.statuses.get(&status.mid_id).is_some_and(|(last, _)| status.counter <= last.counter) {
+            return Err(MidError::Replayed(status.mid_id));
+        }
+        self.statuses.insert(status.mid_id.clone(), (status, now));
+        Ok(())
+    }
+
+    /// The MID's status, if heard recently enough to act on.
+    pub fn fresh(&self, mid_id: &str, now: i64) -> Option<&MidStatus> {
+        self.statuses.get(mid_id)
+            .filter(|(_, heard)| now - heard <= MID_STATUS_TTL_SECS)
+            .map(|(status, _)| status)
+    }
+}
+
+/// What a MID signs: its status encoded without the signature.
+fn signed_bytes(status: &MidStatus) -> Vec<u8> {
+    MidStatus { signature: Vec::new(), ..status.clone() }.encode_to_vec()
+}
+
+/// Sign `status` as the MID holding `key`.
+pub fn sign(status: &mut MidStatus, key: &SigningKey) {
+    status.signature = key.sign(&signed_bytes(status)).to_bytes().to_vec();
+}
+
+fn verify(status: &MidStatus, key: &VerifyingKey) -> bool {
+    Signature::from_slice(&status.signature).is_ok_and(|sig| key.verify_strict(&signed_bytes(status), &sig).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed(isolated: bool, counter: u64, timestamp: i64, key: &SigningKey) -> MidStatus {
+        let mut status = MidStatus { mid_id: "mid_t1".to_string(), isolated, counter, timestamp, ..Default::default() };
+        sign(&mut status, key);
+        status
+    }
+
+    #[test]
+    fn test_stale_status_is_unknown() {
+        let key = SigningKey::from_bytes(&[5; 32]);
+        let mut table = MidTable::default();
+        table.trust("mid_t1", key.verifying_key());
+        table.record(signed(true, 1, 100, &key), 100).unwrap();
+        assert!(table.fresh("mid_t1", 100 + MID_STATUS_TTL_SECS).unwrap().isolated);
+        assert!(table.fresh("mid_t1", 101 + MID_STATUS_TTL_SECS).is_none());
+        assert!(table.fresh("mid_t2", 100).is_none());
+    }
+
+    #[test]
+    fn test_only_fresh_statuses_signed_by_the_mid_count() {
+        let key = SigningKey::from_bytes(&[5; 32]);
+        let mesh_node = SigningKey::from_bytes(&[6; 32]);
+        let mut table = MidTable::default();
+        table.trust("mid_t1", key.verifying_key());
+
+        // A node with the mesh key forging reconnection, unsigned or with its own key
+        let mut forged = MidStatus { mid_id: "mid_t1".to_string(), reconnect_permitted: true, counter: 1, timestamp: 100, ..Default::default() };
+        assert_eq!(table.record(forged.clone(), 100), Err(MidError::BadSignature("mid_t1".to_string())));
+        sign(&mut forged, &mesh_node);
+        assert_eq!(table.record(forged, 100), Err(MidError::BadSignature("mid_t1".to_string())));
+        assert!(table.fresh("mid_t1", 100).is_none());
+
+        // The MID's own, replayed from an earlier outage or out of order
+        let old = signed(false, 1, 100, &key);
+        assert_eq!(table.record(old.clone(), 100 + MID_STATUS_TTL_SECS + 1), Err(MidError::Stale("mid_t1".to_string())));
+        table.record(signed(true, 5, 200, &key), 200).unwrap();
+        assert_eq!(table.record(signed(false, 4, 200, &key), 201), Err(MidError::Replayed("mid_t1".to_string())));
+        assert_eq!(table.record(signed(false, 5, 200, &key), 201), Err(MidError::Replayed("mid_t1".to_string())));
+        assert!(table.fresh("mid_t1", 201).unwrap().isolated);
+
+        let mut other = MidStatus { mid_id: "mid_t2".to_string(), counter: 1, timestamp: 100, ..Default::default() };
+        sign(&mut other, &key);
+        assert_eq!(table.record(other, 100), Err(MidError::UnknownMid("mid_t2".to_string())));
+    }
+}
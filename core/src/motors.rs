@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Trigger the starter closes and opens relays under, which it alone may use
+pub const MOTOR_START: &str = "motor_start";
+
+fn default_soft_start_secs() -> i64 {
+    5
+}
+
+fn default_min_off_secs() -> i64 {
+    180
+}
+
+fn default_stagger_secs() -> i64 {
+    15
+}
+
+/// A load driven by a motor (well pump, compressor), whose inrush on starting can stall
+/// an island inverter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MotorLoad {
+    /// Relay feeding the motor through a starting resistor or soft starter, closed
+    /// first and opened once the main relay has taken over
+    pub soft_start_relay: Option<String>,
+    /// How long the soft-start relay carries the motor (default 5 s)
+    #[serde(default = "default_soft_start_secs")]
+    pub soft_start_secs: i64,
+    /// Least time off before it may start again, for a compressor's pressures to
+    /// equalise (default 180 s)
+    #[serde(default = "default_min_off_secs")]
+    pub min_off_secs: i64,
+}
+
+/// Motor loads, by relay, and how far apart they start.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MotorSettings {
+    pub loads: BTreeMap<String, MotorLoad>,
+    /// Least time between one motor starting and the next (default 15 s)
+    #[serde(default = "default_stagger_secs")]
+    pub stagger_secs: i64,
+}
+
+/// A motor running up on its soft-start relay.
+#[derive(Debug, Clone, PartialEq)]
+struct SoftStart {
+    relay_id: String,
+    soft_start_relay: String,
+    since: i64,
+}
+
+/// Starts motor loads one at a time, each through its soft-start relay if it has one,
+/// and none sooner than its minimum off-time after it last stopped.
+#[derive(Debug)]
+pub struct MotorStarter {
+    pub settings: MotorSettings,
+    /// Motors waiting to start, in the order asked
+    waiting: VecDeque<String>,
+    stopped_at: HashMap<String, i64>,
+    last_start: Option<i64>,
+    soft_start: Option<SoftStart>,
+}
+
+impl MotorStarter {
+    /// Starter as of boot at `now`: a motor that lost power with the node is treated as
+    /// just stopped, so it doesn't restart straight into its own back-pressure.
+    pub fn new(settings: MotorSettings, now: i64) -> Self {
+        let stopped_at = settings.loads.keys().map(|id| (id.clone(), now)).collect();
+        Self { settings, waiting: VecDeque::new(), stopped_at, last_start: None, soft_start: None }
+    }
+
+    pub fn is_motor(&self, relay_id: &str) -> bool {
+        self.settings.loads.contains_key(relay_id)
+    }
+
+    pub fn is_soft_start_relay(&self, relay_id: &str) -> bool {
+        self.settings.loads.values().any(|m| m.soft_start_relay.as_deref() == Some(relay_id))
+    }
+
+    /// Put a motor in line to start, after those already waiting.
+    pub fn queue(&mut self, relay_id: &str) {
+        let starting = self.soft_start.as_ref().is_some_and(|s| s.relay_id == relay_id);
+        if !starting && !self.waiting.iter().any(|id| id == relay_id) {
+            self.waiting.push_back(relay_id.to_string());
+        }
+    }
+
+    /// Forget the motors waiting, when they are to stay off after all.
+    pub fn clear(&mut self) {
+        self.waiting.clear();
+    }
+
+    /// Record a motor switched off at `now` (`running` if it was on), dropping it from
+    /// the line. Returns its soft-start relay to open if it was part way through starting.
+    pub fn stopped(&mut self, relay_id: &str, running: bool, now: i64) -> Option<String> {
+        self.waiting.retain(|id| id != relay_id);
+        let soft_start = self.soft_start.take_if(|s| s.relay_id == relay_id);
+        if running || soft_start.is_some() {
+            self.stopped_at.insert(relay_id.to_string(), now);
+        }
+        soft_start.map(|s| s.soft_start_relay)
+    }
+
+    /// Relays to switch at `now` (closed or not): the motor on its soft-start relay
+    /// handed over to the main relay once run up, or the next motor due started.
+    pub fn tick(&mut self, now: i64) -> Vec<(String, bool)> {
+        if let Some(soft_start) = &self.soft_start {
+            let secs = self.settings.loads.get(&soft_start.relay_id).map_or(0, |m| m.soft_start_secs);
+            if now - soft_start.since < secs {
+                return Vec::new();
+            }
+            let soft_start = self.soft_start.take().unwrap();
+            return vec![(soft_start.relay_id, true), (soft_start.soft_start_relay, false)];
+        }
+        if self.last_start.is_some_and(|at| now - at < self.settings.stagger_secs) {
+            return Vec::new();
+        }
+
+        let rested = |id: &String| {
+            let min_off = self.settings.loads.get(id).map_or(0, |m| m.min_off_secs);
+            self.stopped_at.get(id).is_none_or(|at| now - at >= min_off)
+        };
+        let Some(index) = self.waiting.iter().position(rested) else { return Vec::new() };
+        let relay_id = self.waiting.remove(index).unwrap();
+        self.last_start = Some(now);
+        match self.settings.loads.get(&relay_id).and_then(|m| m.soft_start_relay.clone()) {
+            Some(soft_start_relay) => {
+                self.soft_start = Some(SoftStart { relay_id, soft_start_relay: soft_start_relay.clone(), since: now });
+                vec![(soft_start_relay, true)]
+            }
+            None => vec![(relay_id, true)],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_motors_start_staggered_soft_and_rested() {
+        let settings = MotorSettings {
+            loads: BTreeMap::from([
+                ("r_well".to_string(), MotorLoad {
+                    soft_start_relay: Some("r_well_soft".to_string()),
+                    soft_start_secs: default_soft_start_secs(),
+                    min_off_secs: 0,
+                }),
+                ("r_heat_pump".to_string(), MotorLoad {
+                    soft_start_relay: None,
+                    soft_start_secs: default_soft_start_secs(),
+                    min_off_secs: default_min_off_secs(),
+                }),
+            ]),
+            stagger_secs: default_stagger_secs(),
+        };
+        let mut starter = MotorStarter::new(settings, 0);
+        assert!(starter.is_soft_start_relay("r_well_soft"));
+
+        // The heat pump lost power at boot, so rests first; the pump goes through its soft starter
+        starter.queue("r_heat_pump");
+        starter.queue("r_well");
+        assert_eq!(starter.tick(10), [("r_well_soft".to_string(), true)]);
+        assert!(starter.tick(12).is_empty());
+        assert_eq!(starter.tick(15), [("r_well".to_string(), true), ("r_well_soft".to_string(), false)]);
+
+        // Rested, but too soon after the pump
+        assert!(starter.tick(20).is_empty());
+        assert!(starter.tick(175).is_empty());
+        assert_eq!(starter.tick(180), [("r_heat_pump".to_string(), true)]);
+
+        // Switched off and straight back on, it waits out its off-time again
+        assert_eq!(starter.stopped("r_heat_pump", true, 200), None);
+        starter.queue("r_heat_pump");
+        assert!(starter.tick(300).is_empty());
+        assert_eq!(starter.tick(380), [("r_heat_pump".to_string(), true)]);
+
+        // Stopped part way through a soft start, the soft-start relay opens
+        starter.queue("r_well");
+        assert_eq!(starter.tick(400), [("r_well_soft".to_string(), true)]);
+        assert_eq!(starter.stopped("r_well", false, 402), Some("r_well_soft".to_string()));
+        assert!(starter.tick(420).is_empty());
+    }
+}
@@ -0,0 +1,216 @@
+use async_trait::async_trait;
+use log::{info, warn};
+use prost::Message;
+use rumqttc::{AsyncClient, Event, MqttOptions, Outgoing, Packet, QoS, TlsConfiguration, Transport};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::comms::{decode_frame, CommsError, CommunicationLayer, LinkStats, NeighborhoodMessage, Result};
+use crate::config::{MqttConfig, MqttTlsConfig};
+use crate::storage::Storage;
+use crate::tls::{self, Pin, TlsIdentity, TlsSettings, TLS_IDENTITY_KEY, TLS_PINS_KEY};
+
+/// Messages buffered between the MQTT event loop and the node's poll.
+const INBOX_CAPACITY: usize = 64;
+
+/// Topic the orchestrator publishes to every node on.
+const BROADCAST_TOPIC: &str = "streetgrid/broadcast";
+
+/// How long closing waits for queued publishes to reach the broker.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where to reach the broker and how to authenticate.
+#[derive(Debug, Clone)]
+pub struct MqttSettings {
+    pub host: String,
+    pub port: u16,
+    pub node_id: String,
+    /// Mutual TLS; None means plaintext (development only)
+    pub tls: Option<TlsSettings>,
+}
+
+impl MqttSettings {
+    /// Settings for `config`; a TLS identity and pins persisted in `storage` (issued on
+    /// joining, or rotated since) win over the configured ones.
+    pub fn from_config(config: &MqttConfig, node_id: &str, storage: Option<&Storage>) -> Result<Self> {
+        Ok(Self {
+            host: config.host.clone(),
+            port: config.port.unwrap_or(if config.tls.is_some() { 8883 } else { 1883 }),
+            node_id: node_id.to_string(),
+            tls: config.tls.as_ref().map(|t| tls_settings(t, storage)).transpose()?,
+        })
+    }
+}
+
+fn tls_settings(config: &MqttTlsConfig, storage: Option<&Storage>) -> Result<TlsSettings> {
+    let stored_identity = storage.and_then(|s| s.get_json::<TlsIdentity>(TLS_IDENTITY_KEY).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable TLS identity: {}", e);
+        None
+    }));
+    let identity = match (stored_identity, &config.client_cert, &config.client_key) {
+        (Some(identity), _, _) => Some(identity),
+        (None, Some(cert), Some(key)) => Some(TlsIdentity::from_files(cert, key)?),
+        _ => {
+            warn!("No TLS client certificate yet: only joining is possible until one is issued");
+            None
+        }
+    };
+    let stored_pins = storage.and_then(|s| s.get_json::<Vec<String>>(TLS_PINS_KEY).ok().flatten());
+    let pins = stored_pins.or_else(|| config.orchestrator_pins.clone()).unwrap_or_default();
+    Ok(TlsSettings {
+        pins: pins.iter().map(|p| tls::parse_pin(p)).collect::<tls::Result<_>>()?,
+        ca_pem: config.ca_cert.as_ref().map(std::fs::read_to_string).transpose()?,
+        identity,
+    })
+}
+
+/// IP transport: protobuf `NeighborhoodMessage`s over MQTT, uplink on
+/// `streetgrid/<node>/up`, downlink on `streetgrid/<node>/down` and the broadcast topic.
+pub struct MqttCommunication {
+    settings: Mutex<MqttSettings>,
+    client: Mutex<AsyncClient>,
+    /// None once closed
+    event_loop: Mutex<Option<JoinHandle<()>>>,
+    inbox_tx: mpsc::Sender<NeighborhoodMessage>,
+    inbox: Mutex<mpsc::Receiver<NeighborhoodMessage>>,
+    tx_packets: AtomicU32,
+    rx_packets: AtomicU32,
+}
+
+impl MqttCommunication {
+    /// Start connecting; the event loop keeps reconnecting in the background.
+    pub fn connect(settings: MqttSettings) -> Result<Self> {
+        let (inbox_tx, inbox) = mpsc::channel(INBOX_CAPACITY);
+        let (client, event_loop) = spawn_client(&settings, inbox_tx.clone())?;
+        Ok(Self {
+            settings: Mutex::new(settings),
+            client: Mutex::new(client),
+            event_loop: Mutex::new(Some(event_loop)),
+            inbox_tx,
+            inbox: Mutex::new(inbox),
+            tx_packets: AtomicU32::new(0),
+            rx_packets: AtomicU32::new(0),
+        })
+    }
+
+    fn uplink_topic(&self) -> String {
+        format!("streetgrid/{}/up", self.settings.lock().unwrap().node_id)
+    }
+}
+
+fn spawn_client(settings: &MqttSettings, inbox: mpsc::Sender<NeighborhoodMessage>) -> Result<(AsyncClient, JoinHandle<()>)> {
+    let mut options = MqttOptions::new(format!("streetgrid-{}", settings.node_id), &settings.host, settings.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(tls) = &settings.tls {
+        options.set_transport(Transport::tls_with_config(TlsConfiguration::Rustls(tls.client_config()?)));
+    } else {
+        warn!("MQTT without TLS: traffic to {} is unauthenticated and in the clear", settings.host);
+    }
+    let (client, mut event_loop) = AsyncClient::new(options, INBOX_CAPACITY);
+
+    let downlink = format!("streetgrid/{}/down", settings.node_id);
+    let subscriber = client.clone();
+    let handle = tokio::spawn(async move {
+        loop {
+            match event_loop.poll().await {
+                // Subscriptions don't survive a clean-session reconnect
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("MQTT connected");
+                    for topic in [downlink.as_str(), BROADCAST_TOPIC] {
+                        if let Err(e) = subscriber.subscribe(topic, QoS::AtLeastOnce).await {
+                            warn!("MQTT subscribe to {} failed: {}", topic, e);
+                        }
+                    }
+                }
+                // Written after every publish queued before it
+                Ok(Event::Outgoing(Outgoing::Disconnect)) => {
+                    info!("MQTT disconnected");
+                    return;
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => match decode_frame(&publish.payload) {
+                    Some(msg) => {
+                        if inbox.try_send(msg).is_err() {
+                            warn!("MQTT inbox full, dropping message");
+                        }
+                    }
+                    None => warn!("Undecodable message on {}", publish.topic),
+                },
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("MQTT connection error: {}; retrying", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    });
+    Ok((client, handle))
+}
+
+#[async_trait]
+impl CommunicationLayer for MqttCommunication {
+    async fn send(&self, msg: NeighborhoodMessage) -> Result<()> {
+        let client = self.client.lock().unwrap().clone();
+        client.publish(self.uplink_topic(), QoS::AtLeastOnce, false, msg.encode_to_vec()).await?;
+        self.tx_packets.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn receive(&self) -> Result<Option<NeighborhoodMessage>> {
+        let msg = self.inbox.lock().unwrap().try_recv().ok();
+        if msg.is_some() {
+            self.rx_packets.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(msg)
+    }
+
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn link_stats(&self) -> LinkStats {
+        LinkStats {
+            tx_packets: self.tx_packets.load(Ordering::Relaxed),
+            rx_packets: self.rx_packets.load(Ordering::Relaxed),
+            ..LinkStats::default()
+        }
+    }
+
+    /// Reconnect with a new client certificate (and orchestrator pins, if given).
+    async fn rotate_tls(&self, identity: TlsIdentity, pins: Vec<Pin>) -> Result<()> {
+        let mut settings = self.settings.lock().unwrap().clone();
+        let Some(tls) = &mut settings.tls else {
+            return Err(CommsError::NoTls(self.name()));
+        };
+        tls.identity = Some(identity);
+        if !pins.is_empty() {
+            tls.pins = pins;
+        }
+        // Build the new connection before tearing down the old one, so a bad identity changes nothing
+        let (client, event_loop) = spawn_client(&settings, self.inbox_tx.clone())?;
+        let old_client = std::mem::replace(&mut *self.client.lock().unwrap(), client);
+        let old_loop = self.event_loop.lock().unwrap().replace(event_loop);
+        let _ = old_client.disconnect().await;
+        if let Some(old_loop) = old_loop {
+            old_loop.abort();
+        }
+        *self.settings.lock().unwrap() = settings;
+        info!("MQTT reconnecting with rotated TLS identity");
+        Ok(())
+    }
+
+    /// Disconnect once the publishes queued so far are written, waiting a while for it.
+    async fn close(&self) -> Result<()> {
+        let client = self.client.lock().unwrap().clone();
+        let Some(mut event_loop) = self.event_loop.lock().unwrap().take() else {
+            return Ok(());
+        };
+        client.disconnect().await?;
+        if tokio::time::timeout(CLOSE_TIMEOUT, &mut event_loop).await.is_err() {
+            event_loop.abort();
+            return Err(CommsError::Unflushed(self.name()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use ed25519_dalek::{Signature, VerifyingKey};
+use thiserror::Error;
+use crate::comms::Approval;
+use crate::types::MeshType;
+
+/// Why a policy could not be built, or a command's approvals fall short of it.
+#[derive(Debug, Error)]
+pub enum MultiSigError {
+    #[error("signer key must be 32 hex-encoded bytes")]
+    BadSignerKey,
+    #[error("invalid signer key: {0}")]
+    SignerKey(#[from] ed25519_dalek::SignatureError),
+    #[error("threshold {threshold} needs between 1 and {signers} signers")]
+    Threshold { threshold: usize, signers: usize },
+    #[error("{approved} of {threshold} required approvals")]
+    NotApproved { approved: usize, threshold: usize },
+}
+
+pub type Result<T, E = MultiSigError> = std::result::Result<T, E>;
+
+/// Co-signatures carried by a command, with the bytes they sign
+/// (the envelope encoded without `auth` and `approvals`).
+#[derive(Debug, Clone, Default)]
+pub struct CoSignatures {
+    pub message: Vec<u8>,
+    pub approvals: Vec<Approval>,
+}
+
+/// Commands that need co-signatures when none are configured: a factory reset
+/// anywhere, and on a sanctioned mesh also dropping the utility connection or
+/// switching the transformer's MID.
+pub fn default_commands(mesh_type: &MeshType) -> Vec<String> {
+    let mut commands = vec!["factory_reset".to_string()];
+    if *mesh_type == MeshType::GovernmentSanctioned {
+        commands.push("disconnect_grid".to_string());
+        commands.push("mid_command".to_string());
+    }
+    commands
+}
+
+/// M-of-N approval for destructive commands: each listed command must carry valid
+/// Ed25519 signatures from at least `threshold` distinct signers (e.g. utility and municipality).
+pub struct MultiSigPolicy {
+    signers: Vec<VerifyingKey>,
+    threshold: usize,
+    commands: Vec<String>,
+}
+
+impl MultiSigPolicy {
+    /// `signers` are hex-encoded Ed25519 public keys.
+    pub fn new(signers: &[String], threshold: usize, commands: Vec<String>) -> Result<Self> {
+        let signers = signers.iter()
+            .map(|k| {
+                let mut bytes = [0u8; 32];
+                hex::decode_to_slice(k.trim(), &mut bytes).map_err(|_| MultiSigError::BadSignerKey)?;
+                Ok(VerifyingKey::from_bytes(&bytes)?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if threshold == 0 || threshold > signers.len() {
+            return Err(MultiSigError::Threshold { threshold, signers: signers.len() });
+        }
+        Ok(Self { signers, threshold, commands })
+    }
+
+    /// Policy no approvals can satisfy, for when the configured one is unusable.
+    pub fn deny_all(commands: Vec<String>) -> Self {
+        Self { signers: Vec::new(), threshold: 1, commands }
+    }
+
+    pub fn requires(&self, kind: &str) -> bool {
+        self.commands.iter().any(|c| c == kind)
+    }
+
+    /// Count signers with a valid approval; each signer counts once however often it appears.
+    pub fn verify(&self, co_signatures: Option<&CoSignatures>) -> Result<()> {
+        let approved = match co_signatures {
+            Some(co) => self.signers.iter()
+                .filter(|signer| co.approvals.iter().any(|a| {
+                    a.public_key == signer.as_bytes()
+                        && Signature::from_slice(&a.signature)
+                            .is_ok_and(|sig| signer.verify_strict(&co.message, &sig).is_ok())
+                }))
+                .count(),
+            None => 0,
+        };
+        if approved < self.threshold {
+            return Err(MultiSigError::NotApproved { approved, threshold: self.threshold });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn approval(key: &SigningKey, message: &[u8]) -> Approval {
+        Approval {
+            public_key: key.verifying_key().to_bytes().to_vec(),
+            signature: key.sign(message).to_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_two_of_three_distinct_signers() {
+        let utility = SigningKey::from_bytes(&[1; 32]);
+        let municipality = SigningKey::from_bytes(&[2; 32]);
+        let outsider = SigningKey::from_bytes(&[3; 32]);
+        let signers: Vec<String> = [&utility, &municipality, &SigningKey::from_bytes(&[4; 32])].iter()
+            .map(|k| hex::encode(k.verifying_key().as_bytes()))
+            .collect();
+        let policy = MultiSigPolicy::new(&signers, 2, default_commands(&MeshType::GovernmentSanctioned)).unwrap();
+        assert!(policy.requires("disconnect_grid"));
+        assert!(!policy.requires("load_shed"));
+
+        let message = b"factory reset node_01".to_vec();
+        let co = |approvals| CoSignatures { message: message.clone(), approvals };
+        assert!(policy.verify(None).is_err());
+        assert!(policy.verify(Some(&co(vec![approval(&utility, &message)]))).is_err());
+        // The same signer twice is still one approval
+        assert!(policy.verify(Some(&co(vec![approval(&utility, &message), approval(&utility, &message)]))).is_err());
+        assert!(policy.verify(Some(&co(vec![approval(&utility, &message), approval(&outsider, &message)]))).is_err());
+        assert!(policy.verify(Some(&co(vec![approval(&utility, b"other"), approval(&municipality, &message)]))).is_err());
+        assert!(policy.verify(Some(&co(vec![approval(&utility, &message), approval(&municipality, &message)]))).is_ok());
+
+        assert!(MultiSigPolicy::new(&signers, 4, Vec::new()).is_err());
+    }
+}
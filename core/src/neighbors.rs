@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+
+/// Neighbours not heard for this long are dropped from reports.
+pub const NEIGHBOR_TTL_SECS: i64 = 15 * 60;
+
+/// Most neighbours tracked; keeps a NeighborReport inside one LoRa packet.
+pub const MAX_NEIGHBORS: usize = 16;
+
+/// Frames expected per neighbour before the counts are halved, so the packet error
+/// rate follows the link as it is now rather than since boot.
+pub const PER_WINDOW: u32 = 100;
+
+/// A sequence number this far behind the last one means the sender restarted; closer,
+/// it is a copy forwarded by a repeater or a late arrival.
+const SEQ_RESTART_GAP: u32 = 64;
+
+/// Another node heard on the radio.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Neighbor {
+    pub node_id: String,
+    pub rssi: Option<i16>,
+    pub snr: Option<f32>,
+    pub last_heard: i64,
+    pub packets: u32,
+    /// Frames it numbered that reached us, and that it sent, over the recent window
+    pub frames_heard: u32,
+    pub frames_expected: u32,
+    pub last_seq: Option<u32>,
+}
+
+impl Neighbor {
+    /// Share of its numbered frames we missed; None until one is heard.
+    pub fn packet_error_rate(&self) -> Option<f32> {
+        (self.frames_expected > 0).then(|| 1.0 - self.frames_heard as f32 / self.frames_expected as f32)
+    }
+
+    fn frame(&mut self, seq: u32) {
+        match self.last_seq {
+            Some(last) if seq <= last && last - seq < SEQ_RESTART_GAP => return,
+            Some(last) if seq > last => self.frames_expected += seq - last,
+            // Restarted: it has sent `seq` frames since
+            Some(_) => self.frames_expected += seq,
+            // We can't know what it sent before we started listening
+            None => self.frames_expected += 1,
+        }
+        self.frames_heard += 1;
+        self.last_seq = Some(seq);
+        while self.frames_expected >= 2 * PER_WINDOW {
+            self.frames_expected /= 2;
+            self.frames_heard /= 2;
+        }
+    }
+}
+
+/// Nodes whose traffic we have overheard recently.
+#[derive(Debug, Default)]
+pub struct NeighborTable {
+    neighbors: BTreeMap<String, Neighbor>,
+}
+
+impl NeighborTable {
+    /// Entry for `node_id`; when full, the longest-silent neighbour makes room.
+    fn entry(&mut self, node_id: &str, now: i64) -> &mut Neighbor {
+        if !self.neighbors.contains_key(node_id) && self.neighbors.len() >= MAX_NEIGHBORS {
+            if let Some(oldest) = self.neighbors.values().min_by_key(|n| n.last_heard).map(|n| n.node_id.clone()) {
+                self.neighbors.remove(&oldest);
+            }
+        }
+        self.neighbors.entry(node_id.to_string()).or_insert_with(|| Neighbor {
+            node_id: node_id.to_string(),
+            last_heard: now,
+            ..Default::default()
+        })
+    }
+
+    /// Note a packet from `node_id`.
+    pub fn heard(&mut self, node_id: &str, rssi: Option<i16>, snr: Option<f32>, now: i64) {
+        let neighbor = self.entry(node_id, now);
+        neighbor.rssi = rssi;
+        neighbor.snr = snr;
+        neighbor.last_heard = now;
+        neighbor.packets += 1;
+    }
+
+    /// Note frame `seq` of the frames `node_id` has sent since it started, for its
+    /// packet error rate.
+    pub fn frame(&mut self, node_id: &str, seq: u32, now: i64) {
+        self.entry(node_id, now).frame(seq);
+    }
+
+    pub fn get(&self, node_id: &str) -> Option<&Neighbor> {
+        self.neighbors.get(node_id)
+    }
+
+    /// Neighbours heard within the TTL; stale ones are forgotten.
+    pub fn current(&mut self, now: i64) -> Vec<Neighbor> {
+        self.neighbors.retain(|_, n| now - n.last_heard <= NEIGHBOR_TTL_SECS);
+        self.neighbors.values().cloned().collect()
+    }
+
+    /// Current neighbours, the most reliable first: lowest packet error rate, then
+    /// those without one yet by signal strength.
+    pub fn ranked(&mut self, now: i64) -> Vec<Neighbor> {
+        let mut neighbors = self.current(now);
+        neighbors.sort_by(|a, b| match (a.packet_error_rate(), b.packet_error_rate()) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => b.rssi.cmp(&a.rssi),
+        });
+        neighbors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_neighbors_expire_and_are_bounded() {
+        let mut table = NeighborTable::default();
+        table.heard("node_02", Some(-90), Some(5.0), 0);
+        table.heard("node_02", Some(-85), Some(6.0), 100);
+        table.heard("node_03", Some(-110), None, 10);
+
+        let current = table.current(200);
+        assert_eq!(current.len(), 2);
+        assert_eq!(current[0].rssi, Some(-85));
+        assert_eq!(current[0].packets, 2);
+
+        // node_03 falls silent
+        assert_eq!(table.current(10 + NEIGHBOR_TTL_SECS + 1).len(), 1);
+
+        for i in 0..MAX_NEIGHBORS + 2 {
+            table.heard(&format!("n{}", i), None, None, 1000 + i as i64);
+        }
+        let current = table.current(1000 + MAX_NEIGHBORS as i64 + 2);
+        assert_eq!(current.len(), MAX_NEIGHBORS);
+        assert!(!current.iter().any(|n| n.node_id == "n0"));
+    }
+
+    #[test]
+    fn test_packet_error_rate_from_sequence_numbers() {
+        let mut table = NeighborTable::default();
+        // Frames 100..=109 sent, 102 and 105 lost, 103 heard again through a repeater
+        for seq in [100, 101, 103, 104, 103, 106, 107, 108, 109] {
+            table.frame("node_02", seq, 0);
+        }
+        let neighbor = table.get("node_02").unwrap();
+        assert_eq!((neighbor.frames_heard, neighbor.frames_expected), (8, 10));
+        assert!((neighbor.packet_error_rate().unwrap() - 0.2).abs() < 1e-6);
+
+        // Restarted, and has sent 3 frames since; we heard the third
+        table.frame("node_02", 3, 0);
+        assert_eq!((table.get("node_02").unwrap().frames_heard, table.get("node_02").unwrap().frames_expected), (9, 13));
+
+        // Counts are halved as they grow, so a link that recovers shows it
+        for seq in 4..4 + 2 * PER_WINDOW {
+            table.frame("node_02", seq, 0);
+        }
+        let neighbor = table.get("node_02").unwrap();
+        assert!(neighbor.frames_expected < 2 * PER_WINDOW);
+        assert!(neighbor.packet_error_rate().unwrap() < 0.05);
+    }
+
+    #[test]
+    fn test_reliable_neighbors_rank_first() {
+        let mut table = NeighborTable::default();
+        table.heard("lossy", Some(-70), None, 0);
+        for seq in [1, 3, 5] {
+            table.frame("lossy", seq, 0);
+        }
+        table.heard("clean", Some(-100), None, 0);
+        for seq in [1, 2, 3] {
+            table.frame("clean", seq, 0);
+        }
+        table.heard("quiet", Some(-80), None, 0);
+        table.heard("faint", Some(-110), None, 0);
+        let ranked: Vec<_> = table.ranked(0).into_iter().map(|n| n.node_id).collect();
+        assert_eq!(ranked, ["clean", "lossy", "quiet", "faint"]);
+    }
+}
@@ -0,0 +1,6187 @@
+use crate::types::{CtDirection, Relay, Priority, RelayType, NodeState, MeshType, NodeRole, Phase};
+use crate::comms::{Heartbeat, HeartbeatReadings, IncomingCommand, OrchestratorClient, EnterIsland, EnterBlackStart, ActivateRelayByIndex, ActivateRelayByPriority, AuditLogRequest, SnapshotRequest, SnapshotData, SnapshotRestore, KeyRotation, KeyRotationAck, ReceivedCommand, JoinAccept, JoinReject, JoinRequest, FirmwareUpdate, FirmwareStatus, SessionGrant, SessionRevoke, ReplayDesync, CounterReset, CertRotation, FactoryReset, FaultReset, DisconnectGrid, PowerGrant, EnergyLedgerRequest, RebalanceDirective, RebalanceAck, MidCommand, MidStatus, LivenessProbe, OrchestratorTakeover, RegistrationAck, BlackStartAck, DemandResponse, DemandResponseAck, CommandResult, CommsError, RelayState};
+use crate::hal::adc::PowerQuality;
+use crate::hal::{AsyncPowerSensor, AsyncRelayControl, HalError, RelayControl, PowerSensor, SecureElement, TamperSwitch, BatteryGauge, BatteryStatus};
+use crate::sysinfo::SystemMonitor;
+use crate::events::{EventBus, NodeEvent};
+use crate::storage::{Storage, StorageError};
+use crate::audit::{AuditLog, AuditRecord, SignatureStatus};
+use crate::measurements::{MeasurementStore, Sample};
+use crate::anomaly::AnomalyDetector;
+use crate::load_profile::{LoadProfile, PROFILE_KEY};
+use crate::outages::{OutageLog, OUTAGE_KEY};
+use crate::ota::{FirmwareImage, OtaError, OtaUpdater, FETCH_TIMEOUT};
+use crate::session::SessionTable;
+use crate::multisig::{CoSignatures, MultiSigPolicy};
+use crate::quorum::OBSERVATION_RESEND_SECS;
+use crate::election::{Election, KIND_ORCHESTRATOR_ALIVE, KIND_RESTORE_STEP};
+use crate::failover::Failover;
+use crate::power::{Demand, MAX_COMMUNITY_CRITICALITY};
+use crate::energy::{self, EnergyLedger, ENERGY_KEY};
+use crate::mid::MID_STATUS_INTERVAL_SECS;
+use crate::sources::{SourceManager, SOURCE_CAPACITY_INTERVAL_SECS};
+use crate::evse::EvCharger;
+use crate::inverter::SolarInverter;
+use crate::generator::{Generator, GeneratorState, RunHours, GENERATOR_HOURS_KEY, GENERATOR_TICK_SECS};
+use crate::transfer::{Step, Supply, TransferGroup, TRANSFER_TICK};
+use crate::demand_response::{self, DrSchedule, DEMAND_RESPONSE_TICK_SECS};
+use crate::cycling::DutyCycler;
+use crate::shedding::{Candidate, ShedStrategy};
+use crate::tariff::{CostShedder, TARIFF_SAVINGS_KEY, TARIFF_TICK_SECS};
+use crate::forecast::{Forecaster, FORECAST_TICK_SECS};
+use crate::phases::{PhaseMonitor, PhaseReading};
+use crate::harmonics::{self, VOLTAGE_THD_LIMIT};
+use crate::arc_fault::{self, ArcDetector, MIN_ARC_AMPS};
+use crate::relay_health::{RelayCheck, RelayFailure, RelayHealth};
+use crate::thermal::{self, ThermalDerating};
+use crate::reserve::{ReserveAction, ReservePolicy};
+use crate::submeter::SubMeter;
+use crate::pickup::ColdLoadPickup;
+use crate::motors::{MotorStarter, MOTOR_START};
+use crate::estop::EStopWatch;
+use crate::clock::{Clock, SystemClock, VirtualClock};
+use crate::interlock::{self, Conditions, Refusal};
+use crate::protection::{Excursion, Protection, ProtectionSettings, Sag};
+use crate::link_budget::{LinkBudget, DEFAULT_MIN_FADE_MARGIN_DB};
+use crate::security::{self, SecurityEvent};
+use crate::tls::{self, TlsIdentity, TLS_IDENTITY_KEY, TLS_PINS_KEY};
+use crate::replay::{Freshness, ReplayGuard, REPLAY_KEY};
+use crate::ratelimit::{CommandLimiter, Verdict};
+use crate::keys::{self, Keyring, KEYRING_KEY, SEALED_KEYRING_KEY, DEFAULT_GRACE_SECS, KEY_LEN, NONCE_LEN};
+use crate::snapshot::{self, Calibration, NodeSnapshot, SNAPSHOT_FORMAT, COUNTER_AUDIT_SEQ};
+use chrono::Timelike;
+use sha2::Digest;
+use log::{debug, info, warn, error};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+/// Storage key for the persisted state machine and relay positions
+const STATE_KEY: &str = "state.json";
+
+/// How often the learned load profile is reported to the orchestrator
+const PROFILE_REPORT_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+/// How often the nodes we can hear are reported, for the orchestrator's topology map
+pub const NEIGHBOR_REPORT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How often election messages are processed and election timers advanced
+const ELECTION_TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often island nodes advertise supply and demand, and the coordinator re-divides it
+const POWER_SHARING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often a node with a battery or solar advertises its capacity
+const SOURCE_CAPACITY_INTERVAL: Duration = Duration::from_secs(SOURCE_CAPACITY_INTERVAL_SECS);
+
+/// How often accepted demand-response events are started and ended
+const DEMAND_RESPONSE_INTERVAL: Duration = Duration::from_secs(DEMAND_RESPONSE_TICK_SECS);
+const TARIFF_INTERVAL: Duration = Duration::from_secs(TARIFF_TICK_SECS);
+const FORECAST_INTERVAL: Duration = Duration::from_secs(FORECAST_TICK_SECS);
+
+/// How often a MID broadcasts its status, and nodes behind one re-check it
+const MID_INTERVAL: Duration = Duration::from_secs(MID_STATUS_INTERVAL_SECS);
+
+/// How often liveness probes are answered and reported on
+const LIVENESS_TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long we wait for a probed node's answer before reporting to the orchestrator
+const LIVENESS_PROBE_WAIT_SECS: i64 = 20;
+
+/// How often the registration schedule is checked
+const REGISTRATION_TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often an unacknowledged FeatureReport is resent
+const REGISTRATION_RETRY_SECS: i64 = 60;
+
+/// How often an acknowledged registration is refreshed, in case the orchestrator lost it
+const REREGISTRATION_SECS: i64 = 60 * 60;
+
+/// Answers to WhoIsThere are spread over this window so the street doesn't transmit at once
+const WHO_IS_THERE_SPREAD_SECS: i64 = 30;
+
+/// Largest current a CT clamp can report; anything beyond it is a faulty ADC
+const MAX_PLAUSIBLE_AMPS: f32 = 200.0;
+
+/// How often counts of rejected traffic are reported (only when non-zero)
+const SECURITY_REPORT_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// How often the enclosure tamper switch is polled
+const TAMPER_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+const GENERATOR_INTERVAL: Duration = Duration::from_secs(GENERATOR_TICK_SECS);
+
+/// Upper bound on audit entries returned per upload request
+const MAX_AUDIT_UPLOAD: usize = 20;
+
+/// Rebalance directives remembered for rollback
+const MAX_REBALANCES: usize = 8;
+
+/// Nominal mains voltage when none is configured
+pub const DEFAULT_VOLTAGE_REF: f32 = 120.0;
+
+/// Upper bound on energy ledger entries returned per upload request
+const MAX_ENERGY_UPLOAD: usize = 20;
+
+/// Periodic work of the event loop. `run` drives it from tokio timers; the scenario
+/// executor from a virtual clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Task {
+    Adc,
+    Tamper,
+    Profile,
+    Neighbors,
+    Election,
+    PowerSharing,
+    Sources,
+    DemandResponse,
+    Tariff,
+    Forecast,
+    Mid,
+    Liveness,
+    Registration,
+    Security,
+    Heartbeat,
+    Generator,
+    Transfer,
+    Messages,
+}
+
+impl Task {
+    pub const ALL: [Task; 18] = [
+        Task::Adc, Task::Tamper, Task::Profile, Task::Neighbors, Task::Election, Task::PowerSharing, Task::Sources,
+        Task::DemandResponse, Task::Tariff, Task::Forecast, Task::Mid, Task::Liveness, Task::Registration, Task::Security, Task::Heartbeat,
+        Task::Generator, Task::Transfer, Task::Messages,
+    ];
+
+    pub fn interval(self) -> Duration {
+        match self {
+            Task::Adc => Duration::from_secs(5),
+            Task::Tamper => TAMPER_POLL_INTERVAL,
+            Task::Profile => PROFILE_REPORT_INTERVAL,
+            Task::Neighbors => NEIGHBOR_REPORT_INTERVAL,
+            Task::Election => ELECTION_TICK_INTERVAL,
+            Task::PowerSharing => POWER_SHARING_INTERVAL,
+            Task::Sources => SOURCE_CAPACITY_INTERVAL,
+            Task::DemandResponse => DEMAND_RESPONSE_INTERVAL,
+            Task::Tariff => TARIFF_INTERVAL,
+            Task::Forecast => FORECAST_INTERVAL,
+            Task::Mid => MID_INTERVAL,
+            Task::Liveness => LIVENESS_TICK_INTERVAL,
+            Task::Registration => REGISTRATION_TICK_INTERVAL,
+            Task::Security => SECURITY_REPORT_INTERVAL,
+            Task::Heartbeat => Duration::from_secs(60),
+            Task::Generator => GENERATOR_INTERVAL,
+            Task::Transfer => TRANSFER_TICK,
+            Task::Messages => Duration::from_millis(100),
+        }
+    }
+
+    /// Reports that wait one interval rather than going out as the loop starts
+    pub fn waits_first(self) -> bool {
+        matches!(self, Task::Heartbeat | Task::Profile | Task::Security | Task::Neighbors)
+    }
+}
+
+/// URL of a firmware image and the background task downloading it
+type FirmwareDownload = (String, tokio::task::JoinHandle<Result<Vec<u8>, OtaError>>);
+
+/// Storage key of the identity issued when the node joined the mesh
+pub const IDENTITY_KEY: &str = "identity.json";
+
+/// Identity assigned by the orchestrator on commissioning; its presence means the node has joined.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Identity {
+    pub node_id: String,
+    pub joined_at: i64,
+}
+
+/// Node state that must survive a reboot (e.g. staying islanded after a power blip)
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedState {
+    state: NodeState,
+    relays: HashMap<String, bool>,
+}
+
+/// Convert a proto priority (0=Critical..3=Low) to our Priority enum
+fn priority_from_proto(priority: i32) -> Priority {
+    match priority {
+        0 => Priority::Critical,
+        1 => Priority::High,
+        2 => Priority::Medium,
+        _ => Priority::Low,
+    }
+}
+
+pub struct EdgeNode {
+    pub id: String,
+    pub state: NodeState,
+    pub mesh_type: MeshType,
+    pub role: NodeRole,
+    pub battery_soc: f32,
+    pub relays: Vec<Relay>,
+    pub relay_pins: HashMap<String, u8>,
+    pub client: Option<OrchestratorClient>,
+    /// Switched on the blocking pool, and shared with the emergency-stop watch, which opens
+    /// the relays through it directly
+    pub relay_driver: Option<AsyncRelayControl>,
+    /// Read on the blocking pool, so bus latency doesn't stall the event loop
+    pub power_sensor: Option<AsyncPowerSensor>,
+    pub voltage_ref: f32,
+    /// Resource metrics reported in heartbeats
+    pub sysinfo: SystemMonitor,
+    /// Live events for local consumers (API, exporters)
+    pub events: EventBus,
+    /// Persistence layer, if configured
+    pub storage: Option<Arc<Storage>>,
+    /// Record of received commands and relay actuations
+    pub audit: Option<AuditLog>,
+    /// Local measurement history
+    pub measurements: Option<Arc<MeasurementStore>>,
+    /// CT calibration in effect, carried in snapshots
+    pub calibration: Option<Calibration>,
+    /// ADC channel of the CT clamp on each relay's circuit
+    pub ct_channels: HashMap<String, u8>,
+    /// How to tell import from export on each ADC channel; `Measured` if absent
+    pub ct_directions: HashMap<u8, CtDirection>,
+    /// Last signed reading of each relay's circuit, negative while it exports
+    circuit_watts: HashMap<String, f32>,
+    /// Power quality last measured on each ADC channel, where voltage is sampled with current
+    power_quality: BTreeMap<u8, PowerQuality>,
+    /// Harmonic distortion of the mains voltage, where it is sampled fast enough, and
+    /// whether it was over the limit at the last check
+    voltage_thd: Option<f32>,
+    distorted: bool,
+    /// Flags abnormal current draw on the CT channels
+    pub anomaly: AnomalyDetector,
+    /// Learned hourly consumption per relay
+    pub load_profile: LoadProfile,
+    /// Grid losses, islands and load interruptions this month, for reliability reporting
+    pub outages: OutageLog,
+    /// Mesh keys shared with the client; when set, only validly signed commands are obeyed
+    pub keyring: Option<Arc<Mutex<Keyring>>>,
+    /// Secret from commissioning, proving the node may join while in `Joining`
+    pub provisioning_token: Option<String>,
+    /// Hardware key storage, signing and RNG, if fitted
+    pub secure_element: Option<Box<dyn SecureElement>>,
+    /// Slot of the secure element's identity key
+    pub sign_slot: u8,
+    /// Secure-element-derived key sealing the keyring at rest; without it keys are stored in the clear
+    pub kek: Option<[u8; KEY_LEN]>,
+    /// Signed firmware installation, if configured
+    pub ota: Option<OtaUpdater>,
+    /// Firmware download in progress, installed once it finishes
+    firmware_download: Option<FirmwareDownload>,
+    /// Per-command rate limits and flood circuit breaker
+    pub limiter: CommandLimiter,
+    /// Identifier of the hardware we are running on
+    pub hardware_id: Option<String>,
+    /// Hardware this identity is bound to; on other hardware the node refuses to register
+    pub bound_hardware_id: Option<String>,
+    /// Per-sender command counters; when set, commands must carry a counter newer than the last
+    pub replay: Option<ReplayGuard>,
+    /// Enclosure tamper switch, if fitted
+    pub tamper: Option<Box<dyn TamperSwitch>>,
+    /// Relay positions (relay_id -> closed) to force when the enclosure is opened
+    pub tamper_safe_state: HashMap<String, bool>,
+    /// Relay positions (relay_id -> closed) to apply before exiting on SIGTERM or SIGINT
+    pub shutdown_safe_state: HashMap<String, bool>,
+    /// Persist the positions from before the shutdown safe state, for a restart to resume
+    pub resume_on_boot: bool,
+    /// Last observed tamper switch position
+    tamper_open: bool,
+    /// Operator sessions; when set, operator commands must name a live session covering them
+    pub sessions: Option<SessionTable>,
+    /// Commands that need co-signatures from independent authorities
+    pub multisig: Option<MultiSigPolicy>,
+    /// Neighbours that must also report under-voltage before we island without the orchestrator
+    pub island_quorum: Option<usize>,
+    /// Coordinator election for when the orchestrator is unreachable, if taking part
+    pub election: Option<Election>,
+    /// Groups this node belongs to, for zone-addressed commands
+    pub zones: Vec<String>,
+    /// Transformer phase the household is on, if known
+    pub phase: Option<Phase>,
+    /// Primary and standby orchestrator, when a standby is configured
+    pub failover: Option<Failover>,
+    /// Battery and solar behind the Source relays, if configured
+    pub sources: Option<SourceManager>,
+    /// Gauge `battery_soc` is read from; without one it stays as set
+    pub battery: Option<Box<dyn BatteryGauge>>,
+    /// What the gauge last reported, limits included
+    pub battery_status: Option<BatteryStatus>,
+    /// When the battery was last told what flowed through it
+    battery_accounted: Option<Instant>,
+    /// EV charger on one of the load relays, throttled under stress before it is shed
+    pub ev_charger: Option<EvCharger>,
+    /// Solar inverter read over SunSpec and regulated by the island's frequency and voltage
+    pub inverter: Option<SolarInverter>,
+    /// Backup generator on a transfer relay, started when islanded on a low battery
+    pub generator: Option<Generator>,
+    /// Grid, generator and inverter relays, switched between break-before-make
+    pub transfer: TransferGroup,
+    /// Utility demand-response events we accepted, or our opt-out
+    pub demand_response: DrSchedule,
+    /// Loads run and rested in turn through demand-response events rather than held off
+    pub duty_cycler: DutyCycler,
+    /// Time-of-use tariff, deferring loads through its peaks if configured to
+    pub cost_shedder: Option<CostShedder>,
+    /// Next-hour load forecast, shedding ahead of the budget if enabled
+    pub forecaster: Option<Forecaster>,
+    /// Per-phase sensing of a three-phase service, shedding off a phase over its limit
+    pub phase_monitor: Option<PhaseMonitor>,
+    /// Circuits watched for arcing, and those locked out on it
+    pub arc_detector: Option<ArcDetector>,
+    /// Relays whose contacts don't follow their commands, kept out of use
+    pub relay_health: RelayHealth,
+    /// Derating of the relays while the board runs hot, if configured
+    pub thermal: Option<ThermalDerating>,
+    /// Loads given up by the battery's charge while islanded, if configured
+    pub reserve: Option<ReservePolicy>,
+    /// Household circuits metered through their own CT clamps, if configured
+    pub submeter: Option<SubMeter>,
+    /// Pacing of loads put back, if configured; otherwise they close at once
+    pub cold_load_pickup: Option<ColdLoadPickup>,
+    /// Staggered, soft starting of motor loads, if configured; otherwise they close at once
+    pub motors: Option<MotorStarter>,
+    /// Emergency-stop input, watched on a thread of its own, if fitted
+    pub estop: Option<EStopWatch>,
+    /// Time source for timestamps, deadlines and rate limits; see `set_clock`
+    pub clock: Arc<dyn Clock>,
+    /// Watts our loads may draw, as granted by the coordinator while islanded
+    pub power_budget: Option<f32>,
+    /// Energy exchanged with neighbours during island events, on AdHoc meshes
+    pub energy: Option<EnergyLedger>,
+    /// MID at our transformer; on a GovernmentSanctioned mesh its status gates the Grid relays
+    pub mid_id: Option<String>,
+    /// As a MID: whether edge nodes may close their Grid relays
+    reconnect_permitted: bool,
+    /// As a MID: our own key, which our status is signed with so no edge node can forge it
+    pub mid_key: Option<ed25519_dalek::SigningKey>,
+    /// Orchestrator probes we relayed, awaiting our report, and when they arrived
+    liveness_probes: Vec<(LivenessProbe, i64)>,
+    /// Whether the orchestrator acknowledged our last FeatureReport, and when to send the next
+    registered: bool,
+    registration_due_at: i64,
+    /// Relay positions before each recent rebalance directive, for rollback
+    rebalances: VecDeque<(String, Vec<(String, bool)>)>,
+    /// Decides from the readings when the grid has failed
+    pub protection: Protection,
+    /// Track last voltage reading for alerts
+    last_voltage: f32,
+    /// Frequency measured with it, if the sensor can
+    last_frequency: Option<f32>,
+    /// Verdict of the last voltage check and when it was last shared with neighbours
+    undervoltage: bool,
+    last_observation_at: i64,
+    /// ADC channels currently returning implausible readings, alarmed once each
+    faulty_channels: BTreeSet<u8>,
+    /// Alarms raised where we can't await, sent at the end of the current task
+    queued_alarms: Vec<(String, String)>,
+    /// What our radio settings allow, to judge the links to our neighbours (LoRa only)
+    pub link_budget: Option<LinkBudget>,
+    /// Fade margin below which a neighbour's link is warned about
+    pub min_fade_margin_db: f32,
+    /// Neighbours whose weak link has been warned about, until it recovers
+    weak_links: BTreeSet<String>,
+    /// Check the invariants after every task, alarming any broken (soak runs, `--chaos`)
+    pub invariant_checks: bool,
+    /// Violations already alarmed, so a lasting one isn't repeated after every task
+    reported_violations: BTreeSet<String>,
+}
+
+/// Why a node could not be built, or could not carry out what it was asked to.
+#[derive(Debug, Error)]
+pub enum NodeError {
+    /// The parts handed to [`EdgeNodeBuilder`] don't fit together
+    #[error("{0}")]
+    InvalidConfig(String),
+    #[error("unknown relay {0}")]
+    UnknownRelay(String),
+    #[error("{0} is not a load relay")]
+    NotALoadRelay(String),
+    #[error("{load:.0} W would exceed the {budget:.0} W budget")]
+    OverBudget { load: f32, budget: f32 },
+    /// The relay's driver failed to switch it
+    #[error("pin {pin}: {source}")]
+    Relay { pin: u8, source: HalError },
+    /// The relay was left as it was: refused by an interlock, or its driver failed
+    #[error("relay {0} did not switch")]
+    NotSwitched(String),
+    #[error("relays {} did not switch back", .0.join(", "))]
+    NotSwitchedBack(Vec<String>),
+    #[error("no relay at index {0}")]
+    NoRelayAtIndex(u32),
+    /// Relays a command should have closed that stayed open, e.g. over the power budget
+    #[error("left open: {}", .0.join(", "))]
+    LeftOpen(Vec<String>),
+    /// The command needs something this node was not set up with
+    #[error("{0} not enabled on this node")]
+    NotEnabled(&'static str),
+    /// The command is understood, but this firmware doesn't carry it out
+    #[error("{0} is not supported")]
+    Unsupported(&'static str),
+    /// The node's state rules the command out for now
+    #[error("{0}")]
+    WrongState(String),
+    /// The command's contents failed a check (a signature, hash, challenge or format)
+    #[error("{0}")]
+    Rejected(String),
+    /// Dropped by the command rate limiter before it was carried out
+    #[error("{0} commands are rate limited")]
+    RateLimited(&'static str),
+    /// Missing the session or co-signatures the command needs
+    #[error("{0}")]
+    Unauthorized(String),
+    /// Storing or encoding what the command asked for failed
+    #[error("{0}")]
+    Failed(String),
+    #[error(transparent)]
+    Comms(#[from] CommsError),
+}
+
+impl NodeError {
+    /// Whether the same request may succeed if made again shortly.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Relay { source, .. } => source.is_transient(),
+            Self::Comms(e) => e.is_transient(),
+            _ => false,
+        }
+    }
+
+    /// Machine-readable name, reported in a [`CommandResult`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidConfig(_) => "invalid_config",
+            Self::UnknownRelay(_) => "unknown_relay",
+            Self::NotALoadRelay(_) => "not_a_load_relay",
+            Self::OverBudget { .. } => "over_budget",
+            Self::Relay { .. } => "relay_fault",
+            Self::NotSwitched(_) => "not_switched",
+            Self::NotSwitchedBack(_) => "not_switched_back",
+            Self::NoRelayAtIndex(_) => "no_relay_at_index",
+            Self::LeftOpen(_) => "left_open",
+            Self::NotEnabled(_) => "not_enabled",
+            Self::Unsupported(_) => "unsupported",
+            Self::WrongState(_) => "wrong_state",
+            Self::Rejected(_) => "rejected",
+            Self::RateLimited(_) => "rate_limited",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::Failed(_) => "failed",
+            Self::Comms(_) => "comms",
+        }
+    }
+}
+
+/// Assembles an [`EdgeNode`] from its relays, hardware and mesh link, checking that they fit
+/// together before the node exists. Everything not set here starts disabled, as in the fields'
+/// docs, and is attached to the built node afterwards.
+pub struct EdgeNodeBuilder {
+    id: String,
+    relays: Vec<Relay>,
+    relay_pins: HashMap<String, u8>,
+    client: Option<OrchestratorClient>,
+    relay_driver: Option<Box<dyn RelayControl>>,
+    power_sensor: Option<Box<dyn PowerSensor>>,
+    voltage_ref: f32,
+    mesh_type: MeshType,
+    protection: ProtectionSettings,
+}
+
+impl EdgeNodeBuilder {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            relays: Vec::new(),
+            relay_pins: HashMap::new(),
+            client: None,
+            relay_driver: None,
+            power_sensor: None,
+            voltage_ref: DEFAULT_VOLTAGE_REF,
+            mesh_type: MeshType::default(),
+            protection: ProtectionSettings::default(),
+        }
+    }
+
+    pub fn relays(mut self, relays: Vec<Relay>) -> Self {
+        self.relays = relays;
+        self
+    }
+
+    /// GPIO pin of each relay the driver switches; relays without one are only tracked
+    pub fn relay_pins(mut self, pins: HashMap<String, u8>) -> Self {
+        self.relay_pins = pins;
+        self
+    }
+
+    pub fn relay_driver(mut self, driver: Box<dyn RelayControl>) -> Self {
+        self.relay_driver = Some(driver);
+        self
+    }
+
+    pub fn power_sensor(mut self, sensor: Box<dyn PowerSensor>) -> Self {
+        self.power_sensor = Some(sensor);
+        self
+    }
+
+    /// Our link to the orchestrator and the rest of the mesh
+    pub fn client(mut self, client: OrchestratorClient) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Nominal mains voltage, reported until the first reading
+    pub fn voltage_ref(mut self, volts: f32) -> Self {
+        self.voltage_ref = volts;
+        self
+    }
+
+    pub fn mesh_type(mut self, mesh_type: MeshType) -> Self {
+        self.mesh_type = mesh_type;
+        self
+    }
+
+    /// Grid protection thresholds (under-voltage, frequency, debounce and ride-through)
+    pub fn protection(mut self, settings: ProtectionSettings) -> Self {
+        self.protection = settings;
+        self
+    }
+
+    /// Check the parts fit together and build the node.
+    pub fn build(self) -> Result<EdgeNode, NodeError> {
+        if self.id.is_empty() {
+            return Err(NodeError::InvalidConfig("node id is empty".to_string()));
+        }
+        let mut ids = BTreeSet::new();
+        for relay in &self.relays {
+            if !ids.insert(relay.id.as_str()) {
+                return Err(NodeError::InvalidConfig(format!("relay {} is listed twice", relay.id)));
+            }
+        }
+        if let Some(relay_id) = self.relay_pins.keys().find(|id| !ids.contains(id.as_str())) {
+            return Err(NodeError::InvalidConfig(format!("relay pin given for unknown relay {}", relay_id)));
+        }
+        let mut pins = BTreeMap::new();
+        for (relay_id, pin) in &self.relay_pins {
+            if let Some(other) = pins.insert(*pin, relay_id) {
+                return Err(NodeError::InvalidConfig(format!("relays {} and {} share GPIO {}", other.min(relay_id), other.max(relay_id), pin)));
+            }
+        }
+        if !(self.voltage_ref.is_finite() && self.voltage_ref > 0.0) {
+            return Err(NodeError::InvalidConfig(format!("reference voltage {} is not a positive voltage", self.voltage_ref)));
+        }
+        let protection = &self.protection;
+        if let (Some(min), Some(max)) = (protection.frequency_min_hz, protection.frequency_max_hz) {
+            if min >= max {
+                return Err(NodeError::InvalidConfig(format!("frequency band {}-{} Hz is empty", min, max)));
+            }
+        }
+        if protection.undervoltage_v >= self.voltage_ref {
+            return Err(NodeError::InvalidConfig(format!("under-voltage threshold {} V is not below the {} V reference", protection.undervoltage_v, self.voltage_ref)));
+        }
+        if protection.debounce_readings == 0 {
+            return Err(NodeError::InvalidConfig("protection needs at least one reading to trip".to_string()));
+        }
+        Ok(EdgeNode {
+            id: self.id,
+            state: NodeState::Normal,
+            mesh_type: self.mesh_type,
+            role: NodeRole::Participant,
+            battery_soc: 1.0,
+            relays: self.relays,
+            relay_pins: self.relay_pins,
+            client: self.client,
+            relay_driver: self.relay_driver.map(AsyncRelayControl::new),
+            power_sensor: self.power_sensor.map(AsyncPowerSensor::new),
+            voltage_ref: self.voltage_ref,
+            sysinfo: SystemMonitor::default(),
+            events: EventBus::new(),
+            storage: None,
+            audit: None,
+            measurements: None,
+            calibration: None,
+            ct_channels: HashMap::new(),
+            ct_directions: HashMap::new(),
+            circuit_watts: HashMap::new(),
+            power_quality: BTreeMap::new(),
+            voltage_thd: None,
+            distorted: false,
+            anomaly: AnomalyDetector::default(),
+            load_profile: LoadProfile::default(),
+            outages: OutageLog::default(),
+            keyring: None,
+            provisioning_token: None,
+            secure_element: None,
+            sign_slot: 0,
+            kek: None,
+            ota: None,
+            firmware_download: None,
+            limiter: CommandLimiter::default(),
+            sessions: None,
+            multisig: None,
+            replay: None,
+            tamper: None,
+            tamper_safe_state: HashMap::new(),
+            shutdown_safe_state: HashMap::new(),
+            resume_on_boot: false,
+            tamper_open: false,
+            hardware_id: None,
+            bound_hardware_id: None,
+            island_quorum: None,
+            election: None,
+            failover: None,
+            sources: None,
+            battery: None,
+            battery_status: None,
+            battery_accounted: None,
+            ev_charger: None,
+            inverter: None,
+            generator: None,
+            transfer: TransferGroup::default(),
+            demand_response: DrSchedule::default(),
+            duty_cycler: DutyCycler::default(),
+            cost_shedder: None,
+            forecaster: None,
+            phase_monitor: None,
+            arc_detector: None,
+            relay_health: RelayHealth::default(),
+            thermal: None,
+            reserve: None,
+            submeter: None,
+            cold_load_pickup: None,
+            motors: None,
+            estop: None,
+            clock: Arc::new(SystemClock),
+            zones: Vec::new(),
+            phase: None,
+            power_budget: None,
+            energy: None,
+            mid_id: None,
+            reconnect_permitted: false,
+            mid_key: None,
+            liveness_probes: Vec::new(),
+            rebalances: VecDeque::new(),
+            registered: false,
+            registration_due_at: 0,
+            protection: Protection::new(self.protection),
+            last_voltage: self.voltage_ref,
+            last_frequency: None,
+            undervoltage: false,
+            last_observation_at: 0,
+            faulty_channels: BTreeSet::new(),
+            queued_alarms: Vec::new(),
+            link_budget: None,
+            min_fade_margin_db: DEFAULT_MIN_FADE_MARGIN_DB,
+            weak_links: BTreeSet::new(),
+            invariant_checks: false,
+            reported_violations: BTreeSet::new(),
+        })
+    }
+}
+
+impl EdgeNode {
+    pub fn builder(id: &str) -> EdgeNodeBuilder {
+        EdgeNodeBuilder::new(id)
+    }
+
+    /// Read the time from `clock`, here and in our orchestrator client. The intervals
+    /// in `run` follow tokio's clock, which tests pause and advance separately.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        if let Some(client) = &mut self.client {
+            client.set_clock(clock.clone());
+        }
+        self.clock = clock;
+    }
+
+    /// Restore persisted state and announce ourselves, before the event loop starts.
+    pub async fn start(&mut self) {
+        info!("Node {} starting up (MeshType: {:?})...", self.id, self.mesh_type);
+
+        self.restore_state().await;
+
+        // Send Initial Setup Message (Feature Report with full relay metadata),
+        // or ask to be commissioned first
+        if self.state == NodeState::Joining {
+            self.send_join_request().await;
+        } else {
+            self.send_feature_report().await;
+        }
+    }
+
+    /// Run the event loop until `stop` resolves, finishing the task in hand first.
+    pub async fn run(&mut self, stop: impl Future<Output = ()>) {
+        self.start().await;
+        tokio::pin!(stop);
+
+        // Event-driven intervals (no busy polling!)
+        let mut adc_interval = tokio::time::interval(Task::Adc.interval());
+        let mut heartbeat_interval = tokio::time::interval(Task::Heartbeat.interval());
+        let mut message_poll_interval = tokio::time::interval(Task::Messages.interval());
+        let mut profile_interval = tokio::time::interval(Task::Profile.interval());
+        let mut tamper_interval = tokio::time::interval(Task::Tamper.interval());
+        let mut security_interval = tokio::time::interval(Task::Security.interval());
+        let mut neighbor_interval = tokio::time::interval(Task::Neighbors.interval());
+        let mut election_interval = tokio::time::interval(Task::Election.interval());
+        let mut power_interval = tokio::time::interval(Task::PowerSharing.interval());
+        let mut mid_interval = tokio::time::interval(Task::Mid.interval());
+        let mut source_interval = tokio::time::interval(Task::Sources.interval());
+        let mut demand_response_interval = tokio::time::interval(Task::DemandResponse.interval());
+        let mut tariff_interval = tokio::time::interval(Task::Tariff.interval());
+        let mut forecast_interval = tokio::time::interval(Task::Forecast.interval());
+        let mut liveness_interval = tokio::time::interval(Task::Liveness.interval());
+        let mut registration_interval = tokio::time::interval(Task::Registration.interval());
+        let mut generator_interval = tokio::time::interval(Task::Generator.interval());
+        let mut transfer_interval = tokio::time::interval(Task::Transfer.interval());
+
+        // First tick fires immediately; skip it for heartbeat and profile reports
+        heartbeat_interval.tick().await;
+        profile_interval.tick().await;
+        security_interval.tick().await;
+        neighbor_interval.tick().await;
+
+        info!("Entering event loop (ADC: 5s, Heartbeat: 60s)");
+
+        loop {
+            let task = tokio::select! {
+                _ = &mut stop => return,
+                _ = adc_interval.tick() => Task::Adc,
+                _ = tamper_interval.tick() => Task::Tamper,
+                _ = profile_interval.tick() => Task::Profile,
+                _ = neighbor_interval.tick() => Task::Neighbors,
+                _ = election_interval.tick() => Task::Election,
+                _ = power_interval.tick() => Task::PowerSharing,
+                _ = source_interval.tick() => Task::Sources,
+                _ = demand_response_interval.tick() => Task::DemandResponse,
+                _ = tariff_interval.tick() => Task::Tariff,
+                _ = forecast_interval.tick() => Task::Forecast,
+                _ = mid_interval.tick() => Task::Mid,
+                _ = liveness_interval.tick() => Task::Liveness,
+                _ = registration_interval.tick() => Task::Registration,
+                _ = security_interval.tick() => Task::Security,
+                _ = heartbeat_interval.tick() => Task::Heartbeat,
+                _ = generator_interval.tick() => Task::Generator,
+                _ = transfer_interval.tick() => Task::Transfer,
+                _ = message_poll_interval.tick() => Task::Messages,
+            };
+            self.run_task(task).await;
+        }
+    }
+
+    /// Run like `run`, but in steps of the fastest task on `clock` (which must be ours),
+    /// advanced `scale` times faster than real time. Tasks run in the same order they
+    /// would at full speed, however fast that is.
+    pub async fn run_accelerated(&mut self, clock: VirtualClock, scale: f64, stop: impl Future<Output = ()>) {
+        self.start().await;
+        tokio::pin!(stop);
+        info!("Entering event loop at {}x real time", scale);
+        let step = Task::Messages.interval();
+        let started = tokio::time::Instant::now();
+        let mut elapsed = Duration::ZERO;
+        loop {
+            self.run_due(elapsed).await;
+            clock.advance(step);
+            elapsed += step;
+            // Behind schedule, we catch up without sleeping
+            tokio::select! {
+                _ = &mut stop => return,
+                _ = tokio::time::sleep_until(started + elapsed.div_f64(scale)) => {}
+            }
+        }
+    }
+
+    /// Wind down before the process exits: put the relays in the shutdown safe state,
+    /// send what's queued and a last heartbeat saying we're going, and flush the
+    /// transport and storage. Storage keeps the safe state, so a restart comes up in it,
+    /// unless `resume_on_boot` asks to resume operation where it left off.
+    pub async fn shutdown(&mut self) {
+        warn!("Node {} shutting down", self.id);
+        let before = self.persisted_state();
+        let safe_state: Vec<(String, bool)> = self.shutdown_safe_state.iter().map(|(id, c)| (id.clone(), *c)).collect();
+        for (relay_id, closed) in safe_state {
+            self.actuate_relay(&relay_id, closed, "shutdown").await;
+        }
+        let persisted = if self.resume_on_boot { before } else { self.persisted_state() };
+        self.send_queued_alarms().await;
+        if let Some(client) = &self.client {
+            let heartbeat = Heartbeat { shutting_down: true, ..self.heartbeat(client) };
+            if let Err(e) = client.send_heartbeat(heartbeat).await {
+                error!("Failed to send last heartbeat: {}", e);
+            }
+            if let Err(e) = client.close().await {
+                warn!("Messages sent while shutting down may be lost: {}", e);
+            }
+        }
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.put_json(STATE_KEY, &persisted).and_then(|_| storage.flush()) {
+                error!("Failed to persist node state on shutdown: {}", e);
+            }
+        }
+        info!("Node {} stopped", self.id);
+    }
+
+    /// Run the tasks due `since_start` into a run stepped through simulated time.
+    pub async fn run_due(&mut self, since_start: Duration) {
+        for task in Task::ALL {
+            let due = since_start.as_millis().is_multiple_of(task.interval().as_millis());
+            if due && !(since_start.is_zero() && task.waits_first()) {
+                self.run_task(task).await;
+            }
+        }
+    }
+
+    /// One round of a periodic task.
+    pub async fn run_task(&mut self, task: Task) {
+        match task {
+            // Event 1: ADC/Voltage check (every 5 seconds)
+            Task::Adc => {
+                self.sample_phases().await;
+                self.check_voltage().await;
+                self.sample_circuits().await;
+                self.sample_submeter().await;
+                self.check_relays().await;
+                self.check_arcs().await;
+                self.sample_power_quality().await;
+                self.update_inverter();
+                self.update_battery();
+                // Rated draw follows the voltage, so re-check the budget
+                self.enforce_power_budget().await;
+                self.enforce_phase_limits().await;
+                self.check_temperature().await;
+                self.check_reserve().await;
+                self.pace_restores().await;
+                self.run_motor_starts().await;
+            }
+            // Enclosure tamper switch and emergency stop; alerts go out immediately
+            Task::Tamper => {
+                self.check_estop().await;
+                self.check_tamper().await;
+            }
+            // Daily load profile report, and the monthly outage report once a month is over
+            Task::Profile => {
+                self.send_load_profile().await;
+                self.send_outage_report().await;
+            }
+            // Who we can hear on the radio, and how well
+            Task::Neighbors => {
+                self.send_neighbor_report().await;
+                self.check_link_margins();
+            }
+            // Coordinator election while the orchestrator is unreachable
+            Task::Election => self.run_election().await,
+            // Island power-sharing negotiation
+            Task::PowerSharing => {
+                self.run_power_sharing().await;
+                self.record_energy().await;
+            }
+            // Spare capacity of our battery and solar
+            Task::Sources => self.send_source_capacity().await,
+            // Utility demand-response events starting and ending
+            Task::DemandResponse => self.run_demand_response().await,
+            // Loads deferred through tariff peaks
+            Task::Tariff => self.run_tariff().await,
+            // Loads shed ahead of a forecast overload
+            Task::Forecast => self.run_forecast().await,
+            // Transformer isolation device status
+            Task::Mid => self.run_mid().await,
+            // Orchestrator looking for a silent node via its neighbours
+            Task::Liveness => self.run_liveness().await,
+            // Resend our FeatureReport until acknowledged, and refresh it now and then
+            Task::Registration => self.run_registration().await,
+            // Rejected-traffic counters
+            Task::Security => self.send_security_report().await,
+            // Event 2: Heartbeat timer (every 60 seconds)
+            Task::Heartbeat => {
+                self.advance_keyring();
+                if self.state == NodeState::Joining {
+                    self.send_join_request().await;
+                } else {
+                    self.check_distortion().await;
+                    self.send_heartbeat().await;
+                }
+            }
+            // Backup generator start/stop sequence
+            Task::Generator => self.run_generator().await,
+            // Source transfers waiting out their dead time
+            Task::Transfer => {
+                self.run_transfer().await;
+            }
+            // Event 3: Check for incoming LoRa messages
+            // NOTE: This is a low-frequency poll (100ms) because the current LoRa mock/stub
+            // returns immediately from receive(). Once we implement the real SX126x driver
+            // (M3), we can replace this with a true async receive that awaits a GPIO
+            // interrupt (DIO1 pin) when a packet arrives, eliminating polling entirely.
+            Task::Messages => {
+                if let Some(cmd) = self.poll_for_command().await {
+                    self.handle_command(cmd).await;
+                }
+                self.finish_firmware_update().await;
+            }
+        }
+        if self.invariant_checks {
+            self.check_invariants();
+        }
+        self.send_queued_alarms().await;
+    }
+
+    /// Alarm invariants newly broken since the last check. Whatever faults the node
+    /// meets on the way, its relays must never break an interlock between tasks.
+    pub fn check_invariants(&mut self) {
+        let violations: BTreeSet<String> = interlock::violations(&self.relays, &self.interlock_conditions())
+            .into_iter()
+            .collect();
+        let new: Vec<String> = violations.difference(&self.reported_violations).cloned().collect();
+        for violation in new {
+            error!("Invariant violated in {:?}: {}", self.state, violation);
+            self.queue_alarm("invariant_violation", &violation);
+        }
+        self.reported_violations = violations;
+    }
+
+    /// Poll the tamper switch and react to it opening or closing.
+    pub async fn check_tamper(&mut self) {
+        let Some(switch) = &mut self.tamper else { return };
+        let opened = match switch.is_open() {
+            Ok(opened) => opened,
+            Err(e) => {
+                warn!("Tamper switch read failed: {}", e);
+                return;
+            }
+        };
+        if opened == self.tamper_open {
+            return;
+        }
+        self.tamper_open = opened;
+        self.audit(AuditRecord::Tamper { opened });
+
+        let mut safe_state_applied = false;
+        if opened {
+            error!("Enclosure opened!");
+            self.events.publish(NodeEvent::Alarm {
+                code: "tamper".to_string(),
+                message: "enclosure opened".to_string(),
+            });
+            let safe_state: Vec<(String, bool)> = self.tamper_safe_state.iter().map(|(id, c)| (id.clone(), *c)).collect();
+            for (relay_id, closed) in safe_state {
+                self.actuate_relay(&relay_id, closed, "tamper").await;
+            }
+            safe_state_applied = !self.tamper_safe_state.is_empty();
+            if safe_state_applied {
+                self.persist_state();
+            }
+        } else {
+            warn!("Enclosure closed");
+        }
+        if let Some(client) = &self.client {
+            if let Err(e) = client.send_tamper_alert(&self.id, opened, safe_state_applied).await {
+                error!("Failed to send tamper alert: {}", e);
+            }
+        }
+    }
+
+    /// Latch the node in Faulted once the emergency stop has tripped. Its watch has
+    /// already opened the relays; this brings our record of them into line, drops any
+    /// loads waiting to come back, and raises the alarm.
+    pub async fn check_estop(&mut self) {
+        let Some(estop) = &self.estop else { return };
+        if !estop.tripped() || self.state == NodeState::Faulted {
+            return;
+        }
+        error!("Emergency stop asserted: latched Faulted until a fault reset");
+        self.set_state(NodeState::Faulted);
+        if let Some(pickup) = &mut self.cold_load_pickup {
+            pickup.clear();
+        }
+        if let Some(motors) = &mut self.motors {
+            motors.clear();
+        }
+        let opened: Vec<String> = self.relays.iter().filter(|r| r.is_closed).map(|r| r.id.clone()).collect();
+        for relay_id in &opened {
+            self.actuate_relay(relay_id, false, "emergency_stop").await;
+        }
+        self.raise_critical_alarm("emergency_stop", &format!("emergency stop asserted; {} relays opened", opened.len())).await;
+    }
+
+    /// Check voltage and send alert if under threshold
+    pub async fn check_voltage(&mut self) {
+        let direction = self.ct_directions.get(&0).copied().unwrap_or_default();
+        let voltage = if let Some(sensor) = self.power_sensor.clone() {
+            match sensor.read_watts(0).await {
+                Ok(watts) if !plausible_amps(watts / self.voltage_ref) => {
+                    warn!("Implausible power reading {} W, using default voltage", watts);
+                    self.sensor_fault(0, watts).await;
+                    self.voltage_ref
+                }
+                Ok(watts) => {
+                    let watts = signed_watts(&sensor, 0, direction, watts).await;
+                    self.faulty_channels.remove(&0);
+                    info!("Power reading: {} W ({})", watts.abs(), if watts < 0.0 { "export" } else { "import" });
+                    self.events.publish(NodeEvent::Measurement { channel: 0, watts, voltage: self.voltage_ref });
+                    if let Some(store) = &self.measurements {
+                        let sample = Sample { timestamp: self.clock.unix(), channel: 0, watts };
+                        if let Err(e) = store.record(&sample) {
+                            warn!("Failed to record measurement: {}", e);
+                        }
+                    }
+                    self.voltage_ref
+                }
+                Err(e) => {
+                    warn!("ADC read failed: {}, using default voltage", e);
+                    self.voltage_ref
+                }
+            }
+        } else {
+            self.voltage_ref
+        };
+        // Where the phases are sampled, the weakest of them decides
+        let voltage = self.phase_monitor.as_ref().and_then(|m| m.lowest_voltage()).unwrap_or(voltage);
+
+        let frequency = match &self.power_sensor {
+            Some(sensor) => sensor.read_frequency_hz().await.ok(),
+            None => None,
+        };
+
+        self.last_voltage = voltage;
+        self.last_frequency = frequency;
+        let sag = self.protection.track_sag(voltage, self.clock.unix(), self.clock.instant());
+        // An under-voltage still inside the ride-through window is only recorded
+        let excursion = self.protection.observe(voltage, frequency)
+            .filter(|e| *e != Excursion::Undervoltage || !self.protection.riding_through());
+        self.share_voltage_observation(voltage, excursion.is_some()).await;
+        if let Some(sag) = sag {
+            self.report_sag(sag).await;
+        }
+
+        // Under-voltage (or frequency) detection flow
+        if let Some(excursion) = excursion {
+            match self.state {
+                NodeState::Normal => {
+                    let message = self.protection.describe(excursion, voltage, frequency);
+                    warn!("Grid failure detected ({})! Sending alert to orchestrator.", message);
+                    self.events.publish(NodeEvent::Alarm { code: excursion.code().to_string(), message });
+                    self.send_voltage_alert(voltage).await;
+                    self.set_state(NodeState::AlertSent);
+                }
+                NodeState::AlertSent => {
+                    // Waiting for orchestrator response, or for enough neighbours to agree
+                    self.check_island_quorum().await;
+                }
+                NodeState::Islanded | NodeState::BlackStart => {
+                    // Already islanded
+                }
+                NodeState::Joining => {
+                    // No trusted orchestrator yet; local protection only
+                }
+                NodeState::Faulted => {
+                    // Everything is open until a fault reset
+                }
+            }
+        }
+    }
+
+    /// Record a sag for power-quality statistics, whether or not it was treated as a grid failure.
+    async fn report_sag(&self, sag: Sag) {
+        info!("Voltage sag to {:.1} V for {:.1} s{}", sag.min_voltage, sag.duration.as_secs_f32(),
+              if sag.escalated { "" } else { ", ridden through" });
+        self.events.publish(NodeEvent::VoltageSag {
+            start: sag.start,
+            duration_secs: sag.duration.as_secs_f32(),
+            min_voltage: sag.min_voltage,
+            escalated: sag.escalated,
+        });
+        if let Some(client) = &self.client {
+            if let Err(e) = client.send_voltage_sag(&self.id, &sag).await {
+                error!("Failed to send voltage sag: {}", e);
+            }
+        }
+    }
+
+    /// Tell neighbours what we measure when the verdict changes, and keep repeating it
+    /// while under voltage so our vote doesn't expire. Only sent when islanding by quorum.
+    async fn share_voltage_observation(&mut self, voltage: f32, undervoltage: bool) {
+        if self.island_quorum.is_none() || self.state == NodeState::Joining {
+            return;
+        }
+        let now = self.clock.unix();
+        let changed = undervoltage != self.undervoltage;
+        self.undervoltage = undervoltage;
+        let resend_due = undervoltage && now - self.last_observation_at >= OBSERVATION_RESEND_SECS;
+        if !(changed || resend_due) {
+            return;
+        }
+        if let Some(client) = &self.client {
+            match client.send_voltage_observation(&self.id, voltage, undervoltage).await {
+                Ok(()) => self.last_observation_at = now,
+                Err(e) => error!("Failed to send voltage observation: {}", e),
+            }
+        }
+    }
+
+    /// Island once enough neighbours see the under-voltage too, so one bad reading
+    /// can't take the street off the grid.
+    async fn check_island_quorum(&mut self) {
+        let Some(quorum) = self.island_quorum else { return };
+        let Some(client) = &self.client else { return };
+        let agreeing = client.undervoltage_neighbors();
+        if agreeing.len() >= quorum {
+            warn!("Islanding: {} neighbours agree on under-voltage ({})", agreeing.len(), agreeing.join(", "));
+            self.enter_island_mode().await;
+        }
+    }
+
+    /// Read every mapped CT channel, learning the load profile and alerting on abnormal current draw.
+    pub async fn sample_circuits(&mut self) {
+        let Some(sensor) = self.power_sensor.clone() else { return };
+        let mut channels: Vec<(&String, &u8)> = self.ct_channels.iter().collect();
+        channels.sort_by_key(|(_, ch)| **ch);
+
+        let hour = self.clock.now().with_timezone(&chrono::Local).hour();
+        let mut anomalies = Vec::new();
+        let mut implausible = Vec::new();
+        for (relay_id, &channel) in channels {
+            let direction = self.ct_directions.get(&channel).copied().unwrap_or_default();
+            match sensor.read_current_amps(channel).await {
+                Ok(amps) if !plausible_amps(amps) => {
+                    warn!("Implausible reading {}A on channel {}, ignoring", amps, channel);
+                    implausible.push((channel, amps * self.voltage_ref));
+                }
+                Ok(amps) => {
+                    let watts = signed_watts(&sensor, channel, direction, amps * self.voltage_ref).await;
+                    self.faulty_channels.remove(&channel);
+                    self.load_profile.record(relay_id, hour, watts);
+                    if let Some(forecaster) = &mut self.forecaster {
+                        forecaster.observe(relay_id, watts);
+                    }
+                    self.circuit_watts.insert(relay_id.clone(), watts);
+                    if let Some(anomaly) = self.anomaly.observe(channel, amps) {
+                        anomalies.push((relay_id.clone(), anomaly));
+                    }
+                }
+                Err(e) => warn!("CT read on channel {} failed: {}", channel, e),
+            }
+        }
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.put_json(PROFILE_KEY, &self.load_profile) {
+                warn!("Failed to persist load profile: {}", e);
+            }
+        }
+
+        for (channel, watts) in implausible {
+            self.sensor_fault(channel, watts).await;
+        }
+        for (relay_id, anomaly) in anomalies {
+            warn!("Abnormal current on {} (channel {}): {:.1}A vs {:.1}A baseline ({:+.1} sigma)",
+                  relay_id, anomaly.channel, anomaly.amps, anomaly.baseline_amps, anomaly.magnitude);
+            self.events.publish(NodeEvent::Alarm {
+                code: "current_anomaly".to_string(),
+                message: format!("{} drawing {:.1}A (baseline {:.1}A)", relay_id, anomaly.amps, anomaly.baseline_amps),
+            });
+            if let Some(client) = &self.client {
+                if let Err(e) = client.send_anomaly_alert(&self.id, Some(&relay_id), &anomaly).await {
+                    error!("Failed to send anomaly alert: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Meter each sub-metered circuit. Those named for a relay also stand in for a
+    /// `ct_channels` clamp on it.
+    pub async fn sample_submeter(&mut self) {
+        let (now, volts) = (self.clock.instant(), self.last_voltage);
+        let Some(mut submeter) = self.submeter.take() else { return };
+        // Each clamp is sampled over several mains cycles: off the event loop
+        let sampled = tokio::task::spawn_blocking(move || {
+            let readings = submeter.sample(now, volts).clone();
+            (submeter, readings)
+        }).await;
+        let readings = match sampled {
+            Ok((submeter, readings)) => {
+                self.submeter = Some(submeter);
+                readings
+            }
+            Err(e) => {
+                error!("Sub-metering stopped: {}", e);
+                return;
+            }
+        };
+        for (circuit, reading) in &readings {
+            if self.relays.iter().any(|r| r.id == *circuit) && !self.ct_channels.contains_key(circuit) {
+                self.circuit_watts.insert(circuit.clone(), reading.watts);
+            }
+            self.events.publish(NodeEvent::CircuitPower {
+                circuit: circuit.clone(),
+                watts: reading.watts,
+                imported_wh: reading.imported_wh,
+                exported_wh: reading.exported_wh,
+            });
+        }
+    }
+
+    /// Read the current on each phase of a three-phase service, and its voltage where that is sampled.
+    pub async fn sample_phases(&mut self) {
+        let (Some(monitor), Some(sensor)) = (&mut self.phase_monitor, &self.power_sensor) else { return };
+        let mut implausible = Vec::new();
+        for (phase, sensing) in monitor.sensing.clone() {
+            let channel = sensing.current_channel;
+            let amps = match sensor.read_current_amps(channel).await {
+                Ok(amps) if !plausible_amps(amps) => {
+                    warn!("Implausible reading {}A on phase {} (channel {}), ignoring", amps, phase.as_str(), channel);
+                    implausible.push((channel, amps * self.voltage_ref));
+                    continue;
+                }
+                Ok(amps) => amps,
+                Err(e) => {
+                    warn!("Phase {} current read failed: {}", phase.as_str(), e);
+                    continue;
+                }
+            };
+            let volts = match sensing.voltage_channel {
+                Some(channel) => sensor.read_voltage(channel).await.unwrap_or_else(|e| {
+                    warn!("Phase {} voltage read failed: {}, using default voltage", phase.as_str(), e);
+                    self.voltage_ref
+                }),
+                None => self.voltage_ref,
+            };
+            self.faulty_channels.remove(&channel);
+            monitor.record(PhaseReading { phase, volts, amps });
+            self.events.publish(NodeEvent::PhaseMeasurement { phase, volts, amps });
+        }
+        for (channel, watts) in implausible {
+            self.sensor_fault(channel, watts).await;
+        }
+    }
+
+    /// Compare each relay's commanded state with its auxiliary contact and the current on
+    /// its circuit. A relay found failed is kept out of use, and opened if it's meant to be
+    /// closed so nothing counts on it, until an operator closes it again.
+    pub async fn check_relays(&mut self) {
+        let mut failed = Vec::new();
+        for relay in &self.relays {
+            let feedback_closed = match (self.relay_pins.get(&relay.id), &self.relay_driver) {
+                (Some(pin), Some(driver)) => driver.read_feedback(*pin).await.unwrap_or_else(|e| {
+                    warn!("Failed to read feedback of {}: {}", relay.id, e);
+                    None
+                }),
+                _ => None,
+            };
+            let amps = self.ct_channels.get(&relay.id)
+                .filter(|ch| !self.faulty_channels.contains(ch))
+                .and_then(|_| self.circuit_watts.get(&relay.id))
+                .map(|watts| watts / self.voltage_ref);
+            if feedback_closed.is_none() && amps.is_none() {
+                continue;
+            }
+            let check = RelayCheck { commanded_closed: relay.is_closed, feedback_closed, amps };
+            if let Some((failure, evidence)) = self.relay_health.observe(&relay.id, check) {
+                failed.push((relay.id.clone(), failure, evidence));
+            }
+        }
+        for (relay_id, failure, evidence) in failed {
+            error!("Relay {} has {} ({}); taking it out of use", relay_id, failure.as_str(), evidence.as_str());
+            if failure == RelayFailure::FailedOpen {
+                self.actuate_relay(&relay_id, false, "relay_failed").await;
+            }
+            self.events.publish(NodeEvent::RelayFailed {
+                relay_id: relay_id.clone(),
+                failure: failure.as_str().to_string(),
+                evidence: evidence.as_str().to_string(),
+            });
+            if let Some(client) = &self.client {
+                if let Err(e) = client.send_failed_relay(&self.id, &relay_id, failure, evidence).await {
+                    error!("Failed to send failed relay alert: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Look for arcing on the circuits watched for it, opening one whose current has
+    /// looked erratic for long enough and keeping it open until an operator closes it.
+    pub async fn check_arcs(&mut self) {
+        let (Some(detector), Some(sensor)) = (&mut self.arc_detector, &self.power_sensor) else { return };
+        let watched: Vec<(String, u8)> = self.relays.iter()
+            .filter(|r| r.is_closed && detector.watches(&r.id))
+            .filter_map(|r| self.ct_channels.get(&r.id).map(|ch| (r.id.clone(), *ch)))
+            .collect();
+        let mut tripped = Vec::new();
+        for (relay_id, channel) in watched {
+            let amps = self.circuit_watts.get(&relay_id).map_or(0.0, |w| w.abs() / self.voltage_ref);
+            let erratic = if amps < MIN_ARC_AMPS {
+                None
+            } else {
+                match sensor.sample_current_waveform(channel).await {
+                    Ok(waveform) => arc_fault::erratic_share(&waveform),
+                    Err(e) => {
+                        debug!("Not checking {} for arcing: {}", relay_id, e);
+                        None
+                    }
+                }
+            };
+            if detector.observe(&relay_id, erratic) {
+                tripped.push((relay_id, channel, erratic.unwrap_or_default()));
+            }
+        }
+        for (relay_id, channel, erratic) in tripped {
+            error!("Arcing on {} (channel {}); opening it", relay_id, channel);
+            self.actuate_relay(&relay_id, false, "arc_fault").await;
+            let message = format!("{} opened: erratic current on channel {} ({:.0}% of it)", relay_id, channel, erratic * 100.0);
+            self.raise_critical_alarm("arc_fault", &message).await;
+        }
+    }
+
+    /// Real, reactive and apparent power and the power factor on the main clamp and each
+    /// mapped CT, where the hardware samples voltage alongside the current.
+    pub async fn sample_power_quality(&mut self) {
+        let Some(sensor) = &self.power_sensor else { return };
+        let channels: BTreeSet<u8> = std::iter::once(0).chain(self.ct_channels.values().copied()).collect();
+        for channel in channels.difference(&self.faulty_channels) {
+            let mut quality = match sensor.read_power_quality(*channel).await {
+                Ok(quality) => quality,
+                Err(e) => {
+                    debug!("Power quality not measured: {}", e);
+                    return;
+                }
+            };
+            let direction = self.ct_directions.get(channel).copied().unwrap_or_default();
+            quality.real_watts = direction.apply(quality.real_watts);
+            self.power_quality.insert(*channel, quality);
+            self.events.publish(NodeEvent::PowerQuality {
+                channel: *channel,
+                real_watts: quality.real_watts,
+                reactive_var: quality.reactive_var,
+                apparent_va: quality.apparent_va,
+                power_factor: quality.power_factor,
+            });
+        }
+    }
+
+    /// Estimate the harmonic distortion of the mains voltage from its sampled waveform,
+    /// alarming once each time it goes over the limit.
+    pub async fn check_distortion(&mut self) {
+        let Some(sensor) = &self.power_sensor else { return };
+        let Ok(waveform) = sensor.sample_voltage_waveform().await else { return };
+        let Some(thd) = harmonics::thd(&waveform) else {
+            debug!("Voltage sampled at {:.0} Hz, too slow to see its harmonics", waveform.sample_rate_hz);
+            return;
+        };
+        self.voltage_thd = Some(thd);
+        self.events.publish(NodeEvent::VoltageDistortion { thd });
+        let distorted = thd > VOLTAGE_THD_LIMIT;
+        if distorted && !self.distorted {
+            warn!("Voltage THD at {:.1}%", thd * 100.0);
+            self.queue_alarm("voltage_distortion", &format!("voltage THD {:.1}% over {:.0}%", thd * 100.0, VOLTAGE_THD_LIMIT * 100.0));
+        }
+        self.distorted = distorted;
+    }
+
+    async fn send_load_profile(&self) {
+        if let Some(client) = &self.client {
+            if let Err(e) = client.send_load_profile(&self.id, &self.load_profile).await {
+                error!("Failed to send load profile: {}", e);
+            }
+        }
+    }
+
+    /// Report last month's interruptions once it is over, retrying daily until sent.
+    async fn send_outage_report(&mut self) {
+        let Some(client) = &self.client else { return };
+        let Some(month) = self.outages.finished(self.clock.unix()).cloned() else { return };
+        match client.send_outage_report(&self.id, &month).await {
+            Ok(()) => {
+                self.outages.reported();
+                self.persist_outages();
+            }
+            Err(e) => error!("Failed to send outage report: {}", e),
+        }
+    }
+
+    async fn send_neighbor_report(&self) {
+        // Not part of the mesh yet
+        if self.state == NodeState::Joining {
+            return;
+        }
+        if let Some(client) = &self.client {
+            if let Err(e) = client.send_neighbor_report(&self.id).await {
+                error!("Failed to send neighbor report: {}", e);
+            }
+        }
+    }
+
+    /// Warn about neighbours we hear with too little fade margin, once each until the
+    /// link recovers, so an installer sees a poorly placed antenna at commissioning.
+    pub fn check_link_margins(&mut self) {
+        let (Some(budget), Some(client)) = (&self.link_budget, &self.client) else { return };
+        let margins = budget.margins(&client.neighbors());
+        let weak: BTreeSet<String> = margins.iter()
+            .filter(|m| m.margin_db < self.min_fade_margin_db)
+            .map(|m| m.node_id.clone())
+            .collect();
+        for margin in margins.iter().filter(|m| weak.contains(&m.node_id) && !self.weak_links.contains(&m.node_id)) {
+            let message = format!("link to {} has {:.1} dB fade margin (RSSI {} dBm, about {:.0} m), below {:.1} dB",
+                                  margin.node_id, margin.margin_db, margin.rssi, margin.estimated_distance_m, self.min_fade_margin_db);
+            warn!("Weak radio {}", message);
+            self.events.publish(NodeEvent::Alarm { code: "weak_link".to_string(), message });
+        }
+        self.weak_links = weak;
+    }
+
+    /// Process election traffic and timers. As coordinator during black start, step
+    /// restoration through the priority classes; as follower, apply the coordinator's steps.
+    pub async fn run_election(&mut self) {
+        if self.state == NodeState::Joining {
+            return;
+        }
+        let Some(client) = &self.client else { return };
+        let now = self.clock.unix();
+        let messages = client.take_coordination();
+        if let Some(failover) = &mut self.failover {
+            for msg in messages.iter().filter(|m| m.kind == KIND_ORCHESTRATOR_ALIVE) {
+                failover.beacon(&msg.node_id, now);
+            }
+        }
+        let Some(election) = &mut self.election else { return };
+        let was_coordinator = election.is_coordinator();
+        let mut outgoing = Vec::new();
+        let mut restore = Vec::new();
+        for msg in messages {
+            if msg.kind == KIND_RESTORE_STEP {
+                if election.accepts_restore_step(&msg) {
+                    restore.push(msg.priority);
+                }
+                continue;
+            }
+            outgoing.extend(election.observe(&msg, now));
+        }
+        outgoing.extend(election.tick(now));
+        if self.state == NodeState::BlackStart {
+            if let Some(step) = election.next_restore_step(now) {
+                restore.push(step.priority);
+                outgoing.push(step);
+            }
+        }
+        match (was_coordinator, election.is_coordinator()) {
+            (false, true) => warn!("Orchestrator unreachable; acting as coordinator for term {}", election.term),
+            (true, false) => info!("Stepping down as coordinator"),
+            _ => {}
+        }
+        for msg in outgoing {
+            if let Err(e) = client.send_coordination(msg).await {
+                error!("Failed to send coordination message: {}", e);
+            }
+        }
+        if self.state != NodeState::BlackStart {
+            return;
+        }
+        for priority in restore {
+            let priority = priority_from_proto(priority);
+            info!("Coordinator restoring {:?} loads", priority);
+            self.activate_relays_by_priority(priority).await;
+        }
+    }
+
+    /// Spare supply from our closed sources (scaled by battery charge and held to what
+    /// the battery's management system lets it give, but never below what the inverter
+    /// measures the panels making), and what our loads would draw.
+    fn power_position(&self) -> (f32, Demand) {
+        let watts = |r: &Relay| r.amperage * self.voltage_ref;
+        let rated = self.relays.iter()
+            .filter(|r| r.relay_type == RelayType::Source && r.is_closed)
+            .map(watts)
+            .sum::<f32>();
+        let solar = self.inverter.as_ref().and_then(SolarInverter::output_watts).unwrap_or(0.0);
+        let discharge_limit = self.battery_status.and_then(|s| s.max_discharge_watts()).unwrap_or(f32::INFINITY);
+        let available = (rated * self.battery_soc).min(discharge_limit).max(solar.min(rated));
+        let loads = || self.relays.iter().filter(|r| r.relay_type == RelayType::Load);
+        let mut demand = Demand {
+            requested_watts: loads().map(watts).sum(),
+            critical_watts: loads().filter(|r| r.priority == Priority::Critical && r.community_criticality == 0).map(watts).sum(),
+            ..Default::default()
+        };
+        for relay in loads().filter(|r| r.community_criticality > 0) {
+            demand.community_watts[(relay.community_criticality.min(MAX_COMMUNITY_CRITICALITY) - 1) as usize] += watts(relay);
+        }
+        (available, demand)
+    }
+
+    /// While islanded: advertise our supply and demand, divide the island's supply if we
+    /// are the coordinator, and live within the budget the coordinator grants us.
+    pub async fn run_power_sharing(&mut self) {
+        if !matches!(self.state, NodeState::Islanded | NodeState::BlackStart) {
+            self.power_budget = None;
+            return;
+        }
+        let (Some(election), Some(client)) = (&self.election, &self.client) else { return };
+        let (available, demand) = self.power_position();
+        if available > 0.0 {
+            if let Err(e) = client.send_power_offer(&self.id, available).await {
+                error!("Failed to send power offer: {}", e);
+            }
+        }
+        if demand.requested_watts > 0.0 {
+            if let Err(e) = client.send_power_request(&self.id, demand).await {
+                error!("Failed to send power request: {}", e);
+            }
+        }
+        if election.is_coordinator() {
+            let now = self.clock.unix();
+            for (node_id, budget_watts, request_timestamp) in client.allocate_power() {
+                let grant = PowerGrant {
+                    target_node_id: node_id,
+                    coordinator_id: self.id.clone(),
+                    term: election.term,
+                    budget_watts,
+                    timestamp: now,
+                    request_timestamp,
+                };
+                if let Err(e) = client.send_power_grant(grant).await {
+                    error!("Failed to send power grant: {}", e);
+                }
+            }
+        }
+        let Some(grant) = client.take_power_grant(&self.id) else { return };
+        // Only the coordinator we follow, in its current term, sets our budget
+        if election.coordinator() != Some(grant.coordinator_id.as_str()) || grant.term != election.term {
+            warn!("Ignoring power grant from {} (term {})", grant.coordinator_id, grant.term);
+            return;
+        }
+        info!("Power budget {:.0} W granted by {}", grant.budget_watts, grant.coordinator_id);
+        self.set_power_budget(grant.budget_watts).await;
+    }
+
+    /// Live within `watts` from now on, shedding loads if we draw more.
+    pub async fn set_power_budget(&mut self, watts: f32) {
+        self.power_budget = Some(watts);
+        self.enforce_power_budget().await;
+    }
+
+    /// Advertise what our battery and solar could supply, for the coordinator to draw
+    /// on while islanded and the orchestrator to plan with.
+    pub async fn send_source_capacity(&self) {
+        let (Some(sources), Some(client)) = (&self.sources, &self.client) else { return };
+        if !self.relays.iter().any(|r| r.relay_type == RelayType::Source) {
+            return;
+        }
+        let capacity = sources.capacity(&self.relays, self.voltage_ref, self.battery_soc, self.local_hour());
+        if let Err(e) = client.send_source_capacity(&self.id, capacity).await {
+            error!("Failed to send source capacity: {}", e);
+        }
+    }
+
+    /// Meter what we draw from or supply to the island, closing a signed ledger entry each
+    /// period and when the island event ends; keep copies of neighbours' entries.
+    pub async fn record_energy(&mut self) {
+        if self.energy.is_none() {
+            return;
+        }
+        let now = self.clock.unix();
+        let islanded = matches!(self.state, NodeState::Islanded | NodeState::BlackStart);
+        let net_watts = self.metered_net_watts();
+        let Some(ledger) = &mut self.energy else { return };
+
+        if let Some(client) = &self.client {
+            for entry in client.take_energy_entries() {
+                ledger.insert(entry);
+            }
+        }
+        if islanded {
+            ledger.accumulate(net_watts, now);
+        }
+        let closing = ledger.is_open() && (!islanded || ledger.period_elapsed(now));
+        if let Some(mut entry) = closing.then(|| ledger.close(&self.id)).flatten() {
+            if let Some(se) = &mut self.secure_element {
+                let digest = energy::signing_digest(&entry);
+                match (se.public_key(self.sign_slot), se.sign(self.sign_slot, &digest)) {
+                    (Ok(public_key), Ok(signature)) => {
+                        entry.device_public_key = public_key.to_vec();
+                        entry.signature = signature.to_vec();
+                    }
+                    (Err(e), _) | (_, Err(e)) => warn!("Energy entry left unsigned: {}", e),
+                }
+            }
+            ledger.insert(entry.clone());
+            if let Some(client) = &self.client {
+                if let Err(e) = client.send_energy_entry(entry).await {
+                    error!("Failed to share energy entry: {}", e);
+                }
+            }
+        }
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.put_json(ENERGY_KEY, ledger) {
+                warn!("Failed to persist energy ledger: {}", e);
+            }
+        }
+    }
+
+    /// What we draw from the island (negative: supply to it), as metered: at the grid tie
+    /// where it has a CT clamp, otherwise by the clamps on our loads and sources, with
+    /// rated figures only for circuits that have none.
+    fn metered_net_watts(&self) -> f32 {
+        let grid_tie = self.relays.iter()
+            .find(|r| r.relay_type == RelayType::Grid && r.is_closed)
+            .and_then(|r| self.circuit_watts.get(&r.id));
+        if let Some(&watts) = grid_tie {
+            return watts;
+        }
+        let supplied: f32 = self.relays.iter()
+            .filter(|r| r.relay_type == RelayType::Source && r.is_closed)
+            .map(|r| self.circuit_watts.get(&r.id).map_or(r.amperage * self.voltage_ref * self.battery_soc, |w| w.abs()))
+            .sum();
+        self.measured_load_amps() * self.voltage_ref - supplied
+    }
+
+    /// Draw of our closed loads: their rating, the EV charger's at its limit, and what a
+    /// circuit with generation behind it measures while exporting.
+    fn load_watts(&self) -> f32 {
+        self.relays.iter()
+            .filter(|r| r.relay_type == RelayType::Load && r.is_closed)
+            .map(|r| self.relay_watts(r))
+            .sum()
+    }
+
+    fn relay_watts(&self, relay: &Relay) -> f32 {
+        if let Some(&watts) = self.circuit_watts.get(&relay.id).filter(|w| **w < 0.0) {
+            return watts;
+        }
+        let amps = match &self.ev_charger {
+            Some(charger) if charger.relay_id == relay.id => relay.amperage.min(charger.limit_amps()),
+            _ => relay.amperage,
+        };
+        amps * self.voltage_ref
+    }
+
+    /// Offer the EV charger what the power budget leaves it, within its range; without
+    /// a budget, all it may take.
+    fn fit_ev_charger(&mut self) {
+        let headroom = self.power_budget.map(|budget| budget - self.load_watts());
+        let voltage = self.voltage_ref.max(1.0);
+        let Some(charger) = &mut self.ev_charger else { return };
+        if !self.relays.iter().any(|r| r.id == charger.relay_id && r.is_closed) {
+            return;
+        }
+        let amps = match headroom {
+            Some(watts) => charger.limit_amps() + watts / voltage,
+            None => charger.ceiling_amps(),
+        };
+        charger.set_limit(amps);
+    }
+
+    /// Local time of day in fractional hours, for solar output.
+    fn local_hour(&self) -> f32 {
+        let now = self.clock.now().with_timezone(&chrono::Local);
+        now.hour() as f32 + now.minute() as f32 / 60.0
+    }
+
+    /// Read the inverter's output into our sources, and have it follow its droops on the
+    /// island's frequency and voltage.
+    fn update_inverter(&mut self) {
+        let Some(inverter) = &mut self.inverter else { return };
+        let watts = inverter.read();
+        let islanded = matches!(self.state, NodeState::Islanded | NodeState::BlackStart);
+        inverter.regulate(self.last_frequency, self.last_voltage, self.voltage_ref, islanded);
+        if let Some(sources) = &mut self.sources {
+            sources.record_solar(watts);
+        }
+    }
+
+    /// Step the generator's sequence and have the transfer group connect it exactly while
+    /// it carries the load. It runs only while we are islanded and off the grid.
+    async fn run_generator(&mut self) {
+        let now = self.clock.instant();
+        let off_grid = matches!(self.state, NodeState::Islanded | NodeState::BlackStart)
+            && !self.relays.iter().any(|r| r.relay_type == RelayType::Grid && r.is_closed);
+        let soc = self.battery.is_some().then_some(self.battery_soc);
+        let Some(generator) = &mut self.generator else { return };
+        let before = generator.state();
+        generator.tick(now, off_grid, soc);
+        let (relay_id, state, hours) = (generator.transfer_relay.clone(), generator.state(), generator.run_hours());
+
+        let transfer = state == GeneratorState::Transferred;
+        let closed = self.relays.iter().any(|r| r.id == relay_id && r.is_closed);
+        let pending = self.transfer.pending();
+        if transfer && !closed && pending.is_none() {
+            if !self.transfer_to(Some(Supply::Generator), "generator").await {
+                if let Some(generator) = &mut self.generator {
+                    generator.transfer_refused(now);
+                }
+            }
+        } else if !transfer && (closed || pending == Some(Some(Supply::Generator))) {
+            self.transfer_to(self.island_supply(), "generator").await;
+        }
+        if state == before {
+            return;
+        }
+        if state == GeneratorState::Failed {
+            self.queue_alarm("generator_fault", &format!("generator failed while {:?}", before));
+        }
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.put_json(GENERATOR_HOURS_KEY, &RunHours { hours }) {
+                warn!("Failed to persist generator run hours: {}", e);
+            }
+        }
+    }
+
+    /// Tell the battery gauge what flowed since the last reading, and take its state of
+    /// charge. Our loads run off the battery only while islanded; the panels charge it
+    /// whenever the sun is up.
+    fn update_battery(&mut self) {
+        let now = self.clock.instant();
+        let elapsed = self.battery_accounted.map_or(Duration::ZERO, |at| now.saturating_duration_since(at));
+        self.battery_accounted = Some(now);
+        let islanded = matches!(self.state, NodeState::Islanded | NodeState::BlackStart);
+        let load = if islanded { self.load_watts() } else { 0.0 };
+        let solar = self.sources.as_ref().map_or(0.0, |sources| sources.solar_watts(self.local_hour()));
+        let Some(battery) = &mut self.battery else { return };
+        battery.account(load, solar, elapsed);
+        match battery.status() {
+            Ok(status) => {
+                self.battery_soc = status.soc.clamp(0.0, 1.0);
+                self.battery_status = Some(status);
+            }
+            Err(e) => {
+                warn!("Battery state of charge unavailable: {}", e);
+                self.battery_status = None;
+            }
+        }
+        if let Some(sources) = &mut self.sources {
+            sources.record_discharge_limit(self.battery_status.and_then(|s| s.max_discharge_watts()));
+        }
+    }
+
+    /// Shed loads, lowest priority first, until we are within the granted budget. The
+    /// EV charger is turned down first, and back up as the budget allows. Critical
+    /// loads are never shed for the budget.
+    pub async fn enforce_power_budget(&mut self) {
+        self.fit_ev_charger();
+        let Some(budget) = self.power_budget else { return };
+        let mut load = self.load_watts();
+        let mut sheddable: Vec<(Priority, String, f32)> = self.relays.iter()
+            .filter(|r| r.relay_type == RelayType::Load && r.is_closed && !interlock::protected_from_budget(r))
+            .map(|r| (r.priority, r.id.clone(), self.relay_watts(r)))
+            // Shedding a circuit that exports would only add to the draw
+            .filter(|(_, _, watts)| *watts > 0.0)
+            .collect();
+        sheddable.sort_by_key(|(priority, _, _)| std::cmp::Reverse(*priority));
+        for (_, relay_id, watts) in sheddable {
+            if load <= budget {
+                break;
+            }
+            self.actuate_relay(&relay_id, false, "power_budget").await;
+            load -= watts;
+        }
+        if load > budget {
+            warn!("Critical loads draw {:.0} W, over the {:.0} W budget", load, budget);
+        }
+    }
+
+    /// Read the board temperature and, while it runs hot, open loads until the relays carry
+    /// no more than the derated current, closing them again once it has cooled.
+    pub async fn check_temperature(&mut self) {
+        let Some(thermal) = &mut self.thermal else { return };
+        let celsius = match thermal::read_celsius(&thermal.settings.sensor) {
+            Ok(celsius) => celsius,
+            Err(e) => {
+                warn!("Board temperature unavailable: {:#}", e);
+                return;
+            }
+        };
+        if let Some(derating) = thermal.record(celsius) {
+            let allowed_amps = thermal.derating().map(|d| d.allowed_amps);
+            match allowed_amps {
+                Some(amps) => warn!("Board at {:.0} °C; derating the relays to {:.0} A", celsius, amps),
+                None => info!("Board cooled to {:.0} °C; derating ended", celsius),
+            }
+            self.events.publish(NodeEvent::ThermalDerating { temperature_c: celsius, derating, allowed_amps });
+        }
+        let amps: HashMap<String, f32> = self.relays.iter()
+            .map(|r| (r.id.clone(), self.relay_watts(r) / self.voltage_ref))
+            .collect();
+        let Some(thermal) = &mut self.thermal else { return };
+        let (open, close) = thermal.tick(&self.relays, |r| amps[&r.id]);
+        for relay_id in open {
+            self.actuate_relay(&relay_id, false, "thermal_derating").await;
+        }
+        for relay_id in close {
+            let Some(priority) = self.relays.iter().find(|r| r.id == relay_id).map(|r| r.priority) else { continue };
+            if !self.demand_response.take_over(&relay_id, priority) {
+                self.actuate_relay(&relay_id, true, "thermal_derating_end").await;
+            }
+        }
+    }
+
+    /// Close the next load waiting to be put back, once the draw has settled from the one
+    /// before and the minute's pickup allowance has room for it.
+    pub async fn pace_restores(&mut self) {
+        let now = self.clock.unix();
+        let draw_amps = self.measured_load_amps();
+        let rated: HashMap<String, f32> = self.relays.iter().map(|r| (r.id.clone(), r.amperage)).collect();
+        let Some(pickup) = &mut self.cold_load_pickup else { return };
+        if let Some(relay_id) = pickup.tick(now, draw_amps, |id| rated.get(id).copied().unwrap_or_default()) {
+            self.close_relay(&relay_id, "cold_load_pickup").await;
+        }
+    }
+
+    /// Start the next motor load due, or hand one over from its soft-start relay.
+    pub async fn run_motor_starts(&mut self) {
+        let now = self.clock.unix();
+        let Some(motors) = &mut self.motors else { return };
+        for (relay_id, closed) in motors.tick(now) {
+            self.actuate_relay(&relay_id, closed, MOTOR_START).await;
+        }
+    }
+
+    /// Route the closing of a motor load through the starter, which closes it in turn once
+    /// it has rested, and keep soft-start relays for the starter's use alone. None for
+    /// relays the starter has no say over.
+    async fn start_motor(&mut self, relay_id: &str, closed: bool) -> Option<bool> {
+        let motors = self.motors.as_mut().filter(|_| closed)?;
+        if motors.is_soft_start_relay(relay_id) {
+            warn!("Leaving {} open: it only closes to start its motor", relay_id);
+            return Some(false);
+        }
+        let is_open = self.relays.iter().any(|r| r.id == relay_id && !r.is_closed);
+        if !(is_open && motors.is_motor(relay_id)) {
+            return None;
+        }
+        motors.queue(relay_id);
+        self.run_motor_starts().await;
+        Some(true)
+    }
+
+    /// Put a load back: at once, or in line to be paced in if cold-load pickup is configured.
+    async fn restore_load(&mut self, relay_id: &str, trigger: &str) -> bool {
+        let is_load = self.relays.iter().any(|r| r.id == relay_id && r.relay_type == RelayType::Load);
+        match &mut self.cold_load_pickup {
+            Some(pickup) if is_load => {
+                pickup.queue(relay_id);
+                true
+            }
+            _ => self.close_relay(relay_id, trigger).await,
+        }
+    }
+
+    /// What the closed loads draw: measured where they have a CT clamp, rated otherwise.
+    fn measured_load_amps(&self) -> f32 {
+        self.relays.iter()
+            .filter(|r| r.relay_type == RelayType::Load && r.is_closed)
+            .map(|r| self.circuit_watts.get(&r.id).map_or(r.amperage, |w| w.abs() / self.voltage_ref))
+            .sum()
+    }
+
+    /// Shed loads by the tier the battery's charge is in while islanded, and put them back
+    /// as it recovers or the grid returns. The last tier alarms that it is about to run out.
+    pub async fn check_reserve(&mut self) {
+        let islanded = matches!(self.state, NodeState::Islanded | NodeState::BlackStart);
+        let soc = self.battery_soc;
+        let Some(reserve) = &mut self.reserve else { return };
+        if let Some(tier) = reserve.record(soc, islanded) {
+            match tier {
+                Some(action) => warn!("Battery at {:.0}%; reserve tier {}", soc * 100.0, action.as_str()),
+                None => info!("Battery reserve tier lifted at {:.0}%", soc * 100.0),
+            }
+            self.events.publish(NodeEvent::BatteryReserve { soc, tier: tier.map(|a| a.as_str().to_string()) });
+            if tier == Some(ReserveAction::PrepareShutdown) {
+                self.queue_alarm("battery_exhausted", &format!("battery at {:.0}%, preparing to shut down", soc * 100.0));
+                // Counters must survive the brownout, or our next messages look replayed
+                self.persist_replay_counters();
+            }
+        }
+        let Some(reserve) = &mut self.reserve else { return };
+        let (open, close) = reserve.tick(&self.relays);
+        for relay_id in open {
+            self.actuate_relay(&relay_id, false, "battery_reserve").await;
+        }
+        for relay_id in close {
+            let Some(priority) = self.relays.iter().find(|r| r.id == relay_id).map(|r| r.priority) else { continue };
+            if !self.demand_response.take_over(&relay_id, priority) {
+                self.restore_load(&relay_id, "battery_reserve_end").await;
+            }
+        }
+    }
+
+    /// Open loads on a phase carrying more than its limit, leaving the other phases alone,
+    /// and close them again once it has room. Loads are only put back on the grid.
+    pub async fn enforce_phase_limits(&mut self) {
+        if self.phase_monitor.is_none() {
+            return;
+        }
+        let amps: HashMap<String, f32> = self.relays.iter()
+            .map(|r| (r.id.clone(), self.relay_watts(r) / self.voltage_ref))
+            .collect();
+        let on_grid = self.state == NodeState::Normal;
+        let Some(monitor) = &mut self.phase_monitor else { return };
+        let (open, close) = monitor.tick(&self.relays, |r| amps[&r.id], self.phase, on_grid);
+
+        for relay_id in open {
+            let phase = self.relays.iter().find(|r| r.id == relay_id).and_then(|r| r.phase.or(self.phase));
+            warn!("Phase {} over its limit; shedding {}", phase.map_or("?", |p| p.as_str()), relay_id);
+            self.actuate_relay(&relay_id, false, "phase_overload").await;
+        }
+        for relay_id in close {
+            let Some(priority) = self.relays.iter().find(|r| r.id == relay_id).map(|r| r.priority) else { continue };
+            if !self.demand_response.take_over(&relay_id, priority) {
+                self.actuate_relay(&relay_id, true, "phase_overload_end").await;
+            }
+        }
+    }
+
+    /// Report rejected traffic since the last report; counts are kept for next time if sending fails.
+    async fn send_security_report(&self) {
+        let Some(client) = &self.client else { return };
+        let counts = security::take_counts();
+        if counts.is_empty() {
+            return;
+        }
+        if let Err(e) = client.send_security_report(&self.id, SECURITY_REPORT_INTERVAL.as_secs() as u32, &counts).await {
+            error!("Failed to send security report: {}", e);
+            security::restore_counts(&counts);
+        }
+    }
+
+    /// Publish an alarm locally and report it to the orchestrator.
+    async fn raise_alarm(&self, code: &str, message: &str) {
+        self.send_alarm(code, message, false).await;
+    }
+
+    /// Raise an alarm that needs attention now.
+    async fn raise_critical_alarm(&self, code: &str, message: &str) {
+        self.send_alarm(code, message, true).await;
+    }
+
+    async fn send_alarm(&self, code: &str, message: &str, critical: bool) {
+        self.events.publish(NodeEvent::Alarm { code: code.to_string(), message: message.to_string() });
+        if let Some(client) = &self.client {
+            if let Err(e) = client.send_alarm(&self.id, code, message, critical).await {
+                error!("Failed to send alarm: {}", e);
+            }
+        }
+    }
+
+    /// Publish an alarm locally now and report it once the current task is done.
+    fn queue_alarm(&mut self, code: &str, message: &str) {
+        self.events.publish(NodeEvent::Alarm { code: code.to_string(), message: message.to_string() });
+        self.queued_alarms.push((code.to_string(), message.to_string()));
+    }
+
+    async fn send_queued_alarms(&mut self) {
+        let Some(client) = &self.client else {
+            self.queued_alarms.clear();
+            return;
+        };
+        for (code, message) in self.queued_alarms.drain(..) {
+            if let Err(e) = client.send_alarm(&self.id, &code, &message, false).await {
+                error!("Failed to send alarm: {}", e);
+            }
+        }
+    }
+
+    /// Alarm the first implausible reading on an ADC channel; quiet until it reads sensibly again.
+    async fn sensor_fault(&mut self, channel: u8, watts: f32) {
+        if self.faulty_channels.insert(channel) {
+            self.raise_alarm("sensor_fault", &format!("ADC channel {} reads {} W", channel, watts)).await;
+        }
+    }
+
+    /// Send voltage alert to orchestrator
+    async fn send_voltage_alert(&self, voltage: f32) {
+        if let Some(client) = &self.client {
+            if let Err(e) = client.send_voltage_alert(&self.id, voltage).await {
+                error!("Failed to send voltage alert: {}", e);
+            }
+        }
+    }
+
+    /// Our heartbeat as it stands.
+    fn heartbeat(&self, client: &OrchestratorClient) -> Heartbeat {
+        let audit = self.audit.as_ref().map(|a| a.head());
+        let following = self.failover.as_ref().map(|f| f.following());
+        let readings = HeartbeatReadings {
+            phases: self.phase_monitor.as_ref().map(|m| m.readings()).unwrap_or_default(),
+            power: self.power_quality.iter().map(|(channel, quality)| (*channel, *quality)).collect(),
+            voltage_thd: self.voltage_thd,
+            thermal: self.thermal.as_ref()
+                .and_then(|t| t.temperature_c().map(|celsius| (celsius, t.derating().map(|d| d.allowed_amps)))),
+            reserve_tier: self.reserve.as_ref().and_then(ReservePolicy::tier),
+            circuits: self.submeter.as_ref()
+                .map(|m| m.readings().iter().map(|(name, reading)| (name.clone(), *reading)).collect())
+                .unwrap_or_default(),
+        };
+        client.heartbeat(&self.id, self.battery_soc, self.sysinfo.collect(), audit, following, &readings)
+    }
+
+    /// Send heartbeat to orchestrator
+    async fn send_heartbeat(&self) {
+        if let Some(client) = &self.client {
+            if let Err(e) = client.send_heartbeat(self.heartbeat(client)).await {
+                error!("Failed to send heartbeat: {}", e);
+            } else {
+                info!("Heartbeat sent");
+            }
+        }
+    }
+
+    /// Poll for incoming command.
+    /// 
+    /// NOTE: This is a temporary polling approach. The current LoRa communication layer
+    /// (comms.rs) returns immediately from receive() because the SX126x driver is stubbed.
+    /// 
+    /// For true async (M3 milestone): The real SX126x driver should use a tokio::sync::Notify
+    /// or mpsc channel that gets signaled when the radio's DIO1 interrupt fires, indicating
+    /// a packet has been received. Then we can await that signal directly in tokio::select!
+    /// instead of polling on an interval.
+    async fn poll_for_command(&self) -> Option<ReceivedCommand> {
+        if let Some(client) = &self.client {
+            match client.receive().await {
+                Ok(Some(cmd)) => Some(cmd),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Audit and dispatch a received command.
+    pub async fn handle_command(&mut self, received: ReceivedCommand) {
+        let ReceivedCommand { command: mut cmd, signature, session_id, sender_id, counter, co_signatures, zone } = received;
+        let source = self.client.as_ref().map(|c| c.source()).unwrap_or("local");
+        self.audit(AuditRecord::Command {
+            source: source.to_string(),
+            payload: format!("{:?}", cmd),
+            signature,
+        });
+
+        let addressed = self.answers(&cmd);
+        let kind = cmd.kind();
+
+        if self.state == NodeState::Joining
+            && !matches!(cmd, IncomingCommand::JoinAccept(_) | IncomingCommand::JoinReject(_))
+        {
+            warn!("Ignoring command while joining the mesh");
+            if addressed {
+                let refusal = NodeError::WrongState("joining the mesh".to_string());
+                self.report_command_result(kind, sender_id, counter, Err(refusal)).await;
+            }
+            return;
+        }
+        // Unauthenticated senders are not told why
+        if self.keyring.is_some() && signature != SignatureStatus::Valid {
+            warn!("Dropping {:?} command from {}", signature, source);
+            security::record(SecurityEvent::AuthFailure);
+            return;
+        }
+        // Join messages predate our keys, and a counter reset is authorized by its challenge
+        let replay_exempt = matches!(
+            cmd,
+            IncomingCommand::JoinAccept(_) | IncomingCommand::JoinReject(_) | IncomingCommand::CounterReset(_)
+        );
+        if !replay_exempt && !self.check_freshness(kind, sender_id.as_deref(), counter).await {
+            if addressed {
+                let refusal = NodeError::Rejected(format!("stale or missing counter {}", counter));
+                self.report_command_result(kind, sender_id, counter, Err(refusal)).await;
+            }
+            return;
+        }
+        // The standby orchestrator is only obeyed once it has taken over
+        if let Some(failover) = &mut self.failover {
+            let takeover = matches!(cmd, IncomingCommand::OrchestratorTakeover(_));
+            if !takeover && !failover.accept(sender_id.as_deref(), self.clock.unix()) {
+                warn!("Ignoring {} command from {}, not the orchestrator we follow", kind, sender_id.as_deref().unwrap_or("an unnamed sender"));
+                if addressed {
+                    let refusal = NodeError::WrongState("not sent by the orchestrator we follow".to_string());
+                    self.report_command_result(kind, sender_id, counter, Err(refusal)).await;
+                }
+                return;
+            }
+        }
+        // A fresh authenticated command proves the orchestrator is reachable
+        if let Some(election) = &mut self.election {
+            election.orchestrator_heard(self.clock.unix());
+        }
+        // Zone-wide commands are broadcast; act on them as if addressed to us
+        if let Some(zone) = &zone {
+            if !self.zones.contains(zone) {
+                return;
+            }
+            if !cmd.retarget(&self.id) {
+                warn!("Ignoring {} command addressed to zone {}: it needs a single node", cmd.kind(), zone);
+                return;
+            }
+        }
+        let report = self.answers(&cmd);
+        let result = match self.authorize(&cmd, session_id.as_deref(), co_signatures.as_ref()).await {
+            Ok(()) => self.execute(cmd, signature, sender_id.as_deref()).await,
+            Err(e) => Err(e),
+        };
+        if report {
+            self.report_command_result(kind, sender_id, counter, result).await;
+        }
+    }
+
+    /// Commands addressed to us get an answer, once carried out or refused; broadcasts
+    /// and acknowledgements don't.
+    fn answers(&self, cmd: &IncomingCommand) -> bool {
+        cmd.target_node_id() == Some(self.id.as_str()) && !matches!(cmd, IncomingCommand::RegistrationAck(_))
+    }
+
+    /// Hold the command to the rate limit, and to the session and co-signatures it needs.
+    /// Only commands we answer count towards the limit: on a broadcast mesh we overhear
+    /// every other node's commands too.
+    async fn authorize(&mut self, cmd: &IncomingCommand, session_id: Option<&str>, co_signatures: Option<&CoSignatures>) -> Result<(), NodeError> {
+        let verdict = if self.answers(cmd) {
+            self.limiter.check(cmd.kind(), cmd.is_safety_critical(), self.clock.instant())
+        } else {
+            Verdict::Allow
+        };
+        match verdict {
+            Verdict::Allow => {}
+            Verdict::Tripped => {
+                let message = format!("{} commands exceeded rate limit; locking out non-safety commands", cmd.kind());
+                warn!("{}", message);
+                self.raise_alarm("command_flood", &message).await;
+                security::record(SecurityEvent::RateLimitTrip);
+                return Err(NodeError::RateLimited(cmd.kind()));
+            }
+            Verdict::LockedOut => {
+                warn!("Command lockout active, dropping {}", cmd.kind());
+                security::record(SecurityEvent::LockoutDrop);
+                return Err(NodeError::RateLimited(cmd.kind()));
+            }
+        }
+        if let Some(sessions) = &self.sessions {
+            if cmd.requires_session() {
+                if let Err(e) = sessions.authorize(session_id, cmd.kind(), self.clock.unix()) {
+                    warn!("Dropping {} command: {}", cmd.kind(), e);
+                    security::record(SecurityEvent::SessionRejection);
+                    return Err(NodeError::Unauthorized(e.to_string()));
+                }
+            }
+        }
+        if let Some(policy) = &self.multisig {
+            if self.approval_kinds(cmd).into_iter().any(|kind| policy.requires(kind)) {
+                if let Err(e) = policy.verify(co_signatures) {
+                    warn!("Dropping {} command: {}", cmd.kind(), e);
+                    security::record(SecurityEvent::AuthFailure);
+                    return Err(NodeError::Unauthorized(e.to_string()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Kinds of command whose co-signatures `cmd` needs: its own, and `disconnect_grid` when
+    /// it would switch our Grid relays, so islanding or a snapshot restore can't do
+    /// unapproved what a DisconnectGrid may not.
+    fn approval_kinds(&self, cmd: &IncomingCommand) -> Vec<&'static str> {
+        let switches_grid = self.answers(cmd) && match cmd {
+            IncomingCommand::EnterIsland(_) => self.island_disconnects_grid(),
+            IncomingCommand::SnapshotRestore(restore) => restore.snapshot.as_ref()
+                .and_then(|data| NodeSnapshot::from_bytes(data.format_version, &data.payload).ok())
+                .is_some_and(|snapshot| snapshot.relays.iter().any(|r| {
+                    r.relay_type == RelayType::Grid
+                        && self.relays.iter().find(|cur| cur.id == r.id).map_or(r.is_closed, |cur| cur.is_closed != r.is_closed)
+                })),
+            _ => false,
+        };
+        let mut kinds = vec![cmd.kind()];
+        if switches_grid {
+            kinds.push("disconnect_grid");
+        }
+        kinds
+    }
+
+    async fn execute(&mut self, cmd: IncomingCommand, signature: SignatureStatus, sender_id: Option<&str>) -> Result<(), NodeError> {
+        match cmd {
+            IncomingCommand::LoadShed(ls) => self.handle_load_shed_command(ls).await,
+            IncomingCommand::EnterIsland(ei) => self.handle_enter_island_command(ei).await,
+            IncomingCommand::EnterBlackStart(ebs) => self.handle_enter_blackstart_command(ebs).await,
+            IncomingCommand::ActivateRelayByIndex(ar) => self.handle_activate_relay_by_index(ar).await,
+            IncomingCommand::ActivateRelayByPriority(arp) => self.handle_activate_relay_by_priority(arp).await,
+            IncomingCommand::AuditLogRequest(req) => self.handle_audit_log_request(req).await,
+            IncomingCommand::SnapshotRequest(req) => self.handle_snapshot_request(req).await,
+            IncomingCommand::SnapshotRestore(restore) => self.handle_snapshot_restore(restore).await,
+            IncomingCommand::KeyRotation(rotation) => self.handle_key_rotation(rotation).await,
+            IncomingCommand::JoinAccept(accept) => self.handle_join_accept(accept).await,
+            IncomingCommand::JoinReject(reject) => self.handle_join_reject(reject),
+            IncomingCommand::FirmwareUpdate(update) => self.handle_firmware_update(update).await,
+            IncomingCommand::SessionGrant(grant) => self.handle_session_grant(grant),
+            IncomingCommand::SessionRevoke(revoke) => self.handle_session_revoke(revoke),
+            IncomingCommand::CounterReset(reset) => self.handle_counter_reset(reset),
+            IncomingCommand::CertRotation(rotation) => self.handle_cert_rotation(rotation).await,
+            IncomingCommand::FactoryReset(reset) => self.handle_factory_reset(reset),
+            IncomingCommand::FaultReset(reset) => self.handle_fault_reset(reset),
+            IncomingCommand::DisconnectGrid(disconnect) => self.handle_disconnect_grid(disconnect).await,
+            IncomingCommand::EnergyLedgerRequest(req) => self.handle_energy_ledger_request(req).await,
+            IncomingCommand::Rebalance(directive) => self.handle_rebalance(directive).await,
+            IncomingCommand::MidCommand(cmd) => self.handle_mid_command(cmd).await,
+            IncomingCommand::LivenessProbe(probe) => self.handle_liveness_probe(probe).await,
+            IncomingCommand::DemandResponse(dr) => self.handle_demand_response(dr).await,
+            IncomingCommand::RegistrationAck(ack) => {
+                self.handle_registration_ack(ack);
+                Ok(())
+            }
+            IncomingCommand::WhoIsThere => {
+                self.handle_who_is_there();
+                Ok(())
+            }
+            IncomingCommand::OrchestratorTakeover(takeover) => {
+                self.handle_orchestrator_takeover(takeover, signature, sender_id).await;
+                Ok(())
+            }
+        }
+    }
+
+    /// Tell the issuer how a command addressed to us went, and where our relays stand after it.
+    async fn report_command_result(&self, command: &str, sender_id: Option<String>, counter: u64, result: Result<(), NodeError>) {
+        let Some(client) = &self.client else { return };
+        let result = CommandResult {
+            node_id: self.id.clone(),
+            sender_id: sender_id.unwrap_or_default(),
+            counter,
+            command: command.to_string(),
+            ok: result.is_ok(),
+            error_code: result.as_ref().err().map(|e| e.code().to_string()).unwrap_or_default(),
+            error: result.err().map(|e| e.to_string()).unwrap_or_default(),
+            relays: self.relays.iter().map(|r| RelayState { id: r.id.clone(), is_closed: r.is_closed }).collect(),
+            timestamp: self.clock.unix(),
+        };
+        if let Err(e) = client.send_command_result(result).await {
+            error!("Failed to send command result: {}", e);
+        }
+    }
+
+    async fn handle_load_shed_command(&mut self, cmd: crate::comms::LoadShed) -> Result<(), NodeError> {
+        if cmd.target_node_id == self.id {
+            if cmd.shed_load {
+                warn!("Received LoadShed command!");
+                self.shed_load(Priority::Medium).await;
+            } else {
+                info!("Received LoadRestore command (ignored for now)");
+                return Err(NodeError::Unsupported("load restore"));
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_enter_island_command(&mut self, cmd: EnterIsland) -> Result<(), NodeError> {
+        if cmd.target_node_id == self.id {
+            warn!("Received EnterIsland command from orchestrator!");
+            self.enter_island_mode().await;
+        }
+        Ok(())
+    }
+
+    async fn handle_enter_blackstart_command(&mut self, cmd: EnterBlackStart) -> Result<(), NodeError> {
+        if cmd.target_node_id == self.id {
+            warn!("Received EnterBlackStart command from orchestrator!");
+            self.enter_blackstart_mode();
+            self.confirm_blackstart_step(&cmd.step_id, &Ok(())).await;
+        }
+        Ok(())
+    }
+
+    async fn handle_activate_relay_by_index(&mut self, cmd: ActivateRelayByIndex) -> Result<(), NodeError> {
+        if cmd.target_node_id != self.id {
+            return Ok(());
+        }
+        let index = cmd.relay_index as usize;
+        let result = if index < self.relays.len() {
+            info!("Activating relay by index {}: {}", index, self.relays[index].name);
+            let relay_id = self.relays[index].id.clone();
+            // Outside a black start, this is an operator clearing a fault by hand
+            if cmd.step_id.is_empty() && self.arc_detector.as_mut().is_some_and(|d| d.clear(&relay_id)) {
+                warn!("Arc fault lockout on {} cleared by operator", relay_id);
+            }
+            if cmd.step_id.is_empty() && self.relay_health.clear(&relay_id) {
+                warn!("Failed relay {} put back in use by operator", relay_id);
+            }
+            if self.close_relay(&relay_id, "activate_by_index").await {
+                Ok(())
+            } else {
+                Err(NodeError::NotSwitched(relay_id))
+            }
+        } else {
+            warn!("ActivateRelayByIndex: index {} out of bounds ({} relays)", index, self.relays.len());
+            Err(NodeError::NoRelayAtIndex(cmd.relay_index))
+        };
+        self.confirm_blackstart_step(&cmd.step_id, &result).await;
+        result
+    }
+
+    async fn handle_activate_relay_by_priority(&mut self, cmd: ActivateRelayByPriority) -> Result<(), NodeError> {
+        if cmd.target_node_id != self.id {
+            return Ok(());
+        }
+        let priority = priority_from_proto(cmd.priority);
+        info!("Activating all relays with priority {:?}", priority);
+        self.activate_relays_by_priority(priority).await;
+        // Loads waiting their turn to be paced in aren't left open
+        let queued = |id: &str| self.cold_load_pickup.as_ref().is_some_and(|p| p.is_queued(id));
+        let open: Vec<String> = self.relays.iter()
+            .filter(|r| r.priority == priority && !r.is_closed && !queued(&r.id))
+            .map(|r| r.id.clone())
+            .collect();
+        let result = if open.is_empty() { Ok(()) } else { Err(NodeError::LeftOpen(open)) };
+        self.confirm_blackstart_step(&cmd.step_id, &result).await;
+        result
+    }
+
+    /// Tell the orchestrator how a step of a fleet-wide blackstart went; commands sent
+    /// outside one carry no step ID and get no answer.
+    async fn confirm_blackstart_step(&self, step_id: &str, result: &Result<(), NodeError>) {
+        let Some(client) = &self.client else { return };
+        if step_id.is_empty() {
+            return;
+        }
+        let ack = BlackStartAck {
+            node_id: self.id.clone(),
+            step_id: step_id.to_string(),
+            ok: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.to_string()).unwrap_or_default(),
+        };
+        if let Err(e) = client.send_blackstart_ack(ack).await {
+            error!("Failed to send blackstart ack: {}", e);
+        }
+    }
+
+    /// Upload a window of the audit log to the orchestrator.
+    async fn handle_audit_log_request(&mut self, req: AuditLogRequest) -> Result<(), NodeError> {
+        if req.target_node_id != self.id {
+            return Ok(());
+        }
+        let (Some(audit), Some(client)) = (&self.audit, &self.client) else {
+            warn!("AuditLogRequest received but audit log is not enabled");
+            return Err(NodeError::NotEnabled("audit log"));
+        };
+        let max = match req.max_entries as usize {
+            0 => MAX_AUDIT_UPLOAD,
+            n => n.min(MAX_AUDIT_UPLOAD),
+        };
+        // Fetch one extra entry to learn whether more remain
+        let mut entries = match audit.entries_since(req.since_seq, max + 1) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to read audit log: {}", e);
+                return Err(NodeError::Failed(format!("reading audit log: {}", e)));
+            }
+        };
+        let more = entries.len() > max;
+        entries.truncate(max);
+        info!("Uploading {} audit entries from seq {}", entries.len(), req.since_seq);
+        client.send_audit_log(&self.id, &entries, more).await.inspect_err(|e| error!("Failed to upload audit log: {}", e))?;
+        Ok(())
+    }
+
+    /// Send our FeatureReport when it is due: until the orchestrator acknowledges it,
+    /// then once in a while so a restarted orchestrator relearns us.
+    pub async fn run_registration(&mut self) {
+        if self.state == NodeState::Joining || self.clock.unix() < self.registration_due_at {
+            return;
+        }
+        if self.registered {
+            info!("Refreshing registration with the orchestrator");
+        }
+        self.send_feature_report().await;
+    }
+
+    fn handle_registration_ack(&mut self, ack: RegistrationAck) {
+        if ack.target_node_id != self.id {
+            return;
+        }
+        if !self.registered {
+            info!("Registration acknowledged by the orchestrator");
+        }
+        self.registered = true;
+        self.registration_due_at = self.clock.unix() + REREGISTRATION_SECS;
+    }
+
+    /// Re-register after a delay derived from our ID, so answers from the whole street
+    /// are spread out rather than colliding on the radio.
+    fn handle_who_is_there(&mut self) {
+        let spread = sha2::Sha256::digest(self.id.as_bytes())[0] as i64 % WHO_IS_THERE_SPREAD_SECS;
+        info!("Orchestrator asked who is there; re-registering in {}s", spread);
+        self.registered = false;
+        self.registration_due_at = self.clock.unix() + spread;
+    }
+
+    /// The orchestrator lost touch with a node we may hear: ping it over the mesh and
+    /// report back once it has had time to answer.
+    async fn handle_liveness_probe(&mut self, probe: LivenessProbe) -> Result<(), NodeError> {
+        if probe.target_node_id != self.id {
+            return Ok(());
+        }
+        let Some(client) = &self.client else { return Ok(()) };
+        let sent = client.send_liveness_ping(&self.id, &probe).await.inspect_err(|e| error!("Failed to relay liveness probe: {}", e));
+        self.liveness_probes.push((probe, self.clock.unix()));
+        Ok(sent?)
+    }
+
+    /// Answer pings for us with a heartbeat, and report on probes whose wait is over.
+    pub async fn run_liveness(&mut self) {
+        let Some(client) = &self.client else { return };
+        if client.take_liveness_pings().iter().any(|ping| ping.silent_node_id == self.id) {
+            info!("Orchestrator is looking for us; sending a heartbeat");
+            self.send_heartbeat().await;
+        }
+        let now = self.clock.unix();
+        let (due, waiting) = std::mem::take(&mut self.liveness_probes).into_iter()
+            .partition(|(_, at)| now - at >= LIVENESS_PROBE_WAIT_SECS);
+        self.liveness_probes = waiting;
+        let Some(client) = &self.client else { return };
+        for (probe, _) in due {
+            if let Err(e) = client.send_liveness_report(&self.id, &probe).await {
+                error!("Failed to send liveness report: {}", e);
+            }
+        }
+    }
+
+    /// Follow the secondary orchestrator on its signed announcement, and say so in a heartbeat straight away.
+    async fn handle_orchestrator_takeover(&mut self, takeover: OrchestratorTakeover, signature: SignatureStatus, sender_id: Option<&str>) {
+        let Some(failover) = &mut self.failover else {
+            warn!("Ignoring takeover by {}: no standby orchestrator configured", takeover.orchestrator_id);
+            return;
+        };
+        if signature != SignatureStatus::Valid || sender_id != Some(takeover.orchestrator_id.as_str()) {
+            warn!("Ignoring unsigned or mismatched takeover by {}", takeover.orchestrator_id);
+            security::record(SecurityEvent::AuthFailure);
+            return;
+        }
+        if !failover.take_over(&takeover.orchestrator_id) {
+            warn!("Ignoring takeover by {}: not our secondary orchestrator", takeover.orchestrator_id);
+            return;
+        }
+        self.send_heartbeat().await;
+    }
+
+    /// As a MID, broadcast our isolation state; behind one, make sure an island isn't
+    /// left tied to a transformer the MID no longer isolates.
+    pub async fn run_mid(&mut self) {
+        match self.role {
+            NodeRole::Mid => self.send_mid_status().await,
+            NodeRole::Participant => {
+                let tied = self.relays.iter().any(|r| r.relay_type == RelayType::Grid && r.is_closed);
+                if self.state == NodeState::Islanded && self.behind_mid() && tied && !self.mid_isolated() {
+                    warn!("MID is not isolating the transformer: disconnecting from utility grid");
+                    self.disconnect_grid().await;
+                }
+            }
+            // Repeaters never run an edge node
+            NodeRole::Repeater => {}
+        }
+    }
+
+    async fn send_mid_status(&self) {
+        let (Some(client), Some(key)) = (&self.client, &self.mid_key) else { return };
+        let isolated = !self.relays.iter().any(|r| r.relay_type == RelayType::Grid && r.is_closed);
+        if let Err(e) = client.send_mid_status(&self.id, isolated, self.reconnect_permitted, key).await {
+            error!("Failed to send MID status: {}", e);
+        }
+    }
+
+    /// As a MID, open or close the transformer breaker (our Grid relays).
+    async fn handle_mid_command(&mut self, cmd: MidCommand) -> Result<(), NodeError> {
+        if cmd.target_node_id != self.id {
+            return Ok(());
+        }
+        if self.role != NodeRole::Mid {
+            warn!("Ignoring MidCommand: this node is not a MID");
+            return Err(NodeError::NotEnabled("transformer isolation"));
+        }
+        if cmd.isolate {
+            warn!("Isolating the transformer from the utility");
+            self.reconnect_permitted = false;
+            self.transfer_to(self.island_supply(), "mid_isolate").await;
+        } else {
+            info!("Reconnecting the transformer (edge nodes may reconnect: {})", cmd.permit_reconnect);
+            self.transfer_to(Some(Supply::Grid), "mid_reconnect").await;
+            self.reconnect_permitted = cmd.permit_reconnect;
+        }
+        self.send_mid_status().await;
+        Ok(())
+    }
+
+    /// Whether our Grid relays answer to a MID: a GovernmentSanctioned mesh with one configured.
+    fn behind_mid(&self) -> bool {
+        self.role == NodeRole::Participant && self.mesh_type == MeshType::GovernmentSanctioned && self.mid_id.is_some()
+    }
+
+    fn mid_status(&self) -> Option<MidStatus> {
+        let (Some(client), Some(mid_id)) = (&self.client, &self.mid_id) else { return None };
+        client.mid_status(mid_id)
+    }
+
+    /// The MID has recently reported the transformer isolated.
+    fn mid_isolated(&self) -> bool {
+        self.mid_status().is_some_and(|s| s.isolated)
+    }
+
+    /// Behind a MID, Grid relays close only while it is heard permitting reconnection.
+    fn grid_close_permitted(&self) -> bool {
+        !self.behind_mid() || self.mid_status().is_some_and(|s| s.reconnect_permitted)
+    }
+
+    /// Apply (or roll back) our side of a load shift between nodes and confirm the outcome,
+    /// so the orchestrator can undo the other side if ours failed.
+    async fn handle_rebalance(&mut self, directive: RebalanceDirective) -> Result<(), NodeError> {
+        if directive.target_node_id != self.id {
+            return Ok(());
+        }
+        let result = if directive.rollback {
+            self.rollback_rebalance(&directive.directive_id).await
+        } else {
+            self.apply_rebalance(&directive).await
+        };
+        if let Err(e) = &result {
+            warn!("Rebalance {} failed: {}", directive.directive_id, e);
+        }
+        if let Some(client) = &self.client {
+            let ack = RebalanceAck {
+                node_id: self.id.clone(),
+                directive_id: directive.directive_id,
+                ok: result.is_ok(),
+                error: result.as_ref().err().map(|e| e.to_string()).unwrap_or_default(),
+                rollback: directive.rollback,
+            };
+            if let Err(e) = client.send_rebalance_ack(ack).await {
+                error!("Failed to send rebalance ack: {}", e);
+            }
+        }
+        result
+    }
+
+    /// Switch the named load relays, all or nothing.
+    pub async fn apply_rebalance(&mut self, directive: &RebalanceDirective) -> Result<(), NodeError> {
+        let changes: Vec<(&String, bool)> = directive.open_relays.iter().map(|id| (id, false))
+            .chain(directive.close_relays.iter().map(|id| (id, true)))
+            .collect();
+        // Check everything up front so a bad directive changes nothing
+        let mut load = self.load_watts();
+        for (relay_id, closed) in &changes {
+            let relay = self.relays.iter().find(|r| &r.id == *relay_id)
+                .ok_or_else(|| NodeError::UnknownRelay(relay_id.to_string()))?;
+            if relay.relay_type != RelayType::Load {
+                return Err(NodeError::NotALoadRelay(relay_id.to_string()));
+            }
+            if relay.is_closed != *closed {
+                let watts = relay.amperage * self.voltage_ref;
+                load += if *closed { watts } else { -watts };
+            }
+        }
+        if let Some(budget) = self.power_budget.filter(|budget| load > *budget) {
+            return Err(NodeError::OverBudget { load, budget });
+        }
+
+        let mut prior = Vec::new();
+        for (relay_id, closed) in changes {
+            let was_closed = self.relays.iter().any(|r| &r.id == relay_id && r.is_closed);
+            prior.push((relay_id.clone(), was_closed));
+            if !self.actuate_relay(relay_id, closed, "rebalance").await {
+                for (relay_id, was_closed) in prior.iter().rev() {
+                    self.actuate_relay(relay_id, *was_closed, "rebalance_rollback").await;
+                }
+                return Err(NodeError::NotSwitched(relay_id.clone()));
+            }
+        }
+        if self.rebalances.len() >= MAX_REBALANCES {
+            self.rebalances.pop_front();
+        }
+        self.rebalances.push_back((directive.directive_id.clone(), prior));
+        Ok(())
+    }
+
+    /// Put back the relays a directive changed. A directive we never applied has nothing to undo.
+    pub async fn rollback_rebalance(&mut self, directive_id: &str) -> Result<(), NodeError> {
+        let Some(index) = self.rebalances.iter().position(|(id, _)| id == directive_id) else {
+            return Ok(());
+        };
+        let (_, prior) = self.rebalances.remove(index).unwrap_or_default();
+        let mut failed = Vec::new();
+        for (relay_id, was_closed) in prior.iter().rev() {
+            if !self.actuate_relay(relay_id, *was_closed, "rebalance_rollback").await {
+                failed.push(relay_id.clone());
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(NodeError::NotSwitchedBack(failed))
+        }
+    }
+
+    /// Accept (or decline, if the household opted out) a utility demand-response event,
+    /// or withdraw one, and tell the orchestrator how much load it takes off.
+    async fn handle_demand_response(&mut self, dr: DemandResponse) -> Result<(), NodeError> {
+        if dr.target_node_id != self.id {
+            return Ok(());
+        }
+        let shed_priority = priority_from_proto(dr.shed_priority.max(1));
+        let opted_out = self.demand_response.opt_out && !dr.cancel;
+        let mut shed_watts = 0.0;
+        if dr.cancel {
+            info!("Demand-response event {} cancelled", dr.event_id);
+            let restore = self.demand_response.cancel(&dr.event_id);
+            self.restore_after_demand_response(restore).await;
+        } else if opted_out {
+            info!("Opting out of demand-response event {}", dr.event_id);
+        } else {
+            info!("Demand-response event {}: shedding {:?} and below from {} to {}", dr.event_id, shed_priority, dr.start, dr.end);
+            let max_watts = (dr.max_watts > 0.0).then_some(dr.max_watts);
+            let strategy = ShedStrategy::parse(&dr.strategy);
+            if strategy.is_none() && !dr.strategy.is_empty() {
+                warn!("Unknown shedding strategy {:?} for event {}, using {:?}", dr.strategy, dr.event_id, self.demand_response.strategy);
+            }
+            let candidates: Vec<Candidate> = demand_response::sheddable(&self.relays, shed_priority)
+                .map(|r| Candidate { relay_id: r.id.clone(), watts: r.amperage * self.voltage_ref })
+                .collect();
+            let cut = max_watts.map(|cap| self.load_watts() - cap);
+            let selected = strategy.unwrap_or(self.demand_response.strategy).select(&candidates, cut, dr.start.max(self.clock.unix()));
+            shed_watts = candidates.iter().filter(|c| selected.contains(&c.relay_id)).map(|c| c.watts).sum();
+            self.demand_response.accept(&dr.event_id, shed_priority, dr.start, dr.end, max_watts, strategy);
+            self.run_demand_response().await;
+        }
+        if let Some(client) = &self.client {
+            let ack = DemandResponseAck { node_id: self.id.clone(), event_id: dr.event_id, opted_out, shed_watts };
+            if let Err(e) = client.send_demand_response_ack(ack).await {
+                error!("Failed to send demand-response ack: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Open loads for events that started and close them again after those that ended,
+    /// cycling those configured to be cycled in between.
+    pub async fn run_demand_response(&mut self) {
+        let now = self.clock.unix();
+        let watts: HashMap<String, f32> = self.relays.iter().map(|r| (r.id.clone(), self.relay_watts(r))).collect();
+        let (open, mut close) = self.demand_response.due(&self.relays, |r| watts[&r.id], now);
+        for relay_id in open {
+            // The car charges slowly through the event rather than not at all
+            if let Some(charger) = self.ev_charger.as_mut().filter(|c| c.relay_id == relay_id) {
+                charger.hold_down(true);
+                continue;
+            }
+            if self.actuate_relay(&relay_id, false, "demand_response").await && self.duty_cycler.cycles(&relay_id) {
+                self.duty_cycler.start(&relay_id, now);
+            }
+        }
+        if let Some(charger) = &mut self.ev_charger {
+            if let Some(index) = close.iter().position(|id| *id == charger.relay_id) {
+                close.remove(index);
+                charger.hold_down(false);
+            }
+        }
+        self.restore_after_demand_response(close).await;
+        for (relay_id, on) in self.duty_cycler.due(now) {
+            // Islanded, the power budget decides what runs
+            if !on || self.state == NodeState::Normal {
+                self.actuate_relay(&relay_id, on, "duty_cycle").await;
+            }
+        }
+        self.fit_ev_charger();
+    }
+
+    /// Defer loads while the tariff prices them above what their priority is worth, and
+    /// put them back once it is cheaper. Only on the grid: islanded, the power budget decides.
+    pub async fn run_tariff(&mut self) {
+        let (hour, now) = (self.local_hour() as u32, self.clock.unix());
+        let on_grid = self.state == NodeState::Normal;
+        let Some(shedder) = &mut self.cost_shedder else { return };
+        let before = shedder.savings().clone();
+        let (open, close) = shedder.tick(&self.relays, self.voltage_ref, hour, now, on_grid);
+        let savings = shedder.savings().clone();
+
+        for relay_id in open {
+            self.actuate_relay(&relay_id, false, "tariff").await;
+        }
+        if !close.is_empty() {
+            info!("Tariff peak over: {:.1} kWh deferred, {:.2} saved so far", savings.kwh_deferred, savings.saved);
+            self.events.publish(NodeEvent::TariffSavings { kwh_deferred: savings.kwh_deferred, saved: savings.saved });
+        }
+        for relay_id in close {
+            let Some(priority) = self.relays.iter().find(|r| r.id == relay_id).map(|r| r.priority) else { continue };
+            // A demand-response event still running keeps it off until the event ends
+            if on_grid && !self.demand_response.take_over(&relay_id, priority) {
+                self.actuate_relay(&relay_id, true, "tariff_end").await;
+            }
+        }
+        if savings != before {
+            if let Some(storage) = &self.storage {
+                if let Err(e) = storage.put_json(TARIFF_SAVINGS_KEY, &savings) {
+                    warn!("Failed to persist tariff savings: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Open loads before the next hour's forecast draw overruns the island budget or a
+    /// demand-response cap, rather than after, and close them again once it fits.
+    pub async fn run_forecast(&mut self) {
+        let hour = self.local_hour() as u32;
+        let Some(forecaster) = &self.forecaster else { return };
+        let watts: HashMap<String, f32> = self.relays.iter()
+            .filter(|r| r.relay_type == RelayType::Load)
+            .map(|r| (r.id.clone(), forecaster.expected_watts(&r.id, &self.load_profile, hour).unwrap_or_else(|| self.relay_watts(r))))
+            .collect();
+        let target = match (self.power_budget, self.demand_response.max_watts(self.clock.unix())) {
+            (Some(budget), Some(cap)) => Some(budget.min(cap)),
+            (budget, cap) => budget.or(cap),
+        };
+        let on_grid = self.state == NodeState::Normal;
+        let Some(forecaster) = &mut self.forecaster else { return };
+        let (open, close) = forecaster.tick(&self.relays, |r| watts[&r.id], target, on_grid);
+
+        if !open.is_empty() {
+            let forecast: f32 = self.relays.iter().filter(|r| r.relay_type == RelayType::Load && r.is_closed).map(|r| watts[&r.id]).sum();
+            info!("Next hour forecast at {:.0} W against {:.0} W; shedding ahead", forecast, target.unwrap_or_default());
+        }
+        for relay_id in open {
+            self.actuate_relay(&relay_id, false, "forecast").await;
+        }
+        for relay_id in close {
+            let Some(priority) = self.relays.iter().find(|r| r.id == relay_id).map(|r| r.priority) else { continue };
+            if !self.demand_response.take_over(&relay_id, priority) {
+                self.actuate_relay(&relay_id, true, "forecast_end").await;
+            }
+        }
+    }
+
+    /// Loads are only put back on the grid; while islanded the power budget decides.
+    async fn restore_after_demand_response(&mut self, relays: Vec<String>) {
+        for relay_id in &relays {
+            self.duty_cycler.stop(relay_id);
+        }
+        if self.state != NodeState::Normal {
+            return;
+        }
+        for relay_id in relays {
+            self.restore_load(&relay_id, "demand_response_end").await;
+        }
+    }
+
+    /// Upload the energy ledger entries we hold, ours and our neighbours', for settlement.
+    async fn handle_energy_ledger_request(&mut self, req: EnergyLedgerRequest) -> Result<(), NodeError> {
+        if req.target_node_id != self.id {
+            return Ok(());
+        }
+        let (Some(ledger), Some(client)) = (&self.energy, &self.client) else {
+            warn!("EnergyLedgerRequest received but the energy ledger is not enabled");
+            return Err(NodeError::NotEnabled("energy ledger"));
+        };
+        let mut entries = ledger.since(req.since);
+        let more = entries.len() > MAX_ENERGY_UPLOAD;
+        entries.truncate(MAX_ENERGY_UPLOAD);
+        info!("Uploading {} energy entries ending after {}", entries.len(), req.since);
+        client.send_energy_ledger(&self.id, entries, more).await.inspect_err(|e| error!("Failed to upload energy ledger: {}", e))?;
+        Ok(())
+    }
+
+    /// Reject commands whose counter isn't newer than the sender's last; on repeated
+    /// staleness ask the orchestrator to re-authorize. True if the command may proceed.
+    async fn check_freshness(&mut self, kind: &str, sender_id: Option<&str>, counter: u64) -> bool {
+        let Some(replay) = &mut self.replay else { return true };
+        let Some(sender_id) = sender_id else {
+            warn!("Dropping {} command without sender counter", kind);
+            security::record(SecurityEvent::ReplayAttempt);
+            return false;
+        };
+        let secure_element = &mut self.secure_element;
+        let freshness = replay.check(sender_id, counter, || {
+            let mut challenge = [0u8; 16];
+            if let Err(e) = keys::random_bytes(secure_element.as_deref_mut(), &mut challenge) {
+                error!("Cannot generate desync challenge: {}", e);
+            }
+            challenge
+        });
+        match freshness {
+            Freshness::Fresh => {
+                self.persist_replay_counters();
+                true
+            }
+            Freshness::Replayed => {
+                warn!("Dropping replayed {} command from {} (counter {})", kind, sender_id, counter);
+                security::record(SecurityEvent::ReplayAttempt);
+                false
+            }
+            Freshness::Desynced { last_counter, challenge } => {
+                let message = format!("{} keeps sending stale counters (last accepted {})", sender_id, last_counter);
+                warn!("{}; requesting re-authorization", message);
+                security::record(SecurityEvent::ReplayAttempt);
+                self.raise_alarm("replay_desync", &message).await;
+                if let Some(client) = &self.client {
+                    let desync = ReplayDesync {
+                        node_id: self.id.clone(),
+                        sender_id: sender_id.to_string(),
+                        last_counter,
+                        challenge: challenge.to_vec(),
+                        timestamp: self.clock.unix(),
+                    };
+                    if let Err(e) = client.send_replay_desync(desync).await {
+                        error!("Failed to send replay desync: {}", e);
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    /// Forget identity, keys and learned state; the audit log and measurements are kept.
+    fn handle_factory_reset(&mut self, cmd: FactoryReset) -> Result<(), NodeError> {
+        if cmd.target_node_id != self.id {
+            return Ok(());
+        }
+        warn!("Factory reset: erasing identity and keys");
+        self.keyring = None;
+        self.load_profile = LoadProfile::default();
+        if let Some(replay) = &mut self.replay {
+            *replay = ReplayGuard::default();
+        }
+        if let Some(sessions) = &mut self.sessions {
+            *sessions = SessionTable::default();
+        }
+        if let Some(energy) = &mut self.energy {
+            *energy = EnergyLedger::default();
+        }
+        self.set_state(NodeState::Joining);
+
+        if let Some(storage) = &self.storage {
+            let keys = [
+                STATE_KEY, IDENTITY_KEY, KEYRING_KEY, SEALED_KEYRING_KEY,
+                TLS_IDENTITY_KEY, TLS_PINS_KEY, REPLAY_KEY, PROFILE_KEY, ENERGY_KEY,
+            ];
+            let result = keys.iter().try_for_each(|key| storage.remove(key)).and_then(|_| storage.flush());
+            if let Err(e) = result {
+                error!("Factory reset incomplete: {}", e);
+                return Err(NodeError::Failed(format!("factory reset incomplete: {}", e)));
+            }
+        }
+        info!("Factory reset complete; restart to re-commission");
+        Ok(())
+    }
+
+    /// Clear the emergency-stop latch once the stop has been released. The relays stay
+    /// open; loads come back as they are asked for.
+    fn handle_fault_reset(&mut self, cmd: FaultReset) -> Result<(), NodeError> {
+        if cmd.target_node_id != self.id {
+            return Ok(());
+        }
+        if self.state != NodeState::Faulted {
+            info!("Fault reset ignored: not faulted");
+            return Ok(());
+        }
+        if self.estop.as_ref().is_some_and(|estop| !estop.reset()) {
+            warn!("Fault reset refused: the emergency stop is still asserted");
+            self.queue_alarm("emergency_stop", "fault reset refused while the emergency stop is asserted");
+            return Err(NodeError::WrongState("the emergency stop is still asserted".to_string()));
+        }
+        warn!("Fault reset: leaving Faulted with every relay open");
+        self.enter_state(NodeState::Normal);
+        Ok(())
+    }
+
+    async fn handle_disconnect_grid(&mut self, cmd: DisconnectGrid) -> Result<(), NodeError> {
+        if cmd.target_node_id == self.id {
+            warn!("Received DisconnectGrid command (MeshType: {:?})", self.mesh_type);
+            self.disconnect_grid().await;
+        }
+        Ok(())
+    }
+
+    fn handle_counter_reset(&mut self, cmd: CounterReset) -> Result<(), NodeError> {
+        if cmd.target_node_id != self.id {
+            return Ok(());
+        }
+        let Some(replay) = &mut self.replay else { return Err(NodeError::NotEnabled("replay protection")) };
+        if !replay.reset(&cmd.sender_id, cmd.counter, &cmd.challenge) {
+            warn!("Ignoring CounterReset for {} that answers no outstanding challenge", cmd.sender_id);
+            return Err(NodeError::Rejected("no outstanding challenge".to_string()));
+        }
+        warn!("Counter for {} re-seated at {}", cmd.sender_id, cmd.counter);
+        self.persist_replay_counters();
+        Ok(())
+    }
+
+    /// Counters are flushed straight away: losing one to a power cut would reopen a replay window.
+    fn persist_replay_counters(&self) {
+        let (Some(storage), Some(replay)) = (&self.storage, &self.replay) else { return };
+        if let Err(e) = storage.put_json(REPLAY_KEY, replay).and_then(|_| storage.flush()) {
+            error!("Failed to persist replay counters: {}", e);
+        }
+    }
+
+    fn handle_session_grant(&mut self, cmd: SessionGrant) -> Result<(), NodeError> {
+        if cmd.target_node_id != self.id {
+            return Ok(());
+        }
+        let Some(sessions) = &mut self.sessions else {
+            info!("SessionGrant ignored: sessions are not enforced");
+            return Err(NodeError::NotEnabled("operator sessions"));
+        };
+        info!("Session {} granted until {} for {:?}", cmd.session_id, cmd.expires_at, cmd.scopes);
+        sessions.grant(&cmd.session_id, cmd.scopes, cmd.expires_at, self.clock.unix());
+        Ok(())
+    }
+
+    fn handle_session_revoke(&mut self, cmd: SessionRevoke) -> Result<(), NodeError> {
+        if cmd.target_node_id != self.id {
+            return Ok(());
+        }
+        if let Some(sessions) = &mut self.sessions {
+            if sessions.revoke(&cmd.session_id) {
+                info!("Session {} revoked", cmd.session_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Start downloading a firmware image in the background; `finish_firmware_update`
+    /// installs it once it arrives, and only if it passes signature, hash, hardware and
+    /// version checks.
+    async fn handle_firmware_update(&mut self, cmd: FirmwareUpdate) -> Result<(), NodeError> {
+        if cmd.target_node_id != self.id {
+            return Ok(());
+        }
+        if self.ota.is_none() {
+            warn!("FirmwareUpdate received but OTA is not configured");
+            return Err(NodeError::NotEnabled("firmware updates"));
+        }
+        if let Some((url, _)) = &self.firmware_download {
+            return Err(NodeError::WrongState(format!("already downloading firmware from {}", url)));
+        }
+        info!("Downloading firmware from {}", cmd.url);
+        let url = cmd.url.clone();
+        let download = tokio::spawn(async move {
+            tokio::time::timeout(FETCH_TIMEOUT, crate::ota::fetch(&url)).await
+                .unwrap_or_else(|_| Err(OtaError::Download(format!("timed out after {}s", FETCH_TIMEOUT.as_secs()))))
+        });
+        self.firmware_download = Some((cmd.url, download));
+        Ok(())
+    }
+
+    /// Install the downloaded firmware image, if the download has finished, and report the outcome.
+    async fn finish_firmware_update(&mut self) {
+        if !self.firmware_download.as_ref().is_some_and(|(_, download)| download.is_finished()) {
+            return;
+        }
+        let (Some((url, download)), Some(ota)) = (self.firmware_download.take(), &self.ota) else { return };
+        let fetched = download.await.unwrap_or_else(|e| Err(OtaError::Download(e.to_string())));
+        let (version, result) = match fetched {
+            Ok(bytes) => (
+                FirmwareImage::parse(&bytes).map(|i| i.manifest.version).unwrap_or_default(),
+                ota.install(&bytes),
+            ),
+            Err(e) => (String::new(), Err(e)),
+        };
+        let status = match result {
+            Ok(manifest) => {
+                info!("Firmware {} installed; restart to activate", manifest.version);
+                FirmwareStatus { node_id: self.id.clone(), version, installed: true, reason: String::new() }
+            }
+            Err(e) => {
+                warn!("Refusing firmware from {}: {}", url, e);
+                self.events.publish(NodeEvent::Alarm {
+                    code: "firmware_rejected".to_string(),
+                    message: e.to_string(),
+                });
+                FirmwareStatus { node_id: self.id.clone(), version, installed: false, reason: e.to_string() }
+            }
+        };
+        if let Some(client) = &self.client {
+            if let Err(e) = client.send_firmware_status(status).await {
+                error!("Failed to send firmware status: {}", e);
+            }
+        }
+    }
+
+    async fn send_join_request(&mut self) {
+        let (Some(_), Some(token)) = (&self.client, &self.provisioning_token) else { return };
+        let timestamp = self.clock.unix();
+        let mut nonce = [0u8; 16];
+        if let Err(e) = keys::random_bytes(self.secure_element.as_deref_mut(), &mut nonce) {
+            error!("Cannot build join request: {}", e);
+            return;
+        }
+        let mut request = JoinRequest {
+            node_id: self.id.clone(),
+            timestamp,
+            token_proof: keys::join_proof(token, &self.id, timestamp, &nonce),
+            firmware_version: env!("CARGO_PKG_VERSION").to_string(),
+            nonce: nonce.to_vec(),
+            device_public_key: Vec::new(),
+            device_signature: Vec::new(),
+        };
+
+        // Attest with the secure element's identity key so the orchestrator can pin the hardware
+        if let Some(se) = &mut self.secure_element {
+            let digest: [u8; 32] = sha2::Sha256::digest(keys::join_challenge(&self.id, timestamp, &nonce)).into();
+            match (se.public_key(self.sign_slot), se.sign(self.sign_slot, &digest)) {
+                (Ok(public_key), Ok(signature)) => {
+                    request.device_public_key = public_key.to_vec();
+                    request.device_signature = signature.to_vec();
+                }
+                (Err(e), _) | (_, Err(e)) => warn!("Secure element signing failed, joining without attestation: {}", e),
+            }
+        }
+
+        if let Some(client) = &self.client {
+            if let Err(e) = client.send_join_request(request).await {
+                error!("Failed to send join request: {}", e);
+            }
+        }
+    }
+
+    /// Adopt the identity and mesh key issued by the orchestrator and become operational.
+    async fn handle_join_accept(&mut self, cmd: JoinAccept) -> Result<(), NodeError> {
+        if cmd.target_node_id != self.id {
+            return Ok(());
+        }
+        if self.state != NodeState::Joining {
+            return Err(NodeError::WrongState("already joined".to_string()));
+        }
+        let Some(token) = &self.provisioning_token else { return Err(NodeError::NotEnabled("provisioning")) };
+        let assigned_id = if cmd.assigned_node_id.is_empty() { self.id.clone() } else { cmd.assigned_node_id };
+        let keyring = match Keyring::from_join(token, &assigned_id, cmd.key_epoch, &cmd.wrapped_key, &cmd.nonce) {
+            Ok(keyring) => Arc::new(Mutex::new(keyring)),
+            Err(e) => {
+                warn!("Rejecting JoinAccept: {}", e);
+                return Err(NodeError::Rejected(e.to_string()));
+            }
+        };
+
+        // IP transports get a per-node client certificate along with the mesh key
+        let tls_identity = if cmd.client_cert.is_empty() {
+            None
+        } else {
+            match keys::unwrap_join_tls_key(token, &assigned_id, &cmd.wrapped_client_key, &cmd.client_key_nonce) {
+                Ok(key_pem) => Some(TlsIdentity { cert_pem: String::from_utf8_lossy(&cmd.client_cert).into_owned(), key_pem }),
+                Err(e) => {
+                    warn!("Rejecting JoinAccept: client key: {}", e);
+                    return Err(NodeError::Rejected(format!("client key: {}", e)));
+                }
+            }
+        };
+
+        info!("Joined the mesh as {} (key epoch {})", assigned_id, cmd.key_epoch);
+        if let Some(client) = &mut self.client {
+            client.set_keyring(keyring.clone());
+        }
+        self.keyring = Some(keyring);
+        self.id = assigned_id;
+        if let Some(storage) = &self.storage {
+            let identity = Identity { node_id: self.id.clone(), joined_at: self.clock.unix() };
+            if let Err(e) = storage.put_json(IDENTITY_KEY, &identity) {
+                error!("Failed to persist identity: {}", e);
+            }
+        }
+        self.persist_keyring();
+        if let Some(identity) = tls_identity {
+            self.install_tls_identity(identity, Vec::new()).await;
+        }
+        self.set_state(NodeState::Normal);
+        self.send_feature_report().await;
+        Ok(())
+    }
+
+    async fn handle_cert_rotation(&mut self, cmd: CertRotation) -> Result<(), NodeError> {
+        if cmd.target_node_id != self.id {
+            return Ok(());
+        }
+        let Some(keyring) = &self.keyring else {
+            warn!("CertRotation received without mesh keys");
+            return Err(NodeError::NotEnabled("mesh keys"));
+        };
+        let key_pem = match keyring.lock().unwrap().unwrap_tls_key(&self.id, &cmd.wrapped_client_key, &cmd.nonce) {
+            Ok(key_pem) => key_pem,
+            Err(e) => {
+                warn!("Rejecting CertRotation: {}", e);
+                return Err(NodeError::Rejected(e.to_string()));
+            }
+        };
+        let pins = match cmd.orchestrator_pins.iter().map(|p| tls::parse_pin(p)).collect::<tls::Result<Vec<_>>>() {
+            Ok(pins) => pins,
+            Err(e) => {
+                warn!("Rejecting CertRotation: {}", e);
+                return Err(NodeError::Rejected(e.to_string()));
+            }
+        };
+        let identity = TlsIdentity { cert_pem: String::from_utf8_lossy(&cmd.client_cert).into_owned(), key_pem };
+        if !self.install_tls_identity(identity, cmd.orchestrator_pins).await {
+            return Err(NodeError::Rejected("the transport refused the new TLS identity".to_string()));
+        }
+        info!("TLS client certificate rotated ({} new orchestrator pins)", pins.len());
+        Ok(())
+    }
+
+    /// Reconnect the transport with a new client identity, then persist it. Returns
+    /// false (and keeps the old identity) if the transport rejects it.
+    async fn install_tls_identity(&mut self, identity: TlsIdentity, pins: Vec<String>) -> bool {
+        let parsed_pins = pins.iter().filter_map(|p| tls::parse_pin(p).ok()).collect();
+        if let Some(client) = &self.client {
+            if let Err(e) = client.rotate_tls(identity.clone(), parsed_pins).await {
+                warn!("TLS identity not applied: {}", e);
+                return false;
+            }
+        }
+        if let Some(storage) = &self.storage {
+            let mut result = storage.put_json(TLS_IDENTITY_KEY, &identity);
+            if !pins.is_empty() {
+                result = result.and_then(|_| storage.put_json(TLS_PINS_KEY, &pins));
+            }
+            if let Err(e) = result.and_then(|_| storage.flush()) {
+                error!("Failed to persist TLS identity: {}", e);
+            }
+        }
+        true
+    }
+
+    fn handle_join_reject(&mut self, cmd: JoinReject) -> Result<(), NodeError> {
+        if cmd.target_node_id == self.id {
+            warn!("Join rejected by orchestrator: {}", cmd.reason);
+        }
+        Ok(())
+    }
+
+    /// Stage the next mesh key and report the outcome.
+    async fn handle_key_rotation(&mut self, cmd: KeyRotation) -> Result<(), NodeError> {
+        if !cmd.target_node_id.is_empty() && cmd.target_node_id != self.id {
+            return Ok(());
+        }
+        let Some(keyring) = self.keyring.clone() else {
+            warn!("KeyRotation received but no mesh key is configured");
+            return Err(NodeError::NotEnabled("mesh keys"));
+        };
+        let grace_secs = if cmd.grace_secs == 0 { DEFAULT_GRACE_SECS } else { cmd.grace_secs };
+        let result = keyring.lock().unwrap()
+            .stage(cmd.new_epoch, &cmd.wrapped_key, &cmd.nonce, cmd.activate_at, grace_secs);
+        let error = match &result {
+            Ok(()) => {
+                info!("Staged mesh key epoch {} (active at {})", cmd.new_epoch, cmd.activate_at);
+                self.persist_keyring();
+                String::new()
+            }
+            Err(e) => {
+                warn!("Rejected key rotation to epoch {}: {}", cmd.new_epoch, e);
+                e.to_string()
+            }
+        };
+        let active_epoch = keyring.lock().unwrap().active_epoch(self.clock.unix());
+        if let Some(client) = &self.client {
+            let ack = KeyRotationAck {
+                node_id: self.id.clone(),
+                new_epoch: cmd.new_epoch,
+                accepted: result.is_ok(),
+                active_epoch,
+                error,
+            };
+            if let Err(e) = client.send_key_rotation_ack(ack).await {
+                error!("Failed to send key rotation ack: {}", e);
+            }
+        }
+        result.map_err(|e| NodeError::Rejected(e.to_string()))
+    }
+
+    /// Activate a due key and retire the old one once its grace window has passed.
+    pub fn advance_keyring(&mut self) {
+        let changed = self.keyring.as_ref()
+            .is_some_and(|k| k.lock().unwrap().advance(self.clock.unix()));
+        if changed {
+            info!("Mesh keyring advanced");
+            self.persist_keyring();
+        }
+    }
+
+    /// Store the keyring, sealed under the secure element's key when one is fitted.
+    fn persist_keyring(&mut self) {
+        let (Some(storage), Some(keyring)) = (&self.storage, &self.keyring) else { return };
+        let result = match self.kek {
+            Some(kek) => {
+                let mut nonce = [0u8; NONCE_LEN];
+                keys::random_bytes(self.secure_element.as_deref_mut(), &mut nonce)
+                    .and_then(|_| keyring.lock().unwrap().seal(&kek, nonce))
+                    .map_err(StorageError::from)
+                    .and_then(|sealed| storage.put_json(SEALED_KEYRING_KEY, &sealed))
+                    .and_then(|_| storage.remove(KEYRING_KEY))
+            }
+            None => storage.put_json(KEYRING_KEY, &*keyring.lock().unwrap()),
+        };
+        if let Err(e) = result {
+            error!("Failed to persist keyring: {}", e);
+        }
+    }
+
+    /// Send the node's full state so it can be restored onto replacement hardware.
+    async fn handle_snapshot_request(&mut self, req: SnapshotRequest) -> Result<(), NodeError> {
+        if req.target_node_id != self.id {
+            return Ok(());
+        }
+        let Some(client) = &self.client else { return Ok(()) };
+        let snapshot = self.take_snapshot();
+        let payload = match snapshot.to_bytes() {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Failed to encode snapshot: {}", e);
+                return Err(NodeError::Failed(format!("encoding snapshot: {}", e)));
+            }
+        };
+        let data = SnapshotData {
+            node_id: self.id.clone(),
+            timestamp: snapshot.taken_at,
+            format_version: SNAPSHOT_FORMAT,
+            payload,
+        };
+        client.send_snapshot(data).await.inspect_err(|e| error!("Failed to send snapshot: {}", e))?;
+        Ok(())
+    }
+
+    /// Seed this node with a snapshot taken from another (or an earlier) node.
+    async fn handle_snapshot_restore(&mut self, cmd: SnapshotRestore) -> Result<(), NodeError> {
+        if cmd.target_node_id != self.id {
+            return Ok(());
+        }
+        let Some(data) = cmd.snapshot else {
+            warn!("SnapshotRestore without snapshot payload");
+            return Err(NodeError::Rejected("no snapshot payload".to_string()));
+        };
+        match NodeSnapshot::from_bytes(data.format_version, &data.payload) {
+            Ok(snapshot) => {
+                warn!("Restoring snapshot of node {} taken at {}", snapshot.node_id, snapshot.taken_at);
+                self.restore_snapshot(snapshot).await;
+                // Orchestrator's view of our relays has changed
+                self.send_feature_report().await;
+                Ok(())
+            }
+            Err(e) => {
+                error!("Rejecting snapshot from {}: {}", data.node_id, e);
+                Err(NodeError::Rejected(e.to_string()))
+            }
+        }
+    }
+
+    pub fn take_snapshot(&self) -> NodeSnapshot {
+        let mut counters = BTreeMap::new();
+        if let Some(audit) = &self.audit {
+            counters.insert(COUNTER_AUDIT_SEQ.to_string(), audit.next_seq());
+        }
+        NodeSnapshot {
+            node_id: self.id.clone(),
+            taken_at: self.clock.unix(),
+            state: self.state,
+            relays: self.relays.clone(),
+            calibration: self.calibration.clone(),
+            counters,
+        }
+    }
+
+    /// Adopt a snapshot's relays, calibration, state and counters, persisting them for the next boot.
+    pub async fn restore_snapshot(&mut self, snapshot: NodeSnapshot) {
+        // Keep the current physical positions so re-asserting the snapshot's is audited as a change
+        let desired: Vec<(String, bool)> = snapshot.relays.iter().map(|r| (r.id.clone(), r.is_closed)).collect();
+        self.relays = snapshot.relays.into_iter()
+            .map(|mut r| {
+                r.is_closed = self.relays.iter().find(|cur| cur.id == r.id).map(|cur| cur.is_closed).unwrap_or(false);
+                r
+            })
+            .collect();
+
+        if let Some(calibration) = snapshot.calibration {
+            self.voltage_ref = calibration.voltage_ref;
+            self.calibration = Some(calibration);
+        }
+        if let (Some(audit), Some(&seq)) = (&mut self.audit, snapshot.counters.get(COUNTER_AUDIT_SEQ)) {
+            audit.advance_to(seq);
+        }
+
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.put_json(snapshot::RELAYS_KEY, &self.relays) {
+                error!("Failed to persist restored relays: {}", e);
+            }
+            if let Some(calibration) = &self.calibration {
+                if let Err(e) = storage.put_json(snapshot::CALIBRATION_KEY, calibration) {
+                    error!("Failed to persist restored calibration: {}", e);
+                }
+            }
+        }
+
+        // Through the Faulted latch, so a snapshot can't clear an emergency stop
+        self.set_state(snapshot.state);
+        for (relay_id, closed) in desired {
+            self.actuate_relay(&relay_id, closed, "snapshot_restore").await;
+        }
+        self.persist_state();
+    }
+
+    /// False if the identity is bound to different hardware, e.g. the SD card was moved to another board.
+    pub fn hardware_binding_ok(&self) -> bool {
+        match &self.bound_hardware_id {
+            Some(bound) => self.hardware_id.as_deref().is_some_and(|id| id.eq_ignore_ascii_case(bound)),
+            None => true,
+        }
+    }
+
+    /// Announce our relays and mesh type to the orchestrator. The FeatureReport is resent
+    /// until the orchestrator acknowledges it.
+    async fn send_feature_report(&mut self) {
+        self.registered = false;
+        self.registration_due_at = self.clock.unix() + REGISTRATION_RETRY_SECS;
+        if !self.hardware_binding_ok() {
+            let message = format!(
+                "identity {} is bound to hardware {} but running on {}",
+                self.id,
+                self.bound_hardware_id.as_deref().unwrap_or_default(),
+                self.hardware_id.as_deref().unwrap_or("unknown"),
+            );
+            error!("Refusing to register: {}", message);
+            self.raise_alarm("hardware_mismatch", &message).await;
+            return;
+        }
+        if let Some(client) = &self.client {
+            let relay_infos: Vec<crate::comms::RelayInfo> = self.relays.iter()
+                .enumerate()
+                .map(|(i, r)| crate::comms::RelayInfo {
+                    index: i as u32,
+                    id: r.id.clone(),
+                    name: r.name.clone(),
+                    relay_type: r.relay_type.clone() as i32,
+                    priority: r.priority as i32,
+                    amperage: r.amperage,
+                    is_closed: r.is_closed,
+                    community_criticality: r.community_criticality as u32,
+                    phase: r.phase.or(self.phase).map(|p| p.as_str().to_string()).unwrap_or_default(),
+                })
+                .collect();
+
+            let mesh_type_str = match self.mesh_type {
+                MeshType::AdHoc => "AdHoc",
+                MeshType::GovernmentSanctioned => "GovernmentSanctioned",
+            };
+
+            if let Err(e) = client.send_feature_report(&self.id, relay_infos, mesh_type_str, self.hardware_id.as_deref(), self.zones.clone(), self.phase).await {
+                error!("Failed to send feature report: {}", e);
+            }
+        }
+    }
+
+    /// Enter BlackStart mode - awaiting targeted relay activation
+    /// NOTE: Island mode is always entered first, which sheds all loads.
+    /// BlackStart is the recovery phase where we selectively re-enable relays.
+    pub fn enter_blackstart_mode(&mut self) {
+        self.set_state(NodeState::BlackStart);
+        info!("Entering BlackStart mode (loads already shed from island mode)");
+
+        // Loads are already shed from island mode - no need to shed again.
+        // We keep grid connected so orchestrator can manage power flow from available sources.
+    }
+
+    /// Activate all relays matching a specific priority, as far as the interlocks allow
+    async fn activate_relays_by_priority(&mut self, priority: Priority) {
+        let to_activate: Vec<String> = self.relays.iter()
+            .filter(|r| r.priority == priority && !r.is_closed)
+            .map(|r| r.id.clone())
+            .collect();
+
+        for relay_id in to_activate {
+            self.restore_load(&relay_id, "activate_by_priority").await;
+        }
+    }
+
+    // Old tick() removed - replaced by check_voltage()
+
+    /// Enter island mode - behavior depends on mesh type
+    pub async fn enter_island_mode(&mut self) {
+        self.set_state(NodeState::Islanded);
+        info!("Entering island mode (MeshType: {:?})", self.mesh_type);
+
+        // 1. Shed ALL loads (regardless of priority)
+        self.shed_all_loads().await;
+
+        // 2. Disconnect from utility grid ONLY in AdHoc mode
+        match self.mesh_type {
+            MeshType::AdHoc => {
+                info!("AdHoc mesh: Disconnecting from utility grid");
+                self.disconnect_grid().await;
+            }
+            MeshType::GovernmentSanctioned if self.island_disconnects_grid() => {
+                warn!("GovernmentSanctioned mesh but the MID has not isolated: disconnecting from utility grid");
+                self.disconnect_grid().await;
+            }
+            MeshType::GovernmentSanctioned => {
+                info!("GovernmentSanctioned mesh: Grid relay stays connected (MID handles isolation)");
+                // Do NOT disconnect - the MID at the transformer handles this
+            }
+        }
+    }
+
+    /// Whether islanding drops the utility connection: always on an AdHoc mesh, on a
+    /// sanctioned one only while the MID that should isolate us hasn't.
+    fn island_disconnects_grid(&self) -> bool {
+        match self.mesh_type {
+            MeshType::AdHoc => true,
+            MeshType::GovernmentSanctioned => self.behind_mid() && !self.mid_isolated(),
+        }
+    }
+
+    /// Shed ALL load relays
+    async fn shed_all_loads(&mut self) {
+        if let Some(pickup) = &mut self.cold_load_pickup {
+            pickup.clear();
+        }
+        if let Some(motors) = &mut self.motors {
+            motors.clear();
+        }
+        let load_ids: Vec<String> = self.relays.iter()
+            .filter(|r| r.relay_type == RelayType::Load && r.is_closed)
+            .map(|r| r.id.clone())
+            .collect();
+
+        for relay_id in load_ids {
+            self.actuate_relay(&relay_id, false, "island_shed_all").await;
+        }
+    }
+
+    /// Disconnect from the utility grid, over to whatever feeds an island
+    async fn disconnect_grid(&mut self) {
+        self.transfer_to(self.island_supply(), "island_disconnect_grid").await;
+    }
+
+    /// What feeds the house off the grid: the generator while it carries the load,
+    /// otherwise a grid-forming inverter if we have one.
+    fn island_supply(&self) -> Option<Supply> {
+        if self.generator.as_ref().is_some_and(|g| g.state() == GeneratorState::Transferred) {
+            Some(Supply::Generator)
+        } else {
+            self.transfer.inverter_relay.as_ref().map(|_| Supply::Inverter)
+        }
+    }
+
+    /// The transfer group's relays and the source each connects: our Grid relays, the
+    /// generator's transfer relay and the inverter's.
+    fn transfer_relays(&self) -> Vec<(String, Supply)> {
+        let mut members: Vec<(String, Supply)> = self.relays.iter()
+            .filter(|r| r.relay_type == RelayType::Grid)
+            .map(|r| (r.id.clone(), Supply::Grid))
+            .collect();
+        members.extend(self.generator.as_ref().map(|g| (g.transfer_relay.clone(), Supply::Generator)));
+        members.extend(self.transfer.inverter_relay.clone().map(|id| (id, Supply::Inverter)));
+        members
+    }
+
+    /// Switch the house over to `to`, or off every source, break-before-make. Nothing is
+    /// disconnected for a source the interlocks would not connect; connecting it may wait
+    /// out the dead time on later transfer ticks. Returns false if a relay would not move,
+    /// which gives the transfer up.
+    async fn transfer_to(&mut self, to: Option<Supply>, trigger: &str) -> bool {
+        if let Some(supply) = to {
+            let mut conditions = self.interlock_conditions();
+            conditions.transfer_relays.clear();
+            conditions.transfer_dead_time = false;
+            let members = self.transfer_relays();
+            for relay in self.relays.iter().filter(|r| members.iter().any(|(id, s)| *id == r.id && *s == supply)) {
+                if let Err(refusal) = interlock::check_close(&self.relays, relay, &conditions) {
+                    warn!("Not switching to {:?}: {} [{}]", supply, refusal, trigger);
+                    return false;
+                }
+            }
+        }
+        self.transfer.begin(to, trigger);
+        self.run_transfer().await
+    }
+
+    /// Take the transfer under way as far as it can go now.
+    async fn run_transfer(&mut self) -> bool {
+        let now = self.clock.instant();
+        let trigger = self.transfer.trigger().to_string();
+        loop {
+            match self.transfer.step(&self.transfer_relays(), &self.relays, now) {
+                Step::Break(relay_ids) => {
+                    for relay_id in relay_ids {
+                        if !self.actuate_relay(&relay_id, false, &trigger).await {
+                            self.transfer.finish();
+                            return false;
+                        }
+                    }
+                    self.transfer.broke(now);
+                }
+                Step::Wait => return true,
+                Step::Make(relay_ids) => {
+                    self.transfer.finish();
+                    let mut made = true;
+                    for relay_id in relay_ids {
+                        made &= self.actuate_relay(&relay_id, true, &trigger).await;
+                    }
+                    return made;
+                }
+                Step::Done => {
+                    self.transfer.finish();
+                    return true;
+                }
+            }
+        }
+    }
+
+    /// Close a relay for a blackstart step; a transfer relay connects its source
+    /// through the transfer group instead.
+    async fn close_relay(&mut self, relay_id: &str, trigger: &str) -> bool {
+        match self.transfer_relays().into_iter().find(|(id, _)| id == relay_id) {
+            Some((_, supply)) => self.transfer_to(Some(supply), trigger).await,
+            None => self.actuate_relay(relay_id, true, trigger).await,
+        }
+    }
+
+    pub async fn shed_load(&mut self, priority_threshold: Priority) {
+        if let Some(pickup) = &mut self.cold_load_pickup {
+            pickup.clear();
+        }
+        if let Some(motors) = &mut self.motors {
+            motors.clear();
+        }
+        // Collect IDs to shed first to avoid borrow issues
+        let to_shed: Vec<String> = self.relays.iter()
+            .filter(|r| r.relay_type == RelayType::Load && r.priority >= priority_threshold && r.is_closed)
+            .map(|r| r.id.clone())
+            .collect();
+
+        for relay_id in to_shed {
+            self.actuate_relay(&relay_id, false, "load_shed").await;
+        }
+    }
+
+    /// Transition the state machine and announce the change.
+    fn set_state(&mut self, state: NodeState) {
+        // Only a fault reset leaves Faulted
+        if self.state == NodeState::Faulted {
+            debug!("Staying Faulted rather than entering {:?}", state);
+            return;
+        }
+        self.enter_state(state);
+    }
+
+    fn enter_state(&mut self, state: NodeState) {
+        if self.state != state {
+            self.outages.state_changed(self.state, state, self.clock.unix());
+            self.persist_outages();
+            self.state = state;
+            self.events.publish(NodeEvent::StateChanged { state });
+            self.persist_state();
+        }
+    }
+
+    fn persist_outages(&self) {
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.put_json(OUTAGE_KEY, &self.outages) {
+                error!("Failed to persist outage counters: {}", e);
+            }
+        }
+    }
+
+    fn persisted_state(&self) -> PersistedState {
+        PersistedState {
+            state: self.state,
+            relays: self.relays.iter().map(|r| (r.id.clone(), r.is_closed)).collect(),
+        }
+    }
+
+    /// Stage the current state and relay positions in storage.
+    fn persist_state(&self) {
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.put_json(STATE_KEY, &self.persisted_state()) {
+                error!("Failed to persist node state: {}", e);
+            }
+        }
+    }
+
+    /// Re-apply the state persisted before the last shutdown, if any.
+    pub async fn restore_state(&mut self) {
+        // An uncommissioned node has no operational state to resume
+        if self.state == NodeState::Joining {
+            return;
+        }
+        let Some(storage) = &self.storage else { return };
+        let saved: PersistedState = match storage.get_json(STATE_KEY) {
+            Ok(Some(saved)) => saved,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Ignoring unreadable persisted state: {}", e);
+                return;
+            }
+        };
+
+        info!("Restoring persisted state {:?}", saved.state);
+        self.state = saved.state;
+        for (relay_id, closed) in saved.relays {
+            self.actuate_relay(&relay_id, closed, "restore_state").await;
+        }
+    }
+
+    /// What closed load circuits measured exporting take off their rated draw.
+    fn export_credit_watts(&self) -> f32 {
+        self.relays.iter()
+            .filter(|r| r.relay_type == RelayType::Load && r.is_closed)
+            .filter_map(|r| self.circuit_watts.get(&r.id).filter(|w| **w < 0.0).map(|w| r.amperage * self.voltage_ref - w))
+            .sum()
+    }
+
+    /// What the relay interlocks depend on right now.
+    fn interlock_conditions(&self) -> Conditions {
+        Conditions {
+            state: self.state,
+            estop_tripped: self.estop.as_ref().is_some_and(|estop| estop.tripped()),
+            mesh_type: self.mesh_type.clone(),
+            grid_close_permitted: self.grid_close_permitted(),
+            power_budget: self.power_budget,
+            voltage_ref: self.voltage_ref,
+            export_credit_watts: self.export_credit_watts(),
+            transfer_relays: self.transfer_relays(),
+            transfer_dead_time: self.transfer.in_dead_time(self.clock.instant()),
+            locked_out: self.arc_detector.as_ref().map(|d| d.locked_out()).unwrap_or_default()
+                .into_iter()
+                .chain(self.relay_health.failed())
+                .collect(),
+            derating: self.thermal.as_ref().and_then(|t| t.derating()),
+        }
+    }
+
+    /// Change a relay's logical and physical state, recording what caused it.
+    /// Returns false if the relay is unknown, an interlock refused, or its driver failed.
+    async fn actuate_relay(&mut self, relay_id: &str, closed: bool, trigger: &str) -> bool {
+        if trigger != MOTOR_START {
+            // Boxed: queuing a motor start can actuate the motor's relay in turn
+            if let Some(queued) = Box::pin(self.start_motor(relay_id, closed)).await {
+                return queued;
+            }
+        }
+        let conditions = self.interlock_conditions();
+        let Some(relay) = self.relays.iter().find(|r| r.id == relay_id) else {
+            warn!("Cannot actuate unknown relay {}", relay_id);
+            return false;
+        };
+        if closed {
+            if let Err(refusal) = interlock::check_close(&self.relays, relay, &conditions) {
+                warn!("Leaving {} open: {} [{}]", relay.name, refusal, trigger);
+                return false;
+            }
+        }
+        let was_closed = relay.is_closed;
+        let (is_load, priority) = (relay.relay_type == RelayType::Load, relay.priority);
+        info!("{} relay: {} (Priority: {:?}) [{}]",
+              if closed { "Closing" } else { "Opening" }, relay.name, relay.priority, trigger);
+        if let Err(e) = self.set_physical_relay(relay_id, closed).await {
+            // The contacts didn't move, so neither does our record of them
+            error!("Failed to set relay {}: {}", relay_id, e);
+            self.queue_alarm("relay_fault", &format!("{} did not {}: {}", relay_id, if closed { "close" } else { "open" }, e));
+            return false;
+        }
+        if let Some(relay) = self.relays.iter_mut().find(|r| r.id == relay_id) {
+            relay.is_closed = closed;
+        }
+        if !closed {
+            // Nothing flows until it is measured again
+            self.circuit_watts.remove(relay_id);
+        }
+        // A soft-start relay opening is the motor running, not an interruption
+        let soft_start = self.motors.as_ref().is_some_and(|m| m.is_soft_start_relay(relay_id));
+        if is_load && !soft_start && was_closed != closed {
+            let now = self.clock.unix();
+            if closed {
+                self.outages.relay_closed(relay_id, now);
+            } else {
+                self.outages.relay_opened(relay_id, priority, now);
+            }
+            self.persist_outages();
+        }
+        if !closed {
+            let now = self.clock.unix();
+            if let Some(soft_start_relay) = self.motors.as_mut().and_then(|m| m.stopped(relay_id, was_closed, now)) {
+                Box::pin(self.actuate_relay(&soft_start_relay, false, MOTOR_START)).await;
+            }
+        }
+
+        self.audit(AuditRecord::RelayActuation {
+            relay_id: relay_id.to_string(),
+            trigger: trigger.to_string(),
+            from_closed: was_closed,
+            to_closed: closed,
+        });
+        self.events.publish(NodeEvent::RelayChanged { relay_id: relay_id.to_string(), closed });
+        self.persist_state();
+        true
+    }
+
+    fn audit(&mut self, record: AuditRecord) {
+        if let Some(audit) = &mut self.audit {
+            if let Err(e) = audit.record(record) {
+                error!("Failed to write audit log: {}", e);
+            }
+        }
+    }
+
+    /// Set a physical relay via HAL driver, trying once more after a transient failure.
+    /// Never closes one while the emergency stop is tripped, even if it tripped since the
+    /// interlocks were checked.
+    async fn set_physical_relay(&mut self, relay_id: &str, closed: bool) -> Result<(), NodeError> {
+        let (Some(&pin), Some(driver)) = (self.relay_pins.get(relay_id), &self.relay_driver) else {
+            return Ok(());
+        };
+        if closed && self.estop.as_ref().is_some_and(|estop| estop.tripped()) {
+            return Err(NodeError::WrongState(Refusal::EmergencyStop.to_string()));
+        }
+        match driver.set_relay(pin, closed).await {
+            Err(e) if e.is_transient() => {
+                warn!("Retrying relay {}: {}", relay_id, e);
+                driver.set_relay(pin, closed).await
+            }
+            result => result,
+        }
+        .map_err(|source| NodeError::Relay { pin, source })
+    }
+}
+
+/// Sign a CT reading by which way power flows through the clamp.
+async fn signed_watts(sensor: &AsyncPowerSensor, channel: u8, direction: CtDirection, watts: f32) -> f32 {
+    let sign = sensor.read_power_sign(channel).await.unwrap_or_else(|e| {
+        warn!("Direction of power on channel {} unknown, taking it as import: {}", channel, e);
+        1.0
+    });
+    direction.apply(watts * sign)
+}
+
+/// Whether a CT reading could come from a working clamp.
+fn plausible_amps(amps: f32) -> bool {
+    amps.is_finite() && (0.0..=MAX_PLAUSIBLE_AMPS).contains(&amps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comms::CommunicationLayer;
+    use crate::hal::{AdcConfig, RelayPin};
+
+    #[tokio::test]
+    async fn test_node_initialization() {
+        let relays = vec![
+            Relay {
+                id: "r_grid".to_string(),
+                name: "Main Grid Tie".to_string(),
+                relay_type: RelayType::Grid,
+                priority: Priority::Critical,
+                amperage: 100.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        assert_eq!(node.relays.len(), 1);
+
+        // Check for Grid relay
+        let grid = node.relays.iter().find(|r| r.relay_type == RelayType::Grid).unwrap();
+        assert!(grid.is_closed);
+    }
+
+    #[test]
+    fn test_builder_rejects_inconsistent_parts() {
+        use crate::protection::ProtectionSettings;
+
+        let relay = |id: &str| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority: Priority::Low,
+            amperage: 10.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        };
+        let valid = || EdgeNode::builder("test_node")
+            .relays(vec![relay("r_hvac"), relay("r_aux")])
+            .relay_pins(HashMap::from([("r_hvac".to_string(), 17), ("r_aux".to_string(), 27)]));
+        assert!(valid().build().is_ok());
+
+        assert!(matches!(EdgeNode::builder("").build(), Err(NodeError::InvalidConfig(_))));
+        assert!(EdgeNode::builder("test_node").relays(vec![relay("r_hvac"), relay("r_hvac")]).build().is_err());
+        // A pin for a relay we don't have, and two relays on one pin
+        assert!(valid().relay_pins(HashMap::from([("r_pool".to_string(), 17)])).build().is_err());
+        assert!(valid().relay_pins(HashMap::from([("r_hvac".to_string(), 17), ("r_aux".to_string(), 17)])).build().is_err());
+        assert!(valid().voltage_ref(0.0).build().is_err());
+        // Thresholds that could never (or always) trip
+        assert!(valid().voltage_ref(100.0).build().is_err());
+        assert!(valid().protection(ProtectionSettings { frequency_min_hz: Some(61.0), frequency_max_hz: Some(59.0), ..Default::default() }).build().is_err());
+        assert!(valid().protection(ProtectionSettings { debounce_readings: 0, ..Default::default() }).build().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_shedding() {
+        let relays = vec![
+            Relay {
+                id: "r_hvac".to_string(),
+                name: "HVAC".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Medium,
+                amperage: 20.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+            Relay {
+                id: "r_aux".to_string(),
+                name: "Living Room Outlets".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+
+        // Ensure everything starts closed
+        assert!(node.relays.iter().all(|r| r.is_closed));
+
+        // Shed Low priority
+        node.shed_load(Priority::Low).await;
+
+        let aux = node.relays.iter().find(|r| r.id == "r_aux").unwrap();
+        assert_eq!(aux.is_closed, false); // Low priority should be open
+
+        let hvac = node.relays.iter().find(|r| r.id == "r_hvac").unwrap();
+        assert_eq!(hvac.is_closed, true); // Medium priority should still be closed
+    }
+
+    #[tokio::test]
+    async fn test_island_mode_transition() {
+         let relays = vec![
+            Relay {
+                id: "r_grid".to_string(),
+                name: "Main Grid Tie".to_string(),
+                relay_type: RelayType::Grid,
+                priority: Priority::Critical,
+                amperage: 100.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+            Relay {
+                id: "r_aux".to_string(),
+                name: "Living Room Outlets".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        node.enter_island_mode().await;
+
+        assert_eq!(node.state, NodeState::Islanded);
+
+        let grid = node.relays.iter().find(|r| r.relay_type == RelayType::Grid).unwrap();
+        assert_eq!(grid.is_closed, false); // Grid must be disconnected
+
+        let aux = node.relays.iter().find(|r| r.id == "r_aux").unwrap();
+        assert_eq!(aux.is_closed, false); // Low priority shed automatically
+    }
+
+    #[tokio::test]
+    async fn test_power_budget_sheds_lowest_priority_first() {
+        let load = |id: &str, priority: Priority, amperage: f32| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        };
+        let relays = vec![
+            load("r_crit", Priority::Critical, 10.0),
+            load("r_hvac", Priority::Medium, 20.0),
+            load("r_aux", Priority::Low, 10.0),
+        ];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+
+        // 4800 W of loads against a 3700 W budget: only the Low load goes
+        node.power_budget = Some(3700.0);
+        node.enforce_power_budget().await;
+        let closed: Vec<&str> = node.relays.iter().filter(|r| r.is_closed).map(|r| r.id.as_str()).collect();
+        assert_eq!(closed, vec!["r_crit", "r_hvac"]);
+
+        // Critical loads stay on even when the budget can't cover them
+        node.power_budget = Some(500.0);
+        node.enforce_power_budget().await;
+        let closed: Vec<&str> = node.relays.iter().filter(|r| r.is_closed).map(|r| r.id.as_str()).collect();
+        assert_eq!(closed, vec!["r_crit"]);
+    }
+
+    #[tokio::test]
+    async fn test_ev_charging_is_turned_down_before_it_is_shed() {
+        use crate::clock::VirtualClock;
+        use crate::evse::EvCharger;
+        use crate::hal::evse::mock::MockChargerControl;
+
+        let load = |id: &str, priority: Priority, amperage: f32| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        };
+        let relays = vec![load("r_hvac", Priority::Medium, 20.0), load("r_ev", Priority::Low, 32.0)];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        let control = MockChargerControl::new();
+        let pilot = control.handle();
+        node.ev_charger = Some(EvCharger::new("r_ev", 32.0, Box::new(control)));
+
+        // 6240 W against 4800 W: the car gives up 12 A and keeps charging
+        node.power_budget = Some(4800.0);
+        node.enforce_power_budget().await;
+        assert_eq!(*pilot.lock().unwrap(), Some(20.0));
+        assert!(node.relays.iter().all(|r| r.is_closed));
+
+        // Even the pilot's 6 A minimum doesn't fit: only then is it shed
+        node.power_budget = Some(2400.0);
+        node.enforce_power_budget().await;
+        assert_eq!(*pilot.lock().unwrap(), Some(6.0));
+        let closed: Vec<&str> = node.relays.iter().filter(|r| r.is_closed).map(|r| r.id.as_str()).collect();
+        assert_eq!(closed, vec!["r_hvac"]);
+
+        // Without a budget it may take everything again
+        node.power_budget = None;
+        node.relays[1].is_closed = true;
+        node.enforce_power_budget().await;
+        assert_eq!(*pilot.lock().unwrap(), Some(32.0));
+
+        // A demand-response event holds it at the minimum rather than opening it
+        let clock = VirtualClock::at(1_700_000_000);
+        node.set_clock(Arc::new(clock.clone()));
+        node.demand_response.accept("evt_1", Priority::Low, clock.unix(), clock.unix() + 1800, None, None);
+        node.run_demand_response().await;
+        assert!(node.relays[1].is_closed);
+        assert_eq!(*pilot.lock().unwrap(), Some(6.0));
+        clock.advance(Duration::from_secs(1800));
+        node.run_demand_response().await;
+        assert!(node.relays[1].is_closed);
+        assert_eq!(*pilot.lock().unwrap(), Some(32.0));
+    }
+
+    #[tokio::test]
+    async fn test_generator_takes_the_load_only_once_off_the_grid_and_steady() {
+        use crate::clock::VirtualClock;
+        use crate::events::NodeEvent;
+        use crate::generator::{Generator, GeneratorSettings, GeneratorState};
+        use crate::hal::generator::mock::MockGenerator;
+        use crate::node::Task;
+
+        let relay = |id: &str, relay_type: RelayType, is_closed: bool| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type,
+            priority: Priority::High,
+            amperage: 30.0,
+            is_closed,
+            community_criticality: 0,
+            phase: None,
+        };
+        let relays = vec![relay("r_grid", RelayType::Grid, true), relay("r_gen", RelayType::Source, false)];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        let clock = VirtualClock::at(1_700_000_000);
+        node.set_clock(Arc::new(clock.clone()));
+        let control = MockGenerator::new();
+        let mock = control.handle();
+        node.generator = Some(Generator::new("r_gen", GeneratorSettings::default(), Box::new(control), 0.0));
+        let run_for = async |node: &mut EdgeNode, secs: u64| {
+            for _ in 0..secs {
+                node.run_task(Task::Generator).await;
+                clock.advance(Duration::from_secs(1));
+            }
+        };
+
+        // On the grid it never starts
+        run_for(&mut node, 5).await;
+        assert!(!mock.lock().unwrap().running);
+
+        // Islanded without a battery gauge: start, warm up, hold steady, then transfer
+        node.enter_island_mode().await;
+        run_for(&mut node, 70).await;
+        assert_eq!(node.generator.as_ref().unwrap().state(), GeneratorState::Stabilizing);
+        assert!(!node.relays[1].is_closed);
+        run_for(&mut node, 2).await;
+        assert_eq!(node.generator.as_ref().unwrap().state(), GeneratorState::Transferred);
+        assert!(node.relays[1].is_closed);
+
+        // Output lost under load: the transfer opens at once and an alarm goes out
+        let mut events = node.events.subscribe();
+        mock.lock().unwrap().fails = true;
+        run_for(&mut node, 1).await;
+        assert!(!node.relays[1].is_closed);
+        assert_eq!(node.generator.as_ref().unwrap().state(), GeneratorState::Failed);
+        let alarms: Vec<String> = std::iter::from_fn(|| events.try_recv().ok())
+            .filter_map(|record| match record.event {
+                NodeEvent::Alarm { code, .. } => Some(code),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(alarms, ["generator_fault"]);
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_applies_all_or_nothing_and_rolls_back() {
+        use crate::comms::RebalanceDirective;
+        let load = |id: &str, is_closed: bool| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority: Priority::Medium,
+            amperage: 10.0,
+            is_closed,
+            community_criticality: 0,
+            phase: None,
+        };
+        let relays = vec![load("r_ev", true), load("r_heat", false)];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        let directive = |id: &str, open: &[&str], close: &[&str]| RebalanceDirective {
+            target_node_id: "test_node".to_string(),
+            directive_id: id.to_string(),
+            open_relays: open.iter().map(|s| s.to_string()).collect(),
+            close_relays: close.iter().map(|s| s.to_string()).collect(),
+            rollback: false,
+        };
+        let closed = |node: &EdgeNode| -> Vec<String> {
+            node.relays.iter().filter(|r| r.is_closed).map(|r| r.id.clone()).collect()
+        };
+
+        // An unknown relay rejects the whole directive
+        let rejected = node.apply_rebalance(&directive("d0", &["r_ev"], &["r_missing"])).await;
+        assert!(matches!(rejected, Err(NodeError::UnknownRelay(id)) if id == "r_missing"));
+        assert_eq!(closed(&node), vec!["r_ev"]);
+
+        node.apply_rebalance(&directive("d1", &["r_ev"], &["r_heat"])).await.unwrap();
+        assert_eq!(closed(&node), vec!["r_heat"]);
+
+        node.rollback_rebalance("d1").await.unwrap();
+        assert_eq!(closed(&node), vec!["r_ev"]);
+        // Rolling back twice (or something never applied) is harmless
+        node.rollback_rebalance("d1").await.unwrap();
+        assert_eq!(closed(&node), vec!["r_ev"]);
+    }
+
+    #[tokio::test]
+    async fn test_commands_are_answered_with_their_result() {
+        use crate::comms::streetgrid::neighborhood_message::Payload;
+        use crate::comms::{ActivateRelayByIndex, CommandResult, IncomingCommand, MockCommunication, ReceivedCommand, RelayState};
+        use crate::audit::SignatureStatus;
+
+        let link = Arc::new(MockCommunication::default());
+        let relays = vec![Relay {
+            id: "r_heat".to_string(),
+            name: "Heater".to_string(),
+            relay_type: RelayType::Load,
+            priority: Priority::Medium,
+            amperage: 10.0,
+            is_closed: false,
+            community_criticality: 0,
+            phase: None,
+        }];
+        let mut node = EdgeNode::builder("test_node")
+            .relays(relays)
+            .client(OrchestratorClient::new(link.clone()))
+            .build()
+            .unwrap();
+        let activate = |target: &str, relay_index: u32, counter: u64| ReceivedCommand {
+            command: IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex {
+                target_node_id: target.to_string(),
+                relay_index,
+                ..Default::default()
+            }),
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: Some("orchestrator".to_string()),
+            counter,
+            co_signatures: None,
+            zone: None,
+        };
+        let results = || -> Vec<CommandResult> {
+            link.sent().into_iter()
+                .filter_map(|msg| match msg.payload {
+                    Some(Payload::CommandResult(result)) => Some(result),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        node.handle_command(activate("test_node", 5, 1)).await;
+        node.handle_command(activate("test_node", 0, 2)).await;
+        // Commands for other nodes, overheard on the mesh, get no answer from us
+        node.handle_command(activate("other_node", 0, 3)).await;
+
+        let results = results();
+        assert_eq!(results.len(), 2);
+        let (failed, done) = (&results[0], &results[1]);
+        assert_eq!((failed.sender_id.as_str(), failed.counter, failed.command.as_str()), ("orchestrator", 1, "activate_relay_by_index"));
+        assert!(!failed.ok);
+        assert_eq!(failed.error_code, "no_relay_at_index");
+        assert_eq!(failed.error, "no relay at index 5");
+        assert_eq!(failed.relays, vec![RelayState { id: "r_heat".to_string(), is_closed: false }]);
+        assert!(done.ok);
+        assert_eq!(done.counter, 2);
+        assert!(done.error_code.is_empty());
+        assert_eq!(done.relays, vec![RelayState { id: "r_heat".to_string(), is_closed: true }]);
+    }
+
+    #[tokio::test]
+    async fn test_commands_dropped_unexecuted_are_answered_with_why() {
+        use crate::comms::streetgrid::neighborhood_message::Payload;
+        use crate::comms::{CommandResult, IncomingCommand, LoadShed, MockCommunication, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+        use crate::replay::ReplayGuard;
+
+        let link = Arc::new(MockCommunication::default());
+        let mut node = EdgeNode::builder("test_node")
+            .client(OrchestratorClient::new(link.clone()))
+            .build()
+            .unwrap();
+        let shed = |sender: &str, counter: u64| ReceivedCommand {
+            command: IncomingCommand::LoadShed(LoadShed { target_node_id: "test_node".to_string(), shed_load: true }),
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: Some(sender.to_string()),
+            counter,
+            co_signatures: None,
+            zone: None,
+        };
+        let results = || -> Vec<CommandResult> {
+            link.sent().into_iter()
+                .filter_map(|msg| match msg.payload {
+                    Some(Payload::CommandResult(result)) => Some(result),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        node.state = NodeState::Joining;
+        node.handle_command(shed("orch_a", 1)).await;
+        node.state = NodeState::Normal;
+        node.replay = Some(ReplayGuard::default());
+        node.handle_command(shed("orch_a", 2)).await;
+        node.handle_command(shed("orch_a", 2)).await;
+        node.failover = Some(Failover::new("orch_a", "orch_b", 300, node.clock.unix()));
+        node.handle_command(shed("orch_b", 1)).await;
+
+        let answers: Vec<_> = results().into_iter().map(|r| (r.sender_id, r.counter, r.ok, r.error_code)).collect();
+        assert_eq!(answers, vec![
+            ("orch_a".to_string(), 1, false, "wrong_state".to_string()),
+            ("orch_a".to_string(), 2, true, String::new()),
+            ("orch_a".to_string(), 2, false, "rejected".to_string()),
+            ("orch_b".to_string(), 1, false, "wrong_state".to_string()),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_applies_and_persists_the_safe_state_unless_resuming() {
+        use crate::comms::streetgrid::neighborhood_message::Payload;
+        use crate::comms::MockCommunication;
+
+        let relays = vec![Relay {
+            id: "r_ev".to_string(),
+            name: "EV Charger".to_string(),
+            relay_type: RelayType::Load,
+            priority: Priority::Low,
+            amperage: 32.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        }];
+        let dir = std::env::temp_dir().join(format!("streetgrid-shutdown-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let link = Arc::new(MockCommunication::default());
+        let mut node = EdgeNode::builder("test_node")
+            .relays(relays.clone())
+            .client(OrchestratorClient::new(link.clone()))
+            .build()
+            .unwrap();
+        node.storage = Some(Storage::open(&dir, Duration::from_secs(60)).unwrap());
+        node.shutdown_safe_state = HashMap::from([("r_ev".to_string(), false)]);
+
+        node.shutdown().await;
+        assert!(!node.relays[0].is_closed);
+        let last = link.sent().into_iter().rev().find_map(|msg| match msg.payload {
+            Some(Payload::Heartbeat(hb)) => Some(hb),
+            _ => None,
+        });
+        assert!(last.is_some_and(|hb| hb.shutting_down));
+
+        // Written out without waiting for the flusher; a restart stays in the safe state
+        let booted_closed = Relay { is_closed: true, ..relays[0].clone() };
+        let mut restarted = EdgeNode::builder("test_node").relays(vec![booted_closed.clone()]).build().unwrap();
+        restarted.storage = Some(Storage::open(&dir, Duration::from_secs(60)).unwrap());
+        restarted.restore_state().await;
+        assert!(!restarted.relays[0].is_closed);
+
+        // Unless asked to resume where it was before the safe state
+        let mut node = EdgeNode::builder("test_node").relays(relays.clone()).build().unwrap();
+        node.storage = restarted.storage.take();
+        node.shutdown_safe_state = HashMap::from([("r_ev".to_string(), false)]);
+        node.resume_on_boot = true;
+        node.shutdown().await;
+        assert!(!node.relays[0].is_closed);
+        let booted_open = Relay { is_closed: false, ..relays[0].clone() };
+        let mut restarted = EdgeNode::builder("test_node").relays(vec![booted_open]).build().unwrap();
+        restarted.storage = node.storage.take();
+        restarted.restore_state().await;
+        assert!(restarted.relays[0].is_closed);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_grid_relays_follow_the_mid() {
+        use crate::comms::{ActivateRelayByIndex, IncomingCommand, MidCommand, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+        use crate::clock::VirtualClock;
+        use crate::node::Task;
+        use crate::types::NodeRole;
+
+        let received = |command| ReceivedCommand {
+            command,
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: None,
+            counter: 0,
+            co_signatures: None,
+            zone: None,
+        };
+
+        let grid = |is_closed: bool| vec![Relay {
+            id: "r_grid".to_string(),
+            name: "Main Grid Tie".to_string(),
+            relay_type: RelayType::Grid,
+            priority: Priority::Critical,
+            amperage: 100.0,
+            is_closed,
+            community_criticality: 0,
+            phase: None,
+        }];
+
+        // Behind a MID that hasn't been heard: no reconnecting, and islanding opens the tie
+        let mut node = EdgeNode::builder("test_node")
+            .relays(grid(true))
+            .mesh_type(MeshType::GovernmentSanctioned)
+            .build()
+            .unwrap();
+        node.mid_id = Some("mid_t1".to_string());
+        node.enter_island_mode().await;
+        assert!(!node.relays[0].is_closed);
+        node.handle_command(received(IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex {
+            target_node_id: "test_node".to_string(),
+            relay_index: 0,
+            step_id: String::new(),
+        }))).await;
+        assert!(!node.relays[0].is_closed);
+
+        // The MID itself switches its breaker on command, leaving the dead time between
+        let mut mid = EdgeNode::builder("mid_t1")
+            .relays(grid(true))
+            .mesh_type(MeshType::GovernmentSanctioned)
+            .build()
+            .unwrap();
+        mid.role = NodeRole::Mid;
+        let clock = VirtualClock::at(1_700_000_000);
+        mid.set_clock(Arc::new(clock.clone()));
+        let switch = |isolate: bool| received(IncomingCommand::MidCommand(MidCommand {
+            target_node_id: "mid_t1".to_string(),
+            isolate,
+            permit_reconnect: true,
+        }));
+        mid.handle_command(switch(true)).await;
+        assert!(!mid.relays[0].is_closed);
+        mid.handle_command(switch(false)).await;
+        assert!(!mid.relays[0].is_closed);
+        clock.advance(mid.transfer.dead_time);
+        mid.run_task(Task::Transfer).await;
+        assert!(mid.relays[0].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_standby_orchestrator_obeyed_only_after_takeover() {
+        use crate::comms::{IncomingCommand, LoadShed, OrchestratorTakeover, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+
+        let received = |command, signature, sender: &str| ReceivedCommand {
+            command,
+            signature,
+            session_id: None,
+            sender_id: Some(sender.to_string()),
+            counter: 0,
+            co_signatures: None,
+            zone: None,
+        };
+        let shed = || IncomingCommand::LoadShed(LoadShed { target_node_id: "test_node".to_string(), shed_load: true });
+        let takeover = || IncomingCommand::OrchestratorTakeover(OrchestratorTakeover {
+            orchestrator_id: "orch_b".to_string(),
+            timestamp: 0,
+        });
+
+        let relays = vec![Relay {
+            id: "r_ev".to_string(),
+            name: "EV Charger".to_string(),
+            relay_type: RelayType::Load,
+            priority: Priority::Low,
+            amperage: 32.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        }];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        node.failover = Some(Failover::new("orch_a", "orch_b", 300, chrono::Utc::now().timestamp()));
+
+        node.handle_command(received(shed(), SignatureStatus::Valid, "orch_b")).await;
+        assert!(node.relays[0].is_closed);
+        node.handle_command(received(shed(), SignatureStatus::Valid, "orch_c")).await;
+        assert!(node.relays[0].is_closed);
+        node.handle_command(received(takeover(), SignatureStatus::Unsigned, "orch_b")).await;
+        assert_eq!(node.failover.as_ref().unwrap().following(), "orch_a");
+
+        node.handle_command(received(takeover(), SignatureStatus::Valid, "orch_b")).await;
+        assert_eq!(node.failover.as_ref().unwrap().following(), "orch_b");
+        node.handle_command(received(shed(), SignatureStatus::Valid, "orch_b")).await;
+        assert!(!node.relays[0].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_zone_commands_reach_every_member() {
+        use crate::comms::{ActivateRelayByIndex, IncomingCommand, LoadShed, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+
+        let to_zone = |command| ReceivedCommand {
+            command,
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: None,
+            counter: 0,
+            co_signatures: None,
+            zone: Some("block_3".to_string()),
+        };
+        let shed = || IncomingCommand::LoadShed(LoadShed { target_node_id: String::new(), shed_load: true });
+        let node = |id: &str, zones: &[&str]| {
+            let relays = vec![Relay {
+                id: "r_ev".to_string(),
+                name: "EV Charger".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Medium,
+                amperage: 32.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            }];
+            let mut node = EdgeNode::builder(id).relays(relays).build().unwrap();
+            node.zones = zones.iter().map(|z| z.to_string()).collect();
+            node
+        };
+
+        let mut member = node("node_01", &["phase_A", "block_3"]);
+        let mut outsider = node("node_02", &["block_4"]);
+        member.handle_command(to_zone(shed())).await;
+        outsider.handle_command(to_zone(shed())).await;
+        assert!(!member.relays[0].is_closed);
+        assert!(outsider.relays[0].is_closed);
+
+        // Relay indices differ between nodes, so they can't be addressed to a zone
+        member.handle_command(to_zone(IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex {
+            target_node_id: String::new(),
+            relay_index: 0,
+            step_id: String::new(),
+        }))).await;
+        assert!(!member.relays[0].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_government_mesh_keeps_grid_connected() {
+        let relays = vec![
+            Relay {
+                id: "r_grid".to_string(),
+                name: "Main Grid Tie".to_string(),
+                relay_type: RelayType::Grid,
+                priority: Priority::Critical,
+                amperage: 100.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+            Relay {
+                id: "r_aux".to_string(),
+                name: "Living Room Outlets".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let mut node = EdgeNode::builder("test_node")
+            .relays(relays)
+            .mesh_type(MeshType::GovernmentSanctioned)
+            .build()
+            .unwrap();
+        node.enter_island_mode().await;
+
+        assert_eq!(node.state, NodeState::Islanded);
+
+        // Government mesh: Grid relay stays CONNECTED (MID handles isolation)
+        let grid = node.relays.iter().find(|r| r.relay_type == RelayType::Grid).unwrap();
+        assert_eq!(grid.is_closed, true); // Grid stays connected!
+
+        // But low priority loads are still shed
+        let aux = node.relays.iter().find(|r| r.id == "r_aux").unwrap();
+        assert_eq!(aux.is_closed, false); // Low priority shed
+    }
+
+    #[tokio::test]
+    async fn test_island_state_survives_restart() {
+        let relays = vec![
+            Relay {
+                id: "r_grid".to_string(),
+                name: "Main Grid Tie".to_string(),
+                relay_type: RelayType::Grid,
+                priority: Priority::Critical,
+                amperage: 100.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let dir = std::env::temp_dir().join(format!("streetgrid-restart-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let storage = Storage::open(&dir, Duration::from_secs(60)).unwrap();
+
+        let mut node = EdgeNode::builder("test_node").relays(relays.clone()).build().unwrap();
+        node.storage = Some(storage.clone());
+        node.enter_island_mode().await;
+        storage.flush().unwrap();
+
+        // Simulated reboot: fresh node, same storage directory
+        let storage = Storage::open(&dir, Duration::from_secs(60)).unwrap();
+        let mut rebooted = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        rebooted.storage = Some(storage);
+        rebooted.restore_state().await;
+
+        assert_eq!(rebooted.state, NodeState::Islanded);
+        assert!(!rebooted.relays[0].is_closed); // Grid stays open after reboot
+    }
+
+    #[tokio::test]
+    async fn test_relay_actuation_is_audited() {
+        use crate::audit::AuditRecord;
+
+        let relays = vec![
+            Relay {
+                id: "r_aux".to_string(),
+                name: "Living Room Outlets".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let dir = std::env::temp_dir().join(format!("streetgrid-audit-node-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let storage = Storage::open(&dir, Duration::from_secs(60)).unwrap();
+
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        node.audit = Some(AuditLog::open(storage).unwrap());
+        node.shed_load(Priority::Low).await;
+
+        let entries = node.audit.as_ref().unwrap().entries_since(0, 10).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].record, AuditRecord::RelayActuation {
+            relay_id: "r_aux".to_string(),
+            trigger: "load_shed".to_string(),
+            from_closed: true,
+            to_closed: false,
+        });
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_seeds_replacement_node() {
+        let relays = vec![
+            Relay {
+                id: "r_grid".to_string(),
+                name: "Main Grid Tie".to_string(),
+                relay_type: RelayType::Grid,
+                priority: Priority::Critical,
+                amperage: 100.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let dir = std::env::temp_dir().join(format!("streetgrid-snapshot-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut old = EdgeNode::builder("node_01").relays(relays).build().unwrap();
+        old.audit = Some(AuditLog::open(Storage::open(dir.join("old"), Duration::ZERO).unwrap()).unwrap());
+        old.enter_island_mode().await;
+        let snapshot = old.take_snapshot();
+
+        // Replacement hardware starts from an empty config
+        let storage = Storage::open(dir.join("new"), Duration::ZERO).unwrap();
+        let mut replacement = EdgeNode::builder("node_01").build().unwrap();
+        replacement.audit = Some(AuditLog::open(storage.clone()).unwrap());
+        replacement.storage = Some(storage.clone());
+        replacement.restore_snapshot(snapshot).await;
+
+        assert_eq!(replacement.state, NodeState::Islanded);
+        assert_eq!(replacement.relays.len(), 1);
+        assert!(!replacement.relays[0].is_closed);
+        // Audit numbering continues past the old node's entries
+        assert!(replacement.audit.as_ref().unwrap().next_seq() > old.audit.as_ref().unwrap().next_seq());
+        // Relay metadata is picked up on the next boot
+        assert_eq!(crate::snapshot::load_relays(&storage).unwrap()[0].id, "r_grid");
+    }
+
+    #[tokio::test]
+    async fn test_current_anomaly_raises_alarm() {
+        use crate::events::NodeEvent;
+        use crate::hal::adc::mock::MockAdcSensor;
+
+        let mut sensor = MockAdcSensor::new(AdcConfig::default()).unwrap();
+        sensor.set_simulated_current(2, 19.0); // HVAC compressor stuck on
+
+        let mut node = EdgeNode::builder("test_node").power_sensor(Box::new(sensor)).build().unwrap();
+        node.ct_channels = HashMap::from([("r_hvac".to_string(), 2)]);
+        node.anomaly = AnomalyDetector::new(3, 4.0);
+        for _ in 0..3 {
+            node.anomaly.observe(2, 6.0);
+        }
+        let mut events = node.events.subscribe();
+
+        node.sample_circuits().await;
+
+        match events.try_recv().unwrap().event {
+            NodeEvent::Alarm { code, message } => {
+                assert_eq!(code, "current_anomaly");
+                assert!(message.contains("r_hvac"));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        // The reading also feeds the load profile (19 A at 120 V)
+        let hourly = node.load_profile.hourly_watts("r_hvac").unwrap();
+        assert!(hourly.iter().any(|&w| (w - 2280.0).abs() < 0.1));
+    }
+
+    #[tokio::test]
+    async fn test_brief_sags_are_ridden_through_and_recorded() {
+        use crate::clock::VirtualClock;
+        use crate::events::NodeEvent;
+        use crate::protection::ProtectionSettings;
+
+        let mut node = EdgeNode::builder("test_node")
+            .protection(ProtectionSettings { ride_through_secs: 10.0, ..Default::default() })
+            .build()
+            .unwrap();
+        let clock = VirtualClock::at(1_700_000_000);
+        node.set_clock(Arc::new(clock.clone()));
+        let mut events = node.events.subscribe();
+        let reading = |node: &mut EdgeNode, volts: f32| {
+            node.voltage_ref = volts;
+            clock.advance(Duration::from_secs(5));
+        };
+
+        // Five seconds at 98 V: no alert, but the sag is published once it is over
+        for volts in [98.0, 120.0] {
+            reading(&mut node, volts);
+            node.check_voltage().await;
+        }
+        assert_eq!(node.state, NodeState::Normal);
+        match events.try_recv().unwrap().event {
+            NodeEvent::VoltageSag { start, duration_secs, min_voltage, escalated } => {
+                assert_eq!((start, duration_secs, min_voltage, escalated), (1_700_000_005, 5.0, 98.0, false));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // Still low ten seconds on, it is a grid failure
+        for volts in [100.0, 95.0] {
+            reading(&mut node, volts);
+            node.check_voltage().await;
+            assert_eq!(node.state, NodeState::Normal);
+        }
+        reading(&mut node, 97.0);
+        node.check_voltage().await;
+        assert_eq!(node.state, NodeState::AlertSent);
+    }
+
+    #[tokio::test]
+    async fn test_three_phase_node_sheds_only_the_overloaded_phase() {
+        use crate::events::NodeEvent;
+        use crate::hal::adc::mock::MockAdcSensor;
+        use crate::phases::PhaseSensing;
+        use crate::protection::ProtectionSettings;
+        use crate::types::Phase;
+
+        let load = |id: &str, priority: Priority, amperage: f32, phase: Phase| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage,
+            is_closed: true,
+            community_criticality: 0,
+            phase: Some(phase),
+        };
+        let mut sensor = MockAdcSensor::new(AdcConfig { voltage_ref: 230.0, ..Default::default() }).unwrap();
+        sensor.set_simulated_current(0, 35.0);
+        sensor.set_simulated_current(1, 50.0);
+        sensor.set_simulated_current(2, 10.0);
+        sensor.set_simulated_voltage(3, 150.0); // Phase C has lost its supply upstream
+        let relays = vec![
+            load("r_lights", Priority::Low, 5.0, Phase::A),
+            load("r_oven", Priority::Medium, 16.0, Phase::B),
+            load("r_heatpump", Priority::Low, 20.0, Phase::B),
+        ];
+        let mut node = EdgeNode::builder("test_node")
+            .relays(relays)
+            .power_sensor(Box::new(sensor))
+            .voltage_ref(230.0)
+            .protection(ProtectionSettings { undervoltage_v: 200.0, debounce_readings: 1, ..Default::default() })
+            .build()
+            .unwrap();
+        let sensing = |channel, voltage_channel| PhaseSensing { current_channel: channel, voltage_channel, max_amps: Some(40.0) };
+        node.phase_monitor = Some(PhaseMonitor::new(BTreeMap::from([
+            (Phase::A, sensing(0, None)),
+            (Phase::B, sensing(1, None)),
+            (Phase::C, sensing(2, Some(3))),
+        ])));
+        let mut events = node.events.subscribe();
+
+        node.sample_phases().await;
+        let readings: Vec<(Phase, f32, f32)> = std::iter::from_fn(|| events.try_recv().ok())
+            .filter_map(|r| match r.event {
+                NodeEvent::PhaseMeasurement { phase, volts, amps } => Some((phase, volts, amps)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(readings, vec![(Phase::A, 230.0, 35.0), (Phase::B, 230.0, 50.0), (Phase::C, 150.0, 10.0)]);
+
+        // 10 A over on phase B: the heat pump goes, enough on its own; phase A is within its limit
+        node.enforce_phase_limits().await;
+        let closed: Vec<bool> = node.relays.iter().map(|r| r.is_closed).collect();
+        assert_eq!(closed, vec![true, true, false]);
+        assert_eq!(node.phase_monitor.as_ref().unwrap().shed(), ["r_heatpump".to_string()]);
+
+        // The dead phase is a grid failure, though the other two are healthy
+        node.check_voltage().await;
+        assert_eq!(node.state, NodeState::AlertSent);
+    }
+
+    #[tokio::test]
+    async fn test_power_factor_is_published_per_channel() {
+        use crate::events::NodeEvent;
+        use crate::hal::adc::mock::MockAdcSensor;
+        use crate::types::CtDirection;
+
+        let mut sensor = MockAdcSensor::new(AdcConfig::default()).unwrap();
+        sensor.set_simulated_current(0, 12.0);
+        sensor.set_simulated_current(2, 10.0);
+        sensor.set_simulated_power_factor(2, 0.6); // Well pump motor
+        let mut node = EdgeNode::builder("test_node").power_sensor(Box::new(sensor)).build().unwrap();
+        node.ct_channels = HashMap::from([("r_pump".to_string(), 2)]);
+        node.ct_directions = HashMap::from([(0, CtDirection::Reversed)]);
+        let mut events = node.events.subscribe();
+
+        node.sample_power_quality().await;
+        let readings: Vec<(u8, f32, f32, f32)> = std::iter::from_fn(|| events.try_recv().ok())
+            .filter_map(|r| match r.event {
+                NodeEvent::PowerQuality { channel, real_watts, reactive_var, power_factor, .. } => Some((channel, real_watts, reactive_var, power_factor)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(readings.len(), 2);
+        // The main clamp is fitted backwards, and sees a resistive load
+        assert_eq!(readings[0], (0, -1440.0, 0.0, 1.0));
+        // 1200 VA through the pump, of which 720 W does work
+        let (channel, real, reactive, power_factor) = readings[1];
+        assert_eq!((channel, power_factor), (2, 0.6));
+        assert!((real - 720.0).abs() < 0.01 && (reactive - 960.0).abs() < 0.01);
+    }
+
+    #[tokio::test]
+    async fn test_voltage_distortion_is_alarmed_once() {
+        use crate::events::NodeEvent;
+        use crate::hal::adc::mock::MockAdcSensor;
+
+        let mut sensor = MockAdcSensor::new(AdcConfig::default()).unwrap();
+        sensor.set_simulated_voltage_thd(0.12); // Island carried by a struggling inverter
+        let mut node = EdgeNode::builder("test_node").power_sensor(Box::new(sensor)).build().unwrap();
+        let mut events = node.events.subscribe();
+
+        node.check_distortion().await;
+        node.check_distortion().await;
+        let events: Vec<NodeEvent> = std::iter::from_fn(|| events.try_recv().ok()).map(|r| r.event).collect();
+        let thds: Vec<f32> = events.iter()
+            .filter_map(|e| match e { NodeEvent::VoltageDistortion { thd } => Some(*thd), _ => None })
+            .collect();
+        assert_eq!(thds.len(), 2);
+        assert!((thds[0] - 0.12).abs() < 0.01, "THD read {}", thds[0]);
+        let alarms = events.iter().filter(|e| matches!(e, NodeEvent::Alarm { code, .. } if code == "voltage_distortion")).count();
+        assert_eq!(alarms, 1);
+    }
+
+    #[tokio::test]
+    async fn test_arcing_circuit_is_opened_until_an_operator_closes_it() {
+        use crate::arc_fault::ArcFaultSettings;
+        use crate::audit::SignatureStatus;
+        use crate::comms::{ActivateRelayByIndex, IncomingCommand, ReceivedCommand};
+        use crate::events::NodeEvent;
+        use crate::hal::adc::mock::MockAdcSensor;
+
+        let shed = Relay {
+            id: "r_shed".to_string(),
+            name: "Garden Shed".to_string(),
+            relay_type: RelayType::Load,
+            priority: Priority::Low,
+            amperage: 15.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        };
+        let mut sensor = MockAdcSensor::new(AdcConfig::default()).unwrap();
+        sensor.set_simulated_current(1, 8.0);
+        sensor.set_simulated_arcing(1, true); // Frayed extension lead
+        let mut node = EdgeNode::builder("test_node")
+            .relays(vec![shed])
+            .power_sensor(Box::new(sensor))
+            .build()
+            .unwrap();
+        node.ct_channels = HashMap::from([("r_shed".to_string(), 1)]);
+        node.arc_detector = Some(ArcDetector::new(HashMap::from([("r_shed".to_string(), ArcFaultSettings::default())])));
+        let mut events = node.events.subscribe();
+
+        for _ in 0..2 {
+            node.sample_circuits().await;
+            node.check_arcs().await;
+        }
+        assert!(node.relays[0].is_closed);
+        node.sample_circuits().await;
+        node.check_arcs().await;
+        assert!(!node.relays[0].is_closed);
+        let alarm = std::iter::from_fn(|| events.try_recv().ok())
+            .find(|r| matches!(&r.event, NodeEvent::Alarm { code, .. } if code == "arc_fault"));
+        assert!(alarm.is_some());
+
+        // A black start step doesn't close it again; an operator does
+        let received = |step_id: &str| ReceivedCommand {
+            command: IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex { target_node_id: "test_node".to_string(), relay_index: 0, step_id: step_id.to_string() }),
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: None,
+            counter: 0,
+            co_signatures: None,
+            zone: None,
+        };
+        node.handle_command(received("bs_1")).await;
+        assert!(!node.relays[0].is_closed);
+        node.handle_command(received("")).await;
+        assert!(node.relays[0].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_hot_board_derates_the_relays_until_it_cools() {
+        use crate::audit::SignatureStatus;
+        use crate::comms::{ActivateRelayByIndex, IncomingCommand, ReceivedCommand};
+        use crate::events::NodeEvent;
+        use crate::thermal::ThermalSettings;
+
+        let dir = std::env::temp_dir().join(format!("streetgrid-thermal-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sensor = dir.join("temp");
+        let load = |id: &str, priority: Priority, amperage: f32| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        };
+        let relays = vec![load("r_fridge", Priority::Critical, 5.0), load("r_oven", Priority::Medium, 20.0), load("r_kiln", Priority::Low, 20.0)];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        node.thermal = Some(ThermalDerating::new(ThermalSettings {
+            sensor: sensor.to_string_lossy().into_owned(),
+            max_amps: 40.0,
+            derate_above_c: 60.0,
+            full_derate_c: 80.0,
+            derated_share: 0.5,
+            high_current_amps: 16.0,
+        }));
+        let mut events = node.events.subscribe();
+
+        // Halfway to full derating the relays may carry 30 A of the 45 A closed
+        std::fs::write(&sensor, "70000\n").unwrap();
+        node.check_temperature().await;
+        assert!(node.relays[0].is_closed && node.relays[1].is_closed && !node.relays[2].is_closed);
+        let derating = std::iter::from_fn(|| events.try_recv().ok())
+            .find_map(|r| match r.event { NodeEvent::ThermalDerating { derating, allowed_amps, .. } => Some((derating, allowed_amps)), _ => None });
+        assert_eq!(derating, Some((true, Some(30.0))));
+
+        // Still too warm to close a high-current load, even by hand
+        std::fs::write(&sensor, "58000\n").unwrap();
+        node.check_temperature().await;
+        node.handle_command(ReceivedCommand {
+            command: IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex { target_node_id: "test_node".to_string(), relay_index: 2, step_id: String::new() }),
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: None,
+            counter: 0,
+            co_signatures: None,
+            zone: None,
+        }).await;
+        assert!(!node.relays[2].is_closed);
+
+        // Cooled well below the threshold, the kiln is put back
+        std::fs::write(&sensor, "50000\n").unwrap();
+        node.check_temperature().await;
+        assert!(node.relays[2].is_closed);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_island_gives_up_loads_by_battery_reserve_tier() {
+        use crate::events::NodeEvent;
+        use crate::reserve::{ReserveSettings, ReservePolicy};
+
+        let load = |id: &str, priority: Priority| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage: 10.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        };
+        let relays = vec![load("r_oxygen", Priority::Critical), load("r_fridge", Priority::High), load("r_hvac", Priority::Medium), load("r_tv", Priority::Low)];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        node.reserve = Some(ReservePolicy::new(ReserveSettings::default()));
+        let mut events = node.events.subscribe();
+        let closed = |node: &EdgeNode| node.relays.iter().filter(|r| r.is_closed).map(|r| r.id.clone()).collect::<Vec<_>>();
+
+        // A low battery on the grid sheds nothing
+        node.battery_soc = 0.35;
+        node.check_reserve().await;
+        assert_eq!(closed(&node).len(), 4);
+
+        node.state = NodeState::Islanded;
+        node.check_reserve().await;
+        assert_eq!(closed(&node), ["r_oxygen", "r_fridge"]);
+        node.battery_soc = 0.05;
+        node.check_reserve().await;
+        assert_eq!(closed(&node), ["r_oxygen"]);
+        let alarms: Vec<String> = std::iter::from_fn(|| events.try_recv().ok())
+            .filter_map(|r| match r.event { NodeEvent::Alarm { code, .. } => Some(code), _ => None })
+            .collect();
+        assert_eq!(alarms, ["battery_exhausted"]);
+        assert_eq!(node.reserve.as_ref().unwrap().tier().map(|t| t.as_str()), Some("prepare_shutdown"));
+
+        // The grid returns and everything is put back
+        node.state = NodeState::Normal;
+        node.check_reserve().await;
+        assert_eq!(closed(&node).len(), 4);
+        assert_eq!(node.reserve.as_ref().unwrap().tier(), None);
+    }
+
+    #[tokio::test]
+    async fn test_welded_and_failed_open_relays_are_taken_out_of_use() {
+        use crate::audit::SignatureStatus;
+        use crate::comms::{ActivateRelayByIndex, IncomingCommand, ReceivedCommand};
+        use crate::events::NodeEvent;
+        use crate::hal::adc::mock::MockAdcSensor;
+        use crate::hal::gpio::mock::MockRelayDriver;
+
+        let load = |id: &str, is_closed: bool| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority: Priority::Low,
+            amperage: 10.0,
+            is_closed,
+            community_criticality: 0,
+            phase: None,
+        };
+        let pins = vec![
+            RelayPin { relay_id: "r_hvac".to_string(), gpio_pin: 17, active_low: false, feedback_pin: Some(27) },
+            RelayPin { relay_id: "r_fridge".to_string(), gpio_pin: 22, active_low: false, feedback_pin: None },
+        ];
+        let mut driver = MockRelayDriver::new(&pins).unwrap();
+        driver.set_stuck(17, true);
+        let mut sensor = MockAdcSensor::new(AdcConfig::default()).unwrap();
+        sensor.set_simulated_current(1, 0.0);
+        let relay_pins = HashMap::from([("r_hvac".to_string(), 17), ("r_fridge".to_string(), 22)]);
+        let relays = vec![load("r_hvac", false), load("r_fridge", true)];
+        let mut node = EdgeNode::builder("test_node")
+            .relays(relays)
+            .relay_pins(relay_pins)
+            .relay_driver(Box::new(driver))
+            .power_sensor(Box::new(sensor))
+            .build()
+            .unwrap();
+        node.ct_channels = HashMap::from([("r_fridge".to_string(), 1)]);
+        node.relay_health = RelayHealth::new(vec!["r_fridge".to_string()]);
+        let mut events = node.events.subscribe();
+
+        for _ in 0..3 {
+            node.sample_circuits().await;
+            node.check_relays().await;
+        }
+        let failures: Vec<(String, String)> = std::iter::from_fn(|| events.try_recv().ok())
+            .filter_map(|r| match r.event {
+                NodeEvent::RelayFailed { relay_id, failure, .. } => Some((relay_id, failure)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(failures, vec![("r_hvac".to_string(), "welded".to_string()), ("r_fridge".to_string(), "failed_open".to_string())]);
+        // Opened so nothing counts on it, and neither closes again until an operator says so
+        assert!(!node.relays[1].is_closed);
+        let received = |relay_index: u32, step_id: &str| ReceivedCommand {
+            command: IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex { target_node_id: "test_node".to_string(), relay_index, step_id: step_id.to_string() }),
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: None,
+            counter: 0,
+            co_signatures: None,
+            zone: None,
+        };
+        node.handle_command(received(1, "bs_1")).await;
+        assert!(!node.relays[1].is_closed);
+        node.handle_command(received(1, "")).await;
+        assert!(node.relays[1].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_exporting_circuit_counts_against_the_budget() {
+        use crate::events::NodeEvent;
+        use crate::hal::adc::mock::MockAdcSensor;
+        use crate::types::CtDirection;
+
+        let load = |id: &str, priority: Priority, amperage: f32| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        };
+        let mut sensor = MockAdcSensor::new(AdcConfig::default()).unwrap();
+        sensor.set_simulated_current(0, 8.0); // Main clamp, fitted backwards
+        sensor.set_simulated_current(1, -5.0); // Garage sub-panel with microinverters
+        sensor.set_simulated_current(2, 9.0);
+        let relays = vec![load("r_garage", Priority::Low, 20.0), load("r_hvac", Priority::Medium, 10.0)];
+        let mut node = EdgeNode::builder("test_node").relays(relays).power_sensor(Box::new(sensor)).build().unwrap();
+        node.ct_channels = HashMap::from([("r_garage".to_string(), 1), ("r_hvac".to_string(), 2)]);
+        node.ct_directions = HashMap::from([(0, CtDirection::Reversed)]);
+        let mut events = node.events.subscribe();
+
+        node.check_voltage().await;
+        match events.try_recv().unwrap().event {
+            NodeEvent::Measurement { watts, .. } => assert_eq!(watts, -960.0),
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // Rated, the two draw 3600 W; measured, the garage gives back 600 W
+        node.sample_circuits().await;
+        node.power_budget = Some(1000.0);
+        node.enforce_power_budget().await;
+        assert!(node.relays.iter().all(|r| r.is_closed));
+        node.power_budget = Some(500.0);
+        node.enforce_power_budget().await;
+        assert_eq!((node.relays[0].is_closed, node.relays[1].is_closed), (true, false));
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_commands_rejected_with_keyring() {
+        use crate::comms::{IncomingCommand, LoadShed, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+
+        let relays = vec![
+            Relay {
+                id: "r_aux".to_string(),
+                name: "Living Room Outlets".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        node.keyring = Some(Arc::new(Mutex::new(Keyring::new(1, [3; 32]))));
+        let shed = || IncomingCommand::LoadShed(LoadShed { target_node_id: "test_node".to_string(), shed_load: true });
+
+        node.handle_command(ReceivedCommand { command: shed(), signature: SignatureStatus::Unsigned, session_id: None, sender_id: None, counter: 0, co_signatures: None, zone: None }).await;
+        node.handle_command(ReceivedCommand { command: shed(), signature: SignatureStatus::Invalid, session_id: None, sender_id: None, counter: 0, co_signatures: None, zone: None }).await;
+        assert!(node.relays[0].is_closed);
+
+        node.handle_command(ReceivedCommand { command: shed(), signature: SignatureStatus::Valid, session_id: None, sender_id: None, counter: 0, co_signatures: None, zone: None }).await;
+        assert!(!node.relays[0].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_joining_node_only_accepts_join() {
+        use crate::comms::{IncomingCommand, JoinAccept, LoadShed, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+        use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+        use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+
+        let relays = vec![
+            Relay {
+                id: "r_aux".to_string(),
+                name: "Living Room Outlets".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let mut node = EdgeNode::builder("factory_01").relays(relays).build().unwrap();
+        node.state = NodeState::Joining;
+        node.provisioning_token = Some("tok-123".to_string());
+        let shed = || IncomingCommand::LoadShed(LoadShed { target_node_id: "node_07".to_string(), shed_load: true });
+
+        node.handle_command(ReceivedCommand { command: shed(), signature: SignatureStatus::Unsigned, session_id: None, sender_id: None, counter: 0, co_signatures: None, zone: None }).await;
+        assert!(node.relays[0].is_closed);
+
+        // Orchestrator side: wrap the mesh key under the token-derived key
+        let mut aad = 4u32.to_be_bytes().to_vec();
+        aad.extend_from_slice(b"node_07");
+        let nonce = [5u8; 12];
+        let wrapped = ChaCha20Poly1305::new_from_slice(&crate::keys::join_key("tok-123")).unwrap()
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &[8u8; 32], aad: &aad })
+            .unwrap();
+        let accept = JoinAccept {
+            target_node_id: "factory_01".to_string(),
+            assigned_node_id: "node_07".to_string(),
+            key_epoch: 4,
+            wrapped_key: wrapped,
+            nonce: nonce.to_vec(),
+            ..Default::default()
+        };
+        node.handle_command(ReceivedCommand { command: IncomingCommand::JoinAccept(accept), signature: SignatureStatus::Unsigned, session_id: None, sender_id: None, counter: 0, co_signatures: None, zone: None }).await;
+
+        assert_eq!(node.state, NodeState::Normal);
+        assert_eq!(node.id, "node_07");
+        assert_eq!(node.keyring.as_ref().unwrap().lock().unwrap().current_epoch(), 4);
+
+        // Operational commands now need a valid signature
+        node.handle_command(ReceivedCommand { command: shed(), signature: SignatureStatus::Valid, session_id: None, sender_id: None, counter: 0, co_signatures: None, zone: None }).await;
+        assert!(!node.relays[0].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_command_flood_locks_out_all_but_safety_opens() {
+        use crate::comms::{IncomingCommand, ActivateRelayByIndex, LoadShed, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+        use crate::events::NodeEvent;
+
+        let relays = vec![
+            Relay {
+                id: "r_aux".to_string(),
+                name: "Living Room Outlets".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        node.limiter = CommandLimiter::new(2, HashMap::new(), Duration::from_secs(300));
+        let mut events = node.events.subscribe();
+        let received = |command| ReceivedCommand { command, signature: SignatureStatus::Unsigned, session_id: None, sender_id: None, counter: 0, co_signatures: None, zone: None };
+        let activate = || IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex { target_node_id: "test_node".to_string(), relay_index: 0, step_id: String::new() });
+
+        for _ in 0..3 {
+            node.handle_command(received(activate())).await;
+        }
+        let alarm = std::iter::from_fn(|| events.try_recv().ok())
+            .find(|r| matches!(&r.event, NodeEvent::Alarm { code, .. } if code == "command_flood"));
+        assert!(alarm.is_some());
+
+        // Shedding still works during lockout, but closing again does not
+        node.handle_command(received(IncomingCommand::LoadShed(LoadShed { target_node_id: "test_node".to_string(), shed_load: true }))).await;
+        assert!(!node.relays[0].is_closed);
+        node.handle_command(received(activate())).await;
+        assert!(!node.relays[0].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_commands_for_other_nodes_do_not_count_towards_the_rate_limit() {
+        use crate::comms::{IncomingCommand, ActivateRelayByIndex, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+
+        let relays = vec![
+            Relay {
+                id: "r_aux".to_string(),
+                name: "Living Room Outlets".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: false,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        node.limiter = CommandLimiter::new(2, HashMap::new(), Duration::from_secs(300));
+        let received = |command| ReceivedCommand { command, signature: SignatureStatus::Unsigned, session_id: None, sender_id: None, counter: 0, co_signatures: None, zone: None };
+        let activate = |target: &str| IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex { target_node_id: target.to_string(), relay_index: 0, step_id: String::new() });
+
+        // A fleet black start closing sources street by street, overheard
+        for _ in 0..20 {
+            node.handle_command(received(activate("other_node"))).await;
+        }
+        node.handle_command(received(activate("test_node"))).await;
+        assert!(node.relays[0].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_firmware_downloads_in_the_background() {
+        use crate::comms::streetgrid::neighborhood_message::Payload;
+        use crate::comms::{CommandResult, FirmwareUpdate, IncomingCommand, MockCommunication, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+        use crate::ota::ImageVerifier;
+
+        let dir = std::env::temp_dir().join(format!("streetgrid-ota-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("fw.img");
+        std::fs::write(&image, b"not firmware").unwrap();
+        let link = Arc::new(MockCommunication::default());
+        let mut node = EdgeNode::builder("test_node")
+            .client(OrchestratorClient::new(link.clone()))
+            .build()
+            .unwrap();
+        let verifier = ImageVerifier::new(&[hex::encode(ed25519_dalek::SigningKey::from_bytes(&[7; 32]).verifying_key().to_bytes())], "rpi4", "0.1.0").unwrap();
+        node.ota = Some(OtaUpdater::new(verifier, dir.join("streetgrid-firmware")));
+        let update = || ReceivedCommand {
+            command: IncomingCommand::FirmwareUpdate(FirmwareUpdate {
+                target_node_id: "test_node".to_string(),
+                url: format!("file://{}", image.display()),
+            }),
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: None,
+            counter: 0,
+            co_signatures: None,
+            zone: None,
+        };
+        let results = || -> Vec<CommandResult> {
+            link.sent().into_iter()
+                .filter_map(|msg| match msg.payload {
+                    Some(Payload::CommandResult(result)) => Some(result),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // Accepted at once; a second update waits for the first to finish
+        node.handle_command(update()).await;
+        node.handle_command(update()).await;
+        let results = results();
+        assert!(results[0].ok);
+        assert!(!results[1].ok);
+        assert_eq!(results[1].error_code, "wrong_state");
+
+        let mut status = None;
+        for _ in 0..50 {
+            node.run_task(Task::Messages).await;
+            status = link.sent().into_iter().find_map(|msg| match msg.payload {
+                Some(Payload::FirmwareStatus(status)) => Some(status),
+                _ => None,
+            });
+            if status.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let status = status.expect("firmware status once the download finished");
+        assert!(!status.installed);
+        assert_eq!(status.reason, "not a StreetGrid firmware image");
+        assert!(!dir.join("streetgrid-firmware").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_operator_commands_need_live_session() {
+        use crate::comms::{ActivateRelayByIndex, IncomingCommand, ReceivedCommand, SessionGrant, SessionRevoke};
+        use crate::audit::SignatureStatus;
+        use crate::session::SessionTable;
+
+        let relays = vec![
+            Relay {
+                id: "r_aux".to_string(),
+                name: "Living Room Outlets".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: false,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        node.sessions = Some(SessionTable::default());
+        let received = |command, session_id: Option<&str>| ReceivedCommand {
+            command,
+            signature: SignatureStatus::Unsigned,
+            session_id: session_id.map(str::to_string),
+            sender_id: None,
+            counter: 0,
+            co_signatures: None,
+            zone: None,
+        };
+        let activate = || IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex { target_node_id: "test_node".to_string(), relay_index: 0, step_id: String::new() });
+
+        // No session yet
+        node.handle_command(received(activate(), Some("op-1"))).await;
+        assert!(!node.relays[0].is_closed);
+
+        let grant = SessionGrant {
+            session_id: "op-1".to_string(),
+            target_node_id: "test_node".to_string(),
+            scopes: vec!["activate_relay_by_index".to_string()],
+            expires_at: chrono::Utc::now().timestamp() + 600,
+        };
+        node.handle_command(received(IncomingCommand::SessionGrant(grant), None)).await;
+        node.handle_command(received(activate(), None)).await;
+        assert!(!node.relays[0].is_closed);
+        node.handle_command(received(activate(), Some("op-1"))).await;
+        assert!(node.relays[0].is_closed);
+
+        node.relays[0].is_closed = false;
+        let revoke = SessionRevoke { session_id: "op-1".to_string(), target_node_id: "test_node".to_string() };
+        node.handle_command(received(IncomingCommand::SessionRevoke(revoke), None)).await;
+        node.handle_command(received(activate(), Some("op-1"))).await;
+        assert!(!node.relays[0].is_closed);
+    }
+
+    #[test]
+    fn test_hardware_binding() {
+        let mut node = EdgeNode::builder("test_node").build().unwrap();
+        node.hardware_id = Some("10000000a1b2c3d4".to_string());
+        assert!(node.hardware_binding_ok());
+
+        node.bound_hardware_id = Some("10000000A1B2C3D4".to_string());
+        assert!(node.hardware_binding_ok());
+
+        // SD card moved to another board
+        node.hardware_id = Some("10000000deadbeef".to_string());
+        assert!(!node.hardware_binding_ok());
+        node.hardware_id = None;
+        assert!(!node.hardware_binding_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tamper_opens_to_safe_state() {
+        use crate::hal::tamper::mock::MockTamperSwitch;
+        use crate::events::NodeEvent;
+        use std::sync::atomic::Ordering;
+
+        let relays = vec![
+            Relay {
+                id: "r_crit".to_string(),
+                name: "Fridge".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Critical,
+                amperage: 5.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+            Relay {
+                id: "r_aux".to_string(),
+                name: "Living Room Outlets".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        let switch = MockTamperSwitch::new();
+        let enclosure = switch.handle();
+        node.tamper = Some(Box::new(switch));
+        node.tamper_safe_state = HashMap::from([("r_aux".to_string(), false)]);
+        let mut events = node.events.subscribe();
+
+        node.check_tamper().await;
+        assert!(node.relays[1].is_closed);
+
+        enclosure.store(true, Ordering::Relaxed);
+        node.check_tamper().await;
+        assert!(node.relays[0].is_closed);
+        assert!(!node.relays[1].is_closed);
+        let alarm = std::iter::from_fn(|| events.try_recv().ok())
+            .find(|r| matches!(&r.event, NodeEvent::Alarm { code, .. } if code == "tamper"));
+        assert!(alarm.is_some());
+
+        // Only the transition is reported
+        node.relays[1].is_closed = true;
+        node.check_tamper().await;
+        assert!(node.relays[1].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_emergency_stop_latches_until_released_and_reset() {
+        use crate::hal::estop::mock::MockEmergencyStop;
+        use crate::hal::gpio::mock::MockRelayDriver;
+        use crate::comms::{ActivateRelayByIndex, FaultReset, IncomingCommand, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+        use crate::estop::{EStopWatch, ESTOP_POLL_INTERVAL};
+        use crate::node::Task;
+        use crate::events::NodeEvent;
+        use std::sync::atomic::Ordering;
+
+        let relays = vec![
+            Relay {
+                id: "r_grid".to_string(),
+                name: "Main Grid Tie".to_string(),
+                relay_type: RelayType::Grid,
+                priority: Priority::Critical,
+                amperage: 100.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+            Relay {
+                id: "r_aux".to_string(),
+                name: "Living Room Outlets".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let pins = HashMap::from([("r_grid".to_string(), 17), ("r_aux".to_string(), 27)]);
+        let driver = MockRelayDriver::new(&[]).unwrap();
+        let mut node = EdgeNode::builder("test_node")
+            .relays(relays)
+            .relay_pins(pins)
+            .relay_driver(Box::new(driver))
+            .build()
+            .unwrap();
+        let driver = node.relay_driver.as_ref().unwrap().shared();
+        driver.lock().unwrap().set_relay(17, true).unwrap();
+        driver.lock().unwrap().set_relay(27, true).unwrap();
+        let input = MockEmergencyStop::new();
+        let button = input.handle();
+        node.estop = Some(EStopWatch::spawn(Box::new(input), driver.clone(), vec![17, 27]));
+        let mut events = node.events.subscribe();
+        let command = |command| ReceivedCommand {
+            command,
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: None,
+            counter: 0,
+            co_signatures: None,
+            zone: None,
+        };
+        let activate = || command(IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex {
+            target_node_id: "test_node".to_string(),
+            relay_index: 1,
+            step_id: String::new(),
+        }));
+        let reset = || command(IncomingCommand::FaultReset(FaultReset { target_node_id: "test_node".to_string() }));
+
+        // The contacts open from the watch's thread, before the event loop hears of it
+        button.store(true, Ordering::SeqCst);
+        std::thread::sleep(ESTOP_POLL_INTERVAL * 5);
+        assert!(!driver.lock().unwrap().get_relay(17).unwrap());
+        assert!(!driver.lock().unwrap().get_relay(27).unwrap());
+        node.run_task(Task::Tamper).await;
+        assert_eq!(node.state, NodeState::Faulted);
+        assert!(node.relays.iter().all(|r| !r.is_closed));
+        let alarm = std::iter::from_fn(|| events.try_recv().ok())
+            .find(|r| matches!(&r.event, NodeEvent::Alarm { code, .. } if code == "emergency_stop"));
+        assert!(alarm.is_some());
+
+        // Latched: nothing closes, and nothing else moves the node out of Faulted
+        node.handle_command(activate()).await;
+        assert!(!node.relays[1].is_closed);
+        node.handle_command(reset()).await;
+        assert_eq!(node.state, NodeState::Faulted);
+
+        // Released and reset, loads come back only as they are asked for
+        button.store(false, Ordering::SeqCst);
+        std::thread::sleep(ESTOP_POLL_INTERVAL * 5);
+        node.run_task(Task::Tamper).await;
+        assert_eq!(node.state, NodeState::Faulted);
+        node.handle_command(reset()).await;
+        assert_eq!(node.state, NodeState::Normal);
+        assert!(!node.relays[1].is_closed);
+        node.handle_command(activate()).await;
+        assert!(node.relays[1].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_tripped_emergency_stop_holds_relays_open_before_latching_and_through_a_restore() {
+        use crate::hal::estop::mock::MockEmergencyStop;
+        use crate::hal::gpio::mock::MockRelayDriver;
+        use crate::comms::{ActivateRelayByIndex, IncomingCommand, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+        use crate::estop::{EStopWatch, ESTOP_POLL_INTERVAL};
+        use crate::node::Task;
+        use std::sync::atomic::Ordering;
+
+        let relays = vec![
+            Relay {
+                id: "r_grid".to_string(),
+                name: "Main Grid Tie".to_string(),
+                relay_type: RelayType::Grid,
+                priority: Priority::Critical,
+                amperage: 100.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+            Relay {
+                id: "r_aux".to_string(),
+                name: "Living Room Outlets".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: false,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let pins = HashMap::from([("r_grid".to_string(), 17), ("r_aux".to_string(), 27)]);
+        let mut node = EdgeNode::builder("test_node")
+            .relays(relays)
+            .relay_pins(pins)
+            .relay_driver(Box::new(MockRelayDriver::new(&[]).unwrap()))
+            .build()
+            .unwrap();
+        let driver = node.relay_driver.as_ref().unwrap().shared();
+        driver.lock().unwrap().set_relay(17, true).unwrap();
+        let input = MockEmergencyStop::new();
+        let button = input.handle();
+        node.estop = Some(EStopWatch::spawn(Box::new(input), driver.clone(), vec![17, 27]));
+        let mut snapshot = node.take_snapshot();
+        snapshot.relays[1].is_closed = true;
+
+        // Tripped, but the event loop has yet to latch Faulted: still nothing closes
+        button.store(true, Ordering::SeqCst);
+        std::thread::sleep(ESTOP_POLL_INTERVAL * 5);
+        node.handle_command(ReceivedCommand {
+            command: IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex {
+                target_node_id: "test_node".to_string(),
+                relay_index: 1,
+                step_id: String::new(),
+            }),
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: None,
+            counter: 0,
+            co_signatures: None,
+            zone: None,
+        }).await;
+        assert_eq!(node.state, NodeState::Normal);
+        assert!(!node.relays[1].is_closed);
+        assert!(!driver.lock().unwrap().get_relay(27).unwrap());
+
+        // Latched, a snapshot taken in Normal with everything closed leaves it all open
+        node.run_task(Task::Tamper).await;
+        assert_eq!(node.state, NodeState::Faulted);
+        node.restore_snapshot(snapshot).await;
+        assert_eq!(node.state, NodeState::Faulted);
+        assert!(node.relays.iter().all(|r| !r.is_closed));
+        assert!(!driver.lock().unwrap().get_relay(17).unwrap());
+        assert!(!driver.lock().unwrap().get_relay(27).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_replayed_command_rejected_after_reboot() {
+        use crate::comms::{ActivateRelayByIndex, IncomingCommand, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+        use crate::replay::{ReplayGuard, REPLAY_KEY};
+
+        let dir = std::env::temp_dir().join(format!("streetgrid-replay-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let relays = vec![
+            Relay {
+                id: "r_aux".to_string(),
+                name: "Living Room Outlets".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: false,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let captured = || ReceivedCommand {
+            command: IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex { target_node_id: "test_node".to_string(), relay_index: 0, step_id: String::new() }),
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: Some("orchestrator".to_string()),
+            counter: 7,
+            co_signatures: None,
+            zone: None,
+        };
+
+        let mut node = EdgeNode::builder("test_node").relays(relays.clone()).build().unwrap();
+        node.storage = Some(Storage::open(&dir, Duration::from_secs(60)).unwrap());
+        node.replay = Some(ReplayGuard::default());
+        node.handle_command(captured()).await;
+        assert!(node.relays[0].is_closed);
+        node.relays[0].is_closed = false;
+        node.handle_command(captured()).await;
+        assert!(!node.relays[0].is_closed);
+
+        // Counters survive a reboot without waiting for the periodic flush
+        let storage = Storage::open(&dir, Duration::from_secs(60)).unwrap();
+        let mut rebooted = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        rebooted.replay = storage.get_json::<ReplayGuard>(REPLAY_KEY).unwrap();
+        assert!(rebooted.replay.is_some());
+        rebooted.handle_command(captured()).await;
+        assert!(!rebooted.relays[0].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_grid_disconnect_needs_two_authorities() {
+        use crate::comms::{Approval, DisconnectGrid, IncomingCommand, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+        use crate::multisig::{CoSignatures, MultiSigPolicy};
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let relays = vec![
+            Relay {
+                id: "r_grid".to_string(),
+                name: "Main Grid Tie".to_string(),
+                relay_type: RelayType::Grid,
+                priority: Priority::Critical,
+                amperage: 100.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let utility = SigningKey::from_bytes(&[1; 32]);
+        let municipality = SigningKey::from_bytes(&[2; 32]);
+        let signers = [&utility, &municipality].map(|k| hex::encode(k.verifying_key().as_bytes()));
+        let mut node = EdgeNode::builder("test_node")
+            .relays(relays)
+            .mesh_type(MeshType::GovernmentSanctioned)
+            .build()
+            .unwrap();
+        node.multisig = Some(MultiSigPolicy::new(&signers, 2, crate::multisig::default_commands(&node.mesh_type)).unwrap());
+
+        let message = b"disconnect test_node".to_vec();
+        let received = |keys: &[&SigningKey]| ReceivedCommand {
+            command: IncomingCommand::DisconnectGrid(DisconnectGrid { target_node_id: "test_node".to_string() }),
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: None,
+            counter: 0,
+            co_signatures: Some(CoSignatures {
+                message: message.clone(),
+                approvals: keys.iter()
+                    .map(|k| Approval { public_key: k.verifying_key().to_bytes().to_vec(), signature: k.sign(&message).to_bytes().to_vec() })
+                    .collect(),
+            }),
+            zone: None,
+        };
+
+        node.handle_command(received(&[&utility])).await;
+        assert!(node.relays[0].is_closed);
+
+        node.handle_command(received(&[&utility, &municipality])).await;
+        assert!(!node.relays[0].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_energy_exchange_is_metered_from_the_clamps() {
+        use crate::clock::VirtualClock;
+        use crate::energy::EnergyLedger;
+
+        let relays = vec![Relay {
+            id: "r_heat".to_string(),
+            name: "Heater".to_string(),
+            relay_type: RelayType::Load,
+            priority: Priority::Medium,
+            amperage: 10.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        }];
+        let clock = VirtualClock::at(1_700_000_000);
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        node.set_clock(Arc::new(clock.clone()));
+        node.energy = Some(EnergyLedger::default());
+        node.set_state(NodeState::Islanded);
+        // Rated for 10 A, but the thermostat has it ticking over
+        node.circuit_watts.insert("r_heat".to_string(), 400.0);
+
+        node.record_energy().await;
+        clock.advance(Duration::from_secs(crate::energy::ENTRY_PERIOD_SECS as u64));
+        node.record_energy().await;
+        let entries = node.energy.as_ref().unwrap().since(0);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].imported_wh, 100.0);
+    }
+
+    #[tokio::test]
+    async fn test_islanding_and_snapshots_that_open_the_grid_need_approval() {
+        use crate::comms::{Approval, EnterIsland, IncomingCommand, ReceivedCommand, SnapshotData, SnapshotRestore};
+        use crate::audit::SignatureStatus;
+        use crate::multisig::{CoSignatures, MultiSigPolicy};
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let relays = vec![
+            Relay {
+                id: "r_grid".to_string(),
+                name: "Main Grid Tie".to_string(),
+                relay_type: RelayType::Grid,
+                priority: Priority::Critical,
+                amperage: 100.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let utility = SigningKey::from_bytes(&[1; 32]);
+        let municipality = SigningKey::from_bytes(&[2; 32]);
+        let signers = [&utility, &municipality].map(|k| hex::encode(k.verifying_key().as_bytes()));
+        let build = || {
+            let mut node = EdgeNode::builder("test_node")
+                .relays(relays.clone())
+                .mesh_type(MeshType::GovernmentSanctioned)
+                .build()
+                .unwrap();
+            // Behind a MID that hasn't isolated, so islanding would open the tie itself
+            node.mid_id = Some("mid_t1".to_string());
+            node.multisig = Some(MultiSigPolicy::new(&signers, 2, crate::multisig::default_commands(&node.mesh_type)).unwrap());
+            node
+        };
+        let message = b"test_node".to_vec();
+        let received = |command, keys: &[&SigningKey]| ReceivedCommand {
+            command,
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: None,
+            counter: 0,
+            co_signatures: Some(CoSignatures {
+                message: message.clone(),
+                approvals: keys.iter()
+                    .map(|k| Approval { public_key: k.verifying_key().to_bytes().to_vec(), signature: k.sign(&message).to_bytes().to_vec() })
+                    .collect(),
+            }),
+            zone: None,
+        };
+        let island = || IncomingCommand::EnterIsland(EnterIsland { target_node_id: "test_node".to_string() });
+
+        let mut node = build();
+        node.handle_command(received(island(), &[&utility])).await;
+        assert!(node.relays[0].is_closed);
+        assert_eq!(node.state, NodeState::Normal);
+        node.handle_command(received(island(), &[&utility, &municipality])).await;
+        assert!(!node.relays[0].is_closed);
+
+        // A snapshot with the tie open
+        let mut snapshot = build().take_snapshot();
+        snapshot.relays[0].is_closed = false;
+        let restore = || IncomingCommand::SnapshotRestore(SnapshotRestore {
+            target_node_id: "test_node".to_string(),
+            snapshot: Some(SnapshotData {
+                node_id: "test_node".to_string(),
+                format_version: SNAPSHOT_FORMAT,
+                payload: snapshot.to_bytes().unwrap(),
+                ..Default::default()
+            }),
+        });
+        let mut node = build();
+        node.handle_command(received(restore(), &[&utility])).await;
+        assert!(node.relays[0].is_closed);
+        node.handle_command(received(restore(), &[&utility, &municipality])).await;
+        assert!(!node.relays[0].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_timers_follow_the_virtual_clock() {
+        use crate::clock::VirtualClock;
+        use crate::comms::{IncomingCommand, ActivateRelayByIndex, ReceivedCommand};
+        use crate::audit::SignatureStatus;
+
+        let relays = vec![
+            Relay {
+                id: "r_pool".to_string(),
+                name: "Pool Pump".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Low,
+                amperage: 10.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        let clock = VirtualClock::at(1_700_000_000);
+        node.set_clock(Arc::new(clock.clone()));
+
+        // A demand-response event starts and ends when the clock says, not the wall
+        let start = clock.unix() + 600;
+        node.demand_response.accept("evt_1", Priority::Low, start, start + 1800, None, None);
+        node.run_demand_response().await;
+        assert!(node.relays[0].is_closed);
+        clock.advance(Duration::from_secs(600));
+        node.run_demand_response().await;
+        assert!(!node.relays[0].is_closed);
+        clock.advance(Duration::from_secs(1800));
+        node.run_demand_response().await;
+        assert!(node.relays[0].is_closed);
+
+        // And a command flood lockout runs out without waiting five minutes
+        node.limiter = CommandLimiter::new(1, HashMap::new(), Duration::from_secs(300));
+        node.relays[0].is_closed = false;
+        let received = || ReceivedCommand {
+            command: IncomingCommand::ActivateRelayByIndex(ActivateRelayByIndex { target_node_id: "test_node".to_string(), relay_index: 0, step_id: String::new() }),
+            signature: SignatureStatus::Unsigned, session_id: None, sender_id: None, counter: 0, co_signatures: None, zone: None,
+        };
+        node.handle_command(received()).await;
+        node.relays[0].is_closed = false;
+        node.handle_command(received()).await;
+        node.handle_command(received()).await;
+        assert!(!node.relays[0].is_closed);
+        clock.advance(Duration::from_secs(301));
+        node.handle_command(received()).await;
+        assert!(node.relays[0].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_forecast_sheds_ahead_of_a_demand_response_cap() {
+        use crate::clock::VirtualClock;
+
+        let load = |id: &str, priority: Priority, amperage: f32| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        };
+        let relays = vec![
+            load("r_fridge", Priority::Critical, 5.0),
+            load("r_hvac", Priority::High, 20.0),
+            load("r_pool", Priority::Medium, 10.0),
+            load("r_tv", Priority::Low, 5.0),
+        ];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        let clock = VirtualClock::at(1_700_000_000);
+        node.set_clock(Arc::new(clock.clone()));
+        node.forecaster = Some(Forecaster::default());
+        node.run_forecast().await;
+        assert!(node.relays.iter().all(|r| r.is_closed));
+
+        // The event sheds the TV; unmetered, the rest are forecast at their rating,
+        // 4200 W against a 3500 W cap, so the pool pump goes too before anything trips
+        let start = clock.unix();
+        node.demand_response.accept("evt_1", Priority::Low, start, start + 1800, Some(3500.0), None);
+        node.run_demand_response().await;
+        node.run_forecast().await;
+        let closed = |node: &EdgeNode| node.relays.iter().filter(|r| r.is_closed).map(|r| r.id.as_str()).collect::<Vec<_>>().join(",");
+        assert_eq!(closed(&node), "r_fridge,r_hvac");
+
+        // Both come back when the event ends
+        clock.advance(Duration::from_secs(1800));
+        node.run_demand_response().await;
+        node.run_forecast().await;
+        assert_eq!(closed(&node), "r_fridge,r_hvac,r_pool,r_tv");
+    }
+
+    #[tokio::test]
+    async fn test_round_robin_event_takes_each_circuit_off_in_turn() {
+        use crate::clock::VirtualClock;
+        use crate::shedding::{ShedStrategy, ROTATION_SECS};
+
+        let load = |id: &str, priority: Priority, amperage: f32| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        };
+        let relays = vec![
+            load("r_fridge", Priority::Critical, 5.0),
+            load("r_dryer", Priority::Low, 10.0),
+            load("r_heater", Priority::Low, 10.0),
+            load("r_pool", Priority::Low, 10.0),
+        ];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        let clock = VirtualClock::at(1_700_000_000);
+        node.set_clock(Arc::new(clock.clone()));
+        let open = |node: &EdgeNode| node.relays.iter().filter(|r| !r.is_closed).map(|r| r.id.clone()).collect::<Vec<_>>();
+
+        // 4200 W against a 3000 W cap: one 1200 W circuit off at a time, a different one each rotation
+        let start = clock.unix();
+        node.demand_response.accept("evt_1", Priority::Low, start, start + 4 * ROTATION_SECS, Some(3000.0), Some(ShedStrategy::RoundRobin));
+        let mut turns = Vec::new();
+        for _ in 0..3 {
+            node.run_demand_response().await;
+            let off = open(&node);
+            assert_eq!(off.len(), 1, "{:?}", off);
+            turns.push(off[0].clone());
+            clock.advance(Duration::from_secs(ROTATION_SECS as u64));
+        }
+        turns.sort();
+        assert_eq!(turns, vec!["r_dryer", "r_heater", "r_pool"]);
+
+        clock.advance(Duration::from_secs(ROTATION_SECS as u64));
+        node.run_demand_response().await;
+        assert!(open(&node).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_loads_come_back_after_an_event_at_the_pickup_rate() {
+        use crate::clock::VirtualClock;
+        use crate::pickup::{ColdLoadPickup, PickupSettings};
+
+        let load = |id: &str| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority: Priority::Low,
+            amperage: 10.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        };
+        let relays = vec![load("r_dryer"), load("r_heater"), load("r_hvac")];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        let clock = VirtualClock::at(1_700_000_000);
+        node.set_clock(Arc::new(clock.clone()));
+        node.cold_load_pickup = Some(ColdLoadPickup::new(PickupSettings { max_amps_per_minute: 30.0, settled_share: 0.05, max_settle_secs: 120 }));
+        let closed = |node: &EdgeNode| node.relays.iter().filter(|r| r.is_closed).count();
+
+        let start = clock.unix();
+        node.demand_response.accept("evt_1", Priority::Low, start, start + 1800, None, None);
+        node.run_demand_response().await;
+        assert_eq!(closed(&node), 0);
+
+        // The event ends, but the loads come back one at a time rather than all at once
+        clock.advance(Duration::from_secs(1800));
+        node.run_demand_response().await;
+        assert_eq!(closed(&node), 0);
+        let mut steps = Vec::new();
+        for _ in 0..12 {
+            node.pace_restores().await;
+            steps.push(closed(&node));
+            clock.advance(Duration::from_secs(5));
+        }
+        // The first is taken to pick up 20 A but measures 10 A, leaving room for the second
+        // once the draw settles; the third waits for room in the minute's 30 A
+        assert_eq!(steps, vec![1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2]);
+        for _ in 0..2 {
+            node.pace_restores().await;
+            clock.advance(Duration::from_secs(5));
+        }
+        assert_eq!(closed(&node), 3);
+        assert_eq!(node.cold_load_pickup.as_ref().unwrap().pickup_amps("r_dryer"), Some(10.0));
+    }
+
+    #[tokio::test]
+    async fn test_hvac_is_cycled_through_an_event_rather_than_held_off() {
+        use crate::clock::VirtualClock;
+        use crate::cycling::DutyCycle;
+
+        let load = |id: &str| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority: Priority::Medium,
+            amperage: 20.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        };
+        let mut node = EdgeNode::builder("test_node").relays(vec![load("r_hvac"), load("r_pool")]).build().unwrap();
+        let clock = VirtualClock::at(1_700_000_000);
+        node.set_clock(Arc::new(clock.clone()));
+        let cycle = DutyCycle { on_minutes: 15, off_minutes: 30 };
+        node.duty_cycler = DutyCycler::new(HashMap::from([("r_hvac".to_string(), cycle)]));
+        let start = clock.unix();
+        node.demand_response.accept("evt_1", Priority::Medium, start, start + 2 * 3600, None, None);
+
+        // Both open as the event starts; the HVAC runs again after half an hour, for a quarter
+        let mut hvac_on = Vec::new();
+        for _ in 0..(2 * 3600 / 30) {
+            node.run_demand_response().await;
+            assert!(!node.relays[1].is_closed);
+            hvac_on.push(node.relays[0].is_closed);
+            clock.advance(Duration::from_secs(30));
+        }
+        let on_minutes = hvac_on.iter().filter(|on| **on).count() / 2;
+        assert_eq!(on_minutes, 30);
+        assert!(!hvac_on[59] && hvac_on[60] && hvac_on[89] && !hvac_on[90]);
+
+        // Both back on when it ends, and the HVAC stays on
+        node.run_demand_response().await;
+        assert!(node.relays.iter().all(|r| r.is_closed));
+        clock.advance(Duration::from_secs(3600));
+        node.run_demand_response().await;
+        assert!(node.relays[0].is_closed);
+    }
+
+    #[tokio::test]
+    async fn test_time_scale_runs_schedules_faster_in_order() {
+        use crate::capture::{CaptureLink, Direction, Frame};
+        use crate::clock::VirtualClock;
+        use crate::comms::streetgrid::neighborhood_message::Payload;
+        use crate::comms::NeighborhoodMessage;
+        use crate::medium::{Medium, RadioConfig};
+        use prost::Message;
+
+        let path = std::env::temp_dir().join(format!("streetgrid-time-scale-{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let clock = VirtualClock::at(1_700_000_000);
+        let medium = Medium::new(RadioConfig::default());
+        let capture = CaptureLink::open(medium.join("test_node", [0.0, 0.0]), path.to_str().unwrap(), Arc::new(clock.clone())).unwrap();
+        let client = OrchestratorClient::new(Arc::new(capture));
+        let mut node = EdgeNode::builder("test_node").client(client).build().unwrap();
+        node.set_clock(Arc::new(clock.clone()));
+
+        // Two seconds at 600x is twenty minutes of schedules, give or take the timer's granularity
+        node.run_accelerated(clock.clone(), 600.0, tokio::time::sleep(Duration::from_secs(2))).await;
+        let elapsed_ms = clock.now().timestamp_millis() - 1_700_000_000_000;
+        assert!((300_000..=1_230_000).contains(&elapsed_ms), "{} ms went by", elapsed_ms);
+
+        // A heartbeat every simulated minute, never early, never skipped
+        let heartbeats: Vec<i64> = std::fs::read_to_string(&path).unwrap().lines()
+            .map(|l| serde_json::from_str::<Frame>(l).unwrap())
+            .filter(|f| f.dir == Direction::Tx)
+            .filter_map(|f| match NeighborhoodMessage::decode(f.bytes.as_slice()).unwrap().payload {
+                Some(Payload::Heartbeat(_)) => Some(f.t_ms),
+                _ => None,
+            })
+            .collect();
+        // Cut off while waiting for the next step, before its tasks ran
+        assert_eq!(heartbeats.len() as i64, (elapsed_ms - 1) / 60_000);
+        assert!(heartbeats.iter().enumerate().all(|(i, &t)| t == 1_700_000_000_000 + (i as i64 + 1) * 60_000));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_broken_invariants_are_alarmed_once() {
+        use crate::events::NodeEvent;
+        use crate::node::Task;
+
+        let relays = vec![
+            Relay {
+                id: "r_grid".to_string(),
+                name: "Main Grid Tie".to_string(),
+                relay_type: RelayType::Grid,
+                priority: Priority::Critical,
+                amperage: 100.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            },
+        ];
+        let mut node = EdgeNode::builder("test_node").relays(relays).build().unwrap();
+        node.invariant_checks = true;
+        node.enter_island_mode().await;
+        let mut events = node.events.subscribe();
+
+        node.run_task(Task::Security).await;
+        assert!(events.try_recv().is_err());
+
+        // Something slipped past the interlocks: alarmed after the task, and only once
+        node.relays[0].is_closed = true;
+        node.run_task(Task::Security).await;
+        node.run_task(Task::Security).await;
+        match events.try_recv().unwrap().event {
+            NodeEvent::Alarm { code, message } => {
+                assert_eq!(code, "invariant_violation");
+                assert!(message.contains("r_grid"));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_weak_links_are_warned_about_once() {
+        use crate::comms::streetgrid::neighborhood_message::Payload;
+        use crate::comms::streetgrid::Heartbeat;
+        use crate::comms::NeighborhoodMessage;
+        use crate::events::NodeEvent;
+        use crate::medium::{Medium, RadioConfig};
+        use crate::hal::LoRaHalConfig;
+        use crate::node::Task;
+
+        let medium = Medium::new(RadioConfig::default());
+        let client = OrchestratorClient::new(medium.join("node_01", [0.0, 0.0]));
+        let mut node = EdgeNode::builder("node_01").client(client).build().unwrap();
+        node.link_budget = Some(LinkBudget::new(&LoRaHalConfig::default(), 0.0));
+        for (id, position) in [("node_02", [20.0, 0.0]), ("node_03", [1500.0, 0.0])] {
+            let heartbeat = NeighborhoodMessage {
+                payload: Some(Payload::Heartbeat(Heartbeat { node_id: id.to_string(), ..Default::default() })),
+                ..Default::default()
+            };
+            medium.join(id, position).send(heartbeat).await.unwrap();
+        }
+        medium.propagate();
+        node.run_task(Task::Messages).await;
+        node.run_task(Task::Messages).await;
+        let mut events = node.events.subscribe();
+
+        node.run_task(Task::Neighbors).await;
+        node.run_task(Task::Neighbors).await;
+        match events.try_recv().unwrap().event {
+            NodeEvent::Alarm { code, message } => {
+                assert_eq!(code, "weak_link");
+                assert!(message.contains("node_03"), "{}", message);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(events.try_recv().is_err());
+    }
+
+    fn registration_node(id: &str) -> (EdgeNode, Arc<crate::comms::MockCommunication>, VirtualClock) {
+        let link = Arc::new(crate::comms::MockCommunication::default());
+        let mut node = EdgeNode::builder(id)
+            .client(OrchestratorClient::new(link.clone()))
+            .build()
+            .unwrap();
+        let clock = VirtualClock::at(1_700_000_000);
+        node.set_clock(Arc::new(clock.clone()));
+        (node, link, clock)
+    }
+
+    fn feature_reports(link: &crate::comms::MockCommunication) -> usize {
+        use crate::comms::streetgrid::neighborhood_message::Payload;
+        link.sent().iter().filter(|msg| matches!(msg.payload, Some(Payload::FeatureReport(_)))).count()
+    }
+
+    fn ack(node_id: &str) -> ReceivedCommand {
+        use crate::audit::SignatureStatus;
+        ReceivedCommand {
+            command: IncomingCommand::RegistrationAck(RegistrationAck { target_node_id: node_id.to_string() }),
+            signature: SignatureStatus::Unsigned,
+            session_id: None,
+            sender_id: None,
+            counter: 0,
+            co_signatures: None,
+            zone: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unacknowledged_registration_is_retried_on_schedule() {
+        let (mut node, link, clock) = registration_node("test_node");
+        node.run_registration().await;
+        assert_eq!(feature_reports(&link), 1);
+
+        // The ack is lost: nothing until the retry is due, then one report per period
+        clock.advance(Duration::from_secs(REGISTRATION_RETRY_SECS as u64 - 1));
+        node.run_registration().await;
+        assert_eq!(feature_reports(&link), 1);
+        clock.advance(Duration::from_secs(1));
+        node.run_registration().await;
+        node.run_registration().await;
+        assert_eq!(feature_reports(&link), 2);
+        clock.advance(Duration::from_secs(REGISTRATION_RETRY_SECS as u64));
+        node.run_registration().await;
+        assert_eq!(feature_reports(&link), 3);
+
+        // An ack for another node doesn't stop the retries; ours does
+        node.handle_command(ack("other_node")).await;
+        clock.advance(Duration::from_secs(REGISTRATION_RETRY_SECS as u64));
+        node.run_registration().await;
+        assert_eq!(feature_reports(&link), 4);
+        node.handle_command(ack("test_node")).await;
+        assert!(node.registered);
+        clock.advance(Duration::from_secs(REGISTRATION_RETRY_SECS as u64));
+        node.run_registration().await;
+        assert_eq!(feature_reports(&link), 4);
+    }
+
+    #[tokio::test]
+    async fn test_registration_is_refreshed_for_a_restarted_orchestrator() {
+        let (mut node, link, clock) = registration_node("test_node");
+        node.run_registration().await;
+        node.handle_command(ack("test_node")).await;
+
+        // The orchestrator restarts and loses us; the periodic refresh re-registers
+        clock.advance(Duration::from_secs(REREGISTRATION_SECS as u64 - 1));
+        node.run_registration().await;
+        assert_eq!(feature_reports(&link), 1);
+        clock.advance(Duration::from_secs(1));
+        node.run_registration().await;
+        assert_eq!(feature_reports(&link), 2);
+        assert!(!node.registered);
+
+        // Until the restarted orchestrator acknowledges, the refresh is retried
+        clock.advance(Duration::from_secs(REGISTRATION_RETRY_SECS as u64));
+        node.run_registration().await;
+        assert_eq!(feature_reports(&link), 3);
+        node.handle_command(ack("test_node")).await;
+        assert!(node.registered);
+    }
+
+    #[tokio::test]
+    async fn test_who_is_there_answers_are_spread_over_the_window() {
+        use crate::audit::SignatureStatus;
+
+        let mut delays = BTreeSet::new();
+        for i in 0..20 {
+            let (mut node, link, clock) = registration_node(&format!("node_{:02}", i));
+            node.run_registration().await;
+            node.handle_command(ack(&node.id.clone())).await;
+            node.handle_command(ReceivedCommand {
+                command: IncomingCommand::WhoIsThere,
+                signature: SignatureStatus::Unsigned,
+                session_id: None,
+                sender_id: None,
+                counter: 0,
+                co_signatures: None,
+                zone: None,
+            }).await;
+            assert!(!node.registered);
+
+            // Each node answers once, within the window
+            let mut delay = None;
+            for secs in 0..=WHO_IS_THERE_SPREAD_SECS {
+                node.run_registration().await;
+                if feature_reports(&link) == 2 && delay.is_none() {
+                    delay = Some(secs);
+                }
+                clock.advance(Duration::from_secs(1));
+            }
+            assert_eq!(feature_reports(&link), 2);
+            let delay = delay.unwrap();
+            assert!(delay < WHO_IS_THERE_SPREAD_SECS);
+            delays.insert(delay);
+        }
+        assert!(delays.len() > 10, "answers bunched at {:?}", delays);
+    }
+}
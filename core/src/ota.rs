@@ -0,0 +1,279 @@
+use ed25519_dalek::{Signature, VerifyingKey, SIGNATURE_LENGTH};
+use log::info;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// First bytes of every firmware image.
+pub const IMAGE_MAGIC: &[u8; 4] = b"SGFW";
+
+/// Largest image we will download (the firmware binary is a few MB).
+const MAX_IMAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Longest a download may take before the update is given up.
+pub const FETCH_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Why an image was not downloaded, trusted or installed.
+#[derive(Debug, Error)]
+pub enum OtaError {
+    /// The bytes aren't laid out as a firmware image
+    #[error("{0}")]
+    Malformed(&'static str),
+    #[error("invalid manifest: {0}")]
+    Manifest(#[from] serde_json::Error),
+    #[error("release key must be 32 hex-encoded bytes")]
+    BadReleaseKey,
+    #[error("invalid release key: {0}")]
+    ReleaseKey(#[from] ed25519_dalek::SignatureError),
+    #[error("no release keys configured")]
+    NoReleaseKeys,
+    #[error("manifest signature is not from a trusted release key")]
+    Untrusted,
+    #[error("image targets {image} but this node is {node}")]
+    WrongHardware { image: String, node: String },
+    #[error("payload is {payload} bytes, manifest says {manifest}")]
+    WrongSize { payload: u64, manifest: u64 },
+    #[error("payload hash does not match manifest")]
+    WrongHash,
+    #[error("version {candidate} is not newer than running {running}")]
+    NotNewer { candidate: String, running: String },
+    #[error("unsupported image URL {0}")]
+    UnsupportedUrl(String),
+    #[error("image download failed: {0}")]
+    Download(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T, E = OtaError> = std::result::Result<T, E>;
+
+/// What a firmware image claims to be; this is what the release key signs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+    /// Hex SHA-256 of the payload
+    pub sha256: String,
+    /// Hardware the build targets, e.g. "rpi4"
+    pub target_hardware: String,
+    pub size: u64,
+}
+
+/// Parsed image: `SGFW | manifest_len (u32 BE) | manifest JSON | Ed25519 signature (64) | payload`.
+pub struct FirmwareImage<'a> {
+    pub manifest: Manifest,
+    manifest_bytes: &'a [u8],
+    signature: [u8; SIGNATURE_LENGTH],
+    payload: &'a [u8],
+}
+
+impl<'a> FirmwareImage<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self> {
+        let rest = bytes.strip_prefix(IMAGE_MAGIC.as_slice())
+            .ok_or(OtaError::Malformed("not a StreetGrid firmware image"))?;
+        let Some((len, rest)) = rest.split_first_chunk::<4>() else {
+            return Err(OtaError::Malformed("truncated image header"));
+        };
+        let manifest_len = u32::from_be_bytes(*len) as usize;
+        if manifest_len.checked_add(SIGNATURE_LENGTH).is_none_or(|end| rest.len() < end) {
+            return Err(OtaError::Malformed("truncated image manifest"));
+        }
+        let (manifest_bytes, rest) = rest.split_at(manifest_len);
+        let (signature, payload) = rest.split_at(SIGNATURE_LENGTH);
+        Ok(Self {
+            manifest: serde_json::from_slice(manifest_bytes)?,
+            manifest_bytes,
+            signature: signature.try_into().expect("split at SIGNATURE_LENGTH"),
+            payload,
+        })
+    }
+}
+
+/// Checks images against the trusted release keys before anything touches the disk.
+pub struct ImageVerifier {
+    keys: Vec<VerifyingKey>,
+    hardware: String,
+    current_version: String,
+}
+
+impl ImageVerifier {
+    /// `keys` are hex-encoded Ed25519 public keys; several allow signing-key rollover.
+    pub fn new(keys: &[String], hardware: &str, current_version: &str) -> Result<Self> {
+        let keys = keys.iter()
+            .map(|k| {
+                let mut bytes = [0u8; 32];
+                hex::decode_to_slice(k.trim(), &mut bytes).map_err(|_| OtaError::BadReleaseKey)?;
+                Ok(VerifyingKey::from_bytes(&bytes)?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if keys.is_empty() {
+            return Err(OtaError::NoReleaseKeys);
+        }
+        Ok(Self {
+            keys,
+            hardware: hardware.to_string(),
+            current_version: current_version.to_string(),
+        })
+    }
+
+    pub fn verify(&self, image: &FirmwareImage) -> Result<()> {
+        let signature = Signature::from_bytes(&image.signature);
+        if !self.keys.iter().any(|k| k.verify_strict(image.manifest_bytes, &signature).is_ok()) {
+            return Err(OtaError::Untrusted);
+        }
+        let manifest = &image.manifest;
+        if manifest.target_hardware != self.hardware {
+            return Err(OtaError::WrongHardware { image: manifest.target_hardware.clone(), node: self.hardware.clone() });
+        }
+        if image.payload.len() as u64 != manifest.size {
+            return Err(OtaError::WrongSize { payload: image.payload.len() as u64, manifest: manifest.size });
+        }
+        if hex::encode(Sha256::digest(image.payload)) != manifest.sha256.to_lowercase() {
+            return Err(OtaError::WrongHash);
+        }
+        if !is_newer(&manifest.version, &self.current_version) {
+            return Err(OtaError::NotNewer { candidate: manifest.version.clone(), running: self.current_version.clone() });
+        }
+        Ok(())
+    }
+}
+
+/// Compare dotted numeric versions ("1.10.0" > "1.9.3"); non-numeric parts compare as 0.
+fn is_newer(candidate: &str, current: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> { v.split('.').map(|p| p.parse().unwrap_or(0)).collect() };
+    parse(candidate) > parse(current)
+}
+
+/// Downloads, verifies and installs firmware images.
+pub struct OtaUpdater {
+    verifier: ImageVerifier,
+    /// Where the verified binary is installed (the service manager restarts into it)
+    install_path: PathBuf,
+}
+
+impl OtaUpdater {
+    pub fn new(verifier: ImageVerifier, install_path: impl Into<PathBuf>) -> Self {
+        Self { verifier, install_path: install_path.into() }
+    }
+
+    /// Verify `bytes` and, only if everything checks out, atomically replace the installed binary.
+    pub fn install(&self, bytes: &[u8]) -> Result<Manifest> {
+        let image = FirmwareImage::parse(bytes)?;
+        self.verifier.verify(&image)?;
+
+        let staging = self.install_path.with_extension("new");
+        std::fs::write(&staging, image.payload)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&staging, std::fs::Permissions::from_mode(0o755))?;
+        }
+        std::fs::rename(&staging, &self.install_path)?;
+        info!("Installed firmware {} at {}", image.manifest.version, self.install_path.display());
+        Ok(image.manifest)
+    }
+}
+
+/// Retrieve an image from `file://` or plain `http://` (HTTP/1.0, so no chunked bodies).
+pub async fn fetch(url: &str) -> Result<Vec<u8>> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return Ok(tokio::fs::read(path).await?);
+    }
+    let Some(rest) = url.strip_prefix("http://") else {
+        return Err(OtaError::UnsupportedUrl(url.to_string()));
+    };
+    let (host, path) = rest.split_once('/').map(|(h, p)| (h, format!("/{}", p))).unwrap_or((rest, "/".to_string()));
+    let addr = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+
+    let mut stream = tokio::net::TcpStream::connect(&addr).await?;
+    stream.write_all(format!("GET {} HTTP/1.0\r\nHost: {}\r\n\r\n", path, host).as_bytes()).await?;
+    let mut response = Vec::new();
+    (&mut stream).take(MAX_IMAGE_SIZE as u64 + 8192).read_to_end(&mut response).await?;
+
+    let header_end = response.windows(4).position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| OtaError::Download("malformed HTTP response".to_string()))?;
+    let status_line = String::from_utf8_lossy(&response[..header_end]).lines().next().unwrap_or_default().to_string();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        return Err(OtaError::Download(status_line));
+    }
+    Ok(response.split_off(header_end + 4))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const RELEASE_KEY: [u8; 32] = [42; 32];
+
+    fn build_image(key: &SigningKey, version: &str, hardware: &str, payload: &[u8]) -> Vec<u8> {
+        let manifest = serde_json::to_vec(&Manifest {
+            version: version.to_string(),
+            sha256: hex::encode(Sha256::digest(payload)),
+            target_hardware: hardware.to_string(),
+            size: payload.len() as u64,
+        }).unwrap();
+        let mut image = IMAGE_MAGIC.to_vec();
+        image.extend_from_slice(&(manifest.len() as u32).to_be_bytes());
+        image.extend_from_slice(&manifest);
+        image.extend_from_slice(&key.sign(&manifest).to_bytes());
+        image.extend_from_slice(payload);
+        image
+    }
+
+    fn updater(dir: &std::path::Path) -> OtaUpdater {
+        let public = hex::encode(SigningKey::from_bytes(&RELEASE_KEY).verifying_key().to_bytes());
+        let verifier = ImageVerifier::new(&[public], "rpi4", "0.1.0").unwrap();
+        OtaUpdater::new(verifier, dir.join("streetgrid-firmware"))
+    }
+
+    #[test]
+    fn test_signed_image_installs() {
+        let dir = std::env::temp_dir().join(format!("streetgrid-ota-ok-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = build_image(&SigningKey::from_bytes(&RELEASE_KEY), "0.2.0", "rpi4", b"\x7fELF new firmware");
+
+        let manifest = updater(&dir).install(&image).unwrap();
+        assert_eq!(manifest.version, "0.2.0");
+        assert_eq!(std::fs::read(dir.join("streetgrid-firmware")).unwrap(), b"\x7fELF new firmware");
+    }
+
+    #[test]
+    fn test_bad_images_are_refused() {
+        let dir = std::env::temp_dir().join(format!("streetgrid-ota-bad-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let updater = updater(&dir);
+        let release = SigningKey::from_bytes(&RELEASE_KEY);
+
+        // Signed by someone else
+        let rogue = build_image(&SigningKey::from_bytes(&[7; 32]), "0.2.0", "rpi4", b"payload");
+        assert!(updater.install(&rogue).is_err());
+
+        // Payload swapped after signing
+        let mut tampered = build_image(&release, "0.2.0", "rpi4", b"payload");
+        *tampered.last_mut().unwrap() ^= 0xFF;
+        assert!(updater.install(&tampered).unwrap_err().to_string().contains("hash"));
+
+        // Unsigned (zeroed signature)
+        let mut unsigned = build_image(&release, "0.2.0", "rpi4", b"payload");
+        let sig_start = unsigned.len() - b"payload".len() - SIGNATURE_LENGTH;
+        unsigned[sig_start..sig_start + SIGNATURE_LENGTH].fill(0);
+        assert!(updater.install(&unsigned).is_err());
+
+        assert!(updater.install(&build_image(&release, "0.2.0", "rpi3", b"payload")).is_err());
+        assert!(updater.install(&build_image(&release, "0.1.0", "rpi4", b"payload")).is_err());
+        assert!(updater.install(b"not an image").is_err());
+
+        // Nothing was installed
+        assert!(!dir.join("streetgrid-firmware").exists());
+    }
+
+    #[test]
+    fn test_version_ordering() {
+        assert!(is_newer("1.10.0", "1.9.3"));
+        assert!(is_newer("0.2", "0.1.9"));
+        assert!(!is_newer("0.1.0", "0.1.0"));
+    }
+}
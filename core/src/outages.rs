@@ -0,0 +1,191 @@
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::types::{NodeState, Priority};
+
+/// Storage key of the outage counters.
+pub const OUTAGE_KEY: &str = "outages.json";
+
+/// How often something was interrupted, and for how long in all.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interruptions {
+    pub count: u32,
+    pub secs: u64,
+}
+
+/// Reliability counters for one calendar month (UTC), from which the utility's
+/// SAIFI (interruptions per customer) and SAIDI (minutes per customer) follow.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OutageMonth {
+    /// "YYYY-MM"
+    pub month: String,
+    /// Loss of grid supply, whether or not the node islanded through it
+    pub grid_losses: Interruptions,
+    /// Time run as an island, black start included
+    pub islands: Interruptions,
+    /// Load relays opened, by relay
+    pub relays: BTreeMap<String, Interruptions>,
+    /// The same, by the priority of the load
+    pub shed_by_priority: BTreeMap<Priority, Interruptions>,
+}
+
+/// Interruptions counted as they start, timed as they end, and rolled into a report at
+/// the end of each month. Those still going at a month's end are timed up to it and
+/// carry on into the next month without being counted again.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OutageLog {
+    current: OutageMonth,
+    /// Last month finished, until it has been reported
+    finished: Option<OutageMonth>,
+    /// Unix seconds the grid was lost, if it still is
+    grid_lost_at: Option<i64>,
+    /// Unix seconds the node islanded, if it still is
+    islanded_at: Option<i64>,
+    /// Load relays open, with the unix seconds they opened
+    open_relays: BTreeMap<String, (i64, Priority)>,
+}
+
+fn month_of(unix: i64) -> String {
+    DateTime::from_timestamp(unix, 0).map(|t| t.format("%Y-%m").to_string()).unwrap_or_default()
+}
+
+fn elapsed(since: i64, now: i64) -> u64 {
+    (now - since).max(0) as u64
+}
+
+fn off_grid(state: NodeState) -> bool {
+    matches!(state, NodeState::AlertSent | NodeState::Islanded | NodeState::BlackStart)
+}
+
+fn islanded(state: NodeState) -> bool {
+    matches!(state, NodeState::Islanded | NodeState::BlackStart)
+}
+
+impl OutageLog {
+    /// Counters for the month in progress.
+    pub fn current(&self) -> &OutageMonth {
+        &self.current
+    }
+
+    /// Record the node moving from `from` to `to` at `now`.
+    pub fn state_changed(&mut self, from: NodeState, to: NodeState, now: i64) {
+        self.roll(now);
+        if !off_grid(from) && off_grid(to) {
+            self.grid_lost_at = Some(now);
+            self.current.grid_losses.count += 1;
+        } else if off_grid(from) && !off_grid(to) {
+            if let Some(at) = self.grid_lost_at.take() {
+                self.current.grid_losses.secs += elapsed(at, now);
+            }
+        }
+        if !islanded(from) && islanded(to) {
+            self.islanded_at = Some(now);
+            self.current.islands.count += 1;
+        } else if islanded(from) && !islanded(to) {
+            if let Some(at) = self.islanded_at.take() {
+                self.current.islands.secs += elapsed(at, now);
+            }
+        }
+    }
+
+    /// Record a load relay opening at `now`.
+    pub fn relay_opened(&mut self, relay_id: &str, priority: Priority, now: i64) {
+        self.roll(now);
+        if self.open_relays.contains_key(relay_id) {
+            return;
+        }
+        self.open_relays.insert(relay_id.to_string(), (now, priority));
+        self.current.relays.entry(relay_id.to_string()).or_default().count += 1;
+        self.current.shed_by_priority.entry(priority).or_default().count += 1;
+    }
+
+    /// Record a load relay closing at `now`.
+    pub fn relay_closed(&mut self, relay_id: &str, now: i64) {
+        self.roll(now);
+        if let Some((at, priority)) = self.open_relays.remove(relay_id) {
+            self.current.relays.entry(relay_id.to_string()).or_default().secs += elapsed(at, now);
+            self.current.shed_by_priority.entry(priority).or_default().secs += elapsed(at, now);
+        }
+    }
+
+    /// The last month finished as of `now`, until `reported` is called.
+    pub fn finished(&mut self, now: i64) -> Option<&OutageMonth> {
+        self.roll(now);
+        self.finished.as_ref()
+    }
+
+    /// Forget the finished month once it has gone out.
+    pub fn reported(&mut self) {
+        self.finished = None;
+    }
+
+    /// Close the month in progress if `now` is past it.
+    fn roll(&mut self, now: i64) {
+        let month = month_of(now);
+        if self.current.month == month {
+            return;
+        }
+        let mut ended = std::mem::take(&mut self.current);
+        if !ended.month.is_empty() {
+            if let Some(at) = &mut self.grid_lost_at {
+                ended.grid_losses.secs += elapsed(*at, now);
+                *at = now;
+            }
+            if let Some(at) = &mut self.islanded_at {
+                ended.islands.secs += elapsed(*at, now);
+                *at = now;
+            }
+            for (relay_id, (at, priority)) in &mut self.open_relays {
+                ended.relays.entry(relay_id.clone()).or_default().secs += elapsed(*at, now);
+                ended.shed_by_priority.entry(*priority).or_default().secs += elapsed(*at, now);
+                *at = now;
+            }
+            self.finished = Some(ended);
+        }
+        self.current.month = month;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interruptions_are_counted_timed_and_reported_by_month() {
+        // 2026-03-31 23:00 UTC
+        let t0 = 1_774_998_000;
+        let mut log = OutageLog::default();
+
+        log.state_changed(NodeState::Normal, NodeState::AlertSent, t0);
+        log.state_changed(NodeState::AlertSent, NodeState::Islanded, t0 + 10);
+        log.relay_opened("r_tv", Priority::Low, t0 + 20);
+        log.relay_opened("r_tv", Priority::Low, t0 + 25);
+        log.relay_opened("r_hvac", Priority::Medium, t0 + 20);
+        log.relay_closed("r_hvac", t0 + 620);
+        assert!(log.finished(t0 + 620).is_none());
+
+        // The island outlasts the month: its time so far goes in March's report
+        let april = t0 + 3600;
+        let march = log.finished(april).cloned().unwrap();
+        assert_eq!(march.month, "2026-03");
+        assert_eq!(march.grid_losses, Interruptions { count: 1, secs: 3600 });
+        assert_eq!(march.islands, Interruptions { count: 1, secs: 3590 });
+        assert_eq!(march.relays["r_tv"], Interruptions { count: 1, secs: 3580 });
+        assert_eq!(march.relays["r_hvac"], Interruptions { count: 1, secs: 600 });
+        assert_eq!(march.shed_by_priority[&Priority::Low], Interruptions { count: 1, secs: 3580 });
+        log.reported();
+        assert!(log.finished(april).is_none());
+
+        // ...and the rest in April's, without counting it twice
+        log.state_changed(NodeState::Islanded, NodeState::Normal, april + 300);
+        log.relay_closed("r_tv", april + 400);
+        assert_eq!(log.current().month, "2026-04");
+        assert_eq!(log.current().grid_losses, Interruptions { count: 0, secs: 300 });
+        assert_eq!(log.current().islands, Interruptions { count: 0, secs: 300 });
+        assert_eq!(log.current().relays["r_tv"], Interruptions { count: 0, secs: 400 });
+
+        // Survives a restart
+        let json = serde_json::to_string(&log).unwrap();
+        assert_eq!(serde_json::from_str::<OutageLog>(&json).unwrap(), log);
+    }
+}
@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::interlock;
+use crate::types::{Phase, Relay, RelayType};
+
+/// Share of a phase's limit left free before a load shed off it is put back, so a load
+/// isn't closed and opened again with every swing in the current.
+const RESTORE_HEADROOM: f32 = 0.1;
+
+/// ADC channels sensing one phase of a three-phase service, and what it may carry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhaseSensing {
+    /// Channel of the CT clamp on the phase conductor
+    pub current_channel: u8,
+    /// Channel sampling the phase voltage; without one it is taken as the reference voltage
+    pub voltage_channel: Option<u8>,
+    /// Current above which loads on the phase are shed
+    pub max_amps: Option<f32>,
+}
+
+/// What one phase last measured.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PhaseReading {
+    pub phase: Phase,
+    pub volts: f32,
+    pub amps: f32,
+}
+
+/// Readings of each phase of a three-phase service, opening loads on a phase that
+/// carries more than it may, lowest priority first, while the others are left alone.
+#[derive(Debug, Default)]
+pub struct PhaseMonitor {
+    pub sensing: BTreeMap<Phase, PhaseSensing>,
+    readings: BTreeMap<Phase, PhaseReading>,
+    /// Relays opened off an overloaded phase
+    shed: Vec<String>,
+}
+
+impl PhaseMonitor {
+    pub fn new(sensing: BTreeMap<Phase, PhaseSensing>) -> Self {
+        Self { sensing, ..Default::default() }
+    }
+
+    pub fn record(&mut self, reading: PhaseReading) {
+        self.readings.insert(reading.phase, reading);
+    }
+
+    pub fn readings(&self) -> Vec<PhaseReading> {
+        self.readings.values().copied().collect()
+    }
+
+    pub fn shed(&self) -> &[String] {
+        &self.shed
+    }
+
+    /// Lowest phase voltage measured; losing any one phase is a grid failure.
+    pub fn lowest_voltage(&self) -> Option<f32> {
+        self.readings.values().map(|r| r.volts).reduce(f32::min)
+    }
+
+    /// Loads to open for each phase over its limit, and loads shed earlier to close again
+    /// once their phase has room for their `amps` with headroom, if `restore`. A relay is on
+    /// its own phase or else `default_phase`; loads the budget never sheds are left alone.
+    pub fn tick(&mut self, relays: &[Relay], amps: impl Fn(&Relay) -> f32, default_phase: Option<Phase>, restore: bool) -> (Vec<String>, Vec<String>) {
+        // Loads closed again by someone else are no longer ours to restore
+        self.shed.retain(|id| relays.iter().any(|r| r.id == *id && !r.is_closed));
+        let mut open = Vec::new();
+        let mut close = Vec::new();
+        for (phase, sensing) in &self.sensing {
+            let (Some(limit), Some(reading)) = (sensing.max_amps, self.readings.get(phase)) else { continue };
+            let on_phase = || relays.iter()
+                .filter(|r| r.relay_type == RelayType::Load && r.phase.or(default_phase) == Some(*phase));
+            let mut total = reading.amps;
+
+            if total > limit {
+                let mut sheddable: Vec<&Relay> = on_phase()
+                    .filter(|r| r.is_closed && !interlock::protected_from_budget(r) && amps(r) > 0.0)
+                    .collect();
+                sheddable.sort_by_key(|r| std::cmp::Reverse(r.priority));
+                for relay in sheddable {
+                    if total <= limit {
+                        break;
+                    }
+                    total -= amps(relay);
+                    open.push(relay.id.clone());
+                }
+                continue;
+            }
+            if !restore {
+                continue;
+            }
+            let mut waiting: Vec<&Relay> = on_phase().filter(|r| self.shed.contains(&r.id)).collect();
+            waiting.sort_by_key(|r| r.priority);
+            for relay in waiting {
+                if total + amps(relay) <= limit * (1.0 - RESTORE_HEADROOM) {
+                    total += amps(relay);
+                    close.push(relay.id.clone());
+                }
+            }
+        }
+        self.shed.retain(|id| !close.contains(id));
+        self.shed.extend(open.iter().cloned());
+        (open, close)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Priority;
+
+    fn load(id: &str, priority: Priority, phase: Option<Phase>) -> Relay {
+        Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage: 20.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase,
+        }
+    }
+
+    #[test]
+    fn test_only_the_overloaded_phase_is_shed() {
+        let sensing = |channel| PhaseSensing { current_channel: channel, voltage_channel: None, max_amps: Some(63.0) };
+        let mut monitor = PhaseMonitor::new(BTreeMap::from([(Phase::A, sensing(0)), (Phase::B, sensing(1))]));
+        monitor.record(PhaseReading { phase: Phase::A, volts: 231.0, amps: 70.0 });
+        monitor.record(PhaseReading { phase: Phase::B, volts: 228.0, amps: 80.0 });
+        assert_eq!(monitor.lowest_voltage(), Some(228.0));
+
+        let mut relays = vec![
+            load("r_pump", Priority::Critical, Some(Phase::B)),
+            load("r_oven", Priority::Medium, Some(Phase::B)),
+            load("r_sauna", Priority::Low, Some(Phase::B)),
+            load("r_kiln", Priority::Low, None),
+        ];
+        let amps = |r: &Relay| if r.id == "r_sauna" { 10.0 } else { 15.0 };
+        // Phase A is over too, but nothing on it is known to be: the kiln is on the node's phase C
+        let (open, close) = monitor.tick(&relays, amps, Some(Phase::C), true);
+        assert_eq!(open, vec!["r_sauna".to_string(), "r_oven".to_string()]);
+        assert!(close.is_empty());
+        relays[1].is_closed = false;
+        relays[2].is_closed = false;
+
+        // Back under the limit, with room for the oven but not the sauna as well
+        monitor.record(PhaseReading { phase: Phase::B, volts: 229.0, amps: 40.0 });
+        assert_eq!(monitor.tick(&relays, amps, Some(Phase::C), false), (vec![], vec![]));
+        assert_eq!(monitor.tick(&relays, amps, Some(Phase::C), true), (vec![], vec!["r_oven".to_string()]));
+        assert_eq!(monitor.shed(), ["r_sauna".to_string()]);
+    }
+}
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Window the restore rate is measured over
+const WINDOW_SECS: i64 = 60;
+
+/// Pickup assumed of a load not yet measured, as a multiple of its rating: after hours
+/// off, compressors and heaters all start at once and draw well above steady state
+pub const UNMEASURED_PICKUP_FACTOR: f32 = 2.0;
+
+fn default_settled_share() -> f32 {
+    0.05
+}
+
+fn default_max_settle_secs() -> i64 {
+    120
+}
+
+/// How fast loads are put back after an outage or a shed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PickupSettings {
+    /// Most pickup current restored loads may add in any minute
+    pub max_amps_per_minute: f32,
+    /// Change in draw between samples, as a share of it, below which it has settled (default 0.05)
+    #[serde(default = "default_settled_share")]
+    pub settled_share: f32,
+    /// Longest to wait for the draw to settle before restoring the next load (default 120 s)
+    #[serde(default = "default_max_settle_secs")]
+    pub max_settle_secs: i64,
+}
+
+/// The load restored last, waiting for the draw to settle.
+#[derive(Debug, Clone, PartialEq)]
+struct Settling {
+    relay_id: String,
+    closed_at: i64,
+    /// Draw just before it closed
+    before_amps: f32,
+    /// Draw at the last sample since, once there has been one
+    last_amps: Option<f32>,
+}
+
+/// Restores loads one at a time, no faster than the pickup current they are measured
+/// to draw allows, waiting between them until the draw has settled.
+#[derive(Debug)]
+pub struct ColdLoadPickup {
+    pub settings: PickupSettings,
+    queue: VecDeque<String>,
+    /// Step in draw each load last caused when restored
+    pickup_amps: HashMap<String, f32>,
+    /// When loads were restored in the last minute, and their pickup
+    recent: Vec<(i64, f32)>,
+    settling: Option<Settling>,
+}
+
+impl ColdLoadPickup {
+    pub fn new(settings: PickupSettings) -> Self {
+        Self { settings, queue: VecDeque::new(), pickup_amps: HashMap::new(), recent: Vec::new(), settling: None }
+    }
+
+    /// Put a load in line to be restored, after those already waiting.
+    pub fn queue(&mut self, relay_id: &str) {
+        if !self.is_queued(relay_id) {
+            self.queue.push_back(relay_id.to_string());
+        }
+    }
+
+    pub fn is_queued(&self, relay_id: &str) -> bool {
+        self.queue.iter().any(|id| id == relay_id)
+    }
+
+    /// Forget the loads waiting, when they are to stay off after all.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    /// Pickup a load last caused, if it has been measured.
+    pub fn pickup_amps(&self, relay_id: &str) -> Option<f32> {
+        self.pickup_amps.get(relay_id).copied()
+    }
+
+    /// Fold in the measured draw at `now`, and return the next load to restore, if the
+    /// draw has settled since the last and its pickup fits in the minute's allowance.
+    /// `rated_amps` estimates the pickup of loads never measured.
+    pub fn tick(&mut self, now: i64, draw_amps: f32, rated_amps: impl Fn(&str) -> f32) -> Option<String> {
+        if let Some(settling) = &mut self.settling {
+            let settled = match settling.last_amps {
+                // The first sample after closing catches the pickup, which replaces the
+                // estimate in the minute's allowance
+                None => {
+                    let pickup = (draw_amps - settling.before_amps).max(0.0);
+                    self.pickup_amps.insert(settling.relay_id.clone(), pickup);
+                    if let Some((_, amps)) = self.recent.last_mut() {
+                        *amps = pickup;
+                    }
+                    false
+                }
+                Some(last) => (draw_amps - last).abs() <= self.settings.settled_share * last.abs().max(1.0),
+            };
+            settling.last_amps = Some(draw_amps);
+            if !settled && now - settling.closed_at < self.settings.max_settle_secs {
+                return None;
+            }
+            self.settling = None;
+        }
+
+        self.recent.retain(|(at, _)| now - at < WINDOW_SECS);
+        let relay_id = self.queue.front()?;
+        let pickup = self.pickup_amps(relay_id).unwrap_or_else(|| rated_amps(relay_id) * UNMEASURED_PICKUP_FACTOR);
+        let used: f32 = self.recent.iter().map(|(_, amps)| amps).sum();
+        // A load bigger than the whole allowance still goes, on its own minute
+        if !self.recent.is_empty() && used + pickup > self.settings.max_amps_per_minute {
+            return None;
+        }
+        let relay_id = self.queue.pop_front()?;
+        self.recent.push((now, pickup));
+        self.settling = Some(Settling { relay_id: relay_id.clone(), closed_at: now, before_amps: draw_amps, last_amps: None });
+        Some(relay_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loads_are_restored_at_the_rate_their_pickup_allows() {
+        let settings = PickupSettings { max_amps_per_minute: 40.0, settled_share: default_settled_share(), max_settle_secs: default_max_settle_secs() };
+        let mut pickup = ColdLoadPickup::new(settings);
+        let rated = |_: &str| 10.0;
+        for relay_id in ["r_fridge", "r_hvac", "r_heater"] {
+            pickup.queue(relay_id);
+        }
+        pickup.queue("r_fridge");
+
+        // Unmeasured, the fridge is taken to pick up twice its rating
+        assert_eq!(pickup.tick(0, 5.0, rated).as_deref(), Some("r_fridge"));
+        // It draws 20 A starting, then settles at 8 A
+        assert_eq!(pickup.tick(5, 25.0, rated), None);
+        assert_eq!(pickup.pickup_amps("r_fridge"), Some(20.0));
+        assert_eq!(pickup.tick(10, 13.0, rated), None);
+        assert_eq!(pickup.tick(15, 13.2, rated).as_deref(), Some("r_hvac"));
+
+        // Another 20 A would pass the 40 A allowance until the fridge's minute is up
+        assert_eq!(pickup.tick(20, 33.0, rated), None);
+        assert_eq!(pickup.tick(25, 33.0, rated), None);
+        assert_eq!(pickup.tick(55, 33.0, rated), None);
+        assert_eq!(pickup.tick(60, 33.0, rated).as_deref(), Some("r_heater"));
+
+        // A draw that never settles holds the next load back only so long
+        pickup.queue("r_pump");
+        assert_eq!(pickup.tick(65, 50.0, rated), None);
+        assert_eq!(pickup.tick(120, 60.0, rated), None);
+        assert_eq!(pickup.tick(180, 70.0, rated).as_deref(), Some("r_pump"));
+        assert!(!pickup.is_queued("r_pump"));
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use crate::comms::PowerGrant;
+use crate::sources::{Capacity, FORECAST_HOURS, SOURCE_CAPACITY_INTERVAL_SECS};
+
+/// Offers and requests not refreshed for this long are left out of the allocation.
+pub const ADVERT_TTL_SECS: i64 = 90;
+
+/// Capacity adverts are dropped after this many missed intervals.
+const CAPACITY_TTL_SECS: i64 = 3 * SOURCE_CAPACITY_INTERVAL_SECS as i64;
+
+/// Highest community criticality a relay can be given.
+pub const MAX_COMMUNITY_CRITICALITY: u8 = 3;
+
+/// What one node asks of the island's supply.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Demand {
+    pub requested_watts: f32,
+    /// Part of the request serving Critical loads not counted in `community_watts`
+    pub critical_watts: f32,
+    /// Part serving loads the neighbourhood relies on (e.g. a dialysis machine), by
+    /// community criticality 1..=MAX_COMMUNITY_CRITICALITY
+    pub community_watts: [f32; MAX_COMMUNITY_CRITICALITY as usize],
+}
+
+impl Demand {
+    /// The request split into the order it is funded in: community criticality from
+    /// highest down, then the node's own Critical loads, then everything else.
+    fn tiers(&self) -> Vec<f32> {
+        let community: f32 = self.community_watts.iter().sum();
+        let mut tiers: Vec<f32> = self.community_watts.iter().rev().copied().collect();
+        tiers.push(self.critical_watts);
+        tiers.push((self.requested_watts - community - self.critical_watts).max(0.0));
+        tiers
+    }
+}
+
+/// Supply offered and demand requested by the nodes we hear, their source capacity,
+/// plus the latest grant for each node.
+#[derive(Debug, Default)]
+pub struct PowerLedger {
+    offers: BTreeMap<String, (f32, i64)>,
+    capacities: BTreeMap<String, (Capacity, i64)>,
+    /// Demand, when the requester sent it and when we heard it
+    requests: BTreeMap<String, (Demand, i64, i64)>,
+    grants: BTreeMap<String, PowerGrant>,
+    /// Request and grant timestamps of the last grant accepted for each node
+    answered: BTreeMap<String, (i64, i64)>,
+}
+
+impl PowerLedger {
+    pub fn offer(&mut self, node_id: &str, available_watts: f32, now: i64) {
+        self.offers.insert(node_id.to_string(), (available_watts.max(0.0), now));
+    }
+
+    pub fn capacity(&mut self, node_id: &str, capacity: Capacity, now: i64) {
+        self.capacities.insert(node_id.to_string(), (capacity, now));
+    }
+
+    /// A request the node sent at `sent_at`, by its clock; grants echo that back.
+    pub fn request(&mut self, node_id: &str, demand: Demand, sent_at: i64, now: i64) {
+        self.requests.insert(node_id.to_string(), (demand, sent_at, now));
+    }
+
+    /// When the node's current request was sent, by its clock.
+    pub fn requested_at(&self, node_id: &str) -> Option<i64> {
+        self.requests.get(node_id).map(|(_, sent_at, _)| *sent_at)
+    }
+
+    /// Keep the newest grant addressed to each node. A grant answering a request that
+    /// has gone stale, or older than one already accepted, is a replay and refused;
+    /// returns whether it was kept.
+    pub fn grant(&mut self, grant: PowerGrant, now: i64) -> bool {
+        if (now - grant.request_timestamp).abs() > ADVERT_TTL_SECS {
+            return false;
+        }
+        let sequence = (grant.request_timestamp, grant.timestamp);
+        if self.answered.get(&grant.target_node_id).is_some_and(|last| sequence <= *last) {
+            return false;
+        }
+        self.answered.insert(grant.target_node_id.clone(), sequence);
+        self.grants.insert(grant.target_node_id.clone(), grant);
+        true
+    }
+
+    pub fn take_grant(&mut self, node_id: &str) -> Option<PowerGrant> {
+        self.grants.remove(node_id)
+    }
+
+    /// Budgets for every node with a fresh request, from the fresh offers. A node
+    /// running on a battery offers no more than its stored energy and expected solar
+    /// can keep up over the forecast horizon, so the island doesn't drain it by dusk.
+    pub fn allocate(&mut self, now: i64) -> Vec<(String, f32)> {
+        self.offers.retain(|_, (_, at)| now - *at <= ADVERT_TTL_SECS);
+        self.requests.retain(|_, (_, _, at)| now - *at <= ADVERT_TTL_SECS);
+        self.capacities.retain(|_, (_, at)| now - *at <= CAPACITY_TTL_SECS);
+        let supply = self.offers.iter()
+            .map(|(id, (watts, _))| match self.capacities.get(id) {
+                Some((capacity, _)) if capacity.battery_capacity_wh > 0.0 => {
+                    watts.min((capacity.stored_wh + capacity.solar_forecast_wh) / FORECAST_HOURS)
+                }
+                _ => *watts,
+            })
+            .sum();
+        let demands: Vec<(String, Demand)> = self.requests.iter().map(|(id, (d, _, _))| (id.clone(), *d)).collect();
+        allocate(supply, &demands)
+    }
+}
+
+/// Divide `supply` among `demands`, street-wide and tier by tier: loads of the highest
+/// community criticality first, whichever node they are on, then lower ones, then each
+/// node's Critical loads, then the rest. A tier that can't be fully met is scaled down
+/// across nodes and the tiers below it get nothing.
+pub fn allocate(supply: f32, demands: &[(String, Demand)]) -> Vec<(String, f32)> {
+    let tiers: Vec<Vec<f32>> = demands.iter().map(|(_, d)| d.tiers()).collect();
+    let mut budgets = vec![0.0; demands.len()];
+    let mut remaining = supply;
+    for tier in 0..tiers.first().map_or(0, Vec::len) {
+        let total: f32 = tiers.iter().map(|t| t[tier]).sum();
+        if total <= 0.0 {
+            continue;
+        }
+        let share = (remaining / total).min(1.0);
+        for (budget, node_tiers) in budgets.iter_mut().zip(&tiers) {
+            *budget += node_tiers[tier] * share;
+        }
+        remaining = (remaining - total).max(0.0);
+    }
+    demands.iter().map(|(id, _)| id.clone()).zip(budgets).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn demand(requested_watts: f32, critical_watts: f32) -> Demand {
+        Demand { requested_watts, critical_watts, ..Default::default() }
+    }
+
+    #[test]
+    fn test_critical_loads_are_funded_first() {
+        let demands = vec![
+            ("node_01".to_string(), demand(3000.0, 1000.0)),
+            ("node_02".to_string(), demand(1000.0, 0.0)),
+        ];
+        // Enough for critical loads plus half of the rest
+        let grants = allocate(2500.0, &demands);
+        assert_eq!(grants, vec![("node_01".to_string(), 2000.0), ("node_02".to_string(), 500.0)]);
+
+        // Not even the critical loads fit
+        let grants = allocate(500.0, &demands);
+        assert_eq!(grants, vec![("node_01".to_string(), 500.0), ("node_02".to_string(), 0.0)]);
+
+        // Surplus isn't handed out beyond what was asked
+        assert_eq!(allocate(10_000.0, &demands)[1].1, 1000.0);
+    }
+
+    #[test]
+    fn test_community_critical_loads_outrank_every_node_own_critical_loads() {
+        // Two houses down, a dialysis machine; here, a fridge marked Critical by its owner
+        let dialysis = Demand { requested_watts: 1500.0, critical_watts: 0.0, community_watts: [0.0, 0.0, 600.0] };
+        let demands = vec![
+            ("node_01".to_string(), demand(2000.0, 800.0)),
+            ("node_03".to_string(), dialysis),
+        ];
+        let grants = allocate(1000.0, &demands);
+        assert_eq!(grants, vec![("node_01".to_string(), 400.0), ("node_03".to_string(), 600.0)]);
+
+        // Not enough for the dialysis machine: it still gets everything there is
+        assert_eq!(allocate(300.0, &demands)[1].1, 300.0);
+    }
+
+    #[test]
+    fn test_stale_adverts_are_dropped() {
+        let mut ledger = PowerLedger::default();
+        ledger.offer("node_01", 2000.0, 0);
+        ledger.offer("node_02", 1000.0, 100);
+        ledger.request("node_03", demand(5000.0, 0.0), 100, 100);
+        assert_eq!(ledger.allocate(120), vec![("node_03".to_string(), 1000.0)]);
+    }
+
+    #[test]
+    fn test_battery_offers_are_capped_to_last_the_horizon() {
+        let mut ledger = PowerLedger::default();
+        ledger.offer("node_01", 5000.0, 0);
+        ledger.offer("node_02", 1000.0, 0);
+        // 6 kWh left and 6 kWh of sun to come: 1 kW sustained over twelve hours
+        let capacity = Capacity { battery_capacity_wh: 10_000.0, stored_wh: 6000.0, solar_forecast_wh: 6000.0, ..Default::default() };
+        ledger.capacity("node_01", capacity, 0);
+        ledger.request("node_03", demand(10_000.0, 0.0), 0, 0);
+        assert_eq!(ledger.allocate(10), vec![("node_03".to_string(), 2000.0)]);
+    }
+
+    #[test]
+    fn test_only_grants_answering_a_newer_request_are_kept() {
+        let grant = |request_timestamp, timestamp, budget_watts| PowerGrant {
+            target_node_id: "node_03".to_string(),
+            coordinator_id: "node_01".to_string(),
+            term: 1,
+            budget_watts,
+            timestamp,
+            request_timestamp,
+        };
+        let mut ledger = PowerLedger::default();
+        assert!(ledger.grant(grant(100, 105, 2000.0), 110));
+        // Re-granted from the same request as supply changes
+        assert!(ledger.grant(grant(100, 115, 1500.0), 120));
+        assert_eq!(ledger.take_grant("node_03").unwrap().budget_watts, 1500.0);
+
+        // Replayed later: the earlier grant, or one answering an outdated request
+        assert!(!ledger.grant(grant(100, 105, 2000.0), 130));
+        assert!(!ledger.grant(grant(100, 500, 9000.0), 100 + ADVERT_TTL_SECS + 1));
+        assert!(ledger.take_grant("node_03").is_none());
+    }
+}
@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Voltage below which the grid is considered lost
+pub const DEFAULT_UNDERVOLTAGE_V: f32 = 110.0;
+
+/// When the node decides the grid has failed. Defaults trip on the first reading below
+/// 110 V and clear as soon as one is back above it, with frequency unsupervised.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProtectionSettings {
+    #[serde(default = "default_undervoltage_v")]
+    pub undervoltage_v: f32,
+    /// Acceptable grid frequency; unsupervised if unset or the sensor can't measure it
+    #[serde(default)]
+    pub frequency_min_hz: Option<f32>,
+    #[serde(default)]
+    pub frequency_max_hz: Option<f32>,
+    /// Consecutive abnormal readings (5s apart) before tripping, so a momentary sag doesn't island us
+    #[serde(default = "default_debounce_readings")]
+    pub debounce_readings: u32,
+    /// How far back inside the limits a reading must be to clear a trip, so a grid hovering
+    /// at a threshold doesn't chatter
+    #[serde(default)]
+    pub hysteresis_v: f32,
+    #[serde(default)]
+    pub hysteresis_hz: f32,
+    /// Seconds an under-voltage is ridden through before it counts as a grid failure;
+    /// shorter sags are only recorded
+    #[serde(default)]
+    pub ride_through_secs: f32,
+}
+
+fn default_undervoltage_v() -> f32 {
+    DEFAULT_UNDERVOLTAGE_V
+}
+
+fn default_debounce_readings() -> u32 {
+    1
+}
+
+impl Default for ProtectionSettings {
+    fn default() -> Self {
+        Self {
+            undervoltage_v: DEFAULT_UNDERVOLTAGE_V,
+            frequency_min_hz: None,
+            frequency_max_hz: None,
+            debounce_readings: default_debounce_readings(),
+            hysteresis_v: 0.0,
+            hysteresis_hz: 0.0,
+            ride_through_secs: 0.0,
+        }
+    }
+}
+
+/// Which limit a reading broke.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Excursion {
+    Undervoltage,
+    Underfrequency,
+    Overfrequency,
+}
+
+impl Excursion {
+    /// Alarm code for the excursion
+    pub fn code(&self) -> &'static str {
+        match self {
+            Excursion::Undervoltage => "undervoltage",
+            Excursion::Underfrequency => "underfrequency",
+            Excursion::Overfrequency => "overfrequency",
+        }
+    }
+}
+
+/// A dip below the under-voltage threshold, from its first low reading to its recovery.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sag {
+    /// Unix seconds of the first low reading
+    pub start: i64,
+    pub duration: Duration,
+    pub min_voltage: f32,
+    /// Outlasted the ride-through window
+    pub escalated: bool,
+}
+
+#[derive(Debug, Clone)]
+struct SagInProgress {
+    start: i64,
+    began: Instant,
+    min_voltage: f32,
+    escalated: bool,
+}
+
+/// Grid-loss detection over successive readings, with debounce and hysteresis.
+#[derive(Debug, Clone, Default)]
+pub struct Protection {
+    pub settings: ProtectionSettings,
+    /// Consecutive abnormal readings so far
+    abnormal: u32,
+    tripped: Option<Excursion>,
+    sag: Option<SagInProgress>,
+}
+
+impl Protection {
+    pub fn new(settings: ProtectionSettings) -> Self {
+        Self { settings, abnormal: 0, tripped: None, sag: None }
+    }
+
+    /// Follow a sag through a reading taken at `now` (`unix` in Unix seconds); returns
+    /// the sag once the voltage is back above the threshold.
+    pub fn track_sag(&mut self, volts: f32, unix: i64, now: Instant) -> Option<Sag> {
+        let ride_through = Duration::from_secs_f32(self.settings.ride_through_secs.max(0.0));
+        if volts < self.settings.undervoltage_v {
+            let sag = self.sag.get_or_insert(SagInProgress { start: unix, began: now, min_voltage: volts, escalated: false });
+            sag.min_voltage = sag.min_voltage.min(volts);
+            sag.escalated |= now.saturating_duration_since(sag.began) >= ride_through;
+            return None;
+        }
+        let sag = self.sag.take()?;
+        Some(Sag {
+            start: sag.start,
+            duration: now.saturating_duration_since(sag.began),
+            min_voltage: sag.min_voltage,
+            escalated: sag.escalated,
+        })
+    }
+
+    /// Whether the voltage is low but not for long enough yet to count as a grid failure.
+    pub fn riding_through(&self) -> bool {
+        self.sag.as_ref().is_some_and(|sag| !sag.escalated)
+    }
+
+    /// Take a reading; returns the excursion while tripped.
+    pub fn observe(&mut self, volts: f32, hz: Option<f32>) -> Option<Excursion> {
+        match (self.tripped, self.excursion(volts, hz, 0.0)) {
+            (None, Some(excursion)) => {
+                self.abnormal += 1;
+                if self.abnormal >= self.settings.debounce_readings.max(1) {
+                    self.tripped = Some(excursion);
+                }
+            }
+            (None, None) => self.abnormal = 0,
+            // Clears only once the reading is back by the hysteresis margin
+            (Some(_), _) => {
+                if self.excursion(volts, hz, 1.0).is_none() {
+                    self.tripped = None;
+                    self.abnormal = 0;
+                }
+            }
+        }
+        self.tripped
+    }
+
+    /// What an excursion looks like, for alarms.
+    pub fn describe(&self, excursion: Excursion, volts: f32, hz: Option<f32>) -> String {
+        let s = &self.settings;
+        let hz = hz.unwrap_or_default();
+        match excursion {
+            Excursion::Undervoltage => format!("{:.1}V below {:.1}V threshold", volts, s.undervoltage_v),
+            Excursion::Underfrequency => format!("{:.2} Hz below {:.2} Hz", hz, s.frequency_min_hz.unwrap_or_default()),
+            Excursion::Overfrequency => format!("{:.2} Hz above {:.2} Hz", hz, s.frequency_max_hz.unwrap_or_default()),
+        }
+    }
+
+    /// The limit a reading breaks, with the limits pulled inward by `hysteresis` times the margins.
+    fn excursion(&self, volts: f32, hz: Option<f32>, hysteresis: f32) -> Option<Excursion> {
+        let s = &self.settings;
+        if volts < s.undervoltage_v + hysteresis * s.hysteresis_v {
+            return Some(Excursion::Undervoltage);
+        }
+        let hz = hz?;
+        if s.frequency_min_hz.is_some_and(|min| hz < min + hysteresis * s.hysteresis_hz) {
+            return Some(Excursion::Underfrequency);
+        }
+        if s.frequency_max_hz.is_some_and(|max| hz > max - hysteresis * s.hysteresis_hz) {
+            return Some(Excursion::Overfrequency);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debounce_and_hysteresis() {
+        let mut protection = Protection::new(ProtectionSettings {
+            frequency_min_hz: Some(59.3),
+            debounce_readings: 2,
+            hysteresis_v: 3.0,
+            hysteresis_hz: 0.2,
+            ..Default::default()
+        });
+        // One low reading is a blip; two in a row trip
+        assert_eq!(protection.observe(100.0, Some(60.0)), None);
+        assert_eq!(protection.observe(119.0, Some(60.0)), None);
+        assert_eq!(protection.observe(100.0, None), None);
+        assert_eq!(protection.observe(105.0, None), Some(Excursion::Undervoltage));
+        // Back above 110 V but not 113 V: still tripped
+        assert_eq!(protection.observe(111.0, None), Some(Excursion::Undervoltage));
+        assert_eq!(protection.observe(114.0, None), None);
+
+        assert_eq!(protection.observe(120.0, Some(59.0)), None);
+        assert_eq!(protection.observe(120.0, Some(59.1)), Some(Excursion::Underfrequency));
+        assert_eq!(protection.observe(120.0, Some(59.4)), Some(Excursion::Underfrequency));
+        assert_eq!(protection.observe(120.0, Some(59.6)), None);
+    }
+
+    #[test]
+    fn test_short_sags_are_ridden_through_but_recorded() {
+        let mut protection = Protection::new(ProtectionSettings { ride_through_secs: 10.0, ..Default::default() });
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        // A 5 s dip to 95 V: never escalated, but reported once it is over
+        assert_eq!(protection.track_sag(100.0, 1000, at(0)), None);
+        assert!(protection.riding_through());
+        assert_eq!(protection.track_sag(95.0, 1005, at(5)), None);
+        let sag = protection.track_sag(118.0, 1010, at(10)).unwrap();
+        assert_eq!(sag, Sag { start: 1000, duration: Duration::from_secs(10), min_voltage: 95.0, escalated: false });
+        assert!(!protection.riding_through());
+        assert_eq!(protection.track_sag(118.0, 1015, at(15)), None);
+
+        // One low for 10 s or more counts as a failure
+        for secs in (20..=30).step_by(5) {
+            protection.track_sag(90.0, 1000 + secs as i64, at(secs));
+        }
+        assert!(!protection.riding_through());
+        assert!(protection.track_sag(120.0, 1035, at(35)).unwrap().escalated);
+    }
+}
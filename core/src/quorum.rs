@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+
+/// Observations older than this no longer count towards a quorum.
+pub const VOTE_TTL_SECS: i64 = 60;
+
+/// How often a node under voltage repeats its observation, so votes stay fresh.
+pub const OBSERVATION_RESEND_SECS: i64 = 30;
+
+/// A neighbour's latest voltage observation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vote {
+    pub voltage: f32,
+    pub undervoltage: bool,
+    /// When the sender took it, by its clock; a later observation must be newer
+    pub observed_at: i64,
+    /// Local receive time, for the TTL
+    pub received_at: i64,
+}
+
+/// Latest voltage observation from each neighbour, for deciding together whether to island.
+#[derive(Debug, Default)]
+pub struct VoteTable {
+    votes: BTreeMap<String, Vote>,
+}
+
+impl VoteTable {
+    /// Record an observation taken at `observed_at`, replacing the sender's previous one.
+    /// One already older than the TTL, or no newer than the sender's last, is a replay
+    /// and ignored; returns whether it was recorded.
+    pub fn record(&mut self, node_id: &str, voltage: f32, undervoltage: bool, observed_at: i64, now: i64) -> bool {
+        if (now - observed_at).abs() > VOTE_TTL_SECS {
+            return false;
+        }
+        if self.votes.get(node_id).is_some_and(|v| observed_at <= v.observed_at) {
+            return false;
+        }
+        self.votes.insert(node_id.to_string(), Vote { voltage, undervoltage, observed_at, received_at: now });
+        true
+    }
+
+    /// Neighbours whose latest observation, still within the TTL, is under-voltage.
+    pub fn agreeing(&mut self, now: i64) -> Vec<String> {
+        self.votes.retain(|_, v| now - v.received_at <= VOTE_TTL_SECS);
+        self.votes.iter().filter(|(_, v)| v.undervoltage).map(|(id, _)| id.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_fresh_undervoltage_votes_agree() {
+        let mut votes = VoteTable::default();
+        votes.record("node_02", 95.0, true, 0, 0);
+        votes.record("node_03", 97.0, true, 30, 30);
+        votes.record("node_04", 121.0, false, 30, 30);
+        assert_eq!(votes.agreeing(40), vec!["node_02", "node_03"]);
+
+        // node_03 recovers, node_02's vote goes stale
+        votes.record("node_03", 119.0, false, 50, 50);
+        assert!(votes.agreeing(VOTE_TTL_SECS + 1).is_empty());
+    }
+
+    #[test]
+    fn test_replayed_observations_are_ignored() {
+        let mut votes = VoteTable::default();
+        // Recorded during an earlier outage, replayed now
+        assert!(!votes.record("node_02", 95.0, true, 1_000, 1_000 + VOTE_TTL_SECS + 1));
+        assert!(votes.agreeing(2_000).is_empty());
+
+        // Within the TTL, but no newer than what node_02 last said
+        assert!(votes.record("node_02", 119.0, false, 2_000, 2_000));
+        assert!(!votes.record("node_02", 95.0, true, 1_990, 2_010));
+        assert!(!votes.record("node_02", 95.0, true, 2_000, 2_010));
+        assert!(votes.agreeing(2_010).is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Window over which command rates are counted.
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Commands of one type allowed per minute unless configured otherwise.
+pub const DEFAULT_PER_MINUTE: u32 = 10;
+/// How long the breaker stays open after a limit is exceeded.
+pub const DEFAULT_LOCKOUT: Duration = Duration::from_secs(300);
+
+/// Outcome of checking a command against the limits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Allow,
+    /// This command exceeded its limit and opened the breaker
+    Tripped,
+    /// The breaker is open; the command is dropped
+    LockedOut,
+}
+
+/// Per-command-type sliding-window limits with a circuit breaker.
+/// Safety-critical commands (those that only open relays) are never limited.
+pub struct CommandLimiter {
+    default_limit: u32,
+    limits: HashMap<String, u32>,
+    lockout: Duration,
+    history: HashMap<&'static str, VecDeque<Instant>>,
+    locked_until: Option<Instant>,
+}
+
+impl CommandLimiter {
+    pub fn new(default_limit: u32, limits: HashMap<String, u32>, lockout: Duration) -> Self {
+        Self {
+            default_limit,
+            limits,
+            lockout,
+            history: HashMap::new(),
+            locked_until: None,
+        }
+    }
+
+    pub fn check(&mut self, kind: &'static str, safety_critical: bool, now: Instant) -> Verdict {
+        if safety_critical {
+            return Verdict::Allow;
+        }
+        if self.locked_until.is_some_and(|until| now < until) {
+            return Verdict::LockedOut;
+        }
+        self.locked_until = None;
+
+        let limit = self.limits.get(kind).copied().unwrap_or(self.default_limit);
+        let times = self.history.entry(kind).or_default();
+        while times.front().is_some_and(|&t| now.duration_since(t) >= WINDOW) {
+            times.pop_front();
+        }
+        if times.len() as u32 >= limit {
+            // Start over once the lockout ends rather than tripping again immediately
+            self.history.clear();
+            self.locked_until = Some(now + self.lockout);
+            return Verdict::Tripped;
+        }
+        times.push_back(now);
+        Verdict::Allow
+    }
+}
+
+impl Default for CommandLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_PER_MINUTE, HashMap::new(), DEFAULT_LOCKOUT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flood_trips_breaker_until_lockout_ends() {
+        let mut limiter = CommandLimiter::new(3, HashMap::from([("audit_log_request".to_string(), 1)]), Duration::from_secs(300));
+        let t0 = Instant::now();
+
+        for i in 0..3 {
+            assert_eq!(limiter.check("activate_relay_by_index", false, t0 + Duration::from_secs(i)), Verdict::Allow);
+        }
+        assert_eq!(limiter.check("activate_relay_by_index", false, t0 + Duration::from_secs(3)), Verdict::Tripped);
+
+        // Everything but safety-critical opens is locked out
+        assert_eq!(limiter.check("snapshot_request", false, t0 + Duration::from_secs(10)), Verdict::LockedOut);
+        assert_eq!(limiter.check("load_shed", true, t0 + Duration::from_secs(10)), Verdict::Allow);
+
+        let after = t0 + Duration::from_secs(304);
+        assert_eq!(limiter.check("activate_relay_by_index", false, after), Verdict::Allow);
+
+        // Per-type override
+        assert_eq!(limiter.check("audit_log_request", false, after), Verdict::Allow);
+        assert_eq!(limiter.check("audit_log_request", false, after), Verdict::Tripped);
+    }
+
+    #[test]
+    fn test_old_commands_leave_the_window() {
+        let mut limiter = CommandLimiter::new(2, HashMap::new(), Duration::from_secs(300));
+        let t0 = Instant::now();
+        for i in 0..10 {
+            assert_eq!(limiter.check("load_shed", false, t0 + Duration::from_secs(i * 31)), Verdict::Allow);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Current through a relay commanded open above which its contacts must be welded shut
+pub const OPEN_CIRCUIT_AMPS: f32 = 0.5;
+
+/// Current below which a closed relay on a load that always draws hasn't made contact
+pub const NO_CURRENT_AMPS: f32 = 0.1;
+
+/// Checks in a row that must disagree with the command before a relay is failed, so a
+/// reading taken as the contacts move isn't taken for one
+const CHECKS_TO_FAIL: u32 = 3;
+
+/// How a relay's contacts fail to follow its commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelayFailure {
+    /// Conducting while commanded open
+    Welded,
+    /// Not conducting while commanded closed
+    FailedOpen,
+}
+
+impl RelayFailure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RelayFailure::Welded => "welded",
+            RelayFailure::FailedOpen => "failed_open",
+        }
+    }
+}
+
+/// What gave a relay's contacts away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Evidence {
+    /// The auxiliary contact read back the other position
+    Feedback,
+    /// The CT clamp on its circuit
+    Current,
+}
+
+impl Evidence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Evidence::Feedback => "feedback",
+            Evidence::Current => "current",
+        }
+    }
+}
+
+/// One check of a relay: what it was commanded to, and what was measured of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RelayCheck {
+    pub commanded_closed: bool,
+    /// Auxiliary contact position, if it has a feedback input
+    pub feedback_closed: Option<bool>,
+    /// Current on its circuit, if it has a CT clamp
+    pub amps: Option<f32>,
+}
+
+/// Checks relays against their feedback inputs and CT readings, and keeps those whose
+/// contacts don't follow their commands out of use until an operator clears them.
+#[derive(Debug, Default)]
+pub struct RelayHealth {
+    /// Loads that draw current whenever they are closed, so none means no contact
+    always_drawing: Vec<String>,
+    /// Checks in a row that disagreed, and how, per relay
+    suspect: HashMap<String, (RelayFailure, Evidence, u32)>,
+    failed: BTreeMap<String, (RelayFailure, Evidence)>,
+}
+
+impl RelayHealth {
+    pub fn new(always_drawing: Vec<String>) -> Self {
+        Self { always_drawing, ..Default::default() }
+    }
+
+    /// Fold in a check of a relay not yet failed. Returns how it failed once it has
+    /// disagreed with its command for long enough.
+    pub fn observe(&mut self, relay_id: &str, check: RelayCheck) -> Option<(RelayFailure, Evidence)> {
+        if self.failed.contains_key(relay_id) {
+            return None;
+        }
+        let Some((failure, evidence)) = self.judge(relay_id, check) else {
+            self.suspect.remove(relay_id);
+            return None;
+        };
+        let entry = self.suspect.entry(relay_id.to_string()).or_insert((failure, evidence, 0));
+        if entry.0 != failure {
+            *entry = (failure, evidence, 0);
+        }
+        entry.2 += 1;
+        if entry.2 < CHECKS_TO_FAIL {
+            return None;
+        }
+        self.suspect.remove(relay_id);
+        self.failed.insert(relay_id.to_string(), (failure, evidence));
+        Some((failure, evidence))
+    }
+
+    /// The auxiliary contact is believed over the current, which a load may not be drawing.
+    fn judge(&self, relay_id: &str, check: RelayCheck) -> Option<(RelayFailure, Evidence)> {
+        let failure = if check.commanded_closed { RelayFailure::FailedOpen } else { RelayFailure::Welded };
+        if let Some(closed) = check.feedback_closed {
+            return (closed != check.commanded_closed).then_some((failure, Evidence::Feedback));
+        }
+        let amps = check.amps?.abs();
+        let disagrees = if check.commanded_closed {
+            amps < NO_CURRENT_AMPS && self.always_drawing.iter().any(|id| id == relay_id)
+        } else {
+            amps > OPEN_CIRCUIT_AMPS
+        };
+        disagrees.then_some((failure, Evidence::Current))
+    }
+
+    /// Relays found failed, which nothing but an operator may close.
+    pub fn failed(&self) -> Vec<String> {
+        self.failed.keys().cloned().collect()
+    }
+
+    pub fn failure(&self, relay_id: &str) -> Option<RelayFailure> {
+        self.failed.get(relay_id).map(|(failure, _)| *failure)
+    }
+
+    /// Put a repaired relay back in use; false if it wasn't failed.
+    pub fn clear(&mut self, relay_id: &str) -> bool {
+        self.failed.remove(relay_id).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contacts_that_disagree_with_their_command_fail_the_relay() {
+        let mut health = RelayHealth::new(vec!["r_fridge".to_string()]);
+        let check = |commanded_closed, feedback_closed, amps| RelayCheck { commanded_closed, feedback_closed, amps };
+
+        // Current flowing through an open relay, though not long enough to fail it
+        assert_eq!(health.observe("r_hvac", check(false, None, Some(12.0))), None);
+        assert_eq!(health.observe("r_hvac", check(false, None, Some(0.0))), None);
+        assert_eq!(health.observe("r_hvac", check(false, None, Some(12.0))), None);
+        assert_eq!(health.observe("r_hvac", check(false, None, Some(12.0))), None);
+        assert_eq!(health.observe("r_hvac", check(false, None, Some(12.0))), Some((RelayFailure::Welded, Evidence::Current)));
+
+        // A load that may simply be off says nothing; one that always draws does
+        for _ in 0..CHECKS_TO_FAIL {
+            assert_eq!(health.observe("r_tv", check(true, None, Some(0.0))), None);
+        }
+        health.observe("r_fridge", check(true, None, Some(0.0)));
+        health.observe("r_fridge", check(true, None, Some(0.0)));
+        assert_eq!(health.observe("r_fridge", check(true, None, Some(0.0))), Some((RelayFailure::FailedOpen, Evidence::Current)));
+
+        // The auxiliary contact outranks the current
+        for _ in 0..CHECKS_TO_FAIL - 1 {
+            health.observe("r_pump", check(false, Some(true), Some(0.0)));
+        }
+        assert_eq!(health.observe("r_pump", check(false, Some(true), Some(0.0))), Some((RelayFailure::Welded, Evidence::Feedback)));
+
+        assert_eq!(health.failed(), vec!["r_fridge".to_string(), "r_hvac".to_string(), "r_pump".to_string()]);
+        assert_eq!(health.failure("r_fridge"), Some(RelayFailure::FailedOpen));
+        assert!(health.clear("r_hvac"));
+        assert!(!health.clear("r_hvac"));
+    }
+}
@@ -0,0 +1,163 @@
+use log::{error, info, warn};
+use sha2::{Digest, Sha256};
+use prost::Message;
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+use crate::comms::{HeartbeatReadings, NeighborhoodMessage, OrchestratorClient};
+use crate::node::NEIGHBOR_REPORT_INTERVAL;
+use crate::sysinfo::SystemMonitor;
+
+/// Messages remembered for duplicate suppression.
+pub const SEEN_CAPACITY: usize = 256;
+
+/// How often a repeater heartbeats and reports its forwarding counters.
+const TELEMETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What a repeater has done since boot, reported in place of relay telemetry.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RepeaterStats {
+    pub forwarded: u32,
+    /// Already forwarded once (heard again from another node or repeater)
+    pub duplicates: u32,
+    /// Failed authentication; never forwarded
+    pub rejected: u32,
+}
+
+/// Digests of recently forwarded messages, so each is repeated once and repeaters
+/// in range of each other don't echo traffic forever.
+#[derive(Debug, Default)]
+pub struct SeenCache {
+    order: VecDeque<[u8; 32]>,
+    seen: HashSet<[u8; 32]>,
+}
+
+impl SeenCache {
+    /// Remember `msg`; false if it was already seen, directly or through a repeater.
+    pub fn insert(&mut self, msg: &NeighborhoodMessage) -> bool {
+        let frame = NeighborhoodMessage { relay_hops: 0, ..msg.clone() };
+        let digest: [u8; 32] = Sha256::digest(frame.encode_to_vec()).into();
+        if !self.seen.insert(digest) {
+            return false;
+        }
+        if self.order.len() >= SEEN_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.order.push_back(digest);
+        true
+    }
+}
+
+/// Dedicated LoRa repeater: no relays or sensors, it floods authenticated mesh traffic
+/// onward once and reports its own telemetry.
+pub struct Repeater {
+    id: String,
+    client: OrchestratorClient,
+    seen: SeenCache,
+    pub stats: RepeaterStats,
+    sysinfo: SystemMonitor,
+}
+
+impl Repeater {
+    pub fn new(id: &str, client: OrchestratorClient, sysinfo: SystemMonitor) -> Self {
+        Self { id: id.to_string(), client, seen: SeenCache::default(), stats: RepeaterStats::default(), sysinfo }
+    }
+
+    pub async fn run(&mut self) {
+        info!("Repeater {} forwarding mesh traffic", self.id);
+        let mut poll_interval = tokio::time::interval(Duration::from_millis(100));
+        let mut telemetry_interval = tokio::time::interval(TELEMETRY_INTERVAL);
+        let mut neighbor_interval = tokio::time::interval(NEIGHBOR_REPORT_INTERVAL);
+        neighbor_interval.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = telemetry_interval.tick() => self.send_telemetry().await,
+
+                _ = neighbor_interval.tick() => {
+                    if let Err(e) = self.client.send_neighbor_report(&self.id).await {
+                        error!("Failed to send neighbor report: {}", e);
+                    }
+                }
+
+                // Same low-frequency poll as the edge node until the radio driver is interrupt-driven
+                _ = poll_interval.tick() => self.poll().await,
+            }
+        }
+    }
+
+    /// Forward the next message heard, if it should be.
+    pub async fn poll(&mut self) {
+        match self.client.receive_for_forwarding().await {
+            Ok(Some((mut msg, trusted))) if self.admit(&msg, trusted) => {
+                // So receivers don't take the echo for a replay or us for its sender
+                msg.relay_hops = msg.relay_hops.saturating_add(1);
+                if let Err(e) = self.client.forward(msg).await {
+                    error!("Failed to forward message: {}", e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Receive failed: {}", e),
+        }
+    }
+
+    /// Whether to forward `msg`, counting what happened to it.
+    pub fn admit(&mut self, msg: &NeighborhoodMessage, trusted: bool) -> bool {
+        if !trusted {
+            self.stats.rejected += 1;
+            return false;
+        }
+        if !self.seen.insert(msg) {
+            self.stats.duplicates += 1;
+            return false;
+        }
+        self.stats.forwarded += 1;
+        true
+    }
+
+    async fn send_telemetry(&self) {
+        // No battery gauge on a repeater; reported full like an edge node without one
+        let heartbeat = self.client.heartbeat(&self.id, 1.0, self.sysinfo.collect(), None, None, &HeartbeatReadings::default());
+        if let Err(e) = self.client.send_heartbeat(heartbeat).await {
+            error!("Failed to send heartbeat: {}", e);
+        }
+        if let Err(e) = self.client.send_repeater_report(&self.id, &self.stats).await {
+            error!("Failed to send repeater report: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comms::{Heartbeat, MockCommunication};
+    use crate::comms::streetgrid::neighborhood_message::Payload;
+    use std::sync::Arc;
+
+    fn heartbeat(node_id: &str) -> NeighborhoodMessage {
+        NeighborhoodMessage {
+            payload: Some(Payload::Heartbeat(Heartbeat { node_id: node_id.to_string(), ..Default::default() })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_each_message_is_forwarded_once() {
+        let client = OrchestratorClient::new(Arc::new(MockCommunication::default()));
+        let mut repeater = Repeater::new("rep_01", client, SystemMonitor::default());
+        assert!(repeater.admit(&heartbeat("node_01"), true));
+        assert!(!repeater.admit(&heartbeat("node_01"), true));
+        // Another repeater's copy differs only in its hop count
+        assert!(!repeater.admit(&NeighborhoodMessage { relay_hops: 1, ..heartbeat("node_01") }, true));
+        assert!(repeater.admit(&heartbeat("node_02"), true));
+        assert!(!repeater.admit(&heartbeat("node_03"), false));
+        assert_eq!(repeater.stats, RepeaterStats { forwarded: 2, duplicates: 2, rejected: 1 });
+
+        // The oldest digests make room for new ones
+        for i in 0..SEEN_CAPACITY {
+            repeater.admit(&heartbeat(&format!("filler_{}", i)), true);
+        }
+        assert!(repeater.admit(&heartbeat("node_01"), true));
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Storage key of the last accepted counter per sender.
+pub const REPLAY_KEY: &str = "replay_counters.json";
+
+/// Consecutive stale commands from one sender before we assume its counter went
+/// backwards (e.g. a restored orchestrator database) rather than an attack.
+pub const DESYNC_THRESHOLD: u32 = 3;
+
+/// Outcome of checking a command's counter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Freshness {
+    Fresh,
+    /// Counter at or below the last accepted one
+    Replayed,
+    /// Repeated stale counters; ask the orchestrator to re-authorize with this challenge
+    Desynced { last_counter: u64, challenge: [u8; 16] },
+}
+
+/// Last accepted command counter per sender. Persisted so a reboot doesn't reopen
+/// the window for replaying old commands.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplayGuard {
+    counters: BTreeMap<String, u64>,
+    #[serde(skip)]
+    stale: BTreeMap<String, u32>,
+    /// Outstanding re-authorization challenge per sender
+    #[serde(skip)]
+    challenges: BTreeMap<String, [u8; 16]>,
+}
+
+impl ReplayGuard {
+    /// Accept `counter` from `sender` only if it is newer than anything seen before.
+    /// `new_challenge` supplies random bytes if a desync has to be declared.
+    pub fn check(&mut self, sender: &str, counter: u64, new_challenge: impl FnOnce() -> [u8; 16]) -> Freshness {
+        let last = self.counters.get(sender).copied().unwrap_or(0);
+        if counter > last {
+            self.counters.insert(sender.to_string(), counter);
+            self.stale.remove(sender);
+            return Freshness::Fresh;
+        }
+        let stale = self.stale.entry(sender.to_string()).or_default();
+        *stale += 1;
+        if *stale >= DESYNC_THRESHOLD && !self.challenges.contains_key(sender) {
+            let challenge = new_challenge();
+            self.challenges.insert(sender.to_string(), challenge);
+            return Freshness::Desynced { last_counter: last, challenge };
+        }
+        Freshness::Replayed
+    }
+
+    /// Re-seat `sender`'s counter, but only in answer to the challenge we issued.
+    pub fn reset(&mut self, sender: &str, counter: u64, challenge: &[u8]) -> bool {
+        if self.challenges.get(sender).map(|c| c.as_slice()) != Some(challenge) {
+            return false;
+        }
+        self.challenges.remove(sender);
+        self.stale.remove(sender);
+        self.counters.insert(sender.to_string(), counter);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replays_are_rejected_across_restarts() {
+        let mut guard = ReplayGuard::default();
+        assert_eq!(guard.check("orch", 5, || [0; 16]), Freshness::Fresh);
+        assert_eq!(guard.check("orch", 5, || [0; 16]), Freshness::Replayed);
+        assert_eq!(guard.check("orch", 6, || [0; 16]), Freshness::Fresh);
+        // Senders are tracked independently
+        assert_eq!(guard.check("gateway", 1, || [0; 16]), Freshness::Fresh);
+
+        let mut restored: ReplayGuard = serde_json::from_slice(&serde_json::to_vec(&guard).unwrap()).unwrap();
+        assert_eq!(restored.check("orch", 6, || [0; 16]), Freshness::Replayed);
+        assert_eq!(restored.check("gateway", 1, || [0; 16]), Freshness::Replayed);
+    }
+
+    #[test]
+    fn test_desync_needs_answered_challenge() {
+        let mut guard = ReplayGuard::default();
+        guard.check("orch", 1000, || [0; 16]);
+
+        // Orchestrator restored from backup, counting from 10 again
+        assert_eq!(guard.check("orch", 10, || [1; 16]), Freshness::Replayed);
+        assert_eq!(guard.check("orch", 11, || [1; 16]), Freshness::Replayed);
+        assert_eq!(guard.check("orch", 12, || [1; 16]), Freshness::Desynced { last_counter: 1000, challenge: [1; 16] });
+        // One challenge at a time
+        assert_eq!(guard.check("orch", 13, || [2; 16]), Freshness::Replayed);
+
+        assert!(!guard.reset("orch", 13, &[2; 16]));
+        assert!(guard.reset("orch", 13, &[1; 16]));
+        assert_eq!(guard.check("orch", 14, || [0; 16]), Freshness::Fresh);
+        // A captured reset can't be replayed
+        assert!(!guard.reset("orch", 0, &[1; 16]));
+    }
+}
@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use crate::interlock;
+use crate::types::{Priority, Relay, RelayType};
+
+fn default_tiers() -> Vec<ReserveTier> {
+    vec![
+        ReserveTier { below_soc: 0.6, action: ReserveAction::ShedLow },
+        ReserveTier { below_soc: 0.4, action: ReserveAction::ShedMedium },
+        ReserveTier { below_soc: 0.2, action: ReserveAction::CriticalOnly },
+        ReserveTier { below_soc: 0.1, action: ReserveAction::PrepareShutdown },
+    ]
+}
+
+fn default_recovery_margin() -> f32 {
+    0.03
+}
+
+/// What the node does to stretch the battery through an island.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReserveAction {
+    /// Open Low priority loads
+    ShedLow,
+    /// Open Medium and Low priority loads
+    ShedMedium,
+    /// Keep only Critical loads and those the neighbourhood relies on
+    CriticalOnly,
+    /// As `CriticalOnly`, alarming that the battery is about to run out
+    PrepareShutdown,
+}
+
+impl ReserveAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReserveAction::ShedLow => "shed_low",
+            ReserveAction::ShedMedium => "shed_medium",
+            ReserveAction::CriticalOnly => "critical_only",
+            ReserveAction::PrepareShutdown => "prepare_shutdown",
+        }
+    }
+
+    /// Highest priority opened in this tier.
+    fn sheds_from(&self) -> Priority {
+        match self {
+            ReserveAction::ShedLow => Priority::Low,
+            ReserveAction::ShedMedium => Priority::Medium,
+            ReserveAction::CriticalOnly | ReserveAction::PrepareShutdown => Priority::High,
+        }
+    }
+}
+
+/// A band of battery charge and what is done in it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ReserveTier {
+    /// The tier is in force while the charge (0..1) is below this
+    pub below_soc: f32,
+    pub action: ReserveAction,
+}
+
+/// Tiers of loads given up as the battery runs down while islanded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReserveSettings {
+    /// Default: shed Low below 60%, Medium below 40%, critical only below 20%, and
+    /// prepare to shut down below 10%
+    #[serde(default = "default_tiers")]
+    pub tiers: Vec<ReserveTier>,
+    /// How far the charge must climb back above a tier's band before it is left, so it
+    /// doesn't flap as the battery hovers at the boundary (default 0.03)
+    #[serde(default = "default_recovery_margin")]
+    pub recovery_margin: f32,
+}
+
+impl Default for ReserveSettings {
+    fn default() -> Self {
+        Self { tiers: default_tiers(), recovery_margin: default_recovery_margin() }
+    }
+}
+
+/// Sheds loads by the tier the battery's charge is in while islanded, and closes them
+/// again as it recovers or the grid comes back.
+#[derive(Debug)]
+pub struct ReservePolicy {
+    pub settings: ReserveSettings,
+    tier: Option<ReserveAction>,
+    /// Relays opened for the tier in force
+    shed: Vec<(String, Priority)>,
+}
+
+impl ReservePolicy {
+    pub fn new(settings: ReserveSettings) -> Self {
+        Self { settings, tier: None, shed: Vec::new() }
+    }
+
+    /// Action of the tier in force, if any.
+    pub fn tier(&self) -> Option<ReserveAction> {
+        self.tier
+    }
+
+    /// Record the battery's charge. Returns the tier now in force, if that changed.
+    pub fn record(&mut self, soc: f32, islanded: bool) -> Option<Option<ReserveAction>> {
+        let tier = if islanded {
+            self.settings.tiers.iter()
+                .filter(|t| {
+                    // The tier in force, and those it is past, hold until the margin is made up
+                    let held = self.tier.is_some_and(|action| action >= t.action);
+                    soc < t.below_soc + if held { self.settings.recovery_margin } else { 0.0 }
+                })
+                .map(|t| t.action)
+                .max()
+        } else {
+            None
+        };
+        (tier != self.tier).then(|| {
+            self.tier = tier;
+            tier
+        })
+    }
+
+    /// Loads to open for the tier in force, and loads shed for a deeper tier to close
+    /// again now that the battery has recovered.
+    pub fn tick(&mut self, relays: &[Relay]) -> (Vec<String>, Vec<String>) {
+        // Loads closed again by someone else are no longer ours to restore
+        self.shed.retain(|(id, _)| relays.iter().any(|r| r.id == *id && !r.is_closed));
+        let sheds_from = self.tier.map(|action| action.sheds_from());
+        let covered = |priority: Priority| sheds_from.is_some_and(|from| priority >= from);
+
+        let (keep, close): (Vec<_>, Vec<_>) = std::mem::take(&mut self.shed).into_iter()
+            .partition(|(_, priority)| covered(*priority));
+        self.shed = keep;
+        let open: Vec<(String, Priority)> = relays.iter()
+            .filter(|r| r.relay_type == RelayType::Load && r.is_closed && covered(r.priority))
+            .filter(|r| !interlock::protected_from_budget(r))
+            .map(|r| (r.id.clone(), r.priority))
+            .collect();
+        self.shed.extend(open.iter().cloned());
+        (open.into_iter().map(|(id, _)| id).collect(), close.into_iter().map(|(id, _)| id).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loads_are_given_up_tier_by_tier_as_the_battery_runs_down() {
+        let load = |id: &str, priority: Priority| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage: 10.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        };
+        let mut relays = vec![
+            load("r_oxygen", Priority::Critical),
+            load("r_fridge", Priority::High),
+            load("r_hvac", Priority::Medium),
+            load("r_tv", Priority::Low),
+        ];
+        let mut policy = ReservePolicy::new(ReserveSettings::default());
+        let apply = |relays: &mut Vec<Relay>, policy: &mut ReservePolicy| {
+            let (open, close) = policy.tick(relays);
+            for relay in relays.iter_mut() {
+                if open.contains(&relay.id) {
+                    relay.is_closed = false;
+                } else if close.contains(&relay.id) {
+                    relay.is_closed = true;
+                }
+            }
+            relays.iter().filter(|r| r.is_closed).map(|r| r.id.clone()).collect::<Vec<_>>()
+        };
+
+        // On the grid, a flat battery is no reason to shed
+        assert_eq!(policy.record(0.05, false), None);
+        assert_eq!(apply(&mut relays, &mut policy).len(), 4);
+
+        assert_eq!(policy.record(0.55, true), Some(Some(ReserveAction::ShedLow)));
+        assert_eq!(apply(&mut relays, &mut policy), ["r_oxygen", "r_fridge", "r_hvac"]);
+        assert_eq!(policy.record(0.15, true), Some(Some(ReserveAction::CriticalOnly)));
+        assert_eq!(apply(&mut relays, &mut policy), ["r_oxygen"]);
+        assert_eq!(policy.record(0.08, true), Some(Some(ReserveAction::PrepareShutdown)));
+        assert_eq!(apply(&mut relays, &mut policy), ["r_oxygen"]);
+
+        // Solar brings it back: the tier holds until the charge is clear of its band
+        assert_eq!(policy.record(0.21, true), Some(Some(ReserveAction::CriticalOnly)));
+        assert_eq!(policy.record(0.22, true), None);
+        assert_eq!(policy.record(0.45, true), Some(Some(ReserveAction::ShedLow)));
+        assert_eq!(apply(&mut relays, &mut policy), ["r_oxygen", "r_fridge", "r_hvac"]);
+
+        // Back on the grid, everything is put back
+        assert_eq!(policy.record(0.45, false), Some(None));
+        assert_eq!(policy.tier(), None);
+        assert_eq!(apply(&mut relays, &mut policy).len(), 4);
+    }
+}
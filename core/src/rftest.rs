@@ -0,0 +1,268 @@
+//! RF test modes for installers and labs: an unmodulated carrier and back-to-back PN9
+//! packets for emissions and antenna measurements, and a packet error rate count at
+//! fixed settings between two nodes, so no separate tooling is needed.
+
+use log::{info, warn};
+use serde::Serialize;
+use std::fmt;
+use std::time::{Duration, Instant};
+use crate::hal::{HalError, LoRaRadio};
+
+/// Marks a packet error rate test frame
+const PER_MAGIC: &[u8; 4] = b"SGPR";
+/// Length of every PER frame, so each has the same airtime
+const PER_FRAME_LEN: usize = 32;
+/// Between PER frames, so a receiver at SF12 still keeps up
+const PER_TX_GAP: Duration = Duration::from_millis(100);
+/// Length of the PN9 packets sent back to back
+const PN9_FRAME_LEN: usize = 255;
+/// Between polls of the receiver, and of the clock while a carrier is on
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// How long a test runs when not told
+pub const DEFAULT_DURATION: Duration = Duration::from_secs(30);
+
+/// The first `len` bytes of the PN9 sequence (x^9 + x^5 + 1, seeded with ones), as
+/// test equipment expects it.
+pub fn pn9(len: usize) -> Vec<u8> {
+    let mut lfsr: u16 = 0x1FF;
+    (0..len)
+        .map(|_| {
+            let mut byte = 0u8;
+            for bit in 0..8 {
+                byte |= ((lfsr & 1) as u8) << bit;
+                let feedback = (lfsr ^ (lfsr >> 5)) & 1;
+                lfsr = (lfsr >> 1) | (feedback << 8);
+            }
+            byte
+        })
+        .collect()
+}
+
+/// PER frame `seq`: magic, sequence number, then PN9 to a fixed length.
+fn per_frame(seq: u32) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(PER_FRAME_LEN);
+    frame.extend_from_slice(PER_MAGIC);
+    frame.extend_from_slice(&seq.to_be_bytes());
+    frame.extend(pn9(PER_FRAME_LEN - frame.len()));
+    frame
+}
+
+/// Sequence number of a PER frame, if that's what it is.
+fn per_sequence(frame: &[u8]) -> Option<u32> {
+    let seq = frame.strip_prefix(PER_MAGIC)?.get(..4)?;
+    Some(u32::from_be_bytes(seq.try_into().unwrap()))
+}
+
+/// What an RF test did. For a PER count, `expected` spans the first to the last
+/// sequence number heard, so frames sent before the receiver started don't count.
+#[derive(Debug, Default, Serialize)]
+pub struct RfTestReport {
+    pub mode: String,
+    pub secs: f64,
+    pub sent: u32,
+    pub received: u32,
+    pub expected: u32,
+    /// PER frames that arrived with different bytes than were sent
+    pub corrupted: u32,
+    pub last_rssi: Option<i16>,
+    /// Driver errors, in order
+    pub errors: Vec<String>,
+}
+
+impl RfTestReport {
+    fn new(mode: &str) -> Self {
+        Self { mode: mode.to_string(), ..Default::default() }
+    }
+
+    /// Share of the expected PER frames not received intact, once any were heard.
+    pub fn per(&self) -> Option<f64> {
+        (self.expected > 0).then(|| 1.0 - self.received as f64 / self.expected as f64)
+    }
+
+    pub fn passed(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    fn error(&mut self, what: &str, e: HalError) {
+        warn!("RF test: {} failed: {}", what, e);
+        self.errors.push(format!("{}: {}", what, e));
+    }
+}
+
+impl fmt::Display for RfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} for {:.1} s", if self.passed() { "PASS" } else { "FAIL" }, self.mode, self.secs)?;
+        if self.sent > 0 {
+            write!(f, ", {} frames sent", self.sent)?;
+        }
+        if let Some(per) = self.per() {
+            write!(f, ", {}/{} received, {} corrupted, PER {:.2}%", self.received, self.expected, self.corrupted, per * 100.0)?;
+        }
+        if let Some(rssi) = self.last_rssi {
+            write!(f, ", last RSSI {} dBm", rssi)?;
+        }
+        for error in &self.errors {
+            write!(f, "\n  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Transmit an unmodulated carrier for `duration`, for frequency and power measurements.
+pub fn run_carrier(radio: &mut dyn LoRaRadio, duration: Duration) -> RfTestReport {
+    let mut report = RfTestReport::new("carrier");
+    let started = Instant::now();
+    match radio.continuous_wave() {
+        Ok(()) => {
+            while started.elapsed() < duration {
+                std::thread::sleep(POLL_INTERVAL.max(duration / 100).min(duration - started.elapsed()));
+            }
+        }
+        Err(e) => report.error("continuous wave", e),
+    }
+    report.secs = started.elapsed().as_secs_f64();
+    report
+}
+
+/// Transmit PN9 packets back to back for `duration`: a modulated signal with the
+/// spectrum of real traffic, for occupied bandwidth and spurious emission checks.
+pub fn run_pn9(radio: &mut dyn LoRaRadio, duration: Duration) -> RfTestReport {
+    let mut report = RfTestReport::new("pn9");
+    let frame = pn9(PN9_FRAME_LEN);
+    let started = Instant::now();
+    while started.elapsed() < duration {
+        match radio.transmit(&frame) {
+            Ok(()) => report.sent += 1,
+            Err(e) => {
+                report.error(&format!("transmit frame {}", report.sent), e);
+                break;
+            }
+        }
+    }
+    report.secs = started.elapsed().as_secs_f64();
+    report
+}
+
+/// Send numbered PER frames for `duration`, for a node running `run_per_rx` to count.
+pub fn run_per_tx(radio: &mut dyn LoRaRadio, duration: Duration) -> RfTestReport {
+    let mut report = RfTestReport::new("per-tx");
+    let started = Instant::now();
+    while started.elapsed() < duration {
+        if let Err(e) = radio.transmit(&per_frame(report.sent)) {
+            report.error(&format!("transmit frame {}", report.sent), e);
+            break;
+        }
+        report.sent += 1;
+        std::thread::sleep(PER_TX_GAP.min(duration.saturating_sub(started.elapsed())));
+    }
+    report.secs = started.elapsed().as_secs_f64();
+    report
+}
+
+/// Count the PER frames received intact over `duration`.
+pub fn run_per_rx(radio: &mut dyn LoRaRadio, duration: Duration) -> RfTestReport {
+    let mut report = RfTestReport::new("per-rx");
+    let mut span: Option<(u32, u32)> = None;
+    let started = Instant::now();
+    while started.elapsed() < duration {
+        match radio.receive() {
+            Ok(Some(frame)) => {
+                let Some(seq) = per_sequence(&frame) else {
+                    report.corrupted += 1;
+                    continue;
+                };
+                span = Some(match span {
+                    Some((first, last)) => (first.min(seq), last.max(seq)),
+                    None => (seq, seq),
+                });
+                if frame == per_frame(seq) {
+                    report.received += 1;
+                    report.last_rssi = radio.last_rssi();
+                } else {
+                    report.corrupted += 1;
+                }
+            }
+            Ok(None) => std::thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                report.error("receive", e);
+                break;
+            }
+        }
+    }
+    report.expected = span.map(|(first, last)| last - first + 1).unwrap_or(0);
+    report.secs = started.elapsed().as_secs_f64();
+    report
+}
+
+/// Run one RF test on `radio` for `duration`, leaving it in standby.
+pub fn run(mode: &str, mut radio: Box<dyn LoRaRadio>, duration: Duration) -> RfTestReport {
+    info!("RF test: {} for {:?}", mode, duration);
+    let mut report = match mode {
+        "carrier" => run_carrier(radio.as_mut(), duration),
+        "pn9" => run_pn9(radio.as_mut(), duration),
+        "per-tx" => run_per_tx(radio.as_mut(), duration),
+        "per-rx" => run_per_rx(radio.as_mut(), duration),
+        other => {
+            let mut report = RfTestReport::new(other);
+            report.errors.push(format!("unknown RF test mode {}", other));
+            return report;
+        }
+    };
+    if let Err(e) = radio.standby() {
+        report.error("standby", e);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::lora::mock::MockLoRaRadio;
+    use crate::hal::LoRaHalConfig;
+
+    #[test]
+    fn test_pn9_matches_the_standard_sequence() {
+        assert_eq!(pn9(8), vec![0xFF, 0xE1, 0x1D, 0x9A, 0xED, 0x85, 0x33, 0x24]);
+        // Repeats every 511 bits
+        let long = pn9(1022);
+        assert_eq!(long[..511], long[511..]);
+        assert_eq!(per_sequence(&per_frame(42)), Some(42));
+        assert_eq!(per_frame(7).len(), PER_FRAME_LEN);
+    }
+
+    #[test]
+    fn test_per_counts_frames_lost_between_two_nodes() {
+        let (mut tx, mut rx) = MockLoRaRadio::pair(LoRaHalConfig::default()).unwrap();
+        for seq in 3..13 {
+            match seq {
+                // Lost on the air, and one garbled
+                5 | 9 => {}
+                11 => rx.inject_rx(b"SGPR\0\0\0\x0bgarbled".to_vec()),
+                _ => tx.transmit(&per_frame(seq)).unwrap(),
+            }
+        }
+        let report = run_per_rx(&mut rx, Duration::from_millis(20));
+        assert_eq!((report.received, report.expected, report.corrupted), (7, 10, 1));
+        assert!((report.per().unwrap() - 0.3).abs() < 1e-9);
+        assert!(report.passed(), "{}", report);
+
+        let report = run_per_tx(&mut tx, Duration::from_millis(250));
+        assert!((2..=3).contains(&report.sent), "{}", report.sent);
+        assert_eq!(per_sequence(&rx.receive().unwrap().unwrap()), Some(0));
+    }
+
+    #[test]
+    fn test_carrier_and_pn9_transmit_for_the_duration() {
+        let mut radio = MockLoRaRadio::new(LoRaHalConfig::default()).unwrap();
+        let report = run_carrier(&mut radio, Duration::from_millis(20));
+        assert!(report.passed(), "{}", report);
+        assert!(radio.carrier_on());
+        assert!(report.secs >= 0.02);
+
+        let report = run("pn9", Box::new(radio), Duration::from_millis(5));
+        assert!(report.sent > 0);
+        assert!(report.passed(), "{}", report);
+        assert!(!run("sweep", Box::new(MockLoRaRadio::new(LoRaHalConfig::default()).unwrap()), Duration::ZERO).passed());
+    }
+}
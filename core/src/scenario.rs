@@ -0,0 +1,766 @@
+use log::{error, info, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use crate::capture::ReplayLink;
+use crate::clock::{Clock, VirtualClock};
+use crate::config::SourcesConfig;
+use crate::election::Election;
+use crate::disturbance::{Disturbance, GridState};
+use crate::comms::streetgrid::neighborhood_message::Payload;
+use crate::comms::{
+    ActivateRelayByIndex, ActivateRelayByPriority, CommunicationLayer, DemandResponse, EnterBlackStart, EnterIsland,
+    LoadShed, NeighborhoodMessage, OrchestratorClient,
+};
+use crate::faults::{FaultSwitch, Faults, FaultyLink, FaultyRelays, FaultySensor};
+use crate::hal::gpio::mock::MockRelayDriver;
+use crate::hal::battery::mock::SimBattery;
+use crate::hal::{self, HalError, PowerSensor};
+use crate::load_model::LoadModel;
+use crate::medium::{Medium, MediumLink, RadioConfig, SimRng};
+use crate::node::{EdgeNode, NodeError};
+use crate::protection::ProtectionSettings;
+use crate::repeater::Repeater;
+use crate::sources::{SourceManager, DEFAULT_RESERVE_SOC};
+use crate::sysinfo::SystemMonitor;
+use crate::types::{MeshType, NodeState, Relay};
+
+/// Simulated time advances in steps of the fastest task, the message poll
+const STEP: Duration = Duration::from_millis(100);
+
+/// Nodes of a street come up at random within this many steps of each other
+const BOOT_SPREAD_STEPS: u64 = 50;
+
+/// Station of the orchestrator's gateway on the simulated radio
+const ORCHESTRATOR: &str = "orchestrator";
+
+/// Why a scenario could not be loaded or run, or did not pass.
+#[derive(Debug, Error)]
+pub enum ScenarioError {
+    #[error("reading scenario {path}: {source}")]
+    Read { path: String, source: std::io::Error },
+    #[error("parsing scenario {path}: {source}")]
+    Parse { path: String, source: serde_yaml::Error },
+    #[error("node {id}: {source}")]
+    Node { id: String, source: NodeError },
+    #[error("scenario \"{name}\": {failures} expectations failed")]
+    Failed { name: String, failures: usize },
+}
+
+/// A scripted run of a node, or of a street of them sharing a radio: timed events,
+/// and checkpoints saying what relays, state and the orchestrator should show by
+/// then. Times are seconds from the start.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    /// ID of the only node, when `nodes` is empty
+    #[serde(default = "default_node_id")]
+    pub node_id: String,
+    #[serde(default = "default_mesh_type")]
+    pub mesh_type: MeshType,
+    /// Line voltage until an event changes it
+    #[serde(default = "default_voltage")]
+    pub voltage: f32,
+    /// Grid frequency in Hz until a disturbance changes it
+    #[serde(default = "default_frequency")]
+    pub frequency: f32,
+    /// Grid-failure detection of every node, to tune against disturbances
+    #[serde(default)]
+    pub protection: Option<ProtectionSettings>,
+    /// Relays of every node that does not list its own
+    #[serde(default)]
+    pub relays: Vec<Relay>,
+    /// ADC channel of the CT clamp on each relay's circuit, for load events
+    #[serde(default)]
+    pub ct_channels: HashMap<String, u8>,
+    /// How each relay's circuit draws current while closed, read on its CT channel
+    #[serde(default)]
+    pub loads: HashMap<String, LoadModel>,
+    /// Battery and solar of every node that does not list its own; the battery is
+    /// simulated, drained by island load and charged by the panels
+    #[serde(default)]
+    pub sources: Option<SourcesConfig>,
+    /// Nodes elect a coordinator to share power while the orchestrator is unreachable
+    #[serde(default)]
+    pub election: bool,
+    #[serde(default)]
+    pub nodes: Vec<NodeSpec>,
+    #[serde(default)]
+    pub radio: RadioConfig,
+    /// Where the orchestrator's gateway stands, in metres
+    #[serde(default)]
+    pub orchestrator: [f32; 2],
+    /// Capture whose received frames the orchestrator's gateway transmits again, at their
+    /// original spacing from the start; relative to the scenario file
+    #[serde(default)]
+    pub replay: Option<String>,
+    /// Unix seconds the virtual clock starts at
+    #[serde(default = "default_start")]
+    pub start: i64,
+    #[serde(default)]
+    pub events: Vec<Event>,
+    #[serde(default)]
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+fn default_node_id() -> String {
+    "sim_node".to_string()
+}
+
+fn default_mesh_type() -> MeshType {
+    MeshType::AdHoc
+}
+
+fn default_voltage() -> f32 {
+    120.0
+}
+
+fn default_frequency() -> f32 {
+    60.0
+}
+
+fn default_start() -> i64 {
+    1_700_000_000
+}
+
+/// A station of a simulated street.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NodeSpec {
+    pub id: String,
+    /// Position in metres
+    #[serde(default)]
+    pub at: [f32; 2],
+    #[serde(default)]
+    pub relays: Option<Vec<Relay>>,
+    #[serde(default)]
+    pub ct_channels: Option<HashMap<String, u8>>,
+    #[serde(default)]
+    pub loads: Option<HashMap<String, LoadModel>>,
+    #[serde(default)]
+    pub sources: Option<SourcesConfig>,
+    /// Neighbours that must agree on under-voltage for the node to island on its own
+    #[serde(default)]
+    pub island_quorum: Option<usize>,
+    /// Forwards mesh traffic rather than running relays
+    #[serde(default)]
+    pub repeater: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Event {
+    pub at: u64,
+    /// Station it happens to; every one if unset
+    #[serde(default)]
+    pub node: Option<String>,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Line voltage the node measures from now on
+    Voltage(f32),
+    /// Current drawn on a CT channel from now on, replacing the load model on its circuit
+    Load { channel: u8, amps: f32 },
+    /// A station's radio, or every one including the orchestrator's, goes off or on
+    Radio(RadioState),
+    /// A command from the orchestrator, addressed to the node; lost while the radio is down
+    Command(ScriptedCommand),
+    /// Hardware and radio faults in effect from now on; an empty set clears them
+    Fault(Faults),
+    /// A sag, swell, frequency excursion or flicker, starting now from the line as it is
+    Disturbance(Disturbance),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RadioState {
+    Up,
+    Down,
+}
+
+/// The orchestrator commands a scenario can send.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptedCommand {
+    LoadShed(bool),
+    EnterIsland,
+    EnterBlackStart,
+    ActivateRelay(u32),
+    ActivatePriority(i32),
+    /// Start and end in seconds from the scenario's start
+    DemandResponse {
+        event_id: String,
+        shed_priority: i32,
+        start: i64,
+        end: i64,
+        #[serde(default)]
+        cancel: bool,
+        /// Cap on the node's draw while the event runs; 0 for none
+        #[serde(default)]
+        max_watts: f32,
+        /// Shedding strategy by name; empty for the node's default
+        #[serde(default)]
+        strategy: String,
+    },
+}
+
+impl ScriptedCommand {
+    fn payload(&self, node_id: &str, scenario_start: i64) -> Payload {
+        let target_node_id = node_id.to_string();
+        match self {
+            ScriptedCommand::LoadShed(shed_load) => Payload::LoadShed(LoadShed { target_node_id, shed_load: *shed_load }),
+            ScriptedCommand::EnterIsland => Payload::EnterIsland(EnterIsland { target_node_id }),
+            ScriptedCommand::EnterBlackStart => Payload::EnterBlackStart(EnterBlackStart { target_node_id, step_id: String::new() }),
+            ScriptedCommand::ActivateRelay(relay_index) => {
+                Payload::ActivateRelayByIndex(ActivateRelayByIndex { target_node_id, relay_index: *relay_index, step_id: String::new() })
+            }
+            ScriptedCommand::ActivatePriority(priority) => {
+                Payload::ActivateRelayByPriority(ActivateRelayByPriority { target_node_id, priority: *priority, step_id: String::new() })
+            }
+            ScriptedCommand::DemandResponse { event_id, shed_priority, start, end, cancel, max_watts, strategy } => Payload::DemandResponse(DemandResponse {
+                target_node_id,
+                event_id: event_id.clone(),
+                shed_priority: *shed_priority,
+                start: scenario_start + start,
+                end: scenario_start + end,
+                cancel: *cancel,
+                max_watts: *max_watts,
+                strategy: strategy.clone(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Position {
+    Open,
+    Closed,
+}
+
+/// What must hold at `at`, for one node or every one. Messages are those the
+/// orchestrator received since the previous checkpoint, named in snake_case
+/// (`voltage_alert`, `heartbeat`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct Checkpoint {
+    pub at: u64,
+    #[serde(default)]
+    pub node: Option<String>,
+    #[serde(default)]
+    pub relays: BTreeMap<String, Position>,
+    #[serde(default)]
+    pub state: Option<NodeState>,
+    /// Bounds on the battery's state of charge, 0.0 to 1.0
+    #[serde(default)]
+    pub soc_below: Option<f32>,
+    #[serde(default)]
+    pub soc_above: Option<f32>,
+    #[serde(default)]
+    pub sent: Vec<String>,
+    #[serde(default)]
+    pub not_sent: Vec<String>,
+}
+
+/// How a run went: checkpoints evaluated, and each expectation that did not hold.
+#[derive(Debug, Default)]
+pub struct Outcome {
+    pub checkpoints: usize,
+    pub failures: Vec<String>,
+}
+
+/// CT clamps reading whatever the scenario last set, and the line's frequency.
+struct SimSensor {
+    amps: Arc<Mutex<[f32; 4]>>,
+    voltage: f32,
+    hz: Arc<Mutex<f32>>,
+}
+
+impl PowerSensor for SimSensor {
+    fn read_raw(&mut self, _channel: u8) -> hal::Result<i16> {
+        Err(HalError::Unsupported("raw reading of a simulated sensor"))
+    }
+
+    fn read_current_amps(&mut self, channel: u8) -> hal::Result<f32> {
+        Ok(self.amps.lock().unwrap().get(channel as usize).copied().unwrap_or(0.0))
+    }
+
+    fn read_watts(&mut self, channel: u8) -> hal::Result<f32> {
+        Ok(self.read_current_amps(channel)? * self.voltage)
+    }
+
+    fn read_frequency_hz(&mut self) -> hal::Result<f32> {
+        Ok(*self.hz.lock().unwrap())
+    }
+}
+
+/// Name of a message's payload in snake_case, e.g. `voltage_alert`.
+fn payload_kind(payload: &Payload) -> String {
+    let debug = format!("{:?}", payload);
+    let variant = debug.split('(').next().unwrap_or_default();
+    let mut kind = String::new();
+    for (i, c) in variant.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            kind.push('_');
+        }
+        kind.push(c.to_ascii_lowercase());
+    }
+    kind
+}
+
+enum Role {
+    Node { node: Box<EdgeNode>, amps: Arc<Mutex<[f32; 4]>>, hz: Arc<Mutex<f32>> },
+    Repeater(Box<Repeater>),
+}
+
+/// A disturbance under way: when it began and the line it began from.
+struct Disturbed {
+    since: Duration,
+    from: GridState,
+    disturbance: Disturbance,
+}
+
+/// A modelled circuit: its relay, CT channel, and when the relay last closed.
+struct Circuit {
+    relay_id: String,
+    channel: u8,
+    model: LoadModel,
+    on_since: Option<Duration>,
+}
+
+/// A virtual node or repeater, and when it boots.
+struct Station {
+    id: String,
+    boot: Duration,
+    role: Role,
+    faults: FaultSwitch,
+    disturbed: Option<Disturbed>,
+    circuits: Vec<Circuit>,
+}
+
+impl Station {
+    fn new(spec: &NodeSpec, scenario: &Scenario, link: Arc<MediumLink>, clock: &VirtualClock, boot: Duration) -> Result<Self, ScenarioError> {
+        let faults = FaultSwitch::default();
+        let mut client = OrchestratorClient::new(Arc::new(FaultyLink::new(link, faults.clone()))).with_origin(&spec.id);
+        client.set_clock(Arc::new(clock.clone()));
+        if spec.repeater {
+            let repeater = Repeater::new(&spec.id, client, SystemMonitor::default());
+            return Ok(Self { id: spec.id.clone(), boot, role: Role::Repeater(Box::new(repeater)), faults, disturbed: None, circuits: Vec::new() });
+        }
+        let amps = Arc::new(Mutex::new([0.0; 4]));
+        let hz = Arc::new(Mutex::new(scenario.frequency));
+        let sensor = SimSensor { amps: amps.clone(), voltage: scenario.voltage, hz: hz.clone() };
+        let relays = spec.relays.clone().unwrap_or_else(|| scenario.relays.clone());
+        // Each relay on the pin of its index
+        let relay_pins = relays.iter().enumerate().map(|(i, r)| (r.id.clone(), i as u8)).collect();
+        let driver = MockRelayDriver::new(&[]).expect("mock relay driver");
+        let mut node = EdgeNode::builder(&spec.id)
+            .relays(relays)
+            .relay_pins(relay_pins)
+            .client(client)
+            .relay_driver(Box::new(FaultyRelays::new(Box::new(driver), faults.clone())))
+            .power_sensor(Box::new(FaultySensor::new(Box::new(sensor), faults.clone())))
+            .voltage_ref(scenario.voltage)
+            .mesh_type(scenario.mesh_type.clone())
+            .protection(scenario.protection.clone().unwrap_or_default())
+            .build()
+            .map_err(|source| ScenarioError::Node { id: spec.id.clone(), source })?;
+        node.ct_channels = spec.ct_channels.clone().unwrap_or_else(|| scenario.ct_channels.clone());
+        let mut circuits: Vec<Circuit> = spec.loads.as_ref().unwrap_or(&scenario.loads).iter()
+            .filter_map(|(relay_id, model)| match node.ct_channels.get(relay_id) {
+                Some(&channel) => Some(Circuit { relay_id: relay_id.clone(), channel, model: model.clone(), on_since: None }),
+                None => {
+                    warn!("{}: no CT channel for the load on {}, ignoring it", spec.id, relay_id);
+                    None
+                }
+            })
+            .collect();
+        circuits.sort_by_key(|c| c.channel);
+        node.island_quorum = spec.island_quorum;
+        if let Some(sources) = spec.sources.as_ref().or(scenario.sources.as_ref()) {
+            node.sources = Some(SourceManager::new(
+                sources.battery_capacity_wh.unwrap_or(0.0),
+                sources.reserve_soc.unwrap_or(DEFAULT_RESERVE_SOC),
+                sources.solar_peak_watts.unwrap_or(0.0),
+            ));
+            if let Some(battery) = sources.battery() {
+                node.battery = Some(Box::new(SimBattery::new(battery)));
+            }
+        }
+        if scenario.election {
+            node.election = Some(Election::new(&spec.id, clock.unix()));
+        }
+        node.set_clock(Arc::new(clock.clone()));
+        node.invariant_checks = true;
+        Ok(Self { id: spec.id.clone(), boot, role: Role::Node { node: Box::new(node), amps, hz }, faults, disturbed: None, circuits })
+    }
+
+    /// Set each modelled circuit's current, `elapsed` into the run: what its model
+    /// draws that long after its relay closed, or nothing while it is open.
+    fn draw(&mut self, elapsed: Duration) {
+        let Role::Node { node, amps, .. } = &self.role else { return };
+        let mut amps = amps.lock().unwrap();
+        for circuit in &mut self.circuits {
+            let closed = node.relays.iter().any(|r| r.id == circuit.relay_id && r.is_closed);
+            circuit.on_since = closed.then(|| circuit.on_since.unwrap_or(elapsed));
+            if let Some(slot) = amps.get_mut(circuit.channel as usize) {
+                *slot = circuit.on_since.map_or(0.0, |since| circuit.model.amps_at((elapsed - since).as_secs_f32()));
+            }
+        }
+    }
+
+    /// Move the line along any disturbance under way, `elapsed` into the run.
+    fn disturb(&mut self, elapsed: Duration) {
+        let (Some(disturbed), Role::Node { node, hz, .. }) = (&self.disturbed, &mut self.role) else { return };
+        let t = (elapsed - disturbed.since).as_secs_f32();
+        let grid = disturbed.disturbance.at(t, disturbed.from).unwrap_or(disturbed.from);
+        node.voltage_ref = grid.volts;
+        *hz.lock().unwrap() = grid.hz;
+        if t >= disturbed.disturbance.duration(disturbed.from) {
+            self.disturbed = None;
+        }
+    }
+
+    /// Everything due `since_boot` into the station's life.
+    async fn step(&mut self, since_boot: Duration) {
+        match &mut self.role {
+            Role::Node { node, .. } => {
+                if since_boot.is_zero() {
+                    node.start().await;
+                }
+                node.run_due(since_boot).await;
+            }
+            Role::Repeater(repeater) => repeater.poll().await,
+        }
+    }
+
+    fn node(&self) -> Option<&EdgeNode> {
+        match &self.role {
+            Role::Node { node, .. } => Some(node),
+            Role::Repeater(_) => None,
+        }
+    }
+}
+
+/// Run the scenario's nodes on a virtual clock and a shared simulated radio, in
+/// simulated time.
+pub async fn run(scenario: &Scenario) -> Outcome {
+    let single = [NodeSpec {
+        id: scenario.node_id.clone(),
+        at: [0.0, 0.0],
+        relays: None,
+        ct_channels: None,
+        loads: None,
+        sources: None,
+        island_quorum: None,
+        repeater: false,
+    }];
+    let specs = if scenario.nodes.is_empty() { &single[..] } else { &scenario.nodes[..] };
+    let clock = VirtualClock::at(scenario.start);
+    let medium = Medium::new(scenario.radio.clone());
+    let gateway = medium.join(ORCHESTRATOR, scenario.orchestrator);
+    let mut rng = SimRng::new(scenario.radio.seed);
+    let stations: Result<Vec<Station>, _> = specs.iter()
+        .map(|spec| {
+            let boot = if specs.len() > 1 { STEP * (rng.next_u64() % BOOT_SPREAD_STEPS) as u32 } else { Duration::ZERO };
+            Station::new(spec, scenario, medium.join(&spec.id, spec.at), &clock, boot)
+        })
+        .collect();
+
+    let mut outcome = Outcome::default();
+    let mut stations = match stations {
+        Ok(stations) => stations,
+        Err(e) => {
+            outcome.failures.push(e.to_string());
+            return outcome;
+        }
+    };
+    let replay = match scenario.replay.as_deref().map(|path| ReplayLink::open(path, Arc::new(clock.clone()))).transpose() {
+        Ok(replay) => replay,
+        Err(e) => {
+            outcome.failures.push(format!("replay: {}", e));
+            return outcome;
+        }
+    };
+
+    let mut events: Vec<&Event> = scenario.events.iter().collect();
+    events.sort_by_key(|e| e.at);
+    let mut checkpoints: Vec<&Checkpoint> = scenario.checkpoints.iter().collect();
+    checkpoints.sort_by_key(|c| c.at);
+    let end = events.iter().map(|e| e.at).chain(checkpoints.iter().map(|c| c.at)).max().unwrap_or(0);
+    let (mut events, mut checkpoints) = (events.into_iter().peekable(), checkpoints.into_iter().peekable());
+
+    // What reached the orchestrator since the last checkpoint: origin and message kind
+    let mut received: Vec<(Option<String>, String)> = Vec::new();
+    let mut elapsed = Duration::ZERO;
+    while elapsed.as_secs() <= end {
+        let now = elapsed.as_secs();
+        let whole_second = elapsed.subsec_nanos() == 0;
+        while let Some(event) = events.next_if(|e| whole_second && e.at == now) {
+            apply(event, &mut stations, &medium, &gateway, scenario.start, elapsed).await;
+        }
+        if let Some(replay) = &replay {
+            while let Ok(Some(msg)) = replay.receive().await {
+                if gateway.send(msg).await.is_err() {
+                    warn!("t={}s: replayed frame lost, the orchestrator's radio is down", now);
+                }
+            }
+        }
+        for station in &mut stations {
+            station.disturb(elapsed);
+            station.draw(elapsed);
+            if let Some(since_boot) = elapsed.checked_sub(station.boot) {
+                station.step(since_boot).await;
+            }
+        }
+        medium.propagate();
+        while let Ok(Some(msg)) = gateway.receive().await {
+            if let Some(payload) = &msg.payload {
+                received.push((medium.origin(&msg), payload_kind(payload)));
+            }
+        }
+        let mut checked = false;
+        while let Some(checkpoint) = checkpoints.next_if(|c| whole_second && c.at == now) {
+            let sent: Vec<String> = received.iter()
+                .filter(|(origin, _)| checkpoint.node.is_none() || *origin == checkpoint.node)
+                .map(|(_, kind)| kind.clone())
+                .collect();
+            outcome.checkpoints += 1;
+            for station in stations.iter().filter(|s| checkpoint.node.as_ref().is_none_or(|id| *id == s.id)) {
+                if let Some(node) = station.node() {
+                    outcome.failures.extend(check(checkpoint, node));
+                }
+            }
+            outcome.failures.extend(check_sent(checkpoint, &sent));
+            checked = true;
+        }
+        if checked {
+            received.clear();
+            medium.clear_origins();
+        }
+        clock.advance(STEP);
+        elapsed += STEP;
+    }
+    outcome
+}
+
+async fn apply(event: &Event, stations: &mut [Station], medium: &Medium, gateway: &MediumLink, scenario_start: i64, elapsed: Duration) {
+    let at = event.at;
+    match &event.action {
+        Action::Radio(state) => match &event.node {
+            Some(id) => medium.set_up(id, *state == RadioState::Up),
+            None => {
+                for id in std::iter::once(ORCHESTRATOR).chain(stations.iter().map(|s| s.id.as_str())) {
+                    medium.set_up(id, *state == RadioState::Up);
+                }
+            }
+        },
+        Action::Fault(faults) => {
+            for station in stations.iter().filter(|s| event.node.as_ref().is_none_or(|id| *id == s.id)) {
+                station.faults.set(faults.clone());
+            }
+        }
+        action => {
+            for station in stations.iter_mut().filter(|s| event.node.as_ref().is_none_or(|id| *id == s.id)) {
+                let Role::Node { node, amps, hz } = &mut station.role else { continue };
+                match action {
+                    Action::Voltage(volts) => node.voltage_ref = *volts,
+                    Action::Load { channel, amps: drawn } => {
+                        station.circuits.retain(|c| c.channel != *channel);
+                        if let Some(slot) = amps.lock().unwrap().get_mut(*channel as usize) {
+                            *slot = *drawn;
+                        }
+                    }
+                    Action::Command(command) => {
+                        let payload = command.payload(&node.id, scenario_start);
+                        let msg = NeighborhoodMessage { payload: Some(payload), ..Default::default() };
+                        if gateway.send(msg).await.is_err() {
+                            warn!("t={}s: {:?} for {} lost, the orchestrator's radio is down", at, command, node.id);
+                        }
+                    }
+                    Action::Disturbance(disturbance) => {
+                        let from = GridState { volts: node.voltage_ref, hz: *hz.lock().unwrap() };
+                        station.disturbed = Some(Disturbed { since: elapsed, from, disturbance: disturbance.clone() });
+                    }
+                    Action::Radio(_) | Action::Fault(_) => {}
+                }
+            }
+        }
+    }
+}
+
+/// Expectations of a checkpoint about a node that do not hold.
+fn check(checkpoint: &Checkpoint, node: &EdgeNode) -> Vec<String> {
+    let at = checkpoint.at;
+    let mut failures = Vec::new();
+    for (relay_id, expected) in &checkpoint.relays {
+        match node.relays.iter().find(|r| &r.id == relay_id) {
+            Some(relay) if relay.is_closed == (*expected == Position::Closed) => {}
+            Some(relay) => failures.push(format!(
+                "t={}s {}: relay {} is {}, expected {:?}",
+                at, node.id, relay_id, if relay.is_closed { "closed" } else { "open" }, expected
+            )),
+            None => failures.push(format!("t={}s {}: no relay {}", at, node.id, relay_id)),
+        }
+    }
+    if let Some(state) = checkpoint.state {
+        if node.state != state {
+            failures.push(format!("t={}s {}: state is {:?}, expected {:?}", at, node.id, node.state, state));
+        }
+    }
+    if let Some(soc) = checkpoint.soc_below.filter(|soc| node.battery_soc >= *soc) {
+        failures.push(format!("t={}s {}: battery at {:.3}, expected below {:.3}", at, node.id, node.battery_soc, soc));
+    }
+    if let Some(soc) = checkpoint.soc_above.filter(|soc| node.battery_soc <= *soc) {
+        failures.push(format!("t={}s {}: battery at {:.3}, expected above {:.3}", at, node.id, node.battery_soc, soc));
+    }
+    failures
+}
+
+/// Expectations of a checkpoint about what the orchestrator heard that do not hold.
+fn check_sent(checkpoint: &Checkpoint, sent: &[String]) -> Vec<String> {
+    let (at, from) = (checkpoint.at, checkpoint.node.as_deref().unwrap_or("any node"));
+    let mut failures = Vec::new();
+    for kind in &checkpoint.sent {
+        if !sent.contains(kind) {
+            failures.push(format!("t={}s: no {} from {} since the previous checkpoint", at, kind, from));
+        }
+    }
+    for kind in &checkpoint.not_sent {
+        if sent.contains(kind) {
+            failures.push(format!("t={}s: {} from {} since the previous checkpoint", at, kind, from));
+        }
+    }
+    failures
+}
+
+/// Load and run a scenario file; fails if any checkpoint does.
+pub async fn run_file(path: &str) -> Result<(), ScenarioError> {
+    let yaml = std::fs::read_to_string(path)
+        .map_err(|source| ScenarioError::Read { path: path.to_string(), source })?;
+    let mut scenario: Scenario = serde_yaml::from_str(&yaml)
+        .map_err(|source| ScenarioError::Parse { path: path.to_string(), source })?;
+    if let (Some(replay), Some(dir)) = (&scenario.replay, std::path::Path::new(path).parent()) {
+        scenario.replay = Some(dir.join(replay).to_string_lossy().into_owned());
+    }
+    info!("Running scenario \"{}\"", scenario.name);
+    let outcome = run(&scenario).await;
+    for failure in &outcome.failures {
+        error!("{}", failure);
+    }
+    if !outcome.failures.is_empty() {
+        return Err(ScenarioError::Failed { name: scenario.name, failures: outcome.failures.len() });
+    }
+    info!("Scenario \"{}\" passed {} checkpoints", scenario.name, outcome.checkpoints);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_brownout_scenario_passes_and_wrong_expectations_fail() {
+        let mut scenario: Scenario = serde_yaml::from_str(include_str!("../../firmware/scenarios/brownout.yaml")).unwrap();
+        let outcome = run(&scenario).await;
+        assert_eq!(outcome.failures, Vec::<String>::new());
+        assert_eq!(outcome.checkpoints, scenario.checkpoints.len());
+
+        scenario.checkpoints[1].state = Some(NodeState::Normal);
+        scenario.checkpoints[1].not_sent.push("voltage_alert".to_string());
+        let outcome = run(&scenario).await;
+        assert_eq!(outcome.failures.len(), 2);
+        assert!(outcome.failures[0].contains("state is AlertSent, expected Normal"));
+    }
+
+    #[tokio::test]
+    async fn test_street_islands_by_quorum_through_the_repeater() {
+        let mut scenario: Scenario = serde_yaml::from_str(include_str!("../../firmware/scenarios/street_quorum.yaml")).unwrap();
+        assert_eq!(run(&scenario).await.failures, Vec::<String>::new());
+
+        // Without the repeater the far node neither registers nor hears its neighbours
+        scenario.nodes.retain(|n| !n.repeater);
+        let failures = run(&scenario).await.failures;
+        assert_eq!(failures.len(), 4);
+        assert!(failures[0].contains("no feature_report from node_04"));
+        assert!(failures[1..].iter().all(|f| f.contains("node_04")));
+    }
+
+    #[tokio::test]
+    async fn test_protection_rides_through_a_momentary_sag() {
+        let mut scenario: Scenario = serde_yaml::from_str(include_str!("../../firmware/scenarios/disturbances.yaml")).unwrap();
+        assert_eq!(run(&scenario).await.failures, Vec::<String>::new());
+
+        // Tripping on a single reading islands on the sag
+        scenario.protection.as_mut().unwrap().debounce_readings = 1;
+        let failures = run(&scenario).await.failures;
+        assert_eq!(failures[0], "t=29s node_01: state is AlertSent, expected Normal");
+    }
+
+    #[tokio::test]
+    async fn test_replayed_capture_drives_the_node() {
+        use crate::capture::{Direction, Frame};
+        use prost::Message;
+
+        let island = NeighborhoodMessage {
+            payload: Some(ScriptedCommand::EnterIsland.payload("node_01", 0)),
+            ..Default::default()
+        };
+        let frames = [(1_000, Direction::Rx, island.clone()), (2_000, Direction::Tx, island.clone()), (9_500, Direction::Rx, island)];
+        let capture: String = frames.into_iter()
+            .map(|(t_ms, dir, msg)| serde_json::to_string(&Frame { t_ms, dir, bytes: msg.encode_to_vec(), rssi: None }).unwrap() + "\n")
+            .collect();
+        let path = std::env::temp_dir().join(format!("streetgrid-scenario-replay-{}.ndjson", std::process::id()));
+        std::fs::write(&path, capture).unwrap();
+
+        // Received frames play from the start; what the node sent is left out
+        let mut scenario: Scenario = serde_yaml::from_str(include_str!("../../firmware/scenarios/brownout.yaml")).unwrap();
+        scenario.events.clear();
+        scenario.replay = Some(path.to_string_lossy().into_owned());
+        scenario.checkpoints = serde_yaml::from_str("[{ at: 1, state: Islanded, relays: { r_grid: open } }]").unwrap();
+        assert_eq!(run(&scenario).await.failures, Vec::<String>::new());
+        std::fs::remove_file(&path).unwrap();
+
+        let outcome = run(&scenario).await;
+        assert!(outcome.failures[0].starts_with("replay: reading capture"));
+    }
+
+    #[tokio::test]
+    async fn test_injected_faults_are_alarmed_and_relays_stay_truthful() {
+        let mut scenario: Scenario = serde_yaml::from_str(include_str!("../../firmware/scenarios/faults.yaml")).unwrap();
+        assert_eq!(run(&scenario).await.failures, Vec::<String>::new());
+
+        // Had the relays switched, the HVAC relay would be open after islanding
+        scenario.checkpoints[0].relays.insert("r_hvac".to_string(), Position::Open);
+        let failures = run(&scenario).await.failures;
+        assert_eq!(failures, vec!["t=13s node_01: relay r_hvac is closed, expected Open".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_cycling_appliances_are_normal_and_a_stalled_compressor_is_not() {
+        let mut scenario: Scenario = serde_yaml::from_str(include_str!("../../firmware/scenarios/loads.yaml")).unwrap();
+        assert_eq!(run(&scenario).await.failures, Vec::<String>::new());
+
+        // Still cycling, the fridge raises nothing
+        scenario.events.clear();
+        let failures = run(&scenario).await.failures;
+        assert_eq!(failures, vec!["t=7320s: no anomaly_alert from any node since the previous checkpoint".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_draining_battery_sheds_loads_through_the_coordinator() {
+        let mut scenario: Scenario = serde_yaml::from_str(include_str!("../../firmware/scenarios/battery.yaml")).unwrap();
+        let outcome = run(&scenario).await;
+        assert_eq!(outcome.failures, Vec::<String>::new());
+        assert_eq!(outcome.checkpoints, scenario.checkpoints.len());
+
+        // A fuller battery keeps the fridge on
+        scenario.sources.as_mut().unwrap().initial_soc = Some(0.9);
+        scenario.checkpoints[2].soc_below = None;
+        let failures = run(&scenario).await.failures;
+        assert_eq!(failures, vec!["t=900s node_01: relay r_fridge is closed, expected Open".to_string()]);
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Kinds of rejected traffic counted for the SecurityReport.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SecurityEvent {
+    /// MAC missing or wrong while mesh keys are in use
+    AuthFailure,
+    /// Command counter not newer than the sender's last
+    ReplayAttempt,
+    /// Frame that could not be decoded
+    MalformedPacket,
+    /// A command flood opened the rate-limit breaker
+    RateLimitTrip,
+    /// Command dropped while the breaker was open
+    LockoutDrop,
+    /// Operator command without a live session covering it
+    SessionRejection,
+}
+
+static AUTH_FAILURES: AtomicU32 = AtomicU32::new(0);
+static REPLAY_ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+static MALFORMED_PACKETS: AtomicU32 = AtomicU32::new(0);
+static RATE_LIMIT_TRIPS: AtomicU32 = AtomicU32::new(0);
+static LOCKOUT_DROPS: AtomicU32 = AtomicU32::new(0);
+static SESSION_REJECTIONS: AtomicU32 = AtomicU32::new(0);
+
+fn counter(event: SecurityEvent) -> &'static AtomicU32 {
+    match event {
+        SecurityEvent::AuthFailure => &AUTH_FAILURES,
+        SecurityEvent::ReplayAttempt => &REPLAY_ATTEMPTS,
+        SecurityEvent::MalformedPacket => &MALFORMED_PACKETS,
+        SecurityEvent::RateLimitTrip => &RATE_LIMIT_TRIPS,
+        SecurityEvent::LockoutDrop => &LOCKOUT_DROPS,
+        SecurityEvent::SessionRejection => &SESSION_REJECTIONS,
+    }
+}
+
+/// Count one rejected message. Callable from transports as well as the node.
+pub fn record(event: SecurityEvent) {
+    counter(event).fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts accumulated since the previous report.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SecurityCounts {
+    pub auth_failures: u32,
+    pub replay_attempts: u32,
+    pub malformed_packets: u32,
+    pub rate_limit_trips: u32,
+    pub lockout_drops: u32,
+    pub session_rejections: u32,
+}
+
+impl SecurityCounts {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Read and reset all counters, so each report covers one interval.
+pub fn take_counts() -> SecurityCounts {
+    let take = |event| counter(event).swap(0, Ordering::Relaxed);
+    SecurityCounts {
+        auth_failures: take(SecurityEvent::AuthFailure),
+        replay_attempts: take(SecurityEvent::ReplayAttempt),
+        malformed_packets: take(SecurityEvent::MalformedPacket),
+        rate_limit_trips: take(SecurityEvent::RateLimitTrip),
+        lockout_drops: take(SecurityEvent::LockoutDrop),
+        session_rejections: take(SecurityEvent::SessionRejection),
+    }
+}
+
+/// Put counts back after a report could not be sent, so they go out with the next one.
+pub fn restore_counts(counts: &SecurityCounts) {
+    counter(SecurityEvent::AuthFailure).fetch_add(counts.auth_failures, Ordering::Relaxed);
+    counter(SecurityEvent::ReplayAttempt).fetch_add(counts.replay_attempts, Ordering::Relaxed);
+    counter(SecurityEvent::MalformedPacket).fetch_add(counts.malformed_packets, Ordering::Relaxed);
+    counter(SecurityEvent::RateLimitTrip).fetch_add(counts.rate_limit_trips, Ordering::Relaxed);
+    counter(SecurityEvent::LockoutDrop).fetch_add(counts.lockout_drops, Ordering::Relaxed);
+    counter(SecurityEvent::SessionRejection).fetch_add(counts.session_rejections, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_are_taken_and_restored() {
+        record(SecurityEvent::MalformedPacket);
+        record(SecurityEvent::MalformedPacket);
+        let counts = take_counts();
+        // Other tests may record concurrently
+        assert!(counts.malformed_packets >= 2);
+        assert!(!counts.is_empty());
+
+        restore_counts(&counts);
+        assert!(take_counts().malformed_packets >= counts.malformed_packets);
+    }
+}
@@ -0,0 +1,177 @@
+use async_trait::async_trait;
+use log::{info, warn};
+use prost::Message;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use crate::comms::{decode_frame, CommsError, CommunicationLayer, LinkStats, NeighborhoodMessage, Result};
+
+/// Marks the start of a frame, so the reader can find the next one after line noise.
+const SYNC: [u8; 2] = [0x53, 0x47];
+
+/// Messages buffered between the reader thread and the node's poll.
+const INBOX_CAPACITY: usize = 64;
+
+/// How long a read blocks before the reader checks whether the node still listens.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Delay before reading again after an I/O error (e.g. a USB adapter unplugged).
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Wired transport: protobuf `NeighborhoodMessage`s over a UART, each frame sent as
+/// the sync bytes, a big-endian u16 length and the encoded message.
+pub struct SerialCommunication {
+    port: Mutex<Box<dyn serialport::SerialPort>>,
+    inbox: Mutex<mpsc::Receiver<NeighborhoodMessage>>,
+    tx_packets: AtomicU32,
+    rx_packets: AtomicU32,
+}
+
+impl SerialCommunication {
+    /// Open the port and start reading frames from it on a dedicated thread.
+    pub fn open(path: &str, baud: u32) -> Result<Self> {
+        let port = serialport::new(path, baud).timeout(READ_TIMEOUT).open()?;
+        let reader = port.try_clone()?;
+        let (inbox_tx, inbox) = mpsc::channel(INBOX_CAPACITY);
+        let name = path.to_string();
+        std::thread::Builder::new()
+            .name("serial-comms".to_string())
+            .spawn(move || read_frames(&name, reader, inbox_tx))?;
+        info!("Serial transport on {} @ {} baud", path, baud);
+        Ok(Self {
+            port: Mutex::new(port),
+            inbox: Mutex::new(inbox),
+            tx_packets: AtomicU32::new(0),
+            rx_packets: AtomicU32::new(0),
+        })
+    }
+}
+
+/// Wrap an encoded message in a frame.
+pub fn encode_frame(msg: &NeighborhoodMessage) -> Result<Vec<u8>> {
+    let body = msg.encode_to_vec();
+    let len = u16::try_from(body.len()).map_err(|_| CommsError::TooLong { size: body.len(), transport: "serial" })?;
+    let mut frame = Vec::with_capacity(body.len() + 4);
+    frame.extend_from_slice(&SYNC);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(&body);
+    Ok(frame)
+}
+
+/// Reassembles frames from the bytes as they arrive, skipping anything before a sync marker.
+#[derive(Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+}
+
+impl FrameDecoder {
+    /// Add received bytes; returns the payloads of the frames they complete.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        self.buf.extend_from_slice(bytes);
+        let mut frames = Vec::new();
+        loop {
+            match self.buf.windows(2).position(|w| w == SYNC) {
+                Some(start) => {
+                    self.buf.drain(..start);
+                }
+                None => {
+                    // Keep a trailing first sync byte, its partner may be in the next read
+                    let keep = usize::from(self.buf.last() == Some(&SYNC[0]));
+                    self.buf.drain(..self.buf.len() - keep);
+                    return frames;
+                }
+            }
+            if self.buf.len() < 4 {
+                return frames;
+            }
+            let len = u16::from_be_bytes([self.buf[2], self.buf[3]]) as usize;
+            if self.buf.len() < 4 + len {
+                return frames;
+            }
+            frames.push(self.buf[4..4 + len].to_vec());
+            self.buf.drain(..4 + len);
+        }
+    }
+}
+
+/// Decode frames off the port until the node drops its end of the inbox.
+fn read_frames(name: &str, mut port: Box<dyn serialport::SerialPort>, inbox: mpsc::Sender<NeighborhoodMessage>) {
+    let mut decoder = FrameDecoder::default();
+    let mut buf = [0u8; 256];
+    while !inbox.is_closed() {
+        let n = match port.read(&mut buf) {
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::TimedOut => continue,
+            Err(e) => {
+                warn!("Serial transport on {} failed: {}", name, e);
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            }
+        };
+        for frame in decoder.push(&buf[..n]) {
+            if let Some(msg) = decode_frame(&frame) {
+                if inbox.try_send(msg).is_err() {
+                    warn!("Serial inbox full, dropping message");
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl CommunicationLayer for SerialCommunication {
+    async fn send(&self, msg: NeighborhoodMessage) -> Result<()> {
+        let frame = encode_frame(&msg)?;
+        let mut port = self.port.lock().unwrap();
+        port.write_all(&frame)?;
+        port.flush()?;
+        self.tx_packets.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn receive(&self) -> Result<Option<NeighborhoodMessage>> {
+        let msg = self.inbox.lock().unwrap().try_recv().ok();
+        if msg.is_some() {
+            self.rx_packets.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(msg)
+    }
+
+    fn name(&self) -> &'static str {
+        "serial"
+    }
+
+    fn link_stats(&self) -> LinkStats {
+        LinkStats {
+            tx_packets: self.tx_packets.load(Ordering::Relaxed),
+            rx_packets: self.rx_packets.load(Ordering::Relaxed),
+            ..LinkStats::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_survive_split_reads_and_line_noise() {
+        let msg = NeighborhoodMessage { sender_id: "node_01".to_string(), counter: 7, ..Default::default() };
+        let frame = encode_frame(&msg).unwrap();
+        let mut stream = vec![0x00, 0x53, 0xff];
+        stream.extend_from_slice(&frame);
+        stream.extend_from_slice(&frame);
+
+        let mut decoder = FrameDecoder::default();
+        let mut frames = Vec::new();
+        for chunk in stream.chunks(3) {
+            frames.extend(decoder.push(chunk));
+        }
+        assert_eq!(frames.len(), 2);
+        for frame in frames {
+            assert_eq!(NeighborhoodMessage::decode(frame.as_slice()).unwrap(), msg);
+        }
+    }
+}
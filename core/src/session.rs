@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Scope that authorizes every command kind.
+pub const SCOPE_ALL: &str = "*";
+
+/// Sessions held at once; the one closest to expiry is dropped to make room.
+const MAX_SESSIONS: usize = 16;
+
+/// Why a command's session does not authorize it.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum SessionError {
+    #[error("no session")]
+    Missing,
+    #[error("unknown session {0}")]
+    Unknown(String),
+    #[error("session {0} expired")]
+    Expired(String),
+    #[error("session {session} does not cover {kind}")]
+    OutOfScope { session: String, kind: String },
+}
+
+#[derive(Debug, Clone)]
+struct Session {
+    scopes: Vec<String>,
+    /// Unix seconds after which the session is no longer honoured
+    expires_at: i64,
+}
+
+/// Operator sessions granted by the orchestrator. Commands must name a live session
+/// whose scopes cover them, so a captured command stops working once its session ends.
+#[derive(Debug, Default)]
+pub struct SessionTable {
+    sessions: HashMap<String, Session>,
+}
+
+impl SessionTable {
+    pub fn grant(&mut self, session_id: &str, scopes: Vec<String>, expires_at: i64, now: i64) {
+        self.sessions.retain(|_, s| s.expires_at > now);
+        if self.sessions.len() >= MAX_SESSIONS && !self.sessions.contains_key(session_id) {
+            if let Some(oldest) = self.sessions.iter().min_by_key(|(_, s)| s.expires_at).map(|(id, _)| id.clone()) {
+                self.sessions.remove(&oldest);
+            }
+        }
+        self.sessions.insert(session_id.to_string(), Session { scopes, expires_at });
+    }
+
+    /// Returns whether the session existed.
+    pub fn revoke(&mut self, session_id: &str) -> bool {
+        self.sessions.remove(session_id).is_some()
+    }
+
+    /// Check that `session_id` is live at `now` and covers the command `kind`.
+    pub fn authorize(&self, session_id: Option<&str>, kind: &str, now: i64) -> Result<(), SessionError> {
+        let Some(session_id) = session_id else {
+            return Err(SessionError::Missing);
+        };
+        let Some(session) = self.sessions.get(session_id) else {
+            return Err(SessionError::Unknown(session_id.to_string()));
+        };
+        if now >= session.expires_at {
+            return Err(SessionError::Expired(session_id.to_string()));
+        }
+        if !session.scopes.iter().any(|s| s == SCOPE_ALL || s == kind) {
+            return Err(SessionError::OutOfScope { session: session_id.to_string(), kind: kind.to_string() });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_scope_expiry_and_revocation() {
+        let mut table = SessionTable::default();
+        table.grant("s1", vec!["activate_relay_by_index".to_string()], 1_000, 0);
+        table.grant("s2", vec![SCOPE_ALL.to_string()], 1_000, 0);
+
+        assert!(table.authorize(Some("s1"), "activate_relay_by_index", 500).is_ok());
+        assert!(table.authorize(Some("s1"), "firmware_update", 500).is_err());
+        assert!(table.authorize(Some("s2"), "firmware_update", 500).is_ok());
+        assert!(table.authorize(Some("s1"), "activate_relay_by_index", 1_000).is_err());
+        assert!(table.authorize(None, "activate_relay_by_index", 500).is_err());
+        assert!(table.authorize(Some("s3"), "activate_relay_by_index", 500).is_err());
+
+        assert!(table.revoke("s2"));
+        assert!(table.authorize(Some("s2"), "firmware_update", 500).is_err());
+        assert!(!table.revoke("s2"));
+    }
+
+    #[test]
+    fn test_table_is_bounded() {
+        let mut table = SessionTable::default();
+        for i in 0..(MAX_SESSIONS as i64 + 4) {
+            table.grant(&format!("s{}", i), vec![SCOPE_ALL.to_string()], 1_000 + i, 0);
+        }
+        assert_eq!(table.sessions.len(), MAX_SESSIONS);
+        // The sessions closest to expiry made room for the newest
+        assert!(table.authorize(Some("s0"), "load_shed", 10).is_err());
+        assert!(table.authorize(Some("s19"), "load_shed", 10).is_ok());
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+/// How long a round-robin or proportional shed keeps the same circuits off before
+/// handing the cut on
+pub const ROTATION_SECS: i64 = 15 * 60;
+
+/// A load that may be shed, and what it draws.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub relay_id: String,
+    pub watts: f32,
+}
+
+/// How the loads to open are picked from the candidates for a cut in draw.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShedStrategy {
+    /// Every candidate, whatever the cut
+    #[default]
+    PriorityThreshold,
+    /// The biggest draws first, opening as few circuits as make the cut
+    LargestFirst,
+    /// Circuits in turn, enough of them to make the cut, starting one circuit further on
+    /// every rotation so no circuit is off for the whole of a long event
+    RoundRobin,
+    /// Every circuit off for the cut's share of each rotation, staggered so that share of
+    /// the circuits is off at any moment
+    Proportional,
+}
+
+impl ShedStrategy {
+    /// Parse the name a DemandResponse carries; empty leaves the choice to the node.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "priority_threshold" => Some(ShedStrategy::PriorityThreshold),
+            "largest_first" => Some(ShedStrategy::LargestFirst),
+            "round_robin" => Some(ShedStrategy::RoundRobin),
+            "proportional" => Some(ShedStrategy::Proportional),
+            _ => None,
+        }
+    }
+
+    /// Whether the circuits it picks change over time, rather than once when shedding starts.
+    pub fn rotates(&self) -> bool {
+        matches!(self, ShedStrategy::RoundRobin | ShedStrategy::Proportional)
+    }
+
+    /// Relays to have open at `now` to take `cut_watts` off the candidates' draw, or all
+    /// of them without a cut. The same candidates and time always give the same answer.
+    pub fn select(&self, candidates: &[Candidate], cut_watts: Option<f32>, now: i64) -> Vec<String> {
+        let mut candidates: Vec<&Candidate> = candidates.iter().collect();
+        candidates.sort_by(|a, b| a.relay_id.cmp(&b.relay_id));
+        let total: f32 = candidates.iter().map(|c| c.watts.max(0.0)).sum();
+        let n = candidates.len();
+        match (self, cut_watts) {
+            (ShedStrategy::PriorityThreshold, _) | (_, None) => candidates.iter().map(|c| c.relay_id.clone()).collect(),
+            (_, Some(cut)) if cut <= 0.0 || n == 0 => Vec::new(),
+            (ShedStrategy::LargestFirst, Some(cut)) => {
+                candidates.sort_by(|a, b| b.watts.total_cmp(&a.watts));
+                take_until(candidates.into_iter(), cut)
+            }
+            (ShedStrategy::RoundRobin, Some(cut)) => {
+                let start = now.div_euclid(ROTATION_SECS).rem_euclid(n as i64) as usize;
+                take_until(candidates.iter().cycle().skip(start).take(n).copied(), cut)
+            }
+            (ShedStrategy::Proportional, Some(cut)) => {
+                let share = (cut / total.max(f32::EPSILON)).min(1.0);
+                let position = now.rem_euclid(ROTATION_SECS) as f32 / ROTATION_SECS as f32;
+                candidates.iter().enumerate()
+                    .filter(|(i, _)| (position - *i as f32 / n as f32).rem_euclid(1.0) < share)
+                    .map(|(_, c)| c.relay_id.clone())
+                    .collect()
+            }
+        }
+    }
+}
+
+fn take_until<'a>(candidates: impl Iterator<Item = &'a Candidate>, cut: f32) -> Vec<String> {
+    let mut shed = 0.0;
+    let mut open = Vec::new();
+    for candidate in candidates {
+        if shed >= cut {
+            break;
+        }
+        shed += candidate.watts.max(0.0);
+        open.push(candidate.relay_id.clone());
+    }
+    open
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strategies_share_a_cut_differently() {
+        let candidates: Vec<Candidate> = [("r_a", 1000.0), ("r_b", 1000.0), ("r_c", 3000.0), ("r_d", 1000.0)]
+            .into_iter()
+            .map(|(relay_id, watts)| Candidate { relay_id: relay_id.to_string(), watts })
+            .collect();
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(ShedStrategy::PriorityThreshold.select(&candidates, Some(1500.0), 0), ids(&["r_a", "r_b", "r_c", "r_d"]));
+        assert_eq!(ShedStrategy::LargestFirst.select(&candidates, Some(1500.0), 0), ids(&["r_c"]));
+        assert_eq!(ShedStrategy::LargestFirst.select(&candidates, None, 0).len(), 4);
+
+        // A third of the draw, starting a circuit further on each rotation
+        let round_robin = |turn: i64| ShedStrategy::RoundRobin.select(&candidates, Some(2000.0), turn * ROTATION_SECS);
+        assert_eq!(round_robin(0), ids(&["r_a", "r_b"]));
+        assert_eq!(round_robin(1), ids(&["r_b", "r_c"]));
+        assert_eq!(round_robin(2), ids(&["r_c"]));
+        assert_eq!(round_robin(3), ids(&["r_d", "r_a"]));
+
+        // Half the draw: each circuit off for half of every rotation, two at a time
+        let proportional = |secs: i64| ShedStrategy::Proportional.select(&candidates, Some(3000.0), secs);
+        assert_eq!(proportional(0), ids(&["r_a", "r_d"]));
+        assert_eq!(proportional(ROTATION_SECS / 4 + 1), ids(&["r_a", "r_b"]));
+        assert_eq!(proportional(ROTATION_SECS / 2 + 1), ids(&["r_b", "r_c"]));
+        assert_eq!(ShedStrategy::parse("round_robin"), Some(ShedStrategy::RoundRobin));
+        assert_eq!(ShedStrategy::parse(""), None);
+    }
+}
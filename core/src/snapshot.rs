@@ -0,0 +1,114 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+use crate::storage::Storage;
+use crate::types::{NodeState, Relay};
+
+/// Bumped whenever the snapshot layout changes incompatibly.
+pub const SNAPSHOT_FORMAT: u32 = 1;
+
+/// Relay metadata seeded by a restore; overrides the relays in config.yaml at boot.
+pub const RELAYS_KEY: &str = "relays.json";
+/// CT calibration seeded by a restore; overrides the ADC settings in config.yaml at boot.
+pub const CALIBRATION_KEY: &str = "calibration.json";
+
+/// Counter name for the next audit log sequence number.
+pub const COUNTER_AUDIT_SEQ: &str = "audit_next_seq";
+
+/// Why a snapshot could not be encoded or decoded.
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("unsupported snapshot format {0} (expected {SNAPSHOT_FORMAT})")]
+    UnsupportedFormat(u32),
+    #[error("snapshot JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T, E = SnapshotError> = std::result::Result<T, E>;
+
+/// Current-sensing calibration for the CT clamps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    pub ct_ratio: f32,
+    pub burden_resistor: f32,
+    pub voltage_ref: f32,
+}
+
+/// Everything needed to seed replacement hardware with a node's identity and history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeSnapshot {
+    pub node_id: String,
+    pub taken_at: i64,
+    pub state: NodeState,
+    pub relays: Vec<Relay>,
+    pub calibration: Option<Calibration>,
+    /// Monotonic counters that must not go backwards on the replacement
+    pub counters: BTreeMap<String, u64>,
+}
+
+impl NodeSnapshot {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    pub fn from_bytes(format_version: u32, data: &[u8]) -> Result<Self> {
+        if format_version != SNAPSHOT_FORMAT {
+            return Err(SnapshotError::UnsupportedFormat(format_version));
+        }
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+/// Relays seeded by a previous restore, if any.
+pub fn load_relays(storage: &Storage) -> Option<Vec<Relay>> {
+    match storage.get_json(RELAYS_KEY) {
+        Ok(relays) => relays,
+        Err(e) => {
+            warn!("Ignoring unreadable {}: {}", RELAYS_KEY, e);
+            None
+        }
+    }
+}
+
+/// Calibration seeded by a previous restore, if any.
+pub fn load_calibration(storage: &Storage) -> Option<Calibration> {
+    match storage.get_json(CALIBRATION_KEY) {
+        Ok(calibration) => calibration,
+        Err(e) => {
+            warn!("Ignoring unreadable {}: {}", CALIBRATION_KEY, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Priority, RelayType};
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let snapshot = NodeSnapshot {
+            node_id: "node_01".to_string(),
+            taken_at: 1_700_000_000,
+            state: NodeState::Normal,
+            relays: vec![Relay {
+                id: "r_crit".to_string(),
+                name: "Critical Panel".to_string(),
+                relay_type: RelayType::Load,
+                priority: Priority::Critical,
+                amperage: 15.0,
+                is_closed: true,
+                community_criticality: 0,
+                phase: None,
+            }],
+            calibration: Some(Calibration { ct_ratio: 100.0, burden_resistor: 33.0, voltage_ref: 121.5 }),
+            counters: BTreeMap::from([(COUNTER_AUDIT_SEQ.to_string(), 42)]),
+        };
+
+        let bytes = snapshot.to_bytes().unwrap();
+        assert_eq!(NodeSnapshot::from_bytes(SNAPSHOT_FORMAT, &bytes).unwrap(), snapshot);
+        assert!(NodeSnapshot::from_bytes(SNAPSHOT_FORMAT + 1, &bytes).is_err());
+    }
+}
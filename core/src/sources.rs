@@ -0,0 +1,196 @@
+use std::f32::consts::PI;
+use crate::types::{Relay, RelayType};
+
+/// How often a node with sources advertises its capacity.
+pub const SOURCE_CAPACITY_INTERVAL_SECS: u64 = 60;
+
+/// Hours of solar production counted in an advert; also how long the coordinator
+/// makes the island's stored energy last.
+pub const FORECAST_HOURS: f32 = 12.0;
+
+/// Battery charge kept back for the household by default.
+pub const DEFAULT_RESERVE_SOC: f32 = 0.2;
+
+/// Clear-sky day used for the solar forecast, in local hours
+const SUNRISE_HOUR: f32 = 6.0;
+const SUNSET_HOUR: f32 = 18.0;
+
+/// Integration step of the forecast, in hours
+const FORECAST_STEP_HOURS: f32 = 0.25;
+
+/// Clear-sky output, as a share of peak, below which a reading says little about the weather
+const MIN_SKY_FACTOR_SHARE: f32 = 0.1;
+
+/// What a node with a battery or solar can do for the island, as advertised in a
+/// SourceCapacity message.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Capacity {
+    pub export_watts: f32,
+    pub soc: f32,
+    /// 0 without a battery
+    pub battery_capacity_wh: f32,
+    /// Usable energy above the reserve
+    pub stored_wh: f32,
+    pub solar_forecast_wh: f32,
+}
+
+/// The household's battery and panels, as configured. There is no battery management
+/// bus yet, so state of charge comes from the node. Solar output is what the inverter
+/// reports when there is one, otherwise a clear-sky estimate of the panels' peak.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceManager {
+    battery_capacity_wh: f32,
+    reserve_soc: f32,
+    solar_peak_watts: f32,
+    /// Latest inverter reading
+    measured_solar_watts: Option<f32>,
+    /// Most the battery's management system lets it give now
+    discharge_limit_watts: Option<f32>,
+}
+
+impl SourceManager {
+    pub fn new(battery_capacity_wh: f32, reserve_soc: f32, solar_peak_watts: f32) -> Self {
+        Self {
+            battery_capacity_wh: battery_capacity_wh.max(0.0),
+            reserve_soc: reserve_soc.clamp(0.0, 1.0),
+            solar_peak_watts: solar_peak_watts.max(0.0),
+            measured_solar_watts: None,
+            discharge_limit_watts: None,
+        }
+    }
+
+    /// Take the inverter's output as the panels' from now on; None while it can't be read.
+    pub fn record_solar(&mut self, watts: Option<f32>) {
+        self.measured_solar_watts = watts;
+    }
+
+    /// Hold exports to what the battery may give plus what the panels make; None lifts it.
+    pub fn record_discharge_limit(&mut self, watts: Option<f32>) {
+        self.discharge_limit_watts = watts;
+    }
+
+    pub fn measured_solar_watts(&self) -> Option<f32> {
+        self.measured_solar_watts
+    }
+
+    /// Output of the panels at `hour` (local, fractional): measured, or clear-sky.
+    pub fn solar_watts(&self, hour: f32) -> f32 {
+        self.measured_solar_watts.unwrap_or_else(|| solar_watts(self.solar_peak_watts, hour))
+    }
+
+    /// Share of clear-sky output the panels are making now, assumed to hold for the rest
+    /// of the forecast. 1 without a reading, or too near dark to tell.
+    fn sky_factor(&self, hour: f32) -> f32 {
+        let clear = solar_watts(self.solar_peak_watts, hour);
+        match self.measured_solar_watts {
+            Some(measured) if clear >= MIN_SKY_FACTOR_SHARE * self.solar_peak_watts => (measured / clear).clamp(0.0, 1.0),
+            _ => 1.0,
+        }
+    }
+
+    /// Capacity at `hour` (local, fractional) with the battery at `soc`. A battery at
+    /// or below its reserve exports nothing; only what the panels make now is offered.
+    /// Nor does it give more than its discharge limit, when one is known.
+    pub fn capacity(&self, relays: &[Relay], voltage: f32, soc: f32, hour: f32) -> Capacity {
+        let closed = |relay_type: RelayType| -> f32 {
+            relays.iter()
+                .filter(|r| r.relay_type == relay_type && r.is_closed)
+                .map(|r| r.amperage * voltage)
+                .sum()
+        };
+        let soc = soc.clamp(0.0, 1.0);
+        let mut supply = closed(RelayType::Source);
+        if self.battery_capacity_wh > 0.0 && soc <= self.reserve_soc {
+            supply = supply.min(self.solar_watts(hour));
+        }
+        if let Some(limit) = self.discharge_limit_watts {
+            supply = supply.min(limit + self.solar_watts(hour));
+        }
+        Capacity {
+            export_watts: (supply - closed(RelayType::Load)).max(0.0),
+            soc,
+            battery_capacity_wh: self.battery_capacity_wh,
+            stored_wh: self.battery_capacity_wh * (soc - self.reserve_soc).max(0.0),
+            solar_forecast_wh: solar_forecast_wh(self.solar_peak_watts, hour, FORECAST_HOURS) * self.sky_factor(hour),
+        }
+    }
+}
+
+/// Clear-sky output of panels peaking at `peak_watts`, at local `hour`.
+pub fn solar_watts(peak_watts: f32, hour: f32) -> f32 {
+    let hour = hour.rem_euclid(24.0);
+    if !(SUNRISE_HOUR..SUNSET_HOUR).contains(&hour) {
+        return 0.0;
+    }
+    peak_watts * (PI * (hour - SUNRISE_HOUR) / (SUNSET_HOUR - SUNRISE_HOUR)).sin()
+}
+
+/// Energy the panels are expected to make over the `hours` from local `from_hour`.
+pub fn solar_forecast_wh(peak_watts: f32, from_hour: f32, hours: f32) -> f32 {
+    let steps = (hours / FORECAST_STEP_HOURS).round() as u32;
+    (0..steps)
+        .map(|i| solar_watts(peak_watts, from_hour + (i as f32 + 0.5) * FORECAST_STEP_HOURS) * FORECAST_STEP_HOURS)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Priority;
+
+    fn relay(id: &str, relay_type: RelayType, amperage: f32) -> Relay {
+        Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type,
+            priority: Priority::Medium,
+            amperage,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        }
+    }
+
+    #[test]
+    fn test_capacity_keeps_the_reserve_and_forecasts_daylight_only() {
+        let relays = vec![relay("r_battery", RelayType::Source, 20.0), relay("r_fridge", RelayType::Load, 5.0)];
+        let sources = SourceManager::new(10_000.0, 0.2, 3000.0);
+
+        // Half charged at noon: the inverter's worth less our own loads
+        let noon = sources.capacity(&relays, 100.0, 0.5, 12.0);
+        assert_eq!(noon.export_watts, 1500.0);
+        assert!((noon.stored_wh - 3000.0).abs() < 0.1);
+        // Six hours of daylight left: half the day's clear-sky production
+        let day_wh = 3000.0 * 12.0 * 2.0 / PI;
+        assert!((noon.solar_forecast_wh - day_wh / 2.0).abs() < 10.0);
+
+        // At the reserve after dark nothing is exported, and nothing comes until sunrise
+        let night = sources.capacity(&relays, 100.0, 0.2, 22.0);
+        assert_eq!((night.export_watts, night.stored_wh), (0.0, 0.0));
+        assert!((night.solar_forecast_wh - solar_forecast_wh(3000.0, 6.0, 4.0)).abs() < 1.0);
+        assert_eq!(solar_watts(3000.0, 3.0), 0.0);
+    }
+
+    #[test]
+    fn test_measured_solar_and_discharge_limit_bound_the_advert() {
+        let relays = vec![relay("r_battery", RelayType::Source, 20.0)];
+        let mut sources = SourceManager::new(10_000.0, 0.2, 3000.0);
+        let clear = sources.capacity(&relays, 100.0, 0.2, 12.0);
+
+        // Overcast: a third of clear-sky output at noon, and so for the afternoon
+        sources.record_solar(Some(1000.0));
+        assert_eq!(sources.solar_watts(12.0), 1000.0);
+        let overcast = sources.capacity(&relays, 100.0, 0.2, 12.0);
+        assert_eq!(overcast.export_watts, 1000.0);
+        assert!((overcast.solar_forecast_wh - clear.solar_forecast_wh / 3.0).abs() < 1.0);
+
+        // The inverter stops answering: back to the estimate
+        sources.record_solar(None);
+        assert_eq!(sources.capacity(&relays, 100.0, 0.2, 12.0), clear);
+
+        // A BMS holding the battery to 500 W: that and the sun, at dusk nothing else
+        sources.record_discharge_limit(Some(500.0));
+        assert_eq!(sources.capacity(&relays, 100.0, 0.8, 12.0).export_watts, 2000.0);
+        assert_eq!(sources.capacity(&relays, 100.0, 0.8, 19.0).export_watts, 500.0);
+    }
+}
@@ -0,0 +1,714 @@
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use log::{debug, info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use crate::keys::{self, KeyError, KEY_LEN, NONCE_LEN};
+use crate::sysinfo;
+
+/// Default minimum time between flushes to the SD card.
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Start of every encrypted frame: `SGE2 | ciphertext len (u32 BE) | nonce | ciphertext`.
+const FRAME_MAGIC: &[u8; 4] = b"SGE2";
+/// Frames written before their position was authenticated, still read
+const LEGACY_FRAME_MAGIC: &[u8; 4] = b"SGE1";
+const FRAME_HEADER_LEN: usize = 4 + 4 + NONCE_LEN;
+
+/// Why a file could not be staged, read or written.
+#[derive(Debug, Error)]
+pub enum StorageError {
+    /// The key would leave the storage root
+    #[error("invalid storage key: {0:?}")]
+    InvalidKey(String),
+    #[error("creating storage directory {}: {source}", .path.display())]
+    CreateRoot { path: PathBuf, source: std::io::Error },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    /// Plaintext found where encryption is on
+    #[error("{0} is not an encrypted storage file")]
+    NotEncrypted(String),
+    #[error("{0} failed authentication (wrong key or tampered)")]
+    Authentication(String),
+    #[error("failed to encrypt {0}")]
+    Encrypt(String),
+    /// No nonce to encrypt with
+    #[error(transparent)]
+    Key(#[from] KeyError),
+}
+
+pub type Result<T, E = StorageError> = std::result::Result<T, E>;
+
+/// Writes staged in memory, waiting for the next flush.
+#[derive(Default)]
+struct Pending {
+    /// Whole-file replacements; a later put for the same key supersedes earlier ones.
+    puts: HashMap<String, Vec<u8>>,
+    /// Bytes to append to log-style files, in order.
+    appends: HashMap<String, Vec<u8>>,
+    /// Keys to delete on the next flush.
+    removals: Vec<String>,
+    last_flush: Option<Instant>,
+}
+
+impl Pending {
+    fn is_empty(&self) -> bool {
+        self.puts.is_empty() && self.appends.is_empty() && self.removals.is_empty()
+    }
+}
+
+/// Shared persistence layer for everything the node keeps on disk.
+///
+/// Writes are coalesced in memory and flushed at most once per `flush_interval`
+/// to spare the SD card. Whole-file writes go through a temp file and an atomic
+/// rename, so a power cut leaves either the old or the new contents, never a mix.
+/// Keys are relative paths below the storage root, e.g. `state.json` or `audit/log`.
+///
+/// When opened with a key, every file is encrypted on disk (ChaCha20-Poly1305, with the
+/// storage key and the frame's position in the file as associated data, so files can't be
+/// swapped nor frames reordered). Each write or append becomes one authenticated frame;
+/// callers always see plaintext. Opening cuts off a last frame torn by a power cut, so
+/// later appends aren't lost behind it.
+pub struct Storage {
+    root: PathBuf,
+    flush_interval: Duration,
+    pending: Mutex<Pending>,
+    cipher: Option<ChaCha20Poly1305>,
+    /// Frames in each file on disk; the next append to it is numbered after them
+    frames: Mutex<HashMap<String, u64>>,
+}
+
+/// What the whole, authenticated frames of an encrypted file hold.
+#[derive(Default)]
+struct Decoded {
+    plain: Vec<u8>,
+    frames: u64,
+    /// Where the last of them ends
+    end: usize,
+}
+
+impl Storage {
+    pub fn open(root: impl Into<PathBuf>, flush_interval: Duration) -> Result<Arc<Self>> {
+        let root = root.into();
+        fs::create_dir_all(&root).map_err(|source| StorageError::CreateRoot { path: root.clone(), source })?;
+        Ok(Arc::new(Self {
+            root,
+            flush_interval,
+            pending: Mutex::new(Pending::default()),
+            cipher: None,
+            frames: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Open storage encrypted under `key`. Plaintext files left from before encryption
+    /// was enabled are encrypted in place, and torn last frames cut off.
+    pub fn open_encrypted(root: impl Into<PathBuf>, flush_interval: Duration, key: &[u8; KEY_LEN]) -> Result<Arc<Self>> {
+        let root = root.into();
+        fs::create_dir_all(&root).map_err(|source| StorageError::CreateRoot { path: root.clone(), source })?;
+        let storage = Self {
+            root,
+            flush_interval,
+            pending: Mutex::new(Pending::default()),
+            cipher: Some(ChaCha20Poly1305::new(Key::from_slice(key))),
+            frames: Mutex::new(HashMap::new()),
+        };
+        let migrated = storage.prepare_files(Path::new(""))?;
+        if migrated > 0 {
+            info!("Encrypted {} existing storage files", migrated);
+        }
+        Ok(Arc::new(storage))
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Stage a whole-file write.
+    pub fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        validate_key(key)?;
+        let mut pending = self.pending.lock().unwrap();
+        pending.removals.retain(|k| k != key);
+        pending.appends.remove(key);
+        pending.puts.insert(key.to_string(), data);
+        Ok(())
+    }
+
+    pub fn put_json<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        self.put(key, serde_json::to_vec(value)?)
+    }
+
+    /// Stage bytes to be appended to a log-style file.
+    pub fn append(&self, key: &str, data: &[u8]) -> Result<()> {
+        validate_key(key)?;
+        let mut pending = self.pending.lock().unwrap();
+        // Appending to a file staged for removal starts it afresh; the old contents must not come back
+        if pending.removals.iter().any(|k| k == key) {
+            pending.removals.retain(|k| k != key);
+            pending.puts.insert(key.to_string(), data.to_vec());
+            return Ok(());
+        }
+        match pending.puts.get_mut(key) {
+            // Appending to a pending whole-file write just extends it
+            Some(contents) => contents.extend_from_slice(data),
+            None => pending.appends.entry(key.to_string()).or_default().extend_from_slice(data),
+        }
+        Ok(())
+    }
+
+    /// Read a file, including any writes not yet flushed.
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        validate_key(key)?;
+        let pending = self.pending.lock().unwrap();
+        self.read(&pending, key)
+    }
+
+    /// Read a file and stage its deletion in one step, so nothing appended in between is lost.
+    pub fn take(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        validate_key(key)?;
+        let mut pending = self.pending.lock().unwrap();
+        let data = self.read(&pending, key)?;
+        pending.puts.remove(key);
+        pending.appends.remove(key);
+        if !pending.removals.iter().any(|k| k == key) {
+            pending.removals.push(key.to_string());
+        }
+        Ok(data)
+    }
+
+    /// Contents of `key` as of the writes staged in `pending`.
+    fn read(&self, pending: &Pending, key: &str) -> Result<Option<Vec<u8>>> {
+        if pending.removals.iter().any(|k| k == key) {
+            return Ok(None);
+        }
+        if let Some(data) = pending.puts.get(key) {
+            return Ok(Some(data.clone()));
+        }
+        let on_disk = match fs::read(self.root.join(key)) {
+            Ok(data) => Some(self.decode(key, &data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        match (on_disk, pending.appends.get(key)) {
+            (Some(mut data), Some(extra)) => {
+                data.extend_from_slice(extra);
+                Ok(Some(data))
+            }
+            (None, Some(extra)) => Ok(Some(extra.clone())),
+            (data, None) => Ok(data),
+        }
+    }
+
+    pub fn get_json<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.get(key)? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stage deletion of a file.
+    pub fn remove(&self, key: &str) -> Result<()> {
+        validate_key(key)?;
+        let mut pending = self.pending.lock().unwrap();
+        pending.puts.remove(key);
+        pending.appends.remove(key);
+        if !pending.removals.iter().any(|k| k == key) {
+            pending.removals.push(key.to_string());
+        }
+        Ok(())
+    }
+
+    /// Keys of the files directly inside `dir` (flushed or staged), sorted.
+    pub fn list(&self, dir: &str) -> Result<Vec<String>> {
+        validate_key(dir)?;
+        let prefix = format!("{}/", dir.trim_end_matches('/'));
+        let mut keys = std::collections::BTreeSet::new();
+        match fs::read_dir(self.root.join(dir)) {
+            Ok(entries) => {
+                for entry in entries {
+                    let entry = entry?;
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    if entry.file_type()?.is_file() && !name.ends_with(".tmp") {
+                        keys.insert(format!("{}{}", prefix, name));
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let pending = self.pending.lock().unwrap();
+        let is_direct_child = |k: &String| k.strip_prefix(&prefix).is_some_and(|rest| !rest.contains('/'));
+        keys.extend(pending.puts.keys().filter(|k| is_direct_child(k)).cloned());
+        keys.extend(pending.appends.keys().filter(|k| is_direct_child(k)).cloned());
+        for key in &pending.removals {
+            keys.remove(key);
+        }
+        Ok(keys.into_iter().collect())
+    }
+
+    /// Bytes used on disk below the storage root (or a subdirectory of it).
+    pub fn disk_usage(&self, dir: Option<&str>) -> Result<u64> {
+        let path = match dir {
+            Some(dir) => {
+                validate_key(dir)?;
+                self.root.join(dir)
+            }
+            None => self.root.clone(),
+        };
+        dir_size(&path)
+    }
+
+    /// Write everything staged to disk now, regardless of the rate limit.
+    /// Entries that fail to write stay staged and are retried on the next flush.
+    pub fn flush(&self) -> Result<()> {
+        let mut pending = self.pending.lock().unwrap();
+        pending.last_flush = Some(Instant::now());
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut first_error = None;
+        let mut note_error = |key: &str, e: StorageError| {
+            warn!("Storage write of {} failed: {}", key, e);
+            sysinfo::record_write_error();
+            first_error.get_or_insert(e);
+        };
+
+        let mut frames = self.frames.lock().unwrap();
+        let removals = std::mem::take(&mut pending.removals);
+        for key in removals {
+            match fs::remove_file(self.root.join(&key)) {
+                Ok(()) => {
+                    frames.remove(&key);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    frames.remove(&key);
+                }
+                Err(e) => {
+                    note_error(&key, e.into());
+                    pending.removals.push(key);
+                }
+            }
+        }
+
+        let puts = std::mem::take(&mut pending.puts);
+        for (key, data) in puts {
+            match self.encode(&key, 0, &data).and_then(|encoded| self.write_atomic(&key, &encoded)) {
+                Ok(()) => {
+                    frames.insert(key, u64::from(!data.is_empty()));
+                }
+                Err(e) => {
+                    note_error(&key, e);
+                    pending.puts.insert(key, data);
+                }
+            }
+        }
+
+        let appends = std::mem::take(&mut pending.appends);
+        for (key, data) in appends {
+            let seq = frames.get(&key).copied().unwrap_or(0);
+            match self.encode(&key, seq, &data).and_then(|encoded| self.write_append(&key, &encoded)) {
+                Ok(()) => {
+                    frames.insert(key, seq + u64::from(!data.is_empty()));
+                }
+                Err(e) => {
+                    note_error(&key, e);
+                    pending.appends.insert(key, data);
+                }
+            }
+        }
+
+        debug!("Storage flushed");
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Flush only if something is staged and the rate limit allows it.
+    pub fn maybe_flush(&self) -> Result<()> {
+        {
+            let pending = self.pending.lock().unwrap();
+            if pending.is_empty() {
+                return Ok(());
+            }
+            if let Some(last) = pending.last_flush {
+                if last.elapsed() < self.flush_interval {
+                    return Ok(());
+                }
+            }
+        }
+        self.flush()
+    }
+
+    /// Background task flushing staged writes every `flush_interval`.
+    pub async fn run_flusher(self: Arc<Self>) {
+        // tokio intervals cannot be zero
+        let mut interval = tokio::time::interval(self.flush_interval.max(Duration::from_secs(1)));
+        loop {
+            interval.tick().await;
+            let storage = self.clone();
+            // Flushing does blocking file I/O; keep it off the event loop
+            let _ = tokio::task::spawn_blocking(move || storage.maybe_flush()).await;
+        }
+    }
+
+    /// On-disk form of `data`: one encrypted frame, the `seq`th of its file, or the data
+    /// itself without a key.
+    fn encode(&self, key: &str, seq: u64, data: &[u8]) -> Result<Vec<u8>> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(data.to_vec());
+        };
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut nonce = [0u8; NONCE_LEN];
+        keys::random_bytes(None, &mut nonce)?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: data, aad: &frame_aad(key, seq) })
+            .map_err(|_| StorageError::Encrypt(key.to_string()))?;
+        let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + ciphertext.len());
+        frame.extend_from_slice(FRAME_MAGIC);
+        frame.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&nonce);
+        frame.extend_from_slice(&ciphertext);
+        Ok(frame)
+    }
+
+    /// Plaintext of a file read from disk: the concatenation of its frames when encrypted.
+    fn decode(&self, key: &str, data: &[u8]) -> Result<Vec<u8>> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(data.to_vec());
+        };
+        let decoded = decode_frames(cipher, key, data)?;
+        if decoded.end < data.len() {
+            warn!("Dropping truncated frame at the end of {}", key);
+        }
+        Ok(decoded.plain)
+    }
+
+    /// Encrypt files below `dir` that are still plaintext, cut torn last frames off the
+    /// encrypted ones and count their frames; returns how many files were encrypted.
+    fn prepare_files(&self, dir: &Path) -> Result<usize> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(0);
+        };
+        let entries = match fs::read_dir(self.root.join(dir)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let mut converted = 0;
+        for entry in entries {
+            let entry = entry?;
+            let rel = dir.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                converted += self.prepare_files(&rel)?;
+                continue;
+            }
+            let key = rel.to_string_lossy().into_owned();
+            if key.ends_with(".tmp") {
+                continue;
+            }
+            let data = fs::read(entry.path())?;
+            if data.is_empty() {
+                continue;
+            }
+            if !data.starts_with(FRAME_MAGIC) && !data.starts_with(LEGACY_FRAME_MAGIC) {
+                self.write_atomic(&key, &self.encode(&key, 0, &data)?)?;
+                self.frames.lock().unwrap().insert(key, 1);
+                converted += 1;
+                continue;
+            }
+            // Left for reads to report; a wrong key must not cost us the file
+            let decoded = match decode_frames(cipher, &key, &data) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    warn!("Not checking {} for a torn frame: {}", key, e);
+                    continue;
+                }
+            };
+            if decoded.end < data.len() {
+                warn!("Cutting a torn frame off the end of {}", key);
+                let file = OpenOptions::new().write(true).open(entry.path())?;
+                file.set_len(decoded.end as u64)?;
+                file.sync_all()?;
+            }
+            self.frames.lock().unwrap().insert(key, decoded.frames);
+        }
+        Ok(converted)
+    }
+
+    fn write_atomic(&self, key: &str, data: &[u8]) -> Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp = path.with_file_name(tmp_name);
+        {
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(data)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    fn write_append(&self, key: &str, data: &[u8]) -> Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        if let Err(e) = file.write_all(data).and_then(|()| file.sync_data()) {
+            // Don't leave part of a frame for the retry to append after
+            let _ = file.set_len(len);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+/// Associated data of the `seq`th frame of the file at `key`.
+fn frame_aad(key: &str, seq: u64) -> Vec<u8> {
+    let mut aad = key.as_bytes().to_vec();
+    aad.extend_from_slice(&seq.to_be_bytes());
+    aad
+}
+
+/// The whole, authenticated frames at the start of an encrypted file. A power cut
+/// mid-append leaves a partial last frame, or a whole one whose tail never reached the
+/// card; either is left out, while a bad frame before the last fails authentication.
+fn decode_frames(cipher: &ChaCha20Poly1305, key: &str, mut data: &[u8]) -> Result<Decoded> {
+    let total = data.len();
+    let mut decoded = Decoded::default();
+    while !data.is_empty() {
+        let magic = &data[..data.len().min(FRAME_MAGIC.len())];
+        let legacy = LEGACY_FRAME_MAGIC.starts_with(magic);
+        if !legacy && !FRAME_MAGIC.starts_with(magic) {
+            return Err(StorageError::NotEncrypted(key.to_string()));
+        }
+        let len = data.get(4..8).map(|l| u32::from_be_bytes(l.try_into().unwrap()) as usize);
+        let Some(ciphertext) = len.and_then(|len| data.get(FRAME_HEADER_LEN..FRAME_HEADER_LEN + len)) else {
+            break;
+        };
+        let nonce = &data[8..FRAME_HEADER_LEN];
+        let rest = &data[FRAME_HEADER_LEN + ciphertext.len()..];
+        let aad = if legacy { key.as_bytes().to_vec() } else { frame_aad(key, decoded.frames) };
+        match cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad }) {
+            Ok(frame) => decoded.plain.extend_from_slice(&frame),
+            Err(_) if rest.is_empty() && decoded.frames > 0 => break,
+            Err(_) => return Err(StorageError::Authentication(key.to_string())),
+        }
+        decoded.frames += 1;
+        decoded.end = total - rest.len();
+        data = rest;
+    }
+    Ok(decoded)
+}
+
+fn dir_size(path: &Path) -> Result<u64> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut total = 0;
+    for entry in entries {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        total += if meta.is_dir() { dir_size(&entry.path())? } else { meta.len() };
+    }
+    Ok(total)
+}
+
+/// Keys must stay inside the storage root.
+fn validate_key(key: &str) -> Result<()> {
+    let path = Path::new(key);
+    let escapes = path.components().any(|c| !matches!(c, Component::Normal(_)));
+    if key.is_empty() || escapes {
+        return Err(StorageError::InvalidKey(key.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_storage(interval: Duration) -> Arc<Storage> {
+        let dir = std::env::temp_dir().join(format!("streetgrid-storage-{}-{:?}",
+            std::process::id(), std::thread::current().id()));
+        let _ = fs::remove_dir_all(&dir);
+        Storage::open(dir, interval).unwrap()
+    }
+
+    #[test]
+    fn test_writes_are_coalesced_until_flush() {
+        let storage = temp_storage(Duration::from_secs(60));
+        storage.put("state.json", b"one".to_vec()).unwrap();
+        storage.put("state.json", b"two".to_vec()).unwrap();
+
+        // Visible through the layer, but not on disk yet
+        assert_eq!(storage.get("state.json").unwrap(), Some(b"two".to_vec()));
+        assert!(!storage.root().join("state.json").exists());
+
+        storage.flush().unwrap();
+        assert_eq!(fs::read(storage.root().join("state.json")).unwrap(), b"two");
+        assert!(!storage.root().join("state.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_flush_is_rate_limited() {
+        let storage = temp_storage(Duration::from_secs(60));
+        storage.put("a", b"1".to_vec()).unwrap();
+        storage.flush().unwrap();
+
+        storage.put("a", b"2".to_vec()).unwrap();
+        storage.maybe_flush().unwrap();
+        assert_eq!(fs::read(storage.root().join("a")).unwrap(), b"1");
+    }
+
+    #[test]
+    fn test_append_and_remove() {
+        let storage = temp_storage(Duration::ZERO);
+        storage.append("logs/events", b"a\n").unwrap();
+        storage.flush().unwrap();
+        storage.append("logs/events", b"b\n").unwrap();
+        assert_eq!(storage.get("logs/events").unwrap(), Some(b"a\nb\n".to_vec()));
+        storage.flush().unwrap();
+        assert_eq!(fs::read(storage.root().join("logs/events")).unwrap(), b"a\nb\n");
+
+        storage.remove("logs/events").unwrap();
+        assert_eq!(storage.get("logs/events").unwrap(), None);
+        storage.flush().unwrap();
+        assert!(!storage.root().join("logs/events").exists());
+    }
+
+    #[test]
+    fn test_append_after_remove_starts_a_new_file() {
+        let storage = temp_storage(Duration::ZERO);
+        storage.append("logs/events", b"old\n").unwrap();
+        storage.flush().unwrap();
+
+        storage.remove("logs/events").unwrap();
+        storage.append("logs/events", b"new\n").unwrap();
+        assert_eq!(storage.get("logs/events").unwrap(), Some(b"new\n".to_vec()));
+        storage.flush().unwrap();
+        assert_eq!(fs::read(storage.root().join("logs/events")).unwrap(), b"new\n");
+    }
+
+    #[test]
+    fn test_take_reads_and_removes_at_once() {
+        let storage = temp_storage(Duration::ZERO);
+        storage.append("raw/day", b"a\n").unwrap();
+        storage.flush().unwrap();
+        storage.append("raw/day", b"b\n").unwrap();
+
+        assert_eq!(storage.take("raw/day").unwrap(), Some(b"a\nb\n".to_vec()));
+        // Recorded after the take: kept, without what was taken
+        storage.append("raw/day", b"c\n").unwrap();
+        storage.flush().unwrap();
+        assert_eq!(storage.get("raw/day").unwrap(), Some(b"c\n".to_vec()));
+    }
+
+    #[test]
+    fn test_list_and_usage() {
+        let storage = temp_storage(Duration::ZERO);
+        storage.put("m/b", b"22".to_vec()).unwrap();
+        storage.flush().unwrap();
+        storage.append("m/a", b"1").unwrap();
+        storage.put("m/sub/c", b"333".to_vec()).unwrap();
+        storage.put("other", b"4444".to_vec()).unwrap();
+
+        assert_eq!(storage.list("m").unwrap(), vec!["m/a".to_string(), "m/b".to_string()]);
+        storage.remove("m/b").unwrap();
+        assert_eq!(storage.list("m").unwrap(), vec!["m/a".to_string()]);
+
+        storage.flush().unwrap();
+        assert_eq!(storage.disk_usage(Some("m")).unwrap(), 4);
+        assert_eq!(storage.disk_usage(None).unwrap(), 8);
+    }
+
+    #[test]
+    fn test_encrypted_storage_round_trips() {
+        let dir = std::env::temp_dir().join(format!("streetgrid-storage-enc-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("audit")).unwrap();
+        fs::write(dir.join("state.json"), b"{\"state\":\"Normal\"}").unwrap();
+
+        let storage = Storage::open_encrypted(&dir, Duration::ZERO, &[7; KEY_LEN]).unwrap();
+        // Existing plaintext was migrated
+        assert!(fs::read(dir.join("state.json")).unwrap().starts_with(FRAME_MAGIC));
+        assert_eq!(storage.get("state.json").unwrap(), Some(b"{\"state\":\"Normal\"}".to_vec()));
+
+        storage.append("audit/log", b"first\n").unwrap();
+        storage.flush().unwrap();
+        storage.append("audit/log", b"second\n").unwrap();
+        storage.flush().unwrap();
+        let on_disk = fs::read(dir.join("audit/log")).unwrap();
+        assert!(!on_disk.windows(5).any(|w| w == b"first"));
+
+        let reopened = Storage::open_encrypted(&dir, Duration::ZERO, &[7; KEY_LEN]).unwrap();
+        assert_eq!(reopened.get("audit/log").unwrap(), Some(b"first\nsecond\n".to_vec()));
+
+        // Wrong key, or a file swapped for another, fails authentication
+        let wrong = Storage::open_encrypted(&dir, Duration::ZERO, &[8; KEY_LEN]).unwrap();
+        assert!(wrong.get("state.json").is_err());
+        fs::copy(dir.join("state.json"), dir.join("keyring.json")).unwrap();
+        assert!(reopened.get("keyring.json").is_err());
+    }
+
+    #[test]
+    fn test_torn_last_frame_is_cut_off_on_open() {
+        let dir = std::env::temp_dir().join(format!("streetgrid-storage-torn-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let storage = Storage::open_encrypted(&dir, Duration::ZERO, &[7; KEY_LEN]).unwrap();
+        storage.append("audit/log", b"first\n").unwrap();
+        storage.flush().unwrap();
+        let first_frame = fs::metadata(dir.join("audit/log")).unwrap().len();
+        storage.append("audit/log", b"second\n").unwrap();
+        storage.flush().unwrap();
+        drop(storage);
+
+        // Power cut halfway through writing the second frame
+        let log = OpenOptions::new().write(true).open(dir.join("audit/log")).unwrap();
+        log.set_len(first_frame + 10).unwrap();
+        drop(log);
+
+        let reopened = Storage::open_encrypted(&dir, Duration::ZERO, &[7; KEY_LEN]).unwrap();
+        assert_eq!(fs::metadata(dir.join("audit/log")).unwrap().len(), first_frame);
+        reopened.append("audit/log", b"third\n").unwrap();
+        reopened.flush().unwrap();
+        let reopened = Storage::open_encrypted(&dir, Duration::ZERO, &[7; KEY_LEN]).unwrap();
+        assert_eq!(reopened.get("audit/log").unwrap(), Some(b"first\nthird\n".to_vec()));
+
+        // Frames moved out of order fail authentication
+        let data = fs::read(dir.join("audit/log")).unwrap();
+        let (first, second) = data.split_at(first_frame as usize);
+        fs::write(dir.join("audit/log"), [second, first].concat()).unwrap();
+        assert!(reopened.get("audit/log").is_err());
+    }
+
+    #[test]
+    fn test_keys_cannot_escape_root() {
+        let storage = temp_storage(Duration::ZERO);
+        assert!(storage.put("../etc/passwd", vec![]).is_err());
+        assert!(storage.put("/abs", vec![]).is_err());
+    }
+}
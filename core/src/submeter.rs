@@ -0,0 +1,189 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Instant;
+use crate::hal::{self, HalError, PowerSensor};
+use crate::types::CtDirection;
+
+/// Most a clamp is believed to read; more is a fault on the clamp or its wiring
+const MAX_PLAUSIBLE_AMPS: f32 = 200.0;
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+/// A CT clamp on a household circuit, which need not have a relay, on any of the node's
+/// ADC chips, with a ratio, burden and calibration of its own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeteredCircuit {
+    /// I2C address of the ADC the clamp is wired to (default the main ADC's)
+    pub address: Option<u8>,
+    pub channel: u8,
+    /// Primary amps per secondary amp (default the main ADC's)
+    pub ct_ratio: Option<f32>,
+    /// Burden resistor across the clamp, in ohms (default the main ADC's)
+    pub burden_resistor: Option<f32>,
+    /// Correction multiplied into readings, from comparing with a reference meter (default 1.0)
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+    /// Amps the clamp reads with nothing flowing, taken off readings
+    #[serde(default)]
+    pub offset_amps: f32,
+    /// How to sign its readings (default Measured, which only the main ADC can tell)
+    #[serde(default)]
+    pub direction: CtDirection,
+}
+
+/// What a circuit last measured, and the energy through it since the node started.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct CircuitReading {
+    pub amps: f32,
+    /// Negative while exporting
+    pub watts: f32,
+    pub imported_wh: f64,
+    pub exported_wh: f64,
+}
+
+/// A circuit's clamp with its calibration resolved against the main ADC's.
+#[derive(Debug, Clone, PartialEq)]
+struct Clamp {
+    address: u8,
+    channel: u8,
+    amps_per_volt: f32,
+    gain: f32,
+    offset_amps: f32,
+    direction: CtDirection,
+}
+
+/// Meters every configured circuit through its own clamp, across as many ADC chips as
+/// they are wired to, turning the node into a circuit-level sub-meter for the house.
+pub struct SubMeter {
+    chips: BTreeMap<u8, Box<dyn PowerSensor>>,
+    circuits: BTreeMap<String, Clamp>,
+    readings: BTreeMap<String, CircuitReading>,
+    sampled_at: Option<Instant>,
+}
+
+impl SubMeter {
+    /// Meter `circuits` through `chips` (keyed by I2C address). Clamps that don't say are
+    /// taken to be on the chip at `address`, with its `ct_ratio` and `burden_resistor`.
+    pub fn new(
+        chips: BTreeMap<u8, Box<dyn PowerSensor>>,
+        circuits: &BTreeMap<String, MeteredCircuit>,
+        address: u8,
+        ct_ratio: f32,
+        burden_resistor: f32,
+    ) -> hal::Result<Self> {
+        let mut clamps = BTreeMap::new();
+        for (name, circuit) in circuits {
+            let address = circuit.address.unwrap_or(address);
+            if !chips.contains_key(&address) {
+                return Err(HalError::NotConfigured(format!("circuit {} is on an ADC at {:#04x} that isn't there", name, address)));
+            }
+            let burden_resistor = circuit.burden_resistor.unwrap_or(burden_resistor);
+            if burden_resistor <= 0.0 {
+                return Err(HalError::NotConfigured(format!("circuit {} needs a positive burden_resistor", name)));
+            }
+            clamps.insert(name.clone(), Clamp {
+                address,
+                channel: circuit.channel,
+                amps_per_volt: circuit.ct_ratio.unwrap_or(ct_ratio) / burden_resistor,
+                gain: circuit.gain,
+                offset_amps: circuit.offset_amps,
+                direction: circuit.direction,
+            });
+        }
+        Ok(Self { chips, circuits: clamps, readings: BTreeMap::new(), sampled_at: None })
+    }
+
+    /// Read every circuit at the mains voltage `volts`, adding the energy through it since
+    /// the last round. Circuits whose clamp can't be read, or reads implausibly, keep
+    /// their last reading.
+    pub fn sample(&mut self, now: Instant, volts: f32) -> &BTreeMap<String, CircuitReading> {
+        let hours = self.sampled_at.map_or(0.0, |at| now.saturating_duration_since(at).as_secs_f64() / 3600.0);
+        self.sampled_at = Some(now);
+        for (name, clamp) in &self.circuits {
+            let Some(chip) = self.chips.get_mut(&clamp.address) else { continue };
+            let amps = match chip.read_input_rms(clamp.channel) {
+                Ok(input) => ((input * clamp.amps_per_volt - clamp.offset_amps) * clamp.gain).max(0.0),
+                Err(e) => {
+                    warn!("Sub-meter read of {} (ADC {:#04x} channel {}) failed: {}", name, clamp.address, clamp.channel, e);
+                    continue;
+                }
+            };
+            if !(amps.is_finite() && amps <= MAX_PLAUSIBLE_AMPS) {
+                warn!("Implausible reading {}A on {}, ignoring", amps, name);
+                continue;
+            }
+            let sign = chip.read_power_sign(clamp.channel).unwrap_or(1.0);
+            let watts = clamp.direction.apply(amps * volts * sign);
+            let reading = self.readings.entry(name.clone()).or_default();
+            if watts >= 0.0 {
+                reading.imported_wh += watts as f64 * hours;
+            } else {
+                reading.exported_wh += -watts as f64 * hours;
+            }
+            reading.amps = amps;
+            reading.watts = watts;
+        }
+        &self.readings
+    }
+
+    pub fn readings(&self) -> &BTreeMap<String, CircuitReading> {
+        &self.readings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::adc::mock::MockAdcSensor;
+    use crate::hal::AdcConfig;
+    use std::time::Duration;
+
+    #[test]
+    fn test_circuits_are_metered_through_their_own_clamps_across_chips() {
+        // Both chips carry 100 A clamps on 33 ohm burdens, as the main ADC is set up
+        let mut main = MockAdcSensor::new(AdcConfig::default()).unwrap();
+        main.set_simulated_current(0, 10.0);
+        main.set_simulated_current(1, -8.0);
+        let mut second = MockAdcSensor::new(AdcConfig { address: 0x49, ..Default::default() }).unwrap();
+        second.set_simulated_current(2, 20.0);
+        let chips: BTreeMap<u8, Box<dyn PowerSensor>> = BTreeMap::from([
+            (0x48, Box::new(main) as Box<dyn PowerSensor>),
+            (0x49, Box::new(second) as Box<dyn PowerSensor>),
+        ]);
+
+        let circuit = |address: Option<u8>, channel: u8| MeteredCircuit {
+            address,
+            channel,
+            ct_ratio: None,
+            burden_resistor: None,
+            gain: default_gain(),
+            offset_amps: 0.0,
+            direction: CtDirection::Measured,
+        };
+        let circuits = BTreeMap::from([
+            // Calibrated 2% high against a reference meter
+            ("kitchen".to_string(), MeteredCircuit { gain: 1.02, ..circuit(None, 0) }),
+            ("solar".to_string(), circuit(None, 1)),
+            // Fitted with a 50 A clamp on the second chip
+            ("garage".to_string(), MeteredCircuit { ct_ratio: Some(50.0), ..circuit(Some(0x49), 2) }),
+        ]);
+        let mut meter = SubMeter::new(chips, &circuits, 0x48, 100.0, 33.0).unwrap();
+
+        let start = Instant::now();
+        meter.sample(start, 120.0);
+        let readings = meter.sample(start + Duration::from_secs(1800), 120.0);
+        assert!((readings["kitchen"].amps - 10.2).abs() < 1e-3);
+        assert!((readings["kitchen"].imported_wh - 612.0).abs() < 0.1);
+        assert!((readings["solar"].watts + 960.0).abs() < 0.1);
+        assert!((readings["solar"].exported_wh - 480.0).abs() < 0.1);
+        assert_eq!(readings["solar"].imported_wh, 0.0);
+        assert!((readings["garage"].amps - 10.0).abs() < 1e-3);
+
+        // A clamp on a chip that isn't fitted is refused
+        let circuits = BTreeMap::from([("shed".to_string(), circuit(Some(0x4A), 0))]);
+        assert!(SubMeter::new(BTreeMap::new(), &circuits, 0x48, 100.0, 33.0).is_err());
+    }
+}
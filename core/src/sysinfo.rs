@@ -0,0 +1,164 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Instant;
+
+/// SD card write failures since boot, incremented by anything that persists to disk.
+static SD_WRITE_ERRORS: AtomicU32 = AtomicU32::new(0);
+
+/// Bytes used by persisted data, as last measured by storage compaction.
+static STORAGE_USED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Record a failed write to the SD card.
+pub fn record_write_error() {
+    SD_WRITE_ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Publish the latest measured size of persisted data.
+pub fn set_storage_used(bytes: u64) {
+    STORAGE_USED_BYTES.store(bytes, Ordering::Relaxed);
+}
+
+/// Point-in-time resource usage of the node.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SystemStats {
+    pub uptime_secs: u64,
+    pub free_memory_kb: u64,
+    pub cpu_load: f32,
+    pub fs_free_kb: u64,
+    pub sd_write_errors: u32,
+    pub storage_used_kb: u64,
+}
+
+/// Gathers resource metrics from /proc and the data filesystem.
+/// Values that cannot be read on this platform are reported as zero.
+pub struct SystemMonitor {
+    started: Instant,
+    data_path: PathBuf,
+}
+
+impl SystemMonitor {
+    pub fn new(data_path: impl Into<PathBuf>) -> Self {
+        Self {
+            started: Instant::now(),
+            data_path: data_path.into(),
+        }
+    }
+
+    pub fn collect(&self) -> SystemStats {
+        SystemStats {
+            uptime_secs: self.started.elapsed().as_secs(),
+            free_memory_kb: fs::read_to_string("/proc/meminfo")
+                .ok()
+                .and_then(|s| parse_meminfo_available(&s))
+                .unwrap_or(0),
+            cpu_load: fs::read_to_string("/proc/loadavg")
+                .ok()
+                .and_then(|s| parse_loadavg(&s))
+                .unwrap_or(0.0),
+            fs_free_kb: fs_free_kb(&self.data_path).unwrap_or(0),
+            sd_write_errors: SD_WRITE_ERRORS.load(Ordering::Relaxed),
+            storage_used_kb: STORAGE_USED_BYTES.load(Ordering::Relaxed) / 1024,
+        }
+    }
+}
+
+impl Default for SystemMonitor {
+    fn default() -> Self {
+        Self::new("/")
+    }
+}
+
+/// Stable identifier of the board: the SoC serial on a Raspberry Pi, otherwise the
+/// systemd machine-id. None if neither can be read.
+pub fn hardware_id() -> Option<String> {
+    fs::read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|s| parse_cpuinfo_serial(&s))
+        .or_else(|| {
+            fs::read_to_string("/etc/machine-id")
+                .ok()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        })
+}
+
+/// Extract the `Serial` line of /proc/cpuinfo; all-zero serials (emulators) are ignored.
+fn parse_cpuinfo_serial(cpuinfo: &str) -> Option<String> {
+    cpuinfo.lines()
+        .find(|l| l.starts_with("Serial"))
+        .and_then(|l| l.split(':').nth(1))
+        .map(|v| v.trim().to_lowercase())
+        .filter(|v| !v.is_empty() && v.chars().any(|c| c != '0'))
+}
+
+/// Extract MemAvailable (falling back to MemFree) from /proc/meminfo, in kB.
+fn parse_meminfo_available(meminfo: &str) -> Option<u64> {
+    let field = |name: &str| {
+        meminfo.lines()
+            .find(|l| l.starts_with(name))
+            .and_then(|l| l.split_whitespace().nth(1))
+            .and_then(|v| v.parse().ok())
+    };
+    field("MemAvailable:").or_else(|| field("MemFree:"))
+}
+
+/// Extract the 1-minute load average from /proc/loadavg.
+fn parse_loadavg(loadavg: &str) -> Option<f32> {
+    loadavg.split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(target_os = "linux")]
+fn fs_free_kb(path: &std::path::Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: c_path is NUL-terminated and stat is a valid out-pointer
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64 / 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn fs_free_kb(_path: &std::path::Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_meminfo() {
+        let meminfo = "MemTotal:         443724 kB\nMemFree:           51232 kB\nMemAvailable:     301548 kB\n";
+        assert_eq!(parse_meminfo_available(meminfo), Some(301548));
+
+        // Older kernels have no MemAvailable
+        assert_eq!(parse_meminfo_available("MemFree:  1024 kB\n"), Some(1024));
+    }
+
+    #[test]
+    fn test_parse_cpuinfo_serial() {
+        let cpuinfo = "processor\t: 0\nHardware\t: BCM2835\nRevision\t: c03112\nSerial\t\t: 10000000A1B2C3D4\nModel\t\t: Raspberry Pi 4\n";
+        assert_eq!(parse_cpuinfo_serial(cpuinfo), Some("10000000a1b2c3d4".to_string()));
+        assert_eq!(parse_cpuinfo_serial("Serial\t\t: 0000000000000000\n"), None);
+        assert_eq!(parse_cpuinfo_serial("processor\t: 0\n"), None);
+    }
+
+    #[test]
+    fn test_parse_loadavg() {
+        assert_eq!(parse_loadavg("0.42 0.30 0.25 1/123 4567\n"), Some(0.42));
+        assert_eq!(parse_loadavg(""), None);
+    }
+
+    #[test]
+    fn test_write_errors_are_counted() {
+        let monitor = SystemMonitor::default();
+        let before = monitor.collect().sd_write_errors;
+        record_write_error();
+        assert!(monitor.collect().sd_write_errors > before);
+    }
+}
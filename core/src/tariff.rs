@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::demand_response;
+use crate::types::{Priority, Relay};
+
+/// Storage key of what deferring loads has saved so far
+pub const TARIFF_SAVINGS_KEY: &str = "tariff_savings.json";
+
+/// How often loads are deferred and restored as the price changes
+pub const TARIFF_TICK_SECS: u64 = 60;
+
+/// A window of the day with its own price.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TariffPeriod {
+    pub name: String,
+    /// Local hour the period starts, and the hour it ends (exclusive); it may run past midnight
+    pub start_hour: u8,
+    pub end_hour: u8,
+    /// Price per kWh
+    pub price: f32,
+}
+
+impl TariffPeriod {
+    fn covers(&self, hour: u32) -> bool {
+        let (start, end) = (self.start_hour as u32, self.end_hour as u32);
+        if start <= end {
+            (start..end).contains(&hour)
+        } else {
+            hour >= start || hour < end
+        }
+    }
+}
+
+/// Price of energy through the day.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tariff {
+    /// Price per kWh outside every period
+    pub base_price: f32,
+    #[serde(default)]
+    pub periods: Vec<TariffPeriod>,
+}
+
+impl Tariff {
+    /// The period in force at a local hour, if any, and the price then.
+    pub fn price_at(&self, hour: u32) -> (Option<&str>, f32) {
+        match self.periods.iter().find(|p| p.covers(hour)) {
+            Some(period) => (Some(&period.name), period.price),
+            None => (None, self.base_price),
+        }
+    }
+
+    /// Cheapest price of the day, which a deferred load is assumed to run at instead.
+    pub fn lowest_price(&self) -> f32 {
+        (0..24).map(|hour| self.price_at(hour).1).fold(f32::INFINITY, f32::min)
+    }
+}
+
+/// What deferring loads has saved, estimated against running them at the day's lowest price.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Savings {
+    pub kwh_deferred: f64,
+    pub saved: f64,
+}
+
+/// Defers loads while energy costs more than their priority is worth, and puts them
+/// back once it is cheaper.
+#[derive(Debug)]
+pub struct CostShedder {
+    pub tariff: Tariff,
+    /// Highest price each priority of load runs at; unlisted priorities always run
+    ceilings: BTreeMap<Priority, f32>,
+    /// Relays deferred and their rated watts
+    deferred: Vec<(String, f32)>,
+    /// When we last ticked, and the price then
+    last_tick: Option<(i64, f32)>,
+    savings: Savings,
+}
+
+impl CostShedder {
+    pub fn new(tariff: Tariff, ceilings: BTreeMap<Priority, f32>, savings: Savings) -> Self {
+        Self { tariff, ceilings, deferred: Vec::new(), last_tick: None, savings }
+    }
+
+    pub fn savings(&self) -> &Savings {
+        &self.savings
+    }
+
+    /// Whether loads of `priority` are worth running at `price`. Critical loads always are.
+    fn worth_running(&self, priority: Priority, price: f32) -> bool {
+        priority == Priority::Critical || self.ceilings.get(&priority).is_none_or(|ceiling| price <= *ceiling)
+    }
+
+    /// Count what was saved since the last tick, at the price then. Then, if `defer`,
+    /// defer the loads not worth running at the price in force at local `hour`.
+    /// Returns the relays to open and those whose deferral is over.
+    pub fn tick(&mut self, relays: &[Relay], voltage_ref: f32, hour: u32, now: i64, defer: bool) -> (Vec<String>, Vec<String>) {
+        let price = self.tariff.price_at(hour).1;
+        if let Some((last, last_price)) = self.last_tick {
+            let hours = (now - last).max(0) as f64 / 3600.0;
+            let kwh = self.deferred.iter().map(|(_, watts)| *watts as f64).sum::<f64>() / 1000.0 * hours;
+            self.savings.kwh_deferred += kwh;
+            self.savings.saved += kwh * (last_price - self.tariff.lowest_price()).max(0.0) as f64;
+        }
+        self.last_tick = Some((now, price));
+
+        let mut close = Vec::new();
+        let mut index = 0;
+        while index < self.deferred.len() {
+            let priority = relays.iter().find(|r| r.id == self.deferred[index].0).map(|r| r.priority);
+            if priority.is_none_or(|priority| self.worth_running(priority, price)) {
+                close.push(self.deferred.remove(index).0);
+            } else {
+                index += 1;
+            }
+        }
+        let open: Vec<(String, f32)> = demand_response::sheddable(relays, Priority::High)
+            .filter(|r| defer && !self.worth_running(r.priority, price))
+            .map(|r| (r.id.clone(), r.amperage * voltage_ref))
+            .collect();
+        self.deferred.extend(open.iter().cloned());
+        (open.into_iter().map(|(id, _)| id).collect(), close)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::RelayType;
+
+    fn load(id: &str, priority: Priority) -> Relay {
+        Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage: 10.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        }
+    }
+
+    #[test]
+    fn test_low_priority_loads_wait_out_the_peak() {
+        let tariff = Tariff {
+            base_price: 0.15,
+            periods: vec![
+                TariffPeriod { name: "peak".to_string(), start_hour: 16, end_hour: 21, price: 0.45 },
+                TariffPeriod { name: "night".to_string(), start_hour: 23, end_hour: 6, price: 0.05 },
+            ],
+        };
+        assert_eq!(tariff.price_at(2), (Some("night"), 0.05));
+        assert_eq!(tariff.price_at(12), (None, 0.15));
+        assert_eq!(tariff.lowest_price(), 0.05);
+
+        let ceilings = BTreeMap::from([(Priority::Low, 0.30), (Priority::Medium, 0.60)]);
+        let mut shedder = CostShedder::new(tariff, ceilings, Savings::default());
+        let mut relays = vec![load("r_fridge", Priority::High), load("r_hvac", Priority::Medium), load("r_washer", Priority::Low)];
+        assert_eq!(shedder.tick(&relays, 120.0, 15, 0, true), (vec![], vec![]));
+
+        // The peak defers only the washer; the HVAC is worth it up to 0.60
+        assert_eq!(shedder.tick(&relays, 120.0, 16, 3600, true), (vec!["r_washer".to_string()], vec![]));
+        relays[2].is_closed = false;
+        assert_eq!(shedder.tick(&relays, 120.0, 20, 5 * 3600, true), (vec![], vec![]));
+        assert_eq!(shedder.tick(&relays, 120.0, 21, 6 * 3600, true), (vec![], vec!["r_washer".to_string()]));
+
+        // 1.2 kW held off through five hours of peak, 0.40 dearer than overnight
+        let savings = shedder.savings();
+        assert!((savings.kwh_deferred - 6.0).abs() < 1e-6);
+        assert!((savings.saved - 6.0 * 0.40).abs() < 1e-4);
+    }
+}
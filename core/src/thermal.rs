@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use crate::hal::{self, HalError};
+use crate::interlock;
+use crate::types::{Relay, RelayType};
+
+/// Degrees the board must cool below `derate_above_c` before derating ends, so it doesn't
+/// flap on and off around the threshold
+const RECOVERY_MARGIN_C: f32 = 5.0;
+
+fn default_sensor() -> String {
+    "/sys/class/thermal/thermal_zone0/temp".to_string()
+}
+
+fn default_derate_above_c() -> f32 {
+    60.0
+}
+
+fn default_full_derate_c() -> f32 {
+    80.0
+}
+
+fn default_derated_share() -> f32 {
+    0.5
+}
+
+fn default_high_current_amps() -> f32 {
+    16.0
+}
+
+/// When and how far the relays are derated as the board heats up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThermalSettings {
+    /// File reading the temperature in millidegrees, as Linux thermal zones and 1-Wire
+    /// sensors do (default the SoC's thermal zone)
+    #[serde(default = "default_sensor")]
+    pub sensor: String,
+    /// Total current the relays may carry while the board is cool
+    pub max_amps: f32,
+    /// Temperature above which derating starts (default 60 °C)
+    #[serde(default = "default_derate_above_c")]
+    pub derate_above_c: f32,
+    /// Temperature at which derating reaches `derated_share` (default 80 °C)
+    #[serde(default = "default_full_derate_c")]
+    pub full_derate_c: f32,
+    /// Share of `max_amps` left at `full_derate_c` and above (default 0.5)
+    #[serde(default = "default_derated_share")]
+    pub derated_share: f32,
+    /// Rating at and above which a relay isn't closed while derating (default 16 A)
+    #[serde(default = "default_high_current_amps")]
+    pub high_current_amps: f32,
+}
+
+/// What the relays may carry while the board is derated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Derating {
+    pub allowed_amps: f32,
+    pub high_current_amps: f32,
+}
+
+/// Read a temperature in millidegrees Celsius from a sysfs file.
+pub fn read_celsius(path: &str) -> hal::Result<f32> {
+    let raw = std::fs::read_to_string(path)
+        .map_err(|e| HalError::Open { device: path.to_string(), reason: e.to_string() })?;
+    let millidegrees: f32 = raw.trim().parse()
+        .map_err(|e| HalError::Protocol(format!("parsing {:?} from {}: {}", raw.trim(), path, e)))?;
+    Ok(millidegrees / 1000.0)
+}
+
+/// Derates the total current the relays may carry while the board runs hot, opening
+/// loads lowest priority first to fit, and closes them again once it has cooled.
+#[derive(Debug)]
+pub struct ThermalDerating {
+    pub settings: ThermalSettings,
+    temperature_c: Option<f32>,
+    derating: bool,
+    /// Relays opened to fit the derated current
+    shed: Vec<String>,
+}
+
+impl ThermalDerating {
+    pub fn new(settings: ThermalSettings) -> Self {
+        Self { settings, temperature_c: None, derating: false, shed: Vec::new() }
+    }
+
+    /// Record a temperature reading. Returns whether the board is now derating, if that changed.
+    pub fn record(&mut self, celsius: f32) -> Option<bool> {
+        self.temperature_c = Some(celsius);
+        let derating = if self.derating {
+            celsius > self.settings.derate_above_c - RECOVERY_MARGIN_C
+        } else {
+            celsius > self.settings.derate_above_c
+        };
+        (derating != self.derating).then(|| {
+            self.derating = derating;
+            derating
+        })
+    }
+
+    pub fn temperature_c(&self) -> Option<f32> {
+        self.temperature_c
+    }
+
+    /// Limits in force while derating, falling linearly from `max_amps` as the board heats.
+    pub fn derating(&self) -> Option<Derating> {
+        if !self.derating {
+            return None;
+        }
+        let settings = &self.settings;
+        let span = (settings.full_derate_c - settings.derate_above_c).max(f32::EPSILON);
+        let progress = ((self.temperature_c? - settings.derate_above_c) / span).clamp(0.0, 1.0);
+        let share = 1.0 - progress * (1.0 - settings.derated_share);
+        Some(Derating { allowed_amps: settings.max_amps * share, high_current_amps: settings.high_current_amps })
+    }
+
+    /// Loads to open while the relays carry more than the derated current, and loads shed
+    /// earlier to close again once derating has ended. Loads the budget never sheds are left alone.
+    pub fn tick(&mut self, relays: &[Relay], amps: impl Fn(&Relay) -> f32) -> (Vec<String>, Vec<String>) {
+        // Loads closed again by someone else are no longer ours to restore
+        self.shed.retain(|id| relays.iter().any(|r| r.id == *id && !r.is_closed));
+        let Some(derating) = self.derating() else {
+            return (Vec::new(), std::mem::take(&mut self.shed));
+        };
+        let loads = || relays.iter().filter(|r| r.relay_type == RelayType::Load && r.is_closed);
+        let mut total: f32 = loads().map(&amps).sum();
+        let mut sheddable: Vec<&Relay> = loads()
+            .filter(|r| !interlock::protected_from_budget(r) && amps(r) > 0.0)
+            .collect();
+        sheddable.sort_by_key(|r| std::cmp::Reverse(r.priority));
+        let mut open = Vec::new();
+        for relay in sheddable {
+            if total <= derating.allowed_amps {
+                break;
+            }
+            total -= amps(relay);
+            open.push(relay.id.clone());
+        }
+        self.shed.extend(open.iter().cloned());
+        (open, Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Priority;
+
+    #[test]
+    fn test_hot_board_sheds_to_the_derated_current_until_it_cools() {
+        let load = |id: &str, priority: Priority| Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type: RelayType::Load,
+            priority,
+            amperage: 20.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        };
+        let settings = ThermalSettings {
+            sensor: default_sensor(),
+            max_amps: 50.0,
+            derate_above_c: default_derate_above_c(),
+            full_derate_c: default_full_derate_c(),
+            derated_share: default_derated_share(),
+            high_current_amps: default_high_current_amps(),
+        };
+        let mut thermal = ThermalDerating::new(settings);
+        let mut relays = vec![load("r_fridge", Priority::Critical), load("r_oven", Priority::Medium), load("r_kiln", Priority::Low)];
+        let amps = |r: &Relay| if r.id == "r_fridge" { 5.0 } else { 20.0 };
+
+        assert_eq!(thermal.record(55.0), None);
+        assert_eq!(thermal.tick(&relays, amps), (vec![], vec![]));
+
+        // Halfway to full derating: three quarters of the 50 A, short of the 45 A drawn
+        assert_eq!(thermal.record(70.0), Some(true));
+        assert_eq!(thermal.derating().unwrap().allowed_amps, 37.5);
+        assert_eq!(thermal.tick(&relays, amps), (vec!["r_kiln".to_string()], vec![]));
+        relays[2].is_closed = false;
+
+        // Still derating until it is well clear of the threshold
+        assert_eq!(thermal.record(58.0), None);
+        assert_eq!(thermal.tick(&relays, amps), (vec![], vec![]));
+        assert_eq!(thermal.record(54.0), Some(false));
+        assert_eq!(thermal.derating(), None);
+        assert_eq!(thermal.tick(&relays, amps), (vec![], vec!["r_kiln".to_string()]));
+    }
+}
@@ -0,0 +1,202 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Storage key of the client certificate and key issued on joining or rotation.
+pub const TLS_IDENTITY_KEY: &str = "tls_identity.json";
+/// Storage key of orchestrator pins delivered by a rotation (supersede the configured ones).
+pub const TLS_PINS_KEY: &str = "tls_pins.json";
+
+/// SHA-256 of a certificate's DER encoding.
+pub type Pin = [u8; 32];
+
+/// Why a TLS identity, pin or client configuration can't be loaded or used.
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("reading {path}: {source}")]
+    Read { path: String, source: std::io::Error },
+    #[error("PEM: {0}")]
+    Pem(#[from] std::io::Error),
+    #[error("client certificate PEM holds no certificates")]
+    NoCertificate,
+    #[error("client key PEM holds no private key")]
+    NoPrivateKey,
+    #[error("TLS needs orchestrator pins or a CA certificate")]
+    NoTrustAnchor,
+    #[error("pin must be a hex SHA-256 certificate fingerprint")]
+    BadPin,
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+}
+
+pub type Result<T, E = TlsError> = std::result::Result<T, E>;
+
+/// Per-node client certificate (PEM chain) and its private key (PEM).
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct TlsIdentity {
+    pub cert_pem: String,
+    pub key_pem: String,
+}
+
+impl std::fmt::Debug for TlsIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsIdentity").field("key_pem", &"<redacted>").finish_non_exhaustive()
+    }
+}
+
+impl TlsIdentity {
+    pub fn from_files(cert_path: &str, key_path: &str) -> Result<Self> {
+        let read = |path: &str| std::fs::read_to_string(path).map_err(|source| TlsError::Read { path: path.to_string(), source });
+        Ok(Self { cert_pem: read(cert_path)?, key_pem: read(key_path)? })
+    }
+
+    fn parse(&self) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+        let certs = rustls_pemfile::certs(&mut self.cert_pem.as_bytes()).collect::<Result<Vec<_>, _>>()?;
+        if certs.is_empty() {
+            return Err(TlsError::NoCertificate);
+        }
+        let key = rustls_pemfile::private_key(&mut self.key_pem.as_bytes())?
+            .ok_or(TlsError::NoPrivateKey)?;
+        Ok((certs, key))
+    }
+}
+
+/// How the node authenticates the orchestrator and itself over TLS.
+#[derive(Debug, Clone, Default)]
+pub struct TlsSettings {
+    /// Accepted orchestrator certificate fingerprints; when set, nothing else is trusted
+    pub pins: Vec<Pin>,
+    /// CA bundle (PEM) used when no pins are configured
+    pub ca_pem: Option<String>,
+    /// Client certificate; without one the node can only reach the provisioning endpoint
+    pub identity: Option<TlsIdentity>,
+}
+
+impl TlsSettings {
+    pub fn client_config(&self) -> Result<Arc<ClientConfig>> {
+        let builder = if self.pins.is_empty() {
+            let Some(ca_pem) = &self.ca_pem else {
+                return Err(TlsError::NoTrustAnchor);
+            };
+            let mut roots = RootCertStore::empty();
+            for cert in rustls_pemfile::certs(&mut ca_pem.as_bytes()) {
+                roots.add(cert?)?;
+            }
+            ClientConfig::builder().with_root_certificates(roots)
+        } else {
+            ClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(PinnedVerifier::new(self.pins.clone())))
+        };
+        let config = match &self.identity {
+            Some(identity) => {
+                let (certs, key) = identity.parse()?;
+                builder.with_client_auth_cert(certs, key)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        Ok(Arc::new(config))
+    }
+}
+
+pub fn fingerprint(der: &[u8]) -> Pin {
+    Sha256::digest(der).into()
+}
+
+pub fn parse_pin(hex_pin: &str) -> Result<Pin> {
+    let mut pin = [0u8; 32];
+    hex::decode_to_slice(hex_pin.trim().replace(':', ""), &mut pin).map_err(|_| TlsError::BadPin)?;
+    Ok(pin)
+}
+
+/// Trusts exactly the pinned orchestrator certificates, regardless of CA or hostname.
+/// Handshake signatures are still checked against the presented certificate.
+#[derive(Debug)]
+struct PinnedVerifier {
+    pins: Vec<Pin>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl PinnedVerifier {
+    fn new(pins: Vec<Pin>) -> Self {
+        Self {
+            pins,
+            algorithms: rustls::crypto::ring::default_provider().signature_verification_algorithms,
+        }
+    }
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if self.pins.contains(&fingerprint(end_entity)) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General("orchestrator certificate does not match any pin".to_string()))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_pinned_certificates_are_trusted() {
+        let orchestrator = CertificateDer::from(b"orchestrator certificate".to_vec());
+        let impostor = CertificateDer::from(b"impostor certificate".to_vec());
+        let pin = parse_pin(&hex::encode(fingerprint(&orchestrator))).unwrap();
+        let verifier = PinnedVerifier::new(vec![pin]);
+        let name = ServerName::try_from("orchestrator.local").unwrap();
+
+        assert!(verifier.verify_server_cert(&orchestrator, &[], &name, &[], UnixTime::now()).is_ok());
+        assert!(verifier.verify_server_cert(&impostor, &[], &name, &[], UnixTime::now()).is_err());
+    }
+
+    #[test]
+    fn test_settings_validation() {
+        assert!(TlsSettings::default().client_config().is_err());
+        let pinned = TlsSettings { pins: vec![[1; 32]], ..Default::default() };
+        assert!(pinned.client_config().is_ok());
+
+        let broken = TlsSettings {
+            identity: Some(TlsIdentity { cert_pem: String::new(), key_pem: String::new() }),
+            ..pinned
+        };
+        assert!(broken.client_config().is_err());
+        assert!(parse_pin("AB:CD").is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use std::time::{Duration, Instant};
+use crate::types::Relay;
+
+/// Dead time when none is configured: long enough for the old source's contacts to
+/// clear and an inverter to lose its reference before the next source is connected.
+pub const DEFAULT_DEAD_TIME: Duration = Duration::from_millis(500);
+
+/// How often a transfer waiting out its dead time is looked at
+pub const TRANSFER_TICK: Duration = Duration::from_millis(100);
+
+/// A source the house can be fed from through the transfer group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Supply {
+    Grid,
+    Generator,
+    /// An inverter forming its own grid, which must never be paralleled with the others
+    Inverter,
+}
+
+/// What a transfer does next.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// Open these relays of other sources
+    Break(Vec<String>),
+    /// The old source is off; waiting out the dead time
+    Wait,
+    /// Close these relays of the new source
+    Make(Vec<String>),
+    /// Nothing left to switch
+    Done,
+}
+
+/// The relays switching the house between sources. At most one source is connected at
+/// a time, and after one is disconnected the next waits out the dead time: every
+/// change of source is break-before-make.
+#[derive(Debug, Clone)]
+pub struct TransferGroup {
+    /// Relay connecting a grid-forming inverter, if it is part of the group
+    pub inverter_relay: Option<String>,
+    pub dead_time: Duration,
+    /// Source being switched to, or none to disconnect them all, while a transfer is under way
+    pending: Option<Option<Supply>>,
+    trigger: String,
+    /// When a source was last disconnected
+    broken_at: Option<Instant>,
+}
+
+impl TransferGroup {
+    pub fn new(dead_time: Duration) -> Self {
+        Self { inverter_relay: None, dead_time, pending: None, trigger: String::new(), broken_at: None }
+    }
+
+    /// Start switching to `to`, or to no source at all, on behalf of `trigger`.
+    pub fn begin(&mut self, to: Option<Supply>, trigger: &str) {
+        self.pending = Some(to);
+        self.trigger = trigger.to_string();
+    }
+
+    /// The source a transfer under way is switching to, `Some(None)` if it is
+    /// disconnecting them all.
+    pub fn pending(&self) -> Option<Option<Supply>> {
+        self.pending
+    }
+
+    /// What started the transfer under way, for the relay audit trail.
+    pub fn trigger(&self) -> &str {
+        &self.trigger
+    }
+
+    /// Whether a source was disconnected less than the dead time before `now`.
+    pub fn in_dead_time(&self, now: Instant) -> bool {
+        self.broken_at.is_some_and(|at| now < at + self.dead_time)
+    }
+
+    /// Next step of the transfer under way. `members` are the group's relays and the
+    /// source each connects; `relays` say which are closed.
+    pub fn step(&self, members: &[(String, Supply)], relays: &[Relay], now: Instant) -> Step {
+        let Some(to) = self.pending else { return Step::Done };
+        let closed = |id: &str| relays.iter().any(|r| r.id == id && r.is_closed);
+        let others: Vec<String> = members.iter()
+            .filter(|(id, supply)| Some(*supply) != to && closed(id))
+            .map(|(id, _)| id.clone())
+            .collect();
+        if !others.is_empty() {
+            return Step::Break(others);
+        }
+        let open: Vec<String> = members.iter()
+            .filter(|(id, supply)| Some(*supply) == to && !closed(id))
+            .map(|(id, _)| id.clone())
+            .collect();
+        match open.is_empty() {
+            true => Step::Done,
+            false if self.in_dead_time(now) => Step::Wait,
+            false => Step::Make(open),
+        }
+    }
+
+    /// The relays of `Step::Break` were opened at `now`.
+    pub fn broke(&mut self, now: Instant) {
+        self.broken_at = Some(now);
+    }
+
+    /// The transfer completed, or was given up.
+    pub fn finish(&mut self) {
+        self.pending = None;
+    }
+}
+
+impl Default for TransferGroup {
+    fn default() -> Self {
+        Self::new(DEFAULT_DEAD_TIME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Priority, RelayType};
+
+    fn relay(id: &str, relay_type: RelayType, is_closed: bool) -> Relay {
+        Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type,
+            priority: Priority::High,
+            amperage: 30.0,
+            is_closed,
+            community_criticality: 0,
+            phase: None,
+        }
+    }
+
+    #[test]
+    fn test_grid_to_generator_breaks_then_waits_out_the_dead_time() {
+        let members = vec![
+            ("r_grid".to_string(), Supply::Grid),
+            ("r_gen".to_string(), Supply::Generator),
+            ("r_inv".to_string(), Supply::Inverter),
+        ];
+        let mut relays = vec![
+            relay("r_grid", RelayType::Grid, true),
+            relay("r_gen", RelayType::Source, false),
+            relay("r_inv", RelayType::Source, false),
+        ];
+        let mut group = TransferGroup::new(Duration::from_millis(500));
+        let start = Instant::now();
+        assert_eq!(group.step(&members, &relays, start), Step::Done);
+
+        group.begin(Some(Supply::Generator), "generator");
+        assert_eq!(group.step(&members, &relays, start), Step::Break(vec!["r_grid".to_string()]));
+        relays[0].is_closed = false;
+        group.broke(start);
+        assert_eq!(group.step(&members, &relays, start + Duration::from_millis(400)), Step::Wait);
+        assert_eq!(group.step(&members, &relays, start + Duration::from_millis(500)), Step::Make(vec!["r_gen".to_string()]));
+        relays[1].is_closed = true;
+        assert_eq!(group.step(&members, &relays, start + Duration::from_millis(500)), Step::Done);
+        group.finish();
+
+        // Disconnecting everything only breaks
+        group.begin(None, "island");
+        assert_eq!(group.step(&members, &relays, start + Duration::from_secs(1)), Step::Break(vec!["r_gen".to_string()]));
+        relays[1].is_closed = false;
+        assert_eq!(group.step(&members, &relays, start + Duration::from_secs(1)), Step::Done);
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+pub enum NodeState {
+    Joining,    // Not commissioned yet: only join traffic is accepted
+    Normal,
+    AlertSent,  // Waiting for orchestrator response after voltage drop
+    Islanded,
+    BlackStart,
+    Faulted,    // Emergency stop asserted: relays open and latched until a fault reset
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum MeshType {
+    AdHoc,              // Option 1: Node disconnects from utility grid
+    GovernmentSanctioned, // Option 2: MID handles isolation, node keeps grid connected
+}
+
+impl Default for MeshType {
+    fn default() -> Self {
+        MeshType::AdHoc
+    }
+}
+
+/// Transformer phase a household or circuit is wired to.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum Phase {
+    A,
+    B,
+    C,
+}
+
+impl Phase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::A => "A",
+            Phase::B => "B",
+            Phase::C => "C",
+        }
+    }
+}
+
+/// Which way power through a CT clamp flows, and how we know: positive readings are
+/// imported from the grid, negative ones exported to it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Default)]
+pub enum CtDirection {
+    /// Against the ADC's voltage reference; without one, everything reads as import
+    #[default]
+    Measured,
+    /// Against the voltage reference, with the clamp fitted the wrong way round
+    Reversed,
+    /// Only ever carries power in
+    Import,
+    /// Only ever carries power out, e.g. a clamp on a solar feed
+    Export,
+}
+
+impl CtDirection {
+    /// Sign a reading from a clamp fitted this way.
+    pub fn apply(self, watts: f32) -> f32 {
+        match self {
+            CtDirection::Measured => watts,
+            CtDirection::Reversed => -watts,
+            CtDirection::Import => watts.abs(),
+            CtDirection::Export => -watts.abs(),
+        }
+    }
+}
+
+/// What the node does on the mesh.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, Default)]
+pub enum NodeRole {
+    #[default]
+    Participant, // Household edge node
+    Mid,         // Transformer isolation device; its Grid relays are the transformer breaker
+    Repeater,    // LoRa repeater: no relays or sensors, forwards mesh traffic
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum RelayType {
+    Source = 0, // Battery, Solar, EV
+    Load = 1,   // Appliances, HVAC
+    Grid = 2,   // Main Grid Connection
+}
+
+#[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Clone, Serialize, Deserialize, Copy)]
+pub enum Priority {
+    Critical = 0, // Medical, Comms (Lowest number = Highest priority)
+    High = 1,     // Fridge, Lights
+    Medium = 2,   // HVAC
+    Low = 3,      // TV, Washer
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Relay {
+    pub id: String,
+    pub name: String,
+    pub relay_type: RelayType,
+    pub priority: Priority,
+    pub amperage: f32, // Max capacity or current draw
+    pub is_closed: bool,
+    /// How much the neighbourhood, not just this household, relies on the load
+    /// (0 = not at all, up to 3 for life support); funded first in a constrained island
+    #[serde(default)]
+    pub community_criticality: u8,
+    /// Phase the circuit is on, for three-phase services; defaults to the node's phase
+    #[serde(default)]
+    pub phase: Option<Phase>,
+}
@@ -1,7 +0,0 @@
-pub mod gpio;
-pub mod adc;
-pub mod lora;
-
-pub use gpio::{RelayControl, RelayPin, create_relay_driver};
-pub use adc::{PowerSensor, AdcConfig, create_power_sensor};
-pub use lora::{LoRaRadio, LoRaHalConfig, create_lora_radio};
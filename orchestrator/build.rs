@@ -0,0 +1,5 @@
+fn main() {
+    tonic_build::configure()
+        .compile(&["../proto/neighborhood.proto", "../proto/northbound.proto"], &["../proto/"])
+        .unwrap();
+}
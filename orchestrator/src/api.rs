@@ -0,0 +1,423 @@
+use anyhow::Result;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use crate::commands::{self, Command};
+use crate::fleet::{reporting_node, Fleet};
+use crate::orchestrator::RemoteCommand;
+use crate::timeline::Timeline;
+use crate::topology::Topology;
+use crate::transport::streetgrid::northbound::issue_command_request::Target;
+use crate::transport::NeighborhoodMessage;
+use crate::vpp;
+
+/// Largest request head we accept; the API only serves simple GETs.
+const MAX_REQUEST_HEAD: usize = 8192;
+
+/// Largest request body accepted by the HTTP endpoints that take one
+const MAX_REQUEST_BODY: usize = 64 * 1024;
+
+/// Idle event streams get a comment this often, so proxies don't close them
+const STREAM_KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// Live data and controls for the bundled web dashboard.
+pub struct Dashboard {
+    pub telemetry: broadcast::Sender<NeighborhoodMessage>,
+    pub timeline: Arc<Mutex<Timeline>>,
+    /// Where dashboard commands go, and the bearer token allowed to send them;
+    /// without it the dashboard is read-only
+    pub commands: Option<(mpsc::Sender<RemoteCommand>, String)>,
+    /// Frontend files, served for any other GET path
+    pub static_dir: Option<PathBuf>,
+}
+
+/// Minimal HTTP API for operator dashboards.
+///
+/// Routes:
+/// - `GET /nodes` — every registered node as a JSON array
+/// - `GET /nodes/<id>` — one node, 404 if it never reported in
+/// - `GET /topology` — who hears whom, connected groups and isolated nodes
+/// - `GET /vpp` — flexible load and storage across the fleet, for demand response
+///
+/// With a dashboard:
+/// - `GET /timeline` — recent alarms, liveness changes and commands, oldest first
+/// - `GET /stream` — server-sent events: `node` with a node's record each time it
+///   reports, `timeline` with each new timeline entry
+/// - `POST /commands` — `{"command": "shed @block_3"}`, any console command that is
+///   sent to a node or zone; needs `Authorization: Bearer <token>`
+/// - `GET /<file>` — the frontend, `index.html` for `/`
+pub struct FleetApi {
+    listener: TcpListener,
+    routes: Routes,
+}
+
+struct Routes {
+    fleet: Arc<Mutex<Fleet>>,
+    topology: Arc<Mutex<Topology>>,
+    dashboard: Option<Dashboard>,
+}
+
+/// Body of `POST /commands`
+#[derive(Debug, Deserialize)]
+struct CommandRequest {
+    command: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CommandResult {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl FleetApi {
+    pub async fn bind(addr: &str, fleet: Arc<Mutex<Fleet>>, topology: Arc<Mutex<Topology>>) -> Result<Self> {
+        let api = Self { listener: TcpListener::bind(addr).await?, routes: Routes { fleet, topology, dashboard: None } };
+        info!("Fleet API listening on {}", api.local_addr()?);
+        Ok(api)
+    }
+
+    /// Also serve the web dashboard's live data, commands and frontend.
+    pub fn with_dashboard(mut self, dashboard: Dashboard) -> Self {
+        if dashboard.commands.is_none() {
+            info!("Dashboard is read-only: no command token configured");
+        }
+        self.routes.dashboard = Some(dashboard);
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accept connections forever, one task per client.
+    pub async fn run(self) {
+        let routes = Arc::new(self.routes);
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, peer)) => {
+                    debug!("API connection from {}", peer);
+                    let routes = routes.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(stream, &routes).await {
+                            debug!("API connection from {} closed: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => warn!("API accept failed: {}", e),
+            }
+        }
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, routes: &Routes) -> Result<()> {
+    let head = read_request_head(&mut stream).await?;
+    let (method, path) = parse_request_line(&head)
+        .ok_or_else(|| anyhow::anyhow!("malformed request line"))?;
+    let (fleet, topology) = (&routes.fleet, &routes.topology);
+
+    // Serialize under the lock, write after releasing it
+    let body = match (method, path.trim_end_matches('/')) {
+        ("GET", "/nodes") => {
+            let fleet = fleet.lock().unwrap();
+            Some(serde_json::to_string(&fleet.nodes().collect::<Vec<_>>())?)
+        }
+        ("GET", "/topology") => {
+            let nodes: Vec<String> = fleet.lock().unwrap().nodes().map(|n| n.node_id.clone()).collect();
+            Some(serde_json::to_string(&topology.lock().unwrap().view(nodes))?)
+        }
+        ("GET", "/vpp") => {
+            let fleet = fleet.lock().unwrap();
+            Some(serde_json::to_string(&vpp::aggregate(fleet.nodes(), chrono::Utc::now().timestamp()))?)
+        }
+        ("GET", route) if route.starts_with("/nodes/") => {
+            fleet.lock().unwrap().get(&route["/nodes/".len()..]).map(serde_json::to_string).transpose()?
+        }
+        _ => match &routes.dashboard {
+            Some(dashboard) => return serve_dashboard(stream, &head, method, path, fleet, dashboard).await,
+            None => None,
+        },
+    };
+    match body {
+        Some(json) => write_response(&mut stream, "200 OK", "application/json", &json).await,
+        None => write_response(&mut stream, "404 Not Found", "text/plain", "not found\n").await,
+    }
+}
+
+async fn serve_dashboard(
+    mut stream: TcpStream,
+    head: &str,
+    method: &str,
+    path: &str,
+    fleet: &Arc<Mutex<Fleet>>,
+    dashboard: &Dashboard,
+) -> Result<()> {
+    match (method, path.trim_end_matches('/')) {
+        ("GET", "/timeline") => {
+            let json = serde_json::to_string(&dashboard.timeline.lock().unwrap().entries().collect::<Vec<_>>())?;
+            write_response(&mut stream, "200 OK", "application/json", &json).await
+        }
+        ("GET", "/stream") => stream_events(stream, fleet, dashboard).await,
+        ("POST", "/commands") => {
+            let Some((commands, token)) = &dashboard.commands else {
+                return write_response(&mut stream, "403 Forbidden", "text/plain", "dashboard is read-only\n").await;
+            };
+            if !bearer_token(head).is_some_and(|presented| token_matches(presented, token)) {
+                return write_response(&mut stream, "401 Unauthorized", "text/plain", "unauthorized\n").await;
+            }
+            let body = read_request_body(&mut stream, head).await?;
+            let (status, result) = match send_command(&body, commands).await {
+                Ok(()) => ("200 OK", CommandResult { ok: true, error: None }),
+                Err(e) => ("409 Conflict", CommandResult { ok: false, error: Some(e.to_string()) }),
+            };
+            write_response(&mut stream, status, "application/json", &serde_json::to_string(&result)?).await
+        }
+        ("GET", _) => match &dashboard.static_dir {
+            Some(dir) => match static_file(dir, path).await {
+                Some((bytes, content_type)) => write_response(&mut stream, "200 OK", content_type, &bytes).await,
+                None => write_response(&mut stream, "404 Not Found", "text/plain", "not found\n").await,
+            },
+            None => write_response(&mut stream, "404 Not Found", "text/plain", "not found\n").await,
+        },
+        _ => write_response(&mut stream, "404 Not Found", "text/plain", "not found\n").await,
+    }
+}
+
+/// Parse a console command line and hand it to the orchestrator loop. Only commands
+/// that go to a node or zone can be sent; the rest are the operator console's.
+async fn send_command(body: &[u8], commands: &mpsc::Sender<RemoteCommand>) -> Result<()> {
+    let request: CommandRequest = serde_json::from_slice(body)?;
+    let (target, payload) = match commands::parse(&request.command, chrono::Utc::now().timestamp())? {
+        Command::Issue { target, payload } => (Target::NodeId(target), payload),
+        Command::IssueZone { zone, payload } => (Target::Zone(zone), payload),
+        _ => anyhow::bail!("only commands sent to a node or zone can be issued from the dashboard"),
+    };
+    let (reply, result) = oneshot::channel();
+    commands.send(RemoteCommand { target, payload, client: "dashboard".to_string(), reply }).await
+        .map_err(|_| anyhow::anyhow!("orchestrator is shutting down"))?;
+    result.await.map_err(|_| anyhow::anyhow!("orchestrator dropped the command"))?
+}
+
+/// Server-sent events until the client goes away.
+async fn stream_events(mut stream: TcpStream, fleet: &Arc<Mutex<Fleet>>, dashboard: &Dashboard) -> Result<()> {
+    let mut telemetry = dashboard.telemetry.subscribe();
+    let mut timeline = dashboard.timeline.lock().unwrap().subscribe();
+    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n").await?;
+    let mut keepalive = tokio::time::interval_at(tokio::time::Instant::now() + STREAM_KEEPALIVE, STREAM_KEEPALIVE);
+    loop {
+        let (event, data) = tokio::select! {
+            msg = telemetry.recv() => match msg {
+                Ok(msg) => {
+                    let Some(node_id) = msg.payload.as_ref().and_then(reporting_node) else { continue };
+                    let Some(json) = fleet.lock().unwrap().get(node_id).map(serde_json::to_string).transpose()? else { continue };
+                    ("node", json)
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            entry = timeline.recv() => match entry {
+                Ok(entry) => ("timeline", serde_json::to_string(&entry)?),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            _ = keepalive.tick() => {
+                stream.write_all(b": keepalive\n\n").await?;
+                continue;
+            }
+        };
+        stream.write_all(format!("event: {}\ndata: {}\n\n", event, data).as_bytes()).await?;
+    }
+}
+
+/// A frontend file under `dir`, and its content type. Paths leaving `dir` are refused.
+async fn static_file(dir: &Path, path: &str) -> Option<(Vec<u8>, &'static str)> {
+    let relative = match path.trim_start_matches('/') {
+        "" => "index.html",
+        relative => relative,
+    };
+    if relative.split('/').any(|part| part.is_empty() || part == "." || part == ".." || part.contains('\\')) {
+        return None;
+    }
+    let bytes = tokio::fs::read(dir.join(relative)).await.ok()?;
+    let content_type = match Path::new(relative).extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript",
+        Some("css") => "text/css",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    };
+    Some((bytes, content_type))
+}
+
+/// The token of an `Authorization: Bearer` header in a request head.
+pub fn bearer_token(head: &str) -> Option<&str> {
+    head.lines()
+        .find_map(|l| l.split_once(':').filter(|(name, _)| name.eq_ignore_ascii_case("authorization")))
+        .and_then(|(_, value)| value.trim().strip_prefix("Bearer "))
+}
+
+/// Whether a presented token is `expected`, in time independent of where they differ.
+pub fn token_matches(presented: &str, expected: &str) -> bool {
+    presented.as_bytes().ct_eq(expected.as_bytes()).into()
+}
+
+pub async fn read_request_head(stream: &mut TcpStream) -> Result<String> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            anyhow::bail!("connection closed before end of headers");
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if buf.len() > MAX_REQUEST_HEAD {
+            anyhow::bail!("request head too large");
+        }
+    }
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// The body following `head` (as returned by `read_request_head`, which may hold its
+/// start), as long as the Content-Length header says.
+pub async fn read_request_body(stream: &mut TcpStream, head: &str) -> Result<Vec<u8>> {
+    let (headers, start) = head.split_once("\r\n\r\n").unwrap_or((head, ""));
+    let length: usize = headers.lines()
+        .find_map(|l| l.split_once(':').filter(|(name, _)| name.eq_ignore_ascii_case("content-length")))
+        .map(|(_, value)| value.trim().parse())
+        .transpose()?
+        .unwrap_or(0);
+    if length > MAX_REQUEST_BODY {
+        anyhow::bail!("request body too large");
+    }
+    let mut body = start.as_bytes().to_vec();
+    let received = body.len();
+    if received < length {
+        body.resize(length, 0);
+        stream.read_exact(&mut body[received..]).await?;
+    }
+    body.truncate(length);
+    Ok(body)
+}
+
+/// Returns (method, path) from the first line of an HTTP request, ignoring any query string.
+pub fn parse_request_line(head: &str) -> Option<(&str, &str)> {
+    let mut parts = head.lines().next()?.split_whitespace();
+    let method = parts.next()?;
+    let target = parts.next()?;
+    let path = target.split('?').next()?;
+    Some((method, path))
+}
+
+pub async fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: impl AsRef<[u8]>) -> Result<()> {
+    let body = body.as_ref();
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, content_type, body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::streetgrid::neighborhood_message::Payload;
+    use crate::transport::streetgrid::Heartbeat;
+
+    async fn get(addr: std::net::SocketAddr, path: &str) -> String {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(format!("GET {} HTTP/1.1\r\nHost: orchestrator\r\n\r\n", path).as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_nodes_are_queryable() {
+        let fleet = Arc::new(Mutex::new(Fleet::default()));
+        fleet.lock().unwrap().observe(&Payload::Heartbeat(Heartbeat {
+            node_id: "node_01".to_string(),
+            battery_level: 0.75,
+            ..Default::default()
+        }), 1000);
+        let api = FleetApi::bind("127.0.0.1:0", fleet, Arc::new(Mutex::new(Topology::default()))).await.unwrap();
+        let addr = api.local_addr().unwrap();
+        tokio::spawn(api.run());
+
+        let all = get(addr, "/nodes").await;
+        assert!(all.starts_with("HTTP/1.1 200"));
+        assert!(all.contains("\"node_id\":\"node_01\""));
+
+        let one = get(addr, "/nodes/node_01").await;
+        assert!(one.contains("\"battery_level\":0.75"));
+        assert!(get(addr, "/nodes/node_99").await.starts_with("HTTP/1.1 404"));
+        assert!(get(addr, "/topology").await.contains("\"isolated\":[\"node_01\"]"));
+        assert!(get(addr, "/vpp").await.contains("\"nodes\":1"));
+    }
+
+    async fn post(addr: std::net::SocketAddr, path: &str, token: &str, body: &str) -> String {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: orchestrator\r\nAuthorization: Bearer {}\r\nContent-Length: {}\r\n\r\n{}",
+            path, token, body.len(), body
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_serves_timeline_stream_and_commands() {
+        let timeline = Arc::new(Mutex::new(Timeline::default()));
+        timeline.lock().unwrap().record(1000, Some("node_01"), "alarm", "node_01: smoke".to_string());
+        let (commands, mut queue) = mpsc::channel::<RemoteCommand>(4);
+        let dashboard = Dashboard {
+            telemetry: broadcast::channel(4).0,
+            timeline: timeline.clone(),
+            commands: Some((commands, "s3cret".to_string())),
+            static_dir: None,
+        };
+        let api = FleetApi::bind("127.0.0.1:0", Arc::new(Mutex::new(Fleet::default())), Arc::new(Mutex::new(Topology::default())))
+            .await.unwrap()
+            .with_dashboard(dashboard);
+        let addr = api.local_addr().unwrap();
+        tokio::spawn(api.run());
+        // Stands in for the orchestrator loop
+        tokio::spawn(async move {
+            while let Some(command) = queue.recv().await {
+                assert_eq!(command.target, Target::Zone("block_3".to_string()));
+                let _ = command.reply.send(Ok(()));
+            }
+        });
+
+        assert!(get(addr, "/timeline").await.contains("\"message\":\"node_01: smoke\""));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /stream HTTP/1.1\r\nHost: orchestrator\r\n\r\n").await.unwrap();
+        let mut buf = vec![0; 4096];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).contains("text/event-stream"));
+
+        let command = r#"{"command": "shed @block_3"}"#;
+        assert!(post(addr, "/commands", "wrong", command).await.starts_with("HTTP/1.1 401"));
+        assert!(post(addr, "/commands", "s3cret", command).await.contains("{\"ok\":true}"));
+        let refused = post(addr, "/commands", "s3cret", r#"{"command": "nodes"}"#).await;
+        assert!(refused.starts_with("HTTP/1.1 409"));
+
+        timeline.lock().unwrap().record(1001, None, "command", "dashboard sent LoadShed to zone block_3".to_string());
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("event: timeline\n"));
+    }
+}
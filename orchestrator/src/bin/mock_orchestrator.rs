@@ -0,0 +1,52 @@
+//! Mock orchestrator for bench testing a node: answers FeatureReports and heartbeats
+//! over the broker in the config, and sends the commands of an optional script.
+
+use log::{info, warn};
+use clap::Parser;
+use streetgrid_orchestrator::config::load_config;
+use streetgrid_orchestrator::keys::MeshKey;
+use streetgrid_orchestrator::mock::{MockOrchestrator, Script};
+use streetgrid_orchestrator::transport::{MqttSettings, MqttTransport};
+use anyhow::Result;
+use std::sync::Arc;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Orchestrator configuration; only its id, mesh and mqtt sections are used
+    #[arg(short, long, default_value = "config.yaml")]
+    config: String,
+
+    /// Commands to send the nodes, and when (YAML)
+    #[arg(long)]
+    script: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+
+    let config = load_config(&args.config)?;
+    let id = config.id.clone().unwrap_or_else(|| "orchestrator".to_string());
+    let Some(mqtt) = &config.mqtt else {
+        anyhow::bail!("{} has no mqtt section: the mock orchestrator talks to nodes through a broker", args.config);
+    };
+    let mesh_key = match &config.mesh {
+        Some(mesh) => Some(MeshKey::from_hex(mesh.key_epoch.unwrap_or(1), &mesh.psk)?),
+        None => {
+            warn!("No mesh key configured: commands go out unsigned");
+            None
+        }
+    };
+    let script = match &args.script {
+        Some(path) => Script::load(path)?,
+        None => Script::default(),
+    };
+    // Its own client ID, so it can share a broker with a real orchestrator
+    let transport = Arc::new(MqttTransport::connect(&MqttSettings::from_config(mqtt, format!("streetgrid-{}-mock", id))?)?);
+
+    let mut mock = MockOrchestrator::new(&id, transport, mesh_key, script)?;
+    info!("Mock orchestrator {} serving nodes on {}", id, mqtt.host);
+    mock.run().await
+}
@@ -0,0 +1,259 @@
+use anyhow::{bail, Result};
+use log::{info, warn};
+use std::collections::{BTreeMap, VecDeque};
+use streetgrid_core::types::{Priority, RelayType};
+use crate::fleet::Fleet;
+use crate::transport::streetgrid::neighborhood_message::Payload;
+use crate::transport::streetgrid::{ActivateRelayByIndex, ActivateRelayByPriority, BlackStartAck, EnterBlackStart};
+
+/// How long a stage may go unconfirmed before the sequence is halted.
+pub const STEP_TIMEOUT_SECS: i64 = 60;
+
+/// Group for nodes that reported no zone, restarted after every zone
+const NO_ZONE: &str = "(no zone)";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    Enter,
+    /// Close one source relay
+    CloseRelay(u32),
+    RestoreCritical,
+}
+
+/// Commands sent together; the next stage waits until every one is confirmed.
+#[derive(Debug, Clone)]
+struct Stage {
+    zone: String,
+    label: &'static str,
+    actions: Vec<(String, Action)>,
+}
+
+/// Brings the fleet back one zone at a time: every node in the zone enters black
+/// start, then closes its sources, then restores its critical loads. Each stage is
+/// only sent once the previous one is confirmed by every node, and a refusal or
+/// silence halts the sequence for the operator to look at.
+#[derive(Debug, Default)]
+pub struct FleetBlackStart {
+    stages: VecDeque<Stage>,
+    /// Stage in progress, and the steps (ID -> node) it is waiting on
+    current: Option<Stage>,
+    pending: BTreeMap<String, String>,
+    /// When the stage in progress was sent
+    since: i64,
+    steps: u64,
+    halted: Option<String>,
+}
+
+impl FleetBlackStart {
+    /// Plan a black start of the nodes in `zones`, in that order; with none given,
+    /// every reported zone in name order followed by the nodes in none. Nodes in
+    /// several zones start with the first. Down nodes are left out.
+    pub fn plan(fleet: &Fleet, zones: &[String]) -> Result<Self> {
+        let reported = fleet.zones();
+        let order: Vec<String> = match zones {
+            [] => reported.keys().cloned().chain([NO_ZONE.to_string()]).collect(),
+            zones => zones.to_vec(),
+        };
+        let mut placed = Vec::new();
+        let mut stages = VecDeque::new();
+        for zone in order {
+            let members: Vec<_> = fleet.nodes()
+                .filter(|n| match zone.as_str() {
+                    NO_ZONE => n.zones.is_empty(),
+                    zone => n.zones.iter().any(|z| z == zone),
+                })
+                .filter(|n| !placed.contains(&n.node_id))
+                .filter(|n| {
+                    let down = n.liveness.is_down();
+                    if down {
+                        warn!("Leaving {} out of the black start: it is down", n.node_id);
+                    }
+                    !down
+                })
+                .collect();
+            if members.is_empty() {
+                continue;
+            }
+            placed.extend(members.iter().map(|n| n.node_id.clone()));
+            let stage = |label, actions: Vec<(String, Action)>| Stage { zone: zone.clone(), label, actions };
+            stages.push_back(stage("enter black start", members.iter().map(|n| (n.node_id.clone(), Action::Enter)).collect()));
+            let sources: Vec<_> = members.iter()
+                .flat_map(|n| {
+                    n.relays.iter()
+                        .filter(|r| r.relay_type == RelayType::Source as i32)
+                        .map(|r| (n.node_id.clone(), Action::CloseRelay(r.index)))
+                })
+                .collect();
+            if !sources.is_empty() {
+                stages.push_back(stage("close sources", sources));
+            }
+            stages.push_back(stage("restore critical loads", members.iter().map(|n| (n.node_id.clone(), Action::RestoreCritical)).collect()));
+        }
+        if stages.is_empty() {
+            bail!("no reachable nodes in {}", if zones.is_empty() { "the fleet".to_string() } else { zones.join(", ") });
+        }
+        info!("Fleet black start planned: {} nodes in {} stages", placed.len(), stages.len());
+        Ok(Self { stages, ..Default::default() })
+    }
+
+    /// Send the next stage once the current one is confirmed, or halt if it has
+    /// gone unconfirmed too long. Returns the commands to send, by node.
+    pub fn advance(&mut self, now: i64) -> Vec<(String, Payload)> {
+        if self.halted.is_some() {
+            return Vec::new();
+        }
+        if !self.pending.is_empty() {
+            if now - self.since >= STEP_TIMEOUT_SECS {
+                let silent: Vec<&str> = self.pending.values().map(String::as_str).collect();
+                self.halt(format!("no confirmation from {}", silent.join(", ")));
+            }
+            return Vec::new();
+        }
+        self.current = self.stages.pop_front();
+        let Some(stage) = &self.current else { return Vec::new() };
+        info!("Fleet black start: zone {}: {}", stage.zone, stage.label);
+        self.since = now;
+        let mut commands = Vec::new();
+        for (node, action) in &stage.actions {
+            self.steps += 1;
+            let step_id = format!("bs-{}-{}", now, self.steps);
+            let target_node_id = node.clone();
+            let payload = match action {
+                Action::Enter => Payload::EnterBlackStart(EnterBlackStart { target_node_id, step_id: step_id.clone() }),
+                Action::CloseRelay(relay_index) => Payload::ActivateRelayByIndex(ActivateRelayByIndex {
+                    target_node_id,
+                    relay_index: *relay_index,
+                    step_id: step_id.clone(),
+                }),
+                Action::RestoreCritical => Payload::ActivateRelayByPriority(ActivateRelayByPriority {
+                    target_node_id,
+                    priority: Priority::Critical as i32,
+                    step_id: step_id.clone(),
+                }),
+            };
+            self.pending.insert(step_id, node.clone());
+            commands.push((node.clone(), payload));
+        }
+        commands
+    }
+
+    /// A node's confirmation of a step; only the node the step was sent to can confirm it.
+    pub fn acknowledge(&mut self, ack: &BlackStartAck) {
+        if self.halted.is_some() {
+            return;
+        }
+        match self.pending.get(&ack.step_id) {
+            Some(node) if *node == ack.node_id => {
+                self.pending.remove(&ack.step_id);
+            }
+            Some(node) => {
+                warn!("Ignoring confirmation of {} from {}: it was sent to {}", ack.step_id, ack.node_id, node);
+                return;
+            }
+            None => return,
+        }
+        if !ack.ok {
+            self.halt(format!("{} refused: {}", ack.node_id, ack.error));
+        }
+    }
+
+    fn halt(&mut self, reason: String) {
+        let stage = self.current.as_ref().map(|s| format!("zone {} ({})", s.zone, s.label)).unwrap_or_default();
+        warn!("Fleet black start halted at {}: {}", stage, reason);
+        self.halted = Some(reason);
+    }
+
+    /// Every stage sent and confirmed.
+    pub fn is_finished(&self) -> bool {
+        self.halted.is_none() && self.pending.is_empty() && self.stages.is_empty()
+    }
+
+    /// One line for the console.
+    pub fn status(&self) -> String {
+        let at = match &self.current {
+            Some(stage) => format!("zone {} ({})", stage.zone, stage.label),
+            None => "not started".to_string(),
+        };
+        match &self.halted {
+            Some(reason) => format!("halted at {}: {}", at, reason),
+            None => format!("at {}, {} steps unconfirmed, {} stages to go", at, self.pending.len(), self.stages.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::streetgrid::{FeatureReport, RelayInfo};
+
+    fn register(fleet: &mut Fleet, node_id: &str, zone: &str) {
+        fleet.observe(&Payload::FeatureReport(FeatureReport {
+            node_id: node_id.to_string(),
+            relays: vec![
+                RelayInfo { index: 0, id: "r_solar".to_string(), relay_type: RelayType::Source as i32, ..Default::default() },
+                RelayInfo { index: 1, id: "r_fridge".to_string(), relay_type: RelayType::Load as i32, ..Default::default() },
+            ],
+            zones: vec![zone.to_string()],
+            ..Default::default()
+        }), 0);
+    }
+
+    fn confirm(blackstart: &mut FleetBlackStart, commands: &[(String, Payload)], ok: bool) {
+        for (node, payload) in commands {
+            let step_id = match payload {
+                Payload::EnterBlackStart(c) => &c.step_id,
+                Payload::ActivateRelayByIndex(c) => &c.step_id,
+                Payload::ActivateRelayByPriority(c) => &c.step_id,
+                other => panic!("unexpected {:?}", other),
+            };
+            blackstart.acknowledge(&BlackStartAck { node_id: node.clone(), step_id: step_id.clone(), ok, error: String::new() });
+        }
+    }
+
+    #[test]
+    fn test_zones_come_back_one_confirmed_stage_at_a_time() {
+        let mut fleet = Fleet::default();
+        register(&mut fleet, "node_01", "block_1");
+        register(&mut fleet, "node_02", "block_1");
+        register(&mut fleet, "node_03", "block_2");
+        let mut blackstart = FleetBlackStart::plan(&fleet, &["block_2".to_string(), "block_1".to_string()]).unwrap();
+
+        let enter = blackstart.advance(0);
+        assert_eq!(enter.len(), 1);
+        assert!(matches!(&enter[0], (node, Payload::EnterBlackStart(_)) if node == "node_03"));
+        // Nothing more until it is confirmed, by the node it was sent to
+        assert!(blackstart.advance(1).is_empty());
+        let forged = vec![("node_01".to_string(), enter[0].1.clone())];
+        confirm(&mut blackstart, &forged, true);
+        assert!(blackstart.advance(1).is_empty());
+        confirm(&mut blackstart, &enter, true);
+        let sources = blackstart.advance(2);
+        assert!(matches!(&sources[0].1, Payload::ActivateRelayByIndex(c) if c.relay_index == 0));
+        confirm(&mut blackstart, &sources, true);
+        let critical = blackstart.advance(3);
+        assert!(matches!(&critical[0].1, Payload::ActivateRelayByPriority(c) if c.priority == Priority::Critical as i32));
+        confirm(&mut blackstart, &critical, true);
+
+        // Then the next zone, both nodes at once; one refusing halts everything
+        let enter = blackstart.advance(4);
+        assert_eq!(enter.len(), 2);
+        confirm(&mut blackstart, &enter, true);
+        let sources = blackstart.advance(5);
+        confirm(&mut blackstart, &sources[..1], false);
+        assert!(blackstart.advance(6).is_empty());
+        assert!(!blackstart.is_finished());
+        assert!(blackstart.status().starts_with("halted at zone block_1 (close sources)"));
+    }
+
+    #[test]
+    fn test_silence_halts_the_sequence() {
+        let mut fleet = Fleet::default();
+        register(&mut fleet, "node_01", "block_1");
+        let mut blackstart = FleetBlackStart::plan(&fleet, &[]).unwrap();
+        blackstart.advance(0);
+        assert!(blackstart.advance(STEP_TIMEOUT_SECS - 1).is_empty());
+        blackstart.advance(STEP_TIMEOUT_SECS);
+        assert_eq!(blackstart.status(), "halted at zone block_1 (enter black start): no confirmation from node_01");
+        assert!(FleetBlackStart::plan(&fleet, &["block_9".to_string()]).is_err());
+    }
+}
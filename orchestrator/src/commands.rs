@@ -0,0 +1,233 @@
+use anyhow::{bail, Context, Result};
+use crate::transport::streetgrid::neighborhood_message::Payload;
+use crate::transport::streetgrid::{
+    ActivateRelayByIndex, ActivateRelayByPriority, AuditLogRequest, DisconnectGrid, EnergyLedgerRequest, EnterBlackStart,
+    EnterIsland, FactoryReset, FaultReset, FirmwareUpdate, LoadShed, MidCommand, SessionGrant, SessionRevoke, SnapshotRequest,
+};
+
+pub const HELP: &str = "\
+nodes                                       list known nodes
+zones                                       zones and the nodes that reported them
+whoisthere                                  ask every node to resend its FeatureReport
+node <node>                                 details of one node
+topology                                    connected groups and isolated nodes
+vpp                                         sheddable load and dispatchable storage across the fleet
+dr-events                                   utility demand-response events and the nodes' answers
+shed <node>                                 shed medium and low priority loads
+island <node>                               enter island mode
+blackstart <node>                           enter black start
+fleet-blackstart [zone ...]                 black start zone by zone (default: every zone): enter, sources, critical loads
+fleet-blackstart-status                     progress of the fleet black start
+fleet-blackstart-abort                      stop sending further stages
+activate <node> <relay_index>               close one relay
+activate-priority <node> <critical|high|medium|low>
+audit <node> [since_seq] [max]              pull audit log entries
+snapshot <node>                             pull a state snapshot
+ledger <node> [since]                       pull energy ledger entries held by a node
+settle [since] [until]                      who owes whom for energy shared while islanded
+restore <from_node> <to_node>               seed a node with the last snapshot of another
+rebalance <from_node> <relay,...> <to_node> <relay,...>
+                                            shed relays on one node, then restore relays on another
+rebalances                                  rebalances awaiting confirmation
+phases [zone]                               closed load per transformer phase
+balance-phases [zone]                       rebalance one load off the heaviest phase onto the lightest
+firmware <node> <url>                       install a signed firmware image
+grant <node> <session> <ttl_secs> <scope,...>
+revoke <node> <session>
+factory-reset <node>                        needs co-signatures under the default policy
+fault-reset <node>                          clear an emergency stop once it is released; relays stay open
+disconnect-grid <node>                      needs co-signatures on sanctioned meshes
+shed, island, blackstart, activate-priority and disconnect-grid also take @<zone> in place
+of <node>, reaching every node in the zone with one message
+mid <mid> <isolate|reconnect|permit>        switch a transformer MID; permit lets edge nodes reconnect
+takeover                                    as the standby orchestrator, tell nodes to follow us
+sim-voltage <@zone|all> <volts>             in simulation mode, set the mains voltage virtual nodes see";
+
+/// A line typed at the operator console.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// Sign and send `payload` to `target`
+    Issue { target: String, payload: Payload },
+    /// Broadcast `payload` to every node in `zone`
+    IssueZone { zone: String, payload: Payload },
+    /// Seed `target` with the snapshot last received from `from`
+    Restore { from: String, target: String },
+    ListNodes,
+    ListZones,
+    ShowNode(String),
+    ShowTopology,
+    /// Flexible capacity for demand response
+    ShowVpp,
+    /// Demand-response events from the utility
+    ShowDemandResponse,
+    /// Open `shed` on `from`, then close `restore` on `to`
+    Rebalance { from: String, shed: Vec<String>, to: String, restore: Vec<String> },
+    ShowRebalances,
+    /// Closed load per phase, across the fleet or one zone
+    ShowPhases(Option<String>),
+    /// Move one load between phases if they are out of balance
+    BalancePhases(Option<String>),
+    /// Black start the nodes in these zones in turn (every zone if empty)
+    FleetBlackStart(Vec<String>),
+    FleetBlackStartStatus,
+    FleetBlackStartAbort,
+    /// Ask every node to register again
+    WhoIsThere,
+    /// Announce that we take over from the primary orchestrator
+    Takeover,
+    /// Set the mains voltage at the virtual nodes of one zone, or all of them
+    SimVoltage { zone: Option<String>, voltage: f32 },
+    /// Reconcile energy entries whose periods ended within the window (Unix seconds)
+    Settle { since: i64, until: i64 },
+    Help,
+}
+
+pub fn parse(line: &str, now: i64) -> Result<Command> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some((&verb, args)) = words.split_first() else {
+        return Ok(Command::Help);
+    };
+    let arg = |i: usize, name: &str| -> Result<String> {
+        args.get(i).map(|s| s.to_string()).with_context(|| format!("{} needs <{}>", verb, name))
+    };
+    let node = || arg(0, "node");
+    let issue = |payload| -> Result<Command> {
+        let target = node()?;
+        if target.starts_with('@') {
+            bail!("{} can't be addressed to a zone", verb);
+        }
+        Ok(Command::Issue { target, payload })
+    };
+    // Commands every node in a zone can act on take `@<zone>` in place of the node;
+    // the payload's target is then left empty and each member fills in its own
+    let target = || node().map(|n| if n.starts_with('@') { String::new() } else { n });
+    let issue_any = |payload| -> Result<Command> {
+        match node()?.strip_prefix('@') {
+            Some(zone) => Ok(Command::IssueZone { zone: zone.to_string(), payload }),
+            None => issue(payload),
+        }
+    };
+
+    match verb {
+        "help" => Ok(Command::Help),
+        "nodes" => Ok(Command::ListNodes),
+        "zones" => Ok(Command::ListZones),
+        "node" => Ok(Command::ShowNode(node()?)),
+        "topology" => Ok(Command::ShowTopology),
+        "vpp" => Ok(Command::ShowVpp),
+        "dr-events" => Ok(Command::ShowDemandResponse),
+        "shed" => issue_any(Payload::LoadShed(LoadShed { target_node_id: target()?, shed_load: true })),
+        "island" => issue_any(Payload::EnterIsland(EnterIsland { target_node_id: target()? })),
+        "blackstart" => issue_any(Payload::EnterBlackStart(EnterBlackStart { target_node_id: target()?, ..Default::default() })),
+        "activate" => issue(Payload::ActivateRelayByIndex(ActivateRelayByIndex {
+            target_node_id: node()?,
+            relay_index: arg(1, "relay_index")?.parse().context("relay index must be a number")?,
+            ..Default::default()
+        })),
+        "activate-priority" => {
+            let priority = match arg(1, "priority")?.as_str() {
+                "critical" => 0,
+                "high" => 1,
+                "medium" => 2,
+                "low" => 3,
+                other => bail!("unknown priority {}", other),
+            };
+            issue_any(Payload::ActivateRelayByPriority(ActivateRelayByPriority { target_node_id: target()?, priority, ..Default::default() }))
+        }
+        "audit" => issue(Payload::AuditLogRequest(AuditLogRequest {
+            target_node_id: node()?,
+            since_seq: args.get(1).map(|s| s.parse()).transpose().context("since_seq must be a number")?.unwrap_or(0),
+            max_entries: args.get(2).map(|s| s.parse()).transpose().context("max must be a number")?.unwrap_or(0),
+        })),
+        "ledger" => issue(Payload::EnergyLedgerRequest(EnergyLedgerRequest {
+            target_node_id: node()?,
+            since: args.get(1).map(|s| s.parse()).transpose().context("since must be Unix seconds")?.unwrap_or(0),
+        })),
+        "settle" => Ok(Command::Settle {
+            since: args.first().map(|s| s.parse()).transpose().context("since must be Unix seconds")?.unwrap_or(0),
+            until: args.get(1).map(|s| s.parse()).transpose().context("until must be Unix seconds")?.unwrap_or(now),
+        }),
+        "snapshot" => issue(Payload::SnapshotRequest(SnapshotRequest { target_node_id: node()? })),
+        "rebalance" => Ok(Command::Rebalance {
+            from: arg(0, "from_node")?,
+            shed: arg(1, "relay,...")?.split(',').map(str::to_string).collect(),
+            to: arg(2, "to_node")?,
+            restore: arg(3, "relay,...")?.split(',').map(str::to_string).collect(),
+        }),
+        "rebalances" => Ok(Command::ShowRebalances),
+        "phases" => Ok(Command::ShowPhases(args.first().map(|s| s.to_string()))),
+        "balance-phases" => Ok(Command::BalancePhases(args.first().map(|s| s.to_string()))),
+        "takeover" => Ok(Command::Takeover),
+        "sim-voltage" => {
+            let zone = match arg(0, "@zone|all")?.as_str() {
+                "all" => None,
+                zone => Some(zone.strip_prefix('@').context("sim-voltage takes @<zone> or all")?.to_string()),
+            };
+            Ok(Command::SimVoltage { zone, voltage: arg(1, "volts")?.parse().context("volts must be a number")? })
+        }
+        "whoisthere" => Ok(Command::WhoIsThere),
+        "fleet-blackstart" => Ok(Command::FleetBlackStart(args.iter().map(|s| s.to_string()).collect())),
+        "fleet-blackstart-status" => Ok(Command::FleetBlackStartStatus),
+        "fleet-blackstart-abort" => Ok(Command::FleetBlackStartAbort),
+        "restore" => Ok(Command::Restore { from: arg(0, "from_node")?, target: arg(1, "to_node")? }),
+        "firmware" => issue(Payload::FirmwareUpdate(FirmwareUpdate { target_node_id: node()?, url: arg(1, "url")? })),
+        "grant" => {
+            let ttl: i64 = arg(2, "ttl_secs")?.parse().context("ttl must be a number of seconds")?;
+            issue(Payload::SessionGrant(SessionGrant {
+                session_id: arg(1, "session")?,
+                target_node_id: node()?,
+                scopes: arg(3, "scope,...")?.split(',').map(str::to_string).collect(),
+                expires_at: now + ttl,
+            }))
+        }
+        "revoke" => issue(Payload::SessionRevoke(SessionRevoke { session_id: arg(1, "session")?, target_node_id: node()? })),
+        "factory-reset" => issue(Payload::FactoryReset(FactoryReset { target_node_id: node()? })),
+        "fault-reset" => issue(Payload::FaultReset(FaultReset { target_node_id: node()? })),
+        "disconnect-grid" => issue_any(Payload::DisconnectGrid(DisconnectGrid { target_node_id: target()? })),
+        "mid" => {
+            let (isolate, permit_reconnect) = match arg(1, "isolate|reconnect|permit")?.as_str() {
+                "isolate" => (true, false),
+                "reconnect" => (false, false),
+                "permit" => (false, true),
+                other => bail!("unknown MID action {}", other),
+            };
+            issue(Payload::MidCommand(MidCommand { target_node_id: node()?, isolate, permit_reconnect }))
+        }
+        other => bail!("unknown command {} (try help)", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_console_lines_become_commands() {
+        assert_eq!(parse("  ", 0).unwrap(), Command::Help);
+        assert_eq!(
+            parse("activate node_01 2", 0).unwrap(),
+            Command::Issue {
+                target: "node_01".to_string(),
+                payload: Payload::ActivateRelayByIndex(ActivateRelayByIndex { target_node_id: "node_01".to_string(), relay_index: 2, ..Default::default() }),
+            }
+        );
+        let Command::Issue { payload: Payload::SessionGrant(grant), .. } = parse("grant node_01 op-1 600 load_shed,enter_island", 1000).unwrap() else {
+            panic!("expected a session grant");
+        };
+        assert_eq!(grant.expires_at, 1600);
+        assert_eq!(grant.scopes, vec!["load_shed", "enter_island"]);
+
+        assert_eq!(
+            parse("shed @block_3", 0).unwrap(),
+            Command::IssueZone {
+                zone: "block_3".to_string(),
+                payload: Payload::LoadShed(LoadShed { target_node_id: String::new(), shed_load: true }),
+            }
+        );
+        assert!(parse("activate @block_3 2", 0).is_err());
+
+        assert!(parse("activate node_01", 0).is_err());
+        assert!(parse("activate-priority node_01 urgent", 0).is_err());
+        assert!(parse("reboot node_01", 0).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use anyhow::Result;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Config {
+    /// Sender ID nodes track our command counter under
+    pub id: Option<String>,
+    pub mesh: Option<MeshConfig>,
+    /// Broker shared with the nodes; without it commands only go to the mock transport
+    pub mqtt: Option<MqttConfig>,
+    /// Where the fleet registry is kept (default fleet.json)
+    pub registry: Option<String>,
+    pub api: Option<ApiConfig>,
+    pub grpc: Option<GrpcConfig>,
+    pub dnp3: Option<Dnp3Config>,
+    pub openadr: Option<OpenAdrConfig>,
+    /// Transformer -> nodes it feeds; load is only rebalanced between nodes on the same one
+    pub transformers: Option<HashMap<String, Vec<String>>>,
+    pub liveness: Option<LivenessConfig>,
+    /// Secure element identity key of each node fitted with one (node -> P-256 x || y,
+    /// hex), recorded at commissioning; their energy ledger entries must be signed by it
+    pub device_keys: Option<HashMap<String, String>>,
+    /// Run against a street of virtual nodes instead of the mesh
+    pub simulation: Option<SimulationConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SimulationConfig {
+    pub nodes: usize,
+    /// Zones the nodes are split evenly into (default 1)
+    pub zones: Option<usize>,
+    /// Neighbours that must also see under-voltage before a virtual node islands (default 2)
+    pub quorum: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LivenessConfig {
+    /// How often the nodes heartbeat (default 60)
+    pub heartbeat_interval_secs: Option<i64>,
+    /// Heartbeats missed before a node is marked stale (default 3)
+    pub missed_heartbeats: Option<u32>,
+    /// Ask the nodes that hear a stale node to ping it before it is declared down
+    pub probe_neighbors: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiConfig {
+    /// Address to serve the fleet API on, e.g. "0.0.0.0:8080"
+    pub bind: String,
+    /// Directory of the web dashboard's frontend, served alongside the API
+    pub static_dir: Option<String>,
+    /// Bearer token the dashboard sends commands with; read-only without it
+    pub command_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GrpcConfig {
+    /// Address to serve the northbound gRPC API on, e.g. "0.0.0.0:50051"
+    pub bind: String,
+    pub tokens: Vec<GrpcToken>,
+}
+
+/// Bearer token a gRPC client presents.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GrpcToken {
+    /// Who holds it, for the log
+    pub name: String,
+    pub token: String,
+    /// May issue commands; otherwise listing and telemetry only
+    #[serde(default)]
+    pub command: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Dnp3Config {
+    /// Address to serve DNP3 over TCP on, e.g. "127.0.0.1:20000"
+    pub bind: String,
+    /// Our link address (default 10)
+    pub outstation_address: Option<u16>,
+    /// Link address of the SCADA master; frames from anyone else are ignored (default 1)
+    pub master_address: Option<u16>,
+    /// IP addresses the SCADA master connects from; without them only local masters may
+    pub allowed_masters: Option<Vec<std::net::IpAddr>>,
+    /// Nodes in point order; node k owns binary inputs 2k..2k+1, analog inputs 3k..3k+2
+    /// and controls 2k..2k+1. Fix this so the points don't move as nodes join.
+    pub nodes: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpenAdrConfig {
+    /// Address the utility posts demand-response events to, e.g. "127.0.0.1:8081"
+    pub bind: String,
+    /// Bearer token the utility presents; required unless `bind` is loopback
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MeshConfig {
+    /// Mesh pre-shared key, 32 bytes hex-encoded (same as the nodes' security.psk)
+    pub psk: String,
+    pub key_epoch: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub tls: Option<MqttTlsConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MqttTlsConfig {
+    /// CA that signed the broker certificate (PEM)
+    pub ca_cert: String,
+    /// Orchestrator client certificate and key (PEM), for brokers requiring mutual TLS
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+}
+
+pub fn load_config(path: &str) -> Result<Config> {
+    let contents = fs::read_to_string(path)?;
+    let config: Config = serde_yaml::from_str(&contents)?;
+    Ok(config)
+}
@@ -0,0 +1,658 @@
+use anyhow::Result;
+use log::{debug, info, warn};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use streetgrid_core::types::RelayType;
+use crate::fleet::Fleet;
+use crate::liveness::Liveness;
+use crate::orchestrator::RemoteCommand;
+use crate::transport::streetgrid::neighborhood_message::Payload;
+use crate::transport::streetgrid::northbound::issue_command_request::Target;
+use crate::transport::streetgrid::{EnterIsland, LoadShed};
+
+/// Outstation and master link addresses when the config doesn't set them
+pub const DEFAULT_OUTSTATION_ADDRESS: u16 = 10;
+pub const DEFAULT_MASTER_ADDRESS: u16 = 1;
+
+/// How long a SELECT stays valid for the OPERATE that follows it
+const SELECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A master silent this long is dropped, freeing the outstation for the next one
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Largest application fragment we reassemble; SCADA requests are far smaller
+const MAX_FRAGMENT: usize = 2048;
+
+// Link layer
+const START: [u8; 2] = [0x05, 0x64];
+const LINK_HEADER_LEN: usize = 10;
+const LINK_BLOCK: usize = 16;
+const LINK_DIR: u8 = 0x80;
+const LINK_PRM: u8 = 0x40;
+const LINK_RESET_LINK_STATES: u8 = 0x00;
+const LINK_CONFIRMED_USER_DATA: u8 = 0x03;
+const LINK_UNCONFIRMED_USER_DATA: u8 = 0x04;
+const LINK_REQUEST_STATUS: u8 = 0x09;
+const LINK_ACK: u8 = 0x00;
+const LINK_STATUS: u8 = 0x0B;
+/// Largest application data carried by one link frame, after the transport header
+const SEGMENT_LEN: usize = 249;
+
+// Transport layer
+const TRANSPORT_FIN: u8 = 0x80;
+const TRANSPORT_FIR: u8 = 0x40;
+
+// Application layer
+const APP_FIR: u8 = 0x80;
+const APP_FIN: u8 = 0x40;
+const FC_CONFIRM: u8 = 0x00;
+const FC_READ: u8 = 0x01;
+const FC_WRITE: u8 = 0x02;
+const FC_SELECT: u8 = 0x03;
+const FC_OPERATE: u8 = 0x04;
+const FC_DIRECT_OPERATE: u8 = 0x05;
+const FC_DIRECT_OPERATE_NR: u8 = 0x06;
+const FC_RESPONSE: u8 = 0x81;
+const IIN1_DEVICE_RESTART: u8 = 0x80;
+const IIN2_NO_FUNC_CODE_SUPPORT: u8 = 0x01;
+const IIN2_OBJECT_UNKNOWN: u8 = 0x02;
+const IIN2_PARAMETER_ERROR: u8 = 0x04;
+const QUALIFIER_RANGE_8: u8 = 0x00;
+const QUALIFIER_RANGE_16: u8 = 0x01;
+const QUALIFIER_ALL: u8 = 0x06;
+const QUALIFIER_COUNT_INDEX_8: u8 = 0x17;
+const QUALIFIER_COUNT_INDEX_16: u8 = 0x28;
+const FLAG_ONLINE: u8 = 0x01;
+const BINARY_STATE: u8 = 0x80;
+// Control relay output block status codes
+const CONTROL_SUCCESS: u8 = 0;
+const CONTROL_NO_SELECT: u8 = 2;
+const CONTROL_NOT_SUPPORTED: u8 = 4;
+const CONTROL_HARDWARE_ERROR: u8 = 6;
+/// Bytes of a g12v1 object after its index
+const CROB_LEN: usize = 11;
+
+/// CRC-16/DNP over one block.
+fn crc(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xA6BC } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct LinkFrame {
+    control: u8,
+    dest: u16,
+    src: u16,
+    data: Vec<u8>,
+}
+
+impl LinkFrame {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = START.to_vec();
+        out.push((5 + self.data.len()) as u8);
+        out.push(self.control);
+        out.extend(self.dest.to_le_bytes());
+        out.extend(self.src.to_le_bytes());
+        out.extend(crc(&out).to_le_bytes());
+        for block in self.data.chunks(LINK_BLOCK) {
+            out.extend(block);
+            out.extend(crc(block).to_le_bytes());
+        }
+        out
+    }
+
+    /// The first frame in `buf`: None until enough bytes have arrived, otherwise the
+    /// bytes consumed and the frame, or no frame if they were garbage or corrupt.
+    fn decode(buf: &[u8]) -> Option<(usize, Option<LinkFrame>)> {
+        if buf.len() < 2 {
+            return None;
+        }
+        if buf[..2] != START {
+            let skip = buf[1..].iter().position(|b| *b == START[0]).map_or(buf.len(), |p| p + 1);
+            return Some((skip, None));
+        }
+        if buf.len() < LINK_HEADER_LEN {
+            return None;
+        }
+        let Some(data_len) = (buf[2] as usize).checked_sub(5) else { return Some((2, None)) };
+        let len = LINK_HEADER_LEN + data_len + 2 * data_len.div_ceil(LINK_BLOCK);
+        if buf.len() < len {
+            return None;
+        }
+        if crc(&buf[..8]) != u16::from_le_bytes([buf[8], buf[9]]) {
+            return Some((2, None));
+        }
+        let mut data = Vec::with_capacity(data_len);
+        for block in buf[LINK_HEADER_LEN..len].chunks(LINK_BLOCK + 2) {
+            let (block, check) = block.split_at(block.len() - 2);
+            if crc(block) != u16::from_le_bytes([check[0], check[1]]) {
+                return Some((len, None));
+            }
+            data.extend(block);
+        }
+        let frame = LinkFrame {
+            control: buf[3],
+            dest: u16::from_le_bytes([buf[4], buf[5]]),
+            src: u16::from_le_bytes([buf[6], buf[7]]),
+            data,
+        };
+        Some((len, Some(frame)))
+    }
+}
+
+/// Fleet state as DNP3 points. Node `k` in the configured order has binary inputs
+/// `2k` (online) and `2k+1` (alarm raised), analog inputs `3k` (battery %), `3k+1`
+/// (last voltage) and `3k+2` (closed load, A), and control outputs `2k` (shed
+/// load) and `2k+1` (island), both operated with LATCH_ON or CLOSE.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Points {
+    binary: Vec<(bool, bool)>,
+    analog: Vec<(bool, f32)>,
+    nodes: Vec<String>,
+}
+
+impl Points {
+    pub fn from_fleet(fleet: &Fleet, nodes: &[String]) -> Self {
+        let mut points = Self { nodes: nodes.to_vec(), ..Default::default() };
+        for node_id in nodes {
+            let record = fleet.get(node_id);
+            let online = record.is_some_and(|n| n.liveness == Liveness::Online);
+            points.binary.push((online, online));
+            points.binary.push((online, record.is_some_and(|n| n.last_alarm.is_some())));
+            let battery = record.and_then(|n| n.battery_level).map(|b| b * 100.0);
+            let voltage = record.and_then(|n| n.last_voltage);
+            let load = record.map(|n| {
+                n.relays.iter().filter(|r| r.relay_type == RelayType::Load as i32 && r.is_closed).map(|r| r.amperage).sum::<f32>()
+            });
+            for value in [battery, voltage, load] {
+                points.analog.push((online && value.is_some(), value.unwrap_or_default()));
+            }
+        }
+        points
+    }
+
+    /// The command control output `index` maps to.
+    fn control(&self, index: usize) -> Option<(String, Payload)> {
+        let node_id = self.nodes.get(index / 2)?.clone();
+        let payload = match index % 2 {
+            0 => Payload::LoadShed(LoadShed { target_node_id: node_id.clone(), shed_load: true }),
+            _ => Payload::EnterIsland(EnterIsland { target_node_id: node_id.clone() }),
+        };
+        Some((node_id, payload))
+    }
+}
+
+/// What a request asks of us: the response to send (if any), and commands to issue
+/// before it goes out, each with the response byte holding its control status.
+#[derive(Debug, Default)]
+struct Reply {
+    response: Option<Vec<u8>>,
+    commands: Vec<(usize, String, Payload)>,
+}
+
+/// Application layer of the outstation, without I/O.
+#[derive(Debug)]
+struct Outstation {
+    /// Until the master clears it, we report having restarted
+    restarted: bool,
+    /// Sequence number, control objects and time of the last SELECT
+    selected: Option<(u8, Vec<u8>, Instant)>,
+}
+
+impl Outstation {
+    fn new() -> Self {
+        Self { restarted: true, selected: None }
+    }
+
+    fn respond(&mut self, request: &[u8], points: &Points, now: Instant) -> Reply {
+        let (Some(&control), Some(&function)) = (request.first(), request.get(1)) else { return Reply::default() };
+        let seq = control & 0x0F;
+        let objects = &request[2..];
+        let mut iin2 = 0;
+        let mut body = Vec::new();
+        let mut commands = Vec::new();
+        match function {
+            FC_CONFIRM => return Reply::default(),
+            FC_READ => iin2 |= read(objects, points, &mut body),
+            FC_WRITE => {
+                // Only clearing the restart indication (g80v1 index 7)
+                if objects.len() >= 6 && objects[..3] == [80, 1, QUALIFIER_RANGE_8] && objects[3] == 7 && objects[4] == 7 {
+                    self.restarted = objects[5] & 0x01 != 0;
+                } else {
+                    iin2 |= IIN2_OBJECT_UNKNOWN;
+                }
+            }
+            FC_SELECT | FC_OPERATE | FC_DIRECT_OPERATE | FC_DIRECT_OPERATE_NR => {
+                let Some(controls) = parse_controls(objects) else {
+                    return Reply { response: Some(header(seq, self.iin1(), IIN2_PARAMETER_ERROR)), commands };
+                };
+                body.extend(objects);
+                let selected = match function {
+                    FC_OPERATE => self.selected.take().is_some_and(|(select_seq, select_objects, at)| {
+                        select_seq == seq.wrapping_sub(1) & 0x0F && select_objects == objects && now.duration_since(at) < SELECT_TIMEOUT
+                    }),
+                    _ => true,
+                };
+                for (status_at, index, code) in controls {
+                    let on = matches!(code & 0x0F, 0x01 | 0x03) && code & 0xC0 != 0x80;
+                    let status = match points.control(index) {
+                        _ if !selected => CONTROL_NO_SELECT,
+                        Some((node_id, payload)) if on => {
+                            if function != FC_SELECT {
+                                commands.push((4 + status_at, node_id, payload));
+                            }
+                            CONTROL_SUCCESS
+                        }
+                        _ => CONTROL_NOT_SUPPORTED,
+                    };
+                    body[status_at] = status;
+                }
+                if function == FC_SELECT {
+                    self.selected = Some((seq, objects.to_vec(), now));
+                }
+                if function == FC_DIRECT_OPERATE_NR {
+                    return Reply { response: None, commands };
+                }
+            }
+            _ => iin2 |= IIN2_NO_FUNC_CODE_SUPPORT,
+        }
+        let mut response = header(seq, self.iin1(), iin2);
+        response.extend(body);
+        Reply { response: Some(response), commands }
+    }
+
+    fn iin1(&self) -> u8 {
+        if self.restarted { IIN1_DEVICE_RESTART } else { 0 }
+    }
+}
+
+fn header(seq: u8, iin1: u8, iin2: u8) -> Vec<u8> {
+    vec![APP_FIR | APP_FIN | seq, FC_RESPONSE, iin1, iin2]
+}
+
+/// Answer the object headers of a READ; returns IIN2 bits for what we couldn't.
+fn read(mut objects: &[u8], points: &Points, out: &mut Vec<u8>) -> u8 {
+    while objects.len() >= 3 {
+        let (group, variation, qualifier) = (objects[0], objects[1], objects[2]);
+        let (range, used) = match qualifier {
+            QUALIFIER_ALL => (None, 3),
+            QUALIFIER_RANGE_8 if objects.len() >= 5 => (Some((objects[3] as usize, objects[4] as usize)), 5),
+            QUALIFIER_RANGE_16 if objects.len() >= 7 => (
+                Some((u16::from_le_bytes([objects[3], objects[4]]) as usize, u16::from_le_bytes([objects[5], objects[6]]) as usize)),
+                7,
+            ),
+            _ => return IIN2_PARAMETER_ERROR,
+        };
+        objects = &objects[used..];
+        match (group, variation) {
+            // Class 0 is all static data; we keep no events for classes 1-3
+            (60, 1) => {
+                binary_inputs(&points.binary, None, out);
+                analog_inputs(&points.analog, None, out);
+            }
+            (60, 2..=4) => {}
+            (1, 0 | 2) => binary_inputs(&points.binary, range, out),
+            (30, 0 | 5) => analog_inputs(&points.analog, range, out),
+            _ => return IIN2_OBJECT_UNKNOWN,
+        }
+    }
+    0
+}
+
+/// Indices `range` (or all) of `count` points, if there are any.
+fn clamp(range: Option<(usize, usize)>, count: usize) -> Option<(usize, usize)> {
+    let (start, stop) = range.unwrap_or((0, count.checked_sub(1)?));
+    (start <= stop && stop < count).then_some((start, stop))
+}
+
+fn range_header(group: u8, variation: u8, (start, stop): (usize, usize), out: &mut Vec<u8>) {
+    out.extend([group, variation, QUALIFIER_RANGE_16]);
+    out.extend((start as u16).to_le_bytes());
+    out.extend((stop as u16).to_le_bytes());
+}
+
+/// g1v2: binary input with flags
+fn binary_inputs(points: &[(bool, bool)], range: Option<(usize, usize)>, out: &mut Vec<u8>) {
+    let Some((start, stop)) = clamp(range, points.len()) else { return };
+    range_header(1, 2, (start, stop), out);
+    for (online, state) in &points[start..=stop] {
+        out.push(if *online { FLAG_ONLINE } else { 0 } | if *state { BINARY_STATE } else { 0 });
+    }
+}
+
+/// g30v5: single-precision analog input with flags
+fn analog_inputs(points: &[(bool, f32)], range: Option<(usize, usize)>, out: &mut Vec<u8>) {
+    let Some((start, stop)) = clamp(range, points.len()) else { return };
+    range_header(30, 5, (start, stop), out);
+    for (online, value) in &points[start..=stop] {
+        out.push(if *online { FLAG_ONLINE } else { 0 });
+        out.extend(value.to_le_bytes());
+    }
+}
+
+/// Control relay output blocks (g12v1) in a control request: for each, the offset of
+/// its status byte within `objects`, its index and its control code.
+fn parse_controls(objects: &[u8]) -> Option<Vec<(usize, usize, u8)>> {
+    let (&[group, variation, qualifier], rest) = objects.split_first_chunk::<3>()?;
+    if (group, variation) != (12, 1) {
+        return None;
+    }
+    let (count, index_len, mut at) = match qualifier {
+        QUALIFIER_COUNT_INDEX_8 => (*rest.first()? as usize, 1, 4),
+        QUALIFIER_COUNT_INDEX_16 => (u16::from_le_bytes([*rest.first()?, *rest.get(1)?]) as usize, 2, 5),
+        _ => return None,
+    };
+    let mut controls = Vec::with_capacity(count);
+    for _ in 0..count {
+        let object = objects.get(at..at + index_len + CROB_LEN)?;
+        let index = match index_len {
+            1 => object[0] as usize,
+            _ => u16::from_le_bytes([object[0], object[1]]) as usize,
+        };
+        controls.push((at + index_len + CROB_LEN - 1, index, object[index_len]));
+        at += index_len + CROB_LEN;
+    }
+    (at == objects.len()).then_some(controls)
+}
+
+/// Application fragment being put back together from transport segments.
+#[derive(Default)]
+struct Reassembly {
+    fragment: Vec<u8>,
+    /// Grew past `MAX_FRAGMENT`; segments are ignored until the next FIR
+    overflowed: bool,
+}
+
+impl Reassembly {
+    /// Add one transport segment; the whole fragment once its FIN segment arrives.
+    fn push(&mut self, transport: u8, data: &[u8]) -> Option<&[u8]> {
+        if transport & TRANSPORT_FIR != 0 {
+            self.fragment.clear();
+            self.overflowed = false;
+        }
+        if self.overflowed {
+            return None;
+        }
+        if self.fragment.len() + data.len() > MAX_FRAGMENT {
+            warn!("DNP3: dropping application fragment over {} bytes", MAX_FRAGMENT);
+            self.fragment.clear();
+            self.overflowed = true;
+            return None;
+        }
+        self.fragment.extend_from_slice(data);
+        (transport & TRANSPORT_FIN != 0).then_some(&self.fragment)
+    }
+}
+
+/// DNP3 outstation over TCP, so the street shows up in the utility's SCADA as one
+/// device: node state as binary and analog inputs, shed and island as controls.
+pub struct Dnp3Outstation {
+    listener: TcpListener,
+    address: u16,
+    master: u16,
+    /// IP addresses masters may connect from; loopback only when empty
+    allowed_masters: Vec<IpAddr>,
+    nodes: Vec<String>,
+    fleet: Arc<Mutex<Fleet>>,
+    commands: mpsc::Sender<RemoteCommand>,
+}
+
+impl Dnp3Outstation {
+    /// `nodes` fixes the point map; without it the nodes registered now are used,
+    /// in ID order, and the map shifts when the fleet changes. Connections from outside
+    /// `allowed_masters` are closed unread.
+    pub async fn bind(
+        addr: &str,
+        address: u16,
+        master: u16,
+        allowed_masters: Vec<IpAddr>,
+        nodes: Option<Vec<String>>,
+        fleet: Arc<Mutex<Fleet>>,
+        commands: mpsc::Sender<RemoteCommand>,
+    ) -> Result<Self> {
+        let nodes = nodes.unwrap_or_else(|| {
+            warn!("dnp3.nodes not set: point indices follow the current fleet and will move as nodes join");
+            fleet.lock().unwrap().nodes().map(|n| n.node_id.clone()).collect()
+        });
+        let outstation = Self { listener: TcpListener::bind(addr).await?, address, master, allowed_masters, nodes, fleet, commands };
+        info!("DNP3 outstation {} listening on {} ({} nodes mapped)", address, outstation.local_addr()?, outstation.nodes.len());
+        if outstation.allowed_masters.is_empty() {
+            warn!("dnp3.allowed_masters not set: only masters on this host can connect");
+        }
+        Ok(outstation)
+    }
+
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serve masters one at a time, as SCADA links usually are.
+    pub async fn run(self) {
+        let mut outstation = Outstation::new();
+        loop {
+            match self.listener.accept().await {
+                Ok((_, peer)) if !self.admits(peer.ip()) => warn!("DNP3: refusing connection from {}, not an allowed master", peer),
+                Ok((stream, peer)) => {
+                    info!("DNP3 master connected from {}", peer);
+                    if let Err(e) = self.session(stream, &mut outstation).await {
+                        debug!("DNP3 session with {} ended: {}", peer, e);
+                    }
+                }
+                Err(e) => warn!("DNP3 accept failed: {}", e),
+            }
+        }
+    }
+
+    fn admits(&self, peer: IpAddr) -> bool {
+        if self.allowed_masters.is_empty() {
+            peer.is_loopback()
+        } else {
+            self.allowed_masters.contains(&peer)
+        }
+    }
+
+    async fn session(&self, mut stream: TcpStream, outstation: &mut Outstation) -> Result<()> {
+        let mut buf = Vec::new();
+        let mut reassembly = Reassembly::default();
+        let mut chunk = [0u8; 1024];
+        let mut response_seq = 0u8;
+        loop {
+            let n = tokio::time::timeout(IDLE_TIMEOUT, stream.read(&mut chunk)).await
+                .map_err(|_| anyhow::anyhow!("idle for {:?}", IDLE_TIMEOUT))??;
+            if n == 0 {
+                return Ok(());
+            }
+            buf.extend_from_slice(&chunk[..n]);
+            while let Some((used, frame)) = LinkFrame::decode(&buf) {
+                buf.drain(..used);
+                let Some(frame) = frame else {
+                    warn!("DNP3: dropping corrupt link frame");
+                    continue;
+                };
+                // Only primary frames from our master, addressed to us
+                if frame.dest != self.address || frame.src != self.master || frame.control & (LINK_DIR | LINK_PRM) != LINK_DIR | LINK_PRM {
+                    continue;
+                }
+                let reply = |control| LinkFrame { control, dest: self.master, src: self.address, data: Vec::new() };
+                match frame.control & 0x0F {
+                    LINK_RESET_LINK_STATES => stream.write_all(&reply(LINK_ACK).encode()).await?,
+                    LINK_REQUEST_STATUS => stream.write_all(&reply(LINK_STATUS).encode()).await?,
+                    function @ (LINK_CONFIRMED_USER_DATA | LINK_UNCONFIRMED_USER_DATA) => {
+                        if function == LINK_CONFIRMED_USER_DATA {
+                            stream.write_all(&reply(LINK_ACK).encode()).await?;
+                        }
+                        let Some((&transport, data)) = frame.data.split_first() else { continue };
+                        let Some(fragment) = reassembly.push(transport, data) else { continue };
+                        let points = Points::from_fleet(&self.fleet.lock().unwrap(), &self.nodes);
+                        let reply = outstation.respond(fragment, &points, Instant::now());
+                        let Some(mut response) = reply.response else {
+                            self.issue(reply.commands).await;
+                            continue;
+                        };
+                        for (status_at, status) in self.issue(reply.commands).await {
+                            response[status_at] = status;
+                        }
+                        for frame in self.segments(&response, &mut response_seq) {
+                            stream.write_all(&frame.encode()).await?;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Send each command through the orchestrator; the status byte of any that failed.
+    async fn issue(&self, commands: Vec<(usize, String, Payload)>) -> Vec<(usize, u8)> {
+        let mut failed = Vec::new();
+        for (status_at, node_id, payload) in commands {
+            info!("DNP3 master operating {:?} on {}", payload, node_id);
+            let (reply, result) = oneshot::channel();
+            let command = RemoteCommand { target: Target::NodeId(node_id), payload, client: "DNP3 master".to_string(), reply };
+            let ok = self.commands.send(command).await.is_ok() && matches!(result.await, Ok(Ok(())));
+            if !ok {
+                failed.push((status_at, CONTROL_HARDWARE_ERROR));
+            }
+        }
+        failed
+    }
+
+    /// Split an application fragment into transport segments, one link frame each.
+    fn segments(&self, fragment: &[u8], seq: &mut u8) -> Vec<LinkFrame> {
+        let chunks: Vec<&[u8]> = fragment.chunks(SEGMENT_LEN).collect();
+        let last = chunks.len() - 1;
+        chunks.into_iter().enumerate().map(|(i, chunk)| {
+            let mut transport = *seq & 0x3F;
+            *seq = seq.wrapping_add(1);
+            if i == 0 {
+                transport |= TRANSPORT_FIR;
+            }
+            if i == last {
+                transport |= TRANSPORT_FIN;
+            }
+            let mut data = vec![transport];
+            data.extend(chunk);
+            LinkFrame { control: LINK_PRM | LINK_UNCONFIRMED_USER_DATA, dest: self.master, src: self.address, data }
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::streetgrid::{FeatureReport, Heartbeat, RelayInfo};
+
+    fn fleet() -> Fleet {
+        let mut fleet = Fleet::default();
+        fleet.observe(&Payload::Heartbeat(Heartbeat { node_id: "node_01".to_string(), battery_level: 0.5, ..Default::default() }), 0);
+        fleet.observe(&Payload::FeatureReport(FeatureReport {
+            node_id: "node_01".to_string(),
+            relays: vec![RelayInfo { relay_type: RelayType::Load as i32, amperage: 12.5, is_closed: true, ..Default::default() }],
+            ..Default::default()
+        }), 0);
+        fleet
+    }
+
+    fn crob(index: u16, code: u8) -> Vec<u8> {
+        let mut objects = vec![12, 1, QUALIFIER_COUNT_INDEX_16, 1, 0];
+        objects.extend(index.to_le_bytes());
+        objects.extend([code, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        objects
+    }
+
+    #[test]
+    fn test_link_frames_round_trip_with_crcs() {
+        assert_eq!(crc(b"123456789"), 0xEA82);
+        let frame = LinkFrame { control: LINK_DIR | LINK_PRM | LINK_UNCONFIRMED_USER_DATA, dest: 10, src: 1, data: (0..40).collect() };
+        let mut bytes = frame.encode();
+        assert_eq!(bytes.len(), LINK_HEADER_LEN + 40 + 2 * 3);
+        assert_eq!(LinkFrame::decode(&bytes[..20]), None);
+        assert_eq!(LinkFrame::decode(&bytes), Some((bytes.len(), Some(frame))));
+        bytes[15] ^= 0xFF;
+        assert_eq!(LinkFrame::decode(&bytes), Some((bytes.len(), None)));
+    }
+
+    #[test]
+    fn test_class_0_poll_reports_node_points() {
+        let points = Points::from_fleet(&fleet(), &["node_01".to_string(), "node_99".to_string()]);
+        let mut outstation = Outstation::new();
+        let reply = outstation.respond(&[APP_FIR | APP_FIN | 3, FC_READ, 60, 1, QUALIFIER_ALL], &points, Instant::now());
+        let response = reply.response.unwrap();
+        assert_eq!(response[..4], [APP_FIR | APP_FIN | 3, FC_RESPONSE, IIN1_DEVICE_RESTART, 0]);
+        // Binary inputs 0..=3: node_01 online without alarm, node_99 never seen
+        assert_eq!(response[4..13], [1, 2, QUALIFIER_RANGE_16, 0, 0, 3, 0, FLAG_ONLINE | BINARY_STATE, FLAG_ONLINE]);
+        assert_eq!(response[13..15], [0, 0]);
+        // Analog inputs 0..=5: battery 50 %, no voltage reported yet, 12.5 A of load
+        assert_eq!(response[15..22], [30, 5, QUALIFIER_RANGE_16, 0, 0, 5, 0]);
+        assert_eq!(response[22..27], [FLAG_ONLINE, 0, 0, 0x48, 0x42]);
+        assert_eq!(response[27], 0);
+        assert_eq!(response[32..37], [FLAG_ONLINE, 0, 0, 0x48, 0x41]);
+
+        // Clearing the restart bit
+        outstation.respond(&[APP_FIR | APP_FIN | 4, FC_WRITE, 80, 1, QUALIFIER_RANGE_8, 7, 7, 0], &points, Instant::now());
+        let reply = outstation.respond(&[APP_FIR | APP_FIN | 5, FC_READ, 60, 2, QUALIFIER_ALL], &points, Instant::now());
+        assert_eq!(reply.response.unwrap(), [APP_FIR | APP_FIN | 5, FC_RESPONSE, 0, 0]);
+    }
+
+    #[test]
+    fn test_oversized_fragments_are_dropped_whole() {
+        let mut reassembly = Reassembly::default();
+        let segment = [0u8; SEGMENT_LEN];
+        assert_eq!(reassembly.push(TRANSPORT_FIR | TRANSPORT_FIN, &[1, 2]), Some(&[1, 2][..]));
+
+        assert_eq!(reassembly.push(TRANSPORT_FIR, &segment), None);
+        for _ in 0..MAX_FRAGMENT / SEGMENT_LEN {
+            assert_eq!(reassembly.push(0, &segment), None);
+        }
+        // The tail of the dropped fragment isn't taken for a request
+        assert_eq!(reassembly.push(TRANSPORT_FIN, &[3]), None);
+        assert!(reassembly.fragment.is_empty());
+
+        assert_eq!(reassembly.push(TRANSPORT_FIR | TRANSPORT_FIN, &[4]), Some(&[4][..]));
+    }
+
+    #[test]
+    fn test_controls_need_a_matching_select() {
+        let points = Points::from_fleet(&fleet(), &["node_01".to_string()]);
+        let mut outstation = Outstation::new();
+        let now = Instant::now();
+        let request = |seq: u8, function: u8, objects: &[u8]| {
+            let mut request = vec![APP_FIR | APP_FIN | seq, function];
+            request.extend(objects);
+            request
+        };
+        let island = crob(1, 0x03);
+
+        // Operate without select
+        let reply = outstation.respond(&request(1, FC_OPERATE, &island), &points, now);
+        assert_eq!(*reply.response.unwrap().last().unwrap(), CONTROL_NO_SELECT);
+        assert!(reply.commands.is_empty());
+
+        // Select, then operate with the next sequence number
+        let reply = outstation.respond(&request(2, FC_SELECT, &island), &points, now);
+        assert_eq!(*reply.response.unwrap().last().unwrap(), CONTROL_SUCCESS);
+        assert!(reply.commands.is_empty());
+        let reply = outstation.respond(&request(3, FC_OPERATE, &island), &points, now);
+        let response = reply.response.unwrap();
+        assert_eq!(reply.commands.len(), 1);
+        let (status_at, node_id, payload) = &reply.commands[0];
+        assert_eq!((*status_at, node_id.as_str()), (response.len() - 1, "node_01"));
+        assert!(matches!(payload, Payload::EnterIsland(_)));
+
+        // Latch off and unmapped points aren't supported
+        let reply = outstation.respond(&request(4, FC_DIRECT_OPERATE, &crob(0, 0x04)), &points, now);
+        assert_eq!(*reply.response.unwrap().last().unwrap(), CONTROL_NOT_SUPPORTED);
+        let reply = outstation.respond(&request(5, FC_DIRECT_OPERATE, &crob(9, 0x03)), &points, now);
+        assert_eq!(*reply.response.unwrap().last().unwrap(), CONTROL_NOT_SUPPORTED);
+    }
+}
@@ -0,0 +1,563 @@
+use anyhow::{Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use crate::liveness::Liveness;
+use crate::transport::streetgrid::neighborhood_message::Payload;
+use crate::transport::streetgrid::RelayInfo;
+
+/// One relay from the node's FeatureReport.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RelayRecord {
+    pub index: u32,
+    pub id: String,
+    pub name: String,
+    /// 0=Source, 1=Load, 2=Grid
+    pub relay_type: i32,
+    /// 0=Critical, 1=High, 2=Medium, 3=Low
+    pub priority: i32,
+    pub amperage: f32,
+    pub is_closed: bool,
+    /// 0 = household only, up to 3 = life support the street keeps powered
+    #[serde(default)]
+    pub community_criticality: u32,
+    /// Transformer phase the circuit is on, if the node knows it
+    pub phase: Option<String>,
+}
+
+impl From<&RelayInfo> for RelayRecord {
+    fn from(info: &RelayInfo) -> Self {
+        Self {
+            index: info.index,
+            id: info.id.clone(),
+            name: info.name.clone(),
+            relay_type: info.relay_type,
+            priority: info.priority,
+            amperage: info.amperage,
+            is_closed: info.is_closed,
+            community_criticality: info.community_criticality,
+            phase: Some(info.phase.clone()).filter(|p| !p.is_empty()),
+        }
+    }
+}
+
+/// Link counters from the node's last heartbeat.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LinkQuality {
+    /// dBm of the last packet the node received; None if unknown
+    pub rssi: Option<i32>,
+    pub snr: f32,
+    pub tx_packets: u32,
+    pub rx_packets: u32,
+    pub retransmissions: u32,
+    /// Average round trip of the node's messages to us (registrations), in ms
+    #[serde(default)]
+    pub rtt_ms: Option<u32>,
+}
+
+/// What the orchestrator last heard from one node.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NodeRecord {
+    pub node_id: String,
+    /// Unix seconds of the last message of any kind
+    pub last_seen: i64,
+    /// Unix seconds of the last heartbeat
+    pub last_heartbeat: Option<i64>,
+    pub battery_level: Option<f32>,
+    pub mesh_type: Option<String>,
+    pub hardware_id: Option<String>,
+    pub firmware_version: Option<String>,
+    /// Orchestrator the node follows, if it has a standby configured
+    pub orchestrator: Option<String>,
+    pub relays: Vec<RelayRecord>,
+    /// Groups the node reported belonging to, for zone-wide commands
+    #[serde(default)]
+    pub zones: Vec<String>,
+    /// Transformer phase the household is on
+    pub phase: Option<String>,
+    /// Per-phase readings of a three-phase node, from its last heartbeat
+    #[serde(default)]
+    pub phases: Vec<PhaseRecord>,
+    /// Power quality per ADC channel, from the last heartbeat of a node sampling voltage
+    #[serde(default)]
+    pub power: Vec<ChannelPowerRecord>,
+    /// Harmonic distortion of the mains voltage at the node, where it samples it
+    #[serde(default)]
+    pub voltage_thd: Option<f32>,
+    /// Board temperature and relay derating, where the node measures it
+    #[serde(default)]
+    pub thermal: Option<ThermalRecord>,
+    /// Battery reserve tier the node is in while islanded
+    #[serde(default)]
+    pub reserve_tier: Option<String>,
+    /// Household circuits the node sub-meters, from its last heartbeat
+    #[serde(default)]
+    pub circuits: Vec<CircuitRecord>,
+    pub link: Option<LinkQuality>,
+    /// Mesh key epoch reported in the last heartbeat
+    pub key_epoch: u32,
+    pub last_voltage: Option<f32>,
+    /// Code of the most recent alarm, tamper or anomaly alert
+    pub last_alarm: Option<String>,
+    /// Set for transformer isolation devices, from their last MidStatus
+    pub mid: Option<MidRecord>,
+    /// Set for dedicated repeaters, from their last RepeaterReport
+    pub repeater: Option<RepeaterRecord>,
+    /// Set for nodes with a battery or solar, from their last SourceCapacity
+    pub source: Option<SourceRecord>,
+    /// Voltage sags the node has reported
+    #[serde(default)]
+    pub sags: SagStats,
+    /// Relays the node has taken out of use, and how each failed ("welded" or "failed_open")
+    #[serde(default)]
+    pub failed_relays: BTreeMap<String, String>,
+    /// Whether the node is keeping up its heartbeats
+    #[serde(default)]
+    pub liveness: Liveness,
+    /// Interruptions the node reported for each month ("YYYY-MM")
+    #[serde(default)]
+    pub outages: BTreeMap<String, OutageRecord>,
+}
+
+/// What one phase of a three-phase node last measured.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhaseRecord {
+    pub phase: String,
+    pub volts: f32,
+    pub amps: f32,
+}
+
+/// Real, reactive and apparent power a node last measured on one ADC channel.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChannelPowerRecord {
+    pub channel: u32,
+    pub real_watts: f32,
+    pub reactive_var: f32,
+    pub apparent_va: f32,
+    pub power_factor: f32,
+}
+
+/// Power through a household circuit a node sub-meters, and the energy each way through it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CircuitRecord {
+    pub circuit: String,
+    pub watts: f32,
+    pub imported_wh: f64,
+    pub exported_wh: f64,
+}
+
+/// A node's board temperature, and the current its relays are derated to while it runs hot.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ThermalRecord {
+    pub board_temp_c: f32,
+    pub derated_amps: Option<f32>,
+}
+
+/// Isolation state a MID last reported.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MidRecord {
+    pub isolated: bool,
+    pub reconnect_permitted: bool,
+}
+
+/// Forwarding counters a repeater last reported, since its boot.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RepeaterRecord {
+    pub forwarded: u32,
+    pub duplicates: u32,
+    pub rejected: u32,
+}
+
+/// Spare supply and stored energy a node with sources last advertised.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceRecord {
+    pub export_watts: f32,
+    pub soc: f32,
+    pub battery_capacity_wh: f32,
+    pub stored_wh: f32,
+    pub solar_forecast_wh: f32,
+}
+
+/// Power-quality statistics from the voltage sags a node reported.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SagStats {
+    pub count: u32,
+    /// Sags that outlasted the node's ride-through window
+    pub escalated: u32,
+    pub lowest_voltage: Option<f32>,
+    pub longest_secs: f32,
+    /// Unix seconds the last one started
+    pub last_start: Option<i64>,
+}
+
+/// A node's interruptions over one month: grid losses, islands, and load relays opened.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OutageRecord {
+    pub grid_losses: u32,
+    pub grid_loss_secs: u64,
+    pub islands: u32,
+    pub island_secs: u64,
+    /// Summed over the node's load relays
+    pub load_interruptions: u32,
+    pub load_interrupted_secs: u64,
+}
+
+/// Registry of every node that has reported in, keyed by node ID. Persisted as JSON
+/// so the picture of the street survives an orchestrator restart.
+#[derive(Debug, Default)]
+pub struct Fleet {
+    nodes: BTreeMap<String, NodeRecord>,
+    /// Where the registry is saved; None keeps it in memory only
+    path: Option<PathBuf>,
+    dirty: bool,
+}
+
+/// Node that sent an uplink message, for the payloads that carry one.
+pub fn reporting_node(payload: &Payload) -> Option<&str> {
+    let node_id = match payload {
+        Payload::Heartbeat(m) => &m.node_id,
+        Payload::FeatureReport(m) => &m.node_id,
+        Payload::VoltageAlert(m) => &m.node_id,
+        Payload::AuditLogUpload(m) => &m.node_id,
+        Payload::SnapshotData(m) => &m.node_id,
+        Payload::AnomalyAlert(m) => &m.node_id,
+        Payload::LoadProfileReport(m) => &m.node_id,
+        Payload::KeyRotationAck(m) => &m.node_id,
+        Payload::JoinRequest(m) => &m.node_id,
+        Payload::Alarm(m) => &m.node_id,
+        Payload::FirmwareStatus(m) => &m.node_id,
+        Payload::TamperAlert(m) => &m.node_id,
+        Payload::ReplayDesync(m) => &m.node_id,
+        Payload::SecurityReport(m) => &m.node_id,
+        Payload::NeighborReport(m) => &m.node_id,
+        Payload::VoltageObservation(m) => &m.node_id,
+        Payload::Coordination(m) => &m.node_id,
+        Payload::PowerOffer(m) => &m.node_id,
+        Payload::SourceCapacity(m) => &m.node_id,
+        Payload::PowerRequest(m) => &m.node_id,
+        Payload::PowerGrant(m) => &m.coordinator_id,
+        Payload::EnergyEntry(m) => &m.node_id,
+        Payload::EnergyLedgerUpload(m) => &m.node_id,
+        Payload::RebalanceAck(m) => &m.node_id,
+        Payload::BlackStartAck(m) => &m.node_id,
+        Payload::DemandResponseAck(m) => &m.node_id,
+        Payload::VoltageSag(m) => &m.node_id,
+        Payload::FailedRelay(m) => &m.node_id,
+        Payload::OutageReport(m) => &m.node_id,
+        Payload::MidStatus(m) => &m.mid_id,
+        Payload::LivenessPing(m) => &m.node_id,
+        Payload::LivenessReport(m) => &m.node_id,
+        Payload::RepeaterReport(m) => &m.node_id,
+        Payload::CommandResult(m) => &m.node_id,
+        _ => return None,
+    };
+    Some(node_id)
+}
+
+impl Fleet {
+    /// Load the registry saved at `path`, or start an empty one there.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let nodes = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice::<Vec<NodeRecord>>(&data)
+                .with_context(|| format!("parsing {}", path.display()))?
+                .into_iter()
+                .map(|n| (n.node_id.clone(), n))
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+        info!("Fleet registry {} holds {} nodes", path.display(), nodes.len());
+        Ok(Self { nodes, path: Some(path), dirty: false })
+    }
+
+    /// Write the registry if it changed since the last save. Written to a temporary
+    /// file first so a crash never leaves a truncated registry behind.
+    pub fn save_if_dirty(&mut self) -> Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        if !self.dirty {
+            return Ok(());
+        }
+        let tmp = path.with_extension("tmp");
+        let nodes: Vec<&NodeRecord> = self.nodes.values().collect();
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&nodes)?)?;
+        std::fs::rename(&tmp, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Fold an authenticated uplink message into the node's record.
+    pub fn observe(&mut self, payload: &Payload, now: i64) {
+        let Some(node_id) = reporting_node(payload) else { return };
+        // Join requests come from nodes that aren't part of the mesh yet
+        if matches!(payload, Payload::JoinRequest(_)) {
+            return;
+        }
+        let record = self.nodes.entry(node_id.to_string()).or_insert_with(|| NodeRecord {
+            node_id: node_id.to_string(),
+            ..Default::default()
+        });
+        record.last_seen = now;
+        match payload {
+            Payload::Heartbeat(hb) => {
+                record.last_heartbeat = Some(now);
+                if hb.shutting_down {
+                    record.liveness = Liveness::ShutDown { since: now };
+                }
+                record.battery_level = Some(hb.battery_level);
+                record.key_epoch = hb.key_epoch;
+                record.orchestrator = Some(hb.orchestrator_id.clone()).filter(|o| !o.is_empty());
+                record.phases = hb.phases.iter()
+                    .map(|p| PhaseRecord { phase: p.phase.clone(), volts: p.volts, amps: p.amps })
+                    .collect();
+                record.power = hb.power.iter()
+                    .map(|c| ChannelPowerRecord {
+                        channel: c.channel,
+                        real_watts: c.real_watts,
+                        reactive_var: c.reactive_var,
+                        apparent_va: c.apparent_va,
+                        power_factor: c.power_factor,
+                    })
+                    .collect();
+                record.voltage_thd = Some(hb.voltage_thd).filter(|thd| *thd > 0.0);
+                record.thermal = hb.thermal.as_ref().map(|t| ThermalRecord {
+                    board_temp_c: t.board_temp_c,
+                    derated_amps: Some(t.derated_amps).filter(|amps| *amps > 0.0),
+                });
+                record.reserve_tier = Some(hb.reserve_tier.clone()).filter(|tier| !tier.is_empty());
+                record.circuits = hb.circuits.iter()
+                    .map(|c| CircuitRecord {
+                        circuit: c.circuit.clone(),
+                        watts: c.watts,
+                        imported_wh: c.imported_wh,
+                        exported_wh: c.exported_wh,
+                    })
+                    .collect();
+                if !hb.firmware_version.is_empty() {
+                    record.firmware_version = Some(hb.firmware_version.clone());
+                }
+                record.link = hb.link.as_ref().map(|link| LinkQuality {
+                    rssi: Some(link.last_rssi).filter(|&r| r != 0),
+                    snr: link.last_snr,
+                    tx_packets: link.tx_packets,
+                    rx_packets: link.rx_packets,
+                    retransmissions: link.retransmissions,
+                    rtt_ms: link.deliveries.iter()
+                        .find(|d| d.destination == "orchestrator" && d.delivered > 0)
+                        .map(|d| d.rtt_avg_ms),
+                });
+            }
+            Payload::FeatureReport(report) => {
+                record.relays = report.relays.iter().map(RelayRecord::from).collect();
+                record.mesh_type = Some(report.mesh_type.clone());
+                record.hardware_id = Some(report.hardware_id.clone()).filter(|h| !h.is_empty());
+                record.zones = report.zones.clone();
+                record.phase = Some(report.phase.clone()).filter(|p| !p.is_empty());
+                // An operator closing a failed relay puts it back in use
+                record.failed_relays.retain(|id, _| !report.relays.iter().any(|r| r.id == *id && r.is_closed));
+            }
+            Payload::FirmwareStatus(status) if status.installed => record.firmware_version = Some(status.version.clone()),
+            Payload::VoltageAlert(alert) => record.last_voltage = Some(alert.voltage),
+            Payload::VoltageObservation(obs) => record.last_voltage = Some(obs.voltage),
+            Payload::VoltageSag(sag) => {
+                let stats = &mut record.sags;
+                stats.count += 1;
+                stats.escalated += sag.escalated as u32;
+                stats.lowest_voltage = Some(stats.lowest_voltage.map_or(sag.min_voltage, |v| v.min(sag.min_voltage)));
+                stats.longest_secs = stats.longest_secs.max(sag.duration_secs);
+                stats.last_start = Some(sag.start);
+            }
+            Payload::Alarm(alarm) => record.last_alarm = Some(alarm.code.clone()),
+            Payload::TamperAlert(alert) if alert.opened => record.last_alarm = Some("tamper".to_string()),
+            Payload::AnomalyAlert(_) => record.last_alarm = Some("anomaly".to_string()),
+            Payload::FailedRelay(failed) => {
+                record.failed_relays.insert(failed.relay_id.clone(), failed.failure.clone());
+                record.last_alarm = Some("failed_relay".to_string());
+            }
+            Payload::OutageReport(report) => {
+                let (grid_losses, islands) = (report.grid_losses.clone().unwrap_or_default(), report.islands.clone().unwrap_or_default());
+                let loads = report.relays.iter().filter_map(|r| r.interruptions.as_ref());
+                record.outages.insert(report.month.clone(), OutageRecord {
+                    grid_losses: grid_losses.count,
+                    grid_loss_secs: grid_losses.secs,
+                    islands: islands.count,
+                    island_secs: islands.secs,
+                    load_interruptions: loads.clone().map(|i| i.count).sum(),
+                    load_interrupted_secs: loads.map(|i| i.secs).sum(),
+                });
+            }
+            Payload::MidStatus(status) => record.mid = Some(MidRecord {
+                isolated: status.isolated,
+                reconnect_permitted: status.reconnect_permitted,
+            }),
+            Payload::RepeaterReport(report) => record.repeater = Some(RepeaterRecord {
+                forwarded: report.forwarded,
+                duplicates: report.duplicates,
+                rejected: report.rejected,
+            }),
+            Payload::SourceCapacity(capacity) => record.source = Some(SourceRecord {
+                export_watts: capacity.export_watts,
+                soc: capacity.soc,
+                battery_capacity_wh: capacity.battery_capacity_wh,
+                stored_wh: capacity.stored_wh,
+                solar_forecast_wh: capacity.solar_forecast_wh,
+            }),
+            // Answers to commands say where the relays stand, without waiting for a re-registration
+            Payload::CommandResult(result) => {
+                for state in &result.relays {
+                    if let Some(relay) = record.relays.iter_mut().find(|r| r.id == state.id) {
+                        relay.is_closed = state.is_closed;
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.dirty = true;
+    }
+
+    pub fn set_liveness(&mut self, node_id: &str, liveness: Liveness) {
+        if let Some(record) = self.nodes.get_mut(node_id) {
+            record.liveness = liveness;
+            self.dirty = true;
+        }
+    }
+
+    pub fn get(&self, node_id: &str) -> Option<&NodeRecord> {
+        self.nodes.get(node_id)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &NodeRecord> {
+        self.nodes.values()
+    }
+
+    /// Zone -> nodes that reported belonging to it.
+    pub fn zones(&self) -> BTreeMap<String, Vec<String>> {
+        let mut zones: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for node in self.nodes.values() {
+            for zone in &node.zones {
+                zones.entry(zone.clone()).or_default().push(node.node_id.clone());
+            }
+        }
+        zones
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use streetgrid_core::types::RelayType;
+    use crate::transport::streetgrid::{CommandResult, FeatureReport, Heartbeat, RelayState, JoinRequest, LinkMetrics, DeliveryMetrics, ChannelPower, CircuitMeasurement, FailedRelay, Interruptions, OutageReport, PhaseMeasurement, RelayOutage, ThermalStatus, VoltageSag};
+
+    #[test]
+    fn test_reports_build_node_records() {
+        let mut fleet = Fleet::default();
+        fleet.observe(&Payload::FeatureReport(FeatureReport {
+            node_id: "node_01".to_string(),
+            relays: vec![RelayInfo { id: "r_grid".to_string(), relay_type: RelayType::Grid as i32, is_closed: true, ..Default::default() }],
+            mesh_type: "AdHoc".to_string(),
+            hardware_id: String::new(),
+            zones: vec!["block_3".to_string()],
+            phase: "B".to_string(),
+        }), 100);
+        fleet.observe(&Payload::Heartbeat(Heartbeat {
+            node_id: "node_01".to_string(),
+            battery_level: 0.5,
+            key_epoch: 3,
+            firmware_version: "0.2.0".to_string(),
+            link: Some(LinkMetrics {
+                last_rssi: -97,
+                tx_packets: 12,
+                deliveries: vec![DeliveryMetrics { destination: "orchestrator".to_string(), sent: 2, delivered: 1, rtt_avg_ms: 350, rtt_max_ms: 350 }],
+                ..Default::default()
+            }),
+            phases: vec![PhaseMeasurement { phase: "B".to_string(), volts: 229.5, amps: 41.0 }],
+            power: vec![ChannelPower { channel: 0, real_watts: 1150.0, reactive_var: 1992.0, apparent_va: 2300.0, power_factor: 0.5 }],
+            voltage_thd: 0.045,
+            thermal: Some(ThermalStatus { board_temp_c: 68.0, derated_amps: 48.0 }),
+            reserve_tier: "critical_only".to_string(),
+            circuits: vec![CircuitMeasurement { circuit: "kitchen".to_string(), watts: 1224.0, imported_wh: 8532.5, exported_wh: 0.0 }],
+            ..Default::default()
+        }), 160);
+        fleet.observe(&Payload::JoinRequest(JoinRequest { node_id: "stranger".to_string(), ..Default::default() }), 170);
+        fleet.observe(&Payload::CommandResult(CommandResult {
+            node_id: "node_01".to_string(),
+            command: "disconnect_grid".to_string(),
+            ok: true,
+            relays: vec![RelayState { id: "r_grid".to_string(), is_closed: false }],
+            ..Default::default()
+        }), 150);
+        let sag = |start: i64, duration_secs: f32, min_voltage: f32, escalated: bool| {
+            Payload::VoltageSag(VoltageSag { node_id: "node_01".to_string(), start, duration_secs, min_voltage, escalated })
+        };
+        fleet.observe(&sag(130, 2.5, 96.0, false), 135);
+        fleet.observe(&sag(140, 15.0, 101.0, true), 160);
+        fleet.observe(&Payload::FailedRelay(FailedRelay {
+            node_id: "node_01".to_string(),
+            relay_id: "r_hvac".to_string(),
+            failure: "welded".to_string(),
+            evidence: "current".to_string(),
+            timestamp: 155,
+        }), 160);
+        let interruptions = |count: u32, secs: u64| Some(Interruptions { count, secs });
+        fleet.observe(&Payload::OutageReport(OutageReport {
+            node_id: "node_01".to_string(),
+            month: "2026-03".to_string(),
+            grid_losses: interruptions(2, 5400),
+            islands: interruptions(1, 4800),
+            relays: vec![
+                RelayOutage { relay_id: "r_hvac".to_string(), interruptions: interruptions(3, 3600) },
+                RelayOutage { relay_id: "r_tv".to_string(), interruptions: interruptions(1, 4000) },
+            ],
+            ..Default::default()
+        }), 160);
+
+        let record = fleet.get("node_01").unwrap();
+        assert_eq!(record.last_seen, 160);
+        assert_eq!(record.relays.len(), 1);
+        assert!(!record.relays[0].is_closed);
+        assert_eq!(record.mesh_type.as_deref(), Some("AdHoc"));
+        assert_eq!(record.hardware_id, None);
+        assert_eq!(record.phase.as_deref(), Some("B"));
+        assert_eq!(record.battery_level, Some(0.5));
+        assert_eq!(record.key_epoch, 3);
+        assert_eq!(record.firmware_version.as_deref(), Some("0.2.0"));
+        assert_eq!(record.link.as_ref().unwrap().rssi, Some(-97));
+        assert_eq!(record.link.as_ref().unwrap().rtt_ms, Some(350));
+        assert_eq!(record.phases, vec![PhaseRecord { phase: "B".to_string(), volts: 229.5, amps: 41.0 }]);
+        assert_eq!(record.power[0].power_factor, 0.5);
+        assert_eq!(record.voltage_thd, Some(0.045));
+        assert_eq!(record.thermal, Some(ThermalRecord { board_temp_c: 68.0, derated_amps: Some(48.0) }));
+        assert_eq!(record.reserve_tier.as_deref(), Some("critical_only"));
+        assert_eq!(record.circuits, vec![CircuitRecord { circuit: "kitchen".to_string(), watts: 1224.0, imported_wh: 8532.5, exported_wh: 0.0 }]);
+        assert_eq!(record.sags, SagStats { count: 2, escalated: 1, lowest_voltage: Some(96.0), longest_secs: 15.0, last_start: Some(140) });
+        assert_eq!(record.failed_relays.get("r_hvac").map(String::as_str), Some("welded"));
+        assert_eq!(record.outages["2026-03"], OutageRecord {
+            grid_losses: 2,
+            grid_loss_secs: 5400,
+            islands: 1,
+            island_secs: 4800,
+            load_interruptions: 4,
+            load_interrupted_secs: 7600,
+        });
+        assert_eq!(record.last_alarm.as_deref(), Some("failed_relay"));
+        assert!(fleet.get("stranger").is_none());
+        assert_eq!(fleet.zones().get("block_3"), Some(&vec!["node_01".to_string()]));
+    }
+
+    #[test]
+    fn test_registry_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("streetgrid-fleet-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("fleet.json");
+
+        let mut fleet = Fleet::open(&path).unwrap();
+        fleet.observe(&Payload::Heartbeat(Heartbeat { node_id: "node_01".to_string(), ..Default::default() }), 42);
+        fleet.save_if_dirty().unwrap();
+
+        let reopened = Fleet::open(&path).unwrap();
+        assert_eq!(reopened.get("node_01"), fleet.get("node_01"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
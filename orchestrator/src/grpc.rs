@@ -0,0 +1,245 @@
+use anyhow::Result;
+use log::{info, warn};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_stream::wrappers::{BroadcastStream, TcpListenerStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+use crate::api::token_matches;
+use crate::config::GrpcToken;
+use crate::fleet::{reporting_node, Fleet, NodeRecord};
+use crate::orchestrator::RemoteCommand;
+use crate::transport::streetgrid::northbound::northbound_server::{Northbound, NorthboundServer};
+use crate::transport::streetgrid::northbound::{
+    IssueCommandRequest, IssueCommandResponse, ListNodesRequest, ListNodesResponse, NodeSummary, TelemetryRequest,
+};
+use crate::transport::streetgrid::neighborhood_message::Payload;
+use crate::transport::streetgrid::RelayInfo;
+use crate::transport::NeighborhoodMessage;
+
+/// Northbound gRPC service for utilities: node listing, telemetry streaming and
+/// command issuance, authorized by bearer token.
+pub struct GrpcApi {
+    listener: TcpListener,
+    service: NorthboundService,
+}
+
+impl GrpcApi {
+    pub async fn bind(
+        addr: &str,
+        tokens: Vec<GrpcToken>,
+        fleet: Arc<Mutex<Fleet>>,
+        telemetry: broadcast::Sender<NeighborhoodMessage>,
+        commands: mpsc::Sender<RemoteCommand>,
+    ) -> Result<Self> {
+        if tokens.is_empty() {
+            warn!("gRPC API has no tokens configured: every call will be refused");
+        }
+        let api = Self { listener: TcpListener::bind(addr).await?, service: NorthboundService { tokens, fleet, telemetry, commands } };
+        info!("gRPC API listening on {}", api.local_addr()?);
+        if !api.local_addr()?.ip().is_loopback() {
+            warn!("gRPC API is plaintext: tokens and commands cross the network unencrypted unless a TLS proxy fronts it");
+        }
+        Ok(api)
+    }
+
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub async fn run(self) {
+        let incoming = TcpListenerStream::new(self.listener);
+        if let Err(e) = tonic::transport::Server::builder()
+            .add_service(NorthboundServer::new(self.service))
+            .serve_with_incoming(incoming)
+            .await
+        {
+            warn!("gRPC API stopped: {}", e);
+        }
+    }
+}
+
+struct NorthboundService {
+    tokens: Vec<GrpcToken>,
+    fleet: Arc<Mutex<Fleet>>,
+    telemetry: broadcast::Sender<NeighborhoodMessage>,
+    commands: mpsc::Sender<RemoteCommand>,
+}
+
+impl NorthboundService {
+    /// The token presented with `request`, if it is one of ours and allowed to
+    /// issue commands when `command` is set.
+    #[allow(clippy::result_large_err)]
+    fn authorize<T>(&self, request: &Request<T>, command: bool) -> Result<&GrpcToken, Status> {
+        let presented = request.metadata().get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("missing bearer token"))?;
+        let token = self.tokens.iter().find(|t| token_matches(presented, &t.token))
+            .ok_or_else(|| Status::unauthenticated("unknown token"))?;
+        if command && !token.command {
+            return Err(Status::permission_denied(format!("{} may not issue commands", token.name)));
+        }
+        Ok(token)
+    }
+}
+
+/// Commands utilities may issue: shedding, islanding, black start, relay switching and
+/// demand response. Key rotation, firmware, configuration and the rest stay with the operator.
+fn issuable(payload: &Payload) -> bool {
+    matches!(
+        payload,
+        Payload::LoadShed(_)
+            | Payload::EnterIsland(_)
+            | Payload::EnterBlackStart(_)
+            | Payload::ActivateRelayByIndex(_)
+            | Payload::ActivateRelayByPriority(_)
+            | Payload::DemandResponse(_)
+    )
+}
+
+fn summary(node: &NodeRecord) -> NodeSummary {
+    NodeSummary {
+        node_id: node.node_id.clone(),
+        last_seen: node.last_seen,
+        liveness: node.liveness.label().to_string(),
+        zones: node.zones.clone(),
+        phase: node.phase.clone().unwrap_or_default(),
+        battery_level: node.battery_level.unwrap_or_default(),
+        firmware_version: node.firmware_version.clone().unwrap_or_default(),
+        relays: node.relays.iter()
+            .map(|r| RelayInfo {
+                index: r.index,
+                id: r.id.clone(),
+                name: r.name.clone(),
+                relay_type: r.relay_type,
+                priority: r.priority,
+                amperage: r.amperage,
+                is_closed: r.is_closed,
+                community_criticality: r.community_criticality,
+                phase: r.phase.clone().unwrap_or_default(),
+            })
+            .collect(),
+    }
+}
+
+type TelemetryStream = Pin<Box<dyn Stream<Item = Result<NeighborhoodMessage, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Northbound for NorthboundService {
+    async fn list_nodes(&self, request: Request<ListNodesRequest>) -> Result<Response<ListNodesResponse>, Status> {
+        self.authorize(&request, false)?;
+        let nodes = self.fleet.lock().unwrap().nodes().map(summary).collect();
+        Ok(Response::new(ListNodesResponse { nodes }))
+    }
+
+    type StreamTelemetryStream = TelemetryStream;
+
+    async fn stream_telemetry(&self, request: Request<TelemetryRequest>) -> Result<Response<TelemetryStream>, Status> {
+        let client = self.authorize(&request, false)?.name.clone();
+        let node_ids = request.into_inner().node_ids;
+        let stream = BroadcastStream::new(self.telemetry.subscribe()).filter_map(move |msg| match msg {
+            Ok(msg) => {
+                let node = msg.payload.as_ref().and_then(reporting_node);
+                (node_ids.is_empty() || node.is_some_and(|n| node_ids.iter().any(|id| id == n))).then_some(Ok(msg))
+            }
+            Err(e) => {
+                warn!("Telemetry stream to {} fell behind: {}", client, e);
+                None
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn issue_command(&self, request: Request<IssueCommandRequest>) -> Result<Response<IssueCommandResponse>, Status> {
+        let client = self.authorize(&request, true)?.name.clone();
+        let request = request.into_inner();
+        let target = request.target.ok_or_else(|| Status::invalid_argument("no node_id or zone"))?;
+        let payload = request.command.and_then(|c| c.payload).ok_or_else(|| Status::invalid_argument("no command payload"))?;
+        if !issuable(&payload) {
+            return Err(Status::invalid_argument("command may not be issued over the northbound API"));
+        }
+        let (reply, result) = oneshot::channel();
+        self.commands.send(RemoteCommand { target, payload, client, reply }).await
+            .map_err(|_| Status::unavailable("orchestrator is shutting down"))?;
+        match result.await {
+            Ok(Ok(())) => Ok(Response::new(IssueCommandResponse {})),
+            Ok(Err(e)) => Err(Status::failed_precondition(e.to_string())),
+            Err(_) => Err(Status::unavailable("orchestrator dropped the command")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orchestrator::REMOTE_COMMAND_QUEUE;
+    use crate::transport::streetgrid::northbound::issue_command_request::Target;
+    use crate::transport::streetgrid::northbound::northbound_client::NorthboundClient;
+    use crate::transport::streetgrid::{Heartbeat, LoadShed};
+    use tonic::Code;
+
+    fn authed<T>(message: T, token: &str) -> Request<T> {
+        let mut request = Request::new(message);
+        request.metadata_mut().insert("authorization", format!("Bearer {}", token).parse().unwrap());
+        request
+    }
+
+    #[tokio::test]
+    async fn test_tokens_gate_listing_and_commands() {
+        let fleet = Arc::new(Mutex::new(Fleet::default()));
+        let heartbeat = Payload::Heartbeat(Heartbeat { node_id: "node_01".to_string(), ..Default::default() });
+        fleet.lock().unwrap().observe(&heartbeat, 1000);
+        let (telemetry, _) = broadcast::channel(16);
+        let (commands, mut queued) = mpsc::channel(REMOTE_COMMAND_QUEUE);
+        let tokens = vec![
+            GrpcToken { name: "dashboard".to_string(), token: "read-secret".to_string(), command: false },
+            GrpcToken { name: "utility".to_string(), token: "command-secret".to_string(), command: true },
+        ];
+        let api = GrpcApi::bind("127.0.0.1:0", tokens, fleet, telemetry.clone(), commands).await.unwrap();
+        let addr = api.local_addr().unwrap();
+        tokio::spawn(api.run());
+        // Stand in for the orchestrator loop
+        tokio::spawn(async move {
+            while let Some(command) = queued.recv().await {
+                assert!(matches!(command.target, Target::NodeId(ref n) if n == "node_01"));
+                assert_eq!(command.client, "utility");
+                let _ = command.reply.send(Ok(()));
+            }
+        });
+        let mut client = NorthboundClient::connect(format!("http://{}", addr)).await.unwrap();
+
+        let refused = client.list_nodes(ListNodesRequest {}).await.unwrap_err();
+        assert_eq!(refused.code(), Code::Unauthenticated);
+        let nodes = client.list_nodes(authed(ListNodesRequest {}, "read-secret")).await.unwrap().into_inner().nodes;
+        assert_eq!((nodes[0].node_id.as_str(), nodes[0].liveness.as_str()), ("node_01", "online"));
+
+        let shed = || IssueCommandRequest {
+            target: Some(Target::NodeId("node_01".to_string())),
+            command: Some(NeighborhoodMessage {
+                payload: Some(Payload::LoadShed(LoadShed { target_node_id: "node_01".to_string(), shed_load: true })),
+                ..Default::default()
+            }),
+        };
+        let denied = client.issue_command(authed(shed(), "read-secret")).await.unwrap_err();
+        assert_eq!(denied.code(), Code::PermissionDenied);
+        client.issue_command(authed(shed(), "command-secret")).await.unwrap();
+        // Only operational commands pass, whatever the token
+        let mut rotate = shed();
+        rotate.command.as_mut().unwrap().payload = Some(Payload::KeyRotation(Default::default()));
+        let refused = client.issue_command(authed(rotate, "command-secret")).await.unwrap_err();
+        assert_eq!(refused.code(), Code::InvalidArgument);
+
+        // Telemetry only from the nodes asked for
+        let request = TelemetryRequest { node_ids: vec!["node_02".to_string()] };
+        let mut stream = client.stream_telemetry(authed(request, "read-secret")).await.unwrap().into_inner();
+        for node_id in ["node_01", "node_02"] {
+            let payload = Payload::Heartbeat(Heartbeat { node_id: node_id.to_string(), ..Default::default() });
+            telemetry.send(NeighborhoodMessage { payload: Some(payload), ..Default::default() }).unwrap();
+        }
+        let received = stream.message().await.unwrap().unwrap();
+        assert!(matches!(received.payload, Some(Payload::Heartbeat(hb)) if hb.node_id == "node_02"));
+    }
+}
@@ -0,0 +1,154 @@
+//! In-process integration harness: a real firmware node and the orchestrator wired
+//! together over tokio channels, so whole command/response flows run in `cargo test`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use streetgrid_core::channel::ChannelLink;
+use streetgrid_core::comms::{CommunicationLayer, OrchestratorClient};
+use streetgrid_core::hal::gpio::mock::MockRelayDriver;
+use streetgrid_core::node::{EdgeNode, Task};
+use streetgrid_core::types::Relay;
+use tokio::sync::mpsc;
+use crate::fleet::Fleet;
+use crate::orchestrator::Orchestrator;
+use crate::transport::ChannelTransport;
+
+/// Exchanges before `settle` gives up on the two sides going quiet.
+const MAX_ROUNDS: usize = 100;
+
+pub struct Harness {
+    pub node: EdgeNode,
+    pub orchestrator: Orchestrator,
+    link: Arc<ChannelLink>,
+}
+
+impl Harness {
+    /// An unsigned mesh of one AdHoc node with `relays` on mock drivers.
+    pub fn new(node_id: &str, relays: Vec<Relay>) -> Self {
+        let (uplink_tx, uplink_rx) = mpsc::unbounded_channel();
+        let (downlink_tx, downlink_rx) = mpsc::unbounded_channel();
+        let link = Arc::new(ChannelLink::new(uplink_tx, downlink_rx));
+        let transport = Arc::new(ChannelTransport::new(downlink_tx, uplink_rx));
+
+        let relay_pins = relays.iter().enumerate().map(|(i, r)| (r.id.clone(), i as u8)).collect::<HashMap<_, _>>();
+        let driver = MockRelayDriver::new(&[]).expect("mock relay driver");
+        let client = OrchestratorClient::new(link.clone()).with_origin(node_id);
+        let node = EdgeNode::builder(node_id)
+            .relays(relays)
+            .relay_pins(relay_pins)
+            .client(client)
+            .relay_driver(Box::new(driver))
+            .build()
+            .unwrap();
+        let orchestrator = Orchestrator::new("orchestrator", transport, None, Fleet::default());
+        Self { node, orchestrator, link }
+    }
+
+    /// Boot the node and let it register.
+    pub async fn start(&mut self) {
+        self.node.start().await;
+        self.settle().await;
+    }
+
+    /// Run one of the node's periodic tasks, then let the exchange it starts finish.
+    pub async fn run_task(&mut self, task: Task) {
+        self.node.run_task(task).await;
+        self.settle().await;
+    }
+
+    /// Pass messages both ways until neither side has anything left to say: the
+    /// orchestrator handles what the node sent, the node what the orchestrator sent.
+    pub async fn settle(&mut self) {
+        for _ in 0..MAX_ROUNDS {
+            let mut quiet = true;
+            while let Ok(Some(msg)) = tokio::time::timeout(Duration::ZERO, self.orchestrator.receive()).await {
+                self.orchestrator.handle(msg).await;
+                quiet = false;
+            }
+            let received = self.link.link_stats().rx_packets;
+            self.node.run_task(Task::Messages).await;
+            if quiet && self.link.link_stats().rx_packets == received {
+                return;
+            }
+        }
+        panic!("node and orchestrator still talking after {} rounds", MAX_ROUNDS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{self, Command};
+    use streetgrid_core::types::{NodeState, Priority, RelayType};
+
+    fn relay(id: &str, relay_type: RelayType, priority: Priority) -> Relay {
+        Relay {
+            id: id.to_string(),
+            name: id.to_string(),
+            relay_type,
+            priority,
+            amperage: 10.0,
+            is_closed: true,
+            community_criticality: 0,
+            phase: None,
+        }
+    }
+
+    async fn console(harness: &mut Harness, line: &str) {
+        match commands::parse(line, chrono::Utc::now().timestamp()).unwrap() {
+            Command::Issue { target, payload } => harness.orchestrator.issue(&target, payload).await.unwrap(),
+            other => panic!("`{}` is not a node command: {:?}", line, other),
+        }
+        harness.settle().await;
+    }
+
+    fn harness() -> Harness {
+        Harness::new("node_01", vec![
+            relay("r_grid", RelayType::Grid, Priority::Critical),
+            relay("r_fridge", RelayType::Load, Priority::High),
+            relay("r_hvac", RelayType::Load, Priority::Medium),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_node_registers_and_heartbeats_reach_the_fleet() {
+        let mut harness = harness();
+        harness.start().await;
+        {
+            let fleet = harness.orchestrator.fleet.lock().unwrap();
+            let record = fleet.get("node_01").unwrap();
+            assert_eq!(record.relays.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), ["r_grid", "r_fridge", "r_hvac"]);
+            assert_eq!(record.last_heartbeat, None);
+        }
+
+        harness.run_task(Task::Heartbeat).await;
+        let fleet = harness.orchestrator.fleet.lock().unwrap();
+        let record = fleet.get("node_01").unwrap();
+        assert!(record.last_heartbeat.is_some());
+        // The registration was acknowledged, and timed
+        assert!(record.link.as_ref().unwrap().rtt_ms.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_console_commands_drive_the_node_and_answers_come_back() {
+        let mut harness = harness();
+        harness.start().await;
+
+        console(&mut harness, "island node_01").await;
+        assert_eq!(harness.node.state, NodeState::Islanded);
+        assert!(harness.node.relays.iter().all(|r| !r.is_closed));
+        console(&mut harness, "activate node_01 1").await;
+        assert!(harness.node.relays[1].is_closed);
+
+        // Commands for other nodes are not ours to act on
+        console(&mut harness, "shed node_02").await;
+        assert!(harness.node.relays[1].is_closed);
+
+        // A snapshot comes back, and can seed a node
+        assert!(harness.orchestrator.restore_snapshot("node_01", "node_01").await.is_err());
+        console(&mut harness, "snapshot node_01").await;
+        harness.orchestrator.restore_snapshot("node_01", "node_01").await.unwrap();
+        harness.settle().await;
+    }
+}
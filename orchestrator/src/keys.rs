@@ -0,0 +1,49 @@
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const KEY_LEN: usize = 32;
+
+/// Mesh pre-shared key; MACs are HMAC-SHA256 over the message encoded without `auth`,
+/// matching the firmware's keyring.
+pub struct MeshKey {
+    pub epoch: u32,
+    key: [u8; KEY_LEN],
+}
+
+impl std::fmt::Debug for MeshKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MeshKey").field("epoch", &self.epoch).finish_non_exhaustive()
+    }
+}
+
+impl MeshKey {
+    pub fn new(epoch: u32, key: [u8; KEY_LEN]) -> Self {
+        Self { epoch, key }
+    }
+
+    /// Build from the hex-encoded PSK in config.yaml.
+    pub fn from_hex(epoch: u32, psk: &str) -> Result<Self> {
+        let mut key = [0u8; KEY_LEN];
+        hex::decode_to_slice(psk.trim(), &mut key)
+            .with_context(|| format!("PSK must be {} hex-encoded bytes", KEY_LEN))?;
+        Ok(Self::new(epoch, key))
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Vec<u8> {
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    pub fn verify(&self, epoch: u32, data: &[u8], tag: &[u8]) -> bool {
+        if epoch != self.epoch {
+            return false;
+        }
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(data);
+        mac.verify_slice(tag).is_ok()
+    }
+}
@@ -0,0 +1,23 @@
+pub mod config;
+pub mod transport;
+pub mod keys;
+pub mod fleet;
+pub mod commands;
+pub mod orchestrator;
+pub mod api;
+pub mod topology;
+pub mod settlement;
+pub mod rebalance;
+pub mod liveness;
+pub mod blackstart;
+pub mod phases;
+pub mod vpp;
+pub mod simulation;
+pub mod grpc;
+pub mod dnp3;
+pub mod openadr;
+pub mod timeline;
+pub mod mock;
+
+#[cfg(test)]
+mod harness;
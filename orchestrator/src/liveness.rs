@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::fleet::Fleet;
+use crate::topology::Topology;
+use crate::transport::streetgrid::{LivenessProbe, LivenessReport};
+
+/// Nodes heartbeat once a minute.
+pub const DEFAULT_HEARTBEAT_INTERVAL_SECS: i64 = 60;
+
+pub const DEFAULT_MISSED_HEARTBEATS: u32 = 3;
+
+/// How long a stale node has to turn up, directly or through a neighbour, before it
+/// is declared down.
+pub const DOWN_AFTER_SECS: i64 = 2 * 60;
+
+/// Whether a node is keeping up its heartbeats.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Liveness {
+    #[default]
+    Online,
+    /// Missed too many heartbeats
+    Stale { since: i64 },
+    /// Stayed stale and no neighbour could reach it
+    Down { since: i64 },
+    /// Said it was stopping on purpose; quiet until it heartbeats again
+    ShutDown { since: i64 },
+}
+
+impl Liveness {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Liveness::Online => "online",
+            Liveness::Stale { .. } => "stale",
+            Liveness::Down { .. } => "down",
+            Liveness::ShutDown { .. } => "shut_down",
+        }
+    }
+
+    /// Not running as far as we know, whether it was lost or stopped on purpose.
+    pub fn is_down(&self) -> bool {
+        matches!(self, Liveness::Down { .. } | Liveness::ShutDown { .. })
+    }
+}
+
+/// A change worth alerting on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Stale { node_id: String, silent_secs: i64 },
+    /// Down, though a neighbour still hears it: the node is alive but cut off from us
+    Unreachable { node_id: String, heard_by: String },
+    Down { node_id: String },
+    Recovered { node_id: String },
+}
+
+/// Marks nodes stale after missed heartbeats and down once nobody can reach them,
+/// optionally asking the nodes that hear a silent node to ping it first.
+#[derive(Debug)]
+pub struct LivenessMonitor {
+    timeout_secs: i64,
+    probe_neighbors: bool,
+    /// Silent node -> neighbour that last reported hearing it, and when it did
+    heard: BTreeMap<String, (String, i64)>,
+    probes: u64,
+}
+
+impl Default for LivenessMonitor {
+    fn default() -> Self {
+        Self::new(DEFAULT_HEARTBEAT_INTERVAL_SECS, DEFAULT_MISSED_HEARTBEATS, false)
+    }
+}
+
+impl LivenessMonitor {
+    pub fn new(heartbeat_interval_secs: i64, missed_heartbeats: u32, probe_neighbors: bool) -> Self {
+        Self {
+            timeout_secs: heartbeat_interval_secs * missed_heartbeats.max(1) as i64,
+            probe_neighbors,
+            heard: BTreeMap::new(),
+            probes: 0,
+        }
+    }
+
+    /// Re-evaluate every node; returns what changed and the probes to send.
+    pub fn check(&mut self, fleet: &mut Fleet, topology: &Topology, now: i64) -> (Vec<Event>, Vec<LivenessProbe>) {
+        let nodes: Vec<(String, i64, Liveness)> = fleet.nodes()
+            .map(|n| (n.node_id.clone(), n.last_heartbeat.unwrap_or(n.last_seen), n.liveness))
+            .collect();
+        let online: Vec<&str> = nodes.iter().filter(|(_, _, l)| *l == Liveness::Online).map(|(id, _, _)| id.as_str()).collect();
+        let mut events = Vec::new();
+        let mut probes = Vec::new();
+        for (node_id, last, liveness) in &nodes {
+            let fresh = now - last <= self.timeout_secs;
+            let changed = match liveness {
+                Liveness::Online if !fresh => {
+                    events.push(Event::Stale { node_id: node_id.clone(), silent_secs: now - last });
+                    if self.probe_neighbors {
+                        for hearer in topology.hearers_of(node_id).into_iter().filter(|h| online.contains(&h.as_str())) {
+                            self.probes += 1;
+                            probes.push(LivenessProbe {
+                                target_node_id: hearer,
+                                silent_node_id: node_id.clone(),
+                                probe_id: format!("lp-{}-{}", now, self.probes),
+                            });
+                        }
+                    }
+                    Some(Liveness::Stale { since: now })
+                }
+                Liveness::Stale { since } if !fresh && now - since >= DOWN_AFTER_SECS => {
+                    // Heard by a neighbour after its last heartbeat reached us: alive, but cut off
+                    match self.heard.get(node_id).filter(|(_, at)| at > last) {
+                        Some((heard_by, _)) => events.push(Event::Unreachable { node_id: node_id.clone(), heard_by: heard_by.clone() }),
+                        None => events.push(Event::Down { node_id: node_id.clone() }),
+                    }
+                    Some(Liveness::Down { since: now })
+                }
+                Liveness::Stale { .. } | Liveness::Down { .. } if fresh => {
+                    self.heard.remove(node_id);
+                    events.push(Event::Recovered { node_id: node_id.clone() });
+                    Some(Liveness::Online)
+                }
+                // Back from a restart; its silence until then was expected
+                Liveness::ShutDown { since } if last > since => {
+                    events.push(Event::Recovered { node_id: node_id.clone() });
+                    Some(Liveness::Online)
+                }
+                _ => None,
+            };
+            if let Some(liveness) = changed {
+                fleet.set_liveness(node_id, liveness);
+            }
+        }
+        (events, probes)
+    }
+
+    /// A neighbour's findings on a node we probed.
+    pub fn report(&mut self, report: &LivenessReport) {
+        if report.last_heard > 0 {
+            self.heard.insert(report.silent_node_id.clone(), (report.node_id.clone(), report.last_heard));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::streetgrid::neighborhood_message::Payload;
+    use crate::transport::streetgrid::{Heartbeat, Neighbor, NeighborReport};
+
+    fn heartbeat(fleet: &mut Fleet, node_id: &str, now: i64) {
+        fleet.observe(&Payload::Heartbeat(Heartbeat { node_id: node_id.to_string(), ..Default::default() }), now);
+    }
+
+    #[test]
+    fn test_silent_node_is_probed_then_declared_down() {
+        let mut fleet = Fleet::default();
+        let mut topology = Topology::default();
+        topology.update(&NeighborReport {
+            node_id: "node_02".to_string(),
+            neighbors: vec![Neighbor { node_id: "node_01".to_string(), ..Default::default() }],
+            ..Default::default()
+        });
+        let mut monitor = LivenessMonitor::new(60, 3, true);
+        heartbeat(&mut fleet, "node_01", 0);
+        heartbeat(&mut fleet, "node_02", 170);
+
+        assert_eq!(monitor.check(&mut fleet, &topology, 180), (Vec::new(), Vec::new()));
+        let (events, probes) = monitor.check(&mut fleet, &topology, 181);
+        assert_eq!(events, vec![Event::Stale { node_id: "node_01".to_string(), silent_secs: 181 }]);
+        assert_eq!(probes.len(), 1);
+        assert_eq!((probes[0].target_node_id.as_str(), probes[0].silent_node_id.as_str()), ("node_02", "node_01"));
+        assert_eq!(fleet.get("node_01").unwrap().liveness, Liveness::Stale { since: 181 });
+
+        // Nobody reached it
+        heartbeat(&mut fleet, "node_02", 300);
+        let (events, _) = monitor.check(&mut fleet, &topology, 181 + DOWN_AFTER_SECS);
+        assert_eq!(events, vec![Event::Down { node_id: "node_01".to_string() }]);
+
+        heartbeat(&mut fleet, "node_01", 400);
+        let (events, _) = monitor.check(&mut fleet, &topology, 400);
+        assert_eq!(events, vec![Event::Recovered { node_id: "node_01".to_string() }]);
+        assert_eq!(fleet.get("node_01").unwrap().liveness, Liveness::Online);
+    }
+
+    #[test]
+    fn test_node_a_neighbour_still_hears_is_unreachable_not_down() {
+        let mut fleet = Fleet::default();
+        let mut monitor = LivenessMonitor::new(60, 3, false);
+        heartbeat(&mut fleet, "node_01", 0);
+        monitor.check(&mut fleet, &Topology::default(), 181);
+        monitor.report(&LivenessReport {
+            node_id: "node_02".to_string(),
+            silent_node_id: "node_01".to_string(),
+            last_heard: 190,
+            ..Default::default()
+        });
+        let (events, _) = monitor.check(&mut fleet, &Topology::default(), 181 + DOWN_AFTER_SECS);
+        assert_eq!(events, vec![Event::Unreachable { node_id: "node_01".to_string(), heard_by: "node_02".to_string() }]);
+    }
+
+    #[test]
+    fn test_node_that_said_it_was_shutting_down_is_not_alerted_on() {
+        let mut fleet = Fleet::default();
+        let mut monitor = LivenessMonitor::new(60, 3, false);
+        let last = Heartbeat { node_id: "node_01".to_string(), shutting_down: true, ..Default::default() };
+        fleet.observe(&Payload::Heartbeat(last), 100);
+        assert_eq!(fleet.get("node_01").unwrap().liveness, Liveness::ShutDown { since: 100 });
+
+        assert_eq!(monitor.check(&mut fleet, &Topology::default(), 100 + 10 * DOWN_AFTER_SECS), (Vec::new(), Vec::new()));
+        assert!(fleet.get("node_01").unwrap().liveness.is_down());
+
+        heartbeat(&mut fleet, "node_01", 2000);
+        let (events, _) = monitor.check(&mut fleet, &Topology::default(), 2000);
+        assert_eq!(events, vec![Event::Recovered { node_id: "node_01".to_string() }]);
+        assert_eq!(fleet.get("node_01").unwrap().liveness, Liveness::Online);
+    }
+}
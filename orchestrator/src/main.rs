@@ -0,0 +1,369 @@
+use log::{info, error, warn};
+use clap::Parser;
+use streetgrid_orchestrator::{commands, phases, vpp};
+use streetgrid_orchestrator::commands::Command;
+use streetgrid_orchestrator::api::{Dashboard, FleetApi};
+use streetgrid_orchestrator::config::load_config;
+use streetgrid_orchestrator::dnp3::{Dnp3Outstation, DEFAULT_MASTER_ADDRESS, DEFAULT_OUTSTATION_ADDRESS};
+use streetgrid_orchestrator::fleet::Fleet;
+use streetgrid_orchestrator::grpc::GrpcApi;
+use streetgrid_core::keys::Keyring;
+use streetgrid_orchestrator::keys::MeshKey;
+use streetgrid_orchestrator::liveness::{LivenessMonitor, DEFAULT_HEARTBEAT_INTERVAL_SECS, DEFAULT_MISSED_HEARTBEATS};
+use streetgrid_orchestrator::openadr::{NodeResponse, OpenAdrWebhook};
+use streetgrid_orchestrator::orchestrator::{Orchestrator, RemoteCommand, REMOTE_COMMAND_QUEUE};
+use streetgrid_orchestrator::rebalance::Rebalancer;
+use streetgrid_orchestrator::settlement::Settlement;
+use streetgrid_orchestrator::simulation::{Simulation, DEFAULT_QUORUM};
+use streetgrid_orchestrator::transport::streetgrid::neighborhood_message::Payload;
+use streetgrid_orchestrator::transport::streetgrid::northbound::issue_command_request::Target;
+use streetgrid_orchestrator::transport::{MockTransport, MqttSettings, MqttTransport, Transport};
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// Registry file used when the config doesn't name one
+const DEFAULT_REGISTRY: &str = "fleet.json";
+
+/// How often registry changes are written out
+const REGISTRY_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often unconfirmed rebalance steps are checked for timeouts
+const REBALANCE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often a fleet black start checks for confirmations and sends its next stage
+const BLACKSTART_STEP_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often nodes are checked for missed heartbeats
+const LIVENESS_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How often simulated time moves on in simulation mode, keeping pace with the wall clock
+const SIMULATION_TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often we broadcast that we're alive; nodes elect a coordinator after 3 minutes without it
+const ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Path to the configuration file
+    #[arg(short, long, default_value = "config.yaml")]
+    config: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+
+    info!("StreetGrid Orchestrator v{}", env!("CARGO_PKG_VERSION"));
+    let config = load_config(&args.config)?;
+    let id = config.id.clone().unwrap_or_else(|| "orchestrator".to_string());
+
+    let mesh_key = match &config.mesh {
+        Some(mesh) => Some(MeshKey::from_hex(mesh.key_epoch.unwrap_or(1), &mesh.psk)?),
+        None => {
+            warn!("No mesh key configured: commands go out unsigned");
+            None
+        }
+    };
+
+    let simulation = match &config.simulation {
+        Some(sim) => {
+            info!("Simulating {} nodes in {} zones", sim.nodes, sim.zones.unwrap_or(1));
+            let keyring = config.mesh.as_ref().map(|m| Keyring::from_hex(m.key_epoch.unwrap_or(1), &m.psk)).transpose()?;
+            Some(Arc::new(Simulation::new(sim.nodes, sim.zones.unwrap_or(1), sim.quorum.unwrap_or(DEFAULT_QUORUM), keyring)?))
+        }
+        None => None,
+    };
+    let transport: Arc<dyn Transport> = match (&simulation, &config.mqtt) {
+        (Some(simulation), _) => simulation.clone(),
+        (None, Some(mqtt)) => Arc::new(MqttTransport::connect(&MqttSettings::from_config(mqtt, format!("streetgrid-{}", id))?)?),
+        (None, None) => Arc::new(MockTransport::new()),
+    };
+
+    let fleet = Fleet::open(config.registry.as_deref().unwrap_or(DEFAULT_REGISTRY))?;
+    let mut orchestrator = Orchestrator::new(&id, transport, mesh_key, fleet);
+    if let Some(liveness) = &config.liveness {
+        orchestrator.liveness = LivenessMonitor::new(
+            liveness.heartbeat_interval_secs.unwrap_or(DEFAULT_HEARTBEAT_INTERVAL_SECS),
+            liveness.missed_heartbeats.unwrap_or(DEFAULT_MISSED_HEARTBEATS),
+            liveness.probe_neighbors.unwrap_or(false),
+        );
+    }
+    if let Some(transformers) = &config.transformers {
+        orchestrator.rebalancer = Rebalancer::new(transformers);
+    }
+    if let Some(device_keys) = &config.device_keys {
+        orchestrator.settlement = Settlement::new(device_keys)?;
+    }
+    let (remote_commands, mut remote_queue) = tokio::sync::mpsc::channel::<RemoteCommand>(REMOTE_COMMAND_QUEUE);
+    if let Some(api_config) = &config.api {
+        let dashboard = Dashboard {
+            telemetry: orchestrator.telemetry.clone(),
+            timeline: orchestrator.timeline.clone(),
+            commands: api_config.command_token.clone().map(|token| (remote_commands.clone(), token)),
+            static_dir: api_config.static_dir.as_ref().map(PathBuf::from),
+        };
+        let api = FleetApi::bind(&api_config.bind, orchestrator.fleet.clone(), orchestrator.topology.clone()).await?
+            .with_dashboard(dashboard);
+        tokio::spawn(api.run());
+    }
+    if let Some(grpc_config) = &config.grpc {
+        let api = GrpcApi::bind(
+            &grpc_config.bind,
+            grpc_config.tokens.clone(),
+            orchestrator.fleet.clone(),
+            orchestrator.telemetry.clone(),
+            remote_commands.clone(),
+        ).await?;
+        tokio::spawn(api.run());
+    }
+    if let Some(dnp3_config) = &config.dnp3 {
+        let outstation = Dnp3Outstation::bind(
+            &dnp3_config.bind,
+            dnp3_config.outstation_address.unwrap_or(DEFAULT_OUTSTATION_ADDRESS),
+            dnp3_config.master_address.unwrap_or(DEFAULT_MASTER_ADDRESS),
+            dnp3_config.allowed_masters.clone().unwrap_or_default(),
+            dnp3_config.nodes.clone(),
+            orchestrator.fleet.clone(),
+            remote_commands.clone(),
+        ).await?;
+        tokio::spawn(outstation.run());
+    }
+    if let Some(openadr_config) = &config.openadr {
+        let webhook = OpenAdrWebhook::bind(
+            &openadr_config.bind,
+            openadr_config.token.clone(),
+            orchestrator.fleet.clone(),
+            orchestrator.demand_response.clone(),
+            remote_commands.clone(),
+        ).await?;
+        tokio::spawn(webhook.run());
+    }
+    info!("Orchestrator {} listening on {} transport (type 'help' for commands)", id, orchestrator.transport_name());
+
+    // The registry may be stale or missing; have every node register again
+    if let Err(e) = orchestrator.who_is_there().await {
+        warn!("Failed to ask nodes to register: {}", e);
+    }
+
+    let mut console = BufReader::new(tokio::io::stdin()).lines();
+    let mut save_interval = tokio::time::interval(REGISTRY_SAVE_INTERVAL);
+    let mut alive_interval = tokio::time::interval(ALIVE_INTERVAL);
+    let mut rebalance_interval = tokio::time::interval(REBALANCE_CHECK_INTERVAL);
+    let mut liveness_interval = tokio::time::interval(LIVENESS_CHECK_INTERVAL);
+    let mut blackstart_interval = tokio::time::interval(BLACKSTART_STEP_INTERVAL);
+    let mut simulation_interval = tokio::time::interval(SIMULATION_TICK_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = save_interval.tick() => {
+                if let Err(e) = orchestrator.fleet.lock().unwrap().save_if_dirty() {
+                    error!("Failed to save fleet registry: {}", e);
+                }
+            }
+
+            _ = alive_interval.tick() => {
+                if let Err(e) = orchestrator.announce_alive().await {
+                    warn!("Failed to announce liveness: {}", e);
+                }
+            }
+
+            _ = rebalance_interval.tick() => orchestrator.expire_rebalances().await,
+
+            _ = liveness_interval.tick() => orchestrator.check_liveness().await,
+
+            _ = blackstart_interval.tick() => orchestrator.advance_blackstart().await,
+
+            _ = simulation_interval.tick(), if simulation.is_some() => {
+                if let Some(simulation) = &simulation {
+                    simulation.advance(SIMULATION_TICK_INTERVAL).await;
+                }
+            }
+
+            Some(command) = remote_queue.recv() => {
+                let name = command_name(&command.payload);
+                let (node_id, target, result) = match command.target {
+                    Target::NodeId(node_id) => {
+                        info!("{} issuing a command to {}", command.client, node_id);
+                        let result = orchestrator.issue(&node_id, command.payload).await;
+                        (Some(node_id.clone()), node_id, result)
+                    }
+                    Target::Zone(zone) => {
+                        info!("{} issuing a command to zone {}", command.client, zone);
+                        let result = orchestrator.issue_zone(&zone, command.payload).await;
+                        (None, format!("zone {}", zone), result)
+                    }
+                };
+                let message = match &result {
+                    Ok(()) => format!("{} sent {} to {}", command.client, name, target),
+                    Err(e) => format!("{} failed to send {} to {}: {}", command.client, name, target, e),
+                };
+                orchestrator.timeline.lock().unwrap().record(chrono::Utc::now().timestamp(), node_id.as_deref(), "command", message);
+                let _ = command.reply.send(result);
+            }
+
+            msg = orchestrator.receive() => match msg {
+                Some(msg) => orchestrator.handle(msg).await,
+                None => {
+                    error!("Transport closed");
+                    return Ok(());
+                }
+            },
+
+            line = console.next_line() => match line {
+                Ok(Some(line)) => run_console_command(&mut orchestrator, simulation.as_deref(), &line).await,
+                // Console closed (e.g. running as a service); keep serving the fleet
+                Ok(None) | Err(_) => std::future::pending::<()>().await,
+            },
+        }
+    }
+}
+
+/// Payload variant of a command, e.g. "LoadShed", for the timeline.
+fn command_name(payload: &Payload) -> String {
+    let debug = format!("{:?}", payload);
+    debug.split('(').next().unwrap_or_default().to_string()
+}
+
+async fn run_console_command(orchestrator: &mut Orchestrator, simulation: Option<&Simulation>, line: &str) {
+    let now = chrono::Utc::now().timestamp();
+    let result = match commands::parse(line, now) {
+        Ok(Command::Help) => {
+            println!("{}", commands::HELP);
+            Ok(())
+        }
+        Ok(Command::ListNodes) => {
+            for node in orchestrator.fleet.lock().unwrap().nodes() {
+                println!(
+                    "{:<16} {:<7} seen {:>5}s ago  mesh {:<20} fw {:<8} relays {:<2} rssi {:>4}  battery {:?}  alarm {}",
+                    node.node_id,
+                    node.liveness.label(),
+                    now - node.last_seen,
+                    node.mesh_type.as_deref().unwrap_or("?"),
+                    node.firmware_version.as_deref().unwrap_or("?"),
+                    node.relays.len(),
+                    node.link.as_ref().and_then(|l| l.rssi).map(|r| r.to_string()).unwrap_or_else(|| "?".to_string()),
+                    node.battery_level,
+                    node.last_alarm.as_deref().unwrap_or("-"),
+                );
+            }
+            Ok(())
+        }
+        Ok(Command::ListZones) => {
+            for (zone, nodes) in orchestrator.fleet.lock().unwrap().zones() {
+                println!("{:<16} {}", zone, nodes.join(", "));
+            }
+            Ok(())
+        }
+        Ok(Command::ShowNode(node_id)) => {
+            match orchestrator.fleet.lock().unwrap().get(&node_id) {
+                Some(node) => println!("{:#?}", node),
+                None => println!("{} has not reported in", node_id),
+            }
+            Ok(())
+        }
+        Ok(Command::ShowTopology) => {
+            let nodes: Vec<String> = orchestrator.fleet.lock().unwrap().nodes().map(|n| n.node_id.clone()).collect();
+            let view = orchestrator.topology.lock().unwrap().view(nodes);
+            for link in &view.links {
+                match link.packet_error_rate {
+                    Some(per) => println!("{:<16} hears {:<16} rssi {:?} PER {:.1}%", link.from, link.to, link.rssi, per * 100.0),
+                    None => println!("{:<16} hears {:<16} rssi {:?}", link.from, link.to, link.rssi),
+                }
+            }
+            for (i, component) in view.components.iter().enumerate() {
+                println!("group {}: {}", i + 1, component.join(", "));
+            }
+            if !view.isolated.is_empty() {
+                println!("isolated: {}", view.isolated.join(", "));
+            }
+            Ok(())
+        }
+        Ok(Command::ShowVpp) => {
+            let report = vpp::aggregate(orchestrator.fleet.lock().unwrap().nodes(), now);
+            println!("{} nodes online", report.nodes);
+            for (tier, kw) in &report.sheddable_kw {
+                println!("sheddable {:<8} {:>7.1} kW", tier, kw);
+            }
+            println!("sheddable total    {:>7.1} kW", report.sheddable_kw_total);
+            println!("storage            {:>7.1} of {:.1} kWh ({} source nodes)", report.storage_kwh, report.storage_capacity_kwh, report.source_nodes);
+            println!("export now         {:>7.1} kW", report.export_kw);
+            println!("solar expected     {:>7.1} kWh", report.solar_forecast_kwh);
+            Ok(())
+        }
+        Ok(Command::ShowDemandResponse) => {
+            for event in orchestrator.demand_response.lock().unwrap().events() {
+                let count = |f: fn(&NodeResponse) -> bool| event.nodes.values().filter(|r| f(r)).count();
+                println!(
+                    "{:<20} level {} {}..{}{}  {:.1} kW committed  in {}  out {}  pending {}",
+                    event.event_id, event.signal_level, event.start, event.end,
+                    if event.cancelled { " (cancelled)" } else { "" }, event.committed_kw,
+                    count(|r| matches!(r, NodeResponse::OptedIn { .. })),
+                    count(|r| *r == NodeResponse::OptedOut),
+                    count(|r| *r == NodeResponse::Pending),
+                );
+            }
+            Ok(())
+        }
+        Ok(Command::Settle { since, until }) => {
+            for (node_id, balance) in orchestrator.settlement.balances(since, until) {
+                println!(
+                    "{:<16} exported {:>8.0} Wh  imported {:>8.0} Wh  ({} entries, {} unsigned)",
+                    node_id, balance.exported_wh, balance.imported_wh, balance.entries, balance.unsigned
+                );
+            }
+            for transfer in orchestrator.settlement.transfers(since, until) {
+                println!("{:<16} owes {:<16} {:>8.0} Wh", transfer.from, transfer.to, transfer.wh);
+            }
+            Ok(())
+        }
+        Ok(Command::Rebalance { from, shed, to, restore }) => orchestrator.rebalance(&from, shed, &to, restore).await,
+        Ok(Command::ShowRebalances) => {
+            for line in orchestrator.rebalancer.in_flight() {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        Ok(Command::ShowPhases(zone)) => {
+            let fleet = orchestrator.fleet.lock().unwrap();
+            for (phase, amps) in phases::loads(fleet.nodes().filter(|n| zone.as_ref().is_none_or(|z| n.zones.contains(z)))) {
+                println!("phase {}  {:>7.1} A", phase, amps);
+            }
+            Ok(())
+        }
+        Ok(Command::BalancePhases(zone)) => orchestrator.balance_phases(zone.as_deref()).await,
+        Ok(Command::FleetBlackStart(zones)) => orchestrator.start_blackstart(&zones).await,
+        Ok(Command::FleetBlackStartStatus) => {
+            match &orchestrator.blackstart {
+                Some(blackstart) => println!("{}", blackstart.status()),
+                None => println!("no fleet black start in progress"),
+            }
+            Ok(())
+        }
+        Ok(Command::FleetBlackStartAbort) => {
+            if orchestrator.blackstart.take().is_some() {
+                warn!("Fleet black start aborted; nodes stay as they are");
+            }
+            Ok(())
+        }
+        Ok(Command::WhoIsThere) => orchestrator.who_is_there().await,
+        Ok(Command::Takeover) => orchestrator.announce_takeover().await,
+        Ok(Command::SimVoltage { zone, voltage }) => match simulation {
+            Some(simulation) => {
+                simulation.set_voltage(zone.as_deref(), voltage).await;
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("not running a simulation")),
+        },
+        Ok(Command::IssueZone { zone, payload }) => orchestrator.issue_zone(&zone, payload).await,
+        Ok(Command::Issue { target, payload }) => orchestrator.issue(&target, payload).await,
+        Ok(Command::Restore { from, target }) => orchestrator.restore_snapshot(&from, &target).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        println!("error: {}", e);
+    }
+}
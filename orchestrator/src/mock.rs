@@ -0,0 +1,237 @@
+//! Stand-in orchestrator for bench testing a real node end to end: it registers nodes,
+//! answers their heartbeats, and sends commands on a script, without the registry,
+//! APIs or console of the full orchestrator.
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use crate::commands::{self, Command};
+use crate::fleet::Fleet;
+use crate::keys::MeshKey;
+use crate::orchestrator::Orchestrator;
+use crate::transport::streetgrid::neighborhood_message::Payload;
+use crate::transport::{NeighborhoodMessage, Transport};
+
+/// Commands to send to the node under test, e.g.
+///
+/// ```yaml
+/// commands:
+///   - command: "shed node_01"
+///     after_secs: 30
+///   - command: "activate node_01 2"
+///     after_secs: 60
+///     every_secs: 120
+/// ```
+#[derive(Debug, Deserialize, Default)]
+pub struct Script {
+    pub commands: Vec<ScriptedCommand>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScriptedCommand {
+    /// As typed at the orchestrator console (see `help` there)
+    pub command: String,
+    /// Seconds after start before it is first sent (default 0)
+    pub after_secs: Option<u64>,
+    /// Send it again this often; once without
+    pub every_secs: Option<u64>,
+}
+
+impl Script {
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+        serde_yaml::from_str(&contents).with_context(|| format!("parsing {}", path))
+    }
+}
+
+/// A scripted command, parsed up front so a typo fails at start rather than mid-run.
+struct Scheduled {
+    line: String,
+    command: Command,
+    /// Seconds since start it is next due
+    due: u64,
+    every: Option<u64>,
+}
+
+pub struct MockOrchestrator {
+    orchestrator: Orchestrator,
+    schedule: Vec<Scheduled>,
+}
+
+impl MockOrchestrator {
+    pub fn new(id: &str, transport: Arc<dyn Transport>, mesh_key: Option<MeshKey>, script: Script) -> Result<Self> {
+        let mut schedule = Vec::new();
+        for scripted in script.commands {
+            let command = commands::parse(&scripted.command, chrono::Utc::now().timestamp())
+                .with_context(|| format!("scripted command `{}`", scripted.command))?;
+            if !matches!(command, Command::Issue { .. } | Command::IssueZone { .. } | Command::WhoIsThere | Command::Takeover) {
+                bail!("scripted command `{}` sends nothing to the nodes", scripted.command);
+            }
+            if scripted.every_secs == Some(0) {
+                bail!("scripted command `{}` can't repeat every 0 seconds", scripted.command);
+            }
+            schedule.push(Scheduled {
+                line: scripted.command,
+                command,
+                due: scripted.after_secs.unwrap_or(0),
+                every: scripted.every_secs,
+            });
+        }
+        Ok(Self { orchestrator: Orchestrator::new(id, transport, mesh_key, Fleet::default()), schedule })
+    }
+
+    /// Seconds since start the next scripted command is due, if any are left.
+    pub fn next_due(&self) -> Option<u64> {
+        self.schedule.iter().map(|s| s.due).min()
+    }
+
+    /// Send every scripted command due by `elapsed`, rescheduling the repeating ones.
+    pub async fn send_due(&mut self, elapsed: Duration) {
+        let elapsed = elapsed.as_secs();
+        for i in 0..self.schedule.len() {
+            if self.schedule[i].due > elapsed {
+                continue;
+            }
+            let scheduled = &self.schedule[i];
+            info!("Script: {}", scheduled.line);
+            let result = match &scheduled.command {
+                Command::Issue { target, payload } => self.orchestrator.issue(target, payload.clone()).await,
+                Command::IssueZone { zone, payload } => self.orchestrator.issue_zone(zone, payload.clone()).await,
+                Command::WhoIsThere => self.orchestrator.who_is_there().await,
+                Command::Takeover => self.orchestrator.announce_takeover().await,
+                _ => unreachable!("checked when the script was loaded"),
+            };
+            if let Err(e) = result {
+                warn!("Script: `{}` failed: {}", self.schedule[i].line, e);
+            }
+            let scheduled = &mut self.schedule[i];
+            scheduled.due = match scheduled.every {
+                Some(every) => scheduled.due + every,
+                None => u64::MAX,
+            };
+        }
+        self.schedule.retain(|s| s.due != u64::MAX);
+    }
+
+    /// Act on a message from a node: register it (acknowledging its FeatureReport) and
+    /// answer its heartbeats, so it believes an orchestrator is up.
+    pub async fn handle(&mut self, msg: NeighborhoodMessage) {
+        let heartbeat = match &msg.payload {
+            Some(Payload::Heartbeat(hb)) => Some(hb.node_id.clone()),
+            _ => None,
+        };
+        self.orchestrator.handle(msg).await;
+        if let Some(node_id) = heartbeat {
+            // Nothing that never authenticated is in the registry to answer
+            if self.orchestrator.fleet.lock().unwrap().get(&node_id).is_none() {
+                return;
+            }
+            if let Err(e) = self.orchestrator.announce_alive().await {
+                warn!("Failed to answer heartbeat from {}: {}", node_id, e);
+            }
+        }
+    }
+
+    /// Serve the nodes and run the script until the transport closes.
+    pub async fn run(&mut self) -> Result<()> {
+        let started = Instant::now();
+        // Nodes that came up before us register again
+        self.orchestrator.who_is_there().await?;
+        loop {
+            let next = self.next_due().map(|secs| started + Duration::from_secs(secs));
+            tokio::select! {
+                _ = tokio::time::sleep_until(next.unwrap_or(started)), if next.is_some() => {
+                    self.send_due(started.elapsed()).await;
+                }
+                msg = self.orchestrator.receive() => match msg {
+                    Some(msg) => self.handle(msg).await,
+                    None => return Ok(()),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+    use crate::transport::streetgrid::{FeatureReport, Heartbeat, MessageAuth};
+    use crate::transport::MockTransport;
+
+    fn signed(payload: Payload) -> NeighborhoodMessage {
+        let mut msg = NeighborhoodMessage { payload: Some(payload), ..Default::default() };
+        msg.auth = Some(MessageAuth { key_epoch: 1, mac: MeshKey::new(1, [7; 32]).sign(&msg.encode_to_vec()) });
+        msg
+    }
+
+    fn script(yaml: &str) -> Script {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_registers_nodes_and_answers_heartbeats() {
+        let transport = Arc::new(MockTransport::new());
+        let mut mock = MockOrchestrator::new("orchestrator", transport.clone(), Some(MeshKey::new(1, [7; 32])), Script::default()).unwrap();
+
+        mock.handle(signed(Payload::FeatureReport(FeatureReport { node_id: "node_01".to_string(), ..Default::default() }))).await;
+        mock.handle(signed(Payload::Heartbeat(Heartbeat { node_id: "node_01".to_string(), ..Default::default() }))).await;
+        // Unsigned: not one of ours
+        let forged = NeighborhoodMessage {
+            payload: Some(Payload::Heartbeat(Heartbeat { node_id: "node_02".to_string(), ..Default::default() })),
+            ..Default::default()
+        };
+        mock.handle(forged).await;
+
+        let sent = transport.sent();
+        assert_eq!(sent.len(), 2);
+        assert!(matches!(&sent[0], (Some(target), NeighborhoodMessage { payload: Some(Payload::RegistrationAck(ack)), .. })
+            if target == "node_01" && ack.target_node_id == "node_01"));
+        assert!(matches!(&sent[1], (None, NeighborhoodMessage { payload: Some(Payload::Coordination(c)), auth: Some(_), .. })
+            if c.node_id == "orchestrator"));
+    }
+
+    #[tokio::test]
+    async fn test_scripted_commands_are_sent_when_due() {
+        let transport = Arc::new(MockTransport::new());
+        let script = script(r#"
+commands:
+  - command: "shed node_01"
+    after_secs: 5
+  - command: "activate node_01 2"
+    every_secs: 10
+"#);
+        let mut mock = MockOrchestrator::new("orchestrator", transport.clone(), None, script).unwrap();
+        assert_eq!(mock.next_due(), Some(0));
+
+        mock.send_due(Duration::from_secs(0)).await;
+        assert_eq!(mock.next_due(), Some(5));
+        mock.send_due(Duration::from_secs(7)).await;
+        assert_eq!(mock.next_due(), Some(10));
+        mock.send_due(Duration::from_secs(21)).await;
+
+        let payloads: Vec<&str> = transport.sent().iter().map(|(_, msg)| match &msg.payload {
+            Some(Payload::LoadShed(_)) => "shed",
+            Some(Payload::ActivateRelayByIndex(_)) => "activate",
+            other => panic!("unexpected {:?}", other),
+        }).collect();
+        assert_eq!(payloads, vec!["activate", "shed", "activate"]);
+        // Sent once however late, and due again straight away to catch up
+        assert_eq!(mock.next_due(), Some(20));
+    }
+
+    #[test]
+    fn test_script_rejects_console_only_commands() {
+        let transport = Arc::new(MockTransport::new());
+        for yaml in [
+            "commands: [{command: \"nodes\"}]",
+            "commands: [{command: \"shed\"}]",
+            "commands: [{command: \"shed node_01\", every_secs: 0}]",
+        ] {
+            assert!(MockOrchestrator::new("orchestrator", transport.clone(), None, script(yaml)).is_err(), "{}", yaml);
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use anyhow::{bail, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use crate::api::{bearer_token, read_request_body, read_request_head, parse_request_line, token_matches, write_response};
+use crate::fleet::Fleet;
+use crate::orchestrator::RemoteCommand;
+use crate::transport::streetgrid::neighborhood_message::Payload;
+use crate::transport::streetgrid::northbound::issue_command_request::Target;
+use crate::transport::streetgrid::{DemandResponse, DemandResponseAck};
+
+/// Finished events are kept this long for the utility to collect their outcome
+const EVENT_RETENTION_SECS: i64 = 7 * 24 * 3600;
+
+/// Load tier an OpenADR simple signal level sheds down to: moderate sheds low priority
+/// loads, high adds medium, special adds high. Critical loads are never shed.
+fn shed_priority(signal_level: u32) -> Option<i32> {
+    match signal_level {
+        0 => None,
+        1 => Some(3),
+        2 => Some(2),
+        _ => Some(1),
+    }
+}
+
+/// A demand-response event as the utility's VTN posts it to us, reduced to the parts
+/// of an oadrEvent we act on.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct EventNotice {
+    pub event_id: String,
+    /// Bumped by the utility each time it changes the event
+    #[serde(default)]
+    pub modification_number: u32,
+    /// SIMPLE signal: 1 moderate, 2 high, 3 special
+    pub signal_level: u32,
+    /// Unix seconds
+    pub start: i64,
+    pub duration_secs: i64,
+    /// Zones the event applies to; every zone if empty
+    #[serde(default)]
+    pub zones: Vec<String>,
+    #[serde(default)]
+    pub cancelled: bool,
+    /// Most each site may draw while the event runs, if the program caps it
+    #[serde(default)]
+    pub max_load_kw: Option<f32>,
+}
+
+/// What a node told us about an event.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeResponse {
+    /// Sent, not acknowledged yet
+    Pending,
+    OptedIn { shed_kw: f32 },
+    OptedOut,
+}
+
+/// An event we relayed, with each node's answer, for reporting back to the utility.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DrEvent {
+    pub event_id: String,
+    pub modification_number: u32,
+    pub signal_level: u32,
+    pub start: i64,
+    pub end: i64,
+    pub zones: Vec<String>,
+    pub cancelled: bool,
+    pub nodes: BTreeMap<String, NodeResponse>,
+    /// Load the nodes that opted in said they will shed
+    pub committed_kw: f32,
+}
+
+/// Demand-response events received from the utility, shared between the webhook and
+/// the orchestrator loop that records the nodes' acknowledgments.
+#[derive(Debug, Default)]
+pub struct DrEvents {
+    events: BTreeMap<String, DrEvent>,
+}
+
+impl DrEvents {
+    /// Record a new or modified event going to `members` and build the command that
+    /// carries it to them. Stale modifications are refused.
+    pub fn receive(&mut self, notice: &EventNotice, zones: Vec<String>, members: BTreeSet<String>, now: i64) -> Result<Payload> {
+        self.events.retain(|_, e| e.end + EVENT_RETENTION_SECS > now);
+        let Some(priority) = shed_priority(notice.signal_level).or(notice.cancelled.then_some(0)) else {
+            bail!("signal level 0 asks for nothing to be shed");
+        };
+        if notice.duration_secs <= 0 && !notice.cancelled {
+            bail!("event {} has no duration", notice.event_id);
+        }
+        let previous = self.events.get(&notice.event_id);
+        if previous.is_some_and(|p| p.modification_number > notice.modification_number) {
+            bail!("event {} is already at modification {}", notice.event_id, previous.unwrap().modification_number);
+        }
+        let nodes = match previous {
+            // Keep the answers to what is being withdrawn
+            Some(previous) if notice.cancelled => previous.nodes.clone(),
+            // A node that opted out stays out; others answer the modification afresh
+            _ => {
+                let mut nodes: BTreeMap<String, NodeResponse> = members.into_iter().map(|n| (n, NodeResponse::Pending)).collect();
+                for (node_id, response) in previous.iter().flat_map(|p| &p.nodes) {
+                    if *response == NodeResponse::OptedOut {
+                        nodes.insert(node_id.clone(), NodeResponse::OptedOut);
+                    }
+                }
+                nodes
+            }
+        };
+        let event = DrEvent {
+            event_id: notice.event_id.clone(),
+            modification_number: notice.modification_number,
+            signal_level: notice.signal_level,
+            start: notice.start,
+            end: notice.start + notice.duration_secs.max(0),
+            zones,
+            cancelled: notice.cancelled,
+            nodes,
+            committed_kw: 0.0,
+        };
+        let command = Payload::DemandResponse(DemandResponse {
+            target_node_id: String::new(),
+            event_id: event.event_id.clone(),
+            shed_priority: priority,
+            start: event.start,
+            end: event.end,
+            cancel: event.cancelled,
+            max_watts: notice.max_load_kw.map_or(0.0, |kw| kw * 1000.0),
+            strategy: String::new(),
+        });
+        self.events.insert(event.event_id.clone(), event);
+        Ok(command)
+    }
+
+    /// Note a node's answer to an event.
+    pub fn acknowledge(&mut self, ack: &DemandResponseAck) {
+        let Some(event) = self.events.get_mut(&ack.event_id) else {
+            debug!("{}: acknowledged unknown demand-response event {}", ack.node_id, ack.event_id);
+            return;
+        };
+        if event.cancelled {
+            return;
+        }
+        let response = if ack.opted_out { NodeResponse::OptedOut } else { NodeResponse::OptedIn { shed_kw: ack.shed_watts / 1000.0 } };
+        event.nodes.insert(ack.node_id.clone(), response);
+        event.committed_kw = event.nodes.values()
+            .map(|r| match r {
+                NodeResponse::OptedIn { shed_kw } => *shed_kw,
+                _ => 0.0,
+            })
+            .sum();
+    }
+
+    pub fn get(&self, event_id: &str) -> Option<&DrEvent> {
+        self.events.get(event_id)
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = &DrEvent> {
+        self.events.values()
+    }
+}
+
+/// The answer to a posted event, in the spirit of an oadrCreatedEvent: whether the
+/// street takes part, and how many nodes it went to.
+#[derive(Debug, Serialize)]
+struct Created<'a> {
+    event_id: &'a str,
+    modification_number: u32,
+    opt: &'static str,
+    nodes: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Failure {
+    error: String,
+}
+
+/// VEN-style webhook for the utility's demand-response server. Routes:
+/// - `POST /events` — an event (EventNotice as JSON); relayed to the nodes of its zones
+/// - `GET /events` — every event held, with each node's acknowledgment or opt-out
+/// - `GET /events/<id>` — one event
+///
+/// When a token is configured, requests must carry `Authorization: Bearer <token>`;
+/// without one the webhook only listens on loopback.
+pub struct OpenAdrWebhook {
+    listener: TcpListener,
+    token: Option<String>,
+    fleet: Arc<Mutex<Fleet>>,
+    events: Arc<Mutex<DrEvents>>,
+    commands: mpsc::Sender<RemoteCommand>,
+}
+
+impl OpenAdrWebhook {
+    pub async fn bind(
+        addr: &str,
+        token: Option<String>,
+        fleet: Arc<Mutex<Fleet>>,
+        events: Arc<Mutex<DrEvents>>,
+        commands: mpsc::Sender<RemoteCommand>,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        if token.is_none() {
+            if !listener.local_addr()?.ip().is_loopback() {
+                bail!("OpenADR webhook on {} needs a token: without one anyone who can reach it can shed load", addr);
+            }
+            warn!("OpenADR webhook has no token configured: any local process can shed load");
+        }
+        let webhook = Self { listener, token, fleet, events, commands };
+        info!("OpenADR webhook listening on {}", webhook.local_addr()?);
+        Ok(webhook)
+    }
+
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serve the utility's requests one at a time; events are rare.
+    pub async fn run(self) {
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, peer)) => {
+                    if let Err(e) = self.handle_connection(stream).await {
+                        debug!("OpenADR connection from {} closed: {}", peer, e);
+                    }
+                }
+                Err(e) => warn!("OpenADR accept failed: {}", e),
+            }
+        }
+    }
+
+    async fn handle_connection(&self, mut stream: TcpStream) -> Result<()> {
+        let head = read_request_head(&mut stream).await?;
+        let (method, path) = parse_request_line(&head).ok_or_else(|| anyhow::anyhow!("malformed request line"))?;
+        if let Some(token) = &self.token {
+            if !bearer_token(&head).is_some_and(|presented| token_matches(presented, token)) {
+                return write_response(&mut stream, "401 Unauthorized", "text/plain", "unauthorized\n").await;
+            }
+        }
+        let (status, body) = match (method, path.trim_end_matches('/')) {
+            ("POST", "/events") => {
+                let body = read_request_body(&mut stream, &head).await?;
+                match serde_json::from_slice::<EventNotice>(&body) {
+                    Ok(notice) => match self.relay(&notice).await {
+                        Ok(nodes) => {
+                            let created = Created { event_id: &notice.event_id, modification_number: notice.modification_number, opt: "optIn", nodes };
+                            ("200 OK", serde_json::to_string(&created)?)
+                        }
+                        Err(e) => ("409 Conflict", serde_json::to_string(&Failure { error: e.to_string() })?),
+                    },
+                    Err(e) => ("400 Bad Request", serde_json::to_string(&Failure { error: e.to_string() })?),
+                }
+            }
+            ("GET", "/events") => ("200 OK", serde_json::to_string(&self.events.lock().unwrap().events().collect::<Vec<_>>())?),
+            ("GET", route) => match route.strip_prefix("/events/").and_then(|id| self.events.lock().unwrap().get(id).cloned()) {
+                Some(event) => ("200 OK", serde_json::to_string(&event)?),
+                None => return write_response(&mut stream, "404 Not Found", "text/plain", "not found\n").await,
+            },
+            _ => return write_response(&mut stream, "404 Not Found", "text/plain", "not found\n").await,
+        };
+        write_response(&mut stream, status, "application/json", &body).await
+    }
+
+    /// Record the event and send it to every zone it covers; returns how many nodes
+    /// it went to.
+    async fn relay(&self, notice: &EventNotice) -> Result<usize> {
+        let mut zones = self.fleet.lock().unwrap().zones();
+        if !notice.zones.is_empty() {
+            zones.retain(|zone, _| notice.zones.contains(zone));
+        }
+        if zones.is_empty() {
+            bail!("no node has reported being in any of the event's zones");
+        }
+        let members: BTreeSet<String> = zones.values().flatten().cloned().collect();
+        let nodes = members.len();
+        let now = chrono::Utc::now().timestamp();
+        let command = self.events.lock().unwrap().receive(notice, zones.keys().cloned().collect(), members, now)?;
+        info!(
+            "Demand-response event {} (modification {}, level {}{}) for {} nodes",
+            notice.event_id, notice.modification_number, notice.signal_level,
+            if notice.cancelled { ", cancelled" } else { "" }, nodes
+        );
+        for zone in zones.into_keys() {
+            let (reply, result) = oneshot::channel();
+            let client = "OpenADR VTN".to_string();
+            let command = RemoteCommand { target: Target::Zone(zone.clone()), payload: command.clone(), client, reply };
+            if self.commands.send(command).await.is_err() {
+                bail!("orchestrator is shutting down");
+            }
+            if let Ok(Err(e)) = result.await {
+                warn!("Failed to send demand-response event {} to zone {}: {}", notice.event_id, zone, e);
+            }
+        }
+        Ok(nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notice(modification_number: u32) -> EventNotice {
+        EventNotice {
+            event_id: "evt_42".to_string(),
+            modification_number,
+            signal_level: 2,
+            start: 1000,
+            duration_secs: 3600,
+            zones: vec!["block_1".to_string()],
+            cancelled: false,
+            max_load_kw: Some(4.5),
+        }
+    }
+
+    fn ack(node_id: &str, opted_out: bool, shed_watts: f32) -> DemandResponseAck {
+        DemandResponseAck { node_id: node_id.to_string(), event_id: "evt_42".to_string(), opted_out, shed_watts }
+    }
+
+    #[test]
+    fn test_events_track_acknowledgments_and_opt_outs() {
+        let mut events = DrEvents::default();
+        let members = || BTreeSet::from(["node_01".to_string(), "node_02".to_string(), "node_03".to_string()]);
+        let command = events.receive(&notice(0), vec!["block_1".to_string()], members(), 500).unwrap();
+        let Payload::DemandResponse(dr) = command else { panic!("expected a demand-response command") };
+        assert_eq!((dr.shed_priority, dr.start, dr.end, dr.cancel, dr.max_watts), (2, 1000, 4600, false, 4500.0));
+
+        events.acknowledge(&ack("node_01", false, 2400.0));
+        events.acknowledge(&ack("node_02", true, 0.0));
+        let event = events.get("evt_42").unwrap();
+        assert_eq!(event.nodes["node_01"], NodeResponse::OptedIn { shed_kw: 2.4 });
+        assert_eq!(event.nodes["node_02"], NodeResponse::OptedOut);
+        assert_eq!(event.nodes["node_03"], NodeResponse::Pending);
+        assert_eq!(event.committed_kw, 2.4);
+
+        // A modification is answered afresh, except by those who opted out
+        assert!(events.receive(&notice(2), vec![], members(), 600).is_ok());
+        assert!(events.receive(&notice(1), vec![], members(), 600).is_err());
+        let event = events.get("evt_42").unwrap();
+        assert_eq!((event.nodes["node_01"].clone(), event.nodes["node_02"].clone()), (NodeResponse::Pending, NodeResponse::OptedOut));
+
+        let cancel = EventNotice { cancelled: true, ..notice(3) };
+        let Payload::DemandResponse(dr) = events.receive(&cancel, vec![], members(), 700).unwrap() else { panic!() };
+        assert!(dr.cancel);
+        assert!(events.receive(&EventNotice { event_id: "evt_43".to_string(), signal_level: 0, ..notice(0) }, vec![], members(), 700).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_needs_a_token_off_loopback() {
+        let fleet = Arc::new(Mutex::new(Fleet::default()));
+        let events = Arc::new(Mutex::new(DrEvents::default()));
+        let (commands, _) = mpsc::channel(1);
+        assert!(OpenAdrWebhook::bind("0.0.0.0:0", None, fleet.clone(), events.clone(), commands.clone()).await.is_err());
+        OpenAdrWebhook::bind("127.0.0.1:0", None, fleet.clone(), events.clone(), commands.clone()).await.unwrap();
+        OpenAdrWebhook::bind("0.0.0.0:0", Some("secret".to_string()), fleet, events, commands).await.unwrap();
+    }
+}
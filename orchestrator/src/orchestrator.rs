@@ -0,0 +1,563 @@
+use anyhow::{bail, Context, Result};
+use log::{error, info, warn};
+use prost::Message;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, oneshot};
+use crate::blackstart::FleetBlackStart;
+use crate::fleet::{reporting_node, Fleet};
+use crate::keys::MeshKey;
+use crate::liveness::{Event, LivenessMonitor};
+use crate::openadr::DrEvents;
+use crate::phases::{self, DEFAULT_TOLERANCE_AMPS};
+use crate::rebalance::Rebalancer;
+use crate::settlement::Settlement;
+use crate::timeline::Timeline;
+use crate::topology::Topology;
+use crate::transport::streetgrid::neighborhood_message::Payload;
+use crate::transport::streetgrid::{
+    Coordination, CounterReset, EnergyEntry, EnergyLedgerRequest, MessageAuth, OrchestratorTakeover, RebalanceDirective,
+    RegistrationAck, ReplayDesync, SnapshotData, SnapshotRestore, WhoIsThere,
+};
+use crate::transport::streetgrid::northbound::issue_command_request::Target;
+use crate::transport::{NeighborhoodMessage, Transport};
+
+/// Uplink messages buffered for each telemetry subscriber before it misses some
+const TELEMETRY_BUFFER: usize = 256;
+
+/// Commands from API clients waiting for the orchestrator loop
+pub const REMOTE_COMMAND_QUEUE: usize = 32;
+
+/// A command an external client (gRPC, SCADA) asked us to issue; the orchestrator
+/// loop signs and sends it, then answers on `reply`.
+#[derive(Debug)]
+pub struct RemoteCommand {
+    pub target: Target,
+    pub payload: Payload,
+    /// Who asked, for the log
+    pub client: String,
+    pub reply: oneshot::Sender<Result<()>>,
+}
+
+/// `Coordination.kind` values we send or act on
+const KIND_COORDINATOR: u32 = 1;
+const KIND_ORCHESTRATOR_ALIVE: u32 = 3;
+
+/// Coordinator at the other end of the protocol: tracks the fleet and issues
+/// signed commands to nodes.
+pub struct Orchestrator {
+    /// Sender ID nodes track our command counter under
+    pub id: String,
+    transport: Arc<dyn Transport>,
+    /// Mesh key; without one, commands go out unsigned and nothing is verified
+    mesh_key: Option<MeshKey>,
+    /// Last command counter used
+    counter: u64,
+    /// Node registry, shared with the API
+    pub fleet: Arc<Mutex<Fleet>>,
+    /// Who hears whom, from NeighborReports
+    pub topology: Arc<Mutex<Topology>>,
+    /// Last snapshot pulled from each node, for seeding replacement hardware
+    snapshots: HashMap<String, SnapshotData>,
+    /// Energy exchanged between households during island events
+    pub settlement: Settlement,
+    /// Load shifts between nodes awaiting confirmation
+    pub rebalancer: Rebalancer,
+    /// Stale and down node detection
+    pub liveness: LivenessMonitor,
+    /// Zone-by-zone black start in progress, if any
+    pub blackstart: Option<FleetBlackStart>,
+    /// Utility demand-response events and the nodes' answers, shared with the webhook
+    pub demand_response: Arc<Mutex<DrEvents>>,
+    /// Alarms, liveness changes and commands, for dashboards
+    pub timeline: Arc<Mutex<Timeline>>,
+    /// Every authenticated uplink message, for streaming to API clients
+    pub telemetry: broadcast::Sender<NeighborhoodMessage>,
+}
+
+impl Orchestrator {
+    pub fn new(id: &str, transport: Arc<dyn Transport>, mesh_key: Option<MeshKey>, fleet: Fleet) -> Self {
+        Self {
+            id: id.to_string(),
+            transport,
+            mesh_key,
+            counter: 0,
+            fleet: Arc::new(Mutex::new(fleet)),
+            topology: Arc::new(Mutex::new(Topology::default())),
+            snapshots: HashMap::new(),
+            settlement: Settlement::default(),
+            rebalancer: Rebalancer::default(),
+            liveness: LivenessMonitor::default(),
+            blackstart: None,
+            demand_response: Arc::new(Mutex::new(DrEvents::default())),
+            timeline: Arc::new(Mutex::new(Timeline::default())),
+            telemetry: broadcast::channel(TELEMETRY_BUFFER).0,
+        }
+    }
+
+    pub fn transport_name(&self) -> &'static str {
+        self.transport.name()
+    }
+
+    /// Next command counter. Derived from the clock so counters keep rising across
+    /// restarts without having to be persisted.
+    fn next_counter(&mut self) -> u64 {
+        let now_millis = chrono::Utc::now().timestamp_millis().max(0) as u64;
+        self.counter = (self.counter + 1).max(now_millis);
+        self.counter
+    }
+
+    fn sign(&self, msg: &mut NeighborhoodMessage) {
+        if let Some(key) = &self.mesh_key {
+            let mac = key.sign(&msg.encode_to_vec());
+            msg.auth = Some(MessageAuth { key_epoch: key.epoch, mac });
+        }
+    }
+
+    /// Sign and send a command to one node.
+    pub async fn issue(&mut self, target: &str, payload: Payload) -> Result<()> {
+        let mut msg = NeighborhoodMessage {
+            payload: Some(payload),
+            sender_id: self.id.clone(),
+            counter: self.next_counter(),
+            ..Default::default()
+        };
+        self.sign(&mut msg);
+        self.transport.send(Some(target), msg).await
+    }
+
+    /// Sign and broadcast a command to every node in `zone`.
+    pub async fn issue_zone(&mut self, zone: &str, payload: Payload) -> Result<()> {
+        let members = self.fleet.lock().unwrap().zones().remove(zone).unwrap_or_default();
+        if members.is_empty() {
+            bail!("no node has reported being in zone {}", zone);
+        }
+        info!("Addressing {} nodes in zone {}", members.len(), zone);
+        let mut msg = NeighborhoodMessage {
+            payload: Some(payload),
+            sender_id: self.id.clone(),
+            counter: self.next_counter(),
+            target_zone: zone.to_string(),
+            ..Default::default()
+        };
+        self.sign(&mut msg);
+        self.transport.send(None, msg).await
+    }
+
+    /// Ask every node to resend its FeatureReport, e.g. after we restarted.
+    pub async fn who_is_there(&mut self) -> Result<()> {
+        let mut msg = NeighborhoodMessage {
+            payload: Some(Payload::WhoIsThere(WhoIsThere { timestamp: chrono::Utc::now().timestamp() })),
+            sender_id: self.id.clone(),
+            counter: self.next_counter(),
+            ..Default::default()
+        };
+        self.sign(&mut msg);
+        self.transport.send(None, msg).await
+    }
+
+    /// Broadcast that we are alive, so nodes don't elect a coordinator of their own.
+    pub async fn announce_alive(&self) -> Result<()> {
+        let mut msg = NeighborhoodMessage {
+            payload: Some(Payload::Coordination(Coordination {
+                node_id: self.id.clone(),
+                kind: KIND_ORCHESTRATOR_ALIVE,
+                timestamp: chrono::Utc::now().timestamp(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        self.sign(&mut msg);
+        self.transport.send(None, msg).await
+    }
+
+    /// As the standby orchestrator, tell every node to follow us from now on. Nodes only
+    /// honour it if we are their configured secondary and the announcement is signed.
+    pub async fn announce_takeover(&mut self) -> Result<()> {
+        let mut msg = NeighborhoodMessage {
+            payload: Some(Payload::OrchestratorTakeover(OrchestratorTakeover {
+                orchestrator_id: self.id.clone(),
+                timestamp: chrono::Utc::now().timestamp(),
+            })),
+            sender_id: self.id.clone(),
+            counter: self.next_counter(),
+            ..Default::default()
+        };
+        if self.mesh_key.is_none() {
+            warn!("No mesh key: nodes will ignore an unsigned takeover");
+        }
+        self.sign(&mut msg);
+        info!("Announcing takeover as {}", self.id);
+        self.transport.send(None, msg).await
+    }
+
+    /// Seed `target` with the snapshot last pulled from `from`.
+    pub async fn restore_snapshot(&mut self, from: &str, target: &str) -> Result<()> {
+        let snapshot = self.snapshots.get(from).cloned()
+            .with_context(|| format!("no snapshot from {} yet (pull one with: snapshot {})", from, from))?;
+        self.issue(target, Payload::SnapshotRestore(SnapshotRestore {
+            target_node_id: target.to_string(),
+            snapshot: Some(snapshot),
+        })).await
+    }
+
+    /// Shift load from one node to another: `from` opens `shed`, then `to` closes `restore`.
+    pub async fn rebalance(&mut self, from: &str, shed: Vec<String>, to: &str, restore: Vec<String>) -> Result<()> {
+        let directive = self.rebalancer.start(from, shed, to, restore, chrono::Utc::now().timestamp())?;
+        info!("Rebalance {}: shedding on {}", directive.directive_id, from);
+        self.send_rebalance(vec![directive]).await;
+        Ok(())
+    }
+
+    /// Even out the transformer phases (across the fleet, or one zone) by shedding a load
+    /// on the heaviest and restoring one on the lightest, as a confirmed rebalance.
+    pub async fn balance_phases(&mut self, zone: Option<&str>) -> Result<()> {
+        let step = {
+            let fleet = self.fleet.lock().unwrap();
+            let nodes: Vec<_> = fleet.nodes()
+                .filter(|n| !n.liveness.is_down())
+                .filter(|n| zone.is_none_or(|z| n.zones.iter().any(|nz| nz == z)))
+                .collect();
+            phases::plan_move(&nodes, DEFAULT_TOLERANCE_AMPS)
+        };
+        let Some(step) = step else {
+            info!("Phases are within {:.0} A of each other, or no load can be moved", DEFAULT_TOLERANCE_AMPS);
+            return Ok(());
+        };
+        info!(
+            "Phase imbalance {:.0} A -> {:.0} A: shedding {} on {}, restoring {} on {}",
+            step.imbalance.0, step.imbalance.1, step.shed, step.from, step.restore, step.to
+        );
+        self.rebalance(&step.from, vec![step.shed], &step.to, vec![step.restore]).await
+    }
+
+    /// Roll back rebalances a node never confirmed.
+    pub async fn expire_rebalances(&mut self) {
+        let directives = self.rebalancer.expire(chrono::Utc::now().timestamp());
+        self.send_rebalance(directives).await;
+    }
+
+    async fn send_rebalance(&mut self, directives: Vec<RebalanceDirective>) {
+        for directive in directives {
+            let target = directive.target_node_id.clone();
+            if let Err(e) = self.issue(&target, Payload::RebalanceDirective(directive)).await {
+                error!("Failed to send rebalance directive to {}: {}", target, e);
+            }
+        }
+    }
+
+    /// Alert on nodes that stopped heartbeating, and ask their neighbours to look for them.
+    pub async fn check_liveness(&mut self) {
+        let now = chrono::Utc::now().timestamp();
+        let (events, probes) = {
+            let mut fleet = self.fleet.lock().unwrap();
+            let topology = self.topology.lock().unwrap();
+            self.liveness.check(&mut fleet, &topology, now)
+        };
+        for event in events {
+            let (node_id, message) = match event {
+                Event::Stale { node_id, silent_secs } => {
+                    warn!("{}: no heartbeat for {}s; marked stale", node_id, silent_secs);
+                    (node_id, format!("no heartbeat for {}s", silent_secs))
+                }
+                Event::Unreachable { node_id, heard_by } => {
+                    error!("{}: unreachable, though {} still hears it over the mesh", node_id, heard_by);
+                    (node_id, format!("unreachable, though {} still hears it", heard_by))
+                }
+                Event::Down { node_id } => {
+                    error!("{}: down (no neighbour could reach it)", node_id);
+                    (node_id, "down".to_string())
+                }
+                Event::Recovered { node_id } => {
+                    info!("{}: back online", node_id);
+                    (node_id, "back online".to_string())
+                }
+            };
+            self.timeline.lock().unwrap().record(now, Some(&node_id), "liveness", message);
+        }
+        for probe in probes {
+            let target = probe.target_node_id.clone();
+            info!("Asking {} to look for {}", target, probe.silent_node_id);
+            if let Err(e) = self.issue(&target, Payload::LivenessProbe(probe)).await {
+                error!("Failed to send liveness probe to {}: {}", target, e);
+            }
+        }
+    }
+
+    /// Plan and begin a zone-by-zone black start.
+    pub async fn start_blackstart(&mut self, zones: &[String]) -> Result<()> {
+        if self.blackstart.as_ref().is_some_and(|b| !b.is_finished()) {
+            bail!("a fleet black start is already in progress (fleet-blackstart-abort to drop it)");
+        }
+        self.blackstart = Some(FleetBlackStart::plan(&self.fleet.lock().unwrap(), zones)?);
+        self.advance_blackstart().await;
+        Ok(())
+    }
+
+    /// Send the fleet black start's next stage once the last one is confirmed.
+    pub async fn advance_blackstart(&mut self) {
+        let Some(blackstart) = &mut self.blackstart else { return };
+        let commands = blackstart.advance(chrono::Utc::now().timestamp());
+        if commands.is_empty() && blackstart.is_finished() {
+            info!("Fleet black start complete");
+            self.blackstart = None;
+            return;
+        }
+        for (target, payload) in commands {
+            if let Err(e) = self.issue(&target, payload).await {
+                error!("Failed to send black start step to {}: {}", target, e);
+            }
+        }
+    }
+
+    /// Wait for the next message from any node.
+    pub async fn receive(&self) -> Option<NeighborhoodMessage> {
+        self.transport.receive().await
+    }
+
+    /// Authenticate an uplink message and act on it.
+    pub async fn handle(&mut self, mut msg: NeighborhoodMessage) {
+        let auth = msg.auth.take();
+        let Some(payload) = msg.payload.take() else { return };
+        if let Some(key) = &self.mesh_key {
+            // Re-encode without auth or the repeaters' hop count, as it was when the node signed it
+            let signed = NeighborhoodMessage { payload: Some(payload.clone()), relay_hops: 0, ..msg.clone() };
+            let valid = auth.is_some_and(|a| key.verify(a.key_epoch, &signed.encode_to_vec(), &a.mac));
+            // Join requests predate the node's keys
+            if !valid && !matches!(payload, Payload::JoinRequest(_)) {
+                warn!("Dropping unauthenticated message from {}", reporting_node(&payload).unwrap_or("unknown node"));
+                return;
+            }
+        }
+        let previous_mid = match &payload {
+            Payload::MidStatus(status) => self.fleet.lock().unwrap().get(&status.mid_id).and_then(|n| n.mid.clone()),
+            _ => None,
+        };
+        let previous_orchestrator = match &payload {
+            Payload::Heartbeat(hb) => self.fleet.lock().unwrap().get(&hb.node_id).and_then(|n| n.orchestrator.clone()),
+            _ => None,
+        };
+        let now = chrono::Utc::now().timestamp();
+        self.fleet.lock().unwrap().observe(&payload, now);
+        if let Some((kind, message)) = notable(&payload) {
+            self.timeline.lock().unwrap().record(now, reporting_node(&payload), kind, message);
+        }
+        // After the registry, so subscribers looking a node up see what it just sent
+        if self.telemetry.receiver_count() > 0 {
+            let _ = self.telemetry.send(NeighborhoodMessage { payload: Some(payload.clone()), ..msg });
+        }
+
+        match payload {
+            Payload::VoltageAlert(alert) => warn!("{}: voltage alert {:.1} V", alert.node_id, alert.voltage),
+            Payload::Alarm(alarm) if alarm.critical => error!("{}: critical alarm {}: {}", alarm.node_id, alarm.code, alarm.message),
+            Payload::Alarm(alarm) => warn!("{}: alarm {}: {}", alarm.node_id, alarm.code, alarm.message),
+            Payload::FailedRelay(failed) => error!("{}: relay {} {} ({})", failed.node_id, failed.relay_id, failed.failure, failed.evidence),
+            Payload::TamperAlert(alert) => warn!("{}: enclosure {}", alert.node_id, if alert.opened { "opened" } else { "closed" }),
+            Payload::AnomalyAlert(alert) => warn!(
+                "{}: anomaly on channel {} ({:.1} A vs {:.1} A baseline)",
+                alert.node_id, alert.channel, alert.current_amps, alert.baseline_amps
+            ),
+            Payload::SecurityReport(report) => warn!("{}: security report {:?}", report.node_id, report),
+            Payload::OutageReport(report) => info!(
+                "{}: {} had {} grid losses and {} islands",
+                report.node_id, report.month,
+                report.grid_losses.as_ref().map_or(0, |i| i.count), report.islands.as_ref().map_or(0, |i| i.count)
+            ),
+            Payload::FirmwareStatus(status) => info!(
+                "{}: firmware {} {}",
+                status.node_id, status.version, if status.installed { "installed" } else { "rejected" }
+            ),
+            Payload::KeyRotationAck(ack) => info!("{}: key epoch {} accepted={}", ack.node_id, ack.new_epoch, ack.accepted),
+            Payload::CommandResult(result) if !result.ok => warn!(
+                "{}: {} #{} failed ({}): {}",
+                result.node_id, result.command, result.counter, result.error_code, result.error
+            ),
+            Payload::JoinRequest(request) => info!("{}: join request (firmware {})", request.node_id, request.firmware_version),
+            Payload::AuditLogUpload(upload) => {
+                for entry in &upload.entries {
+                    info!("{} audit #{}: {}", upload.node_id, entry.seq, entry.json);
+                }
+            }
+            Payload::SnapshotData(snapshot) => {
+                info!("{}: snapshot received ({} bytes)", snapshot.node_id, snapshot.payload.len());
+                self.snapshots.insert(snapshot.node_id.clone(), snapshot);
+            }
+            Payload::Coordination(c) if c.kind == KIND_COORDINATOR => {
+                warn!("{}: acting as coordinator for term {} (nodes think we're unreachable)", c.node_id, c.term)
+            }
+            Payload::EnergyEntry(entry) => self.record_energy(entry),
+            Payload::EnergyLedgerUpload(upload) => {
+                info!("{}: {} energy entries", upload.node_id, upload.entries.len());
+                let last_end = upload.entries.last().map(|e| e.period_end);
+                for entry in upload.entries {
+                    self.record_energy(entry);
+                }
+                // Keep pulling until the node has sent everything
+                if let (true, Some(since)) = (upload.more, last_end) {
+                    let request = EnergyLedgerRequest { target_node_id: upload.node_id.clone(), since };
+                    if let Err(e) = self.issue(&upload.node_id, Payload::EnergyLedgerRequest(request)).await {
+                        error!("Failed to request more energy entries: {}", e);
+                    }
+                }
+            }
+            Payload::NeighborReport(report) => self.topology.lock().unwrap().update(&report),
+            // Nodes keep resending their FeatureReport until we confirm we have it
+            Payload::FeatureReport(report) => {
+                let ack = RegistrationAck { target_node_id: report.node_id.clone() };
+                if let Err(e) = self.issue(&report.node_id, Payload::RegistrationAck(ack)).await {
+                    error!("Failed to acknowledge registration of {}: {}", report.node_id, e);
+                }
+            }
+            // Repeated every 30s; only changes are worth logging
+            Payload::MidStatus(status)
+                if previous_mid.is_none_or(|p| p.isolated != status.isolated || p.reconnect_permitted != status.reconnect_permitted) =>
+            {
+                info!(
+                    "{}: transformer {} (edge nodes may reconnect: {})",
+                    status.mid_id, if status.isolated { "isolated" } else { "connected" }, status.reconnect_permitted
+                );
+            }
+            Payload::Heartbeat(hb) if hb.shutting_down => info!("{}: shutting down; not alerting on its silence", hb.node_id),
+            Payload::Heartbeat(hb)
+                if !hb.orchestrator_id.is_empty() && previous_orchestrator.as_deref() != Some(hb.orchestrator_id.as_str()) =>
+            {
+                info!("{}: following orchestrator {}", hb.node_id, hb.orchestrator_id);
+            }
+            Payload::LivenessReport(report) => {
+                info!("{}: last heard {} at {}", report.node_id, report.silent_node_id, report.last_heard);
+                self.liveness.report(&report);
+            }
+            Payload::BlackStartAck(ack) => {
+                if !ack.ok {
+                    warn!("{}: black start step {} failed: {}", ack.node_id, ack.step_id, ack.error);
+                }
+                if let Some(blackstart) = &mut self.blackstart {
+                    blackstart.acknowledge(&ack);
+                }
+            }
+            Payload::DemandResponseAck(ack) => {
+                if ack.opted_out {
+                    info!("{}: opted out of demand-response event {}", ack.node_id, ack.event_id);
+                } else {
+                    info!("{}: shedding {:.0} W for demand-response event {}", ack.node_id, ack.shed_watts, ack.event_id);
+                }
+                self.demand_response.lock().unwrap().acknowledge(&ack);
+            }
+            Payload::RebalanceAck(ack) => {
+                let next = self.rebalancer.acknowledge(&ack, chrono::Utc::now().timestamp());
+                self.send_rebalance(next).await;
+            }
+            Payload::ReplayDesync(desync) => self.answer_desync(desync).await,
+            _ => {}
+        }
+    }
+
+    fn record_energy(&mut self, entry: EnergyEntry) {
+        if let Err(e) = self.settlement.record(entry) {
+            warn!("{}", e);
+        }
+    }
+
+    /// A node stopped accepting our counters (e.g. our clock stepped backwards); re-seat it.
+    async fn answer_desync(&mut self, desync: ReplayDesync) {
+        if desync.sender_id != self.id {
+            return;
+        }
+        warn!("{}: rejecting our counters (last accepted {}); resetting", desync.node_id, desync.last_counter);
+        let reset = CounterReset {
+            target_node_id: desync.node_id.clone(),
+            sender_id: self.id.clone(),
+            counter: self.counter + 1,
+            challenge: desync.challenge,
+        };
+        if let Err(e) = self.issue(&desync.node_id, Payload::CounterReset(reset)).await {
+            error!("Failed to send counter reset: {}", e);
+        }
+    }
+}
+
+/// Uplink messages worth a line on the timeline.
+fn notable(payload: &Payload) -> Option<(&'static str, String)> {
+    let entry = match payload {
+        Payload::VoltageAlert(alert) => ("voltage", format!("voltage alert {:.1} V", alert.voltage)),
+        Payload::VoltageSag(sag) => (
+            "voltage",
+            format!("sag to {:.1} V for {:.1} s{}", sag.min_voltage, sag.duration_secs, if sag.escalated { "" } else { ", ridden through" }),
+        ),
+        Payload::Alarm(alarm) => ("alarm", format!("{}{}: {}", if alarm.critical { "critical " } else { "" }, alarm.code, alarm.message)),
+        Payload::FailedRelay(failed) => ("relay", format!("{} {} ({})", failed.relay_id, failed.failure.replace('_', " "), failed.evidence)),
+        Payload::TamperAlert(alert) => ("tamper", format!("enclosure {}", if alert.opened { "opened" } else { "closed" })),
+        Payload::AnomalyAlert(alert) => (
+            "anomaly",
+            format!("channel {} drawing {:.1} A vs {:.1} A baseline", alert.channel, alert.current_amps, alert.baseline_amps),
+        ),
+        Payload::FirmwareStatus(status) => {
+            ("firmware", format!("firmware {} {}", status.version, if status.installed { "installed" } else { "rejected" }))
+        }
+        Payload::JoinRequest(request) => ("join", format!("asked to join (firmware {})", request.firmware_version)),
+        Payload::BlackStartAck(ack) if !ack.ok => ("blackstart", format!("black start step {} failed: {}", ack.step_id, ack.error)),
+        Payload::DemandResponseAck(ack) if ack.opted_out => ("demand_response", format!("opted out of {}", ack.event_id)),
+        Payload::DemandResponseAck(ack) => ("demand_response", format!("shedding {:.0} W for {}", ack.shed_watts, ack.event_id)),
+        Payload::CommandResult(result) if !result.ok => ("command", format!("{} failed: {}", result.command, result.error)),
+        Payload::Heartbeat(hb) if hb.shutting_down => ("liveness", "shutting down".to_string()),
+        _ => return None,
+    };
+    Some(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::streetgrid::{Heartbeat, LoadShed};
+    use crate::transport::MockTransport;
+
+    fn heartbeat(node_id: &str) -> NeighborhoodMessage {
+        NeighborhoodMessage {
+            payload: Some(Payload::Heartbeat(Heartbeat { node_id: node_id.to_string(), ..Default::default() })),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_authenticated_nodes_are_tracked() {
+        let transport = Arc::new(MockTransport::new());
+        let mut orchestrator = Orchestrator::new("orchestrator", transport.clone(), Some(MeshKey::new(1, [7; 32])), Fleet::default());
+
+        let mut signed = heartbeat("node_01");
+        signed.auth = Some(MessageAuth { key_epoch: 1, mac: MeshKey::new(1, [7; 32]).sign(&signed.encode_to_vec()) });
+        orchestrator.handle(signed).await;
+        orchestrator.handle(heartbeat("node_02")).await;
+        // Repeaters mark the frame without re-signing it
+        let mut relayed = heartbeat("node_03");
+        relayed.auth = Some(MessageAuth { key_epoch: 1, mac: MeshKey::new(1, [7; 32]).sign(&relayed.encode_to_vec()) });
+        relayed.relay_hops = 2;
+        orchestrator.handle(relayed).await;
+
+        let fleet = orchestrator.fleet.lock().unwrap();
+        assert!(fleet.get("node_01").is_some());
+        assert!(fleet.get("node_02").is_none());
+        assert!(fleet.get("node_03").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_commands_are_signed_with_rising_counters() {
+        let transport = Arc::new(MockTransport::new());
+        let key = MeshKey::new(1, [7; 32]);
+        let mut orchestrator = Orchestrator::new("orchestrator", transport.clone(), Some(MeshKey::new(1, [7; 32])), Fleet::default());
+        for _ in 0..2 {
+            let shed = Payload::LoadShed(LoadShed { target_node_id: "node_01".to_string(), shed_load: true });
+            orchestrator.issue("node_01", shed).await.unwrap();
+        }
+
+        let sent = transport.sent();
+        assert_eq!(sent.len(), 2);
+        assert!(sent[1].1.counter > sent[0].1.counter);
+        for (target, msg) in sent {
+            assert_eq!(target.as_deref(), Some("node_01"));
+            assert_eq!(msg.sender_id, "orchestrator");
+            let mut msg = msg;
+            let auth = msg.auth.take().unwrap();
+            assert!(key.verify(auth.key_epoch, &msg.encode_to_vec(), &auth.mac));
+        }
+    }
+}
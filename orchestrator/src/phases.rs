@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use streetgrid_core::types::{Priority, RelayType};
+use crate::fleet::NodeRecord;
+
+pub const PHASES: [&str; 3] = ["A", "B", "C"];
+
+/// Phases further apart than this are worth moving load for.
+pub const DEFAULT_TOLERANCE_AMPS: f32 = 10.0;
+
+/// Load switched on by `shed` on one node and `restore` on another, on different phases.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseMove {
+    pub from: String,
+    pub shed: String,
+    pub to: String,
+    pub restore: String,
+    /// Spread between the heaviest and lightest phase before and after
+    pub imbalance: (f32, f32),
+}
+
+/// Closed load on each phase, in amps, from what the nodes last reported.
+pub fn loads<'a>(nodes: impl IntoIterator<Item = &'a NodeRecord>) -> BTreeMap<&'static str, f32> {
+    let mut loads: BTreeMap<&'static str, f32> = PHASES.iter().map(|p| (*p, 0.0)).collect();
+    for node in nodes {
+        for relay in node.relays.iter().filter(|r| r.relay_type == RelayType::Load as i32 && r.is_closed) {
+            let phase = relay.phase.as_deref().or(node.phase.as_deref());
+            if let Some(load) = phase.and_then(|p| loads.get_mut(p)) {
+                *load += relay.amperage;
+            }
+        }
+    }
+    loads
+}
+
+fn imbalance(loads: &BTreeMap<&'static str, f32>) -> f32 {
+    let max = loads.values().copied().fold(f32::MIN, f32::max);
+    let min = loads.values().copied().fold(f32::MAX, f32::min);
+    max - min
+}
+
+/// The single shed/restore pair that best evens out the phases, if they are more
+/// than `tolerance_amps` apart and some pair narrows the gap. Only non-critical loads
+/// are shed, and the most important open loads are restored first.
+pub fn plan_move(nodes: &[&NodeRecord], tolerance_amps: f32) -> Option<PhaseMove> {
+    let before = loads(nodes.iter().copied());
+    let gap = imbalance(&before);
+    if gap <= tolerance_amps {
+        return None;
+    }
+    let relays = || nodes.iter().flat_map(|n| {
+        n.relays.iter()
+            .filter(|r| r.relay_type == RelayType::Load as i32)
+            .filter_map(move |r| Some((n.node_id.as_str(), r, known_phase(r.phase.as_deref().or(n.phase.as_deref())?)?)))
+    });
+    let mut best: Option<(f32, i32, PhaseMove)> = None;
+    for (from, shed, shed_phase) in relays().filter(|(_, r, _)| r.is_closed && r.priority != Priority::Critical as i32 && r.community_criticality == 0) {
+        for (to, restore, restore_phase) in relays().filter(|(_, r, _)| !r.is_closed) {
+            if from == to || shed_phase == restore_phase {
+                continue;
+            }
+            let mut after = before.clone();
+            *after.entry(shed_phase).or_default() -= shed.amperage;
+            *after.entry(restore_phase).or_default() += restore.amperage;
+            let gap_after = imbalance(&after);
+            // Prefer the flattest result, then restoring the more important load
+            let better = best.as_ref().is_none_or(|(g, p, _)| gap_after < *g || (gap_after == *g && restore.priority < *p));
+            if gap_after < gap && better {
+                best = Some((gap_after, restore.priority, PhaseMove {
+                    from: from.to_string(),
+                    shed: shed.id.clone(),
+                    to: to.to_string(),
+                    restore: restore.id.clone(),
+                    imbalance: (gap, gap_after),
+                }));
+            }
+        }
+    }
+    best.map(|(_, _, step)| step)
+}
+
+fn known_phase(phase: &str) -> Option<&'static str> {
+    PHASES.iter().find(|p| **p == phase).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fleet::RelayRecord;
+
+    fn node(node_id: &str, phase: &str, relays: Vec<RelayRecord>) -> NodeRecord {
+        NodeRecord { node_id: node_id.to_string(), phase: Some(phase.to_string()), relays, ..Default::default() }
+    }
+
+    fn load(id: &str, priority: i32, amperage: f32, is_closed: bool) -> RelayRecord {
+        RelayRecord { id: id.to_string(), relay_type: RelayType::Load as i32, priority, amperage, is_closed, ..Default::default() }
+    }
+
+    #[test]
+    fn test_load_moves_off_the_heaviest_phase() {
+        let nodes = [
+            node("node_01", "A", vec![load("r_crit", 0, 15.0, true), load("r_hvac", 2, 20.0, true), load("r_ev", 3, 30.0, true)]),
+            node("node_02", "B", vec![load("r_heat", 1, 20.0, false), load("r_aux", 3, 20.0, false)]),
+            node("node_03", "C", vec![load("r_fridge", 1, 10.0, true)]),
+        ];
+        let refs: Vec<&NodeRecord> = nodes.iter().collect();
+        assert_eq!(loads(refs.iter().copied()), BTreeMap::from([("A", 65.0), ("B", 0.0), ("C", 10.0)]));
+
+        let step = plan_move(&refs, DEFAULT_TOLERANCE_AMPS).unwrap();
+        assert_eq!((step.from.as_str(), step.shed.as_str()), ("node_01", "r_ev"));
+        assert_eq!((step.to.as_str(), step.restore.as_str()), ("node_02", "r_heat"));
+        assert_eq!(step.imbalance, (65.0, 25.0));
+
+        // Close enough already
+        assert_eq!(plan_move(&refs, 100.0), None);
+    }
+}
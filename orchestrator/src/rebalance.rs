@@ -0,0 +1,218 @@
+use anyhow::{bail, Result};
+use log::{info, warn};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::transport::streetgrid::{RebalanceAck, RebalanceDirective};
+
+/// How long a step may go unacknowledged before the rebalance is rolled back.
+pub const REBALANCE_TIMEOUT_SECS: i64 = 60;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Phase {
+    /// Waiting for the source node to open its relays
+    Shedding,
+    /// Source confirmed; waiting for the destination to close its relays
+    Restoring,
+    /// Undoing; waiting for these nodes to confirm
+    RollingBack(BTreeSet<String>),
+}
+
+/// Load moved from one node to another on the same transformer.
+#[derive(Debug, Clone)]
+struct Rebalance {
+    from: String,
+    shed: Vec<String>,
+    to: String,
+    restore: Vec<String>,
+    phase: Phase,
+    /// When the current phase started
+    since: i64,
+}
+
+/// Runs load shifts between nodes: the source sheds first, the destination restores
+/// only once the shed is confirmed, and either side failing or going quiet rolls
+/// back whatever was already switched.
+#[derive(Debug, Default)]
+pub struct Rebalancer {
+    /// Node -> transformer feeding it; empty if the operator hasn't described the feeders
+    transformer_of: HashMap<String, String>,
+    active: BTreeMap<String, Rebalance>,
+    started: u64,
+}
+
+impl Rebalancer {
+    /// `transformers` maps each transformer to the nodes it feeds.
+    pub fn new(transformers: &HashMap<String, Vec<String>>) -> Self {
+        let transformer_of = transformers.iter()
+            .flat_map(|(transformer, nodes)| nodes.iter().map(move |node| (node.clone(), transformer.clone())))
+            .collect();
+        Self { transformer_of, ..Default::default() }
+    }
+
+    /// Begin shifting load; returns the directive for the source node.
+    pub fn start(&mut self, from: &str, shed: Vec<String>, to: &str, restore: Vec<String>, now: i64) -> Result<RebalanceDirective> {
+        if from == to {
+            bail!("rebalance needs two different nodes");
+        }
+        if shed.is_empty() || restore.is_empty() {
+            bail!("rebalance needs relays to shed and relays to restore");
+        }
+        if !self.transformer_of.is_empty() {
+            match (self.transformer_of.get(from), self.transformer_of.get(to)) {
+                (Some(a), Some(b)) if a == b => {}
+                (Some(a), Some(b)) => bail!("{} is on transformer {} but {} is on {}", from, a, to, b),
+                _ => bail!("{} and {} are not both on a configured transformer", from, to),
+            }
+        }
+        if let Some((id, _)) = self.active.iter().find(|(_, r)| [&r.from, &r.to].iter().any(|n| *n == from || *n == to)) {
+            bail!("rebalance {} already involves one of these nodes", id);
+        }
+        self.started += 1;
+        let id = format!("rb-{}-{}", now, self.started);
+        let directive = directive(&id, from, shed.clone(), Vec::new());
+        self.active.insert(id, Rebalance {
+            from: from.to_string(),
+            shed,
+            to: to.to_string(),
+            restore,
+            phase: Phase::Shedding,
+            since: now,
+        });
+        Ok(directive)
+    }
+
+    /// Advance on a node's confirmation; returns the directives to send next.
+    pub fn acknowledge(&mut self, ack: &RebalanceAck, now: i64) -> Vec<RebalanceDirective> {
+        let Some(rebalance) = self.active.get_mut(&ack.directive_id) else {
+            return Vec::new();
+        };
+        let id = ack.directive_id.clone();
+        match &mut rebalance.phase {
+            Phase::Shedding if ack.node_id == rebalance.from && !ack.rollback => {
+                if !ack.ok {
+                    // The node switches all or nothing, so nothing changed
+                    warn!("Rebalance {}: {} could not shed: {}", id, ack.node_id, ack.error);
+                    self.active.remove(&id);
+                    return Vec::new();
+                }
+                rebalance.phase = Phase::Restoring;
+                rebalance.since = now;
+                vec![directive(&id, &rebalance.to, Vec::new(), rebalance.restore.clone())]
+            }
+            Phase::Restoring if ack.node_id == rebalance.to && !ack.rollback => {
+                if ack.ok {
+                    info!("Rebalance {}: moved load from {} to {}", id, rebalance.from, rebalance.to);
+                    self.active.remove(&id);
+                    return Vec::new();
+                }
+                warn!("Rebalance {}: {} could not restore ({}); rolling back {}", id, ack.node_id, ack.error, rebalance.from);
+                rebalance.phase = Phase::RollingBack(BTreeSet::from([rebalance.from.clone()]));
+                rebalance.since = now;
+                vec![rollback(&id, &rebalance.from)]
+            }
+            Phase::RollingBack(pending) if ack.rollback => {
+                if !ack.ok {
+                    warn!("Rebalance {}: {} failed to roll back: {}", id, ack.node_id, ack.error);
+                }
+                pending.remove(&ack.node_id);
+                if pending.is_empty() {
+                    info!("Rebalance {}: rolled back", id);
+                    self.active.remove(&id);
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Roll back rebalances whose current step went unconfirmed; returns the
+    /// rollback directives to send.
+    pub fn expire(&mut self, now: i64) -> Vec<RebalanceDirective> {
+        let mut directives = Vec::new();
+        let mut finished = Vec::new();
+        for (id, rebalance) in self.active.iter_mut().filter(|(_, r)| now - r.since >= REBALANCE_TIMEOUT_SECS) {
+            // The ack may be what got lost, so undo every side that might have switched
+            let undo = match &rebalance.phase {
+                Phase::Shedding => vec![rebalance.from.clone()],
+                Phase::Restoring => vec![rebalance.from.clone(), rebalance.to.clone()],
+                Phase::RollingBack(pending) => {
+                    warn!("Rebalance {}: no rollback confirmation from {:?}; check relays by hand", id, pending);
+                    finished.push(id.clone());
+                    continue;
+                }
+            };
+            warn!("Rebalance {}: timed out; rolling back {:?}", id, undo);
+            directives.extend(undo.iter().map(|node| rollback(id, node)));
+            rebalance.phase = Phase::RollingBack(undo.into_iter().collect());
+            rebalance.since = now;
+        }
+        for id in finished {
+            self.active.remove(&id);
+        }
+        directives
+    }
+
+    /// One line per rebalance awaiting confirmation, for the console.
+    pub fn in_flight(&self) -> Vec<String> {
+        self.active.iter()
+            .map(|(id, r)| format!("{}: {} [{}] -> {} [{}] ({:?})", id, r.from, r.shed.join(","), r.to, r.restore.join(","), r.phase))
+            .collect()
+    }
+}
+
+fn directive(id: &str, node: &str, open_relays: Vec<String>, close_relays: Vec<String>) -> RebalanceDirective {
+    RebalanceDirective {
+        target_node_id: node.to_string(),
+        directive_id: id.to_string(),
+        open_relays,
+        close_relays,
+        rollback: false,
+    }
+}
+
+fn rollback(id: &str, node: &str) -> RebalanceDirective {
+    RebalanceDirective { rollback: true, ..directive(id, node, Vec::new(), Vec::new()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ack(node_id: &str, directive_id: &str, ok: bool, rollback: bool) -> RebalanceAck {
+        RebalanceAck { node_id: node_id.to_string(), directive_id: directive_id.to_string(), ok, error: String::new(), rollback }
+    }
+
+    fn relays(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_restore_follows_confirmed_shed_and_failures_roll_back() {
+        let transformers = HashMap::from([
+            ("T1".to_string(), relays(&["node_01", "node_02"])),
+            ("T2".to_string(), relays(&["node_03"])),
+        ]);
+        let mut rebalancer = Rebalancer::new(&transformers);
+        assert!(rebalancer.start("node_01", relays(&["r_ev"]), "node_03", relays(&["r_heat"]), 0).is_err());
+
+        let shed = rebalancer.start("node_01", relays(&["r_ev"]), "node_02", relays(&["r_heat"]), 0).unwrap();
+        assert_eq!((shed.target_node_id.as_str(), shed.open_relays.clone()), ("node_01", relays(&["r_ev"])));
+        let restore = rebalancer.acknowledge(&ack("node_01", &shed.directive_id, true, false), 1);
+        assert_eq!(restore[0].target_node_id, "node_02");
+        assert_eq!(restore[0].close_relays, relays(&["r_heat"]));
+
+        // The destination can't take the load: the source is put back
+        let undo = rebalancer.acknowledge(&ack("node_02", &shed.directive_id, false, false), 2);
+        assert_eq!(undo, vec![rollback(&shed.directive_id, "node_01")]);
+        assert!(rebalancer.acknowledge(&ack("node_01", &shed.directive_id, true, true), 3).is_empty());
+        assert!(rebalancer.in_flight().is_empty());
+
+        // Silence after the shed rolls back both sides
+        let shed = rebalancer.start("node_01", relays(&["r_ev"]), "node_02", relays(&["r_heat"]), 10).unwrap();
+        rebalancer.acknowledge(&ack("node_01", &shed.directive_id, true, false), 11);
+        assert!(rebalancer.expire(11 + REBALANCE_TIMEOUT_SECS - 1).is_empty());
+        let undo = rebalancer.expire(11 + REBALANCE_TIMEOUT_SECS);
+        let targets: Vec<&str> = undo.iter().map(|d| d.target_node_id.as_str()).collect();
+        assert_eq!(targets, vec!["node_01", "node_02"]);
+        assert!(undo.iter().all(|d| d.rollback));
+    }
+}
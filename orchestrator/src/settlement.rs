@@ -0,0 +1,191 @@
+use anyhow::{bail, Result};
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use prost::Message;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use crate::transport::streetgrid::EnergyEntry;
+
+/// Energy owed by one household to another after an island event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    /// Household that drew from the island
+    pub from: String,
+    /// Household whose battery supplied it
+    pub to: String,
+    pub wh: f32,
+}
+
+/// Net position of one household over the settlement window.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Balance {
+    pub exported_wh: f32,
+    pub imported_wh: f32,
+    pub entries: usize,
+    /// Entries without a secure element signature
+    pub unsigned: usize,
+}
+
+/// Energy ledger entries collected from nodes, reconciled into who owes whom.
+#[derive(Debug, Default)]
+pub struct Settlement {
+    entries: BTreeMap<(String, u64), EnergyEntry>,
+    /// Secure element identity key of each node that has one, recorded at commissioning
+    device_keys: BTreeMap<String, VerifyingKey>,
+}
+
+impl Settlement {
+    /// `device_keys` maps node IDs to their secure element identity key (P-256 x || y, hex).
+    pub fn new(device_keys: &HashMap<String, String>) -> Result<Self> {
+        let device_keys = device_keys.iter()
+            .map(|(node_id, key)| {
+                let mut sec1 = vec![0x04];
+                sec1.extend_from_slice(&hex::decode(key.trim()).map_err(|_| anyhow::anyhow!("device key of {} is not hex", node_id))?);
+                let key = VerifyingKey::from_sec1_bytes(&sec1).map_err(|_| anyhow::anyhow!("bad device key for {}", node_id))?;
+                Ok((node_id.clone(), key))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { device_keys, ..Default::default() })
+    }
+
+    /// Check the entry's ECDSA signature against the node's registered key, never the one
+    /// the entry carries. Nodes without a registered key (no secure element) may only send
+    /// unsigned entries, which are counted separately; nodes with one may only send signed.
+    fn verify(&self, entry: &EnergyEntry) -> Result<()> {
+        let Some(key) = self.device_keys.get(&entry.node_id) else {
+            if !entry.signature.is_empty() {
+                bail!("signed, but the node has no registered device key");
+            }
+            return Ok(());
+        };
+        if entry.signature.is_empty() {
+            bail!("unsigned, but the node signs its entries");
+        }
+        if !entry.device_public_key.is_empty() && entry.device_public_key != key.to_encoded_point(false).as_bytes()[1..] {
+            bail!("carries a key other than the node's registered one");
+        }
+        let unsigned = EnergyEntry { device_public_key: Vec::new(), signature: Vec::new(), ..entry.clone() };
+        let digest = Sha256::digest(unsigned.encode_to_vec());
+        let signature = Signature::from_slice(&entry.signature).map_err(|_| anyhow::anyhow!("malformed signature"))?;
+        if key.verify_prehash(&digest, &signature).is_err() {
+            bail!("signature does not match");
+        }
+        Ok(())
+    }
+
+    /// Keep a verified entry; the same entry arriving from several neighbours is stored
+    /// once, and a different one under the same sequence number never replaces it.
+    pub fn record(&mut self, entry: EnergyEntry) -> Result<()> {
+        if let Some(recorded) = self.entries.get(&(entry.node_id.clone(), entry.seq)) {
+            if *recorded != entry {
+                bail!("energy entry {}#{} rejected: conflicts with the one already recorded", entry.node_id, entry.seq);
+            }
+            return Ok(());
+        }
+        if let Err(e) = self.verify(&entry) {
+            bail!("energy entry {}#{} rejected: {}", entry.node_id, entry.seq, e);
+        }
+        self.entries.insert((entry.node_id.clone(), entry.seq), entry);
+        Ok(())
+    }
+
+    /// Per-household totals for entries ending within `[since, until]`.
+    pub fn balances(&self, since: i64, until: i64) -> BTreeMap<String, Balance> {
+        let mut balances: BTreeMap<String, Balance> = BTreeMap::new();
+        for entry in self.entries.values().filter(|e| e.period_end >= since && e.period_end <= until) {
+            let balance = balances.entry(entry.node_id.clone()).or_default();
+            balance.exported_wh += entry.exported_wh;
+            balance.imported_wh += entry.imported_wh;
+            balance.entries += 1;
+            if entry.signature.is_empty() {
+                balance.unsigned += 1;
+            }
+        }
+        balances
+    }
+
+    /// Who owes whom: each household's net import is owed to the net exporters in
+    /// proportion to what they supplied over the window.
+    pub fn transfers(&self, since: i64, until: i64) -> Vec<Transfer> {
+        let net: Vec<(String, f32)> = self.balances(since, until).into_iter()
+            .map(|(id, b)| (id, b.exported_wh - b.imported_wh))
+            .collect();
+        let supplied: f32 = net.iter().map(|(_, n)| n.max(0.0)).sum();
+        if supplied <= 0.0 {
+            return Vec::new();
+        }
+        let mut transfers = Vec::new();
+        for (importer, n) in net.iter().filter(|(_, n)| *n < 0.0) {
+            for (exporter, e) in net.iter().filter(|(_, e)| *e > 0.0) {
+                transfers.push(Transfer { from: importer.clone(), to: exporter.clone(), wh: -n * e / supplied });
+            }
+        }
+        transfers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::signature::hazmat::PrehashSigner;
+    use p256::ecdsa::SigningKey;
+
+    fn entry(node_id: &str, seq: u64, exported_wh: f32, imported_wh: f32) -> EnergyEntry {
+        EnergyEntry { node_id: node_id.to_string(), seq, period_start: 0, period_end: 900, exported_wh, imported_wh, ..Default::default() }
+    }
+
+    fn sign(mut entry: EnergyEntry, key: &SigningKey) -> EnergyEntry {
+        let digest = Sha256::digest(entry.encode_to_vec());
+        let signature: Signature = key.sign_prehash(&digest).unwrap();
+        entry.signature = signature.to_bytes().to_vec();
+        // SEC1 uncompressed point without the 0x04 tag, as the secure element reports it
+        entry.device_public_key = key.verifying_key().to_encoded_point(false).as_bytes()[1..].to_vec();
+        entry
+    }
+
+    fn registered(key: &SigningKey) -> String {
+        hex::encode(&key.verifying_key().to_encoded_point(false).as_bytes()[1..])
+    }
+
+    #[test]
+    fn test_importers_owe_exporters_pro_rata() {
+        let key = SigningKey::from_slice(&[7; 32]).unwrap();
+        let mut settlement = Settlement::new(&HashMap::from([("node_01".to_string(), registered(&key))])).unwrap();
+        settlement.record(sign(entry("node_01", 1, 3000.0, 0.0), &key)).unwrap();
+        settlement.record(entry("node_02", 1, 1000.0, 0.0)).unwrap();
+        settlement.record(entry("node_03", 1, 0.0, 2000.0)).unwrap();
+        // Heard again from a neighbour
+        settlement.record(entry("node_03", 1, 0.0, 2000.0)).unwrap();
+
+        let mut forged = sign(entry("node_01", 2, 0.0, 500.0), &key);
+        forged.imported_wh = 0.0;
+        assert!(settlement.record(forged).is_err());
+
+        assert_eq!(settlement.balances(0, 1000)["node_01"].unsigned, 0);
+        assert_eq!(settlement.balances(0, 1000)["node_03"].entries, 1);
+        assert_eq!(settlement.transfers(0, 1000), vec![
+            Transfer { from: "node_03".to_string(), to: "node_01".to_string(), wh: 1500.0 },
+            Transfer { from: "node_03".to_string(), to: "node_02".to_string(), wh: 500.0 },
+        ]);
+        assert!(settlement.transfers(1000, 2000).is_empty());
+    }
+
+    #[test]
+    fn test_entries_are_checked_against_the_registered_key() {
+        let key = SigningKey::from_slice(&[7; 32]).unwrap();
+        let impostor = SigningKey::from_slice(&[8; 32]).unwrap();
+        let mut settlement = Settlement::new(&HashMap::from([("node_01".to_string(), registered(&key))])).unwrap();
+
+        // Signed with a key of its own choosing, which it also carries
+        assert!(settlement.record(sign(entry("node_01", 1, 0.0, 0.0), &impostor)).is_err());
+        // Unsigned, from a node whose secure element signs everything
+        assert!(settlement.record(entry("node_01", 1, 0.0, 0.0)).is_err());
+        // Signed by a node with no registered key
+        assert!(settlement.record(sign(entry("node_02", 1, 0.0, 0.0), &impostor)).is_err());
+
+        // A verified entry stays as recorded
+        settlement.record(sign(entry("node_01", 1, 3000.0, 0.0), &key)).unwrap();
+        assert!(settlement.record(sign(entry("node_01", 1, 0.0, 0.0), &key)).is_err());
+        assert_eq!(settlement.balances(0, 1000)["node_01"].exported_wh, 3000.0);
+    }
+}
@@ -0,0 +1,378 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::warn;
+use prost::Message;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use streetgrid_core::channel::ChannelLink;
+use streetgrid_core::clock::{Clock, VirtualClock};
+use streetgrid_core::comms::{CommunicationLayer, OrchestratorClient};
+use streetgrid_core::hal::gpio::mock::MockRelayDriver;
+use streetgrid_core::keys::Keyring;
+use streetgrid_core::node::{EdgeNode, Task};
+use streetgrid_core::types::{Phase, Priority, Relay, RelayType};
+use tokio::sync::mpsc;
+use crate::transport::{NeighborhoodMessage, Transport};
+
+/// Neighbours that must also see under-voltage before a simulated node islands, by default
+pub const DEFAULT_QUORUM: usize = 2;
+
+/// Simulated time between two rounds of the nodes' periodic tasks
+const STEP: Duration = Duration::from_secs(1);
+
+/// Exchanges before `pump` gives up on the nodes going quiet
+const MAX_ROUNDS: usize = 100;
+
+const PHASES: [Phase; 3] = [Phase::A, Phase::B, Phase::C];
+
+/// One household: a firmware node on mock relays, and what it sent not yet carried.
+struct SimNode {
+    node: EdgeNode,
+    link: Arc<ChannelLink>,
+    uplink: mpsc::UnboundedReceiver<Vec<u8>>,
+}
+
+/// Where frames for a node go. Zones are fixed when the street is built.
+struct Route {
+    id: String,
+    zones: Vec<String>,
+    downlink: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+/// The street's nodes, and how far into the run they are.
+struct Street {
+    nodes: Vec<SimNode>,
+    elapsed: Duration,
+}
+
+/// Relays of node `index` of a street: every node has a grid tie and four loads, one in
+/// a critical role; every other node has a battery. Draws vary between nodes so phases
+/// and tiers differ.
+fn household(index: usize) -> Vec<Relay> {
+    let relay = |id: &str, relay_type: RelayType, priority, amperage| Relay {
+        id: id.to_string(),
+        name: id.to_string(),
+        is_closed: relay_type != RelayType::Source,
+        relay_type,
+        priority,
+        amperage,
+        community_criticality: 0,
+        phase: None,
+    };
+    let scale = 1.0 + (index % 4) as f32 * 0.5;
+    let mut relays = vec![
+        relay("r_grid", RelayType::Grid, Priority::Critical, 100.0),
+        relay("r_medical", RelayType::Load, Priority::Critical, 2.0),
+        relay("r_fridge", RelayType::Load, Priority::High, 3.0 * scale),
+        relay("r_hvac", RelayType::Load, Priority::Medium, 10.0 * scale),
+        relay("r_ev", RelayType::Load, Priority::Low, 16.0 * scale),
+    ];
+    if index.is_multiple_of(2) {
+        relays.push(relay("r_battery", RelayType::Source, Priority::High, 40.0));
+    }
+    relays
+}
+
+/// A street of firmware nodes behind an in-process transport, on a virtual clock. Sent
+/// frames wait at the nodes until `pump`, which runs them and queues their replies for the
+/// orchestrator, so runs are deterministic. Nodes in the same zone hear each other's traffic.
+pub struct Simulation {
+    street: tokio::sync::Mutex<Street>,
+    routes: Vec<Route>,
+    clock: VirtualClock,
+    inbox_tx: mpsc::UnboundedSender<NeighborhoodMessage>,
+    inbox: tokio::sync::Mutex<mpsc::UnboundedReceiver<NeighborhoodMessage>>,
+}
+
+impl Simulation {
+    /// `nodes` nodes split evenly into `zones` zones (block_1, block_2, ...), islanding once
+    /// `quorum` neighbours agree. With a keyring, they sign and verify as on a keyed mesh.
+    /// Every fourth node opts out of demand response.
+    pub fn new(nodes: usize, zones: usize, quorum: usize, keyring: Option<Keyring>) -> Result<Self> {
+        let clock = VirtualClock::at(chrono::Utc::now().timestamp());
+        let per_zone = nodes.div_ceil(zones.max(1)).max(1);
+        let mut routes = Vec::with_capacity(nodes);
+        let nodes = (0..nodes)
+            .map(|i| {
+                let id = format!("sim_{:02}", i + 1);
+                let (uplink_tx, uplink) = mpsc::unbounded_channel();
+                let (downlink, downlink_rx) = mpsc::unbounded_channel();
+                let link = Arc::new(ChannelLink::new(uplink_tx, downlink_rx));
+                let keyring = keyring.clone().map(|k| Arc::new(Mutex::new(k)));
+                let mut client = OrchestratorClient::new(link.clone()).with_origin(&id);
+                if let Some(keyring) = &keyring {
+                    client = client.with_keyring(keyring.clone());
+                }
+                let relays = household(i);
+                let relay_pins = relays.iter().enumerate().map(|(pin, r)| (r.id.clone(), pin as u8)).collect::<HashMap<_, _>>();
+                let mut node = EdgeNode::builder(&id)
+                    .relays(relays)
+                    .relay_pins(relay_pins)
+                    .client(client)
+                    .relay_driver(Box::new(MockRelayDriver::new(&[])?))
+                    .build()?;
+                node.keyring = keyring;
+                node.zones = vec![format!("block_{}", i / per_zone + 1)];
+                node.phase = Some(PHASES[i % PHASES.len()]);
+                node.island_quorum = Some(quorum);
+                node.demand_response.opt_out = i % 4 == 3;
+                node.set_clock(Arc::new(clock.clone()));
+                routes.push(Route { id, zones: node.zones.clone(), downlink });
+                Ok(SimNode { node, link, uplink })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let (inbox_tx, inbox) = mpsc::unbounded_channel();
+        Ok(Self {
+            street: tokio::sync::Mutex::new(Street { nodes, elapsed: Duration::ZERO }),
+            routes,
+            clock,
+            inbox_tx,
+            inbox: tokio::sync::Mutex::new(inbox),
+        })
+    }
+
+    /// Simulated time, in Unix seconds.
+    pub fn now(&self) -> i64 {
+        self.clock.now().timestamp()
+    }
+
+    /// Boot every node; they register with the orchestrator.
+    pub async fn start(&self) {
+        let mut street = self.street.lock().await;
+        for sim in &mut street.nodes {
+            sim.node.start().await;
+            sim.node.run_due(Duration::ZERO).await;
+        }
+        self.carry(&mut street.nodes).await;
+    }
+
+    /// Set the mains voltage at every node in `zone`, or the whole street.
+    pub async fn set_voltage(&self, zone: Option<&str>, voltage: f32) {
+        for sim in &mut self.street.lock().await.nodes {
+            if zone.is_none_or(|z| sim.node.zones.iter().any(|nz| nz == z)) {
+                sim.node.voltage_ref = voltage;
+            }
+        }
+    }
+
+    /// Move simulated time on by `by`, running the nodes' periodic tasks as they fall due.
+    pub async fn advance(&self, by: Duration) {
+        let mut street = self.street.lock().await;
+        let end = street.elapsed + by;
+        while street.elapsed < end {
+            street.elapsed += STEP;
+            self.clock.advance(STEP);
+            let elapsed = street.elapsed;
+            for sim in &mut street.nodes {
+                sim.node.run_due(elapsed).await;
+            }
+            self.carry(&mut street.nodes).await;
+        }
+    }
+
+    /// Let the nodes handle what the orchestrator sent them, and carry their replies.
+    pub async fn pump(&self) {
+        self.carry(&mut self.street.lock().await.nodes).await;
+    }
+
+    /// Carry what the nodes sent to the orchestrator and to their zone neighbours, and let
+    /// them handle what they received, until the street is quiet.
+    async fn carry(&self, nodes: &mut [SimNode]) {
+        for _ in 0..MAX_ROUNDS {
+            let mut frames = Vec::new();
+            for (from, sim) in nodes.iter_mut().enumerate() {
+                while let Ok(frame) = sim.uplink.try_recv() {
+                    frames.push((from, frame));
+                }
+            }
+            for (from, frame) in &frames {
+                match NeighborhoodMessage::decode(frame.as_slice()) {
+                    Ok(msg) => {
+                        let _ = self.inbox_tx.send(msg);
+                    }
+                    Err(e) => warn!("[SIM] {} sent an undecodable frame: {}", self.routes[*from].id, e),
+                }
+                let zones = &self.routes[*from].zones;
+                for (_, peer) in self.routes.iter().enumerate().filter(|(i, _)| i != from) {
+                    if peer.zones.iter().any(|z| zones.contains(z)) {
+                        let _ = peer.downlink.send(frame.clone());
+                    }
+                }
+            }
+            let mut quiet = frames.is_empty();
+            for sim in nodes.iter_mut() {
+                while sim.link.link_stats().queue_depth > 0 {
+                    sim.node.run_task(Task::Messages).await;
+                    quiet = false;
+                }
+            }
+            if quiet {
+                return;
+            }
+        }
+        warn!("[SIM] nodes still talking after {} rounds", MAX_ROUNDS);
+    }
+}
+
+#[async_trait]
+impl Transport for Simulation {
+    async fn send(&self, target: Option<&str>, msg: NeighborhoodMessage) -> Result<()> {
+        let frame = msg.encode_to_vec();
+        for route in &self.routes {
+            let addressed = match target {
+                Some(id) => route.id == id,
+                None => msg.target_zone.is_empty() || route.zones.contains(&msg.target_zone),
+            };
+            if addressed {
+                let _ = route.downlink.send(frame.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn receive(&self) -> Option<NeighborhoodMessage> {
+        self.inbox.lock().await.recv().await
+    }
+
+    fn name(&self) -> &'static str {
+        "simulation"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fleet::Fleet;
+    use crate::keys::MeshKey;
+    use crate::openadr::{EventNotice, NodeResponse};
+    use crate::orchestrator::Orchestrator;
+    use streetgrid_core::types::NodeState;
+
+    async fn street(nodes: usize, zones: usize) -> (Arc<Simulation>, Orchestrator) {
+        let sim = Arc::new(Simulation::new(nodes, zones, DEFAULT_QUORUM, Some(Keyring::new(1, [7; 32]))).unwrap());
+        let mut orchestrator = Orchestrator::new("orchestrator", sim.clone(), Some(MeshKey::new(1, [7; 32])), Fleet::default());
+        sim.start().await;
+        settle(&sim, &mut orchestrator).await;
+        (sim, orchestrator)
+    }
+
+    /// Let the orchestrator act on everything the nodes sent, and on their replies to that.
+    async fn settle(sim: &Simulation, orchestrator: &mut Orchestrator) {
+        loop {
+            sim.pump().await;
+            let pending: Vec<_> = {
+                let mut inbox = sim.inbox.lock().await;
+                std::iter::from_fn(|| inbox.try_recv().ok()).collect()
+            };
+            if pending.is_empty() {
+                return;
+            }
+            for msg in pending {
+                orchestrator.handle(msg).await;
+            }
+        }
+    }
+
+    async fn states(sim: &Simulation) -> Vec<(String, NodeState)> {
+        sim.street.lock().await.nodes.iter().map(|s| (s.node.id.clone(), s.node.state)).collect()
+    }
+
+    async fn closed(sim: &Simulation, node_id: &str, relay_id: &str) -> Option<bool> {
+        let street = sim.street.lock().await;
+        let sim = street.nodes.iter().find(|s| s.node.id == node_id)?;
+        sim.node.relays.iter().find(|r| r.id == relay_id).map(|r| r.is_closed)
+    }
+
+    #[tokio::test]
+    async fn test_rolling_blackstart_brings_every_zone_back() {
+        let (sim, mut orchestrator) = street(9, 3).await;
+        assert_eq!(orchestrator.fleet.lock().unwrap().zones().len(), 3);
+
+        sim.set_voltage(None, 0.0).await;
+        sim.advance(Duration::from_secs(15)).await;
+        settle(&sim, &mut orchestrator).await;
+        assert!(states(&sim).await.iter().all(|(_, state)| *state == NodeState::Islanded));
+
+        orchestrator.start_blackstart(&[]).await.unwrap();
+        for _ in 0..20 {
+            settle(&sim, &mut orchestrator).await;
+            orchestrator.advance_blackstart().await;
+        }
+        assert!(orchestrator.blackstart.is_none());
+        for (node_id, state) in states(&sim).await {
+            assert_eq!(state, NodeState::BlackStart);
+            assert_eq!(closed(&sim, &node_id, "r_medical").await, Some(true));
+            assert_ne!(closed(&sim, &node_id, "r_battery").await, Some(false));
+            assert_eq!(closed(&sim, &node_id, "r_ev").await, Some(false));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_islanding_needs_a_quorum_of_neighbours() {
+        let (sim, mut orchestrator) = street(6, 2).await;
+        // One house's sag is local, not an outage
+        sim.street.lock().await.nodes[0].node.voltage_ref = 90.0;
+        sim.advance(Duration::from_secs(15)).await;
+        assert!(states(&sim).await.iter().all(|(_, state)| *state != NodeState::Islanded));
+
+        // The whole of block_1 sags; block_2 is unaffected
+        sim.set_voltage(Some("block_1"), 90.0).await;
+        sim.advance(Duration::from_secs(15)).await;
+        settle(&sim, &mut orchestrator).await;
+        let islanded: Vec<String> = states(&sim).await.into_iter()
+            .filter(|(_, state)| *state == NodeState::Islanded)
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(islanded, ["sim_01", "sim_02", "sim_03"]);
+        assert_eq!(orchestrator.fleet.lock().unwrap().get("sim_01").unwrap().last_voltage, Some(90.0));
+    }
+
+    #[tokio::test]
+    async fn test_phase_rebalance_completes_against_simulated_nodes() {
+        let sim = Arc::new(Simulation::new(3, 1, DEFAULT_QUORUM, None).unwrap());
+        let mut orchestrator = Orchestrator::new("orchestrator", sim.clone(), None, Fleet::default());
+        // sim_01's HVAC is off, leaving phase A light and C heavy
+        for relay in sim.street.lock().await.nodes[0].node.relays.iter_mut().filter(|r| r.id == "r_hvac") {
+            relay.is_closed = false;
+        }
+        sim.start().await;
+        settle(&sim, &mut orchestrator).await;
+
+        orchestrator.balance_phases(None).await.unwrap();
+        settle(&sim, &mut orchestrator).await;
+        assert!(orchestrator.rebalancer.in_flight().is_empty());
+        assert_eq!(closed(&sim, "sim_03", "r_hvac").await, Some(false));
+        assert_eq!(closed(&sim, "sim_01", "r_hvac").await, Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_demand_response_is_acknowledged_per_node() {
+        let (sim, mut orchestrator) = street(4, 1).await;
+
+        let notice = EventNotice {
+            event_id: "evt_1".to_string(),
+            modification_number: 0,
+            signal_level: 2,
+            start: sim.now(),
+            duration_secs: 3600,
+            zones: vec![],
+            cancelled: false,
+            max_load_kw: None,
+        };
+        let members = orchestrator.fleet.lock().unwrap().zones().remove("block_1").unwrap().into_iter().collect();
+        let command = orchestrator.demand_response.lock().unwrap().receive(&notice, vec!["block_1".to_string()], members, sim.now()).unwrap();
+        orchestrator.issue_zone("block_1", command).await.unwrap();
+        settle(&sim, &mut orchestrator).await;
+
+        // Medium and low priority loads are off, except at sim_04, which opted out
+        assert_eq!(closed(&sim, "sim_01", "r_hvac").await, Some(false));
+        assert_eq!(closed(&sim, "sim_01", "r_fridge").await, Some(true));
+        assert_eq!(closed(&sim, "sim_04", "r_ev").await, Some(true));
+        let events = orchestrator.demand_response.lock().unwrap();
+        let event = events.get("evt_1").unwrap();
+        assert_eq!(event.nodes["sim_01"], NodeResponse::OptedIn { shed_kw: 3.12 });
+        assert_eq!(event.nodes["sim_04"], NodeResponse::OptedOut);
+        assert!(!event.nodes.values().any(|r| *r == NodeResponse::Pending));
+    }
+}
@@ -0,0 +1,76 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use tokio::sync::broadcast;
+
+/// Entries kept for dashboards that connect later
+const MAX_ENTRIES: usize = 500;
+
+/// Entries buffered for each live subscriber before it misses some
+const LIVE_BUFFER: usize = 64;
+
+/// Something an operator would want to see on the street's timeline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Entry {
+    /// Unix seconds
+    pub at: i64,
+    /// Node it concerns, if any
+    pub node_id: Option<String>,
+    /// e.g. "alarm", "liveness", "command"
+    pub kind: &'static str,
+    pub message: String,
+}
+
+/// Recent notable events across the fleet: alarms, nodes going quiet, commands issued.
+#[derive(Debug)]
+pub struct Timeline {
+    entries: VecDeque<Entry>,
+    live: broadcast::Sender<Entry>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self { entries: VecDeque::new(), live: broadcast::channel(LIVE_BUFFER).0 }
+    }
+}
+
+impl Timeline {
+    pub fn record(&mut self, at: i64, node_id: Option<&str>, kind: &'static str, message: String) {
+        let entry = Entry { at, node_id: node_id.map(str::to_string), kind, message };
+        if self.entries.len() >= MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry.clone());
+        if self.live.receiver_count() > 0 {
+            let _ = self.live.send(entry);
+        }
+    }
+
+    /// Oldest first.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+
+    /// Entries as they are recorded from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Entry> {
+        self.live.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline_keeps_the_latest_entries_and_streams_new_ones() {
+        let mut timeline = Timeline::default();
+        for i in 0..MAX_ENTRIES as i64 + 5 {
+            timeline.record(i, None, "alarm", format!("entry {}", i));
+        }
+        assert_eq!(timeline.entries().count(), MAX_ENTRIES);
+        assert_eq!(timeline.entries().next().unwrap().at, 5);
+
+        let mut live = timeline.subscribe();
+        timeline.record(1000, Some("node_01"), "liveness", "node_01: down".to_string());
+        assert_eq!(live.try_recv().unwrap().node_id.as_deref(), Some("node_01"));
+    }
+}
@@ -0,0 +1,138 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use crate::transport::streetgrid::NeighborReport;
+
+/// One node hearing another, from the hearer's latest NeighborReport.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Link {
+    pub from: String,
+    pub to: String,
+    /// dBm; None if the node's radio didn't report it
+    pub rssi: Option<i32>,
+    pub snr: f32,
+    pub last_heard: i64,
+    /// Share of the heard node's frames that didn't arrive; None until it numbers them
+    pub packet_error_rate: Option<f32>,
+}
+
+/// Connectivity graph assembled from NeighborReports.
+#[derive(Debug, Default)]
+pub struct Topology {
+    /// Hearer -> links to the nodes it hears
+    heard_by: BTreeMap<String, Vec<Link>>,
+}
+
+/// Snapshot of the graph for the API and console.
+#[derive(Debug, Serialize)]
+pub struct TopologyView {
+    pub links: Vec<Link>,
+    /// Groups of nodes connected to each other; more than one means the mesh is partitioned
+    pub components: Vec<Vec<String>>,
+    /// Nodes that hear nobody and that nobody hears
+    pub isolated: Vec<String>,
+}
+
+impl Topology {
+    /// Replace everything previously reported by the report's sender.
+    pub fn update(&mut self, report: &NeighborReport) {
+        let links = report.neighbors.iter()
+            .map(|n| Link {
+                from: report.node_id.clone(),
+                to: n.node_id.clone(),
+                rssi: Some(n.rssi).filter(|&r| r != 0),
+                snr: n.snr,
+                last_heard: n.last_heard,
+                packet_error_rate: (n.frames_expected > 0)
+                    .then(|| 1.0 - n.frames_heard.min(n.frames_expected) as f32 / n.frames_expected as f32),
+            })
+            .collect();
+        self.heard_by.insert(report.node_id.clone(), links);
+    }
+
+    pub fn links(&self) -> impl Iterator<Item = &Link> {
+        self.heard_by.values().flatten()
+    }
+
+    /// Nodes whose latest report says they hear `node_id`.
+    pub fn hearers_of(&self, node_id: &str) -> Vec<String> {
+        self.links().filter(|l| l.to == node_id).map(|l| l.from.clone()).collect()
+    }
+
+    /// Connected groups among `nodes` plus every node seen in a report. A link in
+    /// either direction joins two nodes.
+    pub fn view(&self, nodes: impl IntoIterator<Item = String>) -> TopologyView {
+        let mut adjacency: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        let nodes: BTreeSet<String> = nodes.into_iter().chain(self.heard_by.keys().cloned()).collect();
+        for node in &nodes {
+            adjacency.entry(node.as_str()).or_default();
+        }
+        for link in self.links() {
+            adjacency.entry(link.from.as_str()).or_default().insert(link.to.as_str());
+            adjacency.entry(link.to.as_str()).or_default().insert(link.from.as_str());
+        }
+
+        let mut seen = BTreeSet::new();
+        let mut components = Vec::new();
+        for &start in adjacency.keys() {
+            if !seen.insert(start) {
+                continue;
+            }
+            let mut component = vec![start.to_string()];
+            let mut stack = vec![start];
+            while let Some(node) = stack.pop() {
+                for &next in &adjacency[node] {
+                    if seen.insert(next) {
+                        component.push(next.to_string());
+                        stack.push(next);
+                    }
+                }
+            }
+            component.sort();
+            components.push(component);
+        }
+        let isolated = components.iter().filter(|c| c.len() == 1).map(|c| c[0].clone()).collect();
+        TopologyView { links: self.links().cloned().collect(), components, isolated }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::streetgrid::Neighbor;
+
+    fn report(node_id: &str, hears: &[&str]) -> NeighborReport {
+        NeighborReport {
+            node_id: node_id.to_string(),
+            timestamp: 0,
+            neighbors: hears.iter()
+                .map(|n| Neighbor { node_id: n.to_string(), rssi: -90, ..Default::default() })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_partitions_and_isolated_nodes() {
+        let mut topology = Topology::default();
+        topology.update(&report("a", &["b"]));
+        topology.update(&report("c", &["b"]));
+        topology.update(&report("d", &["e"]));
+        topology.update(&report("f", &[]));
+
+        let view = topology.view(["g".to_string()]);
+        assert_eq!(view.links.len(), 3);
+        assert_eq!(view.components, vec![vec!["a", "b", "c"], vec!["d", "e"], vec!["f"], vec!["g"]]);
+        assert_eq!(view.isolated, vec!["f", "g"]);
+        assert_eq!(view.links[0].packet_error_rate, None);
+
+        let mut lossy = report("d", &["e"]);
+        lossy.neighbors[0].frames_heard = 45;
+        lossy.neighbors[0].frames_expected = 50;
+        topology.update(&lossy);
+        let link = topology.links().find(|l| l.from == "d").unwrap();
+        assert!((link.packet_error_rate.unwrap() - 0.1).abs() < 1e-6);
+
+        // A later report replaces the earlier one
+        topology.update(&report("c", &[]));
+        assert_eq!(topology.view([]).isolated, vec!["c", "f"]);
+    }
+}
@@ -0,0 +1,252 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{info, warn};
+use prost::Message;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS, TlsConfiguration, Transport as MqttTransportKind};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use crate::config::MqttConfig;
+
+// Include the generated proto modules
+pub mod streetgrid {
+    include!(concat!(env!("OUT_DIR"), "/streetgrid.rs"));
+
+    /// gRPC service for utilities and integrators
+    pub mod northbound {
+        include!(concat!(env!("OUT_DIR"), "/streetgrid.northbound.rs"));
+    }
+}
+
+pub use streetgrid::NeighborhoodMessage;
+
+/// Messages buffered between a transport and the orchestrator loop.
+const INBOX_CAPACITY: usize = 256;
+
+/// Topic every node subscribes to alongside its own downlink.
+const BROADCAST_TOPIC: &str = "streetgrid/broadcast";
+
+/// Uplinks of all nodes.
+const UPLINK_FILTER: &str = "streetgrid/+/up";
+
+/// Link between the orchestrator and the nodes of the street.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Deliver to one node, or to every node when `target` is None.
+    async fn send(&self, target: Option<&str>, msg: NeighborhoodMessage) -> Result<()>;
+
+    /// Wait for the next message from any node; None once the transport has shut down.
+    async fn receive(&self) -> Option<NeighborhoodMessage>;
+
+    fn name(&self) -> &'static str;
+}
+
+// ============================================================================
+// MQTT
+// ============================================================================
+
+/// Broker TLS: CA to trust and the orchestrator's client certificate.
+#[derive(Debug, Clone)]
+pub struct MqttTls {
+    pub ca_pem: Vec<u8>,
+    pub client_cert_pem: Option<Vec<u8>>,
+    pub client_key_pem: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MqttSettings {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub tls: Option<MqttTls>,
+}
+
+impl MqttSettings {
+    /// Settings for the broker in the config, reading the TLS files it names.
+    pub fn from_config(config: &MqttConfig, client_id: String) -> Result<Self> {
+        let tls = match &config.tls {
+            Some(tls) => Some(MqttTls {
+                ca_pem: std::fs::read(&tls.ca_cert)?,
+                client_cert_pem: tls.client_cert.as_ref().map(std::fs::read).transpose()?,
+                client_key_pem: tls.client_key.as_ref().map(std::fs::read).transpose()?,
+            }),
+            None => None,
+        };
+        Ok(Self {
+            host: config.host.clone(),
+            port: config.port.unwrap_or(if tls.is_some() { 8883 } else { 1883 }),
+            client_id,
+            tls,
+        })
+    }
+}
+
+/// Mirror of the firmware's MQTT transport: reads every `streetgrid/<node>/up`,
+/// writes `streetgrid/<node>/down` or the broadcast topic.
+pub struct MqttTransport {
+    client: AsyncClient,
+    inbox: tokio::sync::Mutex<mpsc::Receiver<NeighborhoodMessage>>,
+}
+
+impl MqttTransport {
+    /// Start connecting; the event loop keeps reconnecting in the background.
+    pub fn connect(settings: &MqttSettings) -> Result<Self> {
+        let mut options = MqttOptions::new(&settings.client_id, &settings.host, settings.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        match &settings.tls {
+            Some(tls) => {
+                let client_auth = tls.client_cert_pem.clone().zip(tls.client_key_pem.clone());
+                options.set_transport(MqttTransportKind::tls_with_config(TlsConfiguration::Simple {
+                    ca: tls.ca_pem.clone(),
+                    alpn: None,
+                    client_auth,
+                }));
+            }
+            None => warn!("MQTT without TLS: traffic to {} is unauthenticated and in the clear", settings.host),
+        }
+        let (client, mut event_loop) = AsyncClient::new(options, INBOX_CAPACITY);
+        let (inbox_tx, inbox) = mpsc::channel(INBOX_CAPACITY);
+
+        let subscriber = client.clone();
+        tokio::spawn(async move {
+            loop {
+                match event_loop.poll().await {
+                    // Subscriptions don't survive a clean-session reconnect
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("MQTT connected");
+                        if let Err(e) = subscriber.subscribe(UPLINK_FILTER, QoS::AtLeastOnce).await {
+                            warn!("MQTT subscribe to {} failed: {}", UPLINK_FILTER, e);
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => match NeighborhoodMessage::decode(publish.payload) {
+                        Ok(msg) => {
+                            if inbox_tx.send(msg).await.is_err() {
+                                return;
+                            }
+                        }
+                        Err(e) => warn!("Undecodable message on {}: {}", publish.topic, e),
+                    },
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("MQTT connection error: {}; retrying", e);
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                }
+            }
+        });
+        Ok(Self { client, inbox: tokio::sync::Mutex::new(inbox) })
+    }
+}
+
+#[async_trait]
+impl Transport for MqttTransport {
+    async fn send(&self, target: Option<&str>, msg: NeighborhoodMessage) -> Result<()> {
+        let topic = match target {
+            Some(node_id) => format!("streetgrid/{}/down", node_id),
+            None => BROADCAST_TOPIC.to_string(),
+        };
+        self.client.publish(topic, QoS::AtLeastOnce, false, msg.encode_to_vec()).await?;
+        Ok(())
+    }
+
+    async fn receive(&self) -> Option<NeighborhoodMessage> {
+        self.inbox.lock().await.recv().await
+    }
+
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+}
+
+// ============================================================================
+// Mock
+// ============================================================================
+
+/// In-process transport for development and tests: messages are injected by hand
+/// and everything sent is kept for inspection.
+pub struct MockTransport {
+    inbox_tx: mpsc::Sender<NeighborhoodMessage>,
+    inbox: tokio::sync::Mutex<mpsc::Receiver<NeighborhoodMessage>>,
+    sent: Mutex<Vec<(Option<String>, NeighborhoodMessage)>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        let (inbox_tx, inbox) = mpsc::channel(INBOX_CAPACITY);
+        Self { inbox_tx, inbox: tokio::sync::Mutex::new(inbox), sent: Mutex::new(Vec::new()) }
+    }
+
+    /// Queue a message as if a node had sent it.
+    #[allow(dead_code)]
+    pub async fn inject(&self, msg: NeighborhoodMessage) {
+        let _ = self.inbox_tx.send(msg).await;
+    }
+
+    /// Everything sent so far, with its target.
+    #[allow(dead_code)]
+    pub fn sent(&self) -> Vec<(Option<String>, NeighborhoodMessage)> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+impl Default for MockTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Transport for MockTransport {
+    async fn send(&self, target: Option<&str>, msg: NeighborhoodMessage) -> Result<()> {
+        info!("[MOCK] -> {}: {:?}", target.unwrap_or("broadcast"), msg.payload);
+        self.sent.lock().unwrap().push((target.map(str::to_string), msg));
+        Ok(())
+    }
+
+    async fn receive(&self) -> Option<NeighborhoodMessage> {
+        self.inbox.lock().await.recv().await
+    }
+
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+}
+
+// ============================================================================
+// Channel
+// ============================================================================
+
+/// In-memory link to a single node over tokio channels carrying encoded frames, for
+/// running a node and the orchestrator in the same process. Every frame reaches the
+/// node, which ignores commands addressed to others.
+pub struct ChannelTransport {
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    rx: tokio::sync::Mutex<mpsc::UnboundedReceiver<Vec<u8>>>,
+}
+
+impl ChannelTransport {
+    pub fn new(tx: mpsc::UnboundedSender<Vec<u8>>, rx: mpsc::UnboundedReceiver<Vec<u8>>) -> Self {
+        Self { tx, rx: tokio::sync::Mutex::new(rx) }
+    }
+}
+
+#[async_trait]
+impl Transport for ChannelTransport {
+    async fn send(&self, _target: Option<&str>, msg: NeighborhoodMessage) -> Result<()> {
+        self.tx.send(msg.encode_to_vec()).map_err(|_| anyhow::anyhow!("channel closed"))
+    }
+
+    async fn receive(&self) -> Option<NeighborhoodMessage> {
+        let mut rx = self.rx.lock().await;
+        loop {
+            match NeighborhoodMessage::decode(rx.recv().await?.as_slice()) {
+                Ok(msg) => return Some(msg),
+                Err(e) => warn!("Dropping undecodable frame: {}", e),
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "channel"
+    }
+}
@@ -0,0 +1,101 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use streetgrid_core::types::RelayType;
+use crate::fleet::NodeRecord;
+use crate::liveness::Liveness;
+
+/// Voltage the nodes' reported amps are converted at (the firmware's default reference)
+pub const NOMINAL_VOLTAGE: f32 = 120.0;
+
+/// Priority tiers a utility may ask us to shed; Critical loads are never offered
+const SHEDDABLE_TIERS: [(i32, &str); 3] = [(1, "high"), (2, "medium"), (3, "low")];
+
+/// Flexible capacity of the fleet, as one virtual power plant, for demand-response
+/// bids. Only nodes that are online count: a commitment needs nodes we can reach.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct VppReport {
+    /// Unix seconds the report was built at
+    pub generated_at: i64,
+    pub nodes: usize,
+    /// Closed load that could be shed on request, by priority tier; loads the
+    /// neighbourhood relies on are left out
+    pub sheddable_kw: BTreeMap<&'static str, f32>,
+    pub sheddable_kw_total: f32,
+    /// Battery energy above the households' reserves, ready to dispatch
+    pub storage_kwh: f32,
+    pub storage_capacity_kwh: f32,
+    /// What batteries and solar could export right now
+    pub export_kw: f32,
+    /// Solar expected over the nodes' forecast horizon
+    pub solar_forecast_kwh: f32,
+    /// Nodes with a battery or solar that advertised their capacity
+    pub source_nodes: usize,
+}
+
+/// Aggregate the flexible load and storage the nodes last reported.
+pub fn aggregate<'a>(nodes: impl IntoIterator<Item = &'a NodeRecord>, now: i64) -> VppReport {
+    let mut report = VppReport {
+        generated_at: now,
+        sheddable_kw: SHEDDABLE_TIERS.iter().map(|(_, tier)| (*tier, 0.0)).collect(),
+        ..Default::default()
+    };
+    for node in nodes.into_iter().filter(|n| n.liveness == Liveness::Online) {
+        report.nodes += 1;
+        for relay in node.relays.iter().filter(|r| r.relay_type == RelayType::Load as i32 && r.is_closed && r.community_criticality == 0) {
+            let Some((_, tier)) = SHEDDABLE_TIERS.iter().find(|(p, _)| *p == relay.priority) else { continue };
+            let kw = relay.amperage * NOMINAL_VOLTAGE / 1000.0;
+            *report.sheddable_kw.entry(tier).or_default() += kw;
+            report.sheddable_kw_total += kw;
+        }
+        if let Some(source) = &node.source {
+            report.source_nodes += 1;
+            report.storage_kwh += source.stored_wh / 1000.0;
+            report.storage_capacity_kwh += source.battery_capacity_wh / 1000.0;
+            report.export_kw += source.export_watts / 1000.0;
+            report.solar_forecast_kwh += source.solar_forecast_wh / 1000.0;
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fleet::{RelayRecord, SourceRecord};
+
+    fn load(priority: i32, amperage: f32, community_criticality: u32) -> RelayRecord {
+        RelayRecord { relay_type: RelayType::Load as i32, priority, amperage, is_closed: true, community_criticality, ..Default::default() }
+    }
+
+    #[test]
+    fn test_flexible_capacity_is_summed_across_online_nodes() {
+        let battery = SourceRecord { export_watts: 2000.0, stored_wh: 8000.0, battery_capacity_wh: 13_500.0, ..Default::default() };
+        let nodes = [
+            NodeRecord {
+                node_id: "node_01".to_string(),
+                relays: vec![load(0, 10.0, 0), load(2, 25.0, 0), load(3, 10.0, 0)],
+                source: Some(battery.clone()),
+                ..Default::default()
+            },
+            NodeRecord {
+                node_id: "node_02".to_string(),
+                // An oxygen concentrator stays on whatever its owner's priority says
+                relays: vec![load(3, 5.0, 3), load(1, 5.0, 0)],
+                ..Default::default()
+            },
+            NodeRecord {
+                node_id: "node_03".to_string(),
+                relays: vec![load(3, 50.0, 0)],
+                source: Some(battery),
+                liveness: Liveness::Down { since: 0 },
+                ..Default::default()
+            },
+        ];
+        let report = aggregate(&nodes, 1000);
+        assert_eq!(report.nodes, 2);
+        assert_eq!(report.sheddable_kw, BTreeMap::from([("high", 0.6), ("medium", 3.0), ("low", 1.2)]));
+        assert!((report.sheddable_kw_total - 4.8).abs() < 1e-4);
+        assert_eq!((report.source_nodes, report.storage_kwh, report.export_kw), (1, 8.0, 2.0));
+        assert_eq!(report.storage_capacity_kwh, 13.5);
+    }
+}